@@ -20,6 +20,8 @@ rec {
               gitignore = ''
                 *
                 !syntxt-audio/
+                !syntxt-cli/
+                !syntxt-clap/
                 !syntxt-core/
                 !syntxt-lang/
                 !syntxt-web-wasm/
@@ -37,7 +39,7 @@ rec {
         blacklistedSrc;
 
     nativeBuildInputs = [wasm-pack wasm-bindgen-cli binaryen_90_x86];
-    buildInputs = [sox];
+    buildInputs = [sox opusTools];
 
     postBuild = ''
       wasm-bindgen --version
@@ -56,11 +58,17 @@ rec {
     # '';
 
     postInstall = ''
+      # The `syntxt` command line tool is installed to $out/bin like every other binary of the workspace
+
       # Additionally include the examples in the output
       mkdir -p $out/examples
       ls -lAh target
       cp target/x86_64-unknown-linux-gnu/release/examples/demo $out/examples
 
+      # The plugin for CLAP hosts
+      mkdir -p $out/lib/clap
+      cp target/x86_64-unknown-linux-gnu/release/libsyntxt_clap.so $out/lib/clap/syntxt.clap
+
       # And the WASM stuff
       mkdir -p $out/lib/wasm/pkg
       cp syntxt-web-wasm/index.html $out/lib/wasm
@@ -69,8 +77,11 @@ rec {
     '';
 
     NIX_SOX_BIN = "${sox}/bin";
+    NIX_OPUSENC_BIN = "${opusTools}/bin";
 
-    cargoSha256 = "1qxn8aggwz7jzlp06c51im005x78zl3qq3y5bhg0h7h0xdr3ykk3";
+    # Placeholder for the hash of the vendored dependencies. The first nix-build fails with a
+    # hash mismatch that reports the hash to put here.
+    cargoSha256 = lib.fakeSha256;
   };
 
   syntxt-doc = nixpkgs.callPackage ./doc/default.nix {};
@@ -7,6 +7,10 @@ nixpkgs.mkShell {
     cargo-audit
     # For running the examples
     sox
+    opusTools
+    # For building with the jack feature
+    pkg-config
+    libjack2
     # For documentation stuff
     (python3.withPackages (ps: [
       ps.sphinx
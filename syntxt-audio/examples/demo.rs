@@ -14,11 +14,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use syntxt_audio::effect::{limiter, reverb};
 use syntxt_audio::instrument::wavinator;
 use syntxt_audio::melody::parse_melody;
 use syntxt_audio::play;
+use syntxt_audio::smoothing;
 use syntxt_audio::song::*;
-use syntxt_audio::{automation::Expr, filter::BiquadType, oscillator::WaveShape};
+use syntxt_audio::{automation::Expr, filter::BiquadType, oscillator::WaveShape, tags::Tags, tuning::Tuning, wave::Layout};
 
 use std::io;
 
@@ -27,8 +29,10 @@ fn main() -> io::Result<()> {
     play::song_main(|| {
         let song = Song {
             bpm: 128,
+            sample_rate: None,
             tracks: vec![
                 Track {
+                    name: Some("lead".into()),
                     instrument: Instrument::Wavinator(
                         wavinator::Params {
                             gain: Expr::Const(0.5),
@@ -53,8 +57,21 @@ fn main() -> io::Result<()> {
                         { { c4- d4- e4- d4- } a3+ } { { c4- d4- e4- d4- } a3+ }
                         { a3 c4 } { a3 d4 } { a3 c4 } r
                     ").unwrap(),
+                    clips: vec![],
+                    effects: vec![],
+                    pan: Expr::Const(0.0),
+                    speakers: 0,
+                    bends: vec![],
+                    gain: Expr::Const(1.0),
+                    mute: false,
+                    solo: false,
+                    automation: vec![],
+                    tuning: None,
+                    freeze: false,
+                    sends: vec![AuxSend { bus: 0, amount: 1.0 }],
                 },
                 Track {
+                    name: Some("bass".into()),
                     instrument: Instrument::Wavinator(
                         wavinator::Params {
                             gain: Expr::Const(0.5),
@@ -71,8 +88,33 @@ fn main() -> io::Result<()> {
                         a1 a2- a1- a1- a1- a2
                         e1 e2- e1 e1- e2
                     ").unwrap(),
+                    clips: vec![],
+                    effects: vec![],
+                    pan: Expr::parse("* 0.2 lfo triangle 0.25").unwrap(),
+                    speakers: 0,
+                    bends: vec![],
+                    gain: Expr::Const(1.0),
+                    mute: false,
+                    solo: false,
+                    automation: vec![],
+                    tuning: None,
+                    freeze: false,
+                    sends: vec![AuxSend { bus: 0, amount: 0.3 }],
                 },
             ],
+            buses: vec![
+                // Shared reverb, the dry signal is already played by the tracks themselves
+                Bus { effects: vec![Effect::Reverb(reverb::Params { dry: 0.0, ..reverb::Params::default() })] },
+            ],
+            gain: Expr::Const(1.0),
+            effects: vec![],
+            limiter: Some(limiter::Params::default()),
+            layout: Layout::Stereo,
+            markers: vec![],
+            tags: Tags { title: Some("syn.txt demo".into()), ..Tags::default() },
+            tuning: Tuning::default(),
+            intonation: None,
+            smoothing: smoothing::DEFAULT_TIME,
         };
         Ok(song)
     })
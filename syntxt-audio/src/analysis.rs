@@ -0,0 +1,239 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Looking at the frequency content of rendered audio: magnitude spectra of windowed blocks
+//! and spectrograms following them over time.
+//!
+//! ```
+//! use syntxt_audio::analysis::{Analyzer, Window};
+//! use syntxt_audio::wave::Stereo;
+//! let samples = (0..1024)
+//!     .map(|t| Stereo::mono((2.0 * std::f64::consts::PI * 1000.0 * t as f64 / 8192.0).sin()))
+//!     .collect::<Vec<_>>();
+//! let spectrum = Analyzer::new(8192.0, 1024, Window::Hann).spectrum(&samples);
+//! assert_eq!(spectrum.peak().0, 1000.0);
+//! ```
+
+use crate::fft::{Complex, Fft};
+use crate::wave::Stereo;
+use syntxt_core::util::to_decibels;
+
+/// Weighting of the samples of a block, trading frequency resolution for less leakage
+/// between distant frequencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// All samples count the same: the sharpest peaks, but much leakage.
+    Rectangular,
+    /// A raised cosine, a good compromise.
+    Hann,
+    /// Wider peaks, but very little leakage.
+    Blackman,
+}
+
+impl Window {
+    /// The weights of a block of the given size.
+    pub fn coefficients(self, size: usize) -> Vec<f64> {
+        (0..size)
+            .map(|n| {
+                let x = 2.0 * std::f64::consts::PI * n as f64 / size as f64;
+                match self {
+                    Window::Rectangular => 1.0,
+                    Window::Hann => 0.5 - 0.5 * x.cos(),
+                    Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Magnitudes of the frequencies in a block of samples,
+/// from zero up to the Nyquist frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// Distance between the frequencies of consecutive bins, in Hz.
+    pub resolution: f64,
+    /// Amplitude of the sine at the frequency of each bin,
+    /// so a sine of amplitude 1 has a peak of about 1.
+    pub magnitudes: Vec<f64>,
+}
+
+impl Spectrum {
+    /// Center frequency of a bin in Hz.
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.resolution
+    }
+
+    /// The bin closest to a frequency.
+    pub fn bin(&self, frequency: f64) -> usize {
+        ((frequency / self.resolution).round().max(0.0) as usize).min(self.magnitudes.len() - 1)
+    }
+
+    /// Magnitude of the bin closest to a frequency.
+    pub fn magnitude(&self, frequency: f64) -> f64 {
+        self.magnitudes[self.bin(frequency)]
+    }
+
+    /// Magnitude of the bin closest to a frequency in dB relative to full scale.
+    pub fn decibels(&self, frequency: f64) -> f64 {
+        let magnitude = self.magnitude(frequency);
+        to_decibels(magnitude * magnitude)
+    }
+
+    /// Frequency and magnitude of the strongest bin.
+    pub fn peak(&self) -> (f64, f64) {
+        self.magnitudes.iter().enumerate().fold(
+            (0.0, 0.0),
+            |(frequency, peak), (bin, magnitude)| {
+                if *magnitude > peak {
+                    (self.frequency(bin), *magnitude)
+                } else {
+                    (frequency, peak)
+                }
+            },
+        )
+    }
+}
+
+/// Spectra of consecutive, possibly overlapping blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram {
+    /// Time between the starts of consecutive blocks, in seconds.
+    pub step: f64,
+    pub spectra: Vec<Spectrum>,
+}
+
+impl Spectrogram {
+    /// The spectrum of the block starting closest before the given time in seconds.
+    pub fn at(&self, seconds: f64) -> Option<&Spectrum> {
+        self.spectra.get((seconds / self.step).max(0.0) as usize)
+    }
+}
+
+/// Computes spectra of blocks of a fixed size.
+/// Both channels are mixed before the analysis.
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+    sample_rate: f64,
+    fft: Fft,
+    window: Vec<f64>,
+    /// Turns the transformed values into amplitudes of sines
+    scale: f64,
+}
+
+impl Analyzer {
+    /// Prepare an analysis of blocks of `size` samples, which must be a power of two.
+    pub fn new(sample_rate: f64, size: usize, window: Window) -> Self {
+        let window = window.coefficients(size);
+        // A sine of amplitude 1 sums up to half the sum of the window in its bin
+        let scale = 2.0 / window.iter().sum::<f64>();
+        Self {
+            sample_rate,
+            fft: Fft::new(size),
+            window,
+            scale,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.fft.size()
+    }
+
+    /// The spectrum of the first block of the samples, padded with silence if they are shorter.
+    pub fn spectrum(&self, samples: &[Stereo<f64>]) -> Spectrum {
+        let size = self.size();
+        let mut data = vec![Complex::ZERO; size];
+        for ((bin, sample), weight) in data.iter_mut().zip(samples).zip(self.window.iter()) {
+            bin.re = 0.5 * (sample.left + sample.right) * weight;
+        }
+        self.fft.forward(&mut data);
+        let mut magnitudes = data[..=size / 2]
+            .iter()
+            .map(|bin| bin.norm() * self.scale)
+            .collect::<Vec<_>>();
+        // Zero frequency has no mirror image at negative frequencies
+        magnitudes[0] /= 2.0;
+        Spectrum {
+            resolution: self.sample_rate / size as f64,
+            magnitudes,
+        }
+    }
+
+    /// The spectra of blocks starting every `hop` samples, as long as they are complete.
+    pub fn spectrogram(&self, samples: &[Stereo<f64>], hop: usize) -> Spectrogram {
+        let hop = hop.max(1);
+        let count = if samples.len() >= self.size() {
+            (samples.len() - self.size()) / hop + 1
+        } else {
+            0
+        };
+        Spectrogram {
+            step: hop as f64 / self.sample_rate,
+            spectra: (0..count)
+                .map(|index| self.spectrum(&samples[index * hop..]))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Analyzer, Window};
+    use crate::wave::Stereo;
+
+    fn sine(frequency: f64, amplitude: f64, length: usize) -> Vec<Stereo<f64>> {
+        (0..length)
+            .map(|t| {
+                let angle = 2.0 * std::f64::consts::PI * frequency * t as f64 / 8192.0;
+                Stereo::mono(amplitude * angle.sin())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn magnitudes() {
+        let samples = sine(1000.0, 0.5, 1024)
+            .iter()
+            .zip(sine(3000.0, 0.125, 1024))
+            .map(|(a, b)| *a + b + Stereo::mono(0.25))
+            .collect::<Vec<_>>();
+        for window in [Window::Rectangular, Window::Hann, Window::Blackman].iter() {
+            let spectrum = Analyzer::new(8192.0, 1024, *window).spectrum(&samples);
+            assert_eq!(spectrum.magnitudes.len(), 513);
+            assert_eq!(spectrum.frequency(512), 4096.0);
+            assert!((spectrum.magnitude(1000.0) - 0.5).abs() < 1e-9);
+            assert!((spectrum.magnitude(3000.0) - 0.125).abs() < 1e-9);
+            assert!((spectrum.magnitude(0.0) - 0.25).abs() < 1e-9);
+            assert!(spectrum.magnitude(2000.0) < 1e-9);
+            assert_eq!(spectrum.peak(), (1000.0, spectrum.magnitude(1000.0)));
+        }
+    }
+
+    #[test]
+    fn spectrogram() {
+        let mut samples = sine(512.0, 1.0, 4096);
+        samples.extend(sine(2048.0, 1.0, 4096));
+        let analyzer = Analyzer::new(8192.0, 1024, Window::Hann);
+        let spectrogram = analyzer.spectrogram(&samples, 512);
+        assert_eq!(spectrogram.spectra.len(), 15);
+        assert_eq!(spectrogram.at(0.25).unwrap().peak().0, 512.0);
+        assert_eq!(spectrogram.at(0.75).unwrap().peak().0, 2048.0);
+        assert!(spectrogram.at(2.0).is_none());
+        assert!(analyzer
+            .spectrogram(&samples[..1000], 512)
+            .spectra
+            .is_empty());
+    }
+}
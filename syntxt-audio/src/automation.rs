@@ -16,6 +16,8 @@
 
 use snafu::Snafu;
 
+use crate::lfo::{self, Lfo};
+
 /// Opaque variable datatype.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Var(usize);
@@ -28,6 +30,8 @@ pub enum Expr {
     BuiltInVar(BuiltInVar),
     BinOp(BinOp, Box<Expr>, Box<Expr>),
     UnOp(UnOp, Box<Expr>),
+    /// Value of a low frequency oscillator at the global time.
+    Lfo(Lfo),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -112,6 +116,7 @@ impl Expr {
                     UnOp::Cos => x.cos(),
                 })
             }
+            Expr::Lfo(lfo) => Ok(lfo.eval(builtins.global_time_seconds)),
         }
     }
 
@@ -131,6 +136,12 @@ impl Expr {
             Some("^") => Self::parse_binop(BinOp::Pow, input),
             Some("sin") => Self::parse_unop(UnOp::Sin, input),
             Some("cos") => Self::parse_unop(UnOp::Cos, input),
+            // LFOs with a shape and a frequency in Hz, e.g. `lfo sine 2`
+            Some("lfo") => {
+                let shape = lfo::Shape::parse(input.next()?)?;
+                let frequency = input.next()?.parse().ok()?;
+                Some(Expr::Lfo(Lfo::free(shape, frequency)))
+            }
             // Global constants
             Some("time") => Some(Expr::BuiltInVar(BuiltInVar::GlobalTimeSeconds)),
             Some("note_time") => Some(Expr::BuiltInVar(BuiltInVar::NoteTimeSeconds)),
@@ -159,6 +170,104 @@ impl Expr {
     }
 }
 
+/// How an automation curve moves from one point to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Changing by the same amount in equal times.
+    Linear,
+    /// Changing by the same factor in equal times, which sounds even for frequencies and gains.
+    /// Falls back to linear if the values have different signs or one of them is zero.
+    Exponential,
+    /// Keeping the value until the next point.
+    Step,
+}
+
+/// A point of an automation curve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurvePoint {
+    pub time: f64,
+    pub value: f64,
+    /// How the curve moves from this point to the next one.
+    pub interpolation: Interpolation,
+}
+
+/// A parameter value changing over time, given by points between which the value is interpolated.
+///
+/// The unit of time is up to the user of the curve, e.g. samples in the audio graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    /// The points ordered by time.
+    points: Vec<CurvePoint>,
+}
+
+impl Curve {
+    pub fn new(mut points: Vec<CurvePoint>) -> Self {
+        points.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Self { points }
+    }
+
+    pub fn points(&self) -> &[CurvePoint] {
+        &self.points
+    }
+
+    /// The same curve with a different unit of time, e.g. for converting measures to samples.
+    pub fn map_time(mut self, f: impl Fn(f64) -> f64) -> Self {
+        for point in self.points.iter_mut() {
+            point.time = f(point.time);
+        }
+        self
+    }
+
+    /// The value of the curve at the given time. Before the first and after the last point,
+    /// the value of that point is kept. Only a curve without points has no value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use syntxt_audio::automation::*;
+    /// let point = |time, value, interpolation| CurvePoint { time, value, interpolation };
+    /// let curve = Curve::new(vec![
+    ///     point(0.0, 1.0, Interpolation::Exponential),
+    ///     point(2.0, 100.0, Interpolation::Linear),
+    ///     point(4.0, 0.0, Interpolation::Step),
+    /// ]);
+    /// assert_eq!(curve.value_at(-1.0), Some(1.0));
+    /// assert!((curve.value_at(1.0).unwrap() - 10.0).abs() < 1e-9);
+    /// assert_eq!(curve.value_at(3.0), Some(50.0));
+    /// assert_eq!(curve.value_at(5.0), Some(0.0));
+    /// ```
+    pub fn value_at(&self, time: f64) -> Option<f64> {
+        // Index of the first point after the time
+        let next = self
+            .points
+            .iter()
+            .position(|point| point.time > time)
+            .unwrap_or(self.points.len());
+        if next == 0 {
+            return self.points.first().map(|point| point.value);
+        }
+        let from = &self.points[next - 1];
+        let to = match self.points.get(next) {
+            Some(to) => to,
+            None => return Some(from.value),
+        };
+        let progress = (time - from.time) / (to.time - from.time);
+        Some(match from.interpolation {
+            Interpolation::Step => from.value,
+            Interpolation::Exponential if from.value * to.value > 0.0 => {
+                from.value * (to.value / from.value).powf(progress)
+            }
+            Interpolation::Linear | Interpolation::Exponential => {
+                from.value + (to.value - from.value) * progress
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -216,4 +325,17 @@ mod test {
             Some(Ok(1.0))
         );
     }
+
+    #[test]
+    fn lfo() {
+        let builtins = BuiltInValues {
+            global_time_seconds: 0.75,
+            ..BuiltInValues::default()
+        };
+        assert_eq!(
+            Expr::parse("+ 1 lfo square 1").map(|x| x.eval(&builtins, &[])),
+            Some(Ok(0.0))
+        );
+        assert!(Expr::parse("lfo wobble 1").is_none());
+    }
 }
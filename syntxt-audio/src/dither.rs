@@ -0,0 +1,183 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rounding samples to the resolution of integer formats, e.g. 16 bit for CDs.
+//!
+//! Plain rounding makes the error follow the signal, which is heard as distortion in quiet
+//! passages. Adding a little noise first (dither) turns the error into a constant, benign hiss.
+
+use crate::oscillator::{Noise, NoiseColor};
+use crate::wave::{Frame, Stereo, MAX_CHANNELS};
+
+/// How samples are rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the closest value.
+    None,
+    /// Add noise with a triangular distribution of up to one step before rounding.
+    Triangular,
+    /// Triangular dither where the rounding errors are fed back, moving the noise to high
+    /// frequencies where the ear is less sensitive.
+    Shaped,
+}
+
+impl std::str::FromStr for Dither {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "none" => Ok(Dither::None),
+            "triangular" => Ok(Dither::Triangular),
+            "shaped" => Ok(Dither::Shaped),
+            _ => Err(format!(
+                "unknown dither `{}`, expected none, triangular or shaped",
+                name
+            )),
+        }
+    }
+}
+
+/// Rounds samples between -1 and 1 to a number of bits.
+#[derive(Debug, Clone)]
+pub struct Quantizer {
+    /// Number of steps between 0 and 1
+    steps: f64,
+    dither: Dither,
+    noise: Noise,
+    /// Rounding error of the previous sample of each channel, in steps
+    error: [f64; MAX_CHANNELS],
+}
+
+impl Quantizer {
+    pub fn new(bits: u32, dither: Dither) -> Self {
+        Self {
+            steps: 2.0f64.powi(bits as i32 - 1),
+            dither,
+            noise: Noise::new(NoiseColor::White, 0),
+            error: [0.0; MAX_CHANNELS],
+        }
+    }
+
+    /// Round a sample, which is still returned as float, but lies on the grid of the integer format.
+    pub fn quantize(&mut self, sample: Stereo<f64>) -> Stereo<f64> {
+        self.quantize_frame(Frame::from(sample)).pair(0)
+    }
+
+    /// Round a sample of any number of channels, like `quantize`.
+    pub fn quantize_frame(&mut self, mut frame: Frame) -> Frame {
+        for (index, value) in frame.values_mut().iter_mut().enumerate() {
+            let (rounded, error) = self.channel(*value, self.error[index]);
+            *value = rounded;
+            self.error[index] = error;
+        }
+        frame
+    }
+
+    /// Round a single value, returning it and the rounding error in steps.
+    fn channel(&mut self, value: f64, previous_error: f64) -> (f64, f64) {
+        let mut scaled = value * self.steps;
+        if self.dither == Dither::Shaped {
+            scaled -= previous_error;
+        }
+        let noise = match self.dither {
+            Dither::None => 0.0,
+            // The sum of two uniform distributions is triangular
+            Dither::Triangular | Dither::Shaped => {
+                0.5 * (self.noise.next_sample() + self.noise.next_sample())
+            }
+        };
+        let rounded = (scaled + noise).round();
+        // Clipping is not fed back, the error would grow without bounds
+        let clipped = rounded.max(-self.steps).min(self.steps - 1.0);
+        (clipped / self.steps, rounded - scaled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dither, Quantizer};
+    use crate::analysis::{Analyzer, Window};
+    use crate::wave::Stereo;
+
+    #[test]
+    fn on_grid() {
+        for dither in [Dither::None, Dither::Triangular, Dither::Shaped].iter() {
+            let mut quantizer = Quantizer::new(16, *dither);
+            for t in 0..1000 {
+                let value = (t as f64 * 0.1).sin() * 1.1;
+                let quantized = quantizer.quantize(Stereo::new(value, -value));
+                for x in [quantized.left, quantized.right].iter() {
+                    let steps = x * 32768.0;
+                    assert_eq!(steps, steps.round());
+                    assert!((-32768.0..=32767.0).contains(&steps));
+                }
+                if value.abs() < 0.9 {
+                    // Noise of up to one step, plus half a step of rounding, plus the feedback
+                    let limit = match dither {
+                        Dither::None => 0.5,
+                        Dither::Triangular => 1.5,
+                        Dither::Shaped => 3.0,
+                    };
+                    assert!((quantized.left - value).abs() * 32768.0 <= limit);
+                }
+            }
+        }
+    }
+
+    /// Dither keeps signals below the resolution on average.
+    #[test]
+    fn unbiased() {
+        let level = 0.3 / 128.0;
+        let average = |dither| {
+            let mut quantizer = Quantizer::new(8, dither);
+            (0..10000)
+                .map(|_| quantizer.quantize(Stereo::mono(level)).left)
+                .sum::<f64>()
+                / 10000.0
+        };
+        assert_eq!(average(Dither::None), 0.0);
+        assert!((average(Dither::Triangular) - level).abs() < level / 10.0);
+        assert!((average(Dither::Shaped) - level).abs() < level / 10.0);
+    }
+
+    /// Noise shaping moves the error from low to high frequencies.
+    #[test]
+    fn noise_shaping() {
+        let signal = (0..4096)
+            .map(|t| Stereo::mono(0.5 * (t as f64 * 0.05).sin()))
+            .collect::<Vec<_>>();
+        let error_spectrum = |dither| {
+            let mut quantizer = Quantizer::new(8, dither);
+            let errors = signal
+                .iter()
+                .map(|sample| quantizer.quantize(*sample) - *sample)
+                .collect::<Vec<_>>();
+            let spectrum = Analyzer::new(48000.0, 4096, Window::Hann).spectrum(&errors);
+            let power = |from: f64, to: f64| {
+                let (from, to) = (spectrum.bin(from), spectrum.bin(to));
+                spectrum.magnitudes[from..to]
+                    .iter()
+                    .map(|m| m * m)
+                    .sum::<f64>()
+            };
+            (power(20.0, 4000.0), power(16000.0, 24000.0))
+        };
+        let (flat_low, flat_high) = error_spectrum(Dither::Triangular);
+        let (shaped_low, shaped_high) = error_spectrum(Dither::Shaped);
+        assert!(shaped_low < flat_low / 4.0);
+        assert!(shaped_high > flat_high);
+    }
+}
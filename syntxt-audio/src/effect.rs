@@ -0,0 +1,54 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Effects that transform audio signals, e.g. reverb or chorus.
+
+use crate::wave::Stereo;
+
+pub mod auto_pan;
+pub mod chorus;
+pub mod compressor;
+pub mod convolution;
+pub mod distortion;
+pub mod gate;
+pub mod limiter;
+pub mod multiband;
+pub mod reverb;
+pub mod ring_mod;
+pub mod stereo_width;
+pub mod tremolo;
+
+/// Interface of an audio effect processing a stream of samples.
+pub trait Effect {
+    /// Transform the next samples of the stream in place.
+    fn process(&mut self, samples: &mut [Stereo<f64>]);
+
+    /// Change a parameter of the effect by name, taking effect with the next samples.
+    /// Returns whether the effect has a parameter of that name.
+    fn set_parameter(&mut self, _name: &str, _value: f64) -> bool {
+        false
+    }
+}
+
+impl<E: Effect + ?Sized> Effect for Box<E> {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        (**self).process(samples)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        (**self).set_parameter(name, value)
+    }
+}
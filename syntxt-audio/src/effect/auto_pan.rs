@@ -0,0 +1,107 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Auto-pan, periodically moving the signal between the left and right channel.
+
+use crate::lfo::{Lfo, Shape};
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the auto-pan.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Oscillator driving the panning, use `Lfo::synced` to follow the tempo of the song.
+    /// Positive values move the signal to the right.
+    pub lfo: Lfo,
+    /// How far the signal is moved, between 0 (stays centered) and 1 (fully to either side).
+    pub depth: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            lfo: Lfo::free(Shape::Sine, 0.5),
+            depth: 0.5,
+        }
+    }
+}
+
+pub struct AutoPan {
+    params: Params,
+    sample_rate: f64,
+    /// Number of samples processed so far, which determines the position of the LFO.
+    time: u64,
+}
+
+impl AutoPan {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        Self {
+            params,
+            sample_rate,
+            time: 0,
+        }
+    }
+}
+
+impl Effect for AutoPan {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let depth = self.params.depth.clamp(0.0, 1.0);
+        for sample in samples.iter_mut() {
+            let lfo = self.params.lfo.eval(self.time as f64 / self.sample_rate);
+            *sample = sample.constant_power_pan(depth * lfo);
+            self.time += 1;
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "frequency" => self.params.lfo.frequency = value,
+            "depth" => self.params.depth = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutoPan, Params};
+    use crate::effect::Effect;
+    use crate::lfo::{Lfo, Shape};
+    use crate::wave::Stereo;
+
+    #[test]
+    fn moves_between_channels() {
+        let mut effect = AutoPan::with_params(
+            1000.0,
+            Params {
+                lfo: Lfo::free(Shape::Triangle, 1.0),
+                depth: 1.0,
+            },
+        );
+        let mut samples = vec![Stereo::mono(1.0); 1000];
+        effect.process(&mut samples);
+        // Starting centered, fully right after a quarter period and fully left after three
+        assert!((samples[0].left - 1.0).abs() < 1e-9 && (samples[0].right - 1.0).abs() < 1e-9);
+        assert!(samples[250].left.abs() < 1e-9);
+        assert!(samples[750].right.abs() < 1e-9);
+        // The power stays constant
+        for sample in samples.iter() {
+            assert!((sample.left.powi(2) + sample.right.powi(2) - 2.0).abs() < 1e-9);
+        }
+    }
+}
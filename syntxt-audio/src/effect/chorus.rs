@@ -0,0 +1,235 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Chorus and flanger, mixing the signal with a copy of itself delayed by a slowly changing
+//! amount of time.
+//!
+//! Both use the same algorithm, a chorus uses longer delays without feedback, a flanger
+//! shorter ones with feedback, producing the characteristic comb filter sweep.
+
+use crate::oscillator::{Phase, WaveShape};
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the chorus.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Frequency of the delay modulation in Hz.
+    pub rate: f64,
+    /// Shortest delay of the copy, in seconds.
+    pub delay: f64,
+    /// How much the delay changes on top of the shortest delay, in seconds.
+    pub depth: f64,
+    /// Gain of the delayed signal fed back into the delay, between -1 and 1.
+    pub feedback: f64,
+    /// Phase offset of the modulation between left and right channel, between 0 and 1.
+    /// At 0, both channels are modulated equally, at 0.5, in opposite directions.
+    pub spread: f64,
+    /// Gain of the delayed signal.
+    pub wet: f64,
+    /// Gain of the original signal.
+    pub dry: f64,
+}
+
+impl Params {
+    /// Typical settings for a flanger.
+    pub fn flanger() -> Self {
+        Self {
+            rate: 0.2,
+            delay: 0.001,
+            depth: 0.003,
+            feedback: 0.7,
+            spread: 0.0,
+            wet: 0.7,
+            dry: 0.7,
+        }
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            rate: 0.8,
+            delay: 0.015,
+            depth: 0.005,
+            feedback: 0.0,
+            spread: 0.25,
+            wet: 0.5,
+            dry: 1.0,
+        }
+    }
+}
+
+pub struct Chorus {
+    params: Params,
+    sample_rate: f64,
+    phase: Phase,
+    lines: Stereo<DelayLine>,
+}
+
+impl Chorus {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let longest = (params.delay.max(0.0) + params.depth.max(0.0)) * sample_rate;
+        // One sample more for interpolating between the two samples around the longest delay
+        let length = longest.ceil() as usize + 2;
+        Self {
+            sample_rate,
+            phase: Phase::ZERO,
+            lines: Stereo::new(DelayLine::new(length), DelayLine::new(length)),
+            params,
+        }
+    }
+
+    /// Current delay of a channel in samples, given the phase of its modulation.
+    fn delay(&self, phase: Phase) -> f64 {
+        let modulation = 0.5 * (1.0 + WaveShape::Sine.eval(phase));
+        (self.params.delay.max(0.0) + self.params.depth.max(0.0) * modulation) * self.sample_rate
+    }
+}
+
+impl Effect for Chorus {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let feedback = self.params.feedback.clamp(-0.99, 0.99);
+        for sample in samples.iter_mut() {
+            let delay_left = self.delay(self.phase);
+            let delay_right = self.delay(self.phase.step(self.params.spread));
+            let wet = Stereo::new(
+                self.lines.left.step(sample.left, delay_left, feedback),
+                self.lines.right.step(sample.right, delay_right, feedback),
+            );
+            *sample = *sample * self.params.dry + wet * self.params.wet;
+            self.phase = self
+                .phase
+                .step_frequency(self.params.rate, self.sample_rate);
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        // The delay and depth determine the length of the delay lines and are fixed
+        match name {
+            "rate" => self.params.rate = value,
+            "feedback" => self.params.feedback = value,
+            "spread" => self.params.spread = value,
+            "wet" => self.params.wet = value,
+            "dry" => self.params.dry = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Delay line that can be read at fractional positions.
+struct DelayLine {
+    buffer: Vec<f64>,
+    /// Where the next sample is written.
+    index: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length],
+            index: 0,
+        }
+    }
+
+    /// Feed the next value into the delay line, returning the value from `delay` samples ago,
+    /// linearly interpolated between the neighbouring samples.
+    fn step(&mut self, input: f64, delay: f64, feedback: f64) -> f64 {
+        let len = self.buffer.len();
+        // At least one sample, since the current one has not been written yet
+        let delay = delay.clamp(1.0, (len - 1) as f64);
+        let whole = delay.floor() as usize;
+        let fraction = delay - whole as f64;
+        let newer = self.buffer[(self.index + len - whole) % len];
+        let older = self.buffer[(self.index + len - whole - 1) % len];
+        let output = newer + (older - newer) * fraction;
+        self.buffer[self.index] = input + output * feedback;
+        self.index = (self.index + 1) % len;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chorus, Params};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    fn impulse_response(params: Params, length: usize) -> Vec<Stereo<f64>> {
+        let mut chorus = Chorus::with_params(1000.0, params);
+        let mut samples = vec![Stereo::mono(0.0); length];
+        samples[0] = Stereo::mono(1.0);
+        chorus.process(&mut samples);
+        samples
+    }
+
+    #[test]
+    fn unmodulated_delay() {
+        let samples = impulse_response(
+            Params {
+                delay: 0.01,
+                depth: 0.0,
+                feedback: 0.5,
+                wet: 1.0,
+                dry: 0.0,
+                ..Params::default()
+            },
+            40,
+        );
+        let expected = (0..40)
+            .map(|i| match i {
+                10 => 1.0,
+                20 => 0.5,
+                30 => 0.25,
+                _ => 0.0,
+            })
+            .map(Stereo::mono)
+            .collect::<Vec<_>>();
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn spread_modulates_channels_differently() {
+        let params = Params {
+            rate: 10.0,
+            delay: 0.005,
+            depth: 0.01,
+            dry: 0.0,
+            wet: 1.0,
+            ..Params::default()
+        };
+        let same = impulse_response(
+            Params {
+                spread: 0.0,
+                ..params.clone()
+            },
+            20,
+        );
+        assert!(same.iter().all(|s| s.left == s.right));
+        let spread = impulse_response(
+            Params {
+                spread: 0.5,
+                ..params
+            },
+            20,
+        );
+        assert!(spread.iter().any(|s| s.left != s.right));
+        // Delay between 5 and 15 samples, so the impulse arrives in between
+        assert!(spread[..5].iter().all(|s| *s == Stereo::mono(0.0)));
+    }
+}
@@ -0,0 +1,209 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compressor reducing the dynamic range of a signal.
+//!
+//! The level controlling the gain reduction can be detected from a different signal
+//! than the one being compressed (sidechain), e.g. for ducking a bass whenever the kick plays.
+
+use syntxt_core::util::{from_decibels, to_decibels};
+
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the compressor.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Level in decibels above which the signal is compressed.
+    pub threshold: f64,
+    /// How much the level above the threshold is reduced, e.g. 4 for reducing
+    /// an excess of 8 dB to 2 dB.
+    pub ratio: f64,
+    /// Time in seconds the detector takes to follow a rising level.
+    pub attack: f64,
+    /// Time in seconds the detector takes to follow a falling level.
+    pub release: f64,
+    /// Gain in decibels applied after compression.
+    pub makeup: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            threshold: -20.0,
+            ratio: 4.0,
+            attack: 0.01,
+            release: 0.1,
+            makeup: 0.0,
+        }
+    }
+}
+
+pub struct Compressor {
+    params: Params,
+    attack_coeff: f64,
+    release_coeff: f64,
+    /// Current output of the envelope follower.
+    envelope: f64,
+}
+
+impl Compressor {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let coeff = |time: f64| {
+            if time > 0.0 {
+                (-1.0 / (time * sample_rate)).exp()
+            } else {
+                0.0
+            }
+        };
+        Self {
+            attack_coeff: coeff(params.attack),
+            release_coeff: coeff(params.release),
+            envelope: 0.0,
+            params,
+        }
+    }
+
+    /// Compress the samples in place, with the gain reduction controlled by the level of
+    /// the corresponding samples of `detector`.
+    pub fn process_with_detector(&mut self, samples: &mut [Stereo<f64>], detector: &[Stereo<f64>]) {
+        let makeup = from_decibels(self.params.makeup);
+        let slope = 1.0 - 1.0 / self.params.ratio.max(1.0);
+        for (sample, detected) in samples.iter_mut().zip(detector.iter()) {
+            let level = detected.left.abs().max(detected.right.abs());
+            let coeff = if level > self.envelope {
+                self.attack_coeff
+            } else {
+                self.release_coeff
+            };
+            self.envelope = level + (self.envelope - level) * coeff;
+
+            let excess = to_decibels(self.envelope) - self.params.threshold;
+            let reduction = if excess > 0.0 { excess * slope } else { 0.0 };
+            *sample *= from_decibels(-reduction) * makeup;
+        }
+    }
+}
+
+impl Effect for Compressor {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let detector = samples.to_vec();
+        self.process_with_detector(samples, &detector);
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "threshold" => self.params.threshold = value,
+            "ratio" => self.params.ratio = value,
+            "makeup" => self.params.makeup = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compressor, Params};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    fn instant() -> Params {
+        Params {
+            threshold: -20.0,
+            ratio: 4.0,
+            attack: 0.0,
+            release: 0.0,
+            makeup: 0.0,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn quiet_signal_passes_unchanged() {
+        let mut compressor = Compressor::with_params(1000.0, instant());
+        let mut samples = vec![Stereo::new(0.001, -0.005); 10];
+        compressor.process(&mut samples);
+        assert!(samples.iter().all(|s| *s == Stereo::new(0.001, -0.005)));
+    }
+
+    #[test]
+    fn loud_signal_is_compressed() {
+        let mut compressor = Compressor::with_params(1000.0, instant());
+        // 0 dB, 20 dB above the threshold, reduced to 5 dB above the threshold
+        let mut samples = vec![Stereo::mono(1.0); 10];
+        compressor.process(&mut samples);
+        assert_close(samples[9].left, 10.0f64.powf(-1.5));
+
+        let mut compressor = Compressor::with_params(
+            1000.0,
+            Params {
+                makeup: 15.0,
+                ..instant()
+            },
+        );
+        let mut samples = vec![Stereo::mono(1.0); 10];
+        compressor.process(&mut samples);
+        assert_close(samples[9].left, 1.0);
+    }
+
+    #[test]
+    fn attack_and_release() {
+        let mut compressor = Compressor::with_params(
+            1000.0,
+            Params {
+                attack: 0.01,
+                release: 0.02,
+                ..instant()
+            },
+        );
+        let mut samples = vec![Stereo::mono(1.0); 200];
+        compressor.process(&mut samples);
+        // Compression sets in gradually
+        assert!(samples[0].left > samples[10].left);
+        assert!(samples[10].left > samples[199].left);
+
+        // After the signal stops being loud, the gain recovers more slowly
+        let mut quiet = vec![Stereo::mono(0.005); 200];
+        compressor.process(&mut quiet);
+        assert!(quiet[0].left < 0.005);
+        assert!(quiet[10].left < 0.005);
+        assert_close(quiet[199].left, 0.005);
+    }
+
+    #[test]
+    fn sidechain() {
+        let mut compressor = Compressor::with_params(1000.0, instant());
+        let mut samples = vec![Stereo::mono(0.01); 4];
+        let detector = [0.0, 1.0, 1.0, 0.0]
+            .iter()
+            .map(|x| Stereo::mono(*x))
+            .collect::<Vec<_>>();
+        compressor.process_with_detector(&mut samples, &detector);
+        assert_eq!(samples[0], Stereo::mono(0.01));
+        assert_close(samples[1].left, 0.01 * 10.0f64.powf(-1.5));
+        assert_eq!(samples[3], Stereo::mono(0.01));
+    }
+}
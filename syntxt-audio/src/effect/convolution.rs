@@ -0,0 +1,250 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reverb convolving the signal with the recorded impulse response of a real space.
+//!
+//! The impulse response is split into blocks which are convolved in the frequency domain
+//! (uniformly partitioned overlap-save), so that even responses of several seconds are cheap
+//! to apply. The output is delayed by one block.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::fft::{Complex, Fft};
+use crate::wave::{SampleBuffer, Stereo};
+
+use super::Effect;
+
+/// Number of samples per block, which is also the latency of the effect.
+const BLOCK_SIZE: usize = 256;
+
+/// Parameters of the convolution reverb.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// The response of the space to a single click, e.g. loaded with `graph::load_sample`.
+    /// It is resampled if its sample rate differs from the one of the song.
+    /// The left and right channel are applied separately.
+    pub impulse: Arc<SampleBuffer>,
+    /// Gain of the reverberated signal.
+    pub wet: f64,
+    /// Gain of the original signal.
+    pub dry: f64,
+}
+
+impl Params {
+    pub fn new(impulse: Arc<SampleBuffer>) -> Self {
+        Self {
+            impulse,
+            wet: 0.3,
+            dry: 1.0,
+        }
+    }
+}
+
+/// Convolution of one channel.
+struct Channel {
+    /// Spectra of the blocks of the impulse response
+    partitions: Vec<Vec<Complex>>,
+    /// Spectra of the most recent input blocks, the newest first
+    history: VecDeque<Vec<Complex>>,
+    /// The previous and the current input block
+    window: Vec<f64>,
+}
+
+impl Channel {
+    fn new(fft: &Fft, impulse: impl Iterator<Item = f64>) -> Self {
+        let impulse = impulse.collect::<Vec<_>>();
+        let partitions = impulse
+            .chunks(BLOCK_SIZE)
+            .map(|chunk| {
+                let mut spectrum = vec![Complex::ZERO; 2 * BLOCK_SIZE];
+                for (bin, value) in spectrum.iter_mut().zip(chunk) {
+                    bin.re = *value;
+                }
+                fft.forward(&mut spectrum);
+                spectrum
+            })
+            .collect::<Vec<_>>();
+        let history = (0..partitions.len())
+            .map(|_| vec![Complex::ZERO; 2 * BLOCK_SIZE])
+            .collect();
+        Self {
+            partitions,
+            history,
+            window: vec![0.0; 2 * BLOCK_SIZE],
+        }
+    }
+
+    /// Convolve the next block of input, returning the next block of output.
+    fn process(&mut self, fft: &Fft, block: impl Iterator<Item = f64>, output: &mut [f64]) {
+        self.window.copy_within(BLOCK_SIZE.., 0);
+        for (target, value) in self.window[BLOCK_SIZE..].iter_mut().zip(block) {
+            *target = value;
+        }
+        if self.partitions.is_empty() {
+            output.iter_mut().for_each(|x| *x = 0.0);
+            return;
+        }
+
+        let mut spectrum = self.history.pop_back().unwrap();
+        for (bin, value) in spectrum.iter_mut().zip(self.window.iter()) {
+            *bin = Complex::new(*value, 0.0);
+        }
+        fft.forward(&mut spectrum);
+        self.history.push_front(spectrum);
+
+        let mut sum = vec![Complex::ZERO; 2 * BLOCK_SIZE];
+        for (input, partition) in self.history.iter().zip(self.partitions.iter()) {
+            for ((total, x), h) in sum.iter_mut().zip(input).zip(partition) {
+                *total += *x * *h;
+            }
+        }
+        fft.inverse(&mut sum);
+        // The first half is distorted by the circular convolution and discarded
+        for (out, value) in output.iter_mut().zip(sum[BLOCK_SIZE..].iter()) {
+            *out = value.re;
+        }
+    }
+}
+
+pub struct ConvolutionReverb {
+    params: Params,
+    fft: Fft,
+    left: Channel,
+    right: Channel,
+    /// Input collected for the next block
+    input: Vec<Stereo<f64>>,
+    /// Output of the last block, waiting to be returned
+    output: VecDeque<Stereo<f64>>,
+}
+
+impl ConvolutionReverb {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let fft = Fft::new(2 * BLOCK_SIZE);
+        let impulse = &params.impulse;
+        let samples = if impulse.sample_rate == sample_rate {
+            impulse.samples.clone()
+        } else {
+            // Keep the loudness by scaling with the density of the samples
+            let step = impulse.sample_rate / sample_rate;
+            let length = (impulse.samples.len() as f64 / step).ceil() as usize;
+            (0..length)
+                .map(|index| impulse.interpolate(index as f64 * step) * step)
+                .collect()
+        };
+        Self {
+            left: Channel::new(&fft, samples.iter().map(|s| s.left)),
+            right: Channel::new(&fft, samples.iter().map(|s| s.right)),
+            fft,
+            params,
+            input: Vec::with_capacity(BLOCK_SIZE),
+            output: vec![Stereo::mono(0.0); BLOCK_SIZE].into(),
+        }
+    }
+
+    fn process_block(&mut self) {
+        let mut left = [0.0; BLOCK_SIZE];
+        let mut right = [0.0; BLOCK_SIZE];
+        self.left
+            .process(&self.fft, self.input.iter().map(|s| s.left), &mut left);
+        self.right
+            .process(&self.fft, self.input.iter().map(|s| s.right), &mut right);
+        for (index, dry) in self.input.drain(..).enumerate() {
+            let wet = Stereo::new(left[index], right[index]);
+            self.output
+                .push_back(wet * self.params.wet + dry * self.params.dry);
+        }
+    }
+}
+
+impl Effect for ConvolutionReverb {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        for sample in samples.iter_mut() {
+            self.input.push(*sample);
+            if self.input.len() == BLOCK_SIZE {
+                self.process_block();
+            }
+            *sample = self.output.pop_front().unwrap();
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "wet" => self.params.wet = value,
+            "dry" => self.params.dry = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{ConvolutionReverb, Params, BLOCK_SIZE};
+    use crate::effect::Effect;
+    use crate::wave::{SampleBuffer, Stereo};
+
+    #[test]
+    fn matches_direct_convolution() {
+        // Longer than several blocks, with different channels
+        let impulse = (0..1000)
+            .map(|i| {
+                Stereo::new(
+                    (i as f64 * 0.1).sin() / (1.0 + i as f64),
+                    (i % 7) as f64 * 0.01,
+                )
+            })
+            .collect::<Vec<_>>();
+        let input = (0..3000)
+            .map(|i| {
+                Stereo::new(
+                    (i as f64 * 0.37).cos(),
+                    if i % 100 == 0 { 1.0 } else { 0.0 },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut reverb = ConvolutionReverb::with_params(
+            44100.0,
+            Params {
+                wet: 1.0,
+                dry: 0.5,
+                ..Params::new(Arc::new(SampleBuffer {
+                    sample_rate: 44100.0,
+                    samples: impulse.clone(),
+                }))
+            },
+        );
+        let mut output = input.clone();
+        // Process in odd sizes to cross block boundaries
+        for chunk in output.chunks_mut(77) {
+            reverb.process(chunk);
+        }
+
+        // Everything arrives one block late
+        for (t, actual) in output[BLOCK_SIZE..].iter().enumerate() {
+            let mut expected = input[t] * 0.5;
+            for (k, h) in impulse.iter().enumerate().take(t + 1) {
+                let x = input[t - k];
+                expected += Stereo::new(x.left * h.left, x.right * h.right);
+            }
+            assert!((actual.left - expected.left).abs() < 1e-9, "{}", t);
+            assert!((actual.right - expected.right).abs() < 1e-9, "{}", t);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Distortion shaping the signal with a non-linear transfer curve.
+//!
+//! Distortion adds harmonics that can lie above the Nyquist frequency, where they would fold
+//! back as inharmonic aliases. The curve is therefore applied at a multiple of the sample rate
+//! and the result is filtered before going back to the original rate.
+
+use syntxt_core::util::from_decibels;
+
+use crate::filter::{Biquad, BiquadCoefficients};
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Transfer curve applied to the driven signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// Smooth saturation approaching -1 and 1.
+    Tanh,
+    /// Cut off everything outside of -1 and 1.
+    HardClip,
+    /// Reflect everything outside of -1 and 1 back into that range.
+    Foldback,
+}
+
+impl Curve {
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            Curve::Tanh => x.tanh(),
+            Curve::HardClip => x.clamp(-1.0, 1.0),
+            Curve::Foldback => 1.0 - ((x + 1.0).rem_euclid(4.0) - 2.0).abs(),
+        }
+    }
+}
+
+/// Parameters of the distortion.
+#[derive(Debug, Clone)]
+pub struct Params {
+    pub curve: Curve,
+    /// Gain in decibels applied before the curve.
+    pub drive: f64,
+    /// Gain in decibels applied after the curve.
+    pub output: f64,
+    /// Amount of the distorted signal in the output, between 0 and 1.
+    pub mix: f64,
+    /// Factor by which the sample rate is raised while applying the curve, between 1 and 4.
+    pub oversampling: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            curve: Curve::Tanh,
+            drive: 12.0,
+            output: -6.0,
+            mix: 1.0,
+            oversampling: 4,
+        }
+    }
+}
+
+/// State of the cascaded biquads of the lowpass.
+type Stage = [Biquad; 4];
+
+pub struct Distortion {
+    params: Params,
+    /// Lowpass keeping only the frequencies below the original Nyquist frequency,
+    /// made of cascaded biquads for a steep slope.
+    coefficients: [BiquadCoefficients; 4],
+    /// Filters per channel removing the images of the signal when raising the sample rate.
+    upsampling: [Stage; 2],
+    /// Filters per channel removing the harmonics that would alias when lowering the sample rate.
+    downsampling: [Stage; 2],
+}
+
+impl Distortion {
+    pub fn with_params(sample_rate: f64, mut params: Params) -> Self {
+        params.oversampling = params.oversampling.clamp(1, 4);
+        let oversampled_rate = sample_rate * params.oversampling as f64;
+        Self {
+            // Q factors of an 8th order Butterworth filter
+            coefficients: [0.5098, 0.6013, 0.9000, 2.5629]
+                .map(|q| BiquadCoefficients::lowpass(oversampled_rate, sample_rate * 0.42, q)),
+            upsampling: Default::default(),
+            downsampling: Default::default(),
+            params,
+        }
+    }
+
+    /// Distort a single channel at the oversampled rate, returning the value at the original rate.
+    fn shape(&mut self, channel: usize, input: f64, drive: f64) -> f64 {
+        let factor = self.params.oversampling;
+        if factor == 1 {
+            return self.params.curve.apply(input * drive);
+        }
+        let coefficients = &self.coefficients;
+        let lowpass = |stage: &mut Stage, mut value: f64| {
+            for (filter, coefficients) in stage.iter_mut().zip(coefficients.iter()) {
+                value = filter.step(coefficients, value);
+            }
+            value
+        };
+        let mut output = 0.0;
+        for i in 0..factor {
+            // Insert zeros between the samples, the filter then interpolates them
+            let stuffed = if i == 0 { input * factor as f64 } else { 0.0 };
+            let upsampled = lowpass(&mut self.upsampling[channel], stuffed);
+            let shaped = self.params.curve.apply(upsampled * drive);
+            // Only the last of the oversampled values is kept
+            output = lowpass(&mut self.downsampling[channel], shaped);
+        }
+        output
+    }
+}
+
+impl Effect for Distortion {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let drive = from_decibels(self.params.drive);
+        let output = from_decibels(self.params.output);
+        let mix = self.params.mix.clamp(0.0, 1.0);
+        for sample in samples.iter_mut() {
+            let wet = Stereo::new(
+                self.shape(0, sample.left, drive),
+                self.shape(1, sample.right, drive),
+            );
+            *sample = *sample * (1.0 - mix) + wet * (output * mix);
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "drive" => self.params.drive = value,
+            "output" => self.params.output = value,
+            "mix" => self.params.mix = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Curve, Distortion, Params};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    #[test]
+    fn curves() {
+        for curve in [Curve::Tanh, Curve::HardClip, Curve::Foldback].iter() {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert!((curve.apply(0.25) - 0.25).abs() < 0.01);
+            assert!((curve.apply(-0.25) + 0.25).abs() < 0.01);
+        }
+        assert!((Curve::Tanh.apply(3.0) - 0.995).abs() < 0.001);
+        assert_eq!(Curve::HardClip.apply(3.0), 1.0);
+        assert_eq!(Curve::Foldback.apply(1.5), 0.5);
+        assert_eq!(Curve::Foldback.apply(-2.5), 0.5);
+    }
+
+    /// Magnitude of the given frequency in the signal (Goertzel algorithm).
+    fn magnitude(samples: &[Stereo<f64>], sample_rate: f64, frequency: f64) -> f64 {
+        let coeff = 2.0 * (2.0 * std::f64::consts::PI * frequency / sample_rate).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for sample in samples {
+            let s0 = sample.left + coeff * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        (s1 * s1 + s2 * s2 - coeff * s1 * s2).sqrt() / samples.len() as f64
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        let sample_rate = 44100.0;
+        // The fifth harmonic of 7 kHz lies at 35 kHz, aliasing to 9.1 kHz
+        let input: Vec<_> = (0..44100)
+            .map(|i| {
+                let t = i as f64 / sample_rate;
+                Stereo::mono((2.0 * std::f64::consts::PI * 7000.0 * t).sin())
+            })
+            .collect();
+        let alias = |oversampling| {
+            let mut distortion = Distortion::with_params(
+                sample_rate,
+                Params {
+                    curve: Curve::HardClip,
+                    drive: 12.0,
+                    output: 0.0,
+                    mix: 1.0,
+                    oversampling,
+                },
+            );
+            let mut samples = input.clone();
+            distortion.process(&mut samples);
+            magnitude(&samples, sample_rate, 9100.0)
+        };
+        let aliased = alias(1);
+        assert!(alias(2) * 10.0 < aliased);
+        assert!(alias(4) * 10.0 < aliased);
+    }
+}
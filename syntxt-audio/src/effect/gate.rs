@@ -0,0 +1,190 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Gate silencing the signal while it is quiet (noise gate), or following a rhythmic
+//! pattern regardless of the signal (trance gate).
+
+use syntxt_core::util::from_decibels;
+
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Time in seconds the detected level takes to fall, bridging the zero crossings of low notes.
+const DETECTOR_TIME: f64 = 0.01;
+
+/// Parameters of the gate.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Level in decibels below which the gate closes.
+    pub threshold: f64,
+    /// Time in seconds the gate takes to open.
+    pub attack: f64,
+    /// Time in seconds the gate takes to close.
+    pub release: f64,
+    /// When given, the gate follows this pattern instead of the level of the signal.
+    pub pattern: Option<Pattern>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            threshold: -40.0,
+            attack: 0.001,
+            release: 0.05,
+            pattern: None,
+        }
+    }
+}
+
+/// Rhythm of a trance gate, repeating indefinitely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    /// Time spans in seconds, relative to the start of a repetition, during which the gate is open.
+    pub openings: Vec<(f64, f64)>,
+    /// Length of one repetition in seconds.
+    pub length: f64,
+}
+
+impl Pattern {
+    /// Whether the gate is open at a point in time.
+    pub fn is_open(&self, seconds: f64) -> bool {
+        let position = if self.length > 0.0 {
+            seconds % self.length
+        } else {
+            seconds
+        };
+        self.openings
+            .iter()
+            .any(|(start, end)| (*start..*end).contains(&position))
+    }
+}
+
+pub struct Gate {
+    params: Params,
+    sample_rate: f64,
+    attack_coeff: f64,
+    release_coeff: f64,
+    detector_coeff: f64,
+    /// Decaying peak level of the signal, so that the gate does not close at every zero crossing.
+    envelope: f64,
+    /// Current gain between 0 (closed) and 1 (open).
+    gain: f64,
+    /// Number of samples processed so far, which determines the position in the pattern.
+    time: u64,
+}
+
+impl Gate {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let coeff = |time: f64| {
+            if time > 0.0 {
+                (-1.0 / (time * sample_rate)).exp()
+            } else {
+                0.0
+            }
+        };
+        Self {
+            attack_coeff: coeff(params.attack),
+            release_coeff: coeff(params.release),
+            detector_coeff: coeff(DETECTOR_TIME),
+            params,
+            sample_rate,
+            envelope: 0.0,
+            gain: 0.0,
+            time: 0,
+        }
+    }
+}
+
+impl Effect for Gate {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let threshold = from_decibels(self.params.threshold);
+        for sample in samples.iter_mut() {
+            let open = match &self.params.pattern {
+                Some(pattern) => pattern.is_open(self.time as f64 / self.sample_rate),
+                None => {
+                    let level = sample.left.abs().max(sample.right.abs());
+                    self.envelope = level.max(self.envelope * self.detector_coeff);
+                    self.envelope > threshold
+                }
+            };
+            let (target, coeff) = if open {
+                (1.0, self.attack_coeff)
+            } else {
+                (0.0, self.release_coeff)
+            };
+            self.gain = target + (self.gain - target) * coeff;
+            *sample *= self.gain;
+            self.time += 1;
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "threshold" => self.params.threshold = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Gate, Params, Pattern};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    #[test]
+    fn silences_quiet_signal() {
+        let mut gate = Gate::with_params(1000.0, Params::default());
+        // A loud tone followed by quiet noise
+        let mut samples = (0..1000)
+            .map(|i| {
+                let amplitude = if i < 500 { 0.5 } else { 1e-5 };
+                Stereo::mono(amplitude * (i as f64 * 0.3).sin())
+            })
+            .collect::<Vec<_>>();
+        let original = samples.clone();
+        gate.process(&mut samples);
+        // Open without chattering during the tone, closed once the release is over
+        for (out, input) in samples[10..500].iter().zip(original[10..500].iter()) {
+            assert!((out.left - input.left).abs() < 1e-3);
+        }
+        assert!(samples[800..].iter().all(|s| s.left.abs() < 1e-6));
+    }
+
+    #[test]
+    fn follows_pattern() {
+        let mut gate = Gate::with_params(
+            1000.0,
+            Params {
+                attack: 0.0,
+                release: 0.0,
+                pattern: Some(Pattern {
+                    openings: vec![(0.0, 0.1), (0.2, 0.25)],
+                    length: 0.5,
+                }),
+                ..Params::default()
+            },
+        );
+        let mut samples = vec![Stereo::mono(1.0); 1000];
+        gate.process(&mut samples);
+        let open = |time: usize| samples[time].left == 1.0;
+        assert!(open(0) && open(99) && !open(100) && open(220) && !open(300));
+        // The pattern repeats
+        assert!(open(550) && !open(650) && open(720));
+    }
+}
@@ -0,0 +1,201 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Brickwall limiter keeping the signal below a ceiling, e.g. at the end of the master chain
+//! so that many tracks summing up do not clip.
+//!
+//! The signal is delayed by a short lookahead, so that the gain can already be reduced smoothly
+//! before a peak arrives instead of distorting it.
+
+use std::collections::VecDeque;
+
+use syntxt_core::util::from_decibels;
+
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the limiter.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Level in decibels that the output never exceeds.
+    pub ceiling: f64,
+    /// Time in seconds by which the signal is delayed, and over which the gain is reduced
+    /// ahead of a peak.
+    pub lookahead: f64,
+    /// Time in seconds the gain takes to recover after a peak.
+    pub release: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            ceiling: -0.3,
+            lookahead: 0.005,
+            release: 0.1,
+        }
+    }
+}
+
+pub struct Limiter {
+    ceiling: f64,
+    release_coeff: f64,
+    /// Length of the lookahead in samples.
+    lookahead: usize,
+    /// The input samples that have not been output yet.
+    delay: VecDeque<Stereo<f64>>,
+    /// Candidates for the smallest gain needed by the samples in the lookahead window,
+    /// as (sample index, gain) with increasing gains.
+    minimum: VecDeque<(usize, f64)>,
+    /// The last gains after release, averaged for a smooth transition to a peak.
+    gains: VecDeque<f64>,
+    gain_sum: f64,
+    /// Index of the next input sample.
+    index: usize,
+    release_gain: f64,
+}
+
+impl Limiter {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let lookahead = (params.lookahead.max(0.0) * sample_rate).round() as usize;
+        Self {
+            ceiling: from_decibels(params.ceiling),
+            release_coeff: if params.release > 0.0 {
+                (-1.0 / (params.release * sample_rate)).exp()
+            } else {
+                0.0
+            },
+            lookahead,
+            delay: vec![Stereo::mono(0.0); lookahead].into(),
+            minimum: VecDeque::new(),
+            gains: vec![1.0; lookahead + 1].into(),
+            gain_sum: (lookahead + 1) as f64,
+            index: 0,
+            release_gain: 1.0,
+        }
+    }
+
+    /// Process the next input sample, returning the limited sample from `lookahead` samples ago.
+    fn step(&mut self, input: Stereo<f64>) -> Stereo<f64> {
+        // The gain this sample needs to stay below the ceiling
+        let peak = input.left.abs().max(input.right.abs());
+        let needed = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        // Smallest needed gain of the current sample and the delayed ones still waiting
+        while matches!(self.minimum.back(), Some((_, gain)) if *gain >= needed) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.index, needed));
+        while matches!(self.minimum.front(), Some((index, _)) if *index + self.lookahead < self.index)
+        {
+            self.minimum.pop_front();
+        }
+        let window_minimum = self.minimum.front().map_or(1.0, |(_, gain)| *gain);
+
+        // Reduce the gain instantly, but recover slowly
+        self.release_gain = if window_minimum < self.release_gain {
+            window_minimum
+        } else {
+            window_minimum + (self.release_gain - window_minimum) * self.release_coeff
+        };
+
+        // Every gain averaged here applies to the delayed sample as well,
+        // so the average never exceeds the gain it needs.
+        self.gains.push_back(self.release_gain);
+        self.gain_sum += self.release_gain;
+        self.gain_sum -= self.gains.pop_front().unwrap_or(0.0);
+        let gain = self.gain_sum / self.gains.len() as f64;
+
+        self.index += 1;
+        self.delay.push_back(input);
+        let output = self.delay.pop_front().unwrap_or(input) * gain;
+        // Guard against rounding errors of the running sum
+        Stereo::new(
+            output.left.clamp(-self.ceiling, self.ceiling),
+            output.right.clamp(-self.ceiling, self.ceiling),
+        )
+    }
+}
+
+impl Effect for Limiter {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        for sample in samples.iter_mut() {
+            *sample = self.step(*sample);
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "ceiling" => self.ceiling = from_decibels(value),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limiter, Params};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    fn sine(amplitude: f64) -> Vec<Stereo<f64>> {
+        (0..2000)
+            .map(|i| Stereo::mono(amplitude * (i as f64 * 0.05).sin()))
+            .collect()
+    }
+
+    #[test]
+    fn quiet_signal_is_delayed() {
+        let mut limiter = Limiter::with_params(1000.0, Params::default());
+        let input = sine(0.5);
+        let mut output = input.clone();
+        limiter.process(&mut output);
+        // 5 ms lookahead at 1000 Hz
+        assert!(output[..5].iter().all(|s| *s == Stereo::mono(0.0)));
+        assert_eq!(output[5..], input[..input.len() - 5]);
+    }
+
+    #[test]
+    fn loud_signal_stays_below_ceiling() {
+        let params = Params {
+            ceiling: 0.0,
+            ..Params::default()
+        };
+        let mut limiter = Limiter::with_params(10000.0, params);
+        let input = sine(4.0);
+        let mut output = input.clone();
+        limiter.process(&mut output);
+        assert!(output.iter().all(|s| s.left.abs() <= 1.0));
+        let peak = output.iter().map(|s| s.left.abs()).fold(0.0, f64::max);
+        assert!(peak > 0.9);
+        // The gain changes smoothly instead of clipping the peaks
+        let gains = output[50..]
+            .iter()
+            .zip(input.iter())
+            .map(|(o, i)| Some(o.left / i.left).filter(|_| i.left.abs() > 0.1))
+            .collect::<Vec<_>>();
+        let largest_change = gains
+            .windows(2)
+            .filter_map(|w| Some((w[1]? - w[0]?).abs()))
+            .fold(0.0, f64::max);
+        assert!(largest_change < 0.02, "gain changed by {}", largest_change);
+    }
+}
@@ -0,0 +1,240 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Multiband compressor, splitting the signal into frequency bands which are compressed
+//! independently, e.g. for taming the bass of a mix without pumping the highs.
+//!
+//! The bands are separated by Linkwitz-Riley crossovers, so that they add up to the
+//! original signal (up to a change of phase) when no band is compressed.
+
+use crate::filter::{Biquad, BiquadCoefficients};
+use crate::wave::Stereo;
+
+use super::compressor::{self, Compressor};
+use super::Effect;
+
+/// Parameters of the multiband compressor.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Frequencies in Hz separating the bands, in ascending order.
+    pub crossovers: Vec<f64>,
+    /// Compression of each band, from the lowest to the highest.
+    /// There is one band more than there are crossovers, missing bands use the default compressor.
+    pub bands: Vec<compressor::Params>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            crossovers: vec![200.0, 2000.0],
+            bands: vec![compressor::Params::default(); 3],
+        }
+    }
+}
+
+/// Fourth order Linkwitz-Riley filter, i.e. two identical Butterworth filters in series.
+struct LinkwitzRiley {
+    coefficients: BiquadCoefficients,
+    left: [Biquad; 2],
+    right: [Biquad; 2],
+}
+
+impl LinkwitzRiley {
+    fn new(coefficients: BiquadCoefficients) -> Self {
+        Self {
+            coefficients,
+            left: [Biquad::new(), Biquad::new()],
+            right: [Biquad::new(), Biquad::new()],
+        }
+    }
+
+    fn step(&mut self, input: Stereo<f64>) -> Stereo<f64> {
+        let c = &self.coefficients;
+        let [left0, left1] = &mut self.left;
+        let [right0, right1] = &mut self.right;
+        Stereo::new(
+            left1.step(c, left0.step(c, input.left)),
+            right1.step(c, right0.step(c, input.right)),
+        )
+    }
+}
+
+/// Split of a signal into the frequencies below and above a crossover frequency.
+struct Crossover {
+    lowpass: LinkwitzRiley,
+    highpass: LinkwitzRiley,
+}
+
+impl Crossover {
+    fn new(sample_rate: f64, frequency: f64) -> Self {
+        let q = std::f64::consts::FRAC_1_SQRT_2;
+        Self {
+            lowpass: LinkwitzRiley::new(BiquadCoefficients::lowpass(sample_rate, frequency, q)),
+            highpass: LinkwitzRiley::new(BiquadCoefficients::highpass(sample_rate, frequency, q)),
+        }
+    }
+
+    /// Return the low and the high part of the input.
+    fn split(&mut self, input: Stereo<f64>) -> (Stereo<f64>, Stereo<f64>) {
+        (self.lowpass.step(input), self.highpass.step(input))
+    }
+}
+
+/// One frequency band of the signal.
+struct Band {
+    /// Separates this band from the higher ones, missing for the highest band.
+    crossover: Option<Crossover>,
+    /// Crossovers of the higher bands, applied as allpass filters so that the phase
+    /// of this band matches the phase of the higher ones.
+    compensation: Vec<Crossover>,
+    compressor: Compressor,
+    buffer: Vec<Stereo<f64>>,
+}
+
+pub struct MultibandCompressor {
+    bands: Vec<Band>,
+}
+
+impl MultibandCompressor {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let bands = (0..=params.crossovers.len())
+            .map(|index| Band {
+                crossover: params
+                    .crossovers
+                    .get(index)
+                    .map(|frequency| Crossover::new(sample_rate, *frequency)),
+                compensation: params
+                    .crossovers
+                    .iter()
+                    .skip(index + 1)
+                    .map(|frequency| Crossover::new(sample_rate, *frequency))
+                    .collect(),
+                compressor: Compressor::with_params(
+                    sample_rate,
+                    params.bands.get(index).cloned().unwrap_or_default(),
+                ),
+                buffer: Vec::new(),
+            })
+            .collect();
+        Self { bands }
+    }
+}
+
+impl Effect for MultibandCompressor {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        for band in self.bands.iter_mut() {
+            band.buffer.clear();
+        }
+        for sample in samples.iter() {
+            let mut rest = *sample;
+            for band in self.bands.iter_mut() {
+                let mut value = match &mut band.crossover {
+                    Some(crossover) => {
+                        let (low, high) = crossover.split(rest);
+                        rest = high;
+                        low
+                    }
+                    None => rest,
+                };
+                for allpass in band.compensation.iter_mut() {
+                    let (low, high) = allpass.split(value);
+                    value = low + high;
+                }
+                band.buffer.push(value);
+            }
+        }
+
+        for band in self.bands.iter_mut() {
+            band.compressor.process(&mut band.buffer);
+        }
+        for (index, sample) in samples.iter_mut().enumerate() {
+            *sample = self.bands.iter().map(|band| band.buffer[index]).sum();
+        }
+    }
+
+    /// The parameters of the compressors are prefixed with the index of their band,
+    /// e.g. `0.threshold` for the threshold of the lowest band.
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        let (index, name) = match name.find('.') {
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+            None => return false,
+        };
+        match index
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| self.bands.get_mut(i))
+        {
+            Some(band) => band.compressor.set_parameter(name, value),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MultibandCompressor, Params};
+    use crate::effect::{compressor, Effect};
+    use crate::wave::Stereo;
+
+    /// Power of a sine wave after processing, skipping the transient of the filters.
+    fn power(params: Params, frequency: f64) -> f64 {
+        let mut effect = MultibandCompressor::with_params(44100.0, params);
+        let mut samples = (0..44100)
+            .map(|i| {
+                Stereo::mono((i as f64 / 44100.0 * frequency * 2.0 * std::f64::consts::PI).sin())
+            })
+            .collect::<Vec<_>>();
+        effect.process(&mut samples);
+        samples[4410..].iter().map(|s| s.left.powi(2)).sum::<f64>() / (samples.len() - 4410) as f64
+    }
+
+    fn neutral() -> compressor::Params {
+        compressor::Params {
+            ratio: 1.0,
+            ..compressor::Params::default()
+        }
+    }
+
+    #[test]
+    fn bands_add_up() {
+        let params = Params {
+            crossovers: vec![150.0, 1000.0, 6000.0],
+            bands: vec![neutral(); 4],
+        };
+        for frequency in [50.0, 150.0, 400.0, 1000.0, 3000.0, 6000.0, 12000.0].iter() {
+            let power = power(params.clone(), *frequency);
+            assert!((power - 0.5).abs() < 1e-3, "{} Hz: {}", frequency, power);
+        }
+    }
+
+    #[test]
+    fn compresses_bands_independently() {
+        let params = Params {
+            bands: vec![
+                compressor::Params {
+                    threshold: -20.0,
+                    ratio: 10.0,
+                    ..compressor::Params::default()
+                },
+                neutral(),
+                neutral(),
+            ],
+            ..Params::default()
+        };
+        assert!(power(params.clone(), 60.0) < 0.05);
+        assert!((power(params, 6000.0) - 0.5).abs() < 1e-3);
+    }
+}
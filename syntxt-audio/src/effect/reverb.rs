@@ -0,0 +1,259 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Algorithmic reverb based on Freeverb, a network of parallel comb filters followed by
+//! allpass filters in series.
+
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the reverb.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Size of the simulated room between 0 and 1, controls how long the reverb tail is.
+    pub room_size: f64,
+    /// How much high frequencies are absorbed, between 0 (bright) and 1 (dark).
+    pub damping: f64,
+    /// Time before the reverb starts, in seconds.
+    pub pre_delay: f64,
+    /// Gain of the reverberated signal.
+    pub wet: f64,
+    /// Gain of the original signal.
+    pub dry: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            room_size: 0.5,
+            damping: 0.5,
+            pre_delay: 0.0,
+            wet: 0.3,
+            dry: 1.0,
+        }
+    }
+}
+
+/// Delay lengths of the comb filters in samples at 44.1 kHz, as tuned for Freeverb.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Delay lengths of the allpass filters in samples at 44.1 kHz.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Additional delay of the right channel, decorrelating the channels.
+const STEREO_SPREAD: usize = 23;
+
+/// Input gain, keeping the sum of the comb filters in range.
+const FIXED_GAIN: f64 = 0.015;
+const SCALE_ROOM: f64 = 0.28;
+const OFFSET_ROOM: f64 = 0.7;
+const SCALE_DAMPING: f64 = 0.4;
+const ALLPASS_FEEDBACK: f64 = 0.5;
+
+pub struct Reverb {
+    params: Params,
+    pre_delay: Delay,
+    channels: Stereo<Channel>,
+}
+
+impl Reverb {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        let scale = |samples: usize| ((samples as f64 * sample_rate / 44100.0) as usize).max(1);
+        let channel = |spread: usize| Channel {
+            combs: COMB_TUNING
+                .iter()
+                .map(|len| Comb::new(scale(len + spread)))
+                .collect(),
+            allpasses: ALLPASS_TUNING
+                .iter()
+                .map(|len| Allpass::new(scale(len + spread)))
+                .collect(),
+        };
+        Self {
+            pre_delay: Delay::new((params.pre_delay.max(0.0) * sample_rate) as usize),
+            channels: Stereo::new(channel(0), channel(STEREO_SPREAD)),
+            params,
+        }
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let feedback = self.params.room_size.clamp(0.0, 1.0) * SCALE_ROOM + OFFSET_ROOM;
+        let damping = self.params.damping.clamp(0.0, 1.0) * SCALE_DAMPING;
+        for sample in samples.iter_mut() {
+            let input = self
+                .pre_delay
+                .step((sample.left + sample.right) * FIXED_GAIN);
+            let wet = Stereo::new(
+                self.channels.left.step(input, feedback, damping),
+                self.channels.right.step(input, feedback, damping),
+            );
+            *sample = *sample * self.params.dry + wet * self.params.wet;
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "room_size" => self.params.room_size = value,
+            "damping" => self.params.damping = value,
+            "wet" => self.params.wet = value,
+            "dry" => self.params.dry = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The filter network of a single channel.
+struct Channel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Channel {
+    fn step(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let mut output = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.step(input, feedback, damping))
+            .sum();
+        for allpass in self.allpasses.iter_mut() {
+            output = allpass.step(output);
+        }
+        output
+    }
+}
+
+/// Fixed delay of a signal.
+struct Delay {
+    buffer: Vec<f64>,
+    index: usize,
+}
+
+impl Delay {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length],
+            index: 0,
+        }
+    }
+
+    /// Feed the next value into the delay line, returning the value from `length` samples ago.
+    fn step(&mut self, input: f64) -> f64 {
+        if self.buffer.is_empty() {
+            return input;
+        }
+        let output = std::mem::replace(&mut self.buffer[self.index], input);
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// Feedback comb filter with a lowpass filter in the feedback path.
+struct Comb {
+    delay: Delay,
+    /// State of the lowpass filter.
+    filtered: f64,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Self {
+            delay: Delay::new(length),
+            filtered: 0.0,
+        }
+    }
+
+    fn step(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let output = self.delay.buffer[self.delay.index];
+        self.filtered = output * (1.0 - damping) + self.filtered * damping;
+        self.delay.step(input + self.filtered * feedback);
+        output
+    }
+}
+
+/// Allpass filter diffusing the echoes of the comb filters.
+struct Allpass {
+    delay: Delay,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Self {
+            delay: Delay::new(length),
+        }
+    }
+
+    fn step(&mut self, input: f64) -> f64 {
+        let delayed = self.delay.buffer[self.delay.index];
+        self.delay.step(input + delayed * ALLPASS_FEEDBACK);
+        delayed - input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Params, Reverb};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    fn impulse_response(params: Params, length: usize) -> Vec<Stereo<f64>> {
+        let mut reverb = Reverb::with_params(44100.0, params);
+        let mut samples = vec![Stereo::mono(0.0); length];
+        samples[0] = Stereo::mono(1.0);
+        reverb.process(&mut samples);
+        samples
+    }
+
+    #[test]
+    fn dry_signal_passes_unchanged() {
+        let samples = impulse_response(
+            Params {
+                wet: 0.0,
+                ..Params::default()
+            },
+            100,
+        );
+        assert_eq!(samples[0], Stereo::mono(1.0));
+        assert!(samples[1..].iter().all(|s| *s == Stereo::mono(0.0)));
+    }
+
+    #[test]
+    fn tail_decays() {
+        let energy = |samples: &[Stereo<f64>]| {
+            samples
+                .iter()
+                .map(|s| s.left * s.left + s.right * s.right)
+                .sum::<f64>()
+        };
+        let samples = impulse_response(
+            Params {
+                pre_delay: 0.01,
+                dry: 0.0,
+                ..Params::default()
+            },
+            44100 * 2,
+        );
+        // Nothing is heard before the pre-delay and the shortest comb filter
+        assert_eq!(energy(&samples[..441 + 1116]), 0.0);
+        let early = energy(&samples[..22050]);
+        let late = energy(&samples[66150..]);
+        assert!(early > 0.0);
+        assert!(late < early / 10.0);
+        // The right channel is delayed a bit more
+        assert_ne!(samples[441 + 1116].left, samples[441 + 1116].right);
+    }
+}
@@ -0,0 +1,129 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Ring modulator, multiplying the signal with an internal oscillator.
+//!
+//! With a modulator in the audible range, this produces metallic, bell-like sounds,
+//! with a slow modulator and amplitude modulation, a tremolo.
+
+use crate::oscillator::{Modulation, Phase, WaveShape};
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the ring modulator.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// How the signal is combined with the oscillator.
+    pub modulation: Modulation,
+    /// Shape of the oscillator.
+    pub shape: WaveShape,
+    /// Frequency of the oscillator in Hz.
+    pub frequency: f64,
+    /// How strongly the signal is modulated, between 0 (unchanged) and 1 (fully modulated).
+    pub depth: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            modulation: Modulation::Ring,
+            shape: WaveShape::Sine,
+            frequency: 440.0,
+            depth: 1.0,
+        }
+    }
+}
+
+pub struct RingModulator {
+    params: Params,
+    sample_rate: f64,
+    phase: Phase,
+}
+
+impl RingModulator {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        Self {
+            params,
+            sample_rate,
+            phase: Phase::ZERO,
+        }
+    }
+}
+
+impl Effect for RingModulator {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let depth = self.params.depth.clamp(0.0, 1.0);
+        for sample in samples.iter_mut() {
+            let modulator = self.params.shape.eval(self.phase);
+            let modulation = self.params.modulation;
+            *sample = Stereo::new(
+                modulation.apply(sample.left, modulator, depth),
+                modulation.apply(sample.right, modulator, depth),
+            );
+            self.phase = self
+                .phase
+                .step_frequency(self.params.frequency, self.sample_rate);
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "frequency" => self.params.frequency = value,
+            "depth" => self.params.depth = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Params, RingModulator};
+    use crate::effect::Effect;
+    use crate::oscillator::Modulation;
+    use crate::wave::Stereo;
+
+    /// Modulate a constant signal, which reproduces the (possibly shifted) modulator.
+    fn modulate_constant(params: Params) -> Vec<f64> {
+        let mut effect = RingModulator::with_params(1000.0, params);
+        let mut samples = vec![Stereo::mono(1.0); 1000];
+        effect.process(&mut samples);
+        samples.iter().map(|s| s.left).collect()
+    }
+
+    #[test]
+    fn ring_and_amplitude_modulation() {
+        let ring = modulate_constant(Params {
+            frequency: 10.0,
+            ..Params::default()
+        });
+        let (min, max) = ring
+            .iter()
+            .fold((0.0f64, 0.0f64), |(min, max), x| (min.min(*x), max.max(*x)));
+        assert!(min < -0.99 && max > 0.99);
+
+        let amplitude = modulate_constant(Params {
+            modulation: Modulation::Amplitude,
+            frequency: 10.0,
+            depth: 0.5,
+            ..Params::default()
+        });
+        // Only ever attenuating by up to half
+        assert!(amplitude.iter().all(|x| (0.5..=1.0).contains(x)));
+        assert!(amplitude.iter().any(|x| *x < 0.51));
+    }
+}
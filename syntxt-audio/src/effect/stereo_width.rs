@@ -0,0 +1,142 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Stereo width, making the signal narrower or wider by changing the level of its side
+//! component relative to its mid component.
+//!
+//! Low frequencies can be collapsed to mono, keeping bass and kick centered
+//! regardless of the width of the rest of the signal.
+
+use crate::filter::{Biquad, BiquadCoefficients};
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the stereo width effect.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Gain of the side component: 0 is mono, 1 leaves the signal unchanged
+    /// and larger values widen it.
+    pub width: f64,
+    /// Frequency in Hz below which the signal is turned into mono.
+    pub mono_below: Option<f64>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            mono_below: None,
+        }
+    }
+}
+
+pub struct StereoWidth {
+    params: Params,
+    /// Highpass removing the low frequencies of the side component.
+    coefficients: Option<BiquadCoefficients>,
+    filter: Biquad,
+}
+
+impl StereoWidth {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        Self {
+            coefficients: params.mono_below.map(|cutoff| {
+                BiquadCoefficients::highpass(sample_rate, cutoff, std::f64::consts::FRAC_1_SQRT_2)
+            }),
+            filter: Biquad::new(),
+            params,
+        }
+    }
+}
+
+impl Effect for StereoWidth {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let width = self.params.width.max(0.0);
+        for sample in samples.iter_mut() {
+            let (mid, mut side) = sample.to_mid_side();
+            if let Some(coefficients) = &self.coefficients {
+                side = self.filter.step(coefficients, side);
+            }
+            *sample = Stereo::from_mid_side(mid, side * width);
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "width" => self.params.width = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Params, StereoWidth};
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    /// Process a signal with different content on both channels, returning the power
+    /// of the side component after skipping the transient of the filter.
+    fn side_power(params: Params, frequency: f64) -> f64 {
+        let mut effect = StereoWidth::with_params(44100.0, params);
+        let mut samples = (0..44100)
+            .map(|i| {
+                let phase = i as f64 / 44100.0 * frequency * 2.0 * std::f64::consts::PI;
+                Stereo::new(phase.sin(), 0.0)
+            })
+            .collect::<Vec<_>>();
+        effect.process(&mut samples);
+        samples[4410..]
+            .iter()
+            .map(|s| s.to_mid_side().1.powi(2))
+            .sum::<f64>()
+            / (samples.len() - 4410) as f64
+    }
+
+    #[test]
+    fn width() {
+        let unchanged = side_power(Params::default(), 1000.0);
+        assert!((unchanged - 0.125).abs() < 1e-3);
+        let mono = side_power(
+            Params {
+                width: 0.0,
+                ..Params::default()
+            },
+            1000.0,
+        );
+        assert_eq!(mono, 0.0);
+        let wide = side_power(
+            Params {
+                width: 2.0,
+                ..Params::default()
+            },
+            1000.0,
+        );
+        assert!((wide - 4.0 * unchanged).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mono_below() {
+        let params = Params {
+            mono_below: Some(200.0),
+            ..Params::default()
+        };
+        assert!(side_power(params.clone(), 40.0) < 0.125 / 100.0);
+        assert!((side_power(params, 5000.0) - 0.125).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,104 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tremolo, periodically changing the volume of the signal.
+
+use crate::lfo::{Lfo, Shape};
+use crate::wave::Stereo;
+
+use super::Effect;
+
+/// Parameters of the tremolo.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Oscillator driving the volume, use `Lfo::synced` to follow the tempo of the song.
+    pub lfo: Lfo,
+    /// How much the volume is reduced at the lowest point of the oscillator,
+    /// between 0 (unchanged) and 1 (silent).
+    pub depth: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            lfo: Lfo::free(Shape::Sine, 5.0),
+            depth: 0.5,
+        }
+    }
+}
+
+pub struct Tremolo {
+    params: Params,
+    sample_rate: f64,
+    /// Number of samples processed so far, which determines the position of the LFO.
+    time: u64,
+}
+
+impl Tremolo {
+    pub fn with_params(sample_rate: f64, params: Params) -> Self {
+        Self {
+            params,
+            sample_rate,
+            time: 0,
+        }
+    }
+}
+
+impl Effect for Tremolo {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        let depth = self.params.depth.clamp(0.0, 1.0);
+        for sample in samples.iter_mut() {
+            let lfo = self.params.lfo.eval(self.time as f64 / self.sample_rate);
+            *sample *= 1.0 - depth * (1.0 - lfo) / 2.0;
+            self.time += 1;
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "frequency" => self.params.lfo.frequency = value,
+            "depth" => self.params.depth = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Params, Tremolo};
+    use crate::effect::Effect;
+    use crate::lfo::{Lfo, Shape};
+    use crate::wave::Stereo;
+
+    #[test]
+    fn volume_follows_lfo() {
+        let mut effect = Tremolo::with_params(
+            1000.0,
+            Params {
+                lfo: Lfo::free(Shape::Square, 2.0),
+                depth: 0.75,
+            },
+        );
+        let mut samples = vec![Stereo::new(1.0, -1.0); 1000];
+        effect.process(&mut samples);
+        // Full volume in the first half of every period, a quarter in the second half
+        assert_eq!(samples[0], Stereo::new(1.0, -1.0));
+        assert_eq!(samples[300], Stereo::new(0.25, -0.25));
+        assert_eq!(samples[600], Stereo::new(1.0, -1.0));
+        assert_eq!(samples[999], Stereo::new(0.25, -0.25));
+    }
+}
@@ -14,12 +14,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-/// An Attack-Decay-Sustain-Release envelope.
+//! Envelopes shaping the volume of notes over time.
+
+/// An Attack-Decay-Sustain-Release envelope with linear segments.
 /// When a key is pressed, the amplitude first rises from zero to one over `attack` seconds,
 /// then decays over an additional `decay` seconds to the `sustain` level where it is held
 /// as long as the key is pressed. When the key is released, the volume falls back to zero
 /// over the next `release` seconds.
 ///
+/// See `Envelope` for additional stages and curves.
+///
 /// # Example
 ///
 /// ```
@@ -71,68 +75,256 @@ pub struct ADSR {
 }
 
 impl ADSR {
-    pub fn instantiate(&self, sample_rate: f64) -> EvalADSR {
-        // TODO: what happens if result is not representable as usize?
-        EvalADSR {
-            attack_samples: (self.attack * sample_rate).round() as usize,
-            decay_samples: (self.decay * sample_rate).round() as usize,
-            release_samples: (self.release * sample_rate).round() as usize,
+    pub fn instantiate(&self, sample_rate: f64) -> EvalEnvelope {
+        Envelope::from(self.clone()).instantiate(sample_rate)
+    }
+}
+
+/// The shape of the transition from one level to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// Changes at a constant rate.
+    Linear,
+    /// Changes quickly at first and slows down towards the target,
+    /// like analog envelopes. Makes for punchy drum decays.
+    Exponential,
+    /// Changes slowly at first and speeds up towards the target, e.g. for swelling pads.
+    Logarithmic,
+}
+
+impl Curve {
+    /// Steepness of the non-linear curves.
+    const STEEPNESS: f64 = 4.0;
+
+    /// Map the progress through a segment between 0 and 1 to the fraction of the way
+    /// between its start and target level.
+    pub fn apply(self, progress: f64) -> f64 {
+        let k = Self::STEEPNESS;
+        match self {
+            Curve::Linear => progress,
+            Curve::Exponential => (1.0 - (-k * progress).exp()) / (1.0 - (-k).exp()),
+            Curve::Logarithmic => ((k * progress).exp() - 1.0) / (k.exp() - 1.0),
+        }
+    }
+}
+
+/// What happens when an envelope is triggered again while it is still sounding,
+/// e.g. when a monophonic instrument plays the next note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retrigger {
+    /// Start over from silence, including the delay.
+    Reset,
+    /// Attack again starting from the current level, avoiding clicks.
+    Continue,
+    /// Keep going as if nothing happened, unless the envelope was already released,
+    /// in which case it attacks again from the current level.
+    Legato,
+}
+
+/// A Delay-Attack-Hold-Decay-Sustain-Release envelope.
+/// When a key is pressed, the amplitude stays at zero for `delay` seconds, then rises to one
+/// over `attack` seconds, where it is held for `hold` seconds. It then decays over `decay`
+/// seconds to the `sustain` level, which is held as long as the key is pressed.
+/// When the key is released, the volume falls back to zero over the next `release` seconds.
+///
+/// # Example
+///
+/// ```
+/// use syntxt_audio::envelope::*;
+/// let e = Envelope {
+///     delay: 0.5,
+///     attack: 0.25,
+///     hold: 0.25,
+///     decay: 0.5,
+///     sustain: 0.5,
+///     decay_curve: Curve::Exponential,
+///     ..Envelope::default()
+/// };
+/// let mut eval = e.instantiate(4.0); // 4 samples per second
+/// assert_eq!(eval.step(), 0.0);
+/// assert_eq!(eval.step(), 0.0);
+/// assert_eq!(eval.step(), 0.0);
+/// assert_eq!(eval.step(), 1.0);
+/// assert_eq!(eval.step(), 1.0);
+/// // The exponential decay has almost reached the sustain level half way through
+/// assert!((eval.step() - 0.56).abs() < 0.01);
+/// assert_eq!(eval.step(), 0.5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    /// Time in seconds before the attack starts.
+    pub delay: f64,
+    /// Time in seconds to go from 0.0 to 1.0
+    pub attack: f64,
+    /// Time in seconds to stay at 1.0.
+    pub hold: f64,
+    /// Time in seconds to go from 1.0 to `sustain`.
+    pub decay: f64,
+    /// Constant amplitude while key is held.
+    pub sustain: f64,
+    /// Time in seconds to go from `sustain` to 0.0.
+    pub release: f64,
+    pub attack_curve: Curve,
+    pub decay_curve: Curve,
+    pub release_curve: Curve,
+    pub retrigger: Retrigger,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            delay: 0.0,
+            attack: 0.01,
+            hold: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.1,
+            attack_curve: Curve::Linear,
+            decay_curve: Curve::Linear,
+            release_curve: Curve::Linear,
+            retrigger: Retrigger::Reset,
+        }
+    }
+}
+
+impl From<ADSR> for Envelope {
+    fn from(adsr: ADSR) -> Self {
+        Self {
+            delay: 0.0,
+            attack: adsr.attack,
+            hold: 0.0,
+            decay: adsr.decay,
+            sustain: adsr.sustain,
+            release: adsr.release,
+            ..Envelope::default()
+        }
+    }
+}
+
+impl Envelope {
+    pub fn instantiate(&self, sample_rate: f64) -> EvalEnvelope {
+        let samples = |seconds: f64| (seconds.max(0.0) * sample_rate).round() as usize;
+        let mut eval = EvalEnvelope {
+            delay_samples: samples(self.delay),
+            attack_samples: samples(self.attack),
+            hold_samples: samples(self.hold),
+            decay_samples: samples(self.decay),
+            release_samples: samples(self.release),
             sustain_level: self.sustain,
-            release_level: self.sustain,
-            current_sample: 0,
+            attack_curve: self.attack_curve,
+            decay_curve: self.decay_curve,
+            release_curve: self.release_curve,
+            retrigger: self.retrigger,
+            stage: Stage::Delay,
+            position: 0,
+            start_level: 0.0,
             released: false,
-        }
+        };
+        eval.enter(Stage::Delay);
+        eval
     }
 }
 
-/// Sample-exact evaluator for an ADSR envelope.
-pub struct EvalADSR {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Delay,
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+    Done,
+}
+
+/// Sample-exact evaluator for an envelope.
+#[derive(Debug, Clone)]
+pub struct EvalEnvelope {
+    delay_samples: usize,
     attack_samples: usize,
+    hold_samples: usize,
     decay_samples: usize,
     release_samples: usize,
     sustain_level: f64,
-    current_sample: usize,
-    release_level: f64,
+    attack_curve: Curve,
+    decay_curve: Curve,
+    release_curve: Curve,
+    retrigger: Retrigger,
+    stage: Stage,
+    /// Samples since the start of the current stage.
+    position: usize,
+    /// Level at the start of the attack or release.
+    start_level: f64,
     released: bool,
 }
 
-impl EvalADSR {
+impl EvalEnvelope {
     /// Called for every sample, returning the envelope gain at that sample.
     pub fn step(&mut self) -> f64 {
         let gain = self.compute_gain();
-        let advance_to_sustain =
-            !self.released && self.current_sample < self.attack_samples + self.decay_samples;
-        let advance_to_release = self.released
-            && self.current_sample
-                < self.attack_samples + self.decay_samples + self.release_samples;
-        if advance_to_release || advance_to_sustain {
-            self.current_sample += 1;
+        if let Some(length) = self.length(self.stage) {
+            self.position += 1;
+            if self.position >= length {
+                self.enter(self.next(self.stage));
+            }
         }
         gain
     }
 
+    /// Number of samples of the stage, `None` if it lasts until something happens.
+    fn length(&self, stage: Stage) -> Option<usize> {
+        match stage {
+            Stage::Delay => Some(self.delay_samples),
+            Stage::Attack => Some(self.attack_samples),
+            Stage::Hold => Some(self.hold_samples),
+            Stage::Decay => Some(self.decay_samples),
+            Stage::Release => Some(self.release_samples),
+            Stage::Sustain | Stage::Done => None,
+        }
+    }
+
+    fn next(&self, stage: Stage) -> Stage {
+        match stage {
+            Stage::Delay => Stage::Attack,
+            Stage::Attack => Stage::Hold,
+            Stage::Hold => Stage::Decay,
+            Stage::Decay => Stage::Sustain,
+            Stage::Sustain => Stage::Sustain,
+            Stage::Release | Stage::Done => Stage::Done,
+        }
+    }
+
+    /// Move to the given stage, skipping the ones without duration.
+    fn enter(&mut self, mut stage: Stage) {
+        while self.length(stage) == Some(0) {
+            stage = self.next(stage);
+        }
+        self.stage = stage;
+        self.position = 0;
+    }
+
     fn compute_gain(&self) -> f64 {
-        if self.current_sample < self.attack_samples {
-            // Rise from 0.0 to 1.0
-            self.current_sample as f64 / self.attack_samples as f64
-        } else if self.current_sample < self.attack_samples + self.decay_samples {
-            // Drop from 1.0 to `sustain_level`
-            let progress =
-                (self.current_sample - self.attack_samples) as f64 / self.decay_samples as f64;
-            1.0 - progress * (1.0 - self.sustain_level)
-        } else if !self.released && self.current_sample == self.attack_samples + self.decay_samples
-        {
+        let progress = |length: usize| self.position as f64 / length as f64;
+        match self.stage {
+            Stage::Delay => self.start_level,
+            Stage::Attack => {
+                // Rise from the start level (usually 0.0) to 1.0
+                let fraction = self.attack_curve.apply(progress(self.attack_samples));
+                self.start_level + fraction * (1.0 - self.start_level)
+            }
+            Stage::Hold => 1.0,
+            Stage::Decay => {
+                // Drop from 1.0 to `sustain_level`
+                let fraction = self.decay_curve.apply(progress(self.decay_samples));
+                1.0 - fraction * (1.0 - self.sustain_level)
+            }
             // Hold at `sustain_level` while not released
-            self.sustain_level
-        } else if self.current_sample
-            < self.attack_samples + self.decay_samples + self.release_samples
-        {
-            // Drop from `release_level` to 0.0
-            let progress = (self.current_sample - self.attack_samples - self.decay_samples) as f64
-                / self.release_samples as f64;
-            (1.0 - progress) * self.release_level
-        } else {
-            0.0
+            Stage::Sustain => self.sustain_level,
+            Stage::Release => {
+                // Drop from the level at the time of release to 0.0
+                let fraction = self.release_curve.apply(progress(self.release_samples));
+                (1.0 - fraction) * self.start_level
+            }
+            Stage::Done => 0.0,
         }
     }
 
@@ -143,19 +335,105 @@ impl EvalADSR {
     /// Called when the note is released.
     pub fn release(&mut self) {
         if !self.released {
-            self.release_level = self.compute_gain();
-            self.current_sample = self.attack_samples + self.decay_samples;
+            self.start_level = self.compute_gain();
             self.released = true;
+            self.enter(Stage::Release);
         }
     }
 
+    /// Called when the note is played again before the envelope has faded,
+    /// restarting it according to its `Retrigger` mode.
+    pub fn retrigger(&mut self) {
+        let restart = match self.retrigger {
+            Retrigger::Reset => {
+                self.start_level = 0.0;
+                Stage::Delay
+            }
+            Retrigger::Continue => {
+                self.start_level = self.compute_gain();
+                Stage::Attack
+            }
+            Retrigger::Legato if !self.released => return,
+            Retrigger::Legato => {
+                self.start_level = self.compute_gain();
+                Stage::Attack
+            }
+        };
+        self.released = false;
+        self.enter(restart);
+    }
+
     /// The envelope has faded when all subsequent `step` calls would return zero.
     /// This is the case when
     /// - the note has been released and the envelope reached zero volume
     /// - the sustain_level is zero and the note has decayed.
     pub fn faded(&self) -> bool {
-        let end_decay = self.attack_samples + self.decay_samples;
-        self.current_sample == end_decay + self.release_samples
-            || (self.sustain_level == 0.0 && self.current_sample >= end_decay)
+        self.stage == Stage::Done || (self.stage == Stage::Sustain && self.sustain_level == 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Curve, Envelope, Retrigger};
+
+    #[test]
+    fn curves() {
+        for curve in [Curve::Linear, Curve::Exponential, Curve::Logarithmic].iter() {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-12);
+        }
+        assert!(Curve::Exponential.apply(0.5) > 0.5);
+        assert!(Curve::Logarithmic.apply(0.5) < 0.5);
+    }
+
+    #[test]
+    fn sustain_without_transitions() {
+        let envelope = Envelope {
+            attack: 0.0,
+            release: 0.0,
+            ..Envelope::default()
+        };
+        let mut eval = envelope.instantiate(4.0);
+        assert!(!eval.faded());
+        assert_eq!(eval.step(), 1.0);
+        eval.release();
+        assert_eq!(eval.step(), 0.0);
+        assert!(eval.faded());
+    }
+
+    #[test]
+    fn retrigger() {
+        let envelope = |retrigger| Envelope {
+            attack: 1.0,
+            release: 1.0,
+            retrigger,
+            ..Envelope::default()
+        };
+        let run = |retrigger, release: bool| {
+            let mut eval = envelope(retrigger).instantiate(4.0);
+            for _ in 0..6 {
+                eval.step();
+            }
+            if release {
+                eval.release();
+                eval.step();
+                eval.step();
+            }
+            eval.retrigger();
+            eval.step()
+        };
+        assert_eq!(run(Retrigger::Reset, false), 0.0);
+        assert_eq!(run(Retrigger::Continue, false), 1.0);
+        assert_eq!(run(Retrigger::Legato, false), 1.0);
+        // Released at 1.0 and half way through the release
+        assert_eq!(run(Retrigger::Reset, true), 0.0);
+        assert_eq!(run(Retrigger::Continue, true), 0.5);
+        assert_eq!(run(Retrigger::Legato, true), 0.5);
+
+        let mut legato = envelope(Retrigger::Legato).instantiate(4.0);
+        legato.step();
+        legato.step();
+        legato.retrigger();
+        assert_eq!(legato.step(), 0.5);
     }
 }
@@ -0,0 +1,191 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Fast Fourier transform for moving signals between the time and the frequency domain,
+//! e.g. for fast convolution.
+
+use std::ops;
+
+/// A complex number.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    /// The complex number with the given magnitude and angle in radians.
+    pub fn from_polar(magnitude: f64, angle: f64) -> Self {
+        Self::new(magnitude * angle.cos(), magnitude * angle.sin())
+    }
+
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    pub fn norm(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl ops::AddAssign for Complex {
+    fn add_assign(&mut self, other: Complex) {
+        *self = *self + other;
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl ops::Mul<f64> for Complex {
+    type Output = Complex;
+
+    fn mul(self, factor: f64) -> Complex {
+        Complex::new(self.re * factor, self.im * factor)
+    }
+}
+
+/// An iterative radix-2 FFT of a fixed size, with precomputed twiddle factors.
+///
+/// # Examples
+///
+/// ```
+/// use syntxt_audio::fft::{Complex, Fft};
+/// let fft = Fft::new(8);
+/// let mut data = (0..8).map(|n| Complex::new(n as f64, 0.0)).collect::<Vec<_>>();
+/// fft.forward(&mut data);
+/// assert_eq!(data[0], Complex::new(28.0, 0.0));
+/// fft.inverse(&mut data);
+/// assert!((data[3].re - 3.0).abs() < 1e-12);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Fft {
+    size: usize,
+    /// `exp(-2πik/size)` for the first half of `k`
+    twiddles: Vec<Complex>,
+}
+
+impl Fft {
+    /// Prepare transforms of the given size, which must be a power of two.
+    pub fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two(), "FFT size must be a power of two");
+        let twiddles = (0..size / 2)
+            .map(|k| Complex::from_polar(1.0, -2.0 * std::f64::consts::PI * k as f64 / size as f64))
+            .collect();
+        Self { size, twiddles }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Transform from the time to the frequency domain in place.
+    pub fn forward(&self, data: &mut [Complex]) {
+        self.transform(data, false);
+    }
+
+    /// Transform from the frequency to the time domain in place,
+    /// scaled so that it undoes `forward`.
+    pub fn inverse(&self, data: &mut [Complex]) {
+        self.transform(data, true);
+        let scale = 1.0 / self.size as f64;
+        data.iter_mut().for_each(|x| *x = *x * scale);
+    }
+
+    fn transform(&self, data: &mut [Complex], inverse: bool) {
+        assert_eq!(data.len(), self.size, "data does not match the FFT size");
+        let n = self.size;
+        if n < 2 {
+            return;
+        }
+        // Reorder the input by bit-reversed indices
+        let bits = n.trailing_zeros();
+        for index in 0..n {
+            let reversed = index.reverse_bits() >> (8 * std::mem::size_of::<usize>() as u32 - bits);
+            if index < reversed {
+                data.swap(index, reversed);
+            }
+        }
+        // Combine transforms of increasing length
+        let mut length = 2;
+        while length <= n {
+            let stride = n / length;
+            for start in (0..n).step_by(length) {
+                for k in 0..length / 2 {
+                    let twiddle = self.twiddles[k * stride];
+                    let twiddle = if inverse { twiddle.conj() } else { twiddle };
+                    let even = data[start + k];
+                    let odd = data[start + k + length / 2] * twiddle;
+                    data[start + k] = even + odd;
+                    data[start + k + length / 2] = even - odd;
+                }
+            }
+            length *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Complex, Fft};
+
+    #[test]
+    fn matches_dft() {
+        let n = 32;
+        let input = (0..n)
+            .map(|i| Complex::new((i as f64 * 0.7).sin(), (i as f64 * 0.3).cos()))
+            .collect::<Vec<_>>();
+        let mut output = input.clone();
+        Fft::new(n).forward(&mut output);
+        for (k, actual) in output.iter().enumerate() {
+            let expected = input.iter().enumerate().fold(Complex::ZERO, |sum, (i, x)| {
+                let angle = -2.0 * std::f64::consts::PI * (i * k) as f64 / n as f64;
+                sum + *x * Complex::from_polar(1.0, angle)
+            });
+            assert!((*actual - expected).norm() < 1e-9);
+        }
+    }
+}
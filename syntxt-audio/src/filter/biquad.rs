@@ -24,15 +24,76 @@ pub enum BiquadType {
     Allpass,
     /// Lowpass filter with the given cutoff frequency and Q factor (controls resonance)
     Lowpass { cutoff: f64, q: f64 },
+    /// Highpass filter with the given cutoff frequency and Q factor (controls resonance)
+    Highpass { cutoff: f64, q: f64 },
+    /// Bandpass filter around the given center frequency, the Q factor controls the width.
+    Bandpass { center: f64, q: f64 },
+    /// Notch filter removing the given center frequency, the Q factor controls the width.
+    Notch { center: f64, q: f64 },
+    /// Boost or cut (gain in dB) of the frequencies below the given one.
+    LowShelf { frequency: f64, gain: f64, q: f64 },
+    /// Boost or cut (gain in dB) of a band around the given center frequency,
+    /// the Q factor controls the width of the band.
+    Peaking { frequency: f64, gain: f64, q: f64 },
+    /// Boost or cut (gain in dB) of the frequencies above the given one.
+    HighShelf { frequency: f64, gain: f64, q: f64 },
 }
 
 impl BiquadType {
+    /// The same type of filter with its frequency multiplied by `factor`.
+    pub fn scale_frequency(&self, factor: f64) -> BiquadType {
+        let mut scaled = self.clone();
+        if let Some(frequency) = scaled.frequency_mut() {
+            *frequency *= factor;
+        }
+        scaled
+    }
+
+    /// The same type of filter with a different frequency.
+    pub fn with_frequency(&self, frequency: f64) -> BiquadType {
+        let mut changed = self.clone();
+        if let Some(old) = changed.frequency_mut() {
+            *old = frequency;
+        }
+        changed
+    }
+
+    /// The cutoff, center or corner frequency, depending on the type of filter.
+    fn frequency_mut(&mut self) -> Option<&mut f64> {
+        match self {
+            BiquadType::Allpass => None,
+            BiquadType::Lowpass { cutoff, .. } | BiquadType::Highpass { cutoff, .. } => {
+                Some(cutoff)
+            }
+            BiquadType::Bandpass { center, .. } | BiquadType::Notch { center, .. } => Some(center),
+            BiquadType::LowShelf { frequency, .. }
+            | BiquadType::Peaking { frequency, .. }
+            | BiquadType::HighShelf { frequency, .. } => Some(frequency),
+        }
+    }
+
     pub fn to_coefficients(&self, sample_rate: f64) -> BiquadCoefficients {
         match self {
             BiquadType::Allpass => BiquadCoefficients::allpass(),
             BiquadType::Lowpass { cutoff, q } => {
                 BiquadCoefficients::lowpass(sample_rate, *cutoff, *q)
             }
+            BiquadType::Highpass { cutoff, q } => {
+                BiquadCoefficients::highpass(sample_rate, *cutoff, *q)
+            }
+            BiquadType::Bandpass { center, q } => {
+                BiquadCoefficients::bandpass(sample_rate, *center, *q)
+            }
+            BiquadType::Notch { center, q } => BiquadCoefficients::notch(sample_rate, *center, *q),
+            BiquadType::LowShelf { frequency, gain, q } => {
+                BiquadCoefficients::low_shelf(sample_rate, *frequency, *gain, *q)
+            }
+            BiquadType::Peaking { frequency, gain, q } => {
+                BiquadCoefficients::peaking(sample_rate, *frequency, *gain, *q)
+            }
+            BiquadType::HighShelf { frequency, gain, q } => {
+                BiquadCoefficients::high_shelf(sample_rate, *frequency, *gain, *q)
+            }
         }
     }
 }
@@ -75,6 +136,104 @@ impl BiquadCoefficients {
             a2: a0_inv * (1.0 - alpha),
         }
     }
+
+    /// Highpass filter with the given cutoff frequency and Q factor
+    pub fn highpass(sample_rate: f64, cutoff: f64, q: f64) -> Self {
+        let omega0 = 2.0 * std::f64::consts::PI * cutoff / sample_rate;
+        let (sin_omega, cos_omega) = omega0.sin_cos();
+        let alpha = sin_omega / (2.0 * q);
+        let a0_inv = 1.0 / (1.0 + alpha);
+        Self {
+            b0: a0_inv * (1.0 + cos_omega) / 2.0,
+            b1: a0_inv * -(1.0 + cos_omega),
+            b2: a0_inv * (1.0 + cos_omega) / 2.0,
+            a1: a0_inv * (-2.0 * cos_omega),
+            a2: a0_inv * (1.0 - alpha),
+        }
+    }
+
+    /// Bandpass filter with a peak gain of 0 dB at the center frequency
+    pub fn bandpass(sample_rate: f64, center: f64, q: f64) -> Self {
+        let omega0 = 2.0 * std::f64::consts::PI * center / sample_rate;
+        let (sin_omega, cos_omega) = omega0.sin_cos();
+        let alpha = sin_omega / (2.0 * q);
+        let a0_inv = 1.0 / (1.0 + alpha);
+        Self {
+            b0: a0_inv * alpha,
+            b1: 0.0,
+            b2: a0_inv * -alpha,
+            a1: a0_inv * (-2.0 * cos_omega),
+            a2: a0_inv * (1.0 - alpha),
+        }
+    }
+
+    /// Notch filter removing the center frequency
+    pub fn notch(sample_rate: f64, center: f64, q: f64) -> Self {
+        let omega0 = 2.0 * std::f64::consts::PI * center / sample_rate;
+        let (sin_omega, cos_omega) = omega0.sin_cos();
+        let alpha = sin_omega / (2.0 * q);
+        let a0_inv = 1.0 / (1.0 + alpha);
+        Self {
+            b0: a0_inv,
+            b1: a0_inv * (-2.0 * cos_omega),
+            b2: a0_inv,
+            a1: a0_inv * (-2.0 * cos_omega),
+            a2: a0_inv * (1.0 - alpha),
+        }
+    }
+
+    /// Low shelf filter changing the frequencies below `frequency` by `gain` dB.
+    pub fn low_shelf(sample_rate: f64, frequency: f64, gain: f64, q: f64) -> Self {
+        let (a, sin_omega, cos_omega) = shelf_params(sample_rate, frequency, gain);
+        let alpha = sin_omega / (2.0 * q);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        let a0_inv = 1.0 / ((a + 1.0) + (a - 1.0) * cos_omega + sqrt_a_alpha);
+        Self {
+            b0: a0_inv * a * ((a + 1.0) - (a - 1.0) * cos_omega + sqrt_a_alpha),
+            b1: a0_inv * 2.0 * a * ((a - 1.0) - (a + 1.0) * cos_omega),
+            b2: a0_inv * a * ((a + 1.0) - (a - 1.0) * cos_omega - sqrt_a_alpha),
+            a1: a0_inv * -2.0 * ((a - 1.0) + (a + 1.0) * cos_omega),
+            a2: a0_inv * ((a + 1.0) + (a - 1.0) * cos_omega - sqrt_a_alpha),
+        }
+    }
+
+    /// Peaking filter changing the frequencies around `frequency` by `gain` dB.
+    pub fn peaking(sample_rate: f64, frequency: f64, gain: f64, q: f64) -> Self {
+        let (a, sin_omega, cos_omega) = shelf_params(sample_rate, frequency, gain);
+        let alpha = sin_omega / (2.0 * q);
+        let a0_inv = 1.0 / (1.0 + alpha / a);
+        Self {
+            b0: a0_inv * (1.0 + alpha * a),
+            b1: a0_inv * (-2.0 * cos_omega),
+            b2: a0_inv * (1.0 - alpha * a),
+            a1: a0_inv * (-2.0 * cos_omega),
+            a2: a0_inv * (1.0 - alpha / a),
+        }
+    }
+
+    /// High shelf filter changing the frequencies above `frequency` by `gain` dB.
+    pub fn high_shelf(sample_rate: f64, frequency: f64, gain: f64, q: f64) -> Self {
+        let (a, sin_omega, cos_omega) = shelf_params(sample_rate, frequency, gain);
+        let alpha = sin_omega / (2.0 * q);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        let a0_inv = 1.0 / ((a + 1.0) - (a - 1.0) * cos_omega + sqrt_a_alpha);
+        Self {
+            b0: a0_inv * a * ((a + 1.0) + (a - 1.0) * cos_omega + sqrt_a_alpha),
+            b1: a0_inv * -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_omega),
+            b2: a0_inv * a * ((a + 1.0) + (a - 1.0) * cos_omega - sqrt_a_alpha),
+            a1: a0_inv * 2.0 * ((a - 1.0) - (a + 1.0) * cos_omega),
+            a2: a0_inv * ((a + 1.0) - (a - 1.0) * cos_omega - sqrt_a_alpha),
+        }
+    }
+}
+
+/// Amplitude and the sine and cosine of the normalized frequency,
+/// shared by the filters that boost or cut.
+fn shelf_params(sample_rate: f64, frequency: f64, gain: f64) -> (f64, f64, f64) {
+    let a = 10.0f64.powf(gain / 40.0);
+    let omega0 = 2.0 * std::f64::consts::PI * frequency / sample_rate;
+    let (sin_omega, cos_omega) = omega0.sin_cos();
+    (a, sin_omega, cos_omega)
 }
 
 /// Biquadratic filter with four delay gates, based on https://www.w3.org/2011/audio/audio-eq-cookbook.html.
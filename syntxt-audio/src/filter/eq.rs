@@ -0,0 +1,134 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parametric equalizer made of a chain of biquad filters.
+
+use crate::effect::Effect;
+use crate::wave::Stereo;
+
+use super::{Biquad, BiquadCoefficients, BiquadType};
+
+/// Equalizer applying a series of filter bands, e.g. a low shelf, some peaking filters
+/// and a high shelf.
+pub struct Equalizer {
+    bands: Vec<Band>,
+}
+
+struct Band {
+    coefficients: BiquadCoefficients,
+    filters: Stereo<Biquad>,
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: f64, bands: &[BiquadType]) -> Self {
+        Self {
+            bands: bands
+                .iter()
+                .map(|band| Band {
+                    coefficients: band.to_coefficients(sample_rate),
+                    filters: Stereo::new(Biquad::new(), Biquad::new()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Effect for Equalizer {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        for band in self.bands.iter_mut() {
+            for sample in samples.iter_mut() {
+                sample.left = band.filters.left.step(&band.coefficients, sample.left);
+                sample.right = band.filters.right.step(&band.coefficients, sample.right);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Equalizer;
+    use crate::effect::Effect;
+    use crate::filter::{BiquadCoefficients, BiquadType};
+    use crate::wave::Stereo;
+
+    /// Gain of the filter for a sine wave of the given frequency.
+    fn magnitude(c: &BiquadCoefficients, sample_rate: f64, frequency: f64) -> f64 {
+        let omega = 2.0 * std::f64::consts::PI * frequency / sample_rate;
+        // Evaluate numerator and denominator polynomials at z = e^(i omega)
+        let eval = |k0: f64, k1: f64, k2: f64| {
+            let re = k0 + k1 * omega.cos() + k2 * (2.0 * omega).cos();
+            let im = -k1 * omega.sin() - k2 * (2.0 * omega).sin();
+            (re * re + im * im).sqrt()
+        };
+        eval(c.b0, c.b1, c.b2) / eval(1.0, c.a1, c.a2)
+    }
+
+    fn decibels(gain: f64) -> f64 {
+        20.0 * gain.log10()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn band_gains() {
+        let sample_rate = 44100.0;
+        let q = std::f64::consts::FRAC_1_SQRT_2;
+
+        let low = BiquadCoefficients::low_shelf(sample_rate, 200.0, 6.0, q);
+        assert_close(decibels(magnitude(&low, sample_rate, 10.0)), 6.0);
+        assert_close(decibels(magnitude(&low, sample_rate, 10000.0)), 0.0);
+
+        let peak = BiquadCoefficients::peaking(sample_rate, 1000.0, -4.0, 2.0);
+        assert_close(decibels(magnitude(&peak, sample_rate, 1000.0)), -4.0);
+        assert_close(decibels(magnitude(&peak, sample_rate, 20.0)), 0.0);
+        assert_close(decibels(magnitude(&peak, sample_rate, 15000.0)), 0.0);
+
+        let high = BiquadCoefficients::high_shelf(sample_rate, 5000.0, 3.0, q);
+        assert_close(decibels(magnitude(&high, sample_rate, 10.0)), 0.0);
+        assert_close(decibels(magnitude(&high, sample_rate, 20000.0)), 3.0);
+    }
+
+    #[test]
+    fn bands_are_chained() {
+        let mut eq = Equalizer::new(
+            1000.0,
+            &[
+                BiquadType::LowShelf {
+                    frequency: 50.0,
+                    gain: 6.0,
+                    q: 1.0,
+                },
+                BiquadType::LowShelf {
+                    frequency: 50.0,
+                    gain: 6.0,
+                    q: 1.0,
+                },
+            ],
+        );
+        // A constant signal settles at the combined gain of both shelves
+        let mut samples = vec![Stereo::mono(1.0); 1000];
+        eq.process(&mut samples);
+        assert_close(decibels(samples[999].left), 12.0);
+        assert_eq!(samples[999].left, samples[999].right);
+    }
+}
@@ -0,0 +1,157 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Finite impulse response filters, used for converting oversampled signals back to
+//! the target sample rate without aliasing.
+
+use crate::wave::Stereo;
+
+/// Number of taps per unit of the decimation factor. More taps give a steeper transition band.
+const TAPS_PER_FACTOR: usize = 32;
+
+/// A lowpass filter with a windowed sinc impulse response.
+#[derive(Debug, Clone)]
+pub struct Fir {
+    taps: Vec<f64>,
+}
+
+impl Fir {
+    /// A lowpass filter with the given number of taps and cutoff, relative to the sample rate
+    /// (e.g. 0.25 for a quarter of the sample rate), using a Blackman window.
+    ///
+    /// The taps are normalized for unity gain at DC.
+    pub fn lowpass(taps: usize, cutoff: f64) -> Self {
+        let taps = taps.max(1);
+        let center = (taps - 1) as f64 / 2.0;
+        let mut coefficients = (0..taps)
+            .map(|index| {
+                let x = index as f64 - center;
+                let sinc = if x == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+                };
+                let phase = 2.0 * std::f64::consts::PI * index as f64 / (taps - 1).max(1) as f64;
+                let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+                sinc * window
+            })
+            .collect::<Vec<_>>();
+        let sum = coefficients.iter().sum::<f64>();
+        coefficients.iter_mut().for_each(|tap| *tap /= sum);
+        Self { taps: coefficients }
+    }
+}
+
+/// Reduces the sample rate of a signal by an integer factor, filtering out everything above
+/// the new Nyquist frequency first.
+///
+/// # Examples
+///
+/// ```
+/// use syntxt_audio::filter::fir::Decimator;
+/// use syntxt_audio::wave::Stereo;
+/// let mut decimator = Decimator::new(4);
+/// let mut output = Vec::new();
+/// decimator.process(&vec![Stereo::mono(1.0); 1000], &mut output);
+/// assert_eq!(output.len(), 250);
+/// // A constant signal passes unchanged once the filter is filled
+/// assert!((output[249].left - 1.0).abs() < 1e-9);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Decimator {
+    factor: usize,
+    filter: Fir,
+    /// The most recent input samples, as a ring buffer as long as the filter
+    history: Vec<Stereo<f64>>,
+    /// Position of the next sample in the history
+    position: usize,
+    /// Number of input samples until the next output sample
+    countdown: usize,
+}
+
+impl Decimator {
+    pub fn new(factor: usize) -> Self {
+        let factor = factor.max(1);
+        let filter = if factor == 1 {
+            Fir { taps: vec![1.0] }
+        } else {
+            // Leave some room for the transition band below the new Nyquist frequency
+            Fir::lowpass(TAPS_PER_FACTOR * factor + 1, 0.45 / factor as f64)
+        };
+        Self {
+            factor,
+            history: vec![Stereo::mono(0.0); filter.taps.len()],
+            filter,
+            position: 0,
+            countdown: factor,
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Filter the input and append every `factor`-th sample to the output.
+    /// The input may have any length, the decimation continues across calls.
+    pub fn process(&mut self, input: &[Stereo<f64>], output: &mut Vec<Stereo<f64>>) {
+        let len = self.history.len();
+        for sample in input {
+            self.history[self.position] = *sample;
+            self.position = (self.position + 1) % len;
+            self.countdown -= 1;
+            if self.countdown == 0 {
+                self.countdown = self.factor;
+                // The oldest sample is at the current position
+                let mut value = Stereo::mono(0.0);
+                for (index, tap) in self.filter.taps.iter().enumerate() {
+                    value += self.history[(self.position + index) % len] * *tap;
+                }
+                output.push(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimator;
+    use crate::wave::Stereo;
+
+    /// Peak amplitude of a sine at the given frequency after decimating from 4 * 44100 Hz.
+    fn peak(frequency: f64) -> f64 {
+        let rate = 4.0 * 44100.0;
+        let input = (0..8000)
+            .map(|n| Stereo::mono((2.0 * std::f64::consts::PI * frequency * n as f64 / rate).sin()))
+            .collect::<Vec<_>>();
+        let mut decimator = Decimator::new(4);
+        let mut output = Vec::new();
+        decimator.process(&input, &mut output);
+        output[500..]
+            .iter()
+            .map(|s| s.left.abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn removes_aliases() {
+        // Audible frequencies pass
+        assert!((peak(1000.0) - 1.0).abs() < 0.01);
+        assert!((peak(15000.0) - 1.0).abs() < 0.05);
+        // Frequencies that would fold back into the audible range are removed
+        assert!(peak(30000.0) < 0.01);
+        assert!(peak(60000.0) < 0.01);
+    }
+}
@@ -0,0 +1,121 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Ladder filter modelled after the classic transistor ladder of analog synthesizers.
+//!
+//! Four one-pole lowpass stages are chained, with the output fed back to the input.
+//! The feedback loop is solved without a delay (zero-delay feedback), which keeps the cutoff
+//! and resonance accurate even at high frequencies. The input saturates, so that the resonance
+//! can go up to self-oscillation without the filter blowing up.
+
+#[derive(Debug, Clone)]
+pub struct Ladder {
+    sample_rate: f64,
+    /// Gain of the one-pole stages, derived from the cutoff.
+    g: f64,
+    /// Amount of feedback, self-oscillation starts at 4.
+    k: f64,
+    /// Gain applied before the saturation.
+    drive: f64,
+    /// States of the four stages.
+    stages: [f64; 4],
+}
+
+impl Ladder {
+    /// Create a filter with the given cutoff frequency, resonance between 0 (none) and 1
+    /// and drive, where values above 1 increasingly saturate the input.
+    /// The filter starts to oscillate on its own at a resonance of about 0.9.
+    pub fn new(sample_rate: f64, cutoff: f64, resonance: f64, drive: f64) -> Self {
+        let mut filter = Self {
+            sample_rate,
+            g: 0.0,
+            k: 0.0,
+            drive: 1.0,
+            stages: [0.0; 4],
+        };
+        filter.set_params(cutoff, resonance, drive);
+        filter
+    }
+
+    /// Change the parameters, which takes effect immediately.
+    pub fn set_params(&mut self, cutoff: f64, resonance: f64, drive: f64) {
+        let cutoff = cutoff.clamp(1.0, self.sample_rate * 0.49);
+        let g = (std::f64::consts::PI * cutoff / self.sample_rate).tan();
+        self.g = g / (1.0 + g);
+        // Go a bit beyond the point of self-oscillation, the saturation keeps it stable
+        self.k = 4.4 * resonance.clamp(0.0, 1.0);
+        self.drive = drive.max(0.0);
+    }
+
+    /// Feed the next value through the filter.
+    pub fn step(&mut self, input: f64) -> f64 {
+        let g = self.g;
+        // Contribution of the stage states to the output, for solving the feedback loop
+        let feedback = self
+            .stages
+            .iter()
+            .fold(0.0, |sum, state| sum * g + state * (1.0 - g));
+        let g4 = g * g * g * g;
+        let linear = (input - self.k * feedback) / (1.0 + self.k * g4);
+        let mut value = (linear * self.drive).tanh();
+
+        for state in self.stages.iter_mut() {
+            let v = (value - *state) * g;
+            value = v + *state;
+            *state = value + v;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ladder;
+
+    /// Peak amplitude of the output for a sine of the given frequency, after settling.
+    fn response(filter: &mut Ladder, frequency: f64, amplitude: f64) -> f64 {
+        let sample_rate = 44100.0;
+        (0..44100)
+            .map(|i| {
+                let t = i as f64 / sample_rate;
+                filter.step(amplitude * (2.0 * std::f64::consts::PI * frequency * t).sin())
+            })
+            .skip(22050)
+            .fold(0.0, |peak, output| output.abs().max(peak))
+    }
+
+    #[test]
+    fn lowpass() {
+        let filter = || Ladder::new(44100.0, 1000.0, 0.0, 1.0);
+        assert!((response(&mut filter(), 50.0, 0.1) - 0.1).abs() < 0.001);
+        // Four stages attenuate by 24 dB per octave
+        assert!(response(&mut filter(), 8000.0, 0.1) < 0.001);
+        // Saturation limits the output
+        assert!(response(&mut filter(), 50.0, 10.0) < 1.0);
+    }
+
+    #[test]
+    fn self_oscillation() {
+        let ring = |resonance| {
+            let mut filter = Ladder::new(44100.0, 1000.0, resonance, 1.0);
+            filter.step(1.0);
+            response(&mut filter, 0.0, 0.0)
+        };
+        assert!(ring(0.5) < 0.001);
+        let oscillation = ring(1.0);
+        assert!(oscillation > 0.1 && oscillation < 2.0);
+    }
+}
@@ -17,5 +17,12 @@
 //! Implementations of various digital filters.
 
 pub mod biquad;
+pub mod eq;
+pub mod fir;
+pub mod ladder;
+pub mod svf;
 
 pub use biquad::*;
+pub use eq::Equalizer;
+pub use ladder::Ladder;
+pub use svf::{StateVariable, SvfOutput};
@@ -0,0 +1,184 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State variable filter, providing lowpass, highpass, bandpass and notch outputs at once.
+//!
+//! Unlike the biquad filters, its cutoff and resonance can be changed for every sample, e.g. by
+//! automation, without producing clicks (zipper noise). Parameter changes are smoothed, and the
+//! topology-preserving structure (after Andrew Simper, Cytomic) stays stable while they happen.
+
+/// All outputs of the filter for one input sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvfOutput {
+    pub lowpass: f64,
+    pub highpass: f64,
+    pub bandpass: f64,
+    pub notch: f64,
+}
+
+/// Time in seconds parameter changes take to (mostly) reach their new value.
+const SMOOTHING_TIME: f64 = 0.005;
+
+#[derive(Debug, Clone)]
+pub struct StateVariable {
+    sample_rate: f64,
+    /// Per-sample factor for approaching the target parameters.
+    smoothing: f64,
+    /// Cutoff frequency the filter is moving towards.
+    target_cutoff: f64,
+    /// Resonance the filter is moving towards.
+    target_resonance: f64,
+    cutoff: f64,
+    resonance: f64,
+    /// Coefficients derived from the current cutoff and resonance.
+    g: f64,
+    k: f64,
+    /// States of the two integrators.
+    ic1eq: f64,
+    ic2eq: f64,
+}
+
+impl StateVariable {
+    /// Create a filter with the given cutoff frequency and resonance.
+    /// The resonance is between 0 (none) and 1 (self-oscillation).
+    pub fn new(sample_rate: f64, cutoff: f64, resonance: f64) -> Self {
+        let mut filter = Self {
+            sample_rate,
+            smoothing: (-1.0 / (SMOOTHING_TIME * sample_rate)).exp(),
+            target_cutoff: cutoff,
+            target_resonance: resonance,
+            cutoff,
+            resonance,
+            g: 0.0,
+            k: 0.0,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        };
+        filter.update_coefficients();
+        filter
+    }
+
+    /// Change the parameters, the filter moves towards them over the next few milliseconds.
+    pub fn set_params(&mut self, cutoff: f64, resonance: f64) {
+        self.target_cutoff = cutoff;
+        self.target_resonance = resonance;
+    }
+
+    /// The cutoff frequency the filter currently uses.
+    pub fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    /// Feed the next value through the filter.
+    pub fn step(&mut self, input: f64) -> SvfOutput {
+        if self.cutoff != self.target_cutoff || self.resonance != self.target_resonance {
+            let approach = |current: f64, target: f64, smoothing: f64| {
+                let next = target + (current - target) * smoothing;
+                // Snap to the target once the difference is inaudible
+                if (next - target).abs() < 1e-6 * target.abs().max(1.0) {
+                    target
+                } else {
+                    next
+                }
+            };
+            self.cutoff = approach(self.cutoff, self.target_cutoff, self.smoothing);
+            self.resonance = approach(self.resonance, self.target_resonance, self.smoothing);
+            self.update_coefficients();
+        }
+
+        let a1 = 1.0 / (1.0 + self.g * (self.g + self.k));
+        let a2 = self.g * a1;
+        let a3 = self.g * a2;
+        let v3 = input - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        let lowpass = v2;
+        let bandpass = v1;
+        let highpass = input - self.k * v1 - v2;
+        SvfOutput {
+            lowpass,
+            highpass,
+            bandpass,
+            notch: lowpass + highpass,
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        // Keep the cutoff below the Nyquist frequency, where the tangent explodes
+        let cutoff = self.cutoff.clamp(1.0, self.sample_rate * 0.49);
+        self.g = (std::f64::consts::PI * cutoff / self.sample_rate).tan();
+        // Damping of 2 is no resonance at all (Q = 0.5), 0 would oscillate forever
+        self.k = 2.0 - 2.0 * self.resonance.clamp(0.0, 0.99);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StateVariable, SvfOutput};
+
+    /// Peak amplitude of each output for a sine of the given frequency, after settling.
+    fn response(filter: &mut StateVariable, frequency: f64) -> SvfOutput {
+        let sample_rate = 44100.0;
+        let mut peak = SvfOutput {
+            lowpass: 0.0,
+            highpass: 0.0,
+            bandpass: 0.0,
+            notch: 0.0,
+        };
+        for i in 0..44100 {
+            let input = (2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate).sin();
+            let output = filter.step(input);
+            if i > 22050 {
+                peak.lowpass = peak.lowpass.max(output.lowpass.abs());
+                peak.highpass = peak.highpass.max(output.highpass.abs());
+                peak.bandpass = peak.bandpass.max(output.bandpass.abs());
+                peak.notch = peak.notch.max(output.notch.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn outputs() {
+        let filter = || StateVariable::new(44100.0, 1000.0, 0.0);
+
+        let low = response(&mut filter(), 50.0);
+        assert!(low.lowpass > 0.99 && low.highpass < 0.01 && low.notch > 0.99);
+
+        let high = response(&mut filter(), 15000.0);
+        assert!(high.lowpass < 0.01 && high.highpass > 0.99 && high.notch > 0.99);
+
+        // Without resonance, the bandpass peak is half as loud as the input
+        let center = response(&mut filter(), 1000.0);
+        assert!((center.bandpass - 0.5).abs() < 0.01);
+        assert!(center.notch < 0.01);
+    }
+
+    #[test]
+    fn smooth_parameter_changes() {
+        let mut filter = StateVariable::new(44100.0, 1000.0, 0.0);
+        filter.set_params(5000.0, 0.5);
+        filter.step(0.0);
+        assert!(filter.cutoff() > 1000.0 && filter.cutoff() < 1100.0);
+        for _ in 0..44100 {
+            filter.step(0.0);
+        }
+        assert_eq!(filter.cutoff(), 5000.0);
+    }
+}
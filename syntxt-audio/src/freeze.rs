@@ -0,0 +1,156 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rendering frozen tracks once and reusing the result while other parts of the song change.
+//!
+//! Rendered tracks are identified by a hash of everything that determines their sound,
+//! so editing a frozen track simply renders it again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use log::{debug, warn};
+
+use crate::wave::{SampleBuffer, Stereo};
+
+/// Builds the key of a rendered track from the descriptions of everything it depends on.
+#[derive(Default)]
+pub struct KeyBuilder {
+    hasher: DefaultHasher,
+}
+
+impl KeyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include something in the key by its debug representation,
+    /// which is complete for all parts of a song.
+    pub fn include(mut self, part: &impl std::fmt::Debug) -> Self {
+        let description = format!("{:?}", part);
+        self.hasher.write(description.as_bytes());
+        // Separate the parts, so that moving text from one to the next changes the key
+        self.hasher.write_u8(0xff);
+        self
+    }
+
+    pub fn finish(self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+/// Rendered tracks by their key, kept in memory and optionally in a directory,
+/// so that they survive between runs.
+#[derive(Debug, Default)]
+pub struct FreezeCache {
+    directory: Option<PathBuf>,
+    tracks: HashMap<u64, Rc<Vec<Stereo<f64>>>>,
+}
+
+impl FreezeCache {
+    /// A cache that only lives as long as it is kept around.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache also storing the rendered tracks as files in the given directory.
+    pub fn in_directory(directory: PathBuf) -> Self {
+        Self {
+            directory: Some(directory),
+            tracks: HashMap::new(),
+        }
+    }
+
+    fn path(&self, key: u64) -> Option<PathBuf> {
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{:016x}.f64", key)))
+    }
+
+    /// The rendered track with the given key, if it was rendered before.
+    pub fn get(&mut self, key: u64) -> Option<Rc<Vec<Stereo<f64>>>> {
+        if let Some(samples) = self.tracks.get(&key) {
+            return Some(samples.clone());
+        }
+        let path = self.path(key)?;
+        let bytes = std::fs::read(&path).ok()?;
+        debug!("loaded frozen track from {}", path.display());
+        // The sample rate is part of the key already
+        let samples = Rc::new(SampleBuffer::from_bytes(0.0, &bytes).samples);
+        self.tracks.insert(key, samples.clone());
+        Some(samples)
+    }
+
+    /// Remember a rendered track. Failing to write it to the directory only loses the file.
+    pub fn insert(&mut self, key: u64, samples: Vec<Stereo<f64>>) {
+        if let Some(path) = self.path(key) {
+            if let Err(err) = write_samples(&path, &samples) {
+                warn!(
+                    "could not store frozen track in {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+        self.tracks.insert(key, Rc::new(samples));
+    }
+}
+
+fn write_samples(path: &std::path::Path, samples: &[Stereo<f64>]) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let mut bytes = Vec::with_capacity(samples.len() * 16);
+    for sample in samples.iter() {
+        bytes.extend_from_slice(&sample.left.to_le_bytes());
+        bytes.extend_from_slice(&sample.right.to_le_bytes());
+    }
+    std::fs::write(path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FreezeCache, KeyBuilder};
+    use crate::wave::Stereo;
+
+    #[test]
+    fn keys() {
+        let key = |a: &str, b: &str| KeyBuilder::new().include(&a).include(&b).finish();
+        assert_eq!(key("track", "44100"), key("track", "44100"));
+        assert_ne!(key("track", "44100"), key("track", "48000"));
+        assert_ne!(key("ab", "c"), key("a", "bc"));
+    }
+
+    #[test]
+    fn directory() {
+        let directory = std::env::temp_dir().join(format!("syntxt-freeze-{}", std::process::id()));
+        let samples = vec![Stereo::new(0.25, -0.5), Stereo::new(1e-3, 0.0)];
+        let mut cache = FreezeCache::in_directory(directory.clone());
+        assert!(cache.get(7).is_none());
+        cache.insert(7, samples.clone());
+        assert_eq!(*cache.get(7).unwrap(), samples);
+
+        // A new cache finds the files of the previous one
+        let mut reloaded = FreezeCache::in_directory(directory.clone());
+        assert_eq!(*reloaded.get(7).unwrap(), samples);
+        assert!(reloaded.get(8).is_none());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
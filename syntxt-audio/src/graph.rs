@@ -19,19 +19,45 @@
 //! The output is music.
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::time::Instant;
 
-use crate::wave::AudioBuffer;
+use crate::automation::Curve;
+use crate::wave::{AudioBuffer, Stereo};
 
+mod aiff;
 mod builder;
+mod check;
+mod dot;
+mod effect;
 mod instrument;
+#[cfg(feature = "jack")]
+mod jack;
+mod meter;
+mod mixer;
+mod opus;
+mod profile;
+mod recorder;
 mod sox;
 mod transducers;
+mod wav;
 
+pub use aiff::{AiffSink, AiffWriter};
 pub use builder::{GraphBuildError, GraphBuilder};
+pub use check::{Problem, ProblemKind};
+pub use effect::{CompressorNode, EffectNode};
 pub use instrument::InstrumentSource;
-pub use sox::{SoxSink, SoxTarget};
+#[cfg(feature = "jack")]
+pub use self::jack::JackSink;
+pub use meter::MeterNode;
+pub use mixer::{Mixer, MixerChannel};
+pub use opus::OpusSink;
+pub use profile::{NodeTime, Profile};
+pub use recorder::{Playback, Recorder};
+pub use sox::{load_sample, SoxFormat, SoxSink, SoxTarget};
 pub use transducers::*;
+pub use wav::{Cue, WavFormat, WavSink, WavWriter};
 
 /// Time measured in samples.
 pub type Sample = usize;
@@ -64,12 +90,88 @@ pub struct OutputRef {
 pub struct Graph {
     nodes: Vec<NodeHolder>,
     evaluation_order: Vec<NodeId>,
+    /// Parameters of nodes changing over time, with their curves over time in samples.
+    automations: Vec<Automation>,
+    /// Edges closing a loop, which deliver their signal with a delay.
+    feedbacks: Vec<Feedback>,
+    /// All connections between nodes, with the delay of feedback edges, for describing the graph.
+    edges: Vec<(OutputRef, InputRef, Option<Sample>)>,
+    /// Inspects everything that is rendered, if enabled.
+    checker: Option<check::Checker>,
+    /// Measures the time spent in each node, if enabled.
+    profiler: Option<profile::Profiler>,
     time: Sample,
     buffer_size: Sample,
 }
 
 impl Graph {
+    /// Look for clipping, NaN, infinite and denormal samples in the outputs of all nodes
+    /// from now on, which makes rendering slower.
+    pub fn check(&mut self) {
+        if self.checker.is_none() {
+            self.checker = Some(check::Checker::new());
+        }
+    }
+
+    /// The problems found while checking, in the order they first occurred.
+    pub fn problems(&self) -> &[Problem] {
+        self.checker
+            .as_ref()
+            .map_or(&[], |checker| checker.problems())
+    }
+
+    /// The nodes and their connections in the DOT language of Graphviz, for looking at the graph,
+    /// e.g. with `dot -Tsvg`. Nodes of the same group are drawn together.
+    pub fn to_dot(&self) -> String {
+        dot::to_dot(&self.nodes, &self.edges)
+    }
+
+    /// Change a parameter of a node between steps, e.g. from a user interface.
+    /// Returns whether the node has a parameter of that name.
+    pub fn set_parameter(&mut self, node: NodeId, parameter: &str, value: f64) -> bool {
+        match self.nodes.get_mut(node.0) {
+            Some(holder) => holder.node.set_parameter(parameter, value),
+            None => false,
+        }
+    }
+
+    /// Measure the time spent rendering each node from now on.
+    pub fn profile(&mut self) {
+        if self.profiler.is_none() {
+            self.profiler = Some(profile::Profiler::new(self.nodes.len()));
+        }
+    }
+
+    /// The time spent in each node while profiling, or `None` if it is not enabled.
+    pub fn profiled(&self) -> Option<Profile> {
+        let profiler = self.profiler.as_ref()?;
+        let nodes = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, holder)| {
+                let (time, renders) = profiler.times(NodeId(index));
+                NodeTime {
+                    node: NodeId(index),
+                    name: check::short_name(holder.name),
+                    group: holder.group.clone(),
+                    time,
+                    renders,
+                }
+            })
+            .collect();
+        Some(Profile { nodes })
+    }
+
     pub fn step(&mut self) {
+        // Automated parameters are updated once per buffer
+        for automation in self.automations.iter() {
+            if let Some(value) = automation.curve.value_at(self.time as f64) {
+                self.nodes[automation.node.0]
+                    .node
+                    .set_parameter(&automation.parameter, value);
+            }
+        }
         for id in self.evaluation_order.iter() {
             let holder = &mut self.nodes[id.0];
 
@@ -79,24 +181,86 @@ impl Graph {
                 inputs: &holder.input_buffers,
                 outputs: &holder.output_buffers,
             };
+            let started = self.profiler.as_ref().map(|_| Instant::now());
             holder.node.render(&rio);
+            if let (Some(profiler), Some(started)) = (self.profiler.as_mut(), started) {
+                profiler.record(*id, started.elapsed());
+            }
+            if let Some(checker) = self.checker.as_mut() {
+                checker.inspect(*id, holder.name, &holder.output_buffers, self.time);
+            }
+        }
+        for feedback in self.feedbacks.iter_mut() {
+            feedback.advance();
         }
         self.time += self.buffer_size;
     }
 
+    /// Continue rendering at the given sample instead of the current time,
+    /// e.g. for rendering only the end of a song.
+    /// Nodes see the new time from the next step on.
+    pub fn start_at(&mut self, time: Sample) {
+        self.time = time;
+    }
+
     // REVIEW: allow getting a reference back to a node and downcast?
 
     // pub fn connect(&mut self, from: NodeId, to: NodeId, )
 }
 
+/// A parameter of a node following a curve.
+struct Automation {
+    node: NodeId,
+    parameter: String,
+    curve: Curve,
+}
+
+/// An edge from an output to an input that is only seen by the input after a delay,
+/// so that it may go against the evaluation order.
+struct Feedback {
+    output: Rc<RefCell<AudioBuffer>>,
+    input: Rc<RefCell<AudioBuffer>>,
+    /// Samples that were rendered but not yet delivered to the input.
+    pending: VecDeque<Stereo<f64>>,
+}
+
+impl Feedback {
+    /// Create a feedback edge with a delay of at least one buffer.
+    fn new(
+        output: Rc<RefCell<AudioBuffer>>,
+        input: Rc<RefCell<AudioBuffer>>,
+        delay: Sample,
+        buffer_size: Sample,
+    ) -> Self {
+        debug_assert!(delay >= buffer_size);
+        Self {
+            output,
+            input,
+            pending: vec![Stereo::mono(0.0); delay - buffer_size].into(),
+        }
+    }
+
+    /// Collect the output of the current step and prepare the input for the next one.
+    fn advance(&mut self) {
+        self.pending.extend(self.output.borrow().iter().copied());
+        for sample in self.input.borrow_mut().iter_mut() {
+            *sample = self.pending.pop_front().unwrap();
+        }
+    }
+}
+
 struct NodeHolder {
     node: Box<dyn Node>,
+    /// Type of the node, for diagnostics.
+    name: &'static str,
+    /// The part of the song the node belongs to, see `GraphBuilder::group`.
+    group: String,
     input_buffers: Vec<Rc<RefCell<AudioBuffer>>>,
     output_buffers: Vec<Rc<RefCell<AudioBuffer>>>,
 }
 
 impl NodeHolder {
-    fn new(node: Box<dyn Node>, buffer_size: Sample) -> Self {
+    fn new(node: Box<dyn Node>, name: &'static str, group: String, buffer_size: Sample) -> Self {
         // TODO: It is wasteful that we create input buffers here that are most likely
         // immediately deallocated again when overwritten in the GraphBuilder
         let input_buffers =
@@ -110,6 +274,8 @@ impl NodeHolder {
 
         Self {
             node,
+            name,
+            group,
             input_buffers,
             output_buffers,
         }
@@ -124,6 +290,18 @@ pub trait Node {
     fn num_outputs(&self) -> usize;
 
     fn render(&mut self, rio: &RenderIo);
+
+    /// Change a parameter of the node by name, e.g. for automating it.
+    /// Returns whether the node has a parameter of that name.
+    fn set_parameter(&mut self, _name: &str, _value: f64) -> bool {
+        false
+    }
+
+    /// A short description of what happens to an input, e.g. its gain,
+    /// shown at the incoming edge when describing the graph.
+    fn describe_input(&self, _index: usize) -> Option<String> {
+        None
+    }
 }
 
 /// References to inputs and outputs while rendering a node.
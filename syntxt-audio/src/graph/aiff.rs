@@ -0,0 +1,332 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing AIFF files directly, without sox.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::dither::{Dither, Quantizer};
+use crate::filter::fir::Decimator;
+use crate::tags::Tags;
+use crate::wave::{Frame, Layout, Stereo};
+
+use log::error;
+
+/// Length of the header up to the samples.
+const HEADER_LEN: u64 = 54;
+
+/// A sample rate as the 80 bit extended precision float of the `COMM` chunk.
+fn extended(value: u32) -> [u8; 10] {
+    let mut bytes = [0; 10];
+    if value > 0 {
+        let shift = value.leading_zeros();
+        let exponent = 16383 + 31 - shift as u16;
+        bytes[..2].copy_from_slice(&exponent.to_be_bytes());
+        bytes[2..].copy_from_slice(&((value as u64) << (32 + shift)).to_be_bytes());
+    }
+    bytes
+}
+
+/// Writes big endian integer samples to an AIFF file, whose sizes are filled in by `finish`.
+pub struct AiffWriter<W: Write + Seek> {
+    out: W,
+    bits: u32,
+    channels: usize,
+    /// Number of frames written so far
+    frames: u64,
+    buffer: Vec<u8>,
+    /// Chunks with the tags, which follow the samples
+    text: Vec<u8>,
+}
+
+impl<W: Write + Seek> AiffWriter<W> {
+    /// A writer for frames with the channels of the layout, as integers of 16, 24 or 32 bits.
+    pub fn new(mut out: W, sample_rate: u32, bits: u32, layout: Layout) -> io::Result<Self> {
+        if bits != 16 && bits != 24 && bits != 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "AIFF files hold integers of 16, 24 or 32 bits, not {} bits",
+                    bits
+                ),
+            ));
+        }
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(b"FORM");
+        // Sizes are only known in the end
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(b"AIFF");
+        header.extend_from_slice(b"COMM");
+        header.extend_from_slice(&18u32.to_be_bytes());
+        header.extend_from_slice(&(layout.channels() as u16).to_be_bytes());
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(&(bits as u16).to_be_bytes());
+        header.extend_from_slice(&extended(sample_rate));
+        header.extend_from_slice(b"SSND");
+        header.extend_from_slice(&0u32.to_be_bytes());
+        // Offset and block size, for aligning the samples
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(&0u32.to_be_bytes());
+        out.write_all(&header)?;
+        Ok(Self {
+            out,
+            bits,
+            channels: layout.channels(),
+            frames: 0,
+            buffer: Vec::new(),
+            text: Vec::new(),
+        })
+    }
+
+    /// Describe the file with tags, which are written by `finish`.
+    pub fn set_tags(&mut self, tags: &Tags) {
+        self.text = tags.aiff_chunks();
+    }
+
+    /// Append frames with the channels of the layout, which are clipped to full scale and rounded.
+    pub fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        debug_assert!(frames.iter().all(|frame| frame.channels() == self.channels));
+        self.buffer.clear();
+        let scale = 2.0f64.powi(self.bits as i32 - 1);
+        let bytes = self.bits as usize / 8;
+        for frame in frames {
+            for value in frame.values() {
+                let value = (value * scale).round().max(-scale).min(scale - 1.0) as i32;
+                self.buffer
+                    .extend_from_slice(&value.to_be_bytes()[4 - bytes..]);
+            }
+        }
+        self.frames += frames.len() as u64;
+        self.out.write_all(&self.buffer)
+    }
+
+    /// Append the tags and fill in the sizes of the file, returning the output.
+    /// Files of more than 4 GB are written, but their sizes do not fit into the header.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_len = self.frames * self.channels as u64 * self.bits as u64 / 8;
+        // Chunks have an even length
+        let padding = data_len % 2;
+        self.out.write_all(&vec![0; padding as usize])?;
+        self.out.write_all(&self.text)?;
+        let size = |len: u64| (len.min(u32::MAX as u64) as u32).to_be_bytes();
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&size(
+            HEADER_LEN - 8 + data_len + padding + self.text.len() as u64,
+        ))?;
+        self.out.seek(SeekFrom::Start(22))?;
+        self.out.write_all(&size(self.frames))?;
+        self.out.seek(SeekFrom::Start(42))?;
+        self.out.write_all(&size(8 + data_len))?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes its input to an AIFF file, which is completed when the sink is dropped.
+pub struct AiffSink {
+    writer: Option<AiffWriter<BufWriter<File>>>,
+    /// The first error while writing, reported when the file is completed
+    error: Option<io::Error>,
+    /// Reduces oversampled input to the sample rate of the output
+    decimator: Option<Decimator>,
+    decimated: Vec<Stereo<f64>>,
+    /// Rounds to the bits of the file
+    quantizer: Quantizer,
+}
+
+impl AiffSink {
+    /// Create a file for frames with the channels of the layout.
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        bits: u32,
+        layout: Layout,
+        dither: Dither,
+    ) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            writer: Some(AiffWriter::new(file, sample_rate, bits, layout)?),
+            error: None,
+            decimator: None,
+            decimated: Vec::new(),
+            quantizer: Quantizer::new(bits, dither),
+        })
+    }
+
+    /// Accept input at `factor` times the sample rate of the output,
+    /// filtering it down before writing it.
+    pub fn with_oversampling(mut self, factor: usize) -> Self {
+        self.decimator = if factor > 1 {
+            Some(Decimator::new(factor))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Describe the file with tags.
+    pub fn with_tags(mut self, tags: &Tags) -> Self {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.set_tags(tags);
+        }
+        self
+    }
+
+    /// Write stereo samples directly, e.g. when they were rendered before.
+    /// After an error, further samples are dropped and `finish` returns the error.
+    pub fn write(&mut self, samples: &[Stereo<f64>]) {
+        let samples = match self.decimator.as_mut() {
+            None => samples,
+            Some(decimator) => {
+                self.decimated.clear();
+                decimator.process(samples, &mut self.decimated);
+                &self.decimated
+            }
+        };
+        let frames = samples
+            .iter()
+            .map(|sample| Frame::from(*sample))
+            .collect::<Vec<_>>();
+        self.write_frames(&frames);
+    }
+
+    /// Write frames with the channels of the layout at the sample rate of the file.
+    pub fn write_frames(&mut self, frames: &[Frame]) {
+        let writer = match self.writer.as_mut() {
+            Some(writer) if self.error.is_none() => writer,
+            _ => return,
+        };
+        let quantizer = &mut self.quantizer;
+        let frames = frames
+            .iter()
+            .map(|frame| quantizer.quantize_frame(*frame))
+            .collect::<Vec<_>>();
+        if let Err(err) = writer.write_frames(&frames) {
+            self.error = Some(err);
+        }
+    }
+
+    /// Complete the file, or return the error that stopped writing it.
+    /// Writing afterwards has no effect.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let writer = self.writer.take();
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        match writer {
+            Some(writer) => writer.finish().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for AiffSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            error!("Failed to complete AIFF file: {}", err);
+        }
+    }
+}
+
+impl super::Node for AiffSink {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        self.write(rio.input(0).samples());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{extended, AiffWriter};
+    use crate::tags::Tags;
+    use crate::wave::{Frame, Layout, Stereo};
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut be = [0; 4];
+        be.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_be_bytes(be)
+    }
+
+    #[test]
+    fn sample_rates() {
+        assert_eq!(extended(44100), [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended(48000), [0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(extended(1), [0x3f, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn pcm() {
+        let mut writer = AiffWriter::new(Cursor::new(Vec::new()), 44100, 16, Layout::Quad).unwrap();
+        let pairs = [Stereo::new(0.5, -1.0), Stereo::new(2.0, -0.25)];
+        writer.write_frames(&[Frame::from_pairs(&pairs)]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(&bytes[..4], b"FORM");
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"AIFFCOMM");
+        assert_eq!(u32_at(&bytes, 16), 18);
+        // Channels, frames, bits and sample rate
+        assert_eq!(&bytes[20..22], &[0, 4]);
+        assert_eq!(u32_at(&bytes, 22), 1);
+        assert_eq!(&bytes[26..28], &[0, 16]);
+        assert_eq!(&bytes[28..32], &[0x40, 0x0e, 0xac, 0x44]);
+        assert_eq!(&bytes[38..42], b"SSND");
+        assert_eq!(u32_at(&bytes, 42), 8 + 8);
+        let values = bytes[54..]
+            .chunks(2)
+            .map(|be| i16::from_be_bytes([be[0], be[1]]))
+            .collect::<Vec<_>>();
+        // Clipped to full scale
+        assert_eq!(values, vec![16384, -32768, 32767, -8192]);
+    }
+
+    #[test]
+    fn tags() {
+        let mut writer =
+            AiffWriter::new(Cursor::new(Vec::new()), 48000, 24, Layout::Stereo).unwrap();
+        writer.set_tags(&Tags {
+            title: Some("Song".into()),
+            ..Tags::default()
+        });
+        writer
+            .write_frames(&[Frame::from(Stereo::new(0.5, 0.0))])
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(u32_at(&bytes, 42), 8 + 6);
+        assert_eq!(&bytes[54..60], &[0x40, 0, 0, 0, 0, 0]);
+        // The tags follow the samples
+        assert_eq!(&bytes[60..64], b"NAME");
+        assert_eq!(bytes.len(), 60 + 12);
+    }
+
+    #[test]
+    fn bits() {
+        let writer = |bits| AiffWriter::new(Cursor::new(Vec::new()), 48000, bits, Layout::Stereo);
+        assert!(writer(24).is_ok());
+        assert!(writer(8).is_err());
+        assert!(writer(64).is_err());
+    }
+}
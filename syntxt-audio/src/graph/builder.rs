@@ -22,7 +22,16 @@ use super::*;
 /// Construct an audio graph.
 pub struct GraphBuilder {
     nodes: Vec<Box<dyn Node>>,
+    /// Type names of the nodes, for diagnostics.
+    names: Vec<&'static str>,
+    /// The group of each node, see `group`.
+    groups: Vec<String>,
+    /// The group of nodes added from now on.
+    group: String,
     edges: Vec<(OutputRef, InputRef)>,
+    /// Edges that are allowed to form cycles, with their delay in samples.
+    feedbacks: Vec<(OutputRef, InputRef, Sample)>,
+    automations: Vec<Automation>,
 }
 
 impl Default for GraphBuilder {
@@ -35,28 +44,82 @@ impl GraphBuilder {
     pub fn new() -> Self {
         Self {
             nodes: Vec::new(),
+            names: Vec::new(),
+            groups: Vec::new(),
+            group: String::new(),
             edges: Vec::new(),
+            feedbacks: Vec::new(),
+            automations: Vec::new(),
         }
     }
 
+    /// Let a parameter of a node follow a curve, whose time is measured in samples.
+    pub fn automate(&mut self, node: NodeId, parameter: &str, curve: Curve) {
+        self.automations.push(Automation {
+            node,
+            parameter: parameter.to_string(),
+            curve,
+        });
+    }
+
+    /// Feed an output to an input with a delay, which makes it possible to form loops,
+    /// e.g. for feedback delays or resonators.
+    /// The delay is given in samples and must be at least the buffer size of the graph.
+    pub fn feedback(&mut self, output: OutputRef, input: InputRef, delay: Sample) {
+        self.feedbacks.push((output, input, delay));
+    }
+
+    /// Make the nodes added from now on part of a group, e.g. a track, for telling apart nodes
+    /// of the same type when profiling or drawing the graph.
+    /// Nodes are in no group (an empty one) by default.
+    pub fn group(&mut self, group: &str) {
+        self.group = group.to_string();
+    }
+
     pub fn add_node<N: Node + 'static>(&mut self, node: N) -> NodeBuilder<'_> {
         let id = NodeId(self.nodes.len());
         self.nodes.push(Box::new(node));
+        self.names.push(std::any::type_name::<N>());
+        self.groups.push(self.group.clone());
         NodeBuilder {
             graph_builder: self,
             node: id,
         }
     }
 
-    /// Consume the GraphBuilder and turn it into a graph, provided that the graph structure has no cycles.
+    /// Consume the GraphBuilder and turn it into a graph, provided that the graph structure has no cycles
+    /// other than through feedback edges.
     ///
     /// NOTE: Currently, failure to build the graph means that all nodes are lost.
     /// Do we need a way to recover those?
-    pub fn build(self, buffer_size: Sample) -> Result<Graph, GraphBuildError> {
+    pub fn build(mut self, buffer_size: Sample) -> Result<Graph, GraphBuildError> {
+        // Check the automated parameters, already setting their initial values
+        for automation in self.automations.iter() {
+            let node =
+                self.nodes
+                    .get_mut(automation.node.0)
+                    .ok_or(GraphBuildError::InvalidNode {
+                        node: automation.node,
+                    })?;
+            // A curve without points never changes anything
+            let value = match automation.curve.value_at(0.0) {
+                Some(value) => value,
+                None => continue,
+            };
+            if !node.set_parameter(&automation.parameter, value) {
+                return Err(GraphBuildError::UnknownParameter {
+                    node: automation.node,
+                    parameter: automation.parameter.clone(),
+                });
+            }
+        }
+
         let mut nodes: Vec<NodeHolder> = self
             .nodes
             .into_iter()
-            .map(|node| NodeHolder::new(node, buffer_size))
+            .zip(self.names)
+            .zip(self.groups)
+            .map(|((node, name), group)| NodeHolder::new(node, name, group, buffer_size))
             .collect();
 
         let mut incoming: Vec<Vec<NodeId>> =
@@ -64,6 +127,17 @@ impl GraphBuilder {
         let mut outgoing: Vec<Vec<NodeId>> =
             std::iter::repeat(Vec::new()).take(nodes.len()).collect();
 
+        let edges = self
+            .edges
+            .iter()
+            .map(|(output, input)| (*output, *input, None))
+            .chain(
+                self.feedbacks
+                    .iter()
+                    .map(|(output, input, delay)| (*output, *input, Some(*delay))),
+            )
+            .collect();
+
         // Connect the output buffers to the inputs and prepare topological sorting
         for (output, input) in self.edges {
             let buffer = Rc::clone(
@@ -89,6 +163,35 @@ impl GraphBuilder {
             }
         }
 
+        // Feedback edges get their own input buffer which is filled after every step
+        let mut feedbacks = Vec::new();
+        for (output, input, delay) in self.feedbacks {
+            if delay < buffer_size {
+                return Err(GraphBuildError::FeedbackTooShort { delay, buffer_size });
+            }
+            let output_buffer = Rc::clone(
+                nodes
+                    .get(output.node.0)
+                    .ok_or(GraphBuildError::InvalidNode { node: output.node })?
+                    .output_buffers
+                    .get(output.index)
+                    .ok_or(GraphBuildError::InvalidOutput { output })?,
+            );
+            let input_buffer = Rc::new(RefCell::new(AudioBuffer::new(buffer_size)));
+            *(nodes
+                .get_mut(input.node.0)
+                .ok_or(GraphBuildError::InvalidNode { node: input.node })?
+                .input_buffers
+                .get_mut(input.index)
+                .ok_or(GraphBuildError::InvalidInput { input })?) = Rc::clone(&input_buffer);
+            feedbacks.push(Feedback::new(
+                output_buffer,
+                input_buffer,
+                delay,
+                buffer_size,
+            ));
+        }
+
         // Topological sort using Kahn's algorithm
         let mut sorted_nodes = Vec::new();
         let mut nodes_without_incoming_edges: Vec<_> = incoming
@@ -110,13 +213,18 @@ impl GraphBuilder {
         }
 
         // Cycles are bad because then the order is undefined and makes a difference.
-        // Better solution: add explicit support for feedback loops to the graph builder if ever necessary.
+        // Intentional loops need to be declared as feedback edges instead.
         if incoming.iter().any(|from| !from.is_empty()) {
             Err(GraphBuildError::Cycle)
         } else {
             Ok(Graph {
                 nodes,
                 evaluation_order: sorted_nodes,
+                automations: self.automations,
+                feedbacks,
+                edges,
+                checker: None,
+                profiler: None,
                 time: 0,
                 buffer_size,
             })
@@ -135,6 +243,14 @@ pub enum GraphBuildError {
     InvalidInput { input: InputRef },
     #[snafu(display("Referenced output {:?} does not exist", output))]
     InvalidOutput { output: OutputRef },
+    #[snafu(display("Node {:?} has no parameter `{}`", node, parameter))]
+    UnknownParameter { node: NodeId, parameter: String },
+    #[snafu(display(
+        "Feedback delay of {} samples is shorter than the buffer size {}",
+        delay,
+        buffer_size
+    ))]
+    FeedbackTooShort { delay: Sample, buffer_size: Sample },
 }
 
 /// Construct the connections between nodes.
@@ -170,6 +286,8 @@ impl<'a> NodeBuilder<'a> {
 mod tests {
 
     use super::*;
+    use crate::automation::{CurvePoint, Interpolation};
+    use std::cell::Cell;
 
     /// Check that the builder correctly errors out on a cycle.
     #[test]
@@ -210,6 +328,217 @@ mod tests {
         assert_eq!(graph.evaluation_order, vec![source, x, y, sink]);
     }
 
+    /// Check that automated parameters are updated at the start of every buffer,
+    /// and that unknown parameters are reported.
+    #[test]
+    fn automation() {
+        let ramp = Curve::new(vec![
+            CurvePoint {
+                time: 0.0,
+                value: 0.0,
+                interpolation: Interpolation::Linear,
+            },
+            CurvePoint {
+                time: 20.0,
+                value: 2.0,
+                interpolation: Interpolation::Step,
+            },
+        ]);
+        let value = Rc::new(Cell::new(-1.0));
+        let mut b = GraphBuilder::new();
+        let node = b.add_node(Parameter(Rc::clone(&value))).build();
+        b.automate(node, "value", ramp.clone());
+        let mut graph = b.build(10).unwrap();
+        let mut values = Vec::new();
+        for _ in 0..3 {
+            graph.step();
+            values.push(value.get());
+        }
+        assert_eq!(values, vec![0.0, 1.0, 2.0]);
+
+        let mut b = GraphBuilder::new();
+        let sink = b.add_node(Sink).build();
+        b.automate(sink, "value", ramp);
+        assert_eq!(
+            b.build(10).err(),
+            Some(GraphBuildError::UnknownParameter {
+                node: sink,
+                parameter: "value".to_string()
+            })
+        );
+    }
+
+    /// Check that profiling counts the renders of every node from when it is enabled,
+    /// and that nodes keep their groups.
+    #[test]
+    fn profiling() {
+        let mut b = GraphBuilder::new();
+        let source = b.add_node(Source).build();
+        b.group("output");
+        b.add_node(Sink).input_from(0, source.output(0)).build();
+        let mut graph = b.build(10).unwrap();
+        assert_eq!(graph.profiled(), None);
+        graph.step();
+        graph.profile();
+        graph.step();
+        graph.step();
+        let profile = graph.profiled().unwrap();
+        let nodes = profile
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.group.as_str(), node.renders))
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, vec![("Source", "", 2), ("Sink", "output", 2)]);
+    }
+
+    /// Check that a loop closed by a feedback edge is accepted,
+    /// and that the signal comes back with the requested delay.
+    #[test]
+    fn feedback() {
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let mut b = GraphBuilder::new();
+        let impulse = b.add_node(Impulse).build();
+        let echo = b
+            .add_node(Echo(Rc::clone(&recorded)))
+            .input_from(0, impulse.output(0))
+            .build();
+        b.feedback(echo.output(0), echo.input(1), 15);
+        let mut graph = b.build(10).unwrap();
+        for _ in 0..5 {
+            graph.step();
+        }
+
+        let recorded = recorded.borrow();
+        assert_eq!(recorded.len(), 50);
+        for (time, value) in recorded.iter().enumerate() {
+            let expected = if time % 15 == 0 {
+                0.5f64.powi(time as i32 / 15)
+            } else {
+                0.0
+            };
+            assert_eq!(*value, expected, "{}", time);
+        }
+
+        let mut b = GraphBuilder::new();
+        let echo = b.add_node(Echo(Rc::new(RefCell::new(Vec::new())))).build();
+        b.feedback(echo.output(0), echo.input(1), 5);
+        assert_eq!(
+            b.build(10).err(),
+            Some(GraphBuildError::FeedbackTooShort {
+                delay: 5,
+                buffer_size: 10
+            })
+        );
+    }
+
+    /// Check that broken samples are reported once per node and kind, with their time.
+    #[test]
+    fn check() {
+        let mut b = GraphBuilder::new();
+        let broken = b.add_node(Broken).build();
+        let _sink = b.add_node(Sink).input_from(0, broken.output(0)).build();
+        let mut graph = b.build(10).unwrap();
+        graph.step();
+        assert!(graph.problems().is_empty());
+        graph.check();
+        for _ in 0..3 {
+            graph.step();
+        }
+
+        // The first clipping happened before checking was enabled
+        let problems = graph
+            .problems()
+            .iter()
+            .map(|problem| (problem.kind, problem.time, problem.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                (ProblemKind::Clipping, 12, 2),
+                (ProblemKind::NotANumber, 25, 2),
+                (ProblemKind::Denormal, 33, 2),
+            ]
+        );
+        assert!(graph.problems().iter().all(|problem| problem.node == broken
+            && problem.name == "Broken"
+            && problem.output == 0));
+    }
+
+    /// Clips at samples 2 and 12, NaN at 25 and denormal at 33.
+    pub struct Broken;
+    impl Node for Broken {
+        fn num_inputs(&self) -> usize {
+            0
+        }
+        fn num_outputs(&self) -> usize {
+            1
+        }
+        fn render(&mut self, rio: &RenderIo) {
+            for (index, sample) in rio.output(0).iter_mut().enumerate() {
+                let value = match rio.start() + index {
+                    2 | 12 => -1.5,
+                    25 => f64::NAN,
+                    33 => 1e-310,
+                    _ => 0.5,
+                };
+                *sample = Stereo::mono(value);
+            }
+        }
+    }
+
+    /// A single click at the start.
+    pub struct Impulse;
+    impl Node for Impulse {
+        fn num_inputs(&self) -> usize {
+            0
+        }
+        fn num_outputs(&self) -> usize {
+            1
+        }
+        fn render(&mut self, rio: &RenderIo) {
+            for (index, sample) in rio.output(0).iter_mut().enumerate() {
+                let value = if rio.start() + index == 0 { 1.0 } else { 0.0 };
+                *sample = Stereo::mono(value);
+            }
+        }
+    }
+
+    /// Adds the second input at half volume to the first one, recording the output.
+    pub struct Echo(Rc<RefCell<Vec<f64>>>);
+    impl Node for Echo {
+        fn num_inputs(&self) -> usize {
+            2
+        }
+        fn num_outputs(&self) -> usize {
+            1
+        }
+        fn render(&mut self, rio: &RenderIo) {
+            let dry = rio.input(0);
+            let wet = rio.input(1);
+            let mut output = rio.output(0);
+            for ((out, dry), wet) in output.iter_mut().zip(dry.iter()).zip(wet.iter()) {
+                *out = *dry + *wet * 0.5;
+                self.0.borrow_mut().push(out.left);
+            }
+        }
+    }
+
+    /// A node exposing the value of its only parameter.
+    pub struct Parameter(Rc<Cell<f64>>);
+    impl Node for Parameter {
+        fn num_inputs(&self) -> usize {
+            0
+        }
+        fn num_outputs(&self) -> usize {
+            0
+        }
+        fn render(&mut self, _rio: &RenderIo) {}
+        fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+            self.0.set(value);
+            name == "value"
+        }
+    }
+
     pub struct Source;
     impl Node for Source {
         fn num_inputs(&self) -> usize {
@@ -0,0 +1,173 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of broken samples while rendering, for debugging songs and nodes.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::num::FpCategory;
+use std::rc::Rc;
+
+use super::{NodeId, Sample};
+use crate::wave::AudioBuffer;
+
+/// The ways a sample can be broken.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum ProblemKind {
+    /// Exceeds the range from -1 to 1 and would clip in the output.
+    Clipping,
+    /// Not a number, e.g. from dividing zero by zero.
+    NotANumber,
+    /// Positive or negative infinity.
+    Infinite,
+    /// So close to zero that it is stored with reduced precision, which is very slow to compute with.
+    Denormal,
+}
+
+impl ProblemKind {
+    /// The problem of a sample, if any. NaN and infinity take precedence over clipping.
+    pub fn of(value: f64) -> Option<Self> {
+        match value.classify() {
+            FpCategory::Nan => Some(ProblemKind::NotANumber),
+            FpCategory::Infinite => Some(ProblemKind::Infinite),
+            FpCategory::Subnormal => Some(ProblemKind::Denormal),
+            _ if value.abs() > 1.0 => Some(ProblemKind::Clipping),
+            _ => None,
+        }
+    }
+}
+
+/// Broken samples of one kind at one output of a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub node: NodeId,
+    /// Type of the node, without module paths.
+    pub name: String,
+    pub output: usize,
+    pub kind: ProblemKind,
+    /// Time of the first broken sample.
+    pub time: Sample,
+    /// Value of the first broken sample.
+    pub value: f64,
+    /// Number of broken samples, counting the left and right channel separately.
+    pub count: usize,
+}
+
+/// Collects the problems of all nodes of a graph.
+pub(super) struct Checker {
+    problems: Vec<Problem>,
+    /// Position of the problems in the list
+    index: HashMap<(NodeId, usize, ProblemKind), usize>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            problems: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Look at the outputs a node just rendered, starting at the given time.
+    pub fn inspect(
+        &mut self,
+        node: NodeId,
+        name: &str,
+        outputs: &[Rc<RefCell<AudioBuffer>>],
+        start: Sample,
+    ) {
+        for (output, buffer) in outputs.iter().enumerate() {
+            for (offset, sample) in buffer.borrow().iter().enumerate() {
+                for value in [sample.left, sample.right].iter() {
+                    let kind = match ProblemKind::of(*value) {
+                        Some(kind) => kind,
+                        None => continue,
+                    };
+                    let problems = &mut self.problems;
+                    let position = *self.index.entry((node, output, kind)).or_insert_with(|| {
+                        problems.push(Problem {
+                            node,
+                            name: short_name(name),
+                            output,
+                            kind,
+                            time: start + offset,
+                            value: *value,
+                            count: 0,
+                        });
+                        problems.len() - 1
+                    });
+                    self.problems[position].count += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Remove the module paths from a type name, also in its type arguments.
+pub(super) fn short_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    // Where the path currently written started
+    let mut path_start = 0;
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("::") {
+            short.truncate(path_start);
+            rest = &rest[2..];
+            continue;
+        }
+        short.push(c);
+        if !(c.is_alphanumeric() || c == '_') {
+            path_start = short.len();
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{short_name, ProblemKind};
+
+    #[test]
+    fn problem_kinds() {
+        assert_eq!(ProblemKind::of(0.0), None);
+        assert_eq!(ProblemKind::of(-1.0), None);
+        assert_eq!(ProblemKind::of(1.5), Some(ProblemKind::Clipping));
+        assert_eq!(ProblemKind::of(f64::NAN), Some(ProblemKind::NotANumber));
+        assert_eq!(
+            ProblemKind::of(f64::NEG_INFINITY),
+            Some(ProblemKind::Infinite)
+        );
+        assert_eq!(
+            ProblemKind::of(f64::MIN_POSITIVE / 4.0),
+            Some(ProblemKind::Denormal)
+        );
+    }
+
+    #[test]
+    fn short_names() {
+        assert_eq!(short_name("syntxt_audio::graph::Gain"), "Gain");
+        assert_eq!(
+            short_name("a::EffectNode<b::c::Smooth<d::Reverb>>"),
+            "EffectNode<Smooth<Reverb>>"
+        );
+        assert_eq!(short_name("a::Pair<b::X, c::Y>"), "Pair<X, Y>");
+    }
+}
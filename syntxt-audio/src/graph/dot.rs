@@ -0,0 +1,139 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Describing a graph in the DOT language of Graphviz, for looking at how a song is routed.
+
+use std::fmt::Write;
+
+use super::{check::short_name, InputRef, NodeHolder, OutputRef, Sample};
+
+/// The nodes as boxes labelled with their type, in a cluster for every group,
+/// and the edges between them. Feedback edges are dashed and labelled with their delay.
+pub(super) fn to_dot(
+    nodes: &[NodeHolder],
+    edges: &[(OutputRef, InputRef, Option<Sample>)],
+) -> String {
+    let mut dot = String::new();
+    dot.push_str("digraph {\n    rankdir=LR;\n    node [shape=box];\n");
+
+    // Groups in the order their first node was added
+    let mut groups: Vec<&str> = Vec::new();
+    for holder in nodes.iter() {
+        if !groups.contains(&holder.group.as_str()) {
+            groups.push(&holder.group);
+        }
+    }
+    for (index, group) in groups.iter().enumerate() {
+        let indent = if group.is_empty() {
+            "    "
+        } else {
+            writeln!(dot, "    subgraph cluster_{} {{", index).unwrap();
+            writeln!(dot, "        label={};", quote(group)).unwrap();
+            "        "
+        };
+        for (node, holder) in nodes.iter().enumerate() {
+            if holder.group == *group {
+                let label = quote(&short_name(holder.name));
+                writeln!(dot, "{}n{} [label={}];", indent, node, label).unwrap();
+            }
+        }
+        if !group.is_empty() {
+            dot.push_str("    }\n");
+        }
+    }
+
+    for (output, input, delay) in edges.iter() {
+        let mut label = Vec::new();
+        if nodes[output.node.0].output_buffers.len() > 1 {
+            label.push(format!("output {}", output.index));
+        }
+        let target = &nodes[input.node.0];
+        match target.node.describe_input(input.index) {
+            Some(description) => label.push(description),
+            None if target.input_buffers.len() > 1 => label.push(format!("input {}", input.index)),
+            None => {}
+        }
+        let mut attributes = Vec::new();
+        if let Some(delay) = delay {
+            label.push(format!("delay {}", delay));
+            attributes.push("style=dashed".to_string());
+        }
+        if !label.is_empty() {
+            attributes.push(format!("label={}", quote(&label.join(", "))));
+        }
+        write!(dot, "    n{} -> n{}", output.node.0, input.node.0).unwrap();
+        if !attributes.is_empty() {
+            write!(dot, " [{}]", attributes.join(", ")).unwrap();
+        }
+        dot.push_str(";\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// A quoted string in DOT.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{Gain, GraphBuilder, Mix};
+
+    #[test]
+    fn clusters_and_edges() {
+        let mut b = GraphBuilder::new();
+        b.group("track 0 (Lead)");
+        let lead = b.add_node(Gain::from_decibels(0.0)).build();
+        b.group("bus 0");
+        let send = b
+            .add_node(Mix::new(vec![0.5]))
+            .input_from(0, lead.output(0))
+            .build();
+        b.group("master");
+        let mix = b
+            .add_node(Mix::new(vec![1.0, 0.25]))
+            .input_from(0, lead.output(0))
+            .input_from(1, send.output(0))
+            .build();
+        b.feedback(mix.output(0), lead.input(0), 20);
+        let graph = b.build(10).unwrap();
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph {
+    rankdir=LR;
+    node [shape=box];
+    subgraph cluster_0 {
+        label="track 0 (Lead)";
+        n0 [label="Gain"];
+    }
+    subgraph cluster_1 {
+        label="bus 0";
+        n1 [label="Mix"];
+    }
+    subgraph cluster_2 {
+        label="master";
+        n2 [label="Mix"];
+    }
+    n0 -> n1 [label="gain 0.50"];
+    n0 -> n2 [label="gain 1.00"];
+    n1 -> n2 [label="gain 0.25"];
+    n2 -> n0 [style=dashed, label="delay 20"];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::effect::{compressor::Compressor, Effect};
+use crate::process::{self, ProcessEffect, MAX_BLOCK_SIZE};
+use crate::smoothing::Smooth;
+
+/// A node applying an effect to its input.
+pub struct EffectNode<E: Effect> {
+    effect: ProcessEffect<E>,
+}
+
+impl<E: Effect> EffectNode<E> {
+    pub fn new(effect: E) -> Self {
+        Self {
+            effect: ProcessEffect::new(effect),
+        }
+    }
+}
+
+impl<E: Effect> super::Node for EffectNode<E> {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        1
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        let input = rio.input(0);
+        let mut output = rio.output(0);
+        output.samples_mut().copy_from_slice(input.samples());
+        process::process_in_blocks(&mut self.effect, rio.start(), output.samples_mut(), &[]);
+    }
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.effect.effect_mut().set_parameter(name, value)
+    }
+}
+
+/// A node compressing its first input, optionally with the level detected from its second
+/// input instead (sidechain).
+pub struct CompressorNode {
+    compressor: Smooth<Compressor>,
+    sidechain: bool,
+}
+
+impl CompressorNode {
+    /// A compressor reacting to the level of its only input.
+    pub fn new(compressor: Smooth<Compressor>) -> Self {
+        Self {
+            compressor,
+            sidechain: false,
+        }
+    }
+
+    /// A compressor with a second input whose level controls the compression of the first.
+    pub fn with_sidechain(compressor: Smooth<Compressor>) -> Self {
+        Self {
+            compressor,
+            sidechain: true,
+        }
+    }
+}
+
+impl super::Node for CompressorNode {
+    fn num_inputs(&self) -> usize {
+        if self.sidechain {
+            2
+        } else {
+            1
+        }
+    }
+    fn num_outputs(&self) -> usize {
+        1
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        let input = rio.input(0);
+        let mut output = rio.output(0);
+        output.samples_mut().copy_from_slice(input.samples());
+        let blocks = output.samples_mut().chunks_mut(MAX_BLOCK_SIZE);
+        if self.sidechain {
+            let detector = rio.input(1);
+            for (block, level) in blocks.zip(detector.samples().chunks(MAX_BLOCK_SIZE)) {
+                self.compressor.process_with_detector(block, level);
+            }
+        } else {
+            for block in blocks {
+                self.compressor.process(block);
+            }
+        }
+    }
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.compressor.set_parameter(name, value)
+    }
+}
@@ -14,21 +14,29 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{instrument::Instrument, song::PlayedNote};
+use crate::{
+    instrument::Instrument,
+    process::{self, Event, EventKind, ProcessInstrument},
+    song::{PitchBend, PlayedNote},
+};
 use std::collections::BinaryHeap;
 use syntxt_core::note::{Note, Velocity};
 
 use log::trace;
 
 pub struct InstrumentSource<I: Instrument> {
-    instrument: I,
+    instrument: ProcessInstrument<I>,
     /// The notes that are played on this track
     play_queue: Vec<QueuedPlay>,
     /// The next note to be played
     next_note: usize,
     /// The currently active notes that are to be released in the future.
-    note_releases: BinaryHeap<QueuedRelease<I::PlayHandle>>,
-    /// How many samples were already played.
+    note_releases: BinaryHeap<QueuedRelease>,
+    /// The pitch bends of this track, with the sample number where they happen
+    bend_queue: Vec<(usize, f64)>,
+    /// The next pitch bend to be applied
+    next_bend: usize,
+    /// The sample where the previous buffer ended.
     samples_processed: usize,
 }
 
@@ -46,19 +54,43 @@ impl<I: Instrument> InstrumentSource<I> {
                 end_sample: time_sig.samples(note.start + note.duration, sample_rate) as usize,
                 note: note.note,
                 velocity: note.velocity,
+                detune: note.detune,
             })
             .collect();
         // The notes must be sorted in the order they are played for `fill_buffer` to work correctly.
         play_queue.sort_by_key(|n| n.begin_sample);
 
         Self {
-            instrument,
+            instrument: ProcessInstrument::new(instrument),
             play_queue,
             next_note: 0,
             note_releases: BinaryHeap::new(),
+            bend_queue: Vec::new(),
+            next_bend: 0,
             samples_processed: 0,
         }
     }
+
+    /// Apply the given pitch bends to the instrument while playing.
+    pub fn with_bends(
+        mut self,
+        sample_rate: i64,
+        time_sig: crate::song::TimeSig,
+        bends: Vec<PitchBend>,
+    ) -> Self {
+        self.bend_queue = bends
+            .into_iter()
+            .map(|bend| {
+                (
+                    time_sig.samples(bend.start, sample_rate) as usize,
+                    bend.amount,
+                )
+            })
+            .collect();
+        // Keep the order of bends happening at the same time
+        self.bend_queue.sort_by_key(|(sample, _)| *sample);
+        self
+    }
 }
 
 impl<I: Instrument> super::Node for InstrumentSource<I> {
@@ -70,54 +102,87 @@ impl<I: Instrument> super::Node for InstrumentSource<I> {
     }
     fn render(&mut self, rio: &super::RenderIo) {
         let mut audio_buffer = rio.output(0);
-        audio_buffer.fill_zero();
 
         // Compute start and end time of this buffer in samples
-        let buffer_start = self.samples_processed;
-        let buffer_end = self.samples_processed + audio_buffer.len();
+        let buffer_start = rio.start();
+        let buffer_end = buffer_start + audio_buffer.len();
+        let mut events = Vec::new();
+        if buffer_start != self.samples_processed {
+            // Rendering started later, notes that were still playing then start right away
+            self.instrument.instrument_mut().seek(buffer_start);
+        }
 
         // process all notes that are due in the current window
         while self.next_note < self.play_queue.len()
             && self.play_queue[self.next_note].begin_sample < buffer_end
         {
             let note = &self.play_queue[self.next_note];
-            let handle = self.instrument.play_note(
-                note.begin_sample - buffer_start,
-                note.note,
-                note.velocity,
-            );
+            if note.begin_sample.max(note.end_sample) < buffer_start {
+                self.next_note += 1;
+                continue;
+            }
             trace!(
-                "{:7}: play {:?} as {:?}",
+                "{:7}: play {:?} as #{}",
                 note.begin_sample,
                 note.note,
-                handle
+                self.next_note
             );
+            events.push(Event {
+                offset: note.begin_sample.saturating_sub(buffer_start),
+                kind: EventKind::NoteOn {
+                    id: self.next_note,
+                    note: note.note,
+                    velocity: note.velocity,
+                    detune: note.detune,
+                },
+            });
+            // A note ending before it begins is released right away
             self.note_releases.push(QueuedRelease {
-                end_sample: note.end_sample,
-                note_handle: handle,
+                end_sample: note.end_sample.max(note.begin_sample),
+                id: self.next_note,
             });
             self.next_note += 1;
         }
+        // process all pitch bends that are due in the current window
+        while self.next_bend < self.bend_queue.len()
+            && self.bend_queue[self.next_bend].0 < buffer_end
+        {
+            let (sample, amount) = self.bend_queue[self.next_bend];
+            trace!("{:7}: bend {}", sample, amount);
+            events.push(Event {
+                offset: sample.saturating_sub(buffer_start),
+                kind: EventKind::PitchBend(amount),
+            });
+            self.next_bend += 1;
+        }
         // process all note releases that are due in the current window
         // NOTE: must be done after processing the notes to play in order
         // to catch notes that last shorter than one buffer window.
         while let Some(release) = self.note_releases.peek() {
             if release.end_sample < buffer_end {
-                trace!(
-                    "{:7}: release {:?}",
-                    release.end_sample,
-                    release.note_handle
-                );
+                trace!("{:7}: release #{}", release.end_sample, release.id);
                 let release = self.note_releases.pop().unwrap();
-                self.instrument
-                    .release_note(release.end_sample - buffer_start, release.note_handle)
+                events.push(Event {
+                    offset: release.end_sample.saturating_sub(buffer_start),
+                    kind: EventKind::NoteOff { id: release.id },
+                });
             } else {
                 break;
             }
         }
+        // The sort is stable, so at the same sample notes start before bends and releases
+        events.sort_by_key(|event| event.offset);
 
         self.samples_processed = buffer_end;
-        self.instrument.fill_buffer(audio_buffer.samples_mut());
+        process::process_in_blocks(
+            &mut self.instrument,
+            buffer_start,
+            audio_buffer.samples_mut(),
+            &events,
+        );
+    }
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.instrument.instrument_mut().set_parameter(name, value)
     }
 }
 
@@ -132,26 +197,28 @@ struct QueuedPlay {
     note: Note,
     /// How fast the note is played.
     velocity: Velocity,
+    /// Deviation from the tuning of the instrument in cents.
+    detune: f64,
 }
 
 /// A note that is currently played and scheduled to be released in the future.
-struct QueuedRelease<H> {
+struct QueuedRelease {
     /// The number of the sample where the note stops playing.
     end_sample: usize,
-    /// The handle to the note that is playing, needed for releasing the note.
-    note_handle: H,
+    /// The id of the event that started the note, needed for releasing the note.
+    id: usize,
 }
 
 /// Queued releases are compared by their scheduled time.
-impl<H> PartialEq for QueuedRelease<H> {
+impl PartialEq for QueuedRelease {
     fn eq(&self, other: &Self) -> bool {
         self.end_sample == other.end_sample
     }
 }
 
-impl<H> Eq for QueuedRelease<H> {}
+impl Eq for QueuedRelease {}
 
-impl<H> PartialOrd for QueuedRelease<H> {
+impl PartialOrd for QueuedRelease {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
@@ -159,7 +226,7 @@ impl<H> PartialOrd for QueuedRelease<H> {
 
 /// Queued releases are compared by their scheduled time,
 /// the higher the release time, the smaller the QueuedRelease (in order to use them in the standard binary heap).
-impl<H> Ord for QueuedRelease<H> {
+impl Ord for QueuedRelease {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         // the smallest release time is the largest queued release for the max heap
         other.end_sample.cmp(&self.end_sample)
@@ -0,0 +1,321 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Playing into a JACK session as a client with one output port per channel.
+//!
+//! The song is rendered ahead on the calling thread into a lock-free ring buffer,
+//! from which the real-time thread of JACK takes the samples.
+//! Playback follows the JACK transport: the song starts when the transport starts rolling
+//! and pauses while it is stopped. Relocating the transport is not followed, the song always
+//! continues where it paused.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use jack::{
+    AsyncClient, AudioOut, Client, ClientOptions, ClientStatus, Control, Frames,
+    NotificationHandler, Port, PortFlags, ProcessHandler, ProcessScope, RingBuffer,
+    RingBufferReader, RingBufferWriter, Transport, TransportState,
+};
+use log::{error, info, warn};
+
+use crate::filter::fir::Decimator;
+use crate::wave::Stereo;
+
+/// Bytes of one sample in the ring buffer: two interleaved 32 bit floats.
+const FRAME_BYTES: usize = 8;
+
+/// Bytes rendered ahead, about 0.75 seconds at 44100 Hz.
+const RING_BYTES: usize = 1 << 18;
+
+/// How long to wait for the real-time thread when the ring buffer is full.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+fn jack_error(err: jack::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("JACK: {}", err))
+}
+
+/// State shared with the real-time thread.
+#[derive(Default)]
+struct Shared {
+    /// No more samples are written to the ring buffer.
+    finished: AtomicBool,
+    /// Everything was played after finishing.
+    drained: AtomicBool,
+    /// The server shut the client down, so nothing is played anymore.
+    shutdown: AtomicBool,
+    /// Cycles in which the rolling transport had to be filled with silence.
+    underruns: AtomicUsize,
+    /// Cycles in which the transport was not where the song continued.
+    relocations: AtomicUsize,
+}
+
+/// Plays its input as a JACK client, which is deactivated when the sink is dropped.
+pub struct JackSink {
+    client: Option<AsyncClient<Notifications, Player>>,
+    sample_rate: usize,
+    ring: RingBufferWriter,
+    shared: Arc<Shared>,
+    bytes: Vec<u8>,
+    /// Reduces oversampled input to the sample rate of the server
+    decimator: Option<Decimator>,
+    decimated: Vec<Stereo<f64>>,
+}
+
+impl JackSink {
+    /// Register a client of the given name at the running JACK server,
+    /// with its outputs connected to the first two physical playback ports.
+    pub fn connect(name: &str) -> io::Result<Self> {
+        let (client, status) =
+            Client::new(name, ClientOptions::NO_START_SERVER).map_err(jack_error)?;
+        if status.contains(ClientStatus::NAME_NOT_UNIQUE) {
+            info!("registered at JACK as {}", client.name());
+        }
+        let left = client
+            .register_port("out_left", AudioOut)
+            .map_err(jack_error)?;
+        let right = client
+            .register_port("out_right", AudioOut)
+            .map_err(jack_error)?;
+        let outputs = [
+            left.name().map_err(jack_error)?,
+            right.name().map_err(jack_error)?,
+        ];
+        let playback = client.ports(
+            None,
+            Some("audio"),
+            PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL,
+        );
+
+        let (reader, ring) = RingBuffer::new(RING_BYTES)
+            .map_err(jack_error)?
+            .into_reader_writer();
+        let shared = Arc::new(Shared::default());
+        let sample_rate = client.sample_rate();
+        let player = Player {
+            transport: client.transport(),
+            left,
+            right,
+            ring: reader,
+            bytes: vec![0; client.buffer_size() as usize * FRAME_BYTES],
+            shared: shared.clone(),
+            started: false,
+            next_frame: 0,
+        };
+        let notifications = Notifications {
+            shared: shared.clone(),
+        };
+        let client = client
+            .activate_async(notifications, player)
+            .map_err(jack_error)?;
+        // Ports can only be connected once the client is active
+        for (output, input) in outputs.iter().zip(playback.iter()) {
+            if let Err(err) = client.as_client().connect_ports_by_name(output, input) {
+                warn!("not connecting to {}: {}", input, err);
+            }
+        }
+        info!("waiting for the JACK transport to start");
+        Ok(Self {
+            client: Some(client),
+            sample_rate,
+            ring,
+            shared,
+            bytes: Vec::new(),
+            decimator: None,
+            decimated: Vec::new(),
+        })
+    }
+
+    /// Sample rate of the JACK server, at which the input is expected.
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Accept input at `factor` times the sample rate of the server,
+    /// filtering it down before playing it.
+    pub fn with_oversampling(mut self, factor: usize) -> Self {
+        self.decimator = if factor > 1 {
+            Some(Decimator::new(factor))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Queue samples for playing, waiting while too much is queued already.
+    /// Samples are dropped if the server shut the client down.
+    pub fn write(&mut self, samples: &[Stereo<f64>]) {
+        let samples = match self.decimator.as_mut() {
+            None => samples,
+            Some(decimator) => {
+                self.decimated.clear();
+                decimator.process(samples, &mut self.decimated);
+                &self.decimated
+            }
+        };
+        self.bytes.clear();
+        for sample in samples.iter() {
+            self.bytes
+                .extend_from_slice(&(sample.left as f32).to_ne_bytes());
+            self.bytes
+                .extend_from_slice(&(sample.right as f32).to_ne_bytes());
+        }
+        let mut pending = &self.bytes[..];
+        while !pending.is_empty() {
+            if self.shared.shutdown.load(Ordering::Acquire) {
+                return;
+            }
+            // Only whole samples are written, so that the reader never sees half of one
+            let space = self.ring.space() / FRAME_BYTES * FRAME_BYTES;
+            if space == 0 {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            let written = self.ring.write_buffer(&pending[..space.min(pending.len())]);
+            pending = &pending[written..];
+        }
+    }
+
+    /// Wait until everything queued was played, then deactivate the client.
+    /// Writing afterwards has no effect.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let client = match self.client.take() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        self.shared.finished.store(true, Ordering::Release);
+        while !self.shared.drained.load(Ordering::Acquire)
+            && !self.shared.shutdown.load(Ordering::Acquire)
+        {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        let underruns = self.shared.underruns.load(Ordering::Relaxed);
+        if underruns > 0 {
+            warn!("rendering was too slow for JACK in {} cycles", underruns);
+        }
+        let relocations = self.shared.relocations.load(Ordering::Relaxed);
+        if relocations > 0 {
+            warn!(
+                "the JACK transport was relocated {} times, which was not followed",
+                relocations
+            );
+        }
+        if self.shared.shutdown.load(Ordering::Acquire) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "JACK shut the client down",
+            ));
+        }
+        client.deactivate().map_err(jack_error)?;
+        Ok(())
+    }
+}
+
+impl Drop for JackSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            error!("Failed to complete playing to JACK: {}", err);
+        }
+    }
+}
+
+impl super::Node for JackSink {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        self.write(rio.input(0).samples());
+    }
+}
+
+/// The part of the sink running on the real-time thread of JACK.
+struct Player {
+    transport: Transport,
+    left: Port<AudioOut>,
+    right: Port<AudioOut>,
+    ring: RingBufferReader,
+    /// Space for reading one cycle from the ring buffer
+    bytes: Vec<u8>,
+    shared: Arc<Shared>,
+    /// Whether the transport was rolling before
+    started: bool,
+    /// Transport frame expected in the next cycle while rolling
+    next_frame: Frames,
+}
+
+impl ProcessHandler for Player {
+    fn process(&mut self, _: &Client, scope: &ProcessScope) -> Control {
+        let left = self.left.as_mut_slice(scope);
+        let right = self.right.as_mut_slice(scope);
+        let rolling = match self.transport.query() {
+            Ok(transport) if transport.state == TransportState::Rolling => {
+                let frame = transport.pos.frame();
+                if self.started && frame != self.next_frame {
+                    self.shared.relocations.fetch_add(1, Ordering::Relaxed);
+                }
+                self.started = true;
+                self.next_frame = frame.wrapping_add(scope.n_frames());
+                true
+            }
+            _ => false,
+        };
+
+        let mut played = 0;
+        if rolling {
+            let wanted = (left.len() * FRAME_BYTES).min(self.bytes.len());
+            let read = self.ring.read_buffer(&mut self.bytes[..wanted]);
+            for (index, frame) in self.bytes[..read].chunks_exact(FRAME_BYTES).enumerate() {
+                left[index] = f32::from_ne_bytes([frame[0], frame[1], frame[2], frame[3]]);
+                right[index] = f32::from_ne_bytes([frame[4], frame[5], frame[6], frame[7]]);
+            }
+            played = read / FRAME_BYTES;
+            if played < left.len() {
+                // The flag is checked first, so that nothing written before it is missed
+                if self.shared.finished.load(Ordering::Acquire) {
+                    if self.ring.space() == 0 {
+                        self.shared.drained.store(true, Ordering::Release);
+                    }
+                } else {
+                    self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        left[played..].iter_mut().for_each(|x| *x = 0.0);
+        right[played..].iter_mut().for_each(|x| *x = 0.0);
+        Control::Continue
+    }
+
+    fn buffer_size(&mut self, _: &Client, size: Frames) -> Control {
+        self.bytes.resize(size as usize * FRAME_BYTES, 0);
+        Control::Continue
+    }
+}
+
+/// Tells the sink when the server goes away.
+struct Notifications {
+    shared: Arc<Shared>,
+}
+
+impl NotificationHandler for Notifications {
+    fn shutdown(&mut self, _status: ClientStatus, _reason: &str) {
+        self.shared.shutdown.store(true, Ordering::Release);
+    }
+}
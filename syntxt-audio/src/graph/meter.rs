@@ -0,0 +1,69 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::meter::Meter;
+use crate::wave::{Frame, Stereo, MAX_CHANNELS};
+
+/// A node without outputs measuring the level of its input.
+/// The meter is shared, so that it can be read while and after rendering.
+pub struct MeterNode {
+    meter: Rc<RefCell<Meter>>,
+    /// Number of inputs, each a pair of channels of the layout of the meter
+    inputs: usize,
+}
+
+impl MeterNode {
+    pub fn new(meter: Rc<RefCell<Meter>>) -> Self {
+        Self::with_inputs(meter, 1)
+    }
+
+    /// Measure the channels of a layout with more than one pair of channels,
+    /// one pair on each input.
+    pub fn with_inputs(meter: Rc<RefCell<Meter>>, inputs: usize) -> Self {
+        Self { meter, inputs }
+    }
+}
+
+impl super::Node for MeterNode {
+    fn num_inputs(&self) -> usize {
+        self.inputs
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        if self.inputs == 1 {
+            self.meter.borrow_mut().feed(rio.input(0).samples());
+            return;
+        }
+        let inputs = (0..self.inputs)
+            .map(|index| rio.input(index))
+            .collect::<Vec<_>>();
+        let frames = (0..rio.length())
+            .map(|index| {
+                let mut pairs = [Stereo::mono(0.0); MAX_CHANNELS / 2];
+                for (pair, input) in pairs.iter_mut().zip(inputs.iter()) {
+                    *pair = input.samples()[index];
+                }
+                Frame::from_pairs(&pairs[..self.inputs])
+            })
+            .collect::<Vec<_>>();
+        self.meter.borrow_mut().feed_frames(&frames);
+    }
+}
@@ -0,0 +1,202 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::automation::{BuiltInValues, Expr};
+
+/// Settings of a single input of the mixer.
+#[derive(Debug)]
+pub struct MixerChannel {
+    /// Linear gain, evaluated for every sample.
+    pub gain: Expr,
+    /// Whether the channel is silenced.
+    pub mute: bool,
+    /// Whether the channel is soloed. As soon as one channel is soloed,
+    /// only soloed channels are heard.
+    pub solo: bool,
+    /// Whether the channel keeps playing when other channels are soloed, e.g. a return bus.
+    pub solo_safe: bool,
+    /// The output the channel is mixed into.
+    pub output: usize,
+}
+
+impl MixerChannel {
+    /// An unmuted channel with the given gain.
+    pub fn new(gain: Expr) -> Self {
+        Self {
+            gain,
+            mute: false,
+            solo: false,
+            solo_safe: false,
+            output: 0,
+        }
+    }
+
+    /// Whether the channel is heard, given whether any channel of the mixer is soloed.
+    pub fn audible(&self, soloing: bool) -> bool {
+        !self.mute && (!soloing || self.solo || self.solo_safe)
+    }
+}
+
+/// A node summing its inputs, one for each channel, and applying a master gain.
+/// Channels can be mixed into different outputs, e.g. the speaker pairs of a surround layout.
+pub struct Mixer {
+    sample_rate: f64,
+    channels: Vec<MixerChannel>,
+    /// Linear gain applied to the sum, evaluated for every sample.
+    master: Expr,
+    outputs: usize,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: f64, channels: Vec<MixerChannel>, master: Expr) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            master,
+            outputs: 1,
+        }
+    }
+
+    /// Provide the given number of outputs, which all channels must stay below.
+    pub fn with_outputs(mut self, outputs: usize) -> Self {
+        debug_assert!(self.channels.iter().all(|channel| channel.output < outputs));
+        self.outputs = outputs;
+        self
+    }
+}
+
+impl super::Node for Mixer {
+    fn num_inputs(&self) -> usize {
+        self.channels.len()
+    }
+    fn num_outputs(&self) -> usize {
+        self.outputs
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        let mut outs = (0..self.outputs)
+            .map(|index| rio.output(index))
+            .collect::<Vec<_>>();
+        for out in outs.iter_mut() {
+            out.fill_zero();
+        }
+        let builtins = |index: usize| BuiltInValues {
+            global_time_seconds: (rio.start() + index) as f64 / self.sample_rate,
+            ..BuiltInValues::default()
+        };
+
+        let soloing = self.channels.iter().any(|channel| channel.solo);
+        for (channel_index, channel) in self.channels.iter().enumerate() {
+            // Silenced channels are skipped entirely instead of being multiplied by zero
+            if !channel.audible(soloing) {
+                continue;
+            }
+            let in_ref = rio.input(channel_index);
+            for (index, (i, o)) in in_ref
+                .samples()
+                .iter()
+                .zip(outs[channel.output].samples_mut().iter_mut())
+                .enumerate()
+            {
+                let gain = channel.gain.eval(&builtins(index), &[]).unwrap_or(0.0);
+                *o += *i * gain;
+            }
+        }
+
+        for index in 0..rio.length() {
+            let gain = self.master.eval(&builtins(index), &[]).unwrap_or(0.0);
+            for out in outs.iter_mut() {
+                out.samples_mut()[index] *= gain;
+            }
+        }
+    }
+    fn describe_input(&self, index: usize) -> Option<String> {
+        let channel = self.channels.get(index)?;
+        let mut description = match channel.gain {
+            Expr::Const(gain) => format!("gain {:.2}", gain),
+            _ => "automated gain".to_string(),
+        };
+        if channel.mute {
+            description.push_str(", muted");
+        }
+        if channel.solo {
+            description.push_str(", solo");
+        }
+        if self.outputs > 1 {
+            description.push_str(&format!(" to output {}", channel.output));
+        }
+        Some(description)
+    }
+    /// Channels are muted with `mute.<index>` and soloed with `solo.<index>`,
+    /// where any value but zero switches them on.
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        let (flag, index) = match (name.strip_prefix("mute."), name.strip_prefix("solo.")) {
+            (Some(index), _) => (true, index),
+            (_, Some(index)) => (false, index),
+            _ => return false,
+        };
+        match index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| self.channels.get_mut(index))
+        {
+            Some(channel) if flag => channel.mute = value != 0.0,
+            Some(channel) => channel.solo = value != 0.0,
+            None => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mixer, MixerChannel};
+    use crate::automation::Expr;
+    use crate::graph::Node;
+
+    #[test]
+    fn mute_and_solo() {
+        let channel = |mute, solo, solo_safe| MixerChannel {
+            mute,
+            solo,
+            solo_safe,
+            ..MixerChannel::new(Expr::Const(1.0))
+        };
+        assert!(channel(false, false, false).audible(false));
+        assert!(!channel(true, false, false).audible(false));
+        // Soloing silences all other channels, unless they are solo safe
+        assert!(!channel(false, false, false).audible(true));
+        assert!(channel(false, true, false).audible(true));
+        assert!(channel(false, false, true).audible(true));
+        // Muting takes precedence
+        assert!(!channel(true, true, false).audible(true));
+    }
+
+    #[test]
+    fn live_mute_and_solo() {
+        let channels = vec![
+            MixerChannel::new(Expr::Const(1.0)),
+            MixerChannel::new(Expr::Const(1.0)),
+        ];
+        let mut mixer = Mixer::new(44100.0, channels, Expr::Const(1.0));
+        assert!(mixer.set_parameter("mute.1", 1.0));
+        assert!(mixer.set_parameter("solo.0", 1.0));
+        assert!(mixer.channels[1].mute && mixer.channels[0].solo);
+        assert!(mixer.set_parameter("mute.1", 0.0));
+        assert!(!mixer.channels[1].mute);
+        assert!(!mixer.set_parameter("mute.2", 1.0));
+        assert!(!mixer.set_parameter("gain", 1.0));
+    }
+}
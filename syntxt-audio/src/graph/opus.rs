@@ -0,0 +1,178 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing Opus files by piping the audio to `opusenc` from the opus-tools.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::dither::{Dither, Quantizer};
+use crate::filter::fir::Decimator;
+use crate::tags::Tags;
+use crate::wave::{Frame, Layout, Stereo};
+
+use log::error;
+
+/// Path of the `opusenc` binary.
+fn opusenc_binary() -> PathBuf {
+    match option_env!("NIX_OPUSENC_BIN") {
+        Some(bin) => Path::new(bin).join("opusenc"),
+        None => "opusenc".into(),
+    }
+}
+
+/// Encodes its input to an Opus file, which is completed when the sink is dropped.
+pub struct OpusSink {
+    encoder: Child,
+    /// Closed when finishing, which ends the encoder
+    audio_stream: Option<ChildStdin>,
+    buffer: Vec<u8>,
+    error: bool,
+    /// Reduces oversampled input to the sample rate of the output
+    decimator: Option<Decimator>,
+    decimated: Vec<Stereo<f64>>,
+    /// Rounds to the 16 bits sent to the encoder
+    quantizer: Quantizer,
+}
+
+impl OpusSink {
+    /// Start encoding frames with the channels of the layout to a file described by the tags,
+    /// with the given bitrate in kbit/s or the default of `opusenc`.
+    pub fn create(
+        path: &Path,
+        sample_rate: i32,
+        layout: Layout,
+        bitrate: Option<u32>,
+        dither: Dither,
+        tags: &Tags,
+    ) -> io::Result<Self> {
+        let bitrate_args = match bitrate {
+            Some(bitrate) => vec!["--bitrate".to_string(), bitrate.to_string()],
+            None => Vec::new(),
+        };
+        let tag_args = tags
+            .vorbis_comments()
+            .into_iter()
+            .flat_map(|comment| vec!["--comment".to_string(), comment]);
+        let mut encoder = Command::new(opusenc_binary())
+            .args(&["--quiet", "--raw", "--raw-bits", "16", "--raw-chan"])
+            .arg(layout.channels().to_string())
+            .args(&["--raw-endianness", "0", "--raw-rate"])
+            .arg(sample_rate.to_string())
+            .args(&bitrate_args)
+            .args(tag_args)
+            .arg("-")
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let audio_stream = encoder.stdin.take();
+        Ok(Self {
+            encoder,
+            audio_stream,
+            buffer: Vec::new(),
+            error: false,
+            decimator: None,
+            decimated: Vec::new(),
+            quantizer: Quantizer::new(16, dither),
+        })
+    }
+
+    /// Accept input at `factor` times the sample rate of the output,
+    /// filtering it down before writing it.
+    pub fn with_oversampling(mut self, factor: usize) -> Self {
+        self.decimator = if factor > 1 {
+            Some(Decimator::new(factor))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Write stereo samples directly, e.g. when they were rendered before.
+    /// Errors are logged once, further samples are dropped.
+    pub fn write(&mut self, samples: &[Stereo<f64>]) {
+        let samples = match self.decimator.as_mut() {
+            None => samples,
+            Some(decimator) => {
+                self.decimated.clear();
+                decimator.process(samples, &mut self.decimated);
+                &self.decimated
+            }
+        };
+        let frames = samples
+            .iter()
+            .map(|sample| Frame::from(*sample))
+            .collect::<Vec<_>>();
+        self.write_frames(&frames);
+    }
+
+    /// Write frames with the channels of the layout at the sample rate of the output.
+    pub fn write_frames(&mut self, frames: &[Frame]) {
+        let audio_stream = match self.audio_stream.as_mut() {
+            Some(stream) if !self.error => stream,
+            _ => return,
+        };
+        self.buffer.clear();
+        for frame in frames.iter() {
+            let frame = self.quantizer.quantize_frame(*frame);
+            for value in frame.values() {
+                let value = (value * 32768.0).clamp(-32768.0, 32767.0) as i16;
+                self.buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        if let Err(err) = audio_stream.write_all(&self.buffer) {
+            error!("Failed to write audio to opusenc: {}", err);
+            self.error = true;
+        }
+    }
+
+    /// Wait for the encoder to complete the file. Writing afterwards has no effect.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.audio_stream.take().is_none() {
+            return Ok(());
+        }
+        let status = self.encoder.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("opusenc failed: {}", status),
+            ))
+        }
+    }
+}
+
+impl Drop for OpusSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            error!("Failed to complete Opus file: {}", err);
+        }
+    }
+}
+
+impl super::Node for OpusSink {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        self.write(rio.input(0).samples());
+    }
+}
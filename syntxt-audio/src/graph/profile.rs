@@ -0,0 +1,217 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Measuring the time spent rendering each node, for finding out what makes rendering slow.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::time::Duration;
+
+use super::NodeId;
+
+/// The time spent rendering one node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeTime {
+    pub node: NodeId,
+    /// Type of the node, without module paths.
+    pub name: String,
+    /// The part of the song the node belongs to, e.g. a track, see `GraphBuilder::group`.
+    pub group: String,
+    pub time: Duration,
+    /// Number of buffers rendered.
+    pub renders: usize,
+}
+
+/// The time spent rendering each node of a graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// All nodes in the order they were added to the graph.
+    pub nodes: Vec<NodeTime>,
+}
+
+impl Profile {
+    /// The time spent in all nodes together.
+    pub fn total(&self) -> Duration {
+        self.nodes.iter().map(|node| node.time).sum()
+    }
+
+    /// The groups with the time spent in their nodes, the slowest first.
+    pub fn groups(&self) -> Vec<(&str, Duration)> {
+        let mut groups: Vec<(&str, Duration)> = Vec::new();
+        for node in self.nodes.iter() {
+            match groups.iter_mut().find(|(group, _)| *group == node.group) {
+                Some((_, time)) => *time += node.time,
+                None => groups.push((&node.group, node.time)),
+            }
+        }
+        groups.sort_by_key(|(_, time)| Reverse(*time));
+        groups
+    }
+
+    /// The profile as a JSON object, for processing it with other tools.
+    pub fn to_json(&self) -> String {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                format!(
+                    r#"{{"node":{},"name":{},"group":{},"seconds":{},"renders":{}}}"#,
+                    node.node.0,
+                    json_string(&node.name),
+                    json_string(&node.group),
+                    node.time.as_secs_f64(),
+                    node.renders
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            r#"{{"seconds":{},"nodes":[{}]}}"#,
+            self.total().as_secs_f64(),
+            nodes.join(",")
+        )
+    }
+}
+
+/// A table of the groups and the nodes in them, the slowest first,
+/// with their share of the total time.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        let share = |time: Duration| match total {
+            total if total > 0.0 => 100.0 * time.as_secs_f64() / total,
+            _ => 0.0,
+        };
+        writeln!(f, "  share       time  part")?;
+        for (group, time) in self.groups() {
+            writeln!(
+                f,
+                "{:5.1} % {:8.3} s  {}",
+                share(time),
+                time.as_secs_f64(),
+                group
+            )?;
+            let mut nodes = self
+                .nodes
+                .iter()
+                .filter(|node| node.group == group)
+                .collect::<Vec<_>>();
+            nodes.sort_by_key(|node| Reverse(node.time));
+            for node in nodes {
+                writeln!(
+                    f,
+                    "{:5.1} % {:8.3} s    {} {:?}",
+                    share(node.time),
+                    node.time.as_secs_f64(),
+                    node.name,
+                    node.node
+                )?;
+            }
+        }
+        write!(f, "100.0 % {:8.3} s  total", total)
+    }
+}
+
+/// A string literal in JSON.
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Collects the time spent in each node of a graph.
+pub(super) struct Profiler {
+    /// Time and number of renders by node
+    times: Vec<(Duration, usize)>,
+}
+
+impl Profiler {
+    pub fn new(nodes: usize) -> Self {
+        Self {
+            times: vec![(Duration::default(), 0); nodes],
+        }
+    }
+
+    pub fn record(&mut self, node: NodeId, time: Duration) {
+        let entry = &mut self.times[node.0];
+        entry.0 += time;
+        entry.1 += 1;
+    }
+
+    pub fn times(&self, node: NodeId) -> (Duration, usize) {
+        self.times[node.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeTime, Profile};
+    use crate::graph::NodeId;
+    use std::time::Duration;
+
+    fn profile() -> Profile {
+        let node = |id, name: &str, group: &str, millis| NodeTime {
+            node: NodeId(id),
+            name: name.into(),
+            group: group.into(),
+            time: Duration::from_millis(millis),
+            renders: 10,
+        };
+        Profile {
+            nodes: vec![
+                node(0, "InstrumentSource", "track 0 (Lead)", 500),
+                node(1, "EffectNode<Reverb>", "track 0 (Lead)", 250),
+                node(2, "InstrumentSource", "track 1", 1000),
+                node(3, "Mixer", "master", 250),
+            ],
+        }
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            profile().to_string(),
+            "  share       time  part
+ 50.0 %    1.000 s  track 1
+ 50.0 %    1.000 s    InstrumentSource NodeId(2)
+ 37.5 %    0.750 s  track 0 (Lead)
+ 25.0 %    0.500 s    InstrumentSource NodeId(0)
+ 12.5 %    0.250 s    EffectNode<Reverb> NodeId(1)
+ 12.5 %    0.250 s  master
+ 12.5 %    0.250 s    Mixer NodeId(3)
+100.0 %    2.000 s  total"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let mut profile = profile();
+        profile.nodes.truncate(1);
+        profile.nodes[0].name = "\"quoted\"\n".into();
+        assert_eq!(
+            profile.to_json(),
+            r#"{"seconds":0.5,"nodes":[{"node":0,"name":"\"quoted\"\u000a","group":"track 0 (Lead)","seconds":0.5,"renders":10}]}"#
+        );
+    }
+}
@@ -0,0 +1,89 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::wave::{PackedSamples, Stereo};
+
+/// A node without outputs collecting everything arriving at its input,
+/// e.g. for processing a whole song after rendering it.
+/// The samples are stored at the precision of the given buffer.
+pub struct Recorder {
+    samples: Rc<RefCell<PackedSamples>>,
+}
+
+impl Recorder {
+    pub fn new(samples: Rc<RefCell<PackedSamples>>) -> Self {
+        Self { samples }
+    }
+}
+
+impl super::Node for Recorder {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        self.samples
+            .borrow_mut()
+            .extend_from_slice(rio.input(0).samples());
+    }
+}
+
+/// A node playing back samples from the start, e.g. a recording, followed by silence.
+pub struct Playback {
+    samples: Rc<Vec<Stereo<f64>>>,
+    /// Sample of the graph at which the playback starts.
+    offset: i64,
+}
+
+impl Playback {
+    pub fn new(samples: Rc<Vec<Stereo<f64>>>) -> Self {
+        Self::starting_at(samples, 0)
+    }
+
+    /// Play the samples from a later sample of the graph on, with silence before.
+    /// A negative offset skips the beginning of the samples.
+    pub fn starting_at(samples: Rc<Vec<Stereo<f64>>>, offset: i64) -> Self {
+        Self { samples, offset }
+    }
+}
+
+impl super::Node for Playback {
+    fn num_inputs(&self) -> usize {
+        0
+    }
+    fn num_outputs(&self) -> usize {
+        1
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        let start = rio.start() as i64 - self.offset;
+        for (index, sample) in rio.output(0).iter_mut().enumerate() {
+            let position = start + index as i64;
+            *sample = if position < 0 {
+                Stereo::mono(0.0)
+            } else {
+                self.samples
+                    .get(position as usize)
+                    .copied()
+                    .unwrap_or_else(|| Stereo::mono(0.0))
+            };
+        }
+    }
+}
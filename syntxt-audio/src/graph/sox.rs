@@ -18,54 +18,170 @@
 
 use std::io;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{ChildStdin, Command, Stdio};
 
+use crate::dither::{Dither, Quantizer};
+use crate::filter::fir::Decimator;
+use crate::tags::Tags;
+use crate::wave::{Frame, Layout, Precision, SampleBuffer, Stereo};
+
 use log::error;
 pub enum SoxTarget<'a> {
     Play,
     File(&'a Path),
 }
 
+/// Paths of the `play` and `sox` binaries.
+fn sox_binaries() -> (PathBuf, PathBuf) {
+    // For properly recording the sox dependency on nix:
+    if let Some(sox_bin) = option_env!("NIX_SOX_BIN") {
+        log::debug!("using sox from nix store {}", sox_bin);
+        let play = Path::new(sox_bin).join("play");
+        let sox = Path::new(sox_bin).join("sox");
+        (play, sox)
+    } else {
+        ("play".into(), "sox".into())
+    }
+}
+
+/// Load an audio file in any format supported by sox, converted to stereo at the given sample rate.
+pub fn load_sample(path: &Path, sample_rate: i32) -> io::Result<SampleBuffer> {
+    let (_, sox) = sox_binaries();
+    let output = Command::new(&sox)
+        .arg(path)
+        .args(&["--channels", "2", "--rate"])
+        .arg(format!("{}", sample_rate))
+        .args(&["--type", "f64", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("sox failed to load {}: {}", path.display(), output.status),
+        ));
+    }
+    Ok(SampleBuffer::from_bytes(sample_rate as f64, &output.stdout))
+}
+
+/// Format of the samples sent to sox and written by it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoxFormat {
+    /// Single precision halves the data passing through the pipe.
+    pub precision: Precision,
+    /// Bits per sample of the output, which is then rounded here instead of by sox.
+    /// Only files support choosing it, the speakers get the rounded samples regardless.
+    pub bits: Option<u32>,
+    /// How samples are rounded to the bits of the output.
+    pub dither: Dither,
+    /// Compression of the output file, passed to sox as `-C`, e.g. the quality of
+    /// Ogg Vorbis between -1 and 10.
+    pub compression: Option<f64>,
+    /// The channels of the samples, which sox mixes down if the speakers have fewer.
+    pub layout: Layout,
+}
+
+impl Default for SoxFormat {
+    fn default() -> Self {
+        Self {
+            precision: Precision::Double,
+            bits: None,
+            dither: Dither::Triangular,
+            compression: None,
+            layout: Layout::Stereo,
+        }
+    }
+}
+
+/// Options adding the tags as comments of the output, which sox writes as ID3 tags into MP3 files
+/// and as Vorbis comments into Ogg Vorbis and FLAC files.
+fn tag_args(outfile: &Path, tags: &Tags) -> Vec<String> {
+    let mp3 = outfile
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("mp3"));
+    let comments = if mp3 {
+        tags.id3_comments()
+    } else {
+        tags.vorbis_comments()
+    };
+    comments
+        .into_iter()
+        .flat_map(|comment| vec!["--add-comment".to_string(), comment.replace('\n', " ")])
+        .collect()
+}
+
 pub struct SoxSink {
     audio_stream: ChildStdin,
     buffer: Vec<u8>,
     error: bool,
+    /// Reduces oversampled input to the sample rate of the output
+    decimator: Option<Decimator>,
+    decimated: Vec<Stereo<f64>>,
+    /// Format of the samples sent to sox
+    precision: Precision,
+    /// Rounds to the bits of the output
+    quantizer: Option<Quantizer>,
 }
 
 impl SoxSink {
     pub fn new(sample_rate: i32, target: SoxTarget) -> io::Result<Self> {
+        Self::with_format(sample_rate, target, SoxFormat::default())
+    }
+
+    pub fn with_format(sample_rate: i32, target: SoxTarget, format: SoxFormat) -> io::Result<Self> {
+        Self::with_tags(sample_rate, target, format, &Tags::default())
+    }
+
+    /// Like `with_format`, describing a file with tags if its format supports them.
+    /// Line breaks in the tags become spaces, since sox would split the tags at them.
+    pub fn with_tags(
+        sample_rate: i32,
+        target: SoxTarget,
+        format: SoxFormat,
+        tags: &Tags,
+    ) -> io::Result<Self> {
         let sample_rate_str = format!("{}", sample_rate);
+        let channels_str = format!("{}", format.layout.channels());
+        let sample_type = match format.precision {
+            Precision::Single => "f32",
+            Precision::Double => "f64",
+        };
+        // Samples that are already rounded must not be dithered again
+        let global_args: &[&str] = if format.bits.is_some() { &["-D"] } else { &[] };
+        let mut output_args = match format.bits {
+            Some(bits) => vec!["--bits".to_string(), bits.to_string()],
+            None => Vec::new(),
+        };
+        if let Some(compression) = format.compression {
+            output_args.extend(vec!["-C".to_string(), compression.to_string()]);
+        }
         let input_args = &[
             "-R", // make the output reproducible
             "--channels",
-            "2",
+            &channels_str,
             "--rate",
             &sample_rate_str,
             "--type",
-            "f64",
+            sample_type,
             "/dev/stdin",
         ];
 
-        // For properly recording the sox dependency on nix:
-        let (play, sox) = if let Some(sox_bin) = option_env!("NIX_SOX_BIN") {
-            log::debug!("using sox from nix store {}", sox_bin);
-            let play = Path::new(sox_bin).join("play");
-            let sox = Path::new(sox_bin).join("sox");
-            (play, sox)
-        } else {
-            ("play".into(), "sox".into())
-        };
+        let (play, sox) = sox_binaries();
 
         let mut player = match target {
             SoxTarget::Play => Command::new(&play)
+                .args(global_args)
                 .args(input_args)
                 .stdin(Stdio::piped())
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
                 .spawn()?,
             SoxTarget::File(outfile) => Command::new(&sox)
+                .args(global_args)
                 .args(input_args)
+                .args(&output_args)
+                .args(tag_args(outfile, tags))
                 .arg(outfile)
                 .stdin(Stdio::piped())
                 .spawn()?,
@@ -80,27 +196,63 @@ impl SoxSink {
             audio_stream,
             buffer: Vec::new(),
             error: false,
+            decimator: None,
+            decimated: Vec::new(),
+            precision: format.precision,
+            quantizer: format.bits.map(|bits| Quantizer::new(bits, format.dither)),
         })
     }
-}
 
-impl super::Node for SoxSink {
-    fn num_inputs(&self) -> usize {
-        1
+    /// Accept input at `factor` times the sample rate of the output,
+    /// filtering it down before writing it.
+    pub fn with_oversampling(mut self, factor: usize) -> Self {
+        self.decimator = if factor > 1 {
+            Some(Decimator::new(factor))
+        } else {
+            None
+        };
+        self
     }
-    fn num_outputs(&self) -> usize {
-        0
+
+    /// Write stereo samples directly, e.g. when they were rendered before.
+    /// Errors are logged once, further samples are dropped.
+    pub fn write(&mut self, samples: &[Stereo<f64>]) {
+        let samples = match self.decimator.as_mut() {
+            None => samples,
+            Some(decimator) => {
+                self.decimated.clear();
+                decimator.process(samples, &mut self.decimated);
+                &self.decimated
+            }
+        };
+        let frames = samples
+            .iter()
+            .map(|sample| Frame::from(*sample))
+            .collect::<Vec<_>>();
+        self.write_frames(&frames);
     }
-    fn render(&mut self, rio: &super::RenderIo) {
+
+    /// Write frames with the channels of the layout at the sample rate of the output.
+    pub fn write_frames(&mut self, frames: &[Frame]) {
         if self.error {
             return;
         }
 
-        let inp = rio.input(0);
-        if self.buffer.len() < inp.byte_len() {
-            self.buffer.resize(inp.byte_len(), 0);
+        self.buffer.clear();
+        for frame in frames.iter() {
+            let frame = match self.quantizer.as_mut() {
+                Some(quantizer) => quantizer.quantize_frame(*frame),
+                None => *frame,
+            };
+            for value in frame.values() {
+                match self.precision {
+                    Precision::Single => self
+                        .buffer
+                        .extend_from_slice(&(*value as f32).to_le_bytes()),
+                    Precision::Double => self.buffer.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
         }
-        inp.copy_bytes_to(&mut self.buffer);
 
         let status = self
             .audio_stream
@@ -112,3 +264,15 @@ impl super::Node for SoxSink {
         }
     }
 }
+
+impl super::Node for SoxSink {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        self.write(rio.input(0).samples());
+    }
+}
@@ -14,6 +14,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::automation::{BuiltInValues, Expr};
+
 pub struct Gain {
     gain: f64,
 }
@@ -45,6 +47,48 @@ impl super::Node for Gain {
             *o = *i * self.gain;
         }
     }
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        match name {
+            "gain" => self.gain = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// A node moving its input in the stereo field.
+pub struct Pan {
+    sample_rate: f64,
+    /// Position between -1 (left) and 1 (right), evaluated for every sample.
+    pan: Expr,
+}
+
+impl Pan {
+    pub fn new(sample_rate: f64, pan: Expr) -> Self {
+        Self { sample_rate, pan }
+    }
+}
+
+impl super::Node for Pan {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        1
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        let input = rio.input(0);
+        let mut output = rio.output(0);
+
+        for (index, (i, o)) in input.iter().zip(output.iter_mut()).enumerate() {
+            let builtins = BuiltInValues {
+                global_time_seconds: (rio.start() + index) as f64 / self.sample_rate,
+                ..BuiltInValues::default()
+            };
+            let pan = self.pan.eval(&builtins, &[]).unwrap_or(0.0);
+            *o = i.constant_power_pan(pan);
+        }
+    }
 }
 
 /// A node with an arbitrary but static number of inputs.
@@ -81,3 +125,41 @@ impl super::Node for Sum {
         }
     }
 }
+
+/// A node summing its inputs, each scaled by its own gain, e.g. the signals sent to a bus.
+pub struct Mix {
+    /// Linear gain for each input.
+    gains: Vec<f64>,
+}
+
+impl Mix {
+    pub fn new(gains: Vec<f64>) -> Self {
+        Self { gains }
+    }
+}
+
+impl super::Node for Mix {
+    fn num_inputs(&self) -> usize {
+        self.gains.len()
+    }
+    fn num_outputs(&self) -> usize {
+        1
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        let mut out = rio.output(0);
+        out.fill_zero();
+        let outsamples = out.samples_mut();
+
+        for (index, gain) in self.gains.iter().enumerate() {
+            let in_ref = rio.input(index);
+            for (i, o) in in_ref.samples().iter().zip(outsamples.iter_mut()) {
+                *o += *i * *gain;
+            }
+        }
+    }
+    fn describe_input(&self, index: usize) -> Option<String> {
+        self.gains
+            .get(index)
+            .map(|gain| format!("gain {:.2}", gain))
+    }
+}
@@ -0,0 +1,627 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing RIFF/WAVE files directly, without sox.
+//!
+//! Besides the samples and tags, files can be written as Broadcast WAV files with a `bext`
+//! chunk and cue points, which editors show as markers.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::dither::{Dither, Quantizer};
+use crate::filter::fir::Decimator;
+use crate::tags::Tags;
+use crate::wave::{Frame, Layout, Stereo};
+
+use log::error;
+
+/// Encoding of the samples in a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavFormat {
+    /// Signed integers of 16, 24 or 32 bits.
+    Pcm(u32),
+    /// Floating point numbers of 32 or 64 bits.
+    Float(u32),
+}
+
+impl WavFormat {
+    /// The format with the given bits per sample, as integers or floating point numbers.
+    /// Without bits, 32 bit floats are written, which keep everything above full scale.
+    pub fn new(bits: Option<u32>, float: bool) -> io::Result<Self> {
+        match (bits, float) {
+            (None, _) => Ok(WavFormat::Float(32)),
+            (Some(bits @ 32), true) | (Some(bits @ 64), true) => Ok(WavFormat::Float(bits)),
+            (Some(bits @ 16), false) | (Some(bits @ 24), false) | (Some(bits @ 32), false) => {
+                Ok(WavFormat::Pcm(bits))
+            }
+            (Some(bits), _) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "WAV files hold integers of 16, 24 or 32 bits or floats of 32 or 64 bits, not {} bit {}",
+                    bits,
+                    if float { "floats" } else { "integers" }
+                ),
+            )),
+        }
+    }
+
+    fn bits(self) -> u32 {
+        match self {
+            WavFormat::Pcm(bits) | WavFormat::Float(bits) => bits,
+        }
+    }
+}
+
+/// The GUID of the sample formats of `WAVE_FORMAT_EXTENSIBLE`, after the format tag.
+const SUBFORMAT_GUID: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+/// A named position in a WAV file, shown as a marker by editors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    /// Frame of the file at which the cue point lies.
+    pub position: u32,
+    pub label: String,
+}
+
+/// The `cue ` chunk with the positions of the cue points, followed by the `LIST` chunk
+/// with their labels, or nothing without cue points.
+fn cue_chunks(cues: &[Cue]) -> Vec<u8> {
+    if cues.is_empty() {
+        return Vec::new();
+    }
+    let mut chunks = Vec::new();
+    chunks.extend_from_slice(b"cue ");
+    chunks.extend_from_slice(&(4 + 24 * cues.len() as u32).to_le_bytes());
+    chunks.extend_from_slice(&(cues.len() as u32).to_le_bytes());
+    for (id, cue) in (1u32..).zip(cues.iter()) {
+        // Identifier, position in playing order, chunk, chunk and block start, sample offset
+        chunks.extend_from_slice(&id.to_le_bytes());
+        chunks.extend_from_slice(&cue.position.to_le_bytes());
+        chunks.extend_from_slice(b"data");
+        chunks.extend_from_slice(&[0; 8]);
+        chunks.extend_from_slice(&cue.position.to_le_bytes());
+    }
+    let mut labels = Vec::new();
+    for (id, cue) in (1u32..).zip(cues.iter()) {
+        // Zero-terminated strings, padded to an even length
+        let size = 4 + cue.label.len() + 1;
+        labels.extend_from_slice(b"labl");
+        labels.extend_from_slice(&(size as u32).to_le_bytes());
+        labels.extend_from_slice(&id.to_le_bytes());
+        labels.extend_from_slice(cue.label.as_bytes());
+        labels.resize(labels.len() + 1 + size % 2, 0);
+    }
+    chunks.extend_from_slice(b"LIST");
+    chunks.extend_from_slice(&(4 + labels.len() as u32).to_le_bytes());
+    chunks.extend_from_slice(b"adtl");
+    chunks.extend(labels);
+    chunks
+}
+
+/// Writes samples to a WAV file, whose sizes are filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    format: WavFormat,
+    channels: usize,
+    /// Number of frames written so far
+    frames: u64,
+    buffer: Vec<u8>,
+    /// Chunk with the tags, which follows the samples
+    info: Vec<u8>,
+    /// The `bext` chunk of Broadcast WAV files, following the tags
+    bext: Vec<u8>,
+    /// Chunks with the cue points, which come last
+    cues: Vec<u8>,
+    /// Length of the header up to the samples, including the `fact` chunk of float files
+    header_len: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// A writer for stereo samples.
+    pub fn new(out: W, sample_rate: u32, format: WavFormat) -> io::Result<Self> {
+        Self::with_layout(out, sample_rate, format, Layout::Stereo)
+    }
+
+    /// A writer for frames with the channels of the layout.
+    /// More than two channels are written in the extensible format, which names their speakers.
+    pub fn with_layout(
+        mut out: W,
+        sample_rate: u32,
+        format: WavFormat,
+        layout: Layout,
+    ) -> io::Result<Self> {
+        let bytes = format.bits() / 8;
+        let channels = layout.channels() as u32;
+        let tag = match format {
+            WavFormat::Pcm(_) => 1u16,
+            WavFormat::Float(_) => 3,
+        };
+        let extensible = layout != Layout::Stereo;
+        let fmt_len = match (extensible, format) {
+            (true, _) => 40u32,
+            (false, WavFormat::Pcm(_)) => 16,
+            (false, WavFormat::Float(_)) => 18,
+        };
+        let mut header = Vec::new();
+        header.extend_from_slice(b"RIFF");
+        // Sizes are only known in the end
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&fmt_len.to_le_bytes());
+        header.extend_from_slice(&(if extensible { 0xfffe } else { tag }).to_le_bytes());
+        header.extend_from_slice(&(channels as u16).to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * channels * bytes).to_le_bytes());
+        header.extend_from_slice(&((channels * bytes) as u16).to_le_bytes());
+        header.extend_from_slice(&(format.bits() as u16).to_le_bytes());
+        if extensible {
+            header.extend_from_slice(&22u16.to_le_bytes());
+            header.extend_from_slice(&(format.bits() as u16).to_le_bytes());
+            header.extend_from_slice(&layout.channel_mask().to_le_bytes());
+            header.extend_from_slice(&tag.to_le_bytes());
+            header.extend_from_slice(&SUBFORMAT_GUID);
+        } else if let WavFormat::Float(_) = format {
+            header.extend_from_slice(&0u16.to_le_bytes());
+        }
+        if let WavFormat::Float(_) = format {
+            header.extend_from_slice(b"fact");
+            header.extend_from_slice(&4u32.to_le_bytes());
+            header.extend_from_slice(&0u32.to_le_bytes());
+        }
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        out.write_all(&header)?;
+        Ok(Self {
+            out,
+            format,
+            channels: channels as usize,
+            frames: 0,
+            buffer: Vec::new(),
+            info: Vec::new(),
+            bext: Vec::new(),
+            cues: Vec::new(),
+            header_len: header.len() as u64,
+        })
+    }
+
+    /// Describe the file with tags, which are written by `finish`.
+    pub fn set_tags(&mut self, tags: &Tags) {
+        self.info = tags.riff_info();
+    }
+
+    /// Make the file a Broadcast WAV file, whose `bext` chunk describes it with the tags
+    /// and the position of its first sample in the song. It is written by `finish`.
+    pub fn set_broadcast(&mut self, tags: &Tags, time_reference: u64) {
+        self.bext = tags.bext_chunk(time_reference, SystemTime::now());
+    }
+
+    /// Mark positions in the file, which are written by `finish`.
+    pub fn set_cues(&mut self, cues: &[Cue]) {
+        self.cues = cue_chunks(cues);
+    }
+
+    /// Append stereo samples, which are clipped to full scale and rounded for integer formats.
+    pub fn write(&mut self, samples: &[Stereo<f64>]) -> io::Result<()> {
+        debug_assert_eq!(self.channels, 2);
+        self.append(
+            samples.len(),
+            samples.iter().flat_map(|sample| {
+                std::iter::once(sample.left).chain(std::iter::once(sample.right))
+            }),
+        )
+    }
+
+    /// Append frames with the channels of the layout, like `write`.
+    pub fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        debug_assert!(frames.iter().all(|frame| frame.channels() == self.channels));
+        self.append(
+            frames.len(),
+            frames
+                .iter()
+                .flat_map(|frame| frame.values().iter().copied()),
+        )
+    }
+
+    /// Append the interleaved values of a number of frames.
+    fn append(&mut self, frames: usize, values: impl Iterator<Item = f64>) -> io::Result<()> {
+        self.buffer.clear();
+        for value in values {
+            match self.format {
+                WavFormat::Pcm(bits) => {
+                    let scale = 2.0f64.powi(bits as i32 - 1);
+                    let value = (value * scale).round().max(-scale).min(scale - 1.0) as i32;
+                    let bytes = value.to_le_bytes();
+                    self.buffer.extend_from_slice(&bytes[..bits as usize / 8]);
+                }
+                WavFormat::Float(32) => {
+                    self.buffer.extend_from_slice(&(value as f32).to_le_bytes())
+                }
+                WavFormat::Float(_) => self.buffer.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+        self.frames += frames as u64;
+        self.out.write_all(&self.buffer)
+    }
+
+    /// Append the tags, the `bext` chunk and the cue points and fill in the sizes of the file,
+    /// returning the output.
+    /// Files of more than 4 GB are written, but their sizes do not fit into the header.
+    pub fn finish(mut self) -> io::Result<W> {
+        let trailer = [
+            self.info.as_slice(),
+            self.bext.as_slice(),
+            self.cues.as_slice(),
+        ]
+        .concat();
+        self.out.write_all(&trailer)?;
+        let data_len = self.frames * self.channels as u64 * self.format.bits() as u64 / 8;
+        let header_len = self.header_len;
+        let size = |len: u64| (len.min(u32::MAX as u64) as u32).to_le_bytes();
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&size(header_len - 8 + data_len + trailer.len() as u64))?;
+        if let WavFormat::Float(_) = self.format {
+            // The `fact` chunk directly precedes the `data` chunk
+            self.out.seek(SeekFrom::Start(header_len - 12))?;
+            self.out.write_all(&size(self.frames))?;
+        }
+        self.out.seek(SeekFrom::Start(header_len - 4))?;
+        self.out.write_all(&size(data_len))?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes its input to a WAV file, which is completed when the sink is dropped.
+pub struct WavSink {
+    writer: Option<WavWriter<BufWriter<File>>>,
+    /// The first error while writing, reported when the file is completed
+    error: Option<io::Error>,
+    /// Reduces oversampled input to the sample rate of the output
+    decimator: Option<Decimator>,
+    decimated: Vec<Stereo<f64>>,
+    /// Rounds to the bits of integer formats
+    quantizer: Option<Quantizer>,
+}
+
+impl WavSink {
+    /// Create a file for frames with the channels of the layout.
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        format: WavFormat,
+        layout: Layout,
+        dither: Dither,
+    ) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            writer: Some(WavWriter::with_layout(file, sample_rate, format, layout)?),
+            error: None,
+            decimator: None,
+            decimated: Vec::new(),
+            quantizer: match format {
+                WavFormat::Pcm(bits) => Some(Quantizer::new(bits, dither)),
+                WavFormat::Float(_) => None,
+            },
+        })
+    }
+
+    /// Accept input at `factor` times the sample rate of the output,
+    /// filtering it down before writing it.
+    pub fn with_oversampling(mut self, factor: usize) -> Self {
+        self.decimator = if factor > 1 {
+            Some(Decimator::new(factor))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Describe the file with tags.
+    pub fn with_tags(mut self, tags: &Tags) -> Self {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.set_tags(tags);
+        }
+        self
+    }
+
+    /// Make the file a Broadcast WAV file, see `WavWriter::set_broadcast`.
+    pub fn set_broadcast(&mut self, tags: &Tags, time_reference: u64) {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.set_broadcast(tags, time_reference);
+        }
+    }
+
+    /// Mark positions in the file, given in frames at the sample rate of the file.
+    pub fn set_cues(&mut self, cues: &[Cue]) {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.set_cues(cues);
+        }
+    }
+
+    /// Write stereo samples directly, e.g. when they were rendered before.
+    /// After an error, further samples are dropped and `finish` returns the error.
+    pub fn write(&mut self, samples: &[Stereo<f64>]) {
+        let samples = match self.decimator.as_mut() {
+            None => samples,
+            Some(decimator) => {
+                self.decimated.clear();
+                decimator.process(samples, &mut self.decimated);
+                &self.decimated
+            }
+        };
+        let frames = samples
+            .iter()
+            .map(|sample| Frame::from(*sample))
+            .collect::<Vec<_>>();
+        self.write_frames(&frames);
+    }
+
+    /// Write frames with the channels of the layout at the sample rate of the file.
+    pub fn write_frames(&mut self, frames: &[Frame]) {
+        let writer = match self.writer.as_mut() {
+            Some(writer) if self.error.is_none() => writer,
+            _ => return,
+        };
+        let status = match self.quantizer.as_mut() {
+            Some(quantizer) => writer.write_frames(
+                &frames
+                    .iter()
+                    .map(|frame| quantizer.quantize_frame(*frame))
+                    .collect::<Vec<_>>(),
+            ),
+            None => writer.write_frames(frames),
+        };
+        if let Err(err) = status {
+            self.error = Some(err);
+        }
+    }
+
+    /// Complete the file, or return the error that stopped writing it.
+    /// Writing afterwards has no effect.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let writer = self.writer.take();
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        match writer {
+            Some(writer) => writer.finish().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            error!("Failed to complete WAV file: {}", err);
+        }
+    }
+}
+
+impl super::Node for WavSink {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+    fn num_outputs(&self) -> usize {
+        0
+    }
+    fn render(&mut self, rio: &super::RenderIo) {
+        self.write(rio.input(0).samples());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Cue, WavFormat, WavWriter};
+    use crate::tags::Tags;
+    use crate::wave::{Frame, Layout, Stereo};
+
+    fn write(format: WavFormat, samples: &[Stereo<f64>]) -> Vec<u8> {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 48000, format).unwrap();
+        writer.write(samples).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut le = [0; 4];
+        le.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(le)
+    }
+
+    #[test]
+    fn pcm() {
+        let samples = [Stereo::new(0.5, -1.0), Stereo::new(2.0, -0.25)];
+        let bytes = write(WavFormat::Pcm(16), &samples);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        // Format tag, channels, sample rate, bytes per second, block size and bits
+        assert_eq!(&bytes[20..24], &[1, 0, 2, 0]);
+        assert_eq!(u32_at(&bytes, 24), 48000);
+        assert_eq!(u32_at(&bytes, 28), 48000 * 4);
+        assert_eq!(&bytes[32..36], &[4, 0, 16, 0]);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 8);
+        let values = bytes[44..]
+            .chunks(2)
+            .map(|le| i16::from_le_bytes([le[0], le[1]]))
+            .collect::<Vec<_>>();
+        // Clipped to full scale
+        assert_eq!(values, vec![16384, -32768, 32767, -8192]);
+
+        let bytes = write(WavFormat::Pcm(24), &samples);
+        assert_eq!(u32_at(&bytes, 40), 12);
+        assert_eq!(&bytes[44..50], &[0, 0, 0x40, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn float() {
+        let samples = [Stereo::new(0.1, -1.5)];
+        let bytes = write(WavFormat::Float(64), &samples);
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(u32_at(&bytes, 16), 18);
+        assert_eq!(&bytes[20..22], &[3, 0]);
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(u32_at(&bytes, 46), 1);
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(u32_at(&bytes, 54), 16);
+        assert_eq!(&bytes[58..66], &0.1f64.to_le_bytes());
+        assert_eq!(&bytes[66..74], &(-1.5f64).to_le_bytes());
+
+        let bytes = write(WavFormat::Float(32), &samples);
+        assert_eq!(u32_at(&bytes, 54), 8);
+        assert_eq!(&bytes[58..62], &0.1f32.to_le_bytes());
+    }
+
+    #[test]
+    fn surround() {
+        let mut writer = WavWriter::with_layout(
+            Cursor::new(Vec::new()),
+            48000,
+            WavFormat::Float(32),
+            Layout::Surround51,
+        )
+        .unwrap();
+        let pairs = [
+            Stereo::new(0.5, -0.5),
+            Stereo::mono(0.25),
+            Stereo::mono(1.0),
+        ];
+        writer.write_frames(&[Frame::from_pairs(&pairs)]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        // The extensible format with six channels of front, center, LFE and surround speakers
+        assert_eq!(u32_at(&bytes, 16), 40);
+        assert_eq!(&bytes[20..24], &[0xfe, 0xff, 6, 0]);
+        assert_eq!(u32_at(&bytes, 28), 48000 * 24);
+        assert_eq!(&bytes[32..34], &[24, 0]);
+        assert_eq!(u32_at(&bytes, 40), 0x3f);
+        assert_eq!(&bytes[44..46], &[3, 0]);
+        assert_eq!(&bytes[60..64], b"fact");
+        assert_eq!(u32_at(&bytes, 68), 1);
+        assert_eq!(&bytes[72..76], b"data");
+        assert_eq!(u32_at(&bytes, 76), 24);
+        let values = bytes[80..]
+            .chunks(4)
+            .map(|le| f32::from_le_bytes([le[0], le[1], le[2], le[3]]))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0.5, -0.5, 0.25, 0.25, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn tags() {
+        let mut writer =
+            WavWriter::new(Cursor::new(Vec::new()), 48000, WavFormat::Pcm(16)).unwrap();
+        writer.set_tags(&Tags {
+            title: Some("Song".into()),
+            ..Tags::default()
+        });
+        writer.write(&[Stereo::mono(0.0)]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(u32_at(&bytes, 40), 4);
+        // The tags follow the samples
+        assert_eq!(&bytes[48..52], b"LIST");
+        assert_eq!(&bytes[56..64], b"INFOINAM");
+        assert_eq!(bytes.len(), 48 + 26);
+    }
+
+    #[test]
+    fn broadcast() {
+        let mut writer =
+            WavWriter::new(Cursor::new(Vec::new()), 48000, WavFormat::Pcm(16)).unwrap();
+        let tags = Tags {
+            title: Some("Song".into()),
+            ..Tags::default()
+        };
+        writer.set_tags(&tags);
+        writer.set_broadcast(&tags, 96000);
+        writer.set_cues(&[
+            Cue {
+                position: 0,
+                label: "Intro".into(),
+            },
+            Cue {
+                position: 24000,
+                label: "Verse".into(),
+            },
+        ]);
+        writer.write(&[Stereo::mono(0.0)]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        // The tags and then the other chunks follow the samples
+        assert_eq!(&bytes[48..52], b"LIST");
+        let bext = 48 + 26;
+        assert_eq!(&bytes[bext..bext + 4], b"bext");
+        assert_eq!(u32_at(&bytes, bext + 4), 602);
+        assert_eq!(&bytes[bext + 8..bext + 13], b"Song\0");
+        assert_eq!(u32_at(&bytes, bext + 346), 96000);
+
+        let cue = bext + 610;
+        assert_eq!(&bytes[cue..cue + 4], b"cue ");
+        assert_eq!(u32_at(&bytes, cue + 4), 52);
+        assert_eq!(u32_at(&bytes, cue + 8), 2);
+        assert_eq!(u32_at(&bytes, cue + 36), 2);
+        assert_eq!(u32_at(&bytes, cue + 40), 24000);
+        assert_eq!(&bytes[cue + 44..cue + 48], b"data");
+        assert_eq!(u32_at(&bytes, cue + 56), 24000);
+
+        let list = cue + 60;
+        let expected = [
+            b"LIST".as_ref(),
+            &[40, 0, 0, 0],
+            b"adtl",
+            b"labl",
+            &[10, 0, 0, 0],
+            &[1, 0, 0, 0],
+            b"Intro\0",
+            b"labl",
+            &[10, 0, 0, 0],
+            &[2, 0, 0, 0],
+            b"Verse\0",
+        ]
+        .concat();
+        assert_eq!(&bytes[list..], expected.as_slice());
+
+        // Nothing is added without cue points
+        let mut writer =
+            WavWriter::new(Cursor::new(Vec::new()), 48000, WavFormat::Pcm(16)).unwrap();
+        writer.set_cues(&[]);
+        assert_eq!(writer.finish().unwrap().into_inner().len(), 44);
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(WavFormat::new(None, false).unwrap(), WavFormat::Float(32));
+        assert_eq!(WavFormat::new(Some(24), false).unwrap(), WavFormat::Pcm(24));
+        assert_eq!(
+            WavFormat::new(Some(64), true).unwrap(),
+            WavFormat::Float(64)
+        );
+        assert!(WavFormat::new(Some(8), false).is_err());
+        assert!(WavFormat::new(Some(16), true).is_err());
+    }
+}
@@ -17,7 +17,12 @@
 use crate::wave::Stereo;
 use syntxt_core::note::{Note, Velocity};
 
+pub mod drum_kit;
+pub mod modulation;
+pub mod plucked_string;
 pub mod polyphonic;
+pub mod sampler;
+pub mod velocity;
 pub mod wavinator;
 
 /// Interface of an interactive instrument.
@@ -43,11 +48,39 @@ pub trait Instrument {
         velocity: Velocity,
     ) -> Self::PlayHandle;
 
+    /// Play a note like `play_note`, but detuned by the given number of cents.
+    /// Instruments without pitch play the note unchanged.
+    fn play_detuned_note(
+        &mut self,
+        sample_delay: usize,
+        note: Note,
+        velocity: Velocity,
+        _cents: f64,
+    ) -> Self::PlayHandle {
+        self.play_note(sample_delay, note, velocity)
+    }
+
     /// Release a note that was previously played using `play_note`.
     /// If a note has already been released, this has no effect.
     /// If a note has only been marked for release, the shorter release time is used.
     fn release_note(&mut self, sample_delay: usize, handle: Self::PlayHandle);
 
+    /// Bend the pitch of all notes, starting `sample_delay` samples into the next
+    /// `fill_buffer` call. The amount is between -1 and 1, in multiples of the bend range
+    /// of the instrument. Instruments without pitch ignore it.
+    fn pitch_bend(&mut self, _sample_delay: usize, _amount: f64) {}
+
+    /// Change a parameter of the instrument by name, taking effect with the next
+    /// `fill_buffer` call. Returns whether the instrument has a parameter of that name.
+    fn set_parameter(&mut self, _name: &str, _value: f64) -> bool {
+        false
+    }
+
+    /// Continue at the given sample of the song instead of where the previous `fill_buffer`
+    /// call ended, e.g. when rendering starts in the middle of a song.
+    /// This only moves the time seen by expressions, notes keep playing.
+    fn seek(&mut self, _sample: usize) {}
+
     /// Add the waveforms generated by the currently playing notes onto the buffer.
     fn fill_buffer(&mut self, output: &mut [Stereo<f64>]);
 }
@@ -0,0 +1,270 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Drum kit playing a separate sound for every note, e.g. `kick` or `snare` (see `Note::drum`).
+//!
+//! Drum hits are one-shots, they always sound until the end regardless of the note duration.
+
+use std::sync::Arc;
+
+use crate::automation::{BuiltInValues, Expr};
+use crate::oscillator::{Noise, NoiseColor};
+use crate::wave::*;
+use syntxt_core::note::*;
+
+use super::polyphonic::*;
+
+pub type DrumKit = Poly<Hit>;
+
+/// Parameters of the drum kit.
+#[derive(Debug)]
+pub struct Params {
+    /// Output gain of the drum kit
+    pub gain: Expr,
+    /// The sounds of the kit. Notes without a pad are silent.
+    pub pads: Vec<Pad>,
+}
+
+impl Params {
+    /// A basic kit synthesized from sine sweeps and noise.
+    pub fn synthesized() -> Self {
+        let pad = |name: &str, sound: Sound| Pad::new(Note::drum(name).unwrap(), sound);
+        let tone = |frequency: f64, sweep: f64, decay: f64| Sound::Tone {
+            frequency,
+            sweep,
+            decay,
+        };
+        Self {
+            gain: Expr::Const(1.0),
+            pads: vec![
+                pad("kick", tone(50.0, 2.0, 0.15)),
+                pad("snare", Sound::Noise { decay: 0.12 }),
+                pad("clap", Sound::Noise { decay: 0.08 }),
+                Pad {
+                    volume: 0.5,
+                    ..pad("hat", Sound::Noise { decay: 0.03 })
+                },
+                Pad {
+                    volume: 0.5,
+                    ..pad("openhat", Sound::Noise { decay: 0.3 })
+                },
+                pad("tom", tone(100.0, 1.0, 0.2)),
+                pad("midtom", tone(140.0, 1.0, 0.2)),
+                pad("hitom", tone(180.0, 1.0, 0.2)),
+            ],
+        }
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self::synthesized()
+    }
+}
+
+/// The sound played for a note.
+#[derive(Debug, Clone)]
+pub struct Pad {
+    pub note: Note,
+    pub sound: Sound,
+    /// Linear gain of the pad.
+    pub volume: f64,
+    /// Position in the stereo field between -1 (left) and 1 (right).
+    pub pan: f64,
+    /// Transposition of the sound in semitones. Has no effect on noise.
+    pub pitch: f64,
+}
+
+impl Pad {
+    pub fn new(note: Note, sound: Sound) -> Self {
+        Self {
+            note,
+            sound,
+            volume: 1.0,
+            pan: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Sound {
+    /// A recording played once at its original speed.
+    Sample(Arc<SampleBuffer>),
+    /// A sine decaying within `decay` seconds, whose pitch falls from `sweep` octaves above
+    /// `frequency`, e.g. for kicks and toms.
+    Tone {
+        frequency: f64,
+        sweep: f64,
+        decay: f64,
+    },
+    /// White noise decaying within `decay` seconds, e.g. for snares and hats.
+    Noise { decay: f64 },
+}
+
+/// Gain below which a decaying sound has ended.
+const SILENCE: f64 = 1e-4;
+
+/// State of a sounding drum hit.
+pub struct Hit {
+    /// The pad being played, `None` if the note has no pad.
+    pad: Option<Pad>,
+    /// Samples since the start of the hit.
+    time: usize,
+    /// Position in a sample, or phase of a tone.
+    position: f64,
+    noise: Noise,
+    velocity_gain: f64,
+}
+
+impl NoteSampler for Hit {
+    type Params = Params;
+
+    fn new(note: Note, velocity: Velocity, _sample_rate: f64, params: &Self::Params) -> Self {
+        Self {
+            pad: params.pads.iter().find(|pad| pad.note == note).cloned(),
+            time: 0,
+            position: 0.0,
+            noise: Noise::new(NoiseColor::White, 0),
+            velocity_gain: velocity.as_f64(),
+        }
+    }
+
+    fn sample(
+        &mut self,
+        global_sample_count: usize,
+        sample_rate: f64,
+        params: &Self::Params,
+    ) -> Option<Stereo<f64>> {
+        let pad = self.pad.as_ref()?;
+        let transpose = syntxt_core::util::from_semitones(pad.pitch);
+        let seconds = self.time as f64 / sample_rate;
+        let value = match &pad.sound {
+            Sound::Sample(sample) => {
+                if self.position >= sample.samples.len() as f64 {
+                    return None;
+                }
+                let value = sample.interpolate(self.position);
+                self.position += transpose * sample.sample_rate / sample_rate;
+                value
+            }
+            Sound::Tone {
+                frequency,
+                sweep,
+                decay,
+            } => {
+                let envelope = (-seconds / decay.max(1e-3)).exp();
+                if envelope < SILENCE {
+                    return None;
+                }
+                let frequency = frequency * transpose * (sweep * envelope).exp2();
+                let value = (self.position * 2.0 * std::f64::consts::PI).sin() * envelope;
+                self.position = (self.position + frequency / sample_rate).fract();
+                Stereo::mono(value)
+            }
+            Sound::Noise { decay } => {
+                let envelope = (-seconds / decay.max(1e-3)).exp();
+                if envelope < SILENCE {
+                    return None;
+                }
+                Stereo::mono(self.noise.next_sample() * envelope)
+            }
+        };
+        let builtins = BuiltInValues {
+            global_time_seconds: global_sample_count as f64 / sample_rate,
+            ..BuiltInValues::default()
+        };
+        let gain =
+            params.gain.eval(&builtins, &[]).unwrap_or(0.0) * pad.volume * self.velocity_gain;
+        self.time += 1;
+        Some((value * gain).constant_power_pan(pad.pan))
+    }
+
+    fn release(&mut self) {
+        // Drums are one-shots, they keep playing until they have decayed
+    }
+
+    fn set_parameter(params: &mut Params, name: &str, value: f64) -> bool {
+        match name {
+            "gain" => params.gain = Expr::Const(value),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{DrumKit, Pad, Params, Sound};
+    use crate::automation::Expr;
+    use crate::instrument::Instrument;
+    use crate::wave::{SampleBuffer, Stereo};
+    use syntxt_core::note::{Note, Velocity};
+
+    fn hit(kit: &mut DrumKit, note: Note, length: usize) -> Vec<Stereo<f64>> {
+        let handle = kit.play_note(0, note, Velocity::from_f64(1.0));
+        // Releasing right away does not cut the sound short
+        kit.release_note(1, handle);
+        let mut output = vec![Stereo::mono(0.0); length];
+        kit.fill_buffer(&mut output);
+        output
+    }
+
+    #[test]
+    fn synthesized_pads() {
+        let kit = || DrumKit::with_params(44100.0, Params::synthesized());
+        let kick = hit(&mut kit(), Note::drum("kick").unwrap(), 2 * 44100);
+        assert!(kick[1..100].iter().all(|s| s.left != 0.0));
+        // Decayed after a second and a half
+        assert!(kick[66150..].iter().all(|s| s.left == 0.0));
+
+        let snare = hit(&mut kit(), Note::drum("snare").unwrap(), 100);
+        assert!(snare.iter().any(|s| s.left > 0.0) && snare.iter().any(|s| s.left < 0.0));
+
+        let unmapped = hit(&mut kit(), Note::drum("cowbell").unwrap(), 100);
+        assert!(unmapped.iter().all(|s| *s == Stereo::mono(0.0)));
+    }
+
+    #[test]
+    fn sample_pads() {
+        let sample = Arc::new(SampleBuffer {
+            sample_rate: 100.0,
+            samples: (1..=4).map(|i| Stereo::mono(i as f64)).collect(),
+        });
+        let note = Note::from_midi(36);
+        let mut kit = DrumKit::with_params(
+            100.0,
+            Params {
+                gain: Expr::Const(1.0),
+                pads: vec![Pad {
+                    pitch: 12.0,
+                    pan: -1.0,
+                    ..Pad::new(note, Sound::Sample(sample))
+                }],
+            },
+        );
+        let output = hit(&mut kit, note, 3)
+            .iter()
+            .map(|s| (s.left / 2.0f64.sqrt(), s.right))
+            .collect::<Vec<_>>();
+        assert_eq!(output[0].0, 1.0);
+        assert!((output[1].0 - 3.0).abs() < 1e-9);
+        assert_eq!(output[2].0, 0.0);
+        assert!(output.iter().all(|(_, right)| right.abs() < 1e-9));
+    }
+}
@@ -0,0 +1,227 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Routing modulation sources of a note to the parameters of its sound.
+//!
+//! Each route connects one source to one destination with a depth. Routes to the same destination
+//! are combined, e.g. an LFO and velocity can both change the cutoff of the filter.
+
+use crate::envelope::{Envelope, EvalEnvelope};
+use crate::lfo::{self, Lfo};
+use syntxt_core::note::{Note, Velocity};
+
+use super::velocity::VelocityCurve;
+
+/// Where the modulation comes from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// An LFO restarting with every note, between -1 and 1.
+    Lfo(Lfo),
+    /// An envelope triggered and released along with the note, between 0 and 1.
+    Envelope(Envelope),
+    /// The velocity of the note mapped by a curve, between 0 and 1.
+    Velocity(VelocityCurve),
+    /// The distance of the note from middle C in octaves, for key tracking.
+    Note,
+    /// A random value between -1 and 1, chosen anew for every note.
+    Random,
+}
+
+/// Which parameter of a note is modulated, and how the depth of a route is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    /// Pitch offset in semitones per unit of the source.
+    Pitch,
+    /// Filter frequency offset in octaves per unit of the source.
+    Cutoff,
+    /// Attenuation of the volume: with a depth of 1, the volume is proportional to the source,
+    /// with a depth of 0 it is unaffected.
+    Amp,
+    /// Offset of the stereo position, where -1 is fully left and 1 fully right.
+    Pan,
+}
+
+/// A connection from a source to a destination.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub source: Source,
+    pub destination: Destination,
+    pub depth: f64,
+}
+
+impl Route {
+    pub fn new(source: Source, destination: Destination, depth: f64) -> Self {
+        Self {
+            source,
+            destination,
+            depth,
+        }
+    }
+}
+
+/// The combined modulation of all destinations at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modulation {
+    /// Offset in semitones
+    pub pitch: f64,
+    /// Offset in octaves
+    pub cutoff: f64,
+    /// Factor applied to the volume
+    pub amp: f64,
+    /// Offset of the stereo position
+    pub pan: f64,
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Self {
+            pitch: 0.0,
+            cutoff: 0.0,
+            amp: 1.0,
+            pan: 0.0,
+        }
+    }
+}
+
+/// State of a source for a single note.
+enum EvalSource {
+    Lfo(Lfo),
+    Envelope(EvalEnvelope),
+    /// Sources that stay constant during a note
+    Constant(f64),
+    /// Seeded with the note, the value is chosen when the note starts sounding.
+    Random(i64),
+}
+
+/// The routes of an instrument, evaluated for a single note.
+pub struct EvalMatrix {
+    routes: Vec<(EvalSource, Destination, f64)>,
+}
+
+impl EvalMatrix {
+    pub fn new(routes: &[Route], note: Note, velocity: Velocity, sample_rate: f64) -> Self {
+        let routes = routes
+            .iter()
+            .map(|route| {
+                let source = match &route.source {
+                    Source::Lfo(lfo) => EvalSource::Lfo(lfo.clone()),
+                    Source::Envelope(envelope) => {
+                        EvalSource::Envelope(envelope.instantiate(sample_rate))
+                    }
+                    Source::Velocity(curve) => EvalSource::Constant(curve.apply(velocity)),
+                    Source::Note => EvalSource::Constant((note.to_midi() as f64 - 60.0) / 12.0),
+                    Source::Random => EvalSource::Random(note.to_midi() as i64),
+                };
+                (source, route.destination, route.depth)
+            })
+            .collect();
+        Self { routes }
+    }
+
+    /// Advance all sources by one sample and combine them.
+    ///
+    /// `global_sample_count` is the sample at which the note is evaluated,
+    /// `note_time_seconds` the time since the note started.
+    pub fn step(&mut self, global_sample_count: usize, note_time_seconds: f64) -> Modulation {
+        let mut modulation = Modulation::default();
+        for (source, destination, depth) in self.routes.iter_mut() {
+            let value = match source {
+                EvalSource::Lfo(lfo) => lfo.eval(note_time_seconds),
+                EvalSource::Envelope(envelope) => envelope.step(),
+                EvalSource::Constant(value) => *value,
+                EvalSource::Random(seed) => {
+                    // Notes starting at different times get different values, deterministically
+                    let value = lfo::random(*seed ^ ((global_sample_count as i64) << 8));
+                    *source = EvalSource::Constant(value);
+                    value
+                }
+            };
+            match destination {
+                Destination::Pitch => modulation.pitch += value * *depth,
+                Destination::Cutoff => modulation.cutoff += value * *depth,
+                Destination::Amp => modulation.amp *= (1.0 - *depth * (1.0 - value)).max(0.0),
+                Destination::Pan => modulation.pan += value * *depth,
+            }
+        }
+        modulation
+    }
+
+    /// Release all envelopes along with the note.
+    pub fn release(&mut self) {
+        for (source, _, _) in self.routes.iter_mut() {
+            if let EvalSource::Envelope(envelope) = source {
+                envelope.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Destination, EvalMatrix, Route, Source};
+    use crate::envelope::Envelope;
+    use crate::instrument::velocity::VelocityCurve;
+    use crate::lfo::{Lfo, Shape};
+    use syntxt_core::note::{Note, Velocity};
+
+    #[test]
+    fn combine_routes() {
+        let routes = vec![
+            Route::new(
+                Source::Velocity(VelocityCurve::LINEAR),
+                Destination::Amp,
+                1.0,
+            ),
+            Route::new(Source::Note, Destination::Cutoff, 1.0),
+            Route::new(Source::Note, Destination::Cutoff, 0.5),
+            Route::new(
+                Source::Lfo(Lfo::free(Shape::Square, 1.0)),
+                Destination::Pitch,
+                2.0,
+            ),
+            Route::new(
+                Source::Envelope(Envelope {
+                    attack: 0.0,
+                    release: 0.0,
+                    ..Envelope::default()
+                }),
+                Destination::Pan,
+                -0.5,
+            ),
+        ];
+        let mut matrix =
+            EvalMatrix::new(&routes, Note::from_midi(72), Velocity::from_f64(0.5), 4.0);
+        let modulation = matrix.step(0, 0.0);
+        assert_eq!(modulation.amp, 0.5);
+        assert_eq!(modulation.cutoff, 1.5);
+        assert_eq!(modulation.pitch, 2.0);
+        assert_eq!(modulation.pan, -0.5);
+        assert_eq!(matrix.step(1, 0.75).pitch, -2.0);
+        matrix.release();
+        assert_eq!(matrix.step(2, 1.0).pan, 0.0);
+    }
+
+    #[test]
+    fn random_per_note() {
+        let routes = vec![Route::new(Source::Random, Destination::Pitch, 1.0)];
+        let mut first = EvalMatrix::new(&routes, Note::from_midi(60), Velocity::MAX, 4.0);
+        let mut second = EvalMatrix::new(&routes, Note::from_midi(60), Velocity::MAX, 4.0);
+        let value = first.step(0, 0.0).pitch;
+        // Held for the whole note
+        assert_eq!(first.step(1, 0.25).pitch, value);
+        assert_ne!(second.step(100, 0.0).pitch, value);
+    }
+}
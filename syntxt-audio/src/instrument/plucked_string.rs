@@ -0,0 +1,281 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Plucked strings after Karplus and Strong: a burst of noise circulating in a delay line
+//! as long as one period of the note, losing a little of its energy and brightness every round.
+
+use crate::automation::{BuiltInValues, Expr};
+use crate::envelope::*;
+use crate::oscillator::{Noise, NoiseColor};
+use crate::tuning::*;
+use crate::wave::*;
+use syntxt_core::note::*;
+
+use super::polyphonic::*;
+use super::velocity::VelocityCurve;
+
+pub type PluckedString = Poly<Sampler>;
+
+/// Parameters of the string model.
+#[derive(Debug)]
+pub struct Params {
+    /// Output gain of the instrument
+    pub gain: Expr,
+    /// Time in seconds for a held note to fade by 60 dB
+    pub decay: f64,
+    /// How quickly the high frequencies die out compared to the low ones,
+    /// between 0 (all decay equally) and 1 (a mellow sound that quickly loses its brightness).
+    pub damping: f64,
+    /// Where the string is plucked, between 0 (at the bridge, bright)
+    /// and 0.5 (in the middle, hollow).
+    pub pluck_position: f64,
+    /// Time in seconds for a released note to fall silent, like a finger muting the string
+    pub release: f64,
+    /// How the velocity of a note affects its volume
+    pub velocity_gain: VelocityCurve,
+    /// How many notes can play at once
+    pub voices: Voices,
+    /// How many semitones a full pitch bend changes the pitch
+    pub bend_range: f64,
+    /// The frequencies of the notes
+    pub tuning: Tuning,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            gain: Expr::Const(1.0),
+            decay: 4.0,
+            damping: 0.5,
+            pluck_position: 0.1,
+            release: 0.1,
+            velocity_gain: VelocityCurve::LINEAR,
+            voices: Voices::default(),
+            bend_range: 2.0,
+            tuning: Tuning::default(),
+        }
+    }
+}
+
+/// State needed for a playing note.
+pub struct Sampler {
+    /// Frequency of the note, without the pitch bend
+    frequency: f64,
+    /// Factor applied to the frequency by the pitch bend
+    bend_factor: f64,
+    /// Seed of the excitation, so that repeated notes sound alike but chords do not
+    seed: u64,
+    /// The string, created with the first sample when the final frequency is known
+    line: Vec<f64>,
+    /// Position in the line where the next sample is written
+    position: usize,
+    /// The sample read from the line before, for the damping filter
+    previous: f64,
+    /// Input and output of the fractional delay before, for tuning between whole samples
+    allpass: (f64, f64),
+    /// Fades out the note once it is released
+    envelope: EvalEnvelope,
+    velocity_gain: f64,
+}
+
+impl Sampler {
+    /// Fill the delay line with noise filtered by the pluck position.
+    fn excite(&mut self, sample_rate: f64, params: &Params) {
+        // Leave room for bending down without reallocating
+        let lowest = self.frequency * syntxt_core::util::from_semitones(-params.bend_range.abs());
+        let length = (sample_rate / lowest).ceil() as usize + 2;
+        let period = (sample_rate / self.frequency).round().max(1.0) as usize;
+
+        let mut noise = Noise::new(NoiseColor::White, self.seed);
+        let burst = (0..period).map(|_| noise.next_sample()).collect::<Vec<_>>();
+        // Plucking at a fraction of the string cancels the harmonics with a node there,
+        // which is the same as a comb filter with a delay of that fraction of the period
+        let offset = (params.pluck_position.clamp(0.0, 0.5) * period as f64).round() as usize;
+        let excitation = (0..period).map(|index| {
+            if offset == 0 {
+                burst[index]
+            } else {
+                0.5 * (burst[index] - burst[(index + offset) % period])
+            }
+        });
+        self.line = excitation.cycle().take(length).collect();
+        // The oldest sample of the line is read first
+        self.position = 0;
+    }
+
+    /// Read from the line `delay` samples ago.
+    ///
+    /// The fraction of the delay is realized by an allpass filter, which unlike interpolation
+    /// delays all frequencies without dampening the high ones.
+    fn read(&mut self, delay: f64) -> f64 {
+        let len = self.line.len();
+        // Fractions close to zero make the allpass filter ring
+        let whole = (delay - 0.1).floor().clamp(1.0, (len - 1) as f64);
+        let fraction = (delay - whole).clamp(0.1, 1.1);
+        let coefficient = (1.0 - fraction) / (1.0 + fraction);
+        let input = self.line[(self.position + len - whole as usize) % len];
+        let (previous_input, previous_output) = self.allpass;
+        let output = coefficient * input + previous_input - coefficient * previous_output;
+        self.allpass = (input, output);
+        output
+    }
+}
+
+impl NoteSampler for Sampler {
+    type Params = Params;
+
+    fn voices(params: &Params) -> Voices {
+        params.voices
+    }
+
+    fn new(note: Note, velocity: Velocity, sample_rate: f64, params: &Self::Params) -> Self {
+        Self {
+            frequency: params.tuning.frequency(note),
+            bend_factor: 1.0,
+            seed: note.to_midi() as u64,
+            line: Vec::new(),
+            position: 0,
+            previous: 0.0,
+            allpass: (0.0, 0.0),
+            envelope: Envelope {
+                attack: 0.0,
+                release: params.release,
+                ..Envelope::default()
+            }
+            .instantiate(sample_rate),
+            velocity_gain: params.velocity_gain.apply(velocity),
+        }
+    }
+
+    fn sample(
+        &mut self,
+        global_sample_count: usize,
+        sample_rate: f64,
+        params: &Self::Params,
+    ) -> Option<Stereo<f64>> {
+        if self.envelope.faded() {
+            return None;
+        }
+        if self.line.is_empty() {
+            self.excite(sample_rate, params);
+        }
+        let builtins = BuiltInValues {
+            global_time_seconds: global_sample_count as f64 / sample_rate,
+            ..BuiltInValues::default()
+        };
+
+        let frequency = self.frequency * self.bend_factor;
+        // Averaging with the previous sample delays by half a sample for full damping
+        let damping = params.damping.clamp(0.0, 1.0);
+        let delay = sample_rate / frequency - damping / 2.0;
+        // Loss per round trip for the amplitude to fall to 1/1000 (-60 dB) after the decay time
+        let feedback = 10f64.powf(-3.0 / (params.decay.max(0.001) * frequency));
+
+        let current = self.read(delay);
+        let filtered = (1.0 - damping / 2.0) * current + damping / 2.0 * self.previous;
+        self.previous = current;
+        self.line[self.position] = filtered * feedback;
+        self.position = (self.position + 1) % self.line.len();
+
+        let gain = params.gain.eval(&builtins, &[]).unwrap_or(0.0)
+            * self.envelope.step()
+            * self.velocity_gain;
+        Some(Stereo::mono(current * gain))
+    }
+
+    fn release(&mut self) {
+        self.envelope.release()
+    }
+
+    fn set_parameter(params: &mut Params, name: &str, value: f64) -> bool {
+        match name {
+            "gain" => params.gain = Expr::Const(value),
+            "decay" => params.decay = value,
+            "damping" => params.damping = value,
+            "bend_range" => params.bend_range = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn bend(&mut self, amount: f64, params: &Self::Params) {
+        self.bend_factor = syntxt_core::util::from_semitones(amount * params.bend_range);
+    }
+
+    fn detune(&mut self, cents: f64, _params: &Self::Params) {
+        self.frequency *= syntxt_core::util::from_cents(cents);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Params, PluckedString};
+    use crate::instrument::Instrument;
+    use crate::wave::Stereo;
+    use syntxt_core::note::{Note, Velocity};
+
+    fn pluck(params: Params, seconds: f64) -> Vec<f64> {
+        let mut string = PluckedString::with_params(10000.0, params);
+        string.play_note(0, Note::from_midi(69), Velocity::MAX);
+        let mut output = vec![Stereo::mono(0.0); (seconds * 10000.0) as usize];
+        string.fill_buffer(&mut output);
+        output.iter().map(|s| s.left).collect()
+    }
+
+    fn power(samples: &[f64]) -> f64 {
+        samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn pitch() {
+        let output = pluck(Params::default(), 1.0);
+        // Correlation is highest after one period of a4, i.e. 10000 / 440 samples
+        let window = &output[2000..4000];
+        let correlation = |lag: usize| {
+            window
+                .iter()
+                .zip(&output[2000 + lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f64>()
+        };
+        let best = (15..35).max_by(|a, b| correlation(*a).partial_cmp(&correlation(*b)).unwrap());
+        assert_eq!(best, Some(23));
+    }
+
+    #[test]
+    fn decay() {
+        let params = || Params {
+            decay: 1.0,
+            damping: 0.0,
+            ..Params::default()
+        };
+        let output = pluck(params(), 1.1);
+        let start = power(&output[0..1000]);
+        let end = power(&output[10000..11000]);
+        // -60 dB in amplitude after one second
+        let db = 10.0 * (end / start).log10();
+        assert!((db + 60.0).abs() < 3.0, "{}", db);
+
+        let short = pluck(
+            Params {
+                decay: 0.25,
+                ..params()
+            },
+            1.1,
+        );
+        assert!(power(&short[10000..11000]) < end / 1000.0);
+    }
+}
@@ -17,6 +17,7 @@
 //! Prototype for a polyphonic instrument where each note can be played individually.
 
 use crate::wave::*;
+use syntxt_core::nonnan::F64N;
 use syntxt_core::note::*;
 
 pub struct Poly<Sampler: NoteSampler> {
@@ -32,11 +33,61 @@ pub struct Poly<Sampler: NoteSampler> {
     /// Monotoneously increasing id used for identifying playing notes.
     next_play_handle: usize,
     active_notes: Vec<NoteState<Sampler>>,
+    /// Handle and note of the note played most recently.
+    last_played: Option<(usize, Note)>,
+
+    /// Pitch bends that are yet to happen, with their delay in samples.
+    pending_bends: Vec<(usize, f64)>,
+    /// The amount of pitch bend the instrument moves towards.
+    target_bend: f64,
+    /// The current, smoothed amount of pitch bend.
+    bend: f64,
+}
+
+/// How many notes an instrument can play at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voices {
+    /// Maximum number of sounding notes, `None` for no limit.
+    /// With a limit of 1, the instrument plays like a monophonic synthesizer.
+    pub max: Option<usize>,
+    /// Which note has to make room when the limit is reached.
+    pub stealing: Stealing,
+}
+
+impl Default for Voices {
+    fn default() -> Self {
+        Self {
+            max: None,
+            stealing: Stealing::Oldest,
+        }
+    }
+}
+
+/// Strategy for choosing the note that is cut off for a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stealing {
+    /// The note that was played first.
+    Oldest,
+    /// The note that is currently the quietest.
+    Quietest,
+    /// A note playing the same pitch as the new one, or the oldest note if there is none.
+    SameNote,
 }
 
+/// Time in seconds over which stolen notes fade out, to avoid clicks.
+const STEAL_FADE_SECONDS: f64 = 0.005;
+
+/// Time in seconds the pitch bend takes to (mostly) reach a new value, to avoid clicks.
+const BEND_SMOOTHING_SECONDS: f64 = 0.01;
+
 pub trait NoteSampler {
     type Params: Default;
 
+    /// The voice limit configured in the parameters, unlimited by default.
+    fn voices(_params: &Self::Params) -> Voices {
+        Voices::default()
+    }
+
     /// Initialize the sampler for a single note.
     fn new(note: Note, velocity: Velocity, sample_rate: f64, params: &Self::Params) -> Self;
 
@@ -52,6 +103,27 @@ pub trait NoteSampler {
 
     /// Called before the sample when the note is first released.
     fn release(&mut self);
+
+    /// Called before every sample with the current pitch bend of the instrument,
+    /// between -1 and 1 in multiples of the bend range.
+    fn bend(&mut self, _amount: f64, _params: &Self::Params) {}
+
+    /// Called right after `new` if the note is played detuned by the given number of cents.
+    fn detune(&mut self, _cents: f64, _params: &Self::Params) {}
+
+    /// Called before the first sample with the note played before this one, e.g. for gliding
+    /// from its pitch. `legato` tells whether that note is still held when this one starts.
+    fn follow(&mut self, _previous: Note, _legato: bool, _params: &Self::Params) {}
+
+    /// Change a parameter by name, returning whether there is a parameter of that name.
+    /// Afterwards, `update` is called for all playing notes.
+    fn set_parameter(_params: &mut Self::Params, _name: &str, _value: f64) -> bool {
+        false
+    }
+
+    /// Called after a parameter changed, e.g. for recomputing what was derived from it
+    /// when the note started.
+    fn update(&mut self, _sample_rate: f64, _params: &Self::Params) {}
 }
 
 /// Opaque handle indicating a playing voice.
@@ -71,6 +143,10 @@ impl<Sampler: NoteSampler> Poly<Sampler> {
             samples_processed: 0,
             next_play_handle: 0,
             active_notes: vec![],
+            last_played: None,
+            pending_bends: Vec::new(),
+            target_bend: 0.0,
+            bend: 0.0,
         }
     }
 }
@@ -81,6 +157,85 @@ impl<Sampler: NoteSampler> Poly<Sampler> {
         self.next_play_handle += 1;
         h
     }
+
+    /// Apply the pitch bends due at the current sample and move the smoothed bend towards them.
+    fn update_bend(&mut self, smoothing: f64) {
+        let due = self
+            .pending_bends
+            .iter()
+            .take_while(|(delay, _)| *delay == 0)
+            .count();
+        if let Some((_, amount)) = self.pending_bends.drain(..due).next_back() {
+            self.target_bend = amount;
+        }
+        for (delay, _) in self.pending_bends.iter_mut() {
+            *delay -= 1;
+        }
+
+        if self.bend == self.target_bend {
+            if self.bend == 0.0 {
+                return;
+            }
+        } else if (self.bend - self.target_bend).abs() < 1e-6 {
+            self.bend = self.target_bend;
+        } else {
+            self.bend = self.target_bend + (self.bend - self.target_bend) * smoothing;
+        }
+        for state in self.active_notes.iter_mut() {
+            state.sampler.bend(self.bend, &self.parameters);
+        }
+    }
+
+    /// Tell the notes starting at the current sample about the note played before them.
+    fn follow_previous_notes(&mut self) {
+        for index in 0..self.active_notes.len() {
+            if self.active_notes[index].play_delay_samples > 0 {
+                continue;
+            }
+            if let Some((previous_handle, previous)) = self.active_notes[index].follows.take() {
+                let legato = self.active_notes.iter().any(|state| {
+                    state.handle.0 == previous_handle
+                        && !state.released
+                        && state.release_delay_samples > 0
+                });
+                self.active_notes[index]
+                    .sampler
+                    .follow(previous, legato, &self.parameters);
+            }
+        }
+    }
+
+    /// Indices of the notes that are not already fading out for making room.
+    fn sounding_notes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.active_notes
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.steal.is_none())
+            .map(|(index, _)| index)
+    }
+
+    /// Choose a sounding note to be stolen for playing `note`.
+    /// Must only be called if there is at least one sounding note.
+    fn victim(&self, note: Note, stealing: Stealing) -> usize {
+        let oldest = || {
+            self.sounding_notes()
+                .min_by_key(|index| self.active_notes[*index].handle.0)
+                .unwrap()
+        };
+        match stealing {
+            Stealing::Oldest => oldest(),
+            // A level that became NaN counts as quieter than any other, stealing the broken note
+            Stealing::Quietest => self
+                .sounding_notes()
+                .min_by_key(|index| F64N::new(self.active_notes[*index].loudness()))
+                .unwrap(),
+            Stealing::SameNote => self
+                .sounding_notes()
+                .filter(|index| self.active_notes[*index].note == note)
+                .min_by_key(|index| self.active_notes[*index].handle.0)
+                .unwrap_or_else(oldest),
+        }
+    }
 }
 
 impl<Sampler: NoteSampler> super::Instrument for Poly<Sampler> {
@@ -91,16 +246,49 @@ impl<Sampler: NoteSampler> super::Instrument for Poly<Sampler> {
         sample_delay: usize,
         note: Note,
         velocity: Velocity,
+    ) -> Self::PlayHandle {
+        self.play_detuned_note(sample_delay, note, velocity, 0.0)
+    }
+
+    fn play_detuned_note(
+        &mut self,
+        sample_delay: usize,
+        note: Note,
+        velocity: Velocity,
+        cents: f64,
     ) -> Self::PlayHandle {
         let handle = self.next_play_handle();
 
+        let voices = Sampler::voices(&self.parameters);
+        if let Some(max) = voices.max {
+            let fade_samples = (STEAL_FADE_SECONDS * self.sample_rate).ceil() as usize;
+            while self.sounding_notes().count() >= max.max(1) {
+                let victim = self.victim(note, voices.stealing);
+                log::trace!("stealing {:?}", self.active_notes[victim].handle);
+                self.active_notes[victim].steal = Some(Steal {
+                    delay_samples: sample_delay,
+                    remaining_samples: fade_samples,
+                    fade_samples,
+                });
+            }
+        }
+
+        let follows = self.last_played.replace((handle.0, note));
+        let mut sampler = Sampler::new(note, velocity, self.sample_rate, &self.parameters);
+        if cents != 0.0 {
+            sampler.detune(cents, &self.parameters);
+        }
         self.active_notes.push(NoteState {
             handle: PlayHandle(handle.0),
+            note,
             // state
             play_delay_samples: sample_delay,
             release_delay_samples: std::usize::MAX,
-            sampler: NoteSampler::new(note, velocity, self.sample_rate, &self.parameters),
+            sampler,
             released: false,
+            follows,
+            level: 0.0,
+            steal: None,
         });
         handle
     }
@@ -114,8 +302,33 @@ impl<Sampler: NoteSampler> super::Instrument for Poly<Sampler> {
         }
     }
 
+    fn pitch_bend(&mut self, sample_delay: usize, amount: f64) {
+        self.pending_bends
+            .push((sample_delay, amount.clamp(-1.0, 1.0)));
+        // Bends at the same time are applied in the order they were given
+        self.pending_bends.sort_by_key(|(delay, _)| *delay);
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        if !Sampler::set_parameter(&mut self.parameters, name, value) {
+            return false;
+        }
+        for state in self.active_notes.iter_mut() {
+            state.sampler.update(self.sample_rate, &self.parameters);
+        }
+        true
+    }
+
+    fn seek(&mut self, sample: usize) {
+        self.samples_processed = sample;
+    }
+
     fn fill_buffer(&mut self, output: &mut [Stereo<f64>]) {
+        let smoothing = (-1.0 / (BEND_SMOOTHING_SECONDS * self.sample_rate)).exp();
         for out_sample in output.iter_mut() {
+            self.follow_previous_notes();
+            self.update_bend(smoothing);
+
             let mut wave = Stereo::mono(0.0);
             let voice_count = self.active_notes.len();
             for voice_index in (0..voice_count).rev() {
@@ -145,6 +358,8 @@ struct NoteState<Sampler> {
     /// Handle that has been handed out to the host of the synthesizer when
     /// this note was played, used for releasing it.
     handle: PlayHandle,
+    /// The note being played
+    note: Note,
     /// Number of samples until the note starts
     play_delay_samples: usize,
     /// Number of samples until the note ends
@@ -153,6 +368,21 @@ struct NoteState<Sampler> {
     sampler: Sampler,
     /// Whether the note was already released
     released: bool,
+    /// Handle and note of the note played before this one, until this one starts
+    follows: Option<(usize, Note)>,
+    /// Peak level of the recent output, decaying over time
+    level: f64,
+    /// Set when the note is cut off to make room for another one
+    steal: Option<Steal>,
+}
+
+/// Fade-out of a stolen note.
+struct Steal {
+    /// Number of samples until the fade starts, i.e. when the new note starts
+    delay_samples: usize,
+    /// Number of samples until the note is silent
+    remaining_samples: usize,
+    fade_samples: usize,
 }
 
 impl<Sampler: NoteSampler> NoteState<Sampler> {
@@ -176,8 +406,122 @@ impl<Sampler: NoteSampler> NoteState<Sampler> {
                 self.sampler.release();
             }
 
-            self.sampler
-                .sample(global_sample_count, sample_rate, params)
+            let mut value = self
+                .sampler
+                .sample(global_sample_count, sample_rate, params)?;
+            if let Some(steal) = self.steal.as_mut() {
+                if steal.delay_samples > 0 {
+                    steal.delay_samples -= 1;
+                } else if steal.remaining_samples > 0 {
+                    value *= steal.remaining_samples as f64 / steal.fade_samples as f64;
+                    steal.remaining_samples -= 1;
+                } else {
+                    return None;
+                }
+            }
+            // Follow the peaks quickly, but let the level decay slowly (about 50 ms at 44.1 kHz)
+            self.level = (self.level * 0.9995).max(value.left.abs().max(value.right.abs()));
+            Some(value)
+        }
+    }
+
+    /// The recent level of the note, notes that have not started yet count as loudest.
+    fn loudness(&self) -> f64 {
+        if self.play_delay_samples > 0 {
+            f64::INFINITY
+        } else {
+            self.level
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{NoteSampler, Poly, Stealing, Voices};
+    use crate::instrument::Instrument;
+    use crate::wave::Stereo;
+    use syntxt_core::note::{Note, Velocity};
+
+    /// Plays a constant value for every note, equal to the velocity.
+    struct Constant(f64);
+
+    impl NoteSampler for Constant {
+        type Params = Voices;
+
+        fn voices(params: &Voices) -> Voices {
+            *params
+        }
+
+        fn new(_note: Note, velocity: Velocity, _sample_rate: f64, _params: &Voices) -> Self {
+            Constant(velocity.as_f64())
+        }
+
+        fn sample(&mut self, _: usize, _: f64, _: &Voices) -> Option<Stereo<f64>> {
+            Some(Stereo::mono(self.0))
+        }
+
+        fn release(&mut self) {}
+    }
+
+    /// Play the notes one after the other and return the final output.
+    fn play(stealing: Stealing, notes: &[(Note, f64)]) -> f64 {
+        let voices = Voices {
+            max: Some(2),
+            stealing,
+        };
+        // A sample rate of 1000 makes stolen notes fade out within 5 samples
+        let mut poly = Poly::<Constant>::with_params(1000.0, voices);
+        let mut output = vec![Stereo::mono(0.0); 10];
+        for (note, velocity) in notes {
+            poly.play_note(0, *note, Velocity::from_f64(*velocity));
+            output
+                .iter_mut()
+                .for_each(|sample| *sample = Stereo::mono(0.0));
+            poly.fill_buffer(&mut output);
+        }
+        output[9].left
+    }
+
+    #[test]
+    fn voice_stealing() {
+        let (c, d, e) = (
+            Note::from_midi(60),
+            Note::from_midi(62),
+            Note::from_midi(64),
+        );
+        let notes = [(c, 0.25), (d, 0.125), (e, 0.5)];
+
+        let mut poly = Poly::<Constant>::new(1000.0);
+        let mut output = vec![Stereo::mono(0.0); 10];
+        for (note, velocity) in notes.iter() {
+            poly.play_note(0, *note, Velocity::from_f64(*velocity));
+        }
+        poly.fill_buffer(&mut output);
+        assert_eq!(output[9].left, 0.875);
+
+        assert_eq!(play(Stealing::Oldest, &notes), 0.625);
+        assert_eq!(play(Stealing::Quietest, &notes), 0.75);
+        assert_eq!(play(Stealing::SameNote, &notes), 0.625);
+        assert_eq!(
+            play(Stealing::SameNote, &[(c, 0.25), (d, 0.125), (d, 0.5)]),
+            0.75
+        );
+    }
+
+    #[test]
+    fn steal_nan_level() {
+        let voices = Voices {
+            max: Some(2),
+            stealing: Stealing::Quietest,
+        };
+        let mut poly = Poly::<Constant>::with_params(1000.0, voices);
+        let mut output = vec![Stereo::mono(0.0); 10];
+        poly.play_note(0, Note::from_midi(60), Velocity::from_f64(0.25));
+        poly.play_note(0, Note::from_midi(62), Velocity::from_f64(0.5));
+        poly.fill_buffer(&mut output);
+        poly.active_notes[1].level = f64::NAN;
+        poly.play_note(0, Note::from_midi(64), Velocity::from_f64(0.125));
+        assert!(poly.active_notes[0].steal.is_none());
+        assert!(poly.active_notes[1].steal.is_some());
+    }
+}
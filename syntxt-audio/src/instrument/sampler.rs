@@ -0,0 +1,272 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Instrument playing recorded sounds, e.g. drums or vocals, at the pitch of the played notes.
+
+use std::sync::Arc;
+
+use crate::automation::{BuiltInValues, Expr};
+use crate::envelope::*;
+use crate::tuning::*;
+use crate::wave::*;
+use syntxt_core::note::*;
+
+use super::polyphonic::*;
+use super::velocity::VelocityCurve;
+
+pub type Sampler = Poly<Voice>;
+
+/// Parameters of the sampler.
+#[derive(Debug)]
+pub struct Params {
+    /// Output gain of the sampler
+    pub gain: Expr,
+    /// The recordings mapped onto ranges of notes.
+    /// If the ranges of several zones contain a note, the first one is used.
+    pub zones: Vec<Zone>,
+    /// Envelope for played notes
+    pub envelope: Envelope,
+    /// How the velocity of a note affects its volume
+    pub velocity_gain: VelocityCurve,
+    /// How many notes can play at once
+    pub voices: Voices,
+    /// How many semitones a full pitch bend changes the pitch
+    pub bend_range: f64,
+    /// The pitch of each note relative to the root note of its zone
+    pub tuning: Tuning,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            gain: Expr::Const(1.0),
+            zones: Vec::new(),
+            envelope: Envelope {
+                attack: 0.0,
+                release: 0.05,
+                ..Envelope::default()
+            },
+            velocity_gain: VelocityCurve::LINEAR,
+            voices: Voices::default(),
+            bend_range: 2.0,
+            tuning: Tuning::default(),
+        }
+    }
+}
+
+/// A recording played for a range of notes.
+#[derive(Debug, Clone)]
+pub struct Zone {
+    pub sample: Arc<SampleBuffer>,
+    /// The note at which the recording is played at its original speed.
+    /// Other notes are played faster or slower accordingly.
+    pub root: Note,
+    /// Lowest note played with this recording.
+    pub low: Note,
+    /// Highest note played with this recording.
+    pub high: Note,
+    /// Start and end of a section (in samples of the recording) that is repeated
+    /// for as long as the note sounds. Without a loop, a note ends with the recording.
+    pub loop_points: Option<(usize, usize)>,
+}
+
+impl Zone {
+    /// A zone playing the sample for all notes.
+    pub fn full_range(sample: Arc<SampleBuffer>, root: Note) -> Self {
+        Self {
+            sample,
+            root,
+            low: Note::from_midi(0),
+            high: Note::from_midi(127),
+            loop_points: None,
+        }
+    }
+}
+
+/// State needed for a playing note.
+pub struct Voice {
+    /// The zone playing the note, or `None` if no zone contains the note.
+    zone: Option<Zone>,
+    /// Position in the recording, in samples of the recording.
+    position: f64,
+    /// How far to advance in the recording for every output sample.
+    step: f64,
+    /// Factor applied to the step by the pitch bend.
+    bend_factor: f64,
+    envelope: EvalEnvelope,
+    velocity_gain: f64,
+}
+
+impl NoteSampler for Voice {
+    type Params = Params;
+
+    fn voices(params: &Params) -> Voices {
+        params.voices
+    }
+
+    fn new(note: Note, velocity: Velocity, sample_rate: f64, params: &Self::Params) -> Self {
+        let zone = params
+            .zones
+            .iter()
+            .find(|zone| zone.low <= note && note <= zone.high)
+            .cloned();
+        let step = match &zone {
+            Some(zone) => {
+                let tuning = &params.tuning;
+                let pitch = tuning.frequency(note) / tuning.frequency(zone.root);
+                pitch * zone.sample.sample_rate / sample_rate
+            }
+            None => 0.0,
+        };
+        Self {
+            zone,
+            position: 0.0,
+            step,
+            bend_factor: 1.0,
+            envelope: params.envelope.instantiate(sample_rate),
+            velocity_gain: params.velocity_gain.apply(velocity),
+        }
+    }
+
+    fn sample(
+        &mut self,
+        global_sample_count: usize,
+        sample_rate: f64,
+        params: &Self::Params,
+    ) -> Option<Stereo<f64>> {
+        let zone = self.zone.as_ref()?;
+        if self.envelope.faded() || self.position >= zone.sample.samples.len() as f64 {
+            return None;
+        }
+        let builtins = BuiltInValues {
+            global_time_seconds: global_sample_count as f64 / sample_rate,
+            ..BuiltInValues::default()
+        };
+        let value = zone.sample.interpolate(self.position);
+        let gain = params.gain.eval(&builtins, &[]).unwrap_or(0.0)
+            * self.envelope.step()
+            * self.velocity_gain;
+
+        self.position += self.step * self.bend_factor;
+        if let Some((start, end)) = zone.loop_points {
+            if start < end && self.position >= end as f64 {
+                self.position -= (end - start) as f64;
+            }
+        }
+        Some(value * gain)
+    }
+
+    fn release(&mut self) {
+        self.envelope.release()
+    }
+
+    fn set_parameter(params: &mut Params, name: &str, value: f64) -> bool {
+        match name {
+            "gain" => params.gain = Expr::Const(value),
+            "bend_range" => params.bend_range = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn bend(&mut self, amount: f64, params: &Self::Params) {
+        self.bend_factor = syntxt_core::util::from_semitones(amount * params.bend_range);
+    }
+
+    fn detune(&mut self, cents: f64, _params: &Self::Params) {
+        self.step *= syntxt_core::util::from_cents(cents);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Params, Sampler, Zone};
+    use crate::envelope::ADSR;
+    use crate::instrument::Instrument;
+    use crate::wave::{SampleBuffer, Stereo};
+    use syntxt_core::note::{Note, Velocity};
+
+    fn sampler(zone: Zone) -> Sampler {
+        Sampler::with_params(
+            100.0,
+            Params {
+                zones: vec![zone],
+                envelope: ADSR {
+                    attack: 0.0,
+                    decay: 0.0,
+                    sustain: 1.0,
+                    release: 1.0,
+                }
+                .into(),
+                ..Params::default()
+            },
+        )
+    }
+
+    fn ramp() -> Arc<SampleBuffer> {
+        Arc::new(SampleBuffer {
+            sample_rate: 100.0,
+            samples: (0..8).map(|i| Stereo::mono(i as f64)).collect(),
+        })
+    }
+
+    fn play(sampler: &mut Sampler, note: Note, length: usize) -> Vec<f64> {
+        sampler.play_note(0, note, Velocity::from_f64(1.0));
+        let mut output = vec![Stereo::mono(0.0); length];
+        sampler.fill_buffer(&mut output);
+        output.iter().map(|s| s.left).collect()
+    }
+
+    #[test]
+    fn pitch_mapping() {
+        let root = Note::from_midi(60);
+        let mut at_root = sampler(Zone::full_range(ramp(), root));
+        assert_eq!(
+            play(&mut at_root, root, 10),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 0.0, 0.0]
+        );
+        let mut octave_up = sampler(Zone::full_range(ramp(), root));
+        assert_eq!(
+            play(&mut octave_up, Note::from_midi(72), 5),
+            vec![0.0, 2.0, 4.0, 6.0, 0.0]
+        );
+        let mut octave_down = sampler(Zone::full_range(ramp(), root));
+        assert_eq!(
+            play(&mut octave_down, Note::from_midi(48), 4),
+            vec![0.0, 0.5, 1.0, 1.5]
+        );
+    }
+
+    #[test]
+    fn zones_and_loops() {
+        let root = Note::from_midi(60);
+        let zone = Zone {
+            low: Note::from_midi(60),
+            high: Note::from_midi(64),
+            loop_points: Some((4, 6)),
+            ..Zone::full_range(ramp(), root)
+        };
+        let mut looping = sampler(zone.clone());
+        assert_eq!(
+            play(&mut looping, root, 10),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 4.0, 5.0, 4.0, 5.0]
+        );
+        let mut outside = sampler(zone);
+        assert_eq!(play(&mut outside, Note::from_midi(59), 3), vec![0.0; 3]);
+    }
+}
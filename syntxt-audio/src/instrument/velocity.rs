@@ -0,0 +1,79 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Mapping how hard a key is pressed to parameters of the sound.
+
+use crate::envelope::Curve;
+use syntxt_core::note::Velocity;
+
+/// Maps a velocity to a factor between 0 and 1 that scales some parameter of a note,
+/// e.g. its volume or filter cutoff.
+#[derive(Debug, Clone)]
+pub struct VelocityCurve {
+    /// How the factor rises with the velocity.
+    pub curve: Curve,
+    /// How much the velocity matters, between 0 (not at all, the factor is always 1)
+    /// and 1 (the factor goes down to 0 at the minimum velocity).
+    pub amount: f64,
+}
+
+impl VelocityCurve {
+    /// The velocity does not influence the parameter.
+    pub const NONE: VelocityCurve = VelocityCurve {
+        curve: Curve::Linear,
+        amount: 0.0,
+    };
+
+    /// The parameter is proportional to the velocity.
+    pub const LINEAR: VelocityCurve = VelocityCurve {
+        curve: Curve::Linear,
+        amount: 1.0,
+    };
+
+    pub fn apply(&self, velocity: Velocity) -> f64 {
+        let amount = self.amount.clamp(0.0, 1.0);
+        1.0 - amount * (1.0 - self.curve.apply(velocity.as_f64()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VelocityCurve;
+    use crate::envelope::Curve;
+    use syntxt_core::note::Velocity;
+
+    #[test]
+    fn velocity_curves() {
+        let half = Velocity::from_f64(0.5);
+        assert_eq!(VelocityCurve::NONE.apply(half), 1.0);
+        assert_eq!(VelocityCurve::LINEAR.apply(half), 0.5);
+        assert_eq!(VelocityCurve::LINEAR.apply(Velocity::MIN), 0.0);
+
+        let subtle = VelocityCurve {
+            curve: Curve::Linear,
+            amount: 0.5,
+        };
+        assert_eq!(subtle.apply(Velocity::MIN), 0.5);
+        assert_eq!(subtle.apply(Velocity::MAX), 1.0);
+
+        // Soft notes are quieter than with the linear curve
+        let steep = VelocityCurve {
+            curve: Curve::Logarithmic,
+            amount: 1.0,
+        };
+        assert!(steep.apply(half) < 0.5);
+    }
+}
@@ -24,7 +24,9 @@ use crate::tuning::*;
 use crate::wave::*;
 use syntxt_core::note::*;
 
+use super::modulation::{Destination, EvalMatrix, Route, Source};
 use super::polyphonic::*;
+use super::velocity::VelocityCurve;
 
 use log::trace;
 
@@ -47,16 +49,122 @@ pub struct Params {
     /// The larger the spread, the more evenly the unison voices contribute to the final sound,
     /// the smaller the spread, the more the center frequency dominates.
     pub unison_spread: f64,
+    /// How far the unison voices are spread across the stereo field, between 0 (all in the center)
+    /// and 1 (the outermost voices fully left and right).
+    pub unison_stereo: f64,
+    /// How much the initial phases of the unison voices are randomized, between 0 (all voices
+    /// start in phase) and 1 (fully random). Without it, detuned voices start out as one loud voice.
+    pub unison_random_phase: f64,
 
     /// Oscillator shape
     pub wave_shape: WaveShape,
+    /// Amount of noise mixed into the oscillator, between 0 (none) and 1 (only noise).
+    pub noise: f64,
+    /// Color of the mixed in noise
+    pub noise_color: NoiseColor,
+    /// A second oscillator modulating the amplitude of the first one
+    pub modulator: Option<Modulator>,
+    /// An oscillator one or two octaves below the note, mixed with the voices
+    pub sub: Option<SubOscillator>,
+    /// Restart the voices with every period of the note, while they run at a different frequency
+    pub sync: Option<HardSync>,
 
     /// Evenlope for played notes
-    pub envelope: ADSR,
+    pub envelope: Envelope,
 
     /// The type of filter to apply to the synthesizer output.
     /// Currently limited to biquadratic filters.
     pub filter: filter::BiquadType,
+
+    /// Modulation of pitch, filter, volume and pan of each note,
+    /// by default only making the volume proportional to the velocity.
+    pub modulation: Vec<Route>,
+
+    /// How many notes can play at once
+    pub voices: Voices,
+    /// Sliding in pitch from one note to the next
+    pub glide: Glide,
+    /// How many semitones a full pitch bend changes the pitch
+    pub bend_range: f64,
+    /// The frequencies of the notes
+    pub tuning: Tuning,
+}
+
+/// Settings for a second oscillator that is combined with the unison voices
+/// by ring or amplitude modulation.
+#[derive(Debug, Clone)]
+pub struct Modulator {
+    pub modulation: Modulation,
+    pub shape: WaveShape,
+    /// Frequency of the modulator relative to the frequency of the note
+    pub ratio: f64,
+    /// How strongly the voices are modulated, between 0 (unchanged) and 1 (fully modulated)
+    pub depth: f64,
+}
+
+impl Default for Modulator {
+    fn default() -> Self {
+        Self {
+            modulation: Modulation::Ring,
+            shape: WaveShape::Sine,
+            ratio: 1.0,
+            depth: 1.0,
+        }
+    }
+}
+
+/// Settings for an oscillator playing below the note, thickening the bass.
+#[derive(Debug, Clone)]
+pub struct SubOscillator {
+    pub shape: WaveShape,
+    /// How many octaves below the note the oscillator plays
+    pub octaves: u32,
+    /// Gain relative to the voices
+    pub level: f64,
+}
+
+impl Default for SubOscillator {
+    fn default() -> Self {
+        Self {
+            shape: WaveShape::Sine,
+            octaves: 1,
+            level: 0.5,
+        }
+    }
+}
+
+/// Settings for hard-syncing the voices to a master oscillator at the frequency of the note.
+///
+/// Every time the master starts a new period, the voices restart theirs as well.
+/// The pitch stays that of the note, while changing the ratio sweeps through the overtones.
+#[derive(Debug, Clone)]
+pub struct HardSync {
+    /// Frequency of the voices relative to the master oscillator
+    pub ratio: f64,
+}
+
+impl Default for HardSync {
+    fn default() -> Self {
+        Self { ratio: 2.0 }
+    }
+}
+
+/// Settings for sliding from the pitch of the previous note to the pitch of a new note.
+#[derive(Debug, Clone)]
+pub struct Glide {
+    /// Time in seconds it takes to reach the new pitch, 0 for no glide
+    pub time: f64,
+    /// Only glide if the previous note is still held when the new one starts
+    pub legato_only: bool,
+}
+
+impl Default for Glide {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            legato_only: false,
+        }
+    }
 }
 
 impl Default for Params {
@@ -67,32 +175,68 @@ impl Default for Params {
             unison: 1,
             unison_detune_cents: 3.0,
             unison_spread: 1.0,
+            unison_stereo: 0.0,
+            unison_random_phase: 0.0,
             wave_shape: WaveShape::Sine,
-            envelope: ADSR {
-                attack: 0.01,
-                decay: 0.0,
-                sustain: 1.0,
-                release: 0.1,
-            },
+            noise: 0.0,
+            noise_color: NoiseColor::White,
+            modulator: None,
+            sub: None,
+            sync: None,
+            envelope: Envelope::default(),
             filter: filter::BiquadType::Allpass,
+            modulation: vec![Route::new(
+                Source::Velocity(VelocityCurve::LINEAR),
+                Destination::Amp,
+                1.0,
+            )],
+            voices: Voices::default(),
+            glide: Glide::default(),
+            bend_range: 2.0,
+            tuning: Tuning::default(),
         }
     }
 }
 
+/// A single unison voice of a note.
+struct UnisonVoice {
+    phase: Phase,
+    /// Phase of the master oscillator the voice is synced to
+    master: Phase,
+    /// Frequency factor relative to the center voice
+    detune: f64,
+    /// Gain of the voice on both channels, according to its spread and stereo position
+    gain: Stereo<f64>,
+}
+
 /// State needed for a playing note.
 pub struct Sampler {
     /// The voices producing the sound of the note
-    voices: Vec<Phase>,
+    voices: Vec<UnisonVoice>,
+    /// Sum of the gains of all voices, for normalizing the output
+    voice_gain_sum: f64,
+    /// Phase of the modulator
+    modulator_phase: Phase,
+    /// Phase of the sub oscillator
+    sub_phase: Phase,
+    /// Noise source shared by all voices
+    noise: Noise,
     /// The envelope defining the volume shape of the note
-    envelope: EvalADSR,
+    envelope: EvalEnvelope,
     /// Filter for this note
     biquad: Stereo<filter::Biquad>,
-    /// Index of the center voice (which may be in between two voices)
-    midpoint: f64,
+    /// Coefficients of the filter, adjusted to the modulated cutoff
+    filter_coeffs: filter::BiquadCoefficients,
+    /// Cutoff offset in octaves the coefficients were computed for
+    filter_octaves: Option<f64>,
+    /// Modulation sources of the note
+    matrix: EvalMatrix,
     /// Frequency of the center voice
     center_freq: f64,
-    /// The gain resulting from the initial note velocity
-    velocity_gain: f64,
+    /// Frequency the center voice glides from, and the time in seconds it takes
+    glide_from: Option<(f64, f64)>,
+    /// Factor applied to all frequencies by the pitch bend
+    bend_factor: f64,
     /// Duration of the current note in samples so far
     playtime_samples: usize,
 }
@@ -100,23 +244,59 @@ pub struct Sampler {
 impl NoteSampler for Sampler {
     type Params = Params;
 
+    fn voices(params: &Params) -> Voices {
+        params.voices
+    }
+
     fn new(note: Note, velocity: Velocity, sample_rate: f64, params: &Self::Params) -> Self {
-        // NOTE: number of voices can only be determined at note creation at the moment
+        // NOTE: unison settings can only be determined at note creation at the moment,
+        // which allows computing everything except the phase of the voices up front.
+        let unison = params.unison.max(1);
+        // Compute the index of the center voice (which may be in between two voices).
+        // The number of voices should be odd, so that one voice is playing the actual note frequency.
+        let midpoint = (unison as f64 - 1.0) / 2.0;
+        let spread_squared = params.unison_spread.max(0.001).powi(2);
+        // Seed differently from the noise, but still deterministically
+        let mut random = Noise::new(NoiseColor::White, !(note.to_midi() as u64));
+        let random_phase = params.unison_random_phase.clamp(0.0, 1.0);
+        let mut voices = Vec::with_capacity(unison);
+        let mut voice_gain_sum = 0.0;
+        for index in 0..unison {
+            let delta = index as f64 - midpoint;
+            let gain = (-delta * delta / (2.0 * spread_squared)).exp();
+            let position = if midpoint > 0.0 {
+                delta / midpoint * params.unison_stereo.clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let phase = Phase::new((random.next_sample() + 1.0) / 2.0 * random_phase);
+            voices.push(UnisonVoice {
+                phase,
+                master: phase,
+                detune: syntxt_core::util::from_cents(params.unison_detune_cents * delta),
+                gain: Stereo::panned_mono(gain, position),
+            });
+            voice_gain_sum += gain;
+        }
+
         Self {
-            voices: std::iter::repeat(Phase::ZERO)
-                .take(params.unison.max(1))
-                .collect(),
+            voices,
+            voice_gain_sum,
+            modulator_phase: Phase::ZERO,
+            sub_phase: Phase::ZERO,
+            // Seed with the note, so that chords do not play the same noise multiple times
+            noise: Noise::new(params.noise_color, note.to_midi() as u64),
             envelope: params.envelope.instantiate(sample_rate),
             biquad: Stereo {
                 left: filter::Biquad::new(),
                 right: filter::Biquad::new(),
             },
-            // Compute the index of the center voice (which may be in between two voices).
-            // The number of voices should be odd, so that one voice is playing the actual note frequency.
-            midpoint: (params.unison as f64 - 1.0) / 2.0,
-            center_freq: Tuning::default().frequency(note),
-            // the velocity simply controls the volume of the note
-            velocity_gain: velocity.as_f64(),
+            filter_coeffs: params.filter.to_coefficients(sample_rate),
+            filter_octaves: None,
+            matrix: EvalMatrix::new(&params.modulation, note, velocity, sample_rate),
+            center_freq: params.tuning.frequency(note),
+            glide_from: None,
+            bend_factor: 1.0,
             playtime_samples: 0,
         }
     }
@@ -135,25 +315,70 @@ impl NoteSampler for Sampler {
             note_time_seconds: self.playtime_samples as f64 / sample_rate,
         };
 
-        let mut value = 0.0;
-        let mut value_gain_sum = 0.0;
-        let spread_squared = params.unison_spread.max(0.001).powi(2);
-        for (index, voice) in self.voices.iter_mut().enumerate() {
-            let delta = index as f64 - self.midpoint;
+        let modulation = self
+            .matrix
+            .step(global_sample_count, builtins.note_time_seconds);
 
-            let gain = (-delta * delta / (2.0 * spread_squared)).exp();
+        let mut value = Stereo::mono(0.0);
+        let center_freq = match self.glide_from {
+            Some((from, time)) if builtins.note_time_seconds < time => {
+                // Glide linearly in pitch, i.e. exponentially in frequency
+                let progress = builtins.note_time_seconds / time;
+                from * (self.center_freq / from).powf(progress)
+            }
+            _ => self.center_freq,
+        } * self.bend_factor
+            * syntxt_core::util::from_semitones(modulation.pitch);
+        for voice in self.voices.iter_mut() {
+            value += voice.gain * params.wave_shape.eval(voice.phase);
+            let frequency = voice.detune * center_freq;
+            match &params.sync {
+                Some(sync) => {
+                    let master = voice.master.step_frequency(frequency, sample_rate);
+                    voice.phase = if master.offset() < voice.master.offset() {
+                        // Restart, taking into account how far the master got into its new period
+                        Phase::new(master.offset() * sync.ratio)
+                    } else {
+                        voice
+                            .phase
+                            .step_frequency(frequency * sync.ratio, sample_rate)
+                    };
+                    voice.master = master;
+                }
+                None => voice.phase = voice.phase.step_frequency(frequency, sample_rate),
+            }
+        }
+
+        if let Some(modulator) = &params.modulator {
+            let modulator_sample = modulator.shape.eval(self.modulator_phase);
+            let depth = modulator.depth.clamp(0.0, 1.0);
+            let modulation = modulator.modulation;
+            value = Stereo::new(
+                modulation.apply(value.left, modulator_sample, depth),
+                modulation.apply(value.right, modulator_sample, depth),
+            );
+            self.modulator_phase = self
+                .modulator_phase
+                .step_frequency(modulator.ratio * center_freq, sample_rate);
+        }
 
-            value += params.wave_shape.eval(*voice) * gain;
-            value_gain_sum += gain;
+        if let Some(sub) = &params.sub {
+            let sub_sample = sub.shape.eval(self.sub_phase) * sub.level * self.voice_gain_sum;
+            value += Stereo::mono(sub_sample);
+            self.sub_phase = self
+                .sub_phase
+                .step_frequency(center_freq / 2.0f64.powi(sub.octaves as i32), sample_rate);
+        }
 
-            let detune = syntxt_core::util::from_cents(params.unison_detune_cents * delta);
-            let frequency = detune * self.center_freq;
-            *voice = voice.step_frequency(frequency, sample_rate);
+        let noise = params.noise.clamp(0.0, 1.0);
+        if noise > 0.0 {
+            let noise_sample = self.noise.next_sample() * noise * self.voice_gain_sum;
+            value = value * (1.0 - noise) + Stereo::mono(noise_sample);
         }
 
         let envelope_gain = self.envelope.step();
         let instrument_gain = params.gain.eval(&builtins, &[]).unwrap_or(0.0);
-        let correction_gain = value_gain_sum.recip();
+        let correction_gain = self.voice_gain_sum.recip();
 
         trace!(
             "e = {}, i = {}, c = {}",
@@ -162,22 +387,352 @@ impl NoteSampler for Sampler {
             correction_gain
         );
 
-        let final_gain = instrument_gain * envelope_gain * self.velocity_gain * correction_gain;
+        let final_gain = instrument_gain * envelope_gain * modulation.amp * correction_gain;
 
-        let pan = params.pan.eval(&builtins, &[]).unwrap_or(0.0);
-        let output = final_gain * Stereo::panned_mono(value, pan);
+        let pan = params.pan.eval(&builtins, &[]).unwrap_or(0.0) + modulation.pan;
+        let balance = Stereo::panned_mono(final_gain, pan);
+        let output = Stereo::new(value.left * balance.left, value.right * balance.right);
 
-        // TODO: make filter automatable
-        let filter_coeffs = params.filter.to_coefficients(sample_rate);
+        if self.filter_octaves != Some(modulation.cutoff) {
+            self.filter_coeffs = params
+                .filter
+                .scale_frequency(syntxt_core::util::from_octaves(modulation.cutoff))
+                .to_coefficients(sample_rate);
+            self.filter_octaves = Some(modulation.cutoff);
+        }
         let filtered_output = Stereo {
-            left: self.biquad.left.step(&filter_coeffs, output.left),
-            right: self.biquad.right.step(&filter_coeffs, output.right),
+            left: self.biquad.left.step(&self.filter_coeffs, output.left),
+            right: self.biquad.right.step(&self.filter_coeffs, output.right),
         };
         self.playtime_samples += 1;
         Some(filtered_output)
     }
 
     fn release(&mut self) {
-        self.envelope.release()
+        self.envelope.release();
+        self.matrix.release();
+    }
+
+    fn set_parameter(params: &mut Params, name: &str, value: f64) -> bool {
+        match name {
+            "gain" => params.gain = Expr::Const(value),
+            "pan" => params.pan = Expr::Const(value),
+            "detune" => params.unison_detune_cents = value,
+            "noise" => params.noise = value,
+            "cutoff" => params.filter = params.filter.with_frequency(value),
+            "bend_range" => params.bend_range = value,
+            "sub_level" => match &mut params.sub {
+                Some(sub) => sub.level = value,
+                None => return false,
+            },
+            "sync_ratio" => match &mut params.sync {
+                Some(sync) => sync.ratio = value,
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn update(&mut self, _sample_rate: f64, params: &Params) {
+        // Recompute the filter coefficients with the next sample
+        self.filter_octaves = None;
+        let midpoint = (self.voices.len() as f64 - 1.0) / 2.0;
+        for (index, voice) in self.voices.iter_mut().enumerate() {
+            let delta = index as f64 - midpoint;
+            voice.detune = syntxt_core::util::from_cents(params.unison_detune_cents * delta);
+        }
+    }
+
+    fn bend(&mut self, amount: f64, params: &Self::Params) {
+        self.bend_factor = syntxt_core::util::from_semitones(amount * params.bend_range);
+    }
+
+    fn detune(&mut self, cents: f64, _params: &Self::Params) {
+        self.center_freq *= syntxt_core::util::from_cents(cents);
+    }
+
+    fn follow(&mut self, previous: Note, legato: bool, params: &Self::Params) {
+        if params.glide.time > 0.0 && (legato || !params.glide.legato_only) {
+            self.glide_from = Some((params.tuning.frequency(previous), params.glide.time));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Glide, HardSync, Modulator, Params, SubOscillator, Wavinator};
+    use crate::envelope::Envelope;
+    use crate::filter::BiquadType;
+    use crate::instrument::Instrument;
+    use crate::oscillator::WaveShape;
+    use crate::wave::Stereo;
+    use syntxt_core::note::{Note, Velocity};
+
+    /// Play a4 after a3 and count the zero crossings of a4 in two windows of 0.1 seconds,
+    /// right after it started and half a second later.
+    /// The two notes overlap by the given number of samples.
+    fn crossings(glide: Glide, overlap: usize) -> (usize, usize) {
+        let mut synth = Wavinator::with_params(
+            10000.0,
+            Params {
+                // Cut off the previous note right away
+                envelope: Envelope {
+                    attack: 0.0,
+                    release: 0.0,
+                    ..Envelope::default()
+                },
+                glide,
+                ..Params::default()
+            },
+        );
+        let mut buffer = vec![Stereo::mono(0.0); 1000];
+        let a3 = synth.play_note(0, Note::from_midi(57), Velocity::MAX);
+        synth.fill_buffer(&mut buffer);
+        synth.play_note(0, Note::from_midi(69), Velocity::MAX);
+        synth.release_note(overlap, a3);
+
+        let mut count = || {
+            let mut buffer = vec![Stereo::mono(0.0); 1000];
+            synth.fill_buffer(&mut buffer);
+            buffer
+                .windows(2)
+                .filter(|w| (w[0].left < 0.0) != (w[1].left < 0.0))
+                .count()
+        };
+        let start = count();
+        (0..4).for_each(|_| {
+            count();
+        });
+        (start, count())
+    }
+
+    #[test]
+    fn pitch_bend() {
+        let mut synth = Wavinator::with_params(
+            10000.0,
+            Params {
+                bend_range: 12.0,
+                ..Params::default()
+            },
+        );
+        synth.play_note(0, Note::from_midi(57), Velocity::MAX);
+        synth.pitch_bend(1000, 1.0);
+        let mut buffer = vec![Stereo::mono(0.0); 2000];
+        synth.fill_buffer(&mut buffer);
+        let crossings = |samples: &[Stereo<f64>]| {
+            samples
+                .windows(2)
+                .filter(|w| (w[0].left < 0.0) != (w[1].left < 0.0))
+                .count() as i64
+        };
+        // The bend raises a3 by an octave after 0.1 seconds, smoothed over a few milliseconds
+        assert!((crossings(&buffer[..1000]) - 44).abs() <= 1);
+        assert!((crossings(&buffer[1500..2000]) - 44).abs() <= 1);
+    }
+
+    /// Render the first 0.1 seconds of a4 played with the given unison settings.
+    fn unison(params: Params) -> Vec<Stereo<f64>> {
+        let mut synth = Wavinator::with_params(
+            10000.0,
+            Params {
+                unison: 5,
+                ..params
+            },
+        );
+        synth.play_note(0, Note::from_midi(69), Velocity::MAX);
+        let mut buffer = vec![Stereo::mono(0.0); 1000];
+        synth.fill_buffer(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn unison_stereo() {
+        let mono = unison(Params::default());
+        assert!(mono.iter().all(|s| s.left == s.right));
+
+        let wide = unison(Params {
+            unison_detune_cents: 20.0,
+            unison_stereo: 1.0,
+            ..Params::default()
+        });
+        assert!(wide.iter().any(|s| (s.left - s.right).abs() > 0.1));
+    }
+
+    #[test]
+    fn unison_random_phase() {
+        let peak = |buffer: &[Stereo<f64>]| buffer.iter().map(|s| s.left.abs()).fold(0.0, f64::max);
+        // Without detuning, voices starting in phase add up to a single loud voice
+        let aligned = unison(Params {
+            unison_detune_cents: 0.0,
+            ..Params::default()
+        });
+        assert!(peak(&aligned) > 0.99);
+
+        let random = || {
+            unison(Params {
+                unison_detune_cents: 0.0,
+                unison_random_phase: 1.0,
+                ..Params::default()
+            })
+        };
+        assert!(peak(&random()) < 0.9);
+        // The phases are deterministic
+        assert_eq!(random(), random());
+    }
+
+    #[test]
+    fn ring_modulation() {
+        let mut synth = Wavinator::with_params(
+            10000.0,
+            Params {
+                modulator: Some(Modulator::default()),
+                ..Params::default()
+            },
+        );
+        synth.play_note(0, Note::from_midi(69), Velocity::MAX);
+        let mut buffer = vec![Stereo::mono(0.0); 1000];
+        synth.fill_buffer(&mut buffer);
+        // A sine multiplied with itself never becomes negative, and oscillates at twice the frequency
+        assert!(buffer.iter().all(|s| s.left > -1e-9));
+        let peaks = buffer
+            .windows(3)
+            .filter(|w| w[1].left > w[0].left && w[1].left >= w[2].left)
+            .count();
+        assert!((peaks as i64 - 88).abs() <= 1);
+    }
+
+    /// Render a4 for 0.1 seconds after the attack, returning the magnitude of the given frequencies.
+    fn spectrum(params: Params, frequencies: &[f64]) -> Vec<f64> {
+        let mut synth = Wavinator::with_params(10000.0, params);
+        synth.play_note(0, Note::from_midi(69), Velocity::MAX);
+        let mut buffer = vec![Stereo::mono(0.0); 1000];
+        synth.fill_buffer(&mut buffer);
+        synth.fill_buffer(&mut buffer);
+        frequencies
+            .iter()
+            .map(|frequency| {
+                let (re, im) = buffer
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (t, s)| {
+                        let angle = 2.0 * std::f64::consts::PI * frequency * t as f64 / 10000.0;
+                        (re + s.left * angle.cos(), im - s.left * angle.sin())
+                    });
+                (re * re + im * im).sqrt() / buffer.len() as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sub_oscillator() {
+        let without = spectrum(Params::default(), &[110.0, 220.0, 440.0]);
+        assert!(without[0] < without[2] / 20.0 && without[1] < without[2] / 20.0);
+
+        let one = spectrum(
+            Params {
+                sub: Some(SubOscillator {
+                    level: 1.0,
+                    ..SubOscillator::default()
+                }),
+                ..Params::default()
+            },
+            &[110.0, 220.0, 440.0],
+        );
+        assert!(one[0] < one[1] / 20.0);
+        assert!((one[1] - one[2]).abs() < one[2] / 100.0);
+
+        let two = spectrum(
+            Params {
+                sub: Some(SubOscillator {
+                    octaves: 2,
+                    ..SubOscillator::default()
+                }),
+                ..Params::default()
+            },
+            &[110.0, 220.0, 440.0],
+        );
+        assert!((two[0] - two[2] / 2.0).abs() < two[2] / 100.0);
+        assert!(two[1] < two[0] / 20.0);
+    }
+
+    #[test]
+    fn hard_sync() {
+        let frequencies = [440.0, 1100.0, 1320.0];
+        let free = spectrum(
+            Params {
+                wave_shape: WaveShape::Sine,
+                ..Params::default()
+            },
+            &frequencies,
+        );
+        assert!(free[1] < free[0] / 20.0 && free[2] < free[0] / 20.0);
+
+        // Running at 2.5 times the note, but restarting with every period of the note,
+        // so that only its overtones are present
+        let synced = spectrum(
+            Params {
+                sync: Some(HardSync { ratio: 2.5 }),
+                ..Params::default()
+            },
+            &frequencies,
+        );
+        assert!(synced[0] > free[0] / 10.0 && synced[2] > free[0] / 10.0);
+        assert!(synced[1] < synced[2] / 10.0);
+    }
+
+    #[test]
+    fn automated_cutoff() {
+        let mut synth = Wavinator::with_params(
+            10000.0,
+            Params {
+                filter: BiquadType::Lowpass {
+                    cutoff: 4000.0,
+                    q: 0.7,
+                },
+                ..Params::default()
+            },
+        );
+        let power = |synth: &mut Wavinator| {
+            let mut buffer = vec![Stereo::mono(0.0); 1000];
+            synth.fill_buffer(&mut buffer);
+            buffer.iter().map(|s| s.left * s.left).sum::<f64>()
+        };
+        synth.play_note(0, Note::from_midi(69), Velocity::MAX);
+        // Skip the attack of the envelope
+        power(&mut synth);
+        let open = power(&mut synth);
+        // Also changes the filter of the note that is already playing
+        assert!(synth.set_parameter("cutoff", 100.0));
+        // Let the filter settle after the jump
+        power(&mut synth);
+        assert!(power(&mut synth) < open / 100.0);
+        assert!(!synth.set_parameter("resonance", 1.0));
+    }
+
+    #[test]
+    fn glide() {
+        let without = crossings(Glide::default(), 0);
+        let with = crossings(
+            Glide {
+                time: 0.3,
+                legato_only: false,
+            },
+            0,
+        );
+        // 440 Hz crosses zero 88 times in 0.1 seconds, 220 Hz only 44 times
+        assert!((without.0 as i64 - 88).abs() <= 2);
+        assert!(with.0 < 70);
+        // Only the phase differs once the glide is over
+        assert!((with.1 as i64 - without.1 as i64).abs() <= 1);
+
+        let legato = Glide {
+            time: 0.3,
+            legato_only: true,
+        };
+        // The previous note was released at the same time
+        assert_eq!(crossings(legato.clone(), 0), without);
+        // The previous note was still held
+        assert!(crossings(legato, 1).0 < 70);
     }
 }
@@ -0,0 +1,152 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Low frequency oscillators for modulating parameters over time.
+//!
+//! An LFO is routed to a parameter by using it in the automation expression of that
+//! parameter, e.g. `Expr::parse("+ 0.5 * 0.25 lfo sine 2")` for a gain wobbling twice per second
+//! between 0.25 and 0.75.
+
+use crate::oscillator::{Phase, WaveShape};
+use crate::song::{Time, TimeSig};
+
+/// Shape of the modulation signal. All shapes produce values between -1 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Sine,
+    Triangle,
+    Square,
+    /// A new random value for every period, held until the next one (sample and hold).
+    SampleAndHold,
+}
+
+impl Shape {
+    pub fn parse(input: &str) -> Option<Shape> {
+        match input {
+            "sine" => Some(Shape::Sine),
+            "triangle" => Some(Shape::Triangle),
+            "square" => Some(Shape::Square),
+            "s&h" => Some(Shape::SampleAndHold),
+            _ => None,
+        }
+    }
+}
+
+/// A low frequency oscillator.
+///
+/// It has no state, its value only depends on the time, so that all parameters using the same
+/// LFO stay in sync.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lfo {
+    pub shape: Shape,
+    /// Number of periods per second.
+    pub frequency: f64,
+    /// Offset of the start of the first period, between 0 and 1.
+    pub phase: f64,
+}
+
+impl Lfo {
+    /// An LFO running at a fixed frequency.
+    pub fn free(shape: Shape, frequency: f64) -> Self {
+        Self {
+            shape,
+            frequency,
+            phase: 0.0,
+        }
+    }
+
+    /// An LFO whose period has the given length in measures, e.g. `1/4` for one period per beat
+    /// in 4/4 time.
+    pub fn synced(shape: Shape, period: Time, sig: &TimeSig) -> Self {
+        let seconds = sig.seconds(period);
+        Self::free(
+            shape,
+            seconds.denominator() as f64 / seconds.numerator() as f64,
+        )
+    }
+
+    /// Value of the LFO at a point in time.
+    pub fn eval(&self, seconds: f64) -> f64 {
+        let position = seconds * self.frequency + self.phase;
+        let phase = Phase::new(position.fract());
+        match self.shape {
+            Shape::Sine => WaveShape::Sine.eval(phase),
+            // The triangle wave of the oscillators starts at zero, like the sine
+            Shape::Triangle => WaveShape::Triangle.eval(phase),
+            Shape::Square => WaveShape::Rectangle.eval(phase),
+            Shape::SampleAndHold => random(position.floor() as i64),
+        }
+    }
+}
+
+/// Pseudo-random value between -1 and 1 derived from an integer (splitmix64).
+pub(crate) fn random(seed: i64) -> f64 {
+    let mut z = (seed as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    // Use the upper 53 bits for a uniformly distributed float in [0, 1)
+    (z >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lfo, Shape};
+    use crate::song::TimeSig;
+    use syntxt_core::rational::Rational;
+
+    #[test]
+    fn shapes() {
+        let square = Lfo::free(Shape::Square, 2.0);
+        assert_eq!(square.eval(0.0), 1.0);
+        assert_eq!(square.eval(0.3), -1.0);
+        assert_eq!(square.eval(0.5), 1.0);
+
+        let triangle = Lfo::free(Shape::Triangle, 1.0);
+        assert_eq!(triangle.eval(0.25), 1.0);
+        assert_eq!(triangle.eval(0.75), -1.0);
+
+        let sine = Lfo {
+            phase: 0.25,
+            ..Lfo::free(Shape::Sine, 1.0)
+        };
+        assert!((sine.eval(0.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sample_and_hold() {
+        let lfo = Lfo::free(Shape::SampleAndHold, 4.0);
+        // Constant during a period
+        assert_eq!(lfo.eval(0.0), lfo.eval(0.2));
+        let values = (0..100)
+            .map(|period| lfo.eval(period as f64 / 4.0))
+            .collect::<Vec<_>>();
+        assert!(values.iter().all(|v| (-1.0..1.0).contains(v)));
+        assert!(values.windows(2).any(|w| w[0] != w[1]));
+        assert!(values.iter().any(|v| *v < 0.0) && values.iter().any(|v| *v > 0.0));
+    }
+
+    #[test]
+    fn synced() {
+        let sig = TimeSig {
+            beats_per_minute: 120,
+            beat_unit: 4,
+        };
+        // One beat lasts half a second
+        let lfo = Lfo::synced(Shape::Sine, Rational::new(1, 4), &sig);
+        assert_eq!(lfo.frequency, 2.0);
+    }
+}
@@ -15,16 +15,30 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 // modules for making sounds
+pub mod analysis;
 pub mod automation;
+pub mod dither;
+pub mod effect;
 pub mod envelope;
+pub mod fft;
 pub mod filter;
 pub mod instrument;
+pub mod lfo;
+pub mod meter;
 pub mod oscillator;
+pub mod plugin;
+pub mod process;
+pub mod smoothing;
+pub mod tuner;
 pub mod tuning;
 pub mod wave;
 
 // Building songs
+pub mod freeze;
 pub mod graph;
 pub mod melody;
 pub mod play;
+pub mod sequencer;
 pub mod song;
+pub mod tags;
+pub mod verify;
@@ -89,6 +89,7 @@ impl<'a> Parser<'a> {
                         duration: sym.duration,
                         start: time,
                         velocity: Velocity::from_f64(0.5),
+                        detune: 0.0,
                     });
                     time += sym.duration;
                 }
@@ -129,6 +130,7 @@ impl<'a> Parser<'a> {
                         duration: sym.duration,
                         start,
                         velocity: Velocity::from_f64(0.5),
+                        detune: 0.0,
                     });
                     time = time.max(start + sym.duration);
                 }
@@ -0,0 +1,367 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Measuring the level of signals: peak, RMS and loudness according to ITU-R BS.1770 (LUFS).
+//!
+//! A `Meter` is fed with consecutive buffers of a signal, e.g. by a `graph::MeterNode`
+//! tapping the output of any node, and can be queried at any time.
+
+use std::collections::VecDeque;
+
+use crate::filter::{Biquad, BiquadCoefficients};
+use crate::wave::{Frame, Layout, Stereo};
+use syntxt_core::util::{from_decibels, to_decibels};
+
+/// Length of the blocks loudness is measured over, in seconds.
+const BLOCK_SECONDS: f64 = 0.4;
+/// Number of steps per block, i.e. consecutive blocks overlap by 75%.
+const STEPS_PER_BLOCK: usize = 4;
+/// Blocks quieter than this are ignored for the integrated loudness, in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks more than this far below the ungated loudness are ignored as well, in LU.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Levels of a signal measured so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// Largest absolute sample value.
+    pub peak: f64,
+    /// Root mean square of the samples of all channels.
+    pub rms: f64,
+    /// Integrated loudness in LUFS, negative infinity if nothing was loud enough to measure.
+    pub loudness: f64,
+}
+
+impl Measurement {
+    /// Peak level in dB relative to full scale (dBFS).
+    pub fn peak_decibels(&self) -> f64 {
+        to_decibels(self.peak * self.peak)
+    }
+
+    /// RMS level in dB relative to full scale (dBFS).
+    pub fn rms_decibels(&self) -> f64 {
+        to_decibels(self.rms * self.rms)
+    }
+
+    /// The levels the signal has after applying a linear gain.
+    pub fn scaled(&self, gain: f64) -> Self {
+        Self {
+            peak: self.peak * gain.abs(),
+            rms: self.rms * gain.abs(),
+            loudness: self.loudness + to_decibels(gain * gain),
+        }
+    }
+}
+
+/// A level a whole signal is brought to by a constant gain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Integrated loudness in LUFS, e.g. -14 for streaming platforms.
+    Loudness(f64),
+    /// Peak level in dBFS, e.g. -1.
+    Peak(f64),
+}
+
+impl Normalization {
+    /// The linear gain bringing a signal with the measured levels to the target.
+    /// Silence is left alone.
+    pub fn gain(&self, measurement: &Measurement) -> f64 {
+        match *self {
+            Normalization::Loudness(target) if measurement.loudness.is_finite() => {
+                from_decibels(target - measurement.loudness).sqrt()
+            }
+            Normalization::Peak(target) if measurement.peak > 0.0 => {
+                from_decibels(target).sqrt() / measurement.peak
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Measurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "peak {:.1} dBFS, RMS {:.1} dBFS, {:.1} LUFS",
+            self.peak_decibels(),
+            self.rms_decibels(),
+            self.loudness
+        )
+    }
+}
+
+/// Accumulates the levels of a signal.
+pub struct Meter {
+    /// The K-weighting filter: a shelf modelling the head, then a highpass
+    shelf: BiquadCoefficients,
+    highpass: BiquadCoefficients,
+    /// The filters of each channel
+    filters: Vec<[Biquad; 2]>,
+    /// Weight of each channel in the loudness
+    weights: &'static [f64],
+    peak: f64,
+    /// Sum of the squares of all samples of all channels
+    sum_squares: f64,
+    samples: u64,
+    /// Number of samples per step
+    step_length: usize,
+    /// Sum of the squares of the weighted samples of the current step, and their number
+    step_power: f64,
+    step_samples: usize,
+    /// Largest absolute sample of the current step
+    step_peak: f64,
+    /// Mean power of the most recent steps, enough for one block
+    steps: VecDeque<f64>,
+    /// Largest absolute sample of the most recent steps
+    step_peaks: VecDeque<f64>,
+    /// Mean power of all blocks so far
+    blocks: Vec<f64>,
+}
+
+impl Meter {
+    pub fn new(sample_rate: f64) -> Self {
+        Self::with_layout(sample_rate, Layout::Stereo)
+    }
+
+    /// A meter for frames of the given layout, weighting the channels when measuring loudness.
+    pub fn with_layout(sample_rate: f64, layout: Layout) -> Self {
+        Self {
+            shelf: BiquadCoefficients::high_shelf(
+                sample_rate,
+                1500.0,
+                4.0,
+                std::f64::consts::FRAC_1_SQRT_2,
+            ),
+            highpass: BiquadCoefficients::highpass(sample_rate, 38.0, 0.5),
+            filters: vec![[Biquad::new(), Biquad::new()]; layout.channels()],
+            weights: layout.loudness_weights(),
+            peak: 0.0,
+            sum_squares: 0.0,
+            samples: 0,
+            step_length: ((BLOCK_SECONDS * sample_rate) as usize / STEPS_PER_BLOCK).max(1),
+            step_power: 0.0,
+            step_samples: 0,
+            step_peak: 0.0,
+            steps: VecDeque::with_capacity(STEPS_PER_BLOCK),
+            step_peaks: VecDeque::with_capacity(STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Measure the next samples of the signal.
+    pub fn feed(&mut self, samples: &[Stereo<f64>]) {
+        for sample in samples.iter() {
+            self.feed_values(&[sample.left, sample.right]);
+        }
+    }
+
+    /// Measure the next frames of a signal with the channels of the layout of the meter.
+    pub fn feed_frames(&mut self, frames: &[Frame]) {
+        for frame in frames.iter() {
+            self.feed_values(frame.values());
+        }
+    }
+
+    /// Measure one sample of each channel.
+    fn feed_values(&mut self, values: &[f64]) {
+        debug_assert_eq!(values.len(), self.filters.len());
+        for ((value, [filter0, filter1]), weight) in values
+            .iter()
+            .zip(self.filters.iter_mut())
+            .zip(self.weights.iter())
+        {
+            self.peak = self.peak.max(value.abs());
+            self.step_peak = self.step_peak.max(value.abs());
+            self.sum_squares += value * value;
+            let weighted = filter1.step(&self.highpass, filter0.step(&self.shelf, *value));
+            self.step_power += weight * weighted * weighted;
+        }
+        self.samples += 1;
+        self.step_samples += 1;
+
+        if self.step_samples == self.step_length {
+            if self.steps.len() == STEPS_PER_BLOCK {
+                self.steps.pop_front();
+                self.step_peaks.pop_front();
+            }
+            self.step_peaks.push_back(self.step_peak);
+            self.steps
+                .push_back(self.step_power / self.step_length as f64);
+            if self.steps.len() == STEPS_PER_BLOCK {
+                self.blocks
+                    .push(self.steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64);
+            }
+            self.step_power = 0.0;
+            self.step_samples = 0;
+            self.step_peak = 0.0;
+        }
+    }
+
+    /// Loudness of the most recent block in LUFS, suitable for a loudness meter
+    /// following the signal.
+    pub fn momentary(&self) -> f64 {
+        self.blocks
+            .last()
+            .map_or(f64::NEG_INFINITY, |power| loudness(*power))
+    }
+
+    /// Largest absolute sample of the most recent block, including the samples since then,
+    /// suitable for a peak meter following the signal.
+    pub fn momentary_peak(&self) -> f64 {
+        self.step_peaks
+            .iter()
+            .fold(self.step_peak, |peak, step| peak.max(*step))
+    }
+
+    /// The levels of everything measured so far.
+    pub fn measurement(&self) -> Measurement {
+        let rms = if self.samples > 0 {
+            (self.sum_squares / (self.filters.len() as u64 * self.samples) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Measurement {
+            peak: self.peak,
+            rms,
+            loudness: self.integrated(),
+        }
+    }
+
+    /// Loudness of the blocks passing the absolute and the relative gate.
+    fn integrated(&self) -> f64 {
+        let mean_above = |threshold: f64| {
+            let (sum, count) = self
+                .blocks
+                .iter()
+                .filter(|power| loudness(**power) > threshold)
+                .fold((0.0, 0), |(sum, count), power| (sum + power, count + 1));
+            if count > 0 {
+                Some(sum / count as f64)
+            } else {
+                None
+            }
+        };
+        match mean_above(ABSOLUTE_GATE) {
+            Some(ungated) => {
+                let threshold = loudness(ungated) + RELATIVE_GATE;
+                loudness(mean_above(threshold.max(ABSOLUTE_GATE)).unwrap_or(ungated))
+            }
+            None => f64::NEG_INFINITY,
+        }
+    }
+}
+
+/// Loudness in LUFS of the summed mean power of the weighted channels.
+fn loudness(power: f64) -> f64 {
+    -0.691 + to_decibels(power)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Meter, Normalization};
+    use crate::wave::{Frame, Layout, Stereo};
+
+    fn sine(frequency: f64, amplitude: f64, seconds: f64) -> Vec<Stereo<f64>> {
+        let length = (48000.0 * seconds) as usize;
+        (0..length)
+            .map(|i| {
+                let phase = i as f64 / 48000.0 * frequency * 2.0 * std::f64::consts::PI;
+                Stereo::mono(amplitude * phase.sin())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_scale_sine() {
+        let mut meter = Meter::new(48000.0);
+        meter.feed(&sine(997.0, 1.0, 3.0));
+        let measurement = meter.measurement();
+        assert!((measurement.peak - 1.0).abs() < 1e-3);
+        assert!((measurement.rms_decibels() + 3.01).abs() < 0.01);
+        // A full scale sine at 1 kHz in both channels is the reference of 0 LUFS
+        assert!(measurement.loudness.abs() < 0.1, "{}", measurement.loudness);
+        assert!(meter.momentary().abs() < 0.1);
+    }
+
+    #[test]
+    fn gating() {
+        let mut meter = Meter::new(48000.0);
+        assert_eq!(meter.measurement().loudness, f64::NEG_INFINITY);
+        meter.feed(&sine(997.0, 0.1, 3.0));
+        let loud = meter.measurement().loudness;
+        assert!((loud + 20.0).abs() < 0.1, "{}", loud);
+        // Silence and much quieter parts do not count, only the blocks overlapping the end of the tone
+        meter.feed(&vec![Stereo::mono(0.0); 48000]);
+        meter.feed(&sine(997.0, 0.001, 3.0));
+        let gated = meter.measurement().loudness;
+        assert!((gated - loud).abs() < 0.5, "{}", gated);
+        assert_eq!(meter.momentary(), meter.momentary().min(-50.0));
+    }
+
+    #[test]
+    fn momentary_peak() {
+        let mut meter = Meter::new(48000.0);
+        assert_eq!(meter.momentary_peak(), 0.0);
+        meter.feed(&sine(997.0, 0.5, 1.0));
+        assert!((meter.momentary_peak() - 0.5).abs() < 1e-3);
+        // The peak is forgotten once a whole block has passed, unlike the overall peak
+        meter.feed(&sine(997.0, 0.1, 0.5));
+        assert!((meter.momentary_peak() - 0.1).abs() < 1e-3);
+        assert!((meter.measurement().peak - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn surround() {
+        let loudness = |pair: usize| {
+            let mut meter = Meter::with_layout(48000.0, Layout::Surround51);
+            let frames = sine(997.0, 0.1, 3.0)
+                .into_iter()
+                .map(|sample| {
+                    let mut pairs = [Stereo::mono(0.0); 3];
+                    pairs[pair] = sample;
+                    Frame::from_pairs(&pairs)
+                })
+                .collect::<Vec<_>>();
+            meter.feed_frames(&frames);
+            meter.measurement().loudness
+        };
+        assert!((loudness(0) + 20.0).abs() < 0.1, "{}", loudness(0));
+        // The surround channels are weighted by 1.5 dB, the center counts, the LFE does not
+        assert!((loudness(2) - loudness(0) - 1.5).abs() < 0.05);
+        assert!((loudness(1) + 23.0).abs() < 0.1, "{}", loudness(1));
+    }
+
+    #[test]
+    fn normalization() {
+        let mut meter = Meter::new(48000.0);
+        meter.feed(&sine(997.0, 0.5, 3.0));
+        let measurement = meter.measurement();
+
+        let gain = Normalization::Loudness(-14.0).gain(&measurement);
+        assert!((measurement.scaled(gain).loudness + 14.0).abs() < 1e-9);
+        let mut normalized = Meter::new(48000.0);
+        normalized.feed(&sine(997.0, 0.5 * gain, 3.0));
+        assert!((normalized.measurement().loudness + 14.0).abs() < 1e-6);
+
+        let gain = Normalization::Peak(-1.0).gain(&measurement);
+        assert!((measurement.scaled(gain).peak_decibels() + 1.0).abs() < 1e-9);
+
+        // Silence stays silent
+        let silence = Meter::new(48000.0).measurement();
+        assert_eq!(Normalization::Loudness(-14.0).gain(&silence), 1.0);
+        assert_eq!(Normalization::Peak(-1.0).gain(&silence), 1.0);
+    }
+}
@@ -135,3 +135,150 @@ impl Oscillator {
         result
     }
 }
+
+/// Ways of combining a carrier signal with a modulator signal by multiplying them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Modulation {
+    /// Multiplying with the modulator as is, producing the sum and difference frequencies
+    /// of both signals without the carrier itself. Sounds metallic and bell-like.
+    Ring,
+    /// Multiplying with the modulator shifted to be between 0 and 1,
+    /// which keeps the carrier audible. Slow modulators produce a tremolo.
+    Amplitude,
+}
+
+impl Modulation {
+    /// Modulate a carrier sample by a modulator sample between -1 and 1.
+    ///
+    /// The depth between 0 and 1 blends between the unchanged and the fully modulated carrier.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use syntxt_audio::oscillator::Modulation;
+    /// assert_eq!(Modulation::Ring.apply(0.5, -1.0, 1.0), -0.5);
+    /// assert_eq!(Modulation::Amplitude.apply(0.5, -1.0, 1.0), 0.0);
+    /// assert_eq!(Modulation::Amplitude.apply(0.5, -1.0, 0.0), 0.5);
+    /// ```
+    pub fn apply(self, carrier: f64, modulator: f64, depth: f64) -> f64 {
+        let modulator = match self {
+            Modulation::Ring => modulator,
+            Modulation::Amplitude => 0.5 * (modulator + 1.0),
+        };
+        carrier * (1.0 - depth + depth * modulator)
+    }
+}
+
+/// The color of noise determines how its power is distributed over the frequencies.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseColor {
+    /// Equal power at all frequencies, bright and hissing.
+    White,
+    /// Power falling by 3 dB per octave, equal power per octave like many natural sounds.
+    Pink,
+    /// Power falling by 6 dB per octave, a deep rumble.
+    Brown,
+}
+
+/// A generator of pseudo-random noise between -1 and 1.
+///
+/// The noise is deterministic, generators with the same seed produce the same sequence.
+/// The coloring filters are tuned for sample rates around 44.1 kHz.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    color: NoiseColor,
+    /// State of the xorshift generator, never zero.
+    state: u64,
+    /// States of the filters shaping white into colored noise.
+    filters: [f64; 7],
+}
+
+impl Noise {
+    pub fn new(color: NoiseColor, seed: u64) -> Self {
+        Self {
+            color,
+            // Zero is a fixed point of xorshift
+            state: (seed ^ 0x2545_F491_4F6C_DD1D).max(1),
+            filters: [0.0; 7],
+        }
+    }
+
+    /// Uniformly distributed white noise.
+    fn white(&mut self) -> f64 {
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+
+    pub fn next_sample(&mut self) -> f64 {
+        let white = self.white();
+        let b = &mut self.filters;
+        let value = match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                // Sum of one-pole filters approximating the 3 dB slope (Paul Kellet)
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.1538520;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            NoiseColor::Brown => {
+                // Leaky integration of white noise, the leak keeps it from drifting away
+                b[0] = (b[0] + 0.02 * white) / 1.02;
+                b[0] * 3.5
+            }
+        };
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Noise, NoiseColor};
+
+    /// Ratio of the power of the changes between samples to the power of the samples,
+    /// the darker the noise, the smaller it is.
+    fn brightness(color: NoiseColor) -> f64 {
+        let mut noise = Noise::new(color, 1);
+        let samples: Vec<f64> = (0..44100).map(|_| noise.next_sample()).collect();
+        let power = samples.iter().map(|x| x * x).sum::<f64>();
+        let change = samples
+            .windows(2)
+            .map(|w| (w[1] - w[0]).powi(2))
+            .sum::<f64>();
+        change / power
+    }
+
+    #[test]
+    fn noise_colors() {
+        let mut white = Noise::new(NoiseColor::White, 1);
+        let samples: Vec<f64> = (0..44100).map(|_| white.next_sample()).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.01);
+        assert!(samples.iter().all(|x| x.abs() <= 1.0));
+
+        let (white, pink, brown) = (
+            brightness(NoiseColor::White),
+            brightness(NoiseColor::Pink),
+            brightness(NoiseColor::Brown),
+        );
+        assert!(white > pink && pink > brown, "{} {} {}", white, pink, brown);
+    }
+
+    #[test]
+    fn noise_is_deterministic() {
+        let mut a = Noise::new(NoiseColor::Pink, 42);
+        let mut b = Noise::new(NoiseColor::Pink, 42);
+        let mut c = Noise::new(NoiseColor::Pink, 43);
+        let a: Vec<f64> = (0..100).map(|_| a.next_sample()).collect();
+        assert_eq!(a, (0..100).map(|_| b.next_sample()).collect::<Vec<_>>());
+        assert_ne!(a, (0..100).map(|_| c.next_sample()).collect::<Vec<_>>());
+    }
+}
@@ -16,15 +16,31 @@
 
 //! Translate an abstract description of music into waveforms
 
+use std::cell::RefCell;
 use std::io;
 use std::path::PathBuf;
+use std::rc::Rc;
 
-use log::info;
+use log::{info, warn};
 use structopt::StructOpt;
 
+use crate::automation::Expr;
+use crate::dither::Dither;
+use crate::effect;
+use crate::filter;
+use crate::filter::fir::Decimator;
+use crate::freeze::{FreezeCache, KeyBuilder};
 use crate::graph;
 use crate::instrument;
-use crate::song::{Instrument, Song, Time, TimeSig};
+use crate::meter::{Measurement, Meter, Normalization};
+use crate::plugin;
+use crate::sequencer;
+use crate::smoothing::Smooth;
+use crate::song::{
+    AutomationTarget, Effect, Instrument, PitchBend, PlayedNote, Song, Time, TimeSig,
+};
+use crate::verify::Checksum;
+use crate::wave::{Frame, Layout, PackedSamples, Precision, Stereo, MAX_CHANNELS};
 use std::path::Path;
 
 #[derive(Debug, StructOpt)]
@@ -33,24 +49,204 @@ struct Opt {
     #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
     verbose: usize,
 
+    /// Output file, written directly for WAV and AIFF files, through opusenc for Opus files
+    /// and through sox for any other format.
+    /// Music is played directly if not given.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Send the notes to this MIDI port in real time instead of rendering audio,
+    /// e.g. the raw MIDI device /dev/snd/midiC1D0.
+    #[structopt(long, parse(from_os_str), conflicts_with = "output")]
+    midi_out: Option<PathBuf>,
+
+    /// Play as a JACK client following the transport of the JACK session,
+    /// which requires building with the `jack` feature.
+    #[structopt(long, conflicts_with_all = &["output", "midi-out"])]
+    jack: bool,
+
+    #[structopt(flatten)]
+    render: RenderArgs,
+
+    /// Render the song without playing it and print a checksum of the audio.
+    /// Given the checksum of an earlier render, fail unless the audio is still the same.
+    #[structopt(long, conflicts_with_all = &["output", "midi-out", "jack"])]
+    #[allow(clippy::option_option)]
+    verify: Option<Option<String>>,
+}
+
+// Command line arguments deciding how a song is rendered, shared by all commands rendering songs.
+// Not a doc comment, which would replace the description of the commands including them.
+#[derive(Debug, StructOpt)]
+pub struct RenderArgs {
     /// Final gain applied to the output of the song.
     #[structopt(short = "g", long = "gain", default_value = "1.0")]
     gain: f64,
 
-    /// Output file (any sox-supported format). Music is played directly if not given.
-    #[structopt(short, long, parse(from_os_str))]
-    output: Option<PathBuf>,
+    /// Sample rate of the output in Hz, by default 44100.
+    #[structopt(long)]
+    sample_rate: Option<i64>,
+
+    /// Render at this multiple of the sample rate (1, 2 or 4) and filter the result down,
+    /// reducing aliasing of distortion and other nonlinear effects at the cost of CPU time.
+    #[structopt(long, default_value = "1")]
+    oversampling: usize,
+
+    /// Bring the output to this integrated loudness in LUFS, e.g. -14.
+    #[structopt(long, allow_hyphen_values = true, conflicts_with = "normalize-peak")]
+    normalize_loudness: Option<f64>,
+
+    /// Bring the peak of the output to this level in dBFS, e.g. -1.
+    #[structopt(long, allow_hyphen_values = true)]
+    normalize_peak: Option<f64>,
 
-    /// Dump the description of the song generated from evaluating the code.
+    /// Report clipping, NaN, infinite and denormal samples in the output of any node,
+    /// with the song time where they first occur. Slows down rendering.
     #[structopt(long)]
-    #[allow(clippy::option_option)]
-    dump_description: Option<Option<PathBuf>>,
+    check: bool,
+
+    /// Keep the rendered song and send it to sox as 32 bit floats instead of 64 bit,
+    /// halving the memory needed for normalizing long songs.
+    #[structopt(long)]
+    single_precision: bool,
+
+    /// Bits per sample of the output file, e.g. 16 or 24.
+    #[structopt(long)]
+    bits: Option<u32>,
+
+    /// Write WAV files with floating point samples of the given bits (32 or 64).
+    #[structopt(long)]
+    float: bool,
+
+    /// Quality of Ogg Vorbis files from -1 to 10, or the compression of other formats
+    /// written by sox.
+    #[structopt(long, allow_hyphen_values = true)]
+    quality: Option<f64>,
+
+    /// Bitrate of Opus files in kbit/s.
+    #[structopt(long)]
+    bitrate: Option<u32>,
+
+    /// How samples are rounded to the bits of the output: none, triangular or shaped.
+    #[structopt(long, default_value = "triangular")]
+    dither: Dither,
+
+    /// Directory keeping the rendered frozen tracks between runs,
+    /// by default in the temporary directory of the system.
+    #[structopt(long, parse(from_os_str))]
+    freeze_cache: Option<PathBuf>,
+
+    /// Render only from this time on, in whole notes, e.g. 8/4 for the third measure.
+    /// Notes and effects that began earlier are heard as well.
+    #[structopt(long)]
+    from: Option<Time>,
+
+    /// Stop rendering at this time in whole notes.
+    #[structopt(long)]
+    to: Option<Time>,
+
+    /// Write WAV files as Broadcast WAV files, with a `bext` chunk describing the song
+    /// and its markers, e.g. the lines of the lyrics, as cue points.
+    #[structopt(long)]
+    broadcast: bool,
+
+    /// Write every track to its own WAV file in this directory while rendering,
+    /// with the effects and panning of the track but before the mixer.
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["from", "to"])]
+    stems: Option<PathBuf>,
+
+    /// Write every return bus to its own file as well when writing stems.
+    #[structopt(long, requires = "stems")]
+    bus_stems: bool,
+
+    /// Measure the time spent rendering every instrument, effect and track,
+    /// and print a breakdown when the song is done.
+    #[structopt(long)]
+    profile: bool,
+
+    /// Write the measured times to this file as JSON as well, which implies --profile.
+    #[structopt(long, parse(from_os_str))]
+    profile_json: Option<PathBuf>,
+}
+
+impl RenderArgs {
+    /// Use these settings where they are not given on the command line,
+    /// e.g. those of the configuration of a project, and then those of the song.
+    pub fn defaults(&mut self, sample_rate: Option<i64>, bits: Option<u32>) {
+        self.sample_rate = self.sample_rate.or(sample_rate);
+        self.bits = self.bits.or(bits);
+    }
+
+    /// The options for rendering, by default at 44.1 kHz, with frozen tracks kept in the given
+    /// directory or the temporary directory of the system.
+    pub fn options(&self) -> Options {
+        let normalization = match (self.normalize_loudness, self.normalize_peak) {
+            (Some(lufs), _) => Some(Normalization::Loudness(lufs)),
+            (None, Some(dbfs)) => Some(Normalization::Peak(dbfs)),
+            (None, None) => None,
+        };
+        Options {
+            sample_rate: self.sample_rate.unwrap_or(44100),
+            output_gain: self.gain,
+            oversampling: self.oversampling,
+            normalization,
+            check: self.check,
+            precision: if self.single_precision {
+                Precision::Single
+            } else {
+                Precision::Double
+            },
+            bits: self.bits,
+            float: self.float,
+            quality: self.quality,
+            bitrate: self.bitrate,
+            dither: self.dither,
+            broadcast: self.broadcast,
+            jack: false,
+            from: self.from,
+            to: self.to,
+            stems: self.stems.clone().map(|directory| Stems {
+                directory,
+                buses: self.bus_stems,
+            }),
+            profile: self.profile || self.profile_json.is_some(),
+            profile_json: self.profile_json.clone(),
+            freeze_cache: Some(Rc::new(RefCell::new(FreezeCache::in_directory(
+                self.freeze_cache
+                    .clone()
+                    .unwrap_or_else(|| std::env::temp_dir().join("syntxt-freeze")),
+            )))),
+        }
+    }
 }
 
 pub fn song_main<F: FnOnce() -> io::Result<crate::song::Song>>(compose: F) -> io::Result<()> {
-    let opt: Opt = Opt::from_args();
+    let mut opt: Opt = Opt::from_args();
+    init_logging(opt.verbose);
 
-    let level = match opt.verbose {
+    let song = compose()?;
+    opt.render.defaults(song.sample_rate, None);
+    if let Some(port) = opt.midi_out {
+        let messages = sequencer::messages(&song);
+        info!("sending {} messages to {}", messages.len(), port.display());
+        let mut port = std::fs::OpenOptions::new().write(true).open(port)?;
+        return sequencer::send(&messages, &mut port);
+    }
+    let options = Options {
+        jack: opt.jack,
+        ..opt.render.options()
+    };
+    let levels = match opt.verify {
+        Some(expected) => verify(song, expected, &options)?,
+        None => play(song, opt.output.as_deref(), &options)?,
+    };
+    levels.log();
+    Ok(())
+}
+
+/// Log to the terminal, showing debug messages with a verbosity of 1 and everything above.
+pub fn init_logging(verbosity: usize) {
+    let level = match verbosity {
         0 => log::LevelFilter::Info,
         1 => log::LevelFilter::Debug,
         _ => log::LevelFilter::Trace,
@@ -59,22 +255,319 @@ pub fn song_main<F: FnOnce() -> io::Result<crate::song::Song>>(compose: F) -> io
         .with_level(level)
         .init()
         .unwrap();
+}
 
-    let dump_out = opt
-        .dump_description
-        .map(|path| path.unwrap_or_else(|| "/dev/stdout".into()));
-    let song = compose()?;
-    if let Some(dump_out_path) = dump_out {
-        use std::io::Write;
-        let mut f = std::fs::File::create(dump_out_path)?;
-        writeln!(f, "{:?}", song)?;
+/// Render a song and print the checksum of its audio, failing if it differs from the expected one.
+fn verify(song: Song, expected: Option<String>, options: &Options) -> io::Result<Levels> {
+    let expected = match expected {
+        None => None,
+        Some(hex) => Some(u64::from_str_radix(&hex, 16).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid checksum `{}`: {}", hex, err),
+            )
+        })?),
+    };
+    let mut rendering = render(song, options)?;
+    let mut checksum = Checksum::new();
+    for block in rendering.by_ref() {
+        checksum.feed(&block.frames);
+    }
+    println!("{}", checksum);
+    info!("rendered {} frames", checksum.frames());
+    match expected {
+        Some(expected) if expected != checksum.hash() => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the rendered audio has the checksum {}, expected {:016x}",
+                checksum, expected
+            ),
+        )),
+        _ => Ok(rendering.levels()),
     }
-    play(song, opt.gain, opt.output.as_deref())
 }
 
-/// Play a song on the default speakers.
-pub fn play(song: Song, output_gain: f64, outfile: Option<&Path>) -> io::Result<()> {
-    let sample_rate = 44100;
+/// Levels measured while playing a song.
+#[derive(Debug, Clone)]
+pub struct Levels {
+    /// Every track after its effects and panning, before the mixer.
+    pub tracks: Vec<Measurement>,
+    /// Every return bus after its effects, before the mixer.
+    pub buses: Vec<Measurement>,
+    /// The final output.
+    pub master: Measurement,
+}
+
+impl Levels {
+    /// Log the levels of everything, one line at a time.
+    pub fn log(&self) {
+        for (index, track) in self.tracks.iter().enumerate() {
+            info!("track {}: {}", index, track);
+        }
+        for (index, bus) in self.buses.iter().enumerate() {
+            info!("bus {}: {}", index, bus);
+        }
+        info!("master: {}", self.master);
+    }
+}
+
+/// Peak levels of the most recent 400 ms while playing a song, as linear amplitudes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peaks {
+    pub tracks: Vec<f64>,
+    pub buses: Vec<f64>,
+    pub master: f64,
+}
+
+/// How a song is rendered.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Sample rate of the output in Hz, unless playing to JACK, which decides it.
+    pub sample_rate: i64,
+    /// Final gain in dB applied to the output of the song.
+    pub output_gain: f64,
+    /// With a factor of 2 or 4, everything is rendered at that multiple of the sample rate
+    /// and filtered down before the output. Other factors than 1 are rejected.
+    pub oversampling: usize,
+    /// The song is rendered completely before the output and brought to this level
+    /// by a final gain.
+    pub normalization: Option<Normalization>,
+    /// All signals are inspected while rendering and clipping, NaN, infinite and
+    /// denormal samples are reported with the node and time where they first occurred.
+    pub check: bool,
+    /// Format of the rendered song while it is kept for normalizing and sent to the output.
+    /// The song is always rendered in double precision, so that this only rounds the result.
+    pub precision: Precision,
+    /// Bits per sample of an output file, e.g. 16 or 24, which the song is rounded to at the end.
+    /// Otherwise WAV files get 32 bit floats, AIFF files 24 bit integers,
+    /// and sox picks the bits of other formats.
+    pub bits: Option<u32>,
+    /// Whether WAV files get floating point instead of integer samples.
+    pub float: bool,
+    /// Quality of lossy files written by sox, e.g. from -1 to 10 for Ogg Vorbis.
+    pub quality: Option<f64>,
+    /// Bitrate of Opus files in kbit/s, by default chosen by the encoder.
+    pub bitrate: Option<u32>,
+    /// How samples are rounded to the bits of the output.
+    pub dither: Dither,
+    /// Whether WAV files are written as Broadcast WAV files, describing the song in a `bext`
+    /// chunk and showing its markers as cue points.
+    pub broadcast: bool,
+    /// Whether the song is played as a JACK client instead of to the output file or sox.
+    /// It starts and pauses with the JACK transport, at the sample rate of the JACK server.
+    pub jack: bool,
+    /// Start of the part of the song that is rendered, by default its beginning.
+    /// Rendering starts a bit earlier, so that notes, envelopes and effect tails from before
+    /// are heard, but time-based patterns of effects start over.
+    pub from: Option<Time>,
+    /// End of the part of the song that is rendered, by default its end.
+    pub to: Option<Time>,
+    /// Where every track is written to its own file while rendering.
+    /// Only possible when rendering the whole song.
+    pub stems: Option<Stems>,
+    /// Where frozen tracks are kept between renders.
+    /// Without it, frozen tracks are rendered like any other.
+    pub freeze_cache: Option<Rc<RefCell<FreezeCache>>>,
+    /// Whether the time spent in every node is measured and logged at the end,
+    /// grouped by track, bus and the master section.
+    pub profile: bool,
+    /// Where the measured times are written as JSON when profiling.
+    pub profile_json: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            output_gain: 1.0,
+            oversampling: 1,
+            normalization: None,
+            check: false,
+            precision: Precision::Double,
+            bits: None,
+            float: false,
+            quality: None,
+            bitrate: None,
+            dither: Dither::Triangular,
+            broadcast: false,
+            jack: false,
+            from: None,
+            to: None,
+            stems: None,
+            freeze_cache: None,
+            profile: false,
+            profile_json: None,
+        }
+    }
+}
+
+/// Seconds rendered before the start of a region of a song,
+/// in which notes and effect tails from before the region settle.
+const PREROLL_SECONDS: f64 = 2.0;
+
+/// Files receiving the tracks of a song separately, e.g. for mixing it elsewhere.
+///
+/// They are written as WAV files in the format of the output, named after the tracks
+/// and numbered in the order of the song. Normalizing the output does not change them.
+#[derive(Debug, Clone)]
+pub struct Stems {
+    pub directory: PathBuf,
+    /// Whether every return bus is written to a file after the tracks.
+    pub buses: bool,
+}
+
+/// Play a song on the default speakers, or write it to a file.
+/// Returns the levels of the tracks, buses and the output.
+pub fn play(song: Song, outfile: Option<&Path>, options: &Options) -> io::Result<Levels> {
+    let mut options = options.clone();
+    let jack_sink = if options.jack {
+        let (sink, sample_rate) = connect_jack()?;
+        options.sample_rate = sample_rate;
+        Some(sink)
+    } else {
+        None
+    };
+    let Options {
+        sample_rate,
+        precision,
+        bits,
+        float,
+        quality,
+        bitrate,
+        dither,
+        broadcast,
+        ..
+    } = options;
+
+    let layout = song.layout;
+    let tags = song.tags.clone();
+    let markers = song.markers.clone();
+    let mut sink = match (jack_sink, outfile) {
+        (Some(sink), _) => {
+            if layout != Layout::Stereo {
+                warn!(
+                    "JACK only plays the front speakers of the {:?} layout",
+                    layout
+                );
+            }
+            sink
+        }
+        (None, Some(path)) if has_extension(path, "opus") => Sink::Opus(graph::OpusSink::create(
+            path,
+            sample_rate as i32,
+            layout,
+            bitrate,
+            dither,
+            &song.tags,
+        )?),
+        (None, Some(path)) if has_extension(path, "wav") => Sink::Wav(
+            graph::WavSink::create(
+                path,
+                sample_rate as u32,
+                graph::WavFormat::new(bits, float)?,
+                layout,
+                dither,
+            )?
+            .with_tags(&song.tags),
+        ),
+        (None, Some(path)) if has_extension(path, "aiff") || has_extension(path, "aif") => {
+            if float {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "AIFF files hold integers, write a WAV file for floats",
+                ));
+            }
+            Sink::Aiff(
+                graph::AiffSink::create(
+                    path,
+                    sample_rate as u32,
+                    bits.unwrap_or(24),
+                    layout,
+                    dither,
+                )?
+                .with_tags(&song.tags),
+            )
+        }
+        (None, _) => {
+            let target = match outfile {
+                None => graph::SoxTarget::Play,
+                Some(path) => graph::SoxTarget::File(path),
+            };
+            let format = graph::SoxFormat {
+                precision,
+                bits,
+                dither,
+                compression: quality,
+                layout,
+            };
+            Sink::Sox(graph::SoxSink::with_tags(
+                sample_rate as i32,
+                target,
+                format,
+                &song.tags,
+            )?)
+        }
+    };
+    let mut rendering = render(song, &options)?;
+    match &mut sink {
+        Sink::Wav(wav) if broadcast => {
+            wav.set_broadcast(&tags, rendering.start_frame() as u64);
+            // Markers outside of the region are left out, as well as those beyond the 32 bit
+            // positions of cue points
+            let cues = markers
+                .iter()
+                .filter_map(|marker| {
+                    let frame = rendering
+                        .frame_at(marker.time)
+                        .filter(|&frame| frame <= u32::MAX as usize)?;
+                    Some(graph::Cue {
+                        position: frame as u32,
+                        label: marker.text.clone(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            wav.set_cues(&cues);
+        }
+        _ if broadcast => warn!("only WAV files are written as Broadcast WAV files"),
+        _ => {}
+    }
+    for block in rendering.by_ref() {
+        sink.write(&block.frames);
+    }
+    sink.finish()?;
+    Ok(rendering.levels())
+}
+
+/// Prepare rendering a song, which happens block by block while iterating the result.
+pub fn render(mut song: Song, options: &Options) -> io::Result<Render> {
+    let Options {
+        sample_rate: output_rate,
+        output_gain,
+        oversampling,
+        normalization,
+        check,
+        precision,
+        bits,
+        float,
+        dither,
+        freeze_cache,
+        from,
+        to,
+        stems,
+        profile,
+        profile_json,
+        ..
+    } = options.clone();
+    if ![1, 2, 4].contains(&oversampling) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "oversampling by a factor of {} is not supported, only 1, 2 or 4",
+                oversampling
+            ),
+        ));
+    }
+    let sample_rate = output_rate * oversampling as i64;
 
     let sig = TimeSig {
         beats_per_minute: song.bpm,
@@ -91,63 +584,1289 @@ pub fn play(song: Song, output_gain: f64, outfile: Option<&Path>) -> io::Result<
         .max()
         .unwrap_or(Time::int(0));
 
-    let players: Vec<_> = song
+    // The recordings are decoded up front, as they can make the song longer than its notes
+    let clips = song
+        .tracks
+        .iter()
+        .map(|track| {
+            track
+                .clips
+                .iter()
+                .map(|clip| {
+                    let sample = graph::load_sample(&clip.path, sample_rate as i32)?;
+                    Ok((
+                        sig.samples(clip.start, sample_rate),
+                        Rc::new(sample.samples),
+                    ))
+                })
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let last_clip_end = clips
+        .iter()
+        .flatten()
+        .map(|(start, samples)| start + samples.len() as i64)
+        .max()
+        .unwrap_or(0);
+
+    // 10 ms buffer at 44100 Hz
+    let buffer_size = 441 * oversampling as i64;
+    // Effects can still be heard for two measures after the last note or recording
+    let max_samples = sig
+        .samples(last_note_end + Time::int(2), sample_rate)
+        .max(last_clip_end + sig.samples(Time::int(2), sample_rate))
+        + buffer_size
+        - 1;
+
+    // Only the region between `from` and `to` is output, measured in samples of the output
+    let song_end = max_samples / buffer_size * buffer_size / oversampling as i64;
+    let region_start = from.map_or(0, |from| sig.samples(from, output_rate).max(0));
+    let region_end = to.map_or(song_end, |to| sig.samples(to, output_rate).min(song_end));
+    if region_start >= region_end {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nothing to render between the start and the end",
+        ));
+    }
+    // Rendering starts earlier, aligned to the buffers of rendering the whole song
+    let preroll = (PREROLL_SECONDS * sample_rate as f64) as i64;
+    let graph_start =
+        (region_start * oversampling as i64 - preroll).max(0) / buffer_size * buffer_size;
+    let partial = graph_start > 0 || region_end < song_end;
+    if partial && stems.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "stems can only be written for the whole song",
+        ));
+    }
+
+    let pairs = song.layout.pairs();
+    if let Some(track) = song.tracks.iter().find(|track| track.speakers >= pairs) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "a track plays on pair {} of the channels, but the {:?} layout has only {} pairs",
+                track.speakers, song.layout, pairs
+            ),
+        ));
+    }
+
+    let soloing = song.tracks.iter().any(|track| track.solo);
+    let track_names = song
+        .tracks
+        .iter()
+        .map(|track| track.name.clone())
+        .collect::<Vec<_>>();
+    let measure_samples = {
+        let seconds = sig.seconds(Time::int(1));
+        seconds.numerator() as f64 / seconds.denominator() as f64 * sample_rate as f64
+    };
+
+    // Instruments are created first, so that effects can listen to any track as sidechain
+    if let Some(intonation) = &song.intonation {
+        let mut pitched = song
+            .tracks
+            .iter_mut()
+            .filter(|track| !matches!(track.instrument, Instrument::DrumKit(_)))
+            .map(|track| &mut track.notes)
+            .collect::<Vec<_>>();
+        intonation.retune(&mut pitched);
+    }
+
+    // Tracks heard by others as sidechain are needed as they are
+    let sidechains = song
+        .tracks
+        .iter()
+        .flat_map(|track| track.effects.iter())
+        .chain(song.buses.iter().flat_map(|bus| bus.effects.iter()))
+        .chain(song.effects.iter())
+        .filter_map(|effect| match effect {
+            Effect::Compressor { sidechain, .. } => *sidechain,
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let frozen = song
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(index, track)| {
+            let cache = freeze_cache.as_ref().filter(|_| track.freeze)?;
+            if sidechains.contains(&index) {
+                warn!("not freezing track {}, which is used as sidechain", index);
+                return None;
+            }
+            // Everything shaping the sound before the mixer, including changes to the recordings
+            let modified = track
+                .clips
+                .iter()
+                .map(|clip| {
+                    std::fs::metadata(&clip.path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                })
+                .collect::<Vec<_>>();
+            let key = KeyBuilder::new()
+                .include(&track.instrument)
+                .include(&track.notes)
+                .include(&track.clips)
+                .include(&modified)
+                .include(&track.effects)
+                .include(&track.pan)
+                .include(&track.bends)
+                .include(&track.automation)
+                .include(track.tuning.as_ref().unwrap_or(&song.tuning))
+                .include(&(sample_rate, song.bpm, song.smoothing, max_samples))
+                .finish();
+            Some(match cache.borrow_mut().get(key) {
+                Some(samples) => {
+                    info!("playing frozen track {}", index);
+                    Frozen::Cached(samples)
+                }
+                // Only complete renderings are kept
+                None if partial => return None,
+                None => Frozen::Recording(key),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let song_tuning = song.tuning;
+    let smoothing = song.smoothing;
+    let (sources, tracks): (Vec<_>, Vec<_>) = song
         .tracks
         .into_iter()
-        .map(|track| match track.instrument {
-            Instrument::Wavinator(ps) => graph_builder
-                .add_node(graph::InstrumentSource::new(
+        .zip(frozen)
+        .zip(clips)
+        .enumerate()
+        .map(|(index, ((track, frozen), clips))| {
+            graph_builder.group(&track_group(index, track.name.as_deref()));
+            let channel = graph::MixerChannel {
+                mute: track.mute,
+                solo: track.solo,
+                output: track.speakers,
+                ..graph::MixerChannel::new(track.gain)
+            };
+            let cached = match &frozen {
+                Some(Frozen::Cached(samples)) => Some(samples.clone()),
+                _ => None,
+            };
+            if let Some(samples) = cached {
+                // The effects and their automation are already part of the recording
+                let source = graph_builder
+                    .add_node(graph::Playback::new(samples))
+                    .build();
+                let settings = (
+                    Vec::new(),
+                    track.pan,
+                    track.sends,
+                    Vec::new(),
+                    frozen,
+                    source,
+                );
+                return Ok((source, (settings, channel)));
+            }
+
+            let tuning = track.tuning.unwrap_or_else(|| song_tuning.clone());
+            let rate = sample_rate as f64;
+            let instrument = match track.instrument {
+                Instrument::Wavinator(mut ps) => {
+                    ps.tuning = tuning;
+                    add_instrument(
+                        &mut graph_builder,
+                        sample_rate,
+                        sig,
+                        smoothing,
+                        instrument::wavinator::Wavinator::with_params(rate, ps),
+                        track.notes,
+                        track.bends,
+                    )
+                }
+                Instrument::Sampler(mut ps) => {
+                    ps.tuning = tuning;
+                    add_instrument(
+                        &mut graph_builder,
+                        sample_rate,
+                        sig,
+                        smoothing,
+                        instrument::sampler::Sampler::with_params(rate, ps),
+                        track.notes,
+                        track.bends,
+                    )
+                }
+                Instrument::PluckedString(mut ps) => {
+                    ps.tuning = tuning;
+                    add_instrument(
+                        &mut graph_builder,
+                        sample_rate,
+                        sig,
+                        smoothing,
+                        instrument::plucked_string::PluckedString::with_params(rate, ps),
+                        track.notes,
+                        track.bends,
+                    )
+                }
+                Instrument::DrumKit(ps) => add_instrument(
+                    &mut graph_builder,
                     sample_rate,
                     sig,
-                    instrument::wavinator::Wavinator::with_params(sample_rate as f64, ps),
+                    smoothing,
+                    instrument::drum_kit::DrumKit::with_params(rate, ps),
                     track.notes,
-                ))
-                .build(),
+                    track.bends,
+                ),
+                Instrument::Plugin(mut ps) => {
+                    ps.tuning = tuning;
+                    add_instrument(
+                        &mut graph_builder,
+                        sample_rate,
+                        sig,
+                        smoothing,
+                        load_plugin(sample_rate, &ps)?,
+                        track.notes,
+                        track.bends,
+                    )
+                }
+            };
+            // The clips are part of the source, so that sidechains hear them as well
+            let source = if clips.is_empty() {
+                instrument
+            } else {
+                let players = clips
+                    .into_iter()
+                    .map(|(start, samples)| {
+                        graph_builder
+                            .add_node(graph::Playback::starting_at(samples, start))
+                            .build()
+                    })
+                    .collect::<Vec<_>>();
+                let gains = vec![1.0; players.len() + 1];
+                std::iter::once(instrument)
+                    .chain(players)
+                    .enumerate()
+                    .fold(
+                        graph_builder.add_node(graph::Mix::new(gains)),
+                        |accum, (index, node)| accum.input_from(index, node.output(0)),
+                    )
+                    .build()
+            };
+            let settings = (
+                track.effects,
+                track.pan,
+                track.sends,
+                track.automation,
+                frozen,
+                instrument,
+            );
+            Ok((source, (settings, channel)))
         })
-        .collect();
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
 
-    let target = match outfile {
-        None => graph::SoxTarget::Play,
-        Some(path) => graph::SoxTarget::File(path),
-    };
+    let mut sends = vec![Vec::new(); song.buses.len()];
+    let mut recordings = Vec::new();
+    let (mut players, mut channels): (Vec<_>, Vec<_>) = tracks
+        .into_iter()
+        .zip(sources.iter())
+        .enumerate()
+        .map(|(index, ((settings, channel), source))| {
+            graph_builder.group(&track_group(index, track_names[index].as_deref()));
+            let (track_effects, pan, track_sends, track_automation, frozen, instrument) = settings;
+            // Chain the effects of the track after its instrument
+            let mut effect_nodes = Vec::new();
+            let mut output = *source;
+            for track_effect in track_effects {
+                output = add_effect(
+                    &mut graph_builder,
+                    sample_rate,
+                    smoothing,
+                    &sources,
+                    output.output(0),
+                    track_effect,
+                )?;
+                effect_nodes.push(output);
+            }
+            for automation in track_automation {
+                let node = match automation.target {
+                    AutomationTarget::Instrument => Some(instrument),
+                    AutomationTarget::Effect(index) => effect_nodes.get(index).copied(),
+                };
+                match node {
+                    Some(node) => graph_builder.automate(
+                        node,
+                        &automation.parameter,
+                        automation
+                            .curve
+                            .map_time(|measures| measures * measure_samples),
+                    ),
+                    None => warn!("ignoring automation of missing {:?}", automation.target),
+                }
+            }
+            let player = match frozen {
+                // The panning is already part of the recording
+                Some(Frozen::Cached(_)) => output,
+                _ => graph_builder
+                    .add_node(graph::Pan::new(sample_rate as f64, pan))
+                    .input_from(0, output.output(0))
+                    .build(),
+            };
+            if let Some(Frozen::Recording(key)) = frozen {
+                let samples = Rc::new(RefCell::new(PackedSamples::new(Precision::Double)));
+                graph_builder
+                    .add_node(graph::Recorder::new(samples.clone()))
+                    .input_from(0, player.output(0))
+                    .build();
+                recordings.push((key, samples));
+            }
+            // Silenced tracks do not reach the buses either
+            let track_sends = if channel.audible(soloing) {
+                track_sends
+            } else {
+                Vec::new()
+            };
+            for send in track_sends {
+                match sends.get_mut(send.bus) {
+                    Some(bus) => bus.push((player, send.amount)),
+                    None => warn!("ignoring send to missing bus {}", send.bus),
+                }
+            }
+            Ok((player, channel))
+        })
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+
+    // The return buses are mixed together with the tracks
+    for (index, (bus, bus_sends)) in song.buses.into_iter().zip(sends).enumerate() {
+        graph_builder.group(&format!("bus {}", index));
+        let gains = bus_sends.iter().map(|(_, amount)| *amount).collect();
+        let input = bus_sends
+            .iter()
+            .enumerate()
+            .fold(
+                graph_builder.add_node(graph::Mix::new(gains)),
+                |accum, (index, (player, _))| accum.input_from(index, player.output(0)),
+            )
+            .build();
+        let output = bus
+            .effects
+            .into_iter()
+            .try_fold(input, |previous, bus_effect| {
+                add_effect(
+                    &mut graph_builder,
+                    sample_rate,
+                    smoothing,
+                    &sources,
+                    previous.output(0),
+                    bus_effect,
+                )
+            })?;
+        players.push(output);
+        channels.push(graph::MixerChannel {
+            solo_safe: true,
+            ..graph::MixerChannel::new(Expr::Const(1.0))
+        });
+    }
+
+    if let Some(stems) = stems {
+        std::fs::create_dir_all(&stems.directory)?;
+        graph_builder.group("stems");
+        let names = track_names
+            .iter()
+            .map(|name| name.as_deref().unwrap_or("track"))
+            .chain(std::iter::repeat("bus"));
+        let count = if stems.buses {
+            players.len()
+        } else {
+            sources.len()
+        };
+        info!("writing {} stems to {}", count, stems.directory.display());
+        for (index, (player, name)) in players.iter().zip(names).take(count).enumerate() {
+            let path = stems.directory.join(stem_file_name(index + 1, name));
+            let sink = graph::WavSink::create(
+                &path,
+                output_rate as u32,
+                graph::WavFormat::new(bits, float)?,
+                Layout::Stereo,
+                dither,
+            )?
+            .with_oversampling(oversampling);
+            graph_builder
+                .add_node(sink)
+                .input_from(0, player.output(0))
+                .build();
+        }
+    }
+
+    graph_builder.group("meters");
+    let mut channel_meters = players
+        .iter()
+        .map(|player| add_meter(&mut graph_builder, sample_rate, *player))
+        .collect::<Vec<_>>();
+    // The tracks come first, then the buses
+    let bus_meters = channel_meters.split_off(sources.len());
 
+    // The mixer has an output for every pair of channels of the layout
+    graph_builder.group("master");
     let mixer = players
         .iter()
         .enumerate()
         .fold(
-            graph_builder.add_node(graph::Sum::new(players.len())),
+            graph_builder.add_node(
+                graph::Mixer::new(sample_rate as f64, channels, song.gain).with_outputs(pairs),
+            ),
             |accum, (index, item)| accum.input_from(index, item.output(0)),
         )
         .build();
 
-    let output_gain = graph_builder
-        .add_node(graph::Gain::from_decibels(output_gain))
-        .input_from(0, mixer.output(0))
-        .build();
+    let mut outputs = Vec::with_capacity(pairs);
+    for pair in 0..pairs {
+        // The master effects can only use the instruments as sidechain
+        let master = song.effects.iter().cloned().try_fold(
+            mixer.output(pair),
+            |previous, master_effect| {
+                add_effect(
+                    &mut graph_builder,
+                    sample_rate,
+                    smoothing,
+                    &sources,
+                    previous,
+                    master_effect,
+                )
+                .map(|node| node.output(0))
+            },
+        )?;
+
+        let output_gain = graph_builder
+            .add_node(graph::Gain::from_decibels(output_gain))
+            .input_from(0, master)
+            .build();
+
+        outputs.push(match song.limiter.clone() {
+            None => output_gain,
+            Some(ps) => graph_builder
+                .add_node(graph::EffectNode::new(
+                    effect::limiter::Limiter::with_params(sample_rate as f64, ps),
+                ))
+                .input_from(0, output_gain.output(0))
+                .build(),
+        });
+    }
 
-    let _sink = graph_builder
-        .add_node(graph::SoxSink::new(44100, target).unwrap())
-        .input_from(0, output_gain.output(0))
+    let master_meter = Rc::new(RefCell::new(Meter::with_layout(
+        sample_rate as f64,
+        song.layout,
+    )));
+    outputs
+        .iter()
+        .enumerate()
+        .fold(
+            graph_builder.add_node(graph::MeterNode::with_inputs(master_meter.clone(), pairs)),
+            |accum, (index, output)| accum.input_from(index, output.output(0)),
+        )
         .build();
 
-    // 10 ms buffer at 44100 Hz
-    let buffer_size = 441;
-    let max_samples = sig.samples(last_note_end + Time::int(2), sample_rate) + buffer_size - 1;
+    let rendered = outputs
+        .iter()
+        .map(|output| {
+            let rendered = Rc::new(RefCell::new(PackedSamples::new(Precision::Double)));
+            graph_builder
+                .add_node(graph::Recorder::new(rendered.clone()))
+                .input_from(0, output.output(0))
+                .build();
+            rendered
+        })
+        .collect::<Vec<_>>();
 
     let mut graph = graph_builder
         .build(buffer_size as usize)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
 
-    info!("playing at {} bpm at {} Hz", song.bpm, sample_rate);
+    info!(
+        "playing at {} bpm at {} Hz ({}x oversampling)",
+        song.bpm, output_rate, oversampling
+    );
     info!(
         "total length {} samples ({:.2} seconds)",
         max_samples,
         max_samples as f64 / sample_rate as f64
     );
+    if partial {
+        info!(
+            "rendering from {:.2} to {:.2} seconds",
+            region_start as f64 / output_rate as f64,
+            region_end as f64 / output_rate as f64
+        );
+    }
 
-    for _ in 0..(max_samples / buffer_size) {
-        graph.step();
+    if check {
+        graph.check();
+    }
+    if profile {
+        graph.profile();
     }
+    graph.start_at(graph_start as usize);
+    Ok(Render {
+        graph,
+        output: rendered,
+        remaining: ((region_end * oversampling as i64 - graph_start + buffer_size - 1)
+            / buffer_size) as usize,
+        decimators: if oversampling > 1 {
+            (0..pairs).map(|_| Decimator::new(oversampling)).collect()
+        } else {
+            Vec::new()
+        },
+        sample_rate,
+        sig,
+        output_rate,
+        region: region_start..region_end,
+        measure_samples,
+        position: 0,
+        discard: (region_start - graph_start / oversampling as i64) as usize,
+        length: (region_end - region_start) as usize,
+        normalization,
+        precision,
+        normalized: None,
+        gain: 1.0,
+        block_size: (buffer_size as usize) / oversampling,
+        freeze_cache,
+        recordings,
+        channel_meters,
+        bus_meters,
+        master_meter,
+        mixer,
+        profile_json,
+    })
+}
 
-    Ok(())
+/// A block of a rendered song at the sample rate of the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedBlock {
+    /// Sample time of the first sample in the block, counted from the start of the rendering.
+    pub start: usize,
+    /// A frame with the channels of the layout of the song for every sample.
+    pub frames: Vec<Frame>,
+}
+
+/// A song being rendered, yielding consecutive blocks of the output.
+///
+/// Only a few milliseconds are rendered at a time, unless the output is normalized:
+/// then the whole song is rendered when the first block is requested.
+pub struct Render {
+    graph: graph::Graph,
+    /// Output of the last step of the graph, for every pair of channels
+    output: Vec<Rc<RefCell<PackedSamples>>>,
+    /// Steps of the graph until the end of the song
+    remaining: usize,
+    /// Reduce oversampled output to the sample rate of the output, one for every pair of channels
+    decimators: Vec<Decimator>,
+    /// Sample rate of the graph, including oversampling
+    sample_rate: i64,
+    sig: TimeSig,
+    output_rate: i64,
+    /// Output samples of the whole song from the start until the end of the region
+    region: std::ops::Range<i64>,
+    measure_samples: f64,
+    /// Output samples yielded so far
+    position: usize,
+    /// Output samples rendered before the region, which are dropped
+    discard: usize,
+    /// Output samples left until the end of the region
+    length: usize,
+    /// Applied before the first block, which requires rendering everything
+    normalization: Option<Normalization>,
+    precision: Precision,
+    /// The whole song for every pair of channels, once it was rendered for normalizing
+    normalized: Option<Vec<PackedSamples>>,
+    gain: f64,
+    /// Number of output samples in normalized blocks
+    block_size: usize,
+    freeze_cache: Option<Rc<RefCell<FreezeCache>>>,
+    recordings: Vec<(u64, Rc<RefCell<PackedSamples>>)>,
+    channel_meters: Vec<Rc<RefCell<Meter>>>,
+    bus_meters: Vec<Rc<RefCell<Meter>>>,
+    master_meter: Rc<RefCell<Meter>>,
+    /// Node mixing the tracks and buses, for changing them while playing
+    mixer: graph::NodeId,
+    profile_json: Option<PathBuf>,
+}
+
+impl Render {
+    /// The sample of the whole song that is output first, which is 0 unless rendering
+    /// starts later.
+    pub fn start_frame(&self) -> usize {
+        self.region.start as usize
+    }
+
+    /// The sample of the whole song that is output next.
+    pub fn frame(&self) -> usize {
+        self.region.start as usize + self.position
+    }
+
+    /// The sample of the whole song where the output ends.
+    pub fn end_frame(&self) -> usize {
+        self.region.end as usize
+    }
+
+    /// The measure of the song that is output next, counted in whole notes from 0,
+    /// with the fraction of it that has passed.
+    pub fn measure(&self) -> f64 {
+        let oversampling = self.sample_rate as f64 / self.output_rate as f64;
+        self.frame() as f64 * oversampling / self.measure_samples
+    }
+
+    /// The sample of the output at a time of the song, if it is rendered.
+    pub fn frame_at(&self, time: Time) -> Option<usize> {
+        let sample = self.sig.samples(time, self.output_rate);
+        if self.region.contains(&sample) {
+            Some((sample - self.region.start) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The graph rendering the song, e.g. for describing how it is routed.
+    pub fn graph(&self) -> &graph::Graph {
+        &self.graph
+    }
+
+    /// Levels of the tracks, buses and the output rendered so far.
+    pub fn levels(&self) -> Levels {
+        let measure = |meters: &[Rc<RefCell<Meter>>]| {
+            meters
+                .iter()
+                .map(|meter| meter.borrow().measurement())
+                .collect()
+        };
+        Levels {
+            tracks: measure(&self.channel_meters),
+            buses: measure(&self.bus_meters),
+            master: self.master_meter.borrow().measurement().scaled(self.gain),
+        }
+    }
+
+    /// Peak levels of the tracks, buses and the output rendered most recently.
+    pub fn peaks(&self) -> Peaks {
+        let peaks = |meters: &[Rc<RefCell<Meter>>]| {
+            meters
+                .iter()
+                .map(|meter| meter.borrow().momentary_peak())
+                .collect()
+        };
+        Peaks {
+            tracks: peaks(&self.channel_meters),
+            buses: peaks(&self.bus_meters),
+            master: self.master_meter.borrow().momentary_peak() * self.gain.abs(),
+        }
+    }
+
+    /// Mute or unmute a track from the next block on.
+    /// Only its channel of the mixer is changed: a track muted in the song still reaches
+    /// no bus when it is unmuted.
+    pub fn set_mute(&mut self, track: usize, mute: bool) {
+        let value = if mute { 1.0 } else { 0.0 };
+        self.graph
+            .set_parameter(self.mixer, &format!("mute.{}", track), value);
+    }
+
+    /// Solo a track or stop soloing it from the next block on, see `set_mute`.
+    pub fn set_solo(&mut self, track: usize, solo: bool) {
+        let value = if solo { 1.0 } else { 0.0 };
+        self.graph
+            .set_parameter(self.mixer, &format!("solo.{}", track), value);
+    }
+
+    /// Step the graph, returning the output at the sample rate of the output.
+    fn step(&mut self) -> Option<Vec<Frame>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.graph.step();
+        self.remaining -= 1;
+        let mut pairs = self
+            .output
+            .iter()
+            .map(|output| {
+                let mut output = output.borrow_mut();
+                let samples = output.iter().collect::<Vec<_>>();
+                output.clear();
+                samples
+            })
+            .collect::<Vec<_>>();
+        if self.remaining == 0 {
+            self.complete();
+        }
+        for (samples, decimator) in pairs.iter_mut().zip(self.decimators.iter_mut()) {
+            let mut decimated = Vec::with_capacity(samples.len() / decimator.factor());
+            decimator.process(samples, &mut decimated);
+            *samples = decimated;
+        }
+        let mut frames = interleave(pairs.iter().map(|samples| samples.iter().copied()));
+        let skipped = self.discard.min(frames.len());
+        self.discard -= skipped;
+        frames.drain(..skipped);
+        frames.truncate(self.length);
+        self.length -= frames.len();
+        Some(frames)
+    }
+
+    /// Report the problems found while checking and the time spent while profiling,
+    /// and keep the frozen tracks.
+    fn complete(&mut self) {
+        for problem in self.graph.problems() {
+            warn!(
+                "{:?} at {:.3} s (measure {:.2}): output {} of node {:?} {} produced {} broken samples, first {}",
+                problem.kind,
+                problem.time as f64 / self.sample_rate as f64,
+                problem.time as f64 / self.measure_samples,
+                problem.output,
+                problem.node,
+                problem.name,
+                problem.count,
+                problem.value
+            );
+        }
+        if let Some(profile) = self.graph.profiled() {
+            info!("time spent rendering:\n{}", profile);
+            if let Some(path) = self.profile_json.take() {
+                if let Err(err) = std::fs::write(&path, profile.to_json()) {
+                    warn!("could not write the profile to {}: {}", path.display(), err);
+                }
+            }
+        }
+        if let Some(cache) = self.freeze_cache.as_ref() {
+            for (key, samples) in self.recordings.drain(..) {
+                cache
+                    .borrow_mut()
+                    .insert(key, samples.borrow().iter().collect());
+            }
+        }
+    }
+}
+
+impl Iterator for Render {
+    type Item = RenderedBlock;
+
+    fn next(&mut self) -> Option<RenderedBlock> {
+        // Normalizing requires knowing the levels of the whole song before yielding anything
+        if let Some(normalization) = self.normalization.take() {
+            let mut normalized = (0..self.output.len())
+                .map(|_| PackedSamples::new(self.precision))
+                .collect::<Vec<_>>();
+            while let Some(frames) = self.step() {
+                for (pair, packed) in normalized.iter_mut().enumerate() {
+                    let samples = frames
+                        .iter()
+                        .map(|frame| frame.pair(pair))
+                        .collect::<Vec<_>>();
+                    packed.extend_from_slice(&samples);
+                }
+            }
+            self.gain = normalization.gain(&self.master_meter.borrow().measurement());
+            info!(
+                "normalizing to {:?} with a gain of {:.1} dB",
+                normalization,
+                syntxt_core::util::to_decibels(self.gain * self.gain)
+            );
+            self.normalized = Some(normalized);
+        }
+        let frames = match self.normalized.as_ref() {
+            // Nothing is output while rendering the pre-roll
+            None => loop {
+                let frames = self.step()?;
+                if !frames.is_empty() {
+                    break frames;
+                }
+            },
+            Some(normalized) => {
+                let gain = self.gain;
+                let range = self.position..self.position + self.block_size;
+                let frames = interleave(
+                    normalized
+                        .iter()
+                        .map(|packed| packed.range(range.clone()).map(|sample| sample * gain)),
+                );
+                if frames.is_empty() {
+                    return None;
+                }
+                frames
+            }
+        };
+        let start = self.position;
+        self.position += frames.len();
+        Some(RenderedBlock { start, frames })
+    }
+}
+
+/// Frames of the samples of the pairs of channels, as long as the shortest pair.
+fn interleave<I: Iterator<Item = Stereo<f64>>>(pairs: impl Iterator<Item = I>) -> Vec<Frame> {
+    let mut pairs = pairs.collect::<Vec<_>>();
+    let mut frames = Vec::new();
+    let mut samples = [Stereo::mono(0.0); MAX_CHANNELS / 2];
+    'frames: loop {
+        for (sample, pair) in samples.iter_mut().zip(pairs.iter_mut()) {
+            match pair.next() {
+                Some(next) => *sample = next,
+                None => break 'frames,
+            }
+        }
+        frames.push(Frame::from_pairs(&samples[..pairs.len()]));
+    }
+    frames
+}
+
+/// The group of the nodes of a track when profiling, e.g. `track 0 (Lead)`.
+fn track_group(index: usize, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("track {} ({})", index, name),
+        None => format!("track {}", index),
+    }
+}
+
+/// File name of a stem, e.g. `02-lead_guitar.wav`.
+/// The number keeps the order of the song and tells tracks of the same name apart.
+fn stem_file_name(number: usize, name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{:02}-{}.wav", number, name)
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case(extension),
+        None => false,
+    }
+}
+
+/// Register at the JACK server, returning the sink and the sample rate of the server.
+#[cfg(feature = "jack")]
+fn connect_jack() -> io::Result<(Sink, i64)> {
+    let sink = graph::JackSink::connect("syntxt")?;
+    let sample_rate = sink.sample_rate() as i64;
+    Ok((Sink::Jack(sink), sample_rate))
+}
+
+#[cfg(not(feature = "jack"))]
+fn connect_jack() -> io::Result<(Sink, i64)> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "JACK is not supported, build with the `jack` feature",
+    ))
+}
+
+/// Where the rendered song goes.
+enum Sink {
+    Sox(graph::SoxSink),
+    Wav(graph::WavSink),
+    Aiff(graph::AiffSink),
+    Opus(graph::OpusSink),
+    #[cfg(feature = "jack")]
+    Jack(graph::JackSink),
+}
+
+impl Sink {
+    fn write(&mut self, frames: &[Frame]) {
+        match self {
+            Sink::Sox(sink) => sink.write_frames(frames),
+            Sink::Wav(sink) => sink.write_frames(frames),
+            Sink::Aiff(sink) => sink.write_frames(frames),
+            Sink::Opus(sink) => sink.write_frames(frames),
+            // Only the front speakers are connected
+            #[cfg(feature = "jack")]
+            Sink::Jack(sink) => {
+                sink.write(&frames.iter().map(|frame| frame.pair(0)).collect::<Vec<_>>())
+            }
+        }
+    }
+
+    /// Complete the output. Files written here are otherwise completed when the sink is dropped.
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Sox(_) => Ok(()),
+            Sink::Wav(sink) => sink.finish(),
+            Sink::Aiff(sink) => sink.finish(),
+            Sink::Opus(sink) => sink.finish(),
+            #[cfg(feature = "jack")]
+            Sink::Jack(sink) => sink.finish(),
+        }
+    }
+}
+
+/// How a frozen track is played.
+enum Frozen {
+    /// From a previous rendering.
+    Cached(Rc<Vec<Stereo<f64>>>),
+    /// Rendered as usual, then stored under the key.
+    Recording(u64),
+}
+
+/// Measure the level of the output of a node.
+fn add_meter(
+    graph_builder: &mut graph::GraphBuilder,
+    sample_rate: i64,
+    node: graph::NodeId,
+) -> Rc<RefCell<Meter>> {
+    let meter = Rc::new(RefCell::new(Meter::new(sample_rate as f64)));
+    graph_builder
+        .add_node(graph::MeterNode::new(meter.clone()))
+        .input_from(0, node.output(0))
+        .build();
+    meter
+}
+
+/// Add a node playing notes and pitch bends on an instrument.
+/// Parameter changes of the instrument are spread over `smoothing` seconds.
+fn add_instrument<I: instrument::Instrument + 'static>(
+    graph_builder: &mut graph::GraphBuilder,
+    sample_rate: i64,
+    sig: TimeSig,
+    smoothing: f64,
+    instrument: I,
+    notes: Vec<PlayedNote>,
+    bends: Vec<PitchBend>,
+) -> graph::NodeId {
+    graph_builder
+        .add_node(
+            graph::InstrumentSource::new(
+                sample_rate,
+                sig,
+                Smooth::new(instrument, sample_rate as f64, smoothing),
+                notes,
+            )
+            .with_bends(sample_rate, sig, bends),
+        )
+        .build()
+}
+
+/// Add a node applying an effect to the output of the previous node,
+/// where `sources` are the instruments of all tracks, available as sidechain.
+/// Parameter changes of the effect are spread over `smoothing` seconds.
+fn add_effect(
+    graph_builder: &mut graph::GraphBuilder,
+    sample_rate: i64,
+    smoothing: f64,
+    sources: &[graph::NodeId],
+    previous: graph::OutputRef,
+    effect: Effect,
+) -> io::Result<graph::NodeId> {
+    let rate = sample_rate as f64;
+    let effect: Box<dyn effect::Effect> = match effect {
+        Effect::Reverb(ps) => Box::new(effect::reverb::Reverb::with_params(rate, ps)),
+        Effect::Chorus(ps) => Box::new(effect::chorus::Chorus::with_params(rate, ps)),
+        Effect::Equalizer(bands) => Box::new(filter::Equalizer::new(rate, &bands)),
+        Effect::Distortion(ps) => Box::new(effect::distortion::Distortion::with_params(rate, ps)),
+        Effect::ConvolutionReverb(ps) => Box::new(
+            effect::convolution::ConvolutionReverb::with_params(rate, ps),
+        ),
+        Effect::RingModulator(ps) => {
+            Box::new(effect::ring_mod::RingModulator::with_params(rate, ps))
+        }
+        Effect::MultibandCompressor(ps) => Box::new(
+            effect::multiband::MultibandCompressor::with_params(rate, ps),
+        ),
+        Effect::StereoWidth(ps) => {
+            Box::new(effect::stereo_width::StereoWidth::with_params(rate, ps))
+        }
+        Effect::Gate(ps) => Box::new(effect::gate::Gate::with_params(rate, ps)),
+        Effect::Tremolo(ps) => Box::new(effect::tremolo::Tremolo::with_params(rate, ps)),
+        Effect::AutoPan(ps) => Box::new(effect::auto_pan::AutoPan::with_params(rate, ps)),
+        Effect::Plugin(ps) => Box::new(load_plugin(sample_rate, &ps)?),
+        Effect::Compressor { params, sidechain } => {
+            let compressor = Smooth::new(
+                effect::compressor::Compressor::with_params(rate, params),
+                rate,
+                smoothing,
+            );
+            return Ok(match sidechain.and_then(|index| sources.get(index)) {
+                None => graph_builder
+                    .add_node(graph::CompressorNode::new(compressor))
+                    .input_from(0, previous)
+                    .build(),
+                Some(detector) => graph_builder
+                    .add_node(graph::CompressorNode::with_sidechain(compressor))
+                    .input_from(0, previous)
+                    .input_from(1, detector.output(0))
+                    .build(),
+            });
+        }
+    };
+    Ok(graph_builder
+        .add_node(graph::EffectNode::new(Smooth::new(effect, rate, smoothing)))
+        .input_from(0, previous)
+        .build())
+}
+
+/// Start a plugin at the sample rate of the graph.
+fn load_plugin(sample_rate: i64, params: &plugin::Params) -> io::Result<plugin::Plugin> {
+    plugin::Plugin::load(sample_rate as f64, params)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{play, render, stem_file_name, Options, Stems};
+    use crate::meter::Normalization;
+    use crate::song::{Song, Time};
+    use crate::wave::Precision;
+
+    const SONG: &str = "Song { bpm: 240 Track { Sequence { notes: [[ c4 e4 ]] } } }";
+
+    #[test]
+    fn blocks() {
+        let song = Song::from_source(SONG).unwrap();
+        let blocks = render(song, &Options::default())
+            .unwrap()
+            .collect::<Vec<_>>();
+        // Two quarter notes at 240 bpm, followed by two measures of silence
+        assert_eq!(blocks.len(), 250);
+        let mut position = 0;
+        for block in blocks.iter() {
+            assert_eq!(block.start, position);
+            assert_eq!(block.frames.len(), 441);
+            position += block.frames.len();
+        }
+        assert!(blocks[0]
+            .frames
+            .iter()
+            .any(|frame| frame.values()[0] != 0.0));
+
+        // Oversampling renders more, but yields the same number of samples
+        let song = Song::from_source(SONG).unwrap();
+        let options = Options {
+            oversampling: 2,
+            ..Options::default()
+        };
+        let oversampled = render(song, &options).unwrap();
+        assert_eq!(
+            oversampled.map(|block| block.frames.len()).sum::<usize>(),
+            position
+        );
+
+        // Factors the filters do not support are rejected instead of rounded
+        for oversampling in [0, 3, 8].iter().copied() {
+            let song = Song::from_source(SONG).unwrap();
+            let options = Options {
+                oversampling,
+                ..Options::default()
+            };
+            assert!(render(song, &options).is_err());
+        }
+    }
+
+    #[test]
+    fn missing_clip() {
+        let song = Song::from_source(
+            r#"Song { Track { Clip { file: "/nonexistent/vocals.wav" } Sequence { notes: [[ c4 ]] } } }"#,
+        )
+        .unwrap();
+        assert!(render(song, &Options::default()).is_err());
+    }
+
+    #[test]
+    fn normalized_blocks() {
+        let song = Song::from_source(SONG).unwrap();
+        let options = Options {
+            normalization: Some(Normalization::Peak(-6.0)),
+            ..Options::default()
+        };
+        let mut rendering = render(song, &options).unwrap();
+        let peak = rendering
+            .by_ref()
+            .flat_map(|block| block.frames)
+            .map(|frame| frame.pair(0))
+            .map(|s| s.left.abs().max(s.right.abs()))
+            .fold(0.0, f64::max);
+        assert!((peak - 0.501187).abs() < 1e-6, "{}", peak);
+        assert!((rendering.levels().master.peak_decibels() + 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn precision() {
+        let rendered = |precision| {
+            let song = Song::from_source(SONG).unwrap();
+            let options = Options {
+                normalization: Some(Normalization::Peak(-6.0)),
+                precision,
+                ..Options::default()
+            };
+            render(song, &options)
+                .unwrap()
+                .flat_map(|block| block.frames)
+                .map(|frame| frame.pair(0))
+                .collect::<Vec<_>>()
+        };
+        let double = rendered(Precision::Double);
+        let single = rendered(Precision::Single);
+        assert_eq!(double.len(), single.len());
+        // Single precision only rounds the rendered samples
+        let difference = double
+            .iter()
+            .zip(single.iter())
+            .map(|(d, s)| (d.left - s.left).abs().max((d.right - s.right).abs()))
+            .fold(0.0, f64::max);
+        assert!(difference > 0.0);
+        assert!(difference < 1e-7, "{}", difference);
+    }
+
+    #[test]
+    fn surround() {
+        let song = Song::from_source(
+            r#"Song { bpm: 240 layout: "5.1" Track { speakers: "rear" pan: -1 Sequence { notes: [[ c4 ]] } } }"#,
+        )
+        .unwrap();
+        let mut rendering = render(song, &Options::default()).unwrap();
+        let frames = rendering
+            .by_ref()
+            .flat_map(|block| block.frames)
+            .collect::<Vec<_>>();
+        assert!(frames.iter().all(|frame| frame.channels() == 6));
+        // Only the left rear speaker plays
+        let peaks = frames.iter().fold(vec![0.0; 6], |peaks, frame| {
+            peaks
+                .iter()
+                .zip(frame.values())
+                .map(|(peak, value)| value.abs().max(*peak))
+                .collect()
+        });
+        assert!(peaks[4] > 0.1);
+        assert_eq!(peaks.iter().filter(|peak| **peak > 0.0).count(), 1);
+        // The surround channels count more than the front ones
+        let rear = rendering.levels().master.loudness;
+        let song =
+            Song::from_source("Song { bpm: 240 Track { pan: -1 Sequence { notes: [[ c4 ]] } } }")
+                .unwrap();
+        let mut rendering = render(song, &Options::default()).unwrap();
+        rendering.by_ref().for_each(drop);
+        let front = rendering.levels().master.loudness;
+        assert!((rear - front - 1.5).abs() < 0.1, "{} {}", rear, front);
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(stem_file_name(2, "lead guitar"), "02-lead_guitar.wav");
+        assert_eq!(stem_file_name(12, "../bass"), "12-___bass.wav");
+
+        let directory = std::env::temp_dir().join(format!("syntxt-stems-{}", std::process::id()));
+        let song = Song::from_source(
+            r#"Song { bpm: 240 Track { name: "lead" Sequence { notes: [[ c4 ]] } } Track { } }"#,
+        )
+        .unwrap();
+        let options = Options {
+            bits: Some(16),
+            stems: Some(Stems {
+                directory: directory.clone(),
+                buses: false,
+            }),
+            ..Options::default()
+        };
+        let length = render(song, &options)
+            .unwrap()
+            .map(|block| block.frames.len())
+            .sum::<usize>();
+        let mut files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, vec!["01-lead.wav", "02-track.wav"]);
+        // The header is followed by two channels of 16 bit samples
+        let bytes = std::fs::read(directory.join("01-lead.wav")).unwrap();
+        assert_eq!(bytes.len(), 44 + length * 4);
+        assert!(bytes[44..].iter().any(|b| *b != 0));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn regions() {
+        let source = "Song { bpm: 240 Track { Sequence { notes: [[ c4 e4++++ r+++ g4+ c5 ]] } } }";
+        let render_samples = |options: &Options| {
+            let song = Song::from_source(source).unwrap();
+            render(song, options)
+                .unwrap()
+                .flat_map(|block| block.frames)
+                .map(|frame| frame.pair(0))
+                .collect::<Vec<_>>()
+        };
+        let whole = render_samples(&Options::default());
+        let region = |from: Time, to: Time| {
+            render_samples(&Options {
+                from: Some(from),
+                to: Some(to),
+                ..Options::default()
+            })
+        };
+
+        // Within the pre-roll, the output is exactly the same
+        let part = region(Time::new(1, 4), Time::new(3, 4));
+        assert_eq!(part[..], whole[11025..33075]);
+
+        // A note from before the pre-roll is still heard
+        let part = region(Time::new(3, 1), Time::new(4, 1));
+        assert_eq!(part.len(), 44100);
+        assert!(part[..100].iter().any(|s| s.left != 0.0));
+
+        // Later notes are in place
+        let part = region(Time::new(6, 1), Time::new(7, 1));
+        let difference = part
+            .iter()
+            .zip(whole[6 * 44100..].iter())
+            .map(|(a, b)| (a.left - b.left).abs())
+            .fold(0.0, f64::max);
+        assert!(difference < 1e-3, "{}", difference);
+
+        // Times of the song are found in the region
+        let song = Song::from_source(source).unwrap();
+        let options = Options {
+            from: Some(Time::new(1, 4)),
+            ..Options::default()
+        };
+        let rendering = render(song, &options).unwrap();
+        assert_eq!(rendering.start_frame(), 11025);
+        assert_eq!(rendering.frame_at(Time::new(1, 2)), Some(11025));
+        assert_eq!(rendering.frame_at(Time::new(1, 8)), None);
+
+        let song = Song::from_source(source).unwrap();
+        let empty = Options {
+            from: Some(Time::new(1, 2)),
+            to: Some(Time::new(1, 4)),
+            ..Options::default()
+        };
+        assert!(render(song, &empty).is_err());
+    }
+
+    #[test]
+    fn broadcast() {
+        let path = std::env::temp_dir().join(format!("syntxt-bwf-{}.wav", std::process::id()));
+        let song = Song::from_source(
+            r#"Song {
+    bpm: 240
+    Track { Sequence { notes: [[ c4 e4 ]] } }
+    Lyrics { Line { start: 1/4 text: "Verse" } Line { start: 9 text: "Too late" } }
+}"#,
+        )
+        .unwrap();
+        let options = Options {
+            bits: Some(16),
+            broadcast: true,
+            ..Options::default()
+        };
+        play(song, Some(&path), &options).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let find = |id: &[u8]| bytes.windows(4).position(|window| window == id).unwrap();
+        let bext = find(b"bext");
+        assert_eq!(&bytes[bext + 8..bext + 12], b"\0\0\0\0");
+        // Only the marker within the song becomes a cue point
+        let cue = find(b"cue ");
+        assert_eq!(&bytes[cue + 8..cue + 12], &[1, 0, 0, 0]);
+        assert_eq!(&bytes[cue + 16..cue + 20], &11025u32.to_le_bytes());
+        assert_eq!(&bytes[bytes.len() - 6..], b"Verse\0");
+    }
 }
@@ -0,0 +1,862 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hosting of external instruments and effects in the CLAP plugin format.
+//!
+//! A plugin is a shared library loaded at runtime. The first plugin in the library is
+//! instantiated, activated at the sample rate of the song and then processes the audio in
+//! stereo blocks of at most `MAX_BLOCK_SIZE` samples, receiving notes and parameter changes
+//! as events at the sample where they happen.
+//!
+//! VST3 plugins are not supported, since their interface is made of C++ classes.
+//! In the browser, where no libraries can be loaded, every plugin fails to load.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::path::PathBuf;
+use std::ptr;
+
+#[cfg(not(target_arch = "wasm32"))]
+use libloading::{Error as LoadError, Library};
+use log::warn;
+use snafu::{ensure, ResultExt, Snafu};
+use syntxt_core::note::{Note, Velocity};
+
+use crate::effect::Effect;
+use crate::instrument::Instrument;
+use crate::process::MAX_BLOCK_SIZE;
+use crate::tuning::Tuning;
+use crate::wave::Stereo;
+
+pub mod clap;
+
+#[cfg(target_arch = "wasm32")]
+type Library = ();
+
+/// The reason for every plugin failing to load in the browser.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
+pub struct LoadError;
+
+#[cfg(target_arch = "wasm32")]
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "plugins cannot be loaded in the browser")
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl std::error::Error for LoadError {}
+
+/// Parameters of a plugin.
+#[derive(Debug, Clone)]
+pub struct Params {
+    /// Path of the `.clap` library containing the plugin.
+    pub path: PathBuf,
+    /// Initial values of parameters of the plugin, by the names it gives them.
+    /// The values are in the units of the plugin, e.g. Hz for a cutoff frequency.
+    pub parameters: Vec<(String, f64)>,
+    /// Pitches of the notes played on the plugin, sent as detuning of the notes
+    /// where they differ from concert pitch.
+    pub tuning: Tuning,
+}
+
+impl Params {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            parameters: Vec::new(),
+            tuning: Tuning::default(),
+        }
+    }
+}
+
+/// Possible errors when loading a plugin.
+#[derive(Debug, Snafu)]
+pub enum PluginError {
+    #[snafu(display("Could not load {}: {}", path.display(), source))]
+    Load { path: PathBuf, source: LoadError },
+    #[snafu(display("{} is not a compatible CLAP plugin", path.display()))]
+    Incompatible { path: PathBuf },
+    #[snafu(display("{} contains no plugin", path.display()))]
+    Empty { path: PathBuf },
+    #[snafu(display("The plugin in {} failed to {}", path.display(), step))]
+    Failed { path: PathBuf, step: &'static str },
+}
+
+/// A running instance of a plugin, which is played as instrument or used as effect.
+pub struct Plugin {
+    path: PathBuf,
+    entry: *const clap::PluginEntry,
+    /// Passed to the plugin, which may keep referring to it until it is destroyed.
+    host: Box<clap::Host>,
+    plugin: *const clap::Plugin,
+    active: bool,
+    processing: bool,
+    /// Names and ids of the parameters of the plugin.
+    parameters: Vec<(String, u32)>,
+    tuning: Tuning,
+    /// Events not yet delivered, with their time counting from the next call.
+    pending: Vec<Event>,
+    /// Events delivered with the current block.
+    block: Vec<Event>,
+    next_note_id: i32,
+    /// Number of samples processed so far.
+    steady_time: i64,
+    /// Left and right channel passed into and out of the plugin.
+    inputs: [Vec<f32>; 2],
+    outputs: [Vec<f32>; 2],
+    /// Whether the plugin already reported an error while processing.
+    failed: bool,
+    /// Unloaded only after the plugin is gone, which happens when dropping the fields.
+    _library: Option<Library>,
+}
+
+/// A note playing on a plugin.
+#[derive(Debug, Clone, Copy)]
+pub struct PluginNote {
+    id: i32,
+    key: i16,
+}
+
+impl Plugin {
+    /// Load the library at the path of the parameters and start its first plugin.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(sample_rate: f64, params: &Params) -> Result<Plugin, PluginError> {
+        let path = &params.path;
+        // Loading runs the initialization code of the library, which is trusted like the song
+        let library = unsafe { Library::new(path) }.context(Load { path: path.clone() })?;
+        let entry = unsafe { library.get::<*const clap::PluginEntry>(clap::ENTRY_SYMBOL) }
+            .context(Load { path: path.clone() })
+            .map(|symbol| *symbol)?;
+        unsafe { Self::from_entry(Some(library), entry, sample_rate, params) }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(_sample_rate: f64, params: &Params) -> Result<Plugin, PluginError> {
+        Err(LoadError).context(Load {
+            path: params.path.clone(),
+        })
+    }
+
+    /// Start the first plugin of an entry, which must stay valid as long as the library.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    unsafe fn from_entry(
+        library: Option<Library>,
+        entry: *const clap::PluginEntry,
+        sample_rate: f64,
+        params: &Params,
+    ) -> Result<Plugin, PluginError> {
+        let path = params.path.clone();
+        ensure!(
+            !entry.is_null() && (*entry).clap_version.is_compatible(),
+            Incompatible { path }
+        );
+        let plugin_path = CString::new(path.to_string_lossy().into_owned()).unwrap_or_default();
+        ensure!(
+            ((*entry).init)(plugin_path.as_ptr()),
+            Failed {
+                path,
+                step: "initialize"
+            }
+        );
+
+        // From here on, dropping the plugin cleans up whatever was started
+        let mut plugin = Plugin {
+            path,
+            entry,
+            host: Box::new(clap::Host {
+                clap_version: clap::VERSION,
+                host_data: ptr::null_mut(),
+                name: b"syn.txt\0".as_ptr() as *const c_char,
+                vendor: b"syn.txt\0".as_ptr() as *const c_char,
+                url: b"https://github.com/fatho/syn-txt\0".as_ptr() as *const c_char,
+                version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+                get_extension: host_get_extension,
+                request_restart: host_request,
+                request_process: host_request,
+                request_callback: host_request,
+            }),
+            plugin: ptr::null(),
+            active: false,
+            processing: false,
+            parameters: Vec::new(),
+            tuning: params.tuning.clone(),
+            pending: Vec::new(),
+            block: Vec::new(),
+            next_note_id: 0,
+            steady_time: 0,
+            inputs: [vec![0.0; MAX_BLOCK_SIZE], vec![0.0; MAX_BLOCK_SIZE]],
+            outputs: [vec![0.0; MAX_BLOCK_SIZE], vec![0.0; MAX_BLOCK_SIZE]],
+            failed: false,
+            _library: library,
+        };
+
+        let factory = ((*entry).get_factory)(clap::PLUGIN_FACTORY_ID.as_ptr() as *const c_char)
+            as *const clap::PluginFactory;
+        ensure!(
+            !factory.is_null(),
+            Incompatible {
+                path: plugin.path.clone()
+            }
+        );
+        let descriptor = if ((*factory).get_plugin_count)(factory) > 0 {
+            ((*factory).get_plugin_descriptor)(factory, 0)
+        } else {
+            ptr::null()
+        };
+        ensure!(
+            !descriptor.is_null(),
+            Empty {
+                path: plugin.path.clone()
+            }
+        );
+        plugin.plugin = ((*factory).create_plugin)(factory, &*plugin.host, (*descriptor).id);
+        let failed = |plugin: &Plugin, step| Failed {
+            path: plugin.path.clone(),
+            step,
+        };
+        ensure!(!plugin.plugin.is_null(), failed(&plugin, "instantiate"));
+        let instance = &*plugin.plugin;
+        ensure!(
+            (instance.init)(plugin.plugin),
+            failed(&plugin, "initialize")
+        );
+        plugin.parameters = parameters(plugin.plugin);
+        plugin.active = (instance.activate)(plugin.plugin, sample_rate, 1, MAX_BLOCK_SIZE as u32);
+        ensure!(plugin.active, failed(&plugin, "activate"));
+        plugin.processing = (instance.start_processing)(plugin.plugin);
+        ensure!(plugin.processing, failed(&plugin, "start processing"));
+
+        for (name, value) in params.parameters.iter() {
+            if !plugin.set_value(name, *value) {
+                warn!("{} has no parameter {}", plugin.path.display(), name);
+            }
+        }
+        Ok(plugin)
+    }
+
+    /// Change a parameter by name with the next block. Returns whether the plugin has it.
+    fn set_value(&mut self, name: &str, value: f64) -> bool {
+        let id = match self.parameters.iter().find(|(other, _)| other == name) {
+            Some((_, id)) => *id,
+            None => return false,
+        };
+        self.pending.push(Event::Param(clap::EventParamValue {
+            header: header::<clap::EventParamValue>(clap::EVENT_PARAM_VALUE, 0),
+            param_id: id,
+            cookie: ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value,
+        }));
+        true
+    }
+
+    /// Deviation of the tuning from concert pitch for a note, in cents.
+    fn detuning(&self, note: Note) -> f64 {
+        let concert = 440.0 * 2.0f64.powf((note.index() - 69) as f64 / 12.0);
+        1200.0 * (self.tuning.frequency(note) / concert).log2()
+    }
+
+    /// Run the plugin over the samples, either adding its output to them
+    /// or, for effects, replacing them with it.
+    fn run(&mut self, samples: &mut [Stereo<f64>], effect: bool) {
+        for chunk in samples.chunks_mut(MAX_BLOCK_SIZE) {
+            let length = chunk.len();
+            for (index, sample) in chunk.iter().enumerate() {
+                let input = if effect { *sample } else { Stereo::mono(0.0) };
+                self.inputs[0][index] = input.left as f32;
+                self.inputs[1][index] = input.right as f32;
+            }
+            for output in self.outputs.iter_mut() {
+                output.iter_mut().for_each(|x| *x = 0.0);
+            }
+
+            // The plugin expects the events of the block ordered by time
+            self.pending.sort_by_key(|event| event.header().time);
+            let due = self
+                .pending
+                .iter()
+                .position(|event| event.header().time as usize >= length)
+                .unwrap_or(self.pending.len());
+            self.block.clear();
+            self.block.extend(self.pending.drain(..due));
+            for event in self.pending.iter_mut() {
+                event.header_mut().time -= length as u32;
+            }
+
+            let mut input_channels = [self.inputs[0].as_mut_ptr(), self.inputs[1].as_mut_ptr()];
+            let mut output_channels = [self.outputs[0].as_mut_ptr(), self.outputs[1].as_mut_ptr()];
+            let input = clap::AudioBuffer {
+                data32: input_channels.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let mut output = clap::AudioBuffer {
+                data32: output_channels.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let in_events = clap::InputEvents {
+                ctx: &self.block as *const Vec<Event> as *mut c_void,
+                size: events_size,
+                get: events_get,
+            };
+            let out_events = clap::OutputEvents {
+                ctx: ptr::null_mut(),
+                try_push: events_discard,
+            };
+            let process = clap::Process {
+                steady_time: self.steady_time,
+                frames_count: length as u32,
+                transport: ptr::null(),
+                audio_inputs: &input,
+                audio_outputs: &mut output,
+                audio_inputs_count: 1,
+                audio_outputs_count: 1,
+                in_events: &in_events,
+                out_events: &out_events,
+            };
+            let status = unsafe { ((*self.plugin).process)(self.plugin, &process) };
+            self.steady_time += length as i64;
+
+            if status == clap::PROCESS_ERROR {
+                if !self.failed {
+                    warn!("{} failed to process audio", self.path.display());
+                    self.failed = true;
+                }
+                continue;
+            }
+            for (index, sample) in chunk.iter_mut().enumerate() {
+                let output =
+                    Stereo::new(self.outputs[0][index] as f64, self.outputs[1][index] as f64);
+                if effect {
+                    *sample = output;
+                } else {
+                    *sample += output;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.plugin.is_null() {
+                let instance = &*self.plugin;
+                if self.processing {
+                    (instance.stop_processing)(self.plugin);
+                }
+                if self.active {
+                    (instance.deactivate)(self.plugin);
+                }
+                (instance.destroy)(self.plugin);
+            }
+            ((*self.entry).deinit)();
+        }
+    }
+}
+
+impl Instrument for Plugin {
+    type PlayHandle = PluginNote;
+
+    fn play_note(&mut self, sample_delay: usize, note: Note, velocity: Velocity) -> PluginNote {
+        self.play_detuned_note(sample_delay, note, velocity, 0.0)
+    }
+
+    fn play_detuned_note(
+        &mut self,
+        sample_delay: usize,
+        note: Note,
+        velocity: Velocity,
+        cents: f64,
+    ) -> PluginNote {
+        let handle = PluginNote {
+            id: self.next_note_id,
+            key: note.to_midi() as i16,
+        };
+        self.next_note_id = (self.next_note_id + 1) % i32::MAX;
+        self.pending.push(Event::Note(clap::EventNote {
+            header: header::<clap::EventNote>(clap::EVENT_NOTE_ON, sample_delay),
+            note_id: handle.id,
+            port_index: 0,
+            channel: 0,
+            key: handle.key,
+            velocity: velocity.as_f64(),
+        }));
+        let cents = cents + self.detuning(note);
+        if cents.abs() > 1e-6 {
+            self.pending
+                .push(Event::Expression(clap::EventNoteExpression {
+                    header: header::<clap::EventNoteExpression>(
+                        clap::EVENT_NOTE_EXPRESSION,
+                        sample_delay,
+                    ),
+                    expression_id: clap::NOTE_EXPRESSION_TUNING,
+                    note_id: handle.id,
+                    port_index: 0,
+                    channel: 0,
+                    key: handle.key,
+                    value: cents / 100.0,
+                }));
+        }
+        handle
+    }
+
+    fn release_note(&mut self, sample_delay: usize, handle: PluginNote) {
+        self.pending.push(Event::Note(clap::EventNote {
+            header: header::<clap::EventNote>(clap::EVENT_NOTE_OFF, sample_delay),
+            note_id: handle.id,
+            port_index: 0,
+            channel: 0,
+            key: handle.key,
+            velocity: 0.0,
+        }));
+    }
+
+    fn pitch_bend(&mut self, sample_delay: usize, amount: f64) {
+        // MIDI pitch bends are 14 bit values centered around 8192
+        let value = ((amount + 1.0) * 8192.0).round().clamp(0.0, 16383.0) as u16;
+        self.pending.push(Event::Midi(clap::EventMidi {
+            header: header::<clap::EventMidi>(clap::EVENT_MIDI, sample_delay),
+            port_index: 0,
+            data: [0xe0, (value & 0x7f) as u8, (value >> 7) as u8],
+        }));
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.set_value(name, value)
+    }
+
+    fn fill_buffer(&mut self, output: &mut [Stereo<f64>]) {
+        self.run(output, false)
+    }
+}
+
+impl Effect for Plugin {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        self.run(samples, true)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.set_value(name, value)
+    }
+}
+
+/// The events this host sends to plugins.
+#[derive(Clone, Copy)]
+enum Event {
+    Note(clap::EventNote),
+    Expression(clap::EventNoteExpression),
+    Param(clap::EventParamValue),
+    Midi(clap::EventMidi),
+}
+
+impl Event {
+    fn header(&self) -> &clap::EventHeader {
+        match self {
+            Event::Note(event) => &event.header,
+            Event::Expression(event) => &event.header,
+            Event::Param(event) => &event.header,
+            Event::Midi(event) => &event.header,
+        }
+    }
+
+    fn header_mut(&mut self) -> &mut clap::EventHeader {
+        match self {
+            Event::Note(event) => &mut event.header,
+            Event::Expression(event) => &mut event.header,
+            Event::Param(event) => &mut event.header,
+            Event::Midi(event) => &mut event.header,
+        }
+    }
+}
+
+fn header<T>(type_: u16, sample_delay: usize) -> clap::EventHeader {
+    clap::EventHeader {
+        size: std::mem::size_of::<T>() as u32,
+        time: sample_delay as u32,
+        space_id: clap::CORE_EVENT_SPACE_ID,
+        type_,
+        flags: 0,
+    }
+}
+
+/// Names and ids of the parameters of a plugin, if it has any.
+unsafe fn parameters(plugin: *const clap::Plugin) -> Vec<(String, u32)> {
+    let params = ((*plugin).get_extension)(plugin, clap::EXT_PARAMS.as_ptr() as *const c_char)
+        as *const clap::PluginParams;
+    if params.is_null() {
+        return Vec::new();
+    }
+    (0..((*params).count)(plugin))
+        .filter_map(|index| {
+            let mut info = clap::ParamInfo {
+                id: 0,
+                flags: 0,
+                cookie: ptr::null_mut(),
+                name: [0; clap::NAME_SIZE],
+                module: [0; clap::PATH_SIZE],
+                min_value: 0.0,
+                max_value: 0.0,
+                default_value: 0.0,
+            };
+            if !((*params).get_info)(plugin, index, &mut info) {
+                return None;
+            }
+            // The name is not necessarily terminated when it fills the whole array
+            info.name[clap::NAME_SIZE - 1] = 0;
+            let name = CStr::from_ptr(info.name.as_ptr())
+                .to_string_lossy()
+                .into_owned();
+            Some((name, info.id))
+        })
+        .collect()
+}
+
+unsafe extern "C" fn host_get_extension(
+    _host: *const clap::Host,
+    _extension_id: *const c_char,
+) -> *const c_void {
+    ptr::null()
+}
+
+/// Requests for restarting or calling back are ignored,
+/// since the plugins are only used for rendering.
+unsafe extern "C" fn host_request(_host: *const clap::Host) {}
+
+unsafe extern "C" fn events_size(list: *const clap::InputEvents) -> u32 {
+    let events = &*((*list).ctx as *const Vec<Event>);
+    events.len() as u32
+}
+
+unsafe extern "C" fn events_get(
+    list: *const clap::InputEvents,
+    index: u32,
+) -> *const clap::EventHeader {
+    let events = &*((*list).ctx as *const Vec<Event>);
+    events
+        .get(index as usize)
+        .map_or(ptr::null(), |event| event.header())
+}
+
+/// Events sent by the plugin, e.g. for notes that ended, are of no interest.
+unsafe extern "C" fn events_discard(
+    _list: *const clap::OutputEvents,
+    _event: *const clap::EventHeader,
+) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_void};
+    use std::ptr;
+
+    use super::{clap, Params, Plugin, PluginError};
+    use crate::effect::Effect;
+    use crate::instrument::Instrument;
+    use crate::wave::Stereo;
+    use syntxt_core::note::{Note, Velocity};
+
+    /// A plugin adding the velocities of the held notes to its input, scaled by `gain`.
+    #[repr(C)]
+    struct Fake {
+        plugin: clap::Plugin,
+        gain: f64,
+        held: Vec<i32>,
+        velocity: f64,
+    }
+
+    const GAIN_ID: u32 = 7;
+
+    static ENTRY: clap::PluginEntry = clap::PluginEntry {
+        clap_version: clap::VERSION,
+        init: entry_init,
+        deinit: entry_deinit,
+        get_factory,
+    };
+
+    static FACTORY: clap::PluginFactory = clap::PluginFactory {
+        get_plugin_count,
+        get_plugin_descriptor,
+        create_plugin,
+    };
+
+    static PARAMS: clap::PluginParams = clap::PluginParams {
+        count: params_count,
+        get_info: params_get_info,
+        get_value: params_get_value,
+        value_to_text: params_value_to_text,
+        text_to_value: params_text_to_value,
+        flush: params_flush,
+    };
+
+    unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+        true
+    }
+
+    unsafe extern "C" fn entry_deinit() {}
+
+    unsafe extern "C" fn get_factory(factory_id: *const c_char) -> *const c_void {
+        if CStr::from_ptr(factory_id).to_bytes_with_nul() == clap::PLUGIN_FACTORY_ID {
+            &FACTORY as *const clap::PluginFactory as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn get_plugin_count(_factory: *const clap::PluginFactory) -> u32 {
+        1
+    }
+
+    unsafe extern "C" fn get_plugin_descriptor(
+        _factory: *const clap::PluginFactory,
+        _index: u32,
+    ) -> *const clap::PluginDescriptor {
+        let name = b"fake\0".as_ptr() as *const c_char;
+        Box::leak(Box::new(clap::PluginDescriptor {
+            clap_version: clap::VERSION,
+            id: name,
+            name,
+            vendor: ptr::null(),
+            url: ptr::null(),
+            manual_url: ptr::null(),
+            support_url: ptr::null(),
+            version: ptr::null(),
+            description: ptr::null(),
+            features: ptr::null(),
+        }))
+    }
+
+    unsafe extern "C" fn create_plugin(
+        _factory: *const clap::PluginFactory,
+        _host: *const clap::Host,
+        _plugin_id: *const c_char,
+    ) -> *const clap::Plugin {
+        let fake = Box::new(Fake {
+            plugin: clap::Plugin {
+                desc: ptr::null(),
+                plugin_data: ptr::null_mut(),
+                init: plugin_succeed,
+                destroy: plugin_destroy,
+                activate: plugin_activate,
+                deactivate: plugin_nothing,
+                start_processing: plugin_succeed,
+                stop_processing: plugin_nothing,
+                reset: plugin_nothing,
+                process: plugin_process,
+                get_extension: plugin_get_extension,
+                on_main_thread: plugin_nothing,
+            },
+            gain: 1.0,
+            held: Vec::new(),
+            velocity: 0.0,
+        });
+        Box::into_raw(fake) as *const clap::Plugin
+    }
+
+    unsafe extern "C" fn plugin_succeed(_plugin: *const clap::Plugin) -> bool {
+        true
+    }
+
+    unsafe extern "C" fn plugin_nothing(_plugin: *const clap::Plugin) {}
+
+    unsafe extern "C" fn plugin_destroy(plugin: *const clap::Plugin) {
+        drop(Box::from_raw(plugin as *mut Fake));
+    }
+
+    unsafe extern "C" fn plugin_activate(
+        _plugin: *const clap::Plugin,
+        _sample_rate: f64,
+        _min_frames_count: u32,
+        max_frames_count: u32,
+    ) -> bool {
+        max_frames_count as usize == super::MAX_BLOCK_SIZE
+    }
+
+    unsafe extern "C" fn plugin_get_extension(
+        _plugin: *const clap::Plugin,
+        id: *const c_char,
+    ) -> *const c_void {
+        if CStr::from_ptr(id).to_bytes_with_nul() == clap::EXT_PARAMS {
+            &PARAMS as *const clap::PluginParams as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn plugin_process(
+        plugin: *const clap::Plugin,
+        process: *const clap::Process,
+    ) -> i32 {
+        let fake = &mut *(plugin as *mut Fake);
+        let process = &*process;
+        let events = &*process.in_events;
+        let mut next = 0;
+        for frame in 0..process.frames_count {
+            while next < (events.size)(events) {
+                let header = &*(events.get)(events, next);
+                if header.time > frame {
+                    break;
+                }
+                match header.type_ {
+                    clap::EVENT_NOTE_ON => {
+                        let note = &*(header as *const clap::EventHeader as *const clap::EventNote);
+                        fake.held.push(note.note_id);
+                        fake.velocity = note.velocity;
+                    }
+                    clap::EVENT_NOTE_OFF => {
+                        let note = &*(header as *const clap::EventHeader as *const clap::EventNote);
+                        fake.held.retain(|id| *id != note.note_id);
+                    }
+                    clap::EVENT_PARAM_VALUE => {
+                        let param =
+                            &*(header as *const clap::EventHeader as *const clap::EventParamValue);
+                        assert_eq!(param.param_id, GAIN_ID);
+                        fake.gain = param.value;
+                    }
+                    _ => {}
+                }
+                next += 1;
+            }
+            let notes = fake.held.len() as f64 * fake.velocity;
+            for channel in 0..2 {
+                let input = *(*(*process.audio_inputs).data32.add(channel)).add(frame as usize);
+                let output = (*(*process.audio_outputs).data32.add(channel)).add(frame as usize);
+                *output = (input as f64 * fake.gain + notes) as f32;
+            }
+        }
+        1
+    }
+
+    unsafe extern "C" fn params_count(_plugin: *const clap::Plugin) -> u32 {
+        1
+    }
+
+    unsafe extern "C" fn params_get_info(
+        _plugin: *const clap::Plugin,
+        _param_index: u32,
+        info: *mut clap::ParamInfo,
+    ) -> bool {
+        let info = &mut *info;
+        info.id = GAIN_ID;
+        for (target, byte) in info.name.iter_mut().zip(b"gain\0") {
+            *target = *byte as c_char;
+        }
+        info.max_value = 2.0;
+        true
+    }
+
+    unsafe extern "C" fn params_get_value(
+        _plugin: *const clap::Plugin,
+        _param_id: u32,
+        _value: *mut f64,
+    ) -> bool {
+        false
+    }
+
+    unsafe extern "C" fn params_value_to_text(
+        _plugin: *const clap::Plugin,
+        _param_id: u32,
+        _value: f64,
+        _display: *mut c_char,
+        _size: u32,
+    ) -> bool {
+        false
+    }
+
+    unsafe extern "C" fn params_text_to_value(
+        _plugin: *const clap::Plugin,
+        _param_id: u32,
+        _display: *const c_char,
+        _value: *mut f64,
+    ) -> bool {
+        false
+    }
+
+    unsafe extern "C" fn params_flush(
+        _plugin: *const clap::Plugin,
+        _in_events: *const clap::InputEvents,
+        _out_events: *const clap::OutputEvents,
+    ) {
+    }
+
+    fn fake(params: &Params) -> Plugin {
+        unsafe { Plugin::from_entry(None, &ENTRY, 44100.0, params) }.unwrap()
+    }
+
+    #[test]
+    fn notes() {
+        let mut plugin = fake(&Params::new("fake.clap"));
+        let note = plugin.play_note(10, Note::from_midi(69), Velocity::from_f64(0.5));
+        // Longer than a block of the plugin
+        let mut samples = vec![Stereo::mono(0.0); 700];
+        plugin.fill_buffer(&mut samples);
+        assert!(samples[..10].iter().all(|s| s.left == 0.0));
+        assert!(samples[10..]
+            .iter()
+            .all(|s| s.left == 0.5 && s.right == 0.5));
+
+        // Events after the end of the buffer are delivered with later calls
+        plugin.release_note(100, note);
+        plugin.play_note(400, Note::from_midi(60), Velocity::from_f64(0.25));
+        let mut samples = vec![Stereo::mono(0.0); 300];
+        plugin.fill_buffer(&mut samples);
+        assert!(samples[..100].iter().all(|s| s.left == 0.5));
+        assert!(samples[100..].iter().all(|s| s.left == 0.0));
+        let mut samples = vec![Stereo::mono(1.0); 300];
+        plugin.fill_buffer(&mut samples);
+        assert!(samples[..100].iter().all(|s| s.left == 1.0));
+        assert!(samples[100..].iter().all(|s| s.left == 1.25));
+    }
+
+    #[test]
+    fn parameters() {
+        let params = Params {
+            parameters: vec![("gain".to_string(), 2.0), ("volume".to_string(), 1.0)],
+            ..Params::new("fake.clap")
+        };
+        let mut plugin = fake(&params);
+        let mut samples = vec![Stereo::new(0.5, -0.25); 10];
+        plugin.process(&mut samples);
+        assert!(samples.iter().all(|s| *s == Stereo::new(1.0, -0.5)));
+
+        assert!(Effect::set_parameter(&mut plugin, "gain", 0.5));
+        assert!(!Effect::set_parameter(&mut plugin, "volume", 0.5));
+        let mut samples = vec![Stereo::mono(1.0); 10];
+        plugin.process(&mut samples);
+        assert!(samples.iter().all(|s| s.left == 0.5));
+    }
+
+    #[test]
+    fn missing_library() {
+        let params = Params::new("/nonexistent/synth.clap");
+        assert!(matches!(
+            Plugin::load(44100.0, &params),
+            Err(PluginError::Load { .. })
+        ));
+    }
+}
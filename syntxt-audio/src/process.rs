@@ -0,0 +1,403 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Processing audio in blocks of bounded size.
+//!
+//! Everything that happens while rendering, like notes and parameter changes, is passed along
+//! with the block as events at a sample offset, so that a processor never needs to look beyond
+//! the block it is rendering.
+
+use std::collections::HashMap;
+
+use syntxt_core::note::{Note, Velocity};
+
+use crate::effect::Effect;
+use crate::instrument::Instrument;
+use crate::wave::Stereo;
+
+/// The largest number of samples processed at once.
+pub const MAX_BLOCK_SIZE: usize = 512;
+
+/// A block of at most `MAX_BLOCK_SIZE` samples that is processed in place.
+pub struct AudioBlock<'a> {
+    /// Sample time of the first sample in the block.
+    start: usize,
+    samples: &'a mut [Stereo<f64>],
+}
+
+impl<'a> AudioBlock<'a> {
+    pub fn new(start: usize, samples: &'a mut [Stereo<f64>]) -> Self {
+        assert!(
+            samples.len() <= MAX_BLOCK_SIZE,
+            "block of {} samples exceeds the maximum of {}",
+            samples.len(),
+            MAX_BLOCK_SIZE
+        );
+        Self { start, samples }
+    }
+
+    /// Sample time of the first sample in the block.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Number of samples in the block.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn samples(&self) -> &[Stereo<f64>] {
+        self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [Stereo<f64>] {
+        self.samples
+    }
+
+    /// Set all samples to zero.
+    pub fn fill_zero(&mut self) {
+        self.samples.iter_mut().for_each(|s| *s = Stereo::mono(0.0));
+    }
+}
+
+/// Something happening at a sample of a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Number of samples from the start of the block.
+    pub offset: usize,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// Start playing a note, which is later referred to by the id.
+    NoteOn {
+        id: usize,
+        note: Note,
+        velocity: Velocity,
+        /// Deviation from the tuning in cents.
+        detune: f64,
+    },
+    /// Release the note that was started with the same id.
+    NoteOff { id: usize },
+    /// Bend the pitch of all notes, see `Instrument::pitch_bend`.
+    PitchBend(f64),
+    /// Change a parameter by name.
+    Parameter { name: String, value: f64 },
+}
+
+/// Anything rendering audio block by block.
+pub trait Process {
+    /// Render the block in place, applying the events at their offsets.
+    /// The events are sorted by their offset, which lies within the block.
+    fn process(&mut self, block: &mut AudioBlock, events: &[Event]);
+}
+
+impl<P: Process + ?Sized> Process for Box<P> {
+    fn process(&mut self, block: &mut AudioBlock, events: &[Event]) {
+        (**self).process(block, events)
+    }
+}
+
+/// Process a buffer of any length in consecutive blocks.
+/// The offsets of the events count from the start of the buffer and must be sorted.
+pub fn process_in_blocks<P: Process + ?Sized>(
+    processor: &mut P,
+    start: usize,
+    samples: &mut [Stereo<f64>],
+    events: &[Event],
+) {
+    debug_assert!(events.windows(2).all(|w| w[0].offset <= w[1].offset));
+    let mut remaining = events;
+    for (index, chunk) in samples.chunks_mut(MAX_BLOCK_SIZE).enumerate() {
+        let offset = index * MAX_BLOCK_SIZE;
+        let count = remaining
+            .iter()
+            .take_while(|event| event.offset < offset + chunk.len())
+            .count();
+        let local = remaining[..count]
+            .iter()
+            .map(|event| Event {
+                offset: event.offset - offset,
+                kind: event.kind.clone(),
+            })
+            .collect::<Vec<_>>();
+        remaining = &remaining[count..];
+        processor.process(&mut AudioBlock::new(start + offset, chunk), &local);
+    }
+}
+
+/// Calls `render` for the parts of the block between parameter changes,
+/// so that parameters change at the exact sample.
+fn split_at_parameters<T>(
+    target: &mut T,
+    block: &mut AudioBlock,
+    events: &[Event],
+    mut apply: impl FnMut(&mut T, usize, &EventKind),
+    mut render: impl FnMut(&mut T, &mut [Stereo<f64>]),
+) {
+    let mut begin = 0;
+    for event in events {
+        if let EventKind::Parameter { .. } = event.kind {
+            if event.offset > begin {
+                render(target, &mut block.samples_mut()[begin..event.offset]);
+                begin = event.offset;
+            }
+        }
+        apply(target, event.offset - begin, &event.kind);
+    }
+    render(target, &mut block.samples_mut()[begin..]);
+}
+
+/// Processes blocks with an effect. Only parameter events are used.
+pub struct ProcessEffect<E: Effect> {
+    effect: E,
+}
+
+impl<E: Effect> ProcessEffect<E> {
+    pub fn new(effect: E) -> Self {
+        Self { effect }
+    }
+
+    pub fn effect_mut(&mut self) -> &mut E {
+        &mut self.effect
+    }
+}
+
+impl<E: Effect> Process for ProcessEffect<E> {
+    fn process(&mut self, block: &mut AudioBlock, events: &[Event]) {
+        split_at_parameters(
+            &mut self.effect,
+            block,
+            events,
+            |effect, _, kind| {
+                if let EventKind::Parameter { name, value } = kind {
+                    effect.set_parameter(name, *value);
+                }
+            },
+            |effect, samples| effect.process(samples),
+        );
+    }
+}
+
+/// Renders blocks with an instrument, replacing their contents.
+pub struct ProcessInstrument<I: Instrument> {
+    instrument: I,
+    /// The handles of the playing notes by the id of their event.
+    handles: HashMap<usize, I::PlayHandle>,
+}
+
+impl<I: Instrument> ProcessInstrument<I> {
+    pub fn new(instrument: I) -> Self {
+        Self {
+            instrument,
+            handles: HashMap::new(),
+        }
+    }
+
+    pub fn instrument_mut(&mut self) -> &mut I {
+        &mut self.instrument
+    }
+}
+
+impl<I: Instrument> Process for ProcessInstrument<I> {
+    fn process(&mut self, block: &mut AudioBlock, events: &[Event]) {
+        block.fill_zero();
+        split_at_parameters(
+            self,
+            block,
+            events,
+            |this, delay, kind| match kind {
+                EventKind::NoteOn {
+                    id,
+                    note,
+                    velocity,
+                    detune,
+                } => {
+                    let handle = this
+                        .instrument
+                        .play_detuned_note(delay, *note, *velocity, *detune);
+                    this.handles.insert(*id, handle);
+                }
+                EventKind::NoteOff { id } => {
+                    if let Some(handle) = this.handles.remove(id) {
+                        this.instrument.release_note(delay, handle);
+                    }
+                }
+                EventKind::PitchBend(amount) => this.instrument.pitch_bend(delay, *amount),
+                EventKind::Parameter { name, value } => {
+                    this.instrument.set_parameter(name, *value);
+                }
+            },
+            |this, samples| this.instrument.fill_buffer(samples),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Multiplies the signal with its only parameter.
+    struct Gain(f64);
+    impl Effect for Gain {
+        fn process(&mut self, samples: &mut [Stereo<f64>]) {
+            for sample in samples.iter_mut() {
+                *sample *= self.0;
+            }
+        }
+        fn set_parameter(&mut self, _name: &str, value: f64) -> bool {
+            self.0 = value;
+            true
+        }
+    }
+
+    /// Outputs the number of notes sounding at each sample.
+    #[derive(Default)]
+    struct Counter {
+        /// Pending changes of the count, relative to the next buffer
+        changes: Vec<(usize, f64)>,
+        count: f64,
+    }
+    impl Instrument for Counter {
+        type PlayHandle = ();
+        fn play_note(&mut self, delay: usize, _note: Note, _velocity: Velocity) {
+            self.changes.push((delay, 1.0));
+        }
+        fn release_note(&mut self, delay: usize, _handle: ()) {
+            self.changes.push((delay, -1.0));
+        }
+        fn fill_buffer(&mut self, output: &mut [Stereo<f64>]) {
+            for (index, sample) in output.iter_mut().enumerate() {
+                for (_, change) in self.changes.iter().filter(|(at, _)| *at == index) {
+                    self.count += change;
+                }
+                *sample += Stereo::mono(self.count);
+            }
+            let length = output.len();
+            self.changes.retain(|(at, _)| *at >= length);
+            self.changes.iter_mut().for_each(|(at, _)| *at -= length);
+        }
+    }
+
+    fn parameter(offset: usize, value: f64) -> Event {
+        Event {
+            offset,
+            kind: EventKind::Parameter {
+                name: "gain".to_string(),
+                value,
+            },
+        }
+    }
+
+    #[test]
+    fn blocks() {
+        struct Log(Vec<(usize, usize, Vec<usize>)>);
+        impl Process for Log {
+            fn process(&mut self, block: &mut AudioBlock, events: &[Event]) {
+                let offsets = events.iter().map(|e| e.offset).collect();
+                self.0.push((block.start(), block.len(), offsets));
+            }
+        }
+
+        let mut log = Log(Vec::new());
+        let mut samples = vec![Stereo::mono(0.0); 2 * MAX_BLOCK_SIZE + 10];
+        let events = [
+            parameter(0, 1.0),
+            parameter(MAX_BLOCK_SIZE - 1, 1.0),
+            parameter(MAX_BLOCK_SIZE, 1.0),
+            parameter(2 * MAX_BLOCK_SIZE + 3, 1.0),
+        ];
+        process_in_blocks(&mut log, 1000, &mut samples, &events);
+        assert_eq!(
+            log.0,
+            vec![
+                (1000, MAX_BLOCK_SIZE, vec![0, MAX_BLOCK_SIZE - 1]),
+                (1000 + MAX_BLOCK_SIZE, MAX_BLOCK_SIZE, vec![0]),
+                (1000 + 2 * MAX_BLOCK_SIZE, 10, vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_accurate_parameters() {
+        let mut processor = ProcessEffect::new(Gain(1.0));
+        let mut samples = vec![Stereo::mono(1.0); 1000];
+        process_in_blocks(
+            &mut processor,
+            0,
+            &mut samples,
+            &[parameter(300, 2.0), parameter(600, 3.0)],
+        );
+        for (index, sample) in samples.iter().enumerate() {
+            let expected = if index < 300 {
+                1.0
+            } else if index < 600 {
+                2.0
+            } else {
+                3.0
+            };
+            assert_eq!(sample.left, expected, "{}", index);
+        }
+    }
+
+    #[test]
+    fn notes_across_blocks() {
+        let note_on = |offset, id| Event {
+            offset,
+            kind: EventKind::NoteOn {
+                id,
+                note: Note::named_str("a4").unwrap(),
+                velocity: Velocity::MAX,
+                detune: 0.0,
+            },
+        };
+        let note_off = |offset, id| Event {
+            offset,
+            kind: EventKind::NoteOff { id },
+        };
+
+        let mut processor = ProcessInstrument::new(Counter::default());
+        let mut samples = vec![Stereo::mono(5.0); 1000];
+        process_in_blocks(
+            &mut processor,
+            0,
+            &mut samples,
+            &[
+                note_on(100, 0),
+                note_on(200, 1),
+                parameter(300, 0.0),
+                note_off(400, 0),
+                note_off(600, 1),
+                note_off(700, 1),
+            ],
+        );
+        for (index, sample) in samples.iter().enumerate() {
+            let expected = match index {
+                0..=99 => 0.0,
+                100..=199 => 1.0,
+                200..=399 => 2.0,
+                400..=599 => 1.0,
+                _ => 0.0,
+            };
+            assert_eq!(sample.left, expected, "{}", index);
+        }
+    }
+}
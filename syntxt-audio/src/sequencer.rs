@@ -0,0 +1,339 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Driving external synthesizers by sending the notes of a song to a MIDI port in real time,
+//! or writing them to a Standard MIDI File.
+//!
+//! The n-th track plays on MIDI channel n (modulo 16). Only notes, velocities and pitch bends
+//! are sent; instruments, effects, automation and tuning are left to the receiving synthesizer.
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::song::{Song, Time, TimeSig};
+
+/// Resolution of exported MIDI files.
+const TICKS_PER_QUARTER: i64 = 480;
+
+/// Sleeping may take a few milliseconds longer than asked for, so the last part of each wait
+/// is spent spinning instead.
+const SPIN: Duration = Duration::from_millis(2);
+
+/// A MIDI message due at a time from the start of the song.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedMessage {
+    pub time: Duration,
+    pub bytes: [u8; 3],
+}
+
+/// The messages playing the audible tracks of a song, in the order they are sent.
+/// At the same time, notes are released before new ones start, so that repeated notes
+/// are struck again.
+pub fn messages(song: &Song) -> Vec<TimedMessage> {
+    let sig = TimeSig {
+        beats_per_minute: song.bpm,
+        beat_unit: 4,
+    };
+    events(song)
+        .into_iter()
+        .map(|event| {
+            let seconds = sig.seconds(event.time);
+            TimedMessage {
+                time: Duration::from_secs_f64(
+                    seconds.numerator() as f64 / seconds.denominator() as f64,
+                ),
+                bytes: event.bytes,
+            }
+        })
+        .collect()
+}
+
+/// A MIDI message of a track at a time of the song in whole notes.
+struct Event {
+    track: usize,
+    time: Time,
+    bytes: [u8; 3],
+}
+
+/// The messages of the audible tracks, like `messages`.
+fn events(song: &Song) -> Vec<Event> {
+    let soloing = song.tracks.iter().any(|track| track.solo);
+
+    // With the rank among messages at the same time
+    let mut messages = Vec::new();
+    for (index, track) in song.tracks.iter().enumerate() {
+        if track.mute || (soloing && !track.solo) {
+            continue;
+        }
+        let channel = (index % 16) as u8;
+        for bend in track.bends.iter() {
+            let value = (8192.0 + bend.amount.clamp(-1.0, 1.0) * 8191.0).round() as u16;
+            let bytes = [0xE0 | channel, (value & 0x7F) as u8, (value >> 7) as u8];
+            messages.push((
+                1,
+                Event {
+                    track: index,
+                    time: bend.start,
+                    bytes,
+                },
+            ));
+        }
+        for note in track.notes.iter() {
+            let key = note.note.to_midi();
+            let velocity = ((note.velocity.as_f64() * 127.0).round() as u8).max(1);
+            messages.push((
+                2,
+                Event {
+                    track: index,
+                    time: note.start,
+                    bytes: [0x90 | channel, key, velocity],
+                },
+            ));
+            messages.push((
+                0,
+                Event {
+                    track: index,
+                    time: note.start + note.duration,
+                    bytes: [0x80 | channel, key, 64],
+                },
+            ));
+        }
+    }
+    messages.sort_by_key(|(rank, message)| (message.time, *rank));
+    messages.into_iter().map(|(_, message)| message).collect()
+}
+
+/// A Standard MIDI File with the messages of the audible tracks, each in its own track
+/// named like it, after a first track holding the tempo of the song.
+pub fn midi_file(song: &Song) -> Vec<u8> {
+    let soloing = song.tracks.iter().any(|track| track.solo);
+    let audible = song
+        .tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| !(track.mute || (soloing && !track.solo)))
+        .collect::<Vec<_>>();
+    let events = events(song);
+
+    let mut file = Vec::new();
+    file.extend_from_slice(b"MThd");
+    file.extend_from_slice(&6u32.to_be_bytes());
+    // Format 1 with simultaneous tracks
+    file.extend_from_slice(&1u16.to_be_bytes());
+    file.extend_from_slice(&(audible.len() as u16 + 1).to_be_bytes());
+    file.extend_from_slice(&(TICKS_PER_QUARTER as u16).to_be_bytes());
+
+    let tempo = (60_000_000 / song.bpm.max(1)) as u32;
+    let mut track = vec![0x00, 0xFF, 0x51, 0x03];
+    track.extend_from_slice(&tempo.to_be_bytes()[1..]);
+    write_track(&mut file, track);
+
+    for (index, song_track) in audible {
+        let mut track = Vec::new();
+        if let Some(name) = &song_track.name {
+            track.extend_from_slice(&[0x00, 0xFF, 0x03]);
+            write_variable(&mut track, name.len() as u64);
+            track.extend_from_slice(name.as_bytes());
+        }
+        let mut last = 0;
+        for event in events.iter().filter(|event| event.track == index) {
+            let tick = (event.time * 4 * TICKS_PER_QUARTER).round().max(last);
+            write_variable(&mut track, (tick - last) as u64);
+            track.extend_from_slice(&event.bytes);
+            last = tick;
+        }
+        write_track(&mut file, track);
+    }
+    file
+}
+
+/// Append a track chunk with the given events, ending the track right after them.
+fn write_track(file: &mut Vec<u8>, mut events: Vec<u8>) {
+    events.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(events.len() as u32).to_be_bytes());
+    file.extend(events);
+}
+
+/// Append a number in the variable length encoding of MIDI files, seven bits per byte.
+fn write_variable(out: &mut Vec<u8>, value: u64) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Send the messages to a MIDI port, e.g. a raw MIDI device like `/dev/snd/midiC1D0`,
+/// each when it is due, counting from now.
+///
+/// Afterwards, or when sending fails, all notes on the used channels are turned off.
+pub fn send(messages: &[TimedMessage], port: &mut impl Write) -> io::Result<()> {
+    let start = Instant::now();
+    let result = messages.iter().try_for_each(|message| {
+        wait_until(start + message.time);
+        port.write_all(&message.bytes)?;
+        port.flush()
+    });
+
+    let mut channels = messages
+        .iter()
+        .map(|message| message.bytes[0] & 0x0F)
+        .collect::<Vec<_>>();
+    channels.sort_unstable();
+    channels.dedup();
+    let all_notes_off = channels
+        .iter()
+        .flat_map(|channel| vec![0xB0 | channel, 123, 0])
+        .collect::<Vec<_>>();
+    let silenced = port.write_all(&all_notes_off).and_then(|_| port.flush());
+    result.and(silenced)
+}
+
+fn wait_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        let remaining = deadline - now;
+        if remaining > SPIN {
+            thread::sleep(remaining - SPIN);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{messages, midi_file, send, write_variable, TimedMessage};
+    use crate::song::Song;
+    use syntxt_core::midi;
+
+    #[test]
+    fn song_messages() {
+        let song = Song::from_source(
+            r#"Song {
+    bpm: 240
+    Track { Sequence { notes: [[ c4- c4-> ]] } }
+    Track { mute: true Sequence { notes: [[ d4 ]] } }
+    Track { Sequence { notes: [[ ^-1 e4 ]] } }
+}"#,
+        )
+        .unwrap();
+        let millis = |millis| Duration::from_millis(millis);
+        assert_eq!(
+            messages(&song),
+            vec![
+                TimedMessage {
+                    time: millis(0),
+                    bytes: [0xE2, 1, 0]
+                },
+                TimedMessage {
+                    time: millis(0),
+                    bytes: [0x90, 60, 64]
+                },
+                TimedMessage {
+                    time: millis(0),
+                    bytes: [0x92, 64, 64]
+                },
+                TimedMessage {
+                    time: millis(125),
+                    bytes: [0x80, 60, 64]
+                },
+                TimedMessage {
+                    time: millis(125),
+                    bytes: [0x90, 60, 95]
+                },
+                TimedMessage {
+                    time: millis(250),
+                    bytes: [0x80, 60, 64]
+                },
+                TimedMessage {
+                    time: millis(250),
+                    bytes: [0x82, 64, 64]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn scheduling() {
+        let message = |millis, key| TimedMessage {
+            time: Duration::from_millis(millis),
+            bytes: [0x93, key, 100],
+        };
+        let mut port = Vec::new();
+        let start = Instant::now();
+        send(&[message(0, 60), message(20, 62)], &mut port).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(port, vec![0x93, 60, 100, 0x93, 62, 100, 0xB3, 123, 0]);
+    }
+
+    #[test]
+    fn file() {
+        let song = Song::from_source(
+            r#"Song {
+    bpm: 240
+    Track { name: "lead" Sequence { notes: [[ c4- c4-> ]] } }
+    Track { mute: true Sequence { notes: [[ d4 ]] } }
+    Track { Sequence { notes: [[ r ^-1 e4+ ]] } }
+}"#,
+        )
+        .unwrap();
+        let file = midi::parse(&midi_file(&song)).unwrap();
+        assert_eq!(file.format, 1);
+        assert_eq!(file.tracks.len(), 3);
+        assert_eq!(file.tempo_map.timing(), midi::Timing::TicksPerQuarter(480));
+        // Four quarter notes per second
+        assert_eq!(file.tempo_map.seconds(480), 0.25);
+
+        assert_eq!(file.tracks[1].name.as_deref(), Some("lead"));
+        let notes = |track: &midi::MidiTrack| {
+            track
+                .notes
+                .iter()
+                .map(|note| (note.channel, note.note.to_midi(), note.start, note.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            notes(&file.tracks[1]),
+            vec![(0, 60, 0, 240), (0, 60, 240, 480)]
+        );
+        // The muted track is left out, the others keep their channels
+        assert_eq!(file.tracks[2].name, None);
+        assert_eq!(notes(&file.tracks[2]), vec![(2, 64, 480, 1440)]);
+    }
+
+    #[test]
+    fn variable_length() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_variable(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(0x7F), vec![0x7F]);
+        assert_eq!(encode(0x80), vec![0x81, 0x00]);
+        assert_eq!(encode(0x0FFF_FFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+}
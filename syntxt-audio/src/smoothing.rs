@@ -0,0 +1,303 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Smoothing of parameter changes.
+//!
+//! Automation updates parameters once per buffer, and a parameter jumping to a new value
+//! is audible as a click, or as zipper noise when it happens repeatedly. Wrapping an effect or
+//! instrument in `Smooth` spreads every change over a short ramp instead.
+
+use std::ops::Range;
+
+use crate::effect::{compressor::Compressor, Effect};
+use crate::instrument::Instrument;
+use crate::wave::Stereo;
+use syntxt_core::note::{Note, Velocity};
+
+/// Time in seconds over which parameter changes are spread by default.
+pub const DEFAULT_TIME: f64 = 0.01;
+
+/// Number of samples processed at once while parameters are ramping.
+/// The parameters are updated between these chunks.
+const CHUNK_SIZE: usize = 16;
+
+/// A value moving linearly towards its target.
+///
+/// # Examples
+///
+/// ```
+/// # use syntxt_audio::smoothing::Smoothed;
+///
+/// let mut gain = Smoothed::new(0.0);
+/// gain.set(1.0, 4);
+/// assert_eq!(gain.advance(1), 0.25);
+/// assert_eq!(gain.advance(2), 0.75);
+/// assert_eq!(gain.advance(2), 1.0);
+/// assert!(!gain.is_ramping());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smoothed {
+    current: f64,
+    target: f64,
+    /// Change per sample
+    step: f64,
+    /// Number of samples until the target is reached
+    remaining: usize,
+}
+
+impl Smoothed {
+    pub fn new(value: f64) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.current
+    }
+
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    pub fn is_ramping(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Move from the current value to the target within the given number of samples.
+    pub fn set(&mut self, target: f64, samples: usize) {
+        if samples == 0 {
+            *self = Self::new(target);
+        } else {
+            self.target = target;
+            self.step = (target - self.current) / samples as f64;
+            self.remaining = samples;
+        }
+    }
+
+    /// Advance by the given number of samples, returning the new value.
+    pub fn advance(&mut self, samples: usize) -> f64 {
+        if samples >= self.remaining {
+            *self = Self::new(self.target);
+        } else {
+            self.current += self.step * samples as f64;
+            self.remaining -= samples;
+        }
+        self.current
+    }
+}
+
+/// An effect or instrument whose parameter changes ramp over a fixed time.
+///
+/// The first value of a parameter is applied right away, as there is nothing to ramp from.
+/// Later values are approached in small steps while processing.
+pub struct Smooth<T> {
+    inner: T,
+    /// Length of the ramps in samples
+    duration: usize,
+    /// The parameters that were set so far
+    parameters: Vec<(String, Smoothed)>,
+}
+
+impl<T> Smooth<T> {
+    /// Smooth the parameter changes of `inner` over the given time in seconds.
+    pub fn new(inner: T, sample_rate: f64, seconds: f64) -> Self {
+        Self {
+            inner,
+            duration: (seconds.max(0.0) * sample_rate).round() as usize,
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Start ramping a parameter to a new value, where `apply` sets a parameter of the inner
+    /// value and returns whether it exists.
+    fn set(
+        &mut self,
+        name: &str,
+        value: f64,
+        mut apply: impl FnMut(&mut T, &str, f64) -> bool,
+    ) -> bool {
+        match self.parameters.iter_mut().find(|(other, _)| other == name) {
+            Some((_, smoothed)) => {
+                smoothed.set(value, self.duration);
+                if !smoothed.is_ramping() {
+                    apply(&mut self.inner, name, value);
+                }
+                true
+            }
+            None => {
+                let known = apply(&mut self.inner, name, value);
+                if known {
+                    self.parameters
+                        .push((name.to_string(), Smoothed::new(value)));
+                }
+                known
+            }
+        }
+    }
+
+    /// Process `length` samples with `process`, in chunks while parameters are ramping.
+    fn chunked(
+        &mut self,
+        length: usize,
+        mut apply: impl FnMut(&mut T, &str, f64) -> bool,
+        mut process: impl FnMut(&mut T, Range<usize>),
+    ) {
+        let mut start = 0;
+        while start < length {
+            let ramping = self.parameters.iter().any(|(_, p)| p.is_ramping());
+            let end = if ramping {
+                (start + CHUNK_SIZE).min(length)
+            } else {
+                length
+            };
+            for (name, smoothed) in self.parameters.iter_mut() {
+                if smoothed.is_ramping() {
+                    apply(&mut self.inner, name, smoothed.advance(end - start));
+                }
+            }
+            process(&mut self.inner, start..end);
+            start = end;
+        }
+    }
+}
+
+impl<E: Effect> Effect for Smooth<E> {
+    fn process(&mut self, samples: &mut [Stereo<f64>]) {
+        self.chunked(samples.len(), E::set_parameter, |effect, range| {
+            effect.process(&mut samples[range])
+        });
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.set(name, value, E::set_parameter)
+    }
+}
+
+impl Smooth<Compressor> {
+    /// Compress with the level detected from another signal, see `Compressor`.
+    pub fn process_with_detector(&mut self, samples: &mut [Stereo<f64>], detector: &[Stereo<f64>]) {
+        self.chunked(
+            samples.len(),
+            Compressor::set_parameter,
+            |compressor, range| {
+                compressor.process_with_detector(&mut samples[range.clone()], &detector[range])
+            },
+        );
+    }
+}
+
+impl<I: Instrument> Instrument for Smooth<I> {
+    type PlayHandle = I::PlayHandle;
+
+    fn play_note(
+        &mut self,
+        sample_delay: usize,
+        note: Note,
+        velocity: Velocity,
+    ) -> Self::PlayHandle {
+        self.inner.play_note(sample_delay, note, velocity)
+    }
+
+    fn play_detuned_note(
+        &mut self,
+        sample_delay: usize,
+        note: Note,
+        velocity: Velocity,
+        cents: f64,
+    ) -> Self::PlayHandle {
+        self.inner
+            .play_detuned_note(sample_delay, note, velocity, cents)
+    }
+
+    fn release_note(&mut self, sample_delay: usize, handle: Self::PlayHandle) {
+        self.inner.release_note(sample_delay, handle)
+    }
+
+    fn pitch_bend(&mut self, sample_delay: usize, amount: f64) {
+        self.inner.pitch_bend(sample_delay, amount)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+        self.set(name, value, I::set_parameter)
+    }
+
+    fn seek(&mut self, sample: usize) {
+        self.inner.seek(sample)
+    }
+
+    fn fill_buffer(&mut self, output: &mut [Stereo<f64>]) {
+        // The delays of notes and bends count from the next call, so they stay valid
+        // when the buffer is filled in several calls
+        self.chunked(output.len(), I::set_parameter, |instrument, range| {
+            instrument.fill_buffer(&mut output[range])
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Smooth;
+    use crate::effect::Effect;
+    use crate::wave::Stereo;
+
+    /// Multiplies the signal with its only parameter.
+    struct Gain(f64);
+    impl Effect for Gain {
+        fn process(&mut self, samples: &mut [Stereo<f64>]) {
+            for sample in samples.iter_mut() {
+                *sample *= self.0;
+            }
+        }
+        fn set_parameter(&mut self, name: &str, value: f64) -> bool {
+            self.0 = value;
+            name == "gain"
+        }
+    }
+
+    #[test]
+    fn ramps_parameter_changes() {
+        let mut effect = Smooth::new(Gain(1.0), 1000.0, 0.064);
+        assert!(!effect.set_parameter("volume", 0.5));
+        // The first value is applied right away
+        assert!(effect.set_parameter("gain", 0.0));
+        let mut samples = vec![Stereo::mono(1.0); 100];
+        effect.process(&mut samples);
+        assert!(samples.iter().all(|s| s.left == 0.0));
+
+        // Later ones in steps until the full ramp of 64 samples is over
+        assert!(effect.set_parameter("gain", 1.0));
+        let mut samples = vec![Stereo::mono(1.0); 100];
+        effect.process(&mut samples);
+        let gains = samples.iter().map(|s| s.left).collect::<Vec<_>>();
+        assert_eq!(gains[0], 0.25);
+        assert_eq!(gains[16], 0.5);
+        assert_eq!(gains[47], 0.75);
+        assert_eq!(gains[48], 1.0);
+        assert!(gains.windows(2).all(|w| w[0] <= w[1]));
+    }
+}
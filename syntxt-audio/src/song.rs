@@ -16,17 +16,245 @@
 
 //! High-level description of a song that can be turned into audio.
 
+use crate::automation::{self, Expr};
+use crate::effect;
+use crate::filter;
 use crate::instrument;
+use crate::plugin;
+use crate::smoothing;
+use crate::tags::Tags;
+use crate::tuner::JustIntonation;
+use crate::tuning::Tuning;
+use crate::wave::Layout;
+use std::path::PathBuf;
 use syntxt_core::note::{Note, Velocity};
 use syntxt_core::rational::Rational;
+use syntxt_lang::diagnostic::{Diagnostic, Severity};
+use syntxt_lang::{ast::Articulation, model, parser::Parser, timeline::NoteEvent};
 
 /// A description of a complete song.
 #[derive(Debug)]
 pub struct Song {
     /// The speed of the song measured in beats per minute.
     pub bpm: i64,
+    /// The sample rate chosen by the song, used for rendering
+    /// unless the command line or the project configuration chooses one.
+    pub sample_rate: Option<i64>,
     /// The tracks of the song, playing simultaneously.
     pub tracks: Vec<Track>,
+    /// Shared effect chains that tracks can send part of their signal to.
+    pub buses: Vec<Bus>,
+    /// Linear gain applied to the mix of all tracks and buses.
+    pub gain: Expr,
+    /// Effects applied to the mix, in order.
+    pub effects: Vec<Effect>,
+    /// Limiter applied last, keeping the output from clipping.
+    pub limiter: Option<effect::limiter::Params>,
+    /// The speakers of the output. With more than one pair of channels,
+    /// the effects and the limiter are applied to each pair on its own.
+    pub layout: Layout,
+    /// Text associated with points in time, e.g. lyrics, ordered by time.
+    pub markers: Vec<Marker>,
+    /// Title, artist and more, written into exported files.
+    pub tags: Tags,
+    /// The tuning of all pitched instruments, unless their track has its own.
+    pub tuning: Tuning,
+    /// Retune the notes of pitched instruments to pure intervals relative to the current chord.
+    pub intonation: Option<JustIntonation>,
+    /// Time in seconds over which parameter changes of instruments and effects are spread.
+    pub smoothing: f64,
+}
+
+/// A piece of text at a point in time of the song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub time: Time,
+    pub text: String,
+}
+
+impl Song {
+    /// Parse a syn.txt source file and build the song it describes.
+    ///
+    /// Fails with all diagnostics if there was at least one error. Warnings are dropped otherwise.
+    pub fn from_source(source: &str) -> Result<Song, Vec<Diagnostic>> {
+        Self::from_source_with_files(source, &model::NoFiles)
+    }
+
+    /// Build a song like `from_source`, reading the files it imports from `files`.
+    pub fn from_source_with_files(
+        source: &str,
+        files: &dyn model::Files,
+    ) -> Result<Song, Vec<Diagnostic>> {
+        let (root, mut diagnostics) = Parser::parse_with_diagnostics(source);
+        let (song, resolve_diagnostics) = model::resolve_with_files(&root, files);
+        diagnostics.extend(resolve_diagnostics);
+        match song {
+            Some(song) if diagnostics.iter().all(|d| d.severity < Severity::Error) => {
+                Ok(Song::from_model(&song))
+            }
+            _ => Err(diagnostics),
+        }
+    }
+
+    /// Build a song from its typed description.
+    /// Every track is played on its plugin or a default `Wavinator` with its number of voices,
+    /// followed by its equalizer if it has one, its gates and then its effect plugins.
+    pub fn from_model(song: &model::Song) -> Song {
+        Song {
+            bpm: song.bpm.value,
+            sample_rate: Some(song.sample_rate.value).filter(|_| !song.sample_rate.is_default()),
+            tracks: song
+                .tracks
+                .iter()
+                .map(|track| Track {
+                    name: track.name.value.clone(),
+                    instrument: match &track.plugin.value {
+                        Some(path) => Instrument::Plugin(plugin_params(path, &track.params)),
+                        None => Instrument::Wavinator(instrument::wavinator::Params {
+                            voices: instrument::polyphonic::Voices {
+                                max: track.voices.value.map(|voices| voices as usize),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        }),
+                    },
+                    notes: track
+                        .sequences
+                        .iter()
+                        .flat_map(|seq| seq.notes.value.iter())
+                        .map(PlayedNote::from_event)
+                        .collect(),
+                    clips: track
+                        .clips
+                        .iter()
+                        .map(|clip| Clip {
+                            path: clip.path.clone(),
+                            start: clip.start.value,
+                        })
+                        .collect(),
+                    effects: {
+                        let mut effects = Vec::new();
+                        if !track.eq.is_empty() {
+                            effects.push(Effect::Equalizer(track.eq.iter().map(eq_band).collect()));
+                        }
+                        let sig = TimeSig {
+                            beats_per_minute: song.bpm.value,
+                            beat_unit: 4,
+                        };
+                        effects.extend(track.gates.iter().map(|g| Effect::Gate(gate(g, &sig))));
+                        effects.extend(
+                            track
+                                .plugins
+                                .iter()
+                                .map(|p| Effect::Plugin(plugin_params(&p.path, &p.params))),
+                        );
+                        effects
+                    },
+                    pan: Expr::Const(track.pan.value),
+                    speakers: song
+                        .layout
+                        .value
+                        .speakers()
+                        .iter()
+                        .position(|speakers| *speakers == track.speakers.value)
+                        .unwrap_or(0),
+                    sends: Vec::new(),
+                    gain: Expr::Const(track.volume.value),
+                    mute: track.mute.value,
+                    solo: track.solo.value,
+                    automation: Vec::new(),
+                    tuning: None,
+                    freeze: track.freeze.value,
+                    bends: {
+                        let mut bends = track
+                            .sequences
+                            .iter()
+                            .flat_map(|seq| seq.bends.iter())
+                            .map(|bend| PitchBend {
+                                start: bend.start,
+                                amount: bend.amount.into_inner(),
+                            })
+                            .collect::<Vec<_>>();
+                        bends.sort_by_key(|bend| bend.start);
+                        bends
+                    },
+                })
+                .collect(),
+            buses: Vec::new(),
+            gain: Expr::Const(song.volume.value),
+            effects: Vec::new(),
+            limiter: Some(effect::limiter::Params::default()),
+            layout: match song.layout.value {
+                model::Layout::Stereo => Layout::Stereo,
+                model::Layout::Quad => Layout::Quad,
+                model::Layout::Surround51 => Layout::Surround51,
+            },
+            markers: song
+                .lyrics
+                .iter()
+                .map(|line| Marker {
+                    time: line.start.value,
+                    text: line.text.clone(),
+                })
+                .collect(),
+            tags: {
+                let meta = &song.meta.value;
+                Tags {
+                    title: meta.name.value.clone(),
+                    artist: meta.author.value.clone(),
+                    year: meta.year.value,
+                    description: meta.description.value.clone(),
+                }
+            },
+            tuning: Tuning::default(),
+            intonation: None,
+            smoothing: smoothing::DEFAULT_TIME,
+        }
+    }
+}
+
+fn eq_band(band: &model::EqBand) -> filter::BiquadType {
+    let frequency = band.frequency.value;
+    let gain = band.gain.value;
+    let q = band.q.value;
+    match band.kind {
+        model::EqBandKind::LowShelf => filter::BiquadType::LowShelf { frequency, gain, q },
+        model::EqBandKind::Peak => filter::BiquadType::Peaking { frequency, gain, q },
+        model::EqBandKind::HighShelf => filter::BiquadType::HighShelf { frequency, gain, q },
+    }
+}
+
+fn plugin_params(path: &str, params: &[model::Param]) -> plugin::Params {
+    plugin::Params {
+        parameters: params
+            .iter()
+            .map(|param| (param.name.clone(), param.value.value))
+            .collect(),
+        ..plugin::Params::new(path)
+    }
+}
+
+fn gate(gate: &model::Gate, sig: &TimeSig) -> effect::gate::Params {
+    let seconds = |time: Rational| {
+        let seconds = sig.seconds(time);
+        seconds.numerator() as f64 / seconds.denominator() as f64
+    };
+    effect::gate::Params {
+        threshold: gate.threshold.value,
+        attack: gate.attack.value,
+        release: gate.release.value,
+        pattern: gate
+            .pattern
+            .value
+            .as_ref()
+            .map(|notes| effect::gate::Pattern {
+                openings: notes
+                    .iter()
+                    .map(|note| (seconds(note.start), seconds(note.end())))
+                    .collect(),
+                length: seconds(gate.length.value),
+            }),
+    }
 }
 
 /// The instrument used for playing a track.
@@ -34,13 +262,125 @@ pub struct Song {
 pub enum Instrument {
     /// The built-in test synthesizer.
     Wavinator(instrument::wavinator::Params),
+    /// Plays recorded sounds, see `graph::load_sample` for loading them from files.
+    Sampler(instrument::sampler::Params),
+    /// Plays a different drum sound for every note.
+    DrumKit(instrument::drum_kit::Params),
+    /// A physical model of a plucked string.
+    PluckedString(instrument::plucked_string::Params),
+    /// An external instrument in the CLAP plugin format.
+    Plugin(plugin::Params),
+}
+
+/// An effect applied to the sound of a track.
+#[derive(Debug, Clone)]
+pub enum Effect {
+    Reverb(effect::reverb::Params),
+    /// Reverb from the recorded impulse response of a real space.
+    ConvolutionReverb(effect::convolution::Params),
+    /// Chorus or flanger, depending on the parameters.
+    Chorus(effect::chorus::Params),
+    /// Equalizer applying the given filters in order.
+    Equalizer(Vec<filter::BiquadType>),
+    Distortion(effect::distortion::Params),
+    /// Ring or amplitude modulation with an internal oscillator.
+    RingModulator(effect::ring_mod::Params),
+    /// Compressors acting on separate frequency bands, e.g. for mastering.
+    MultibandCompressor(effect::multiband::Params),
+    /// Narrow or widen the stereo image, optionally keeping low frequencies in mono.
+    StereoWidth(effect::stereo_width::Params),
+    /// Noise gate or trance gate.
+    Gate(effect::gate::Params),
+    /// Periodically changing volume.
+    Tremolo(effect::tremolo::Params),
+    /// Periodically moving between the left and right channel.
+    AutoPan(effect::auto_pan::Params),
+    /// An external effect in the CLAP plugin format.
+    Plugin(plugin::Params),
+    Compressor {
+        params: effect::compressor::Params,
+        /// Index of the track whose instrument controls the compression instead of
+        /// the signal itself, e.g. the kick drum for ducking a bass.
+        sidechain: Option<usize>,
+    },
 }
 
 /// A single track generating sound by playing notes on an instrument.
 #[derive(Debug)]
 pub struct Track {
+    /// Name of the track, e.g. for naming its stem.
+    pub name: Option<String>,
     pub instrument: Instrument,
     pub notes: Vec<PlayedNote>,
+    /// Recordings played alongside the instrument, before the effects.
+    pub clips: Vec<Clip>,
+    /// Effects applied to the output of the instrument, in order.
+    pub effects: Vec<Effect>,
+    /// Position in the stereo field between -1 (left) and 1 (right), applied after the effects.
+    pub pan: Expr,
+    /// Index of the pair of channels of the layout of the song the track is mixed into,
+    /// e.g. 1 for the rear speakers of `Layout::Quad`.
+    pub speakers: usize,
+    /// Changes of the pitch bend of the instrument, in the order they happen.
+    pub bends: Vec<PitchBend>,
+    /// Parts of the signal sent to buses, in addition to the direct output of the track.
+    pub sends: Vec<AuxSend>,
+    /// Linear gain applied by the mixer.
+    pub gain: Expr,
+    /// Whether the track is silenced, including its sends.
+    pub mute: bool,
+    /// Whether the track is soloed. As soon as one track is soloed, only soloed tracks are heard.
+    pub solo: bool,
+    /// Parameters of the instrument and effects changing over time.
+    pub automation: Vec<Automation>,
+    /// Replaces the tuning of the song for this track.
+    pub tuning: Option<Tuning>,
+    /// Whether the track is rendered once and played from the recording while it is unchanged,
+    /// see `freeze`.
+    pub freeze: bool,
+}
+
+/// An audio file played from a point in time of a track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    /// Path of the file, in any format supported by sox.
+    pub path: PathBuf,
+    pub start: Time,
+}
+
+/// A parameter of the instrument or of an effect of a track following a curve.
+#[derive(Debug, Clone)]
+pub struct Automation {
+    pub target: AutomationTarget,
+    /// Name of the parameter, e.g. `cutoff` of the `Wavinator` or `wet` of a reverb.
+    pub parameter: String,
+    /// The values of the parameter, with the time measured in measures like the notes.
+    pub curve: automation::Curve,
+}
+
+/// The part of a track whose parameter is automated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomationTarget {
+    Instrument,
+    /// The effect with the given index in `Track::effects`.
+    Effect(usize),
+}
+
+/// A return bus applying its effects to the sum of all signals sent to it,
+/// e.g. a single reverb shared by all tracks, which is much cheaper than one reverb per track.
+#[derive(Debug)]
+pub struct Bus {
+    /// Effects applied to the signals sent to the bus, in order.
+    pub effects: Vec<Effect>,
+}
+
+/// Sending part of the signal of a track to a bus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuxSend {
+    /// Index of the bus in `Song::buses`.
+    pub bus: usize,
+    /// Linear gain of the sent signal.
+    pub amount: f64,
 }
 
 /// Time in measures, can be fractional, e.g. a note taking 1/4.
@@ -57,6 +397,48 @@ pub struct PlayedNote {
     pub start: Time,
     /// Time when the key was released
     pub duration: Time,
+    /// Deviation from the pitch given by the tuning in cents, e.g. for just intonation
+    pub detune: f64,
+}
+
+impl PlayedNote {
+    /// Velocity of notes that are not accented.
+    pub const NORMAL_VELOCITY: f64 = 0.5;
+    /// Velocity of accented notes.
+    pub const ACCENT_VELOCITY: f64 = 0.75;
+
+    /// Play a note of a sequence, applying its articulation.
+    ///
+    /// Staccato notes are held for half their written duration, while legato notes are held
+    /// for an additional 1/32 so that they overlap with the following note.
+    /// Notes with a recorded velocity keep it, regardless of their accent.
+    pub fn from_event(event: &NoteEvent) -> PlayedNote {
+        let duration = match event.articulation {
+            Articulation::Normal => event.duration,
+            Articulation::Staccato => event.duration / 2,
+            Articulation::Legato => event.duration + Rational::new(1, 32),
+        };
+        let velocity = match event.velocity {
+            Some(velocity) => velocity,
+            None if event.accent => Velocity::from_f64(Self::ACCENT_VELOCITY),
+            None => Velocity::from_f64(Self::NORMAL_VELOCITY),
+        };
+        PlayedNote {
+            note: event.note,
+            velocity,
+            start: event.start,
+            duration,
+            detune: 0.0,
+        }
+    }
+}
+
+/// A change of the pitch bend of an instrument, which stays in effect until the next change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitchBend {
+    pub start: Time,
+    /// Amount between -1 and 1, in multiples of the bend range of the instrument.
+    pub amount: f64,
 }
 
 /// Time signature of the song, consisting of
@@ -81,3 +463,186 @@ impl TimeSig {
         (self.seconds(note_time) * samples_per_second).round()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Effect, Instrument, PitchBend, Song};
+    use crate::automation::Expr;
+    use crate::wave::Layout;
+    use syntxt_core::rational::Rational;
+
+    #[test]
+    fn song_sample_rate() {
+        let song = Song::from_source("Song { sampleRate: 48000 }").unwrap();
+        assert_eq!(song.sample_rate, Some(48000));
+    }
+
+    #[test]
+    fn song_from_source() {
+        let song = Song::from_source(
+            r#"Song {
+    bpm: 100
+    Track {
+        name: "lead"
+        volume: 0.5
+        solo: true
+        Sequence { start: 1 notes: [[ c4 e4 ^0.5 ]] }
+        Sequence { notes: [[ g4 ]] }
+    }
+    Track { voices: 1 }
+    Lyrics { Line { start: 1/2 text: "la" } }
+}"#,
+        )
+        .unwrap();
+        assert_eq!(song.bpm, 100);
+        assert_eq!(song.sample_rate, None);
+        assert_eq!(song.tracks.len(), 2);
+        assert_eq!(song.tracks[0].name.as_deref(), Some("lead"));
+        assert_eq!(song.tracks[1].name, None);
+        assert!(matches!(
+            &song.tracks[0].instrument,
+            Instrument::Wavinator(params) if params.voices.max.is_none()
+        ));
+        assert!(matches!(
+            &song.tracks[1].instrument,
+            Instrument::Wavinator(params) if params.voices.max == Some(1)
+        ));
+        assert!(matches!(song.tracks[0].gain, Expr::Const(gain) if gain == 0.5));
+        assert!(song.tracks[0].solo && !song.tracks[0].mute);
+        assert!(matches!(song.gain, Expr::Const(gain) if gain == 1.0));
+        let starts = song.tracks[0]
+            .notes
+            .iter()
+            .map(|note| note.start)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            vec![Rational::int(1), Rational::new(5, 4), Rational::zero()]
+        );
+        assert!(song.tracks[1].notes.is_empty());
+        assert_eq!(
+            song.tracks[0].bends,
+            vec![PitchBend {
+                start: Rational::new(3, 2),
+                amount: 0.5,
+            }]
+        );
+        assert_eq!(song.markers[0].time, Rational::new(1, 2));
+        assert_eq!(song.markers[0].text, "la");
+    }
+
+    #[test]
+    fn articulation() {
+        let song =
+            Song::from_source("Song { Track { Sequence { notes: [[ c4' d4~ e4> ]] } } }").unwrap();
+        let notes = &song.tracks[0].notes;
+        assert_eq!(notes[0].duration, Rational::new(1, 8));
+        assert_eq!(notes[1].duration, Rational::new(9, 32));
+        assert_eq!(notes[2].duration, Rational::new(1, 4));
+        assert_eq!(notes[0].velocity, notes[1].velocity);
+        assert!(notes[2].velocity > notes[0].velocity);
+    }
+
+    #[test]
+    fn trance_gate() {
+        let song = Song::from_source(
+            "Song { bpm: 120 Track { Gate { pattern: [[ c4- r- c4 ]] length: 1/2 } } }",
+        )
+        .unwrap();
+        let pattern = match &song.tracks[0].effects[..] {
+            [Effect::Gate(params)] => params.pattern.clone().unwrap(),
+            other => panic!("expected a single gate, got {:?}", other),
+        };
+        // A whole note lasts two seconds
+        assert_eq!(pattern.openings, vec![(0.0, 0.25), (0.5, 1.0)]);
+        assert_eq!(pattern.length, 1.0);
+    }
+
+    #[test]
+    fn plugins() {
+        let song = Song::from_source(
+            r#"Song { Track {
+    plugin: "synth.clap"
+    Param { name: "cutoff" value: 800 }
+    Gate { }
+    Plugin { path: "delay.clap" }
+} }"#,
+        )
+        .unwrap();
+        let track = &song.tracks[0];
+        match &track.instrument {
+            Instrument::Plugin(params) => {
+                assert_eq!(params.path.to_str(), Some("synth.clap"));
+                assert_eq!(params.parameters, vec![("cutoff".to_string(), 800.0)]);
+            }
+            other => panic!("expected a plugin, got {:?}", other),
+        }
+        // Effect plugins come after the gates
+        match &track.effects[..] {
+            [Effect::Gate(_), Effect::Plugin(params)] => {
+                assert_eq!(params.path.to_str(), Some("delay.clap"));
+                assert!(params.parameters.is_empty());
+            }
+            other => panic!("expected a gate and a plugin, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tags() {
+        let song = Song::from_source(
+            r#"Song { meta: Meta { name: "Example" author: "Someone" year: 2021 } }"#,
+        )
+        .unwrap();
+        assert_eq!(song.tags.title.as_deref(), Some("Example"));
+        assert_eq!(song.tags.artist.as_deref(), Some("Someone"));
+        assert_eq!(song.tags.year, Some(2021));
+        assert_eq!(song.tags.description, None);
+    }
+
+    #[test]
+    fn layout() {
+        let song = Song::from_source(
+            r#"Song { layout: "5.1" Track { speakers: "rear" } Track { } Track { speakers: "center" } }"#,
+        )
+        .unwrap();
+        assert_eq!(song.layout, Layout::Surround51);
+        let speakers = song
+            .tracks
+            .iter()
+            .map(|track| track.speakers)
+            .collect::<Vec<_>>();
+        assert_eq!(speakers, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn clips() {
+        let song = Song::from_source(
+            r#"Song { Track { Clip { file: "vocals.wav" start: 16/4 } Clip { file: "intro.wav" } } }"#,
+        )
+        .unwrap();
+        let clips = song.tracks[0]
+            .clips
+            .iter()
+            .map(|clip| (clip.path.to_str().unwrap(), clip.start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            clips,
+            vec![
+                ("vocals.wav", Rational::int(4)),
+                ("intro.wav", Rational::zero())
+            ]
+        );
+    }
+
+    #[test]
+    fn song_from_invalid_source() {
+        let diagnostics = Song::from_source("Song { bpm: 1.5 }").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "expected an integer");
+
+        // Parse errors are reported as well
+        assert!(Song::from_source("Song { Track { }").is_err());
+        // Warnings alone do not prevent building the song
+        assert!(Song::from_source("Song { loud: true }").is_ok());
+    }
+}
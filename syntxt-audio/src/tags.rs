@@ -0,0 +1,274 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Information about a song written into exported files, so that players can show it.
+//!
+//! Every format has its own names for the same fields: WAV files get an `INFO` list,
+//! AIFF files get text chunks, Ogg, Opus and FLAC files get Vorbis comments
+//! and MP3 files get ID3 tags. Broadcast WAV files additionally describe the song
+//! in a `bext` chunk.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The fields describing a song that all formats support.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub year: Option<i64>,
+    pub description: Option<String>,
+}
+
+impl Tags {
+    /// The tags as Vorbis comments like `TITLE=Song`.
+    pub fn vorbis_comments(&self) -> Vec<String> {
+        self.named(["TITLE", "ARTIST", "DATE", "DESCRIPTION"])
+    }
+
+    /// The tags as comments that sox turns into the ID3 tags of MP3 files.
+    pub fn id3_comments(&self) -> Vec<String> {
+        self.named(["Title", "Artist", "Year", "Comment"])
+    }
+
+    /// The `LIST` chunk of a RIFF file holding the tags as `INFO` entries,
+    /// or nothing if there are no tags.
+    pub fn riff_info(&self) -> Vec<u8> {
+        let mut entries = Vec::new();
+        for (id, value) in self.values(["INAM", "IART", "ICRD", "ICMT"]) {
+            // Zero-terminated strings, padded to an even length
+            let size = value.len() + 1;
+            entries.extend_from_slice(id.as_bytes());
+            entries.extend_from_slice(&(size as u32).to_le_bytes());
+            entries.extend_from_slice(value.as_bytes());
+            entries.resize(entries.len() + 1 + size % 2, 0);
+        }
+        if entries.is_empty() {
+            return entries;
+        }
+        let mut chunk = Vec::with_capacity(12 + entries.len());
+        chunk.extend_from_slice(b"LIST");
+        chunk.extend_from_slice(&(4 + entries.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(b"INFO");
+        chunk.extend(entries);
+        chunk
+    }
+
+    /// The text chunks of an AIFF file holding the tags, where the year becomes the copyright.
+    pub fn aiff_chunks(&self) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        for (id, value) in self.values(["NAME", "AUTH", "(c) ", "ANNO"]) {
+            // Big endian sizes, padded to an even length
+            chunks.extend_from_slice(id.as_bytes());
+            chunks.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            chunks.extend_from_slice(value.as_bytes());
+            chunks.resize(chunks.len() + value.len() % 2, 0);
+        }
+        chunks
+    }
+
+    /// The `bext` chunk of a Broadcast WAV file, holding the title and description,
+    /// the artist as originator, the time the file was created in UTC, and the position
+    /// of its first sample in the song, in samples.
+    pub fn bext_chunk(&self, time_reference: u64, created: SystemTime) -> Vec<u8> {
+        let description = match (&self.title, &self.description) {
+            (Some(title), Some(description)) => format!("{} - {}", title, description),
+            (title, description) => title
+                .clone()
+                .or_else(|| description.clone())
+                .unwrap_or_default(),
+        };
+        let seconds = created
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let (year, month, day) = civil_date(seconds / 86400);
+        let time = seconds % 86400;
+
+        let mut chunk = Vec::with_capacity(610);
+        chunk.extend_from_slice(b"bext");
+        chunk.extend_from_slice(&602u32.to_le_bytes());
+        // Fixed size text fields, padded with zeros
+        let mut text = |value: &str, size: usize| {
+            let mut end = value.len().min(size);
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            chunk.extend_from_slice(&value.as_bytes()[..end]);
+            chunk.resize(chunk.len() + size - end, 0);
+        };
+        text(&description, 256);
+        text(self.artist.as_deref().unwrap_or("syn.txt"), 32);
+        text("syn.txt", 32);
+        text(&format!("{:04}-{:02}-{:02}", year, month, day), 10);
+        text(
+            &format!("{:02}:{:02}:{:02}", time / 3600, time / 60 % 60, time % 60),
+            8,
+        );
+        chunk.extend_from_slice(&time_reference.to_le_bytes());
+        // Version 1, without UMID, loudness values and coding history
+        chunk.extend_from_slice(&1u16.to_le_bytes());
+        chunk.resize(chunk.len() + 64 + 190, 0);
+        chunk
+    }
+
+    fn named(&self, names: [&'static str; 4]) -> Vec<String> {
+        self.values(names)
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect()
+    }
+
+    /// The values that are set, with the given names for title, artist, year and description.
+    fn values(&self, names: [&'static str; 4]) -> Vec<(&'static str, String)> {
+        let values = [
+            self.title.clone(),
+            self.artist.clone(),
+            self.year.map(|year| year.to_string()),
+            self.description.clone(),
+        ];
+        names
+            .iter()
+            .zip(values.iter())
+            .filter_map(|(name, value)| Some((*name, value.clone()?)))
+            .collect()
+    }
+}
+
+/// Year, month and day of a number of days since 1970-01-01 in the Gregorian calendar.
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Counted in eras of 400 years from 0000-03-01, so that leap days end the years
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{civil_date, Tags};
+
+    fn tags() -> Tags {
+        Tags {
+            title: Some("Song".into()),
+            year: Some(2021),
+            ..Tags::default()
+        }
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(tags().vorbis_comments(), vec!["TITLE=Song", "DATE=2021"]);
+        assert_eq!(tags().id3_comments(), vec!["Title=Song", "Year=2021"]);
+        assert!(Tags::default().vorbis_comments().is_empty());
+    }
+
+    #[test]
+    fn riff_info() {
+        let chunk = tags().riff_info();
+        let expected = [
+            b"LIST".as_ref(),
+            &[32, 0, 0, 0],
+            b"INFO",
+            // The odd length is padded
+            b"INAM",
+            &[5, 0, 0, 0],
+            b"Song\0\0",
+            b"ICRD",
+            &[5, 0, 0, 0],
+            b"2021\0\0",
+        ]
+        .concat();
+        assert_eq!(chunk, expected);
+        assert!(Tags::default().riff_info().is_empty());
+    }
+
+    #[test]
+    fn aiff_chunks() {
+        let chunks = Tags {
+            artist: Some("Me".into()),
+            ..tags()
+        }
+        .aiff_chunks();
+        let expected = [
+            b"NAME".as_ref(),
+            &[0, 0, 0, 4],
+            b"Song",
+            b"AUTH",
+            &[0, 0, 0, 2],
+            b"Me",
+            b"(c) ",
+            &[0, 0, 0, 4],
+            b"2021",
+        ]
+        .concat();
+        assert_eq!(chunks, expected);
+        let odd = Tags {
+            title: Some("Odd".into()),
+            ..Tags::default()
+        };
+        assert_eq!(odd.aiff_chunks(), b"NAME\0\0\0\x03Odd\0".to_vec());
+    }
+
+    #[test]
+    fn bext_chunk() {
+        let created = UNIX_EPOCH + Duration::from_secs(1_634_390_096);
+        let chunk = Tags {
+            description: Some("A demo".into()),
+            ..tags()
+        }
+        .bext_chunk(88200, created);
+        assert_eq!(chunk.len(), 610);
+        assert_eq!(&chunk[..8], b"bext\x5a\x02\0\0");
+        assert_eq!(&chunk[8..22], b"Song - A demo\0");
+        assert_eq!(&chunk[264..272], b"syn.txt\0");
+        assert_eq!(
+            &chunk[328..354],
+            b"2021-10-1613:14:56\x88\x58\x01\0\0\0\0\0"
+        );
+        assert_eq!(&chunk[354..356], &[1, 0]);
+        assert!(chunk[356..].iter().all(|&byte| byte == 0));
+
+        // Long descriptions are cut off without splitting characters
+        let long = Tags {
+            title: Some(format!("a{}", "ä".repeat(200))),
+            ..Tags::default()
+        }
+        .bext_chunk(0, created);
+        assert_eq!(long.len(), 610);
+        assert_eq!(&long[8..263], format!("a{}", "ä".repeat(127)).as_bytes());
+        assert_eq!(long[263], 0);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(11017), (2000, 3, 1));
+        assert_eq!(civil_date(18992), (2021, 12, 31));
+    }
+}
@@ -0,0 +1,214 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Adjusting the pitch of individual notes to the harmony they are part of.
+//!
+//! Equal temperament makes every key sound the same at the cost of slightly impure intervals.
+//! Just intonation instead tunes the notes in pure frequency ratios relative to the root of the
+//! current chord, which changes along with the harmony of the song.
+
+use crate::song::{PlayedNote, Time};
+use syntxt_core::note::Note;
+
+/// Tuning notes in pure frequency ratios relative to the root of the current chord.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JustIntonation {
+    /// Frequency ratios of the twelve semitones above the root, starting with the root itself.
+    pub ratios: [f64; 12],
+    /// The roots of the harmony, ordered by time. Each one holds until the next.
+    /// If empty, the roots are inferred from the notes.
+    pub roots: Vec<RootChange>,
+}
+
+/// The root of the harmony starting at some point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootChange {
+    pub time: Time,
+    /// Only the pitch class matters, not the octave.
+    pub root: Note,
+}
+
+impl JustIntonation {
+    /// Intervals with ratios of the primes 2, 3 and 5, e.g. 5/4 for the major third.
+    pub const FIVE_LIMIT: [f64; 12] = [
+        1.0,
+        16.0 / 15.0,
+        9.0 / 8.0,
+        6.0 / 5.0,
+        5.0 / 4.0,
+        4.0 / 3.0,
+        45.0 / 32.0,
+        3.0 / 2.0,
+        8.0 / 5.0,
+        5.0 / 3.0,
+        9.0 / 5.0,
+        15.0 / 8.0,
+    ];
+
+    /// Deviation of a note from equal temperament in cents when tuned relative to the root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use syntxt_audio::tuner::JustIntonation;
+    /// use syntxt_core::note::Note;
+    /// let just = JustIntonation::default();
+    /// let (c4, e4) = (Note::from_midi(60), Note::from_midi(64));
+    /// // A pure major third is almost 14 cents lower than the tempered one
+    /// assert!((just.cents(c4, e4) + 13.686).abs() < 0.001);
+    /// assert_eq!(just.cents(c4, c4), 0.0);
+    /// ```
+    pub fn cents(&self, root: Note, note: Note) -> f64 {
+        let interval = (note.index() - root.index()).rem_euclid(12);
+        1200.0 * self.ratios[interval as usize].log2() - 100.0 * interval as f64
+    }
+
+    /// Detune the notes of all given tracks according to the root at their start.
+    /// The roots are inferred from the notes of all tracks together if none are declared.
+    ///
+    /// Notes starting before the first root are tuned relative to it.
+    pub fn retune(&self, tracks: &mut [&mut Vec<PlayedNote>]) {
+        let inferred;
+        let roots = if self.roots.is_empty() {
+            inferred = infer_roots(tracks.iter().flat_map(|notes| notes.iter()));
+            &inferred
+        } else {
+            &self.roots
+        };
+        if roots.is_empty() {
+            return;
+        }
+        for note in tracks.iter_mut().flat_map(|notes| notes.iter_mut()) {
+            let index = roots
+                .iter()
+                .position(|change| change.time > note.start)
+                .unwrap_or(roots.len())
+                .max(1);
+            note.detune += self.cents(roots[index - 1].root, note.note);
+        }
+    }
+}
+
+impl Default for JustIntonation {
+    fn default() -> Self {
+        Self {
+            ratios: Self::FIVE_LIMIT,
+            roots: Vec::new(),
+        }
+    }
+}
+
+/// Guess the roots of the harmony from the notes sounding whenever a note starts.
+///
+/// The root is the pitch class under which most sounding notes are a root, third or fifth.
+/// Ties are broken in favor of the lowest sounding note.
+pub fn infer_roots<'a>(notes: impl Iterator<Item = &'a PlayedNote>) -> Vec<RootChange> {
+    let mut notes = notes.collect::<Vec<_>>();
+    notes.sort_by_key(|note| note.start);
+
+    let mut roots: Vec<RootChange> = Vec::new();
+    for (index, note) in notes.iter().enumerate() {
+        let time = note.start;
+        if index > 0 && notes[index - 1].start == time {
+            continue;
+        }
+        let sounding = notes[..]
+            .iter()
+            .take_while(|other| other.start <= time)
+            .filter(|other| time < other.start + other.duration)
+            .map(|other| other.note)
+            .collect::<Vec<_>>();
+        let lowest = match sounding.iter().min() {
+            Some(lowest) => lowest.index().rem_euclid(12),
+            None => continue,
+        };
+        let score = |root: i32| {
+            let chord_tones = sounding
+                .iter()
+                .filter(|note| [0, 3, 4, 7].contains(&(note.index() - root).rem_euclid(12)))
+                .count();
+            (chord_tones, root == lowest)
+        };
+        let root = (0..12).max_by_key(|root| score(*root)).unwrap();
+        if roots.last().map(|change| change.root.index() % 12) != Some(root) {
+            roots.push(RootChange {
+                time,
+                root: Note::from_midi(60 + root as u8),
+            });
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{infer_roots, JustIntonation, RootChange};
+    use crate::song::{PlayedNote, Time};
+    use syntxt_core::note::{Note, Velocity};
+
+    fn chord(start: i64, notes: &[u8]) -> Vec<PlayedNote> {
+        notes
+            .iter()
+            .map(|midi| PlayedNote {
+                note: Note::from_midi(*midi),
+                velocity: Velocity::MAX,
+                start: Time::int(start),
+                duration: Time::int(1),
+                detune: 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn infer_chord_roots() {
+        // C major, A minor in first inversion, G major in second inversion
+        let mut notes = chord(0, &[60, 64, 67]);
+        notes.extend(chord(1, &[60, 64, 69]));
+        notes.extend(chord(2, &[62, 67, 71]));
+        let roots = infer_roots(notes.iter())
+            .into_iter()
+            .map(|change| (change.time, change.root.index() % 12))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            roots,
+            vec![(Time::int(0), 0), (Time::int(1), 9), (Time::int(2), 7)]
+        );
+    }
+
+    #[test]
+    fn retune_relative_to_root() {
+        let mut melody = chord(0, &[64]);
+        melody.extend(chord(1, &[64]));
+        let just = JustIntonation {
+            roots: vec![
+                RootChange {
+                    time: Time::int(1),
+                    root: Note::from_midi(60),
+                },
+                RootChange {
+                    time: Time::int(1),
+                    root: Note::from_midi(61),
+                },
+            ],
+            ..JustIntonation::default()
+        };
+        just.retune(&mut [&mut melody]);
+        // The first root also applies before it starts
+        assert!((melody[0].detune + 13.686).abs() < 0.001);
+        // The later one of two roots at the same time wins: e is a minor third above c#
+        assert!((melody[1].detune - 15.641).abs() < 0.001);
+    }
+}
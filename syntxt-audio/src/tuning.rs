@@ -14,10 +14,18 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+//! Mapping notes to frequencies, in the usual twelve tone equal temperament
+//! as well as in other scales, e.g. loaded from [Scala](https://www.huygens-fokker.org/scala/) files.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use snafu::{OptionExt, ResultExt, Snafu};
 use syntxt_core::note::*;
 
 /// Defines the tuning of an instrument by assinging a frequency to a certain note.
-/// This defines the frequencies of all other notes at a standard tuning of 12 half-tones per octave.
+/// The frequencies of all other notes follow from the scale and how it is mapped to the keys,
+/// by default 12 half-tones per octave.
 ///
 /// # Examples
 ///
@@ -26,27 +34,385 @@ use syntxt_core::note::*;
 /// use syntxt_audio::tuning::*;
 /// assert_eq!(Tuning::default().frequency(Note::from_midi(57)), 220.0);
 /// assert_eq!(Tuning::default().frequency(Note::from_midi(81)), 880.0);
+///
+/// // 19 equal divisions of the octave, a4 is still 440 Hz
+/// let edo19 = Tuning::equal(19);
+/// assert_eq!(edo19.frequency(Note::from_midi(69 + 19)), 880.0);
 /// ```
+#[derive(Debug, Clone, PartialEq)]
 pub struct Tuning {
     pub reference_note: Note,
     pub reference_frequency: f64,
+    pub scale: Scale,
+    pub mapping: KeyboardMapping,
 }
 
 impl Tuning {
+    /// Concert tuning with the octave divided into the given number of equal steps,
+    /// each key playing the next step.
+    pub fn equal(divisions: usize) -> Tuning {
+        Tuning {
+            scale: Scale::equal(divisions),
+            ..Tuning::default()
+        }
+    }
+
+    /// Load a scale from a Scala `.scl` file, optionally mapped to the keys by a `.kbm` file.
+    /// Without a keyboard mapping, a4 is tuned to 440 Hz and each key plays the next scale degree.
+    pub fn load_scala(scale: &Path, mapping: Option<&Path>) -> Result<Tuning, ScalaError> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).context(Io {
+                path: path.to_path_buf(),
+            })
+        };
+        let scale = Scale::parse_scl(&read(scale)?)?;
+        match mapping {
+            Some(mapping) => KeyboardMapping::parse_kbm(&read(mapping)?, scale),
+            None => Ok(Tuning {
+                scale,
+                ..Tuning::default()
+            }),
+        }
+    }
+
     /// Return the frequency of a note relative to this tuning.
     pub fn frequency(&self, other: Note) -> f64 {
-        let semitones = other.index() - self.reference_note.index();
-        let octaves = semitones as f64 / 12.0;
-        self.reference_frequency * 2.0f64.powf(octaves)
+        let cents = self.cents(other) - self.cents(self.reference_note);
+        self.reference_frequency * 2.0f64.powf(cents / 1200.0)
+    }
+
+    /// Pitch of a note in cents relative to the middle note of the keyboard mapping.
+    fn cents(&self, note: Note) -> f64 {
+        let degree = self.mapping.degree(note);
+        self.scale.cents(degree)
     }
 }
 
 /// Default concert tuning, where A4 corresponds to 440 Hz.
 impl Default for Tuning {
     fn default() -> Self {
+        let reference_note = Note::named(NoteName::A, Accidental::Base, 4);
         Tuning {
-            reference_note: Note::named(NoteName::A, Accidental::Base, 4),
+            reference_note,
             reference_frequency: 440.0,
+            scale: Scale::equal(12),
+            mapping: KeyboardMapping::linear(reference_note),
+        }
+    }
+}
+
+/// The pitches of a scale, repeating in every period (usually the octave).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    /// The pitches of the degrees above the root in cents, ascending.
+    /// The last one is the period after which the scale repeats.
+    pub pitches: Vec<f64>,
+}
+
+impl Scale {
+    /// The octave divided into the given number of equal steps.
+    pub fn equal(divisions: usize) -> Scale {
+        let divisions = divisions.max(1);
+        Scale {
+            pitches: (1..=divisions)
+                .map(|step| 1200.0 * step as f64 / divisions as f64)
+                .collect(),
+        }
+    }
+
+    /// Parse the contents of a Scala `.scl` file.
+    ///
+    /// Pitches containing a period are given in cents, all others are ratios like `3/2` or `2`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use syntxt_audio::tuning::Scale;
+    /// let scale = Scale::parse_scl("! pythagorean.scl\nA fifth and an octave\n 2\n!\n3/2\n1200.0 octave\n")
+    ///     .unwrap();
+    /// assert!((scale.pitches[0] - 701.955).abs() < 0.001);
+    /// assert_eq!(scale.pitches[1], 1200.0);
+    /// ```
+    pub fn parse_scl(input: &str) -> Result<Scale, ScalaError> {
+        let mut lines = scala_lines(input);
+        // The description may be empty, so it cannot be skipped as a blank line
+        lines.next().context(UnexpectedEnd)?;
+        let (line, count) = lines
+            .find(|(_, text)| !text.is_empty())
+            .context(UnexpectedEnd)?;
+        let count: usize = first_word(count).parse().ok().context(Syntax {
+            line,
+            message: "expected the number of notes",
+        })?;
+        let mut pitches = Vec::with_capacity(count);
+        for (line, text) in lines.filter(|(_, text)| !text.is_empty()).take(count) {
+            let pitch = parse_pitch(first_word(text)).context(Syntax {
+                line,
+                message: "expected cents or a ratio",
+            })?;
+            pitches.push(pitch);
+        }
+        if pitches.len() < count {
+            return UnexpectedEnd.fail();
+        }
+        if count == 0 {
+            // A scale of only the root, repeating at the unison
+            pitches.push(0.0);
+        }
+        Ok(Scale { pitches })
+    }
+
+    /// Pitch of a scale degree in cents above the root, any degree below zero or beyond the scale
+    /// continues in the neighbouring periods.
+    pub fn cents(&self, degree: i32) -> f64 {
+        let size = self.pitches.len() as i32;
+        let period = self.pitches[self.pitches.len() - 1];
+        let index = degree.rem_euclid(size);
+        let repetition = degree.div_euclid(size);
+        let offset = if index == 0 {
+            0.0
+        } else {
+            self.pitches[index as usize - 1]
+        };
+        repetition as f64 * period + offset
+    }
+}
+
+/// Which scale degree is played by which key, as described by Scala `.kbm` files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// The key playing the root of the scale.
+    pub middle_note: Note,
+    /// The scale degrees of consecutive keys starting at the middle note, repeating after its
+    /// end. `None` marks keys that are not mapped, they play the degree of the previous key.
+    /// If empty, every key plays the next degree.
+    pub keys: Vec<Option<i32>>,
+    /// The scale degree added every time the keys repeat.
+    pub period_degree: i32,
+}
+
+impl KeyboardMapping {
+    /// Every key plays the next scale degree, starting with the root on the given key.
+    pub fn linear(middle_note: Note) -> KeyboardMapping {
+        KeyboardMapping {
+            middle_note,
+            keys: Vec::new(),
+            period_degree: 0,
+        }
+    }
+
+    /// Parse the contents of a Scala `.kbm` file for the given scale.
+    /// The keyboard mapping also defines the reference frequency, so the result is a complete tuning.
+    ///
+    /// The range of keys to retune given in the file is ignored, all keys follow the mapping.
+    pub fn parse_kbm(input: &str, scale: Scale) -> Result<Tuning, ScalaError> {
+        let mut lines = scala_lines(input).filter(|(_, text)| !text.is_empty());
+        let mut next = |message: &'static str| {
+            let (line, text) = lines.next().context(UnexpectedEnd)?;
+            Ok(Word {
+                line,
+                text: first_word(text).to_string(),
+                message,
+            })
+        };
+
+        let size = next("expected the size of the map")?.parse::<usize>()?;
+        next("expected the first note to retune")?.parse::<i64>()?;
+        next("expected the last note to retune")?.parse::<i64>()?;
+        let middle_note = next("expected the middle note")?.note()?;
+        let reference_note = next("expected the reference note")?.note()?;
+        let reference_frequency = next("expected the reference frequency")?.parse()?;
+        let period_degree = next("expected the scale degree of the formal octave")?.parse()?;
+        let mut keys = Vec::with_capacity(size);
+        for _ in 0..size {
+            let degree = next("expected a scale degree or x")?;
+            keys.push(if degree.text == "x" {
+                None
+            } else {
+                Some(degree.parse()?)
+            });
+        }
+        Ok(Tuning {
+            reference_note,
+            reference_frequency,
+            scale,
+            mapping: KeyboardMapping {
+                middle_note,
+                keys,
+                period_degree,
+            },
+        })
+    }
+
+    /// The scale degree played by a key.
+    pub fn degree(&self, note: Note) -> i32 {
+        let offset = note.index() - self.middle_note.index();
+        if self.keys.is_empty() {
+            return offset;
         }
+        let size = self.keys.len() as i32;
+        let repetition = offset.div_euclid(size);
+        let index = offset.rem_euclid(size) as usize;
+        // Unmapped keys play the closest mapped key below them
+        let degree = self.keys[..=index]
+            .iter()
+            .rev()
+            .find_map(|degree| *degree)
+            .unwrap_or(0);
+        repetition * self.period_degree + degree
+    }
+}
+
+/// Possible errors when loading Scala files.
+#[derive(Debug, Snafu)]
+pub enum ScalaError {
+    #[snafu(display("Could not read {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Line {}: {}", line, message))]
+    Syntax { line: usize, message: &'static str },
+    #[snafu(display("The file ended unexpectedly"))]
+    UnexpectedEnd,
+}
+
+/// The lines of a Scala file with their line number, without comments and surrounding whitespace.
+fn scala_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| !text.starts_with('!'))
+        .map(|(index, text)| (index + 1, text.trim()))
+}
+
+fn first_word(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or("")
+}
+
+/// The first word of a line of a `.kbm` file, with the error to report if it is invalid.
+struct Word {
+    line: usize,
+    text: String,
+    message: &'static str,
+}
+
+impl Word {
+    fn parse<T: FromStr>(&self) -> Result<T, ScalaError> {
+        self.text.parse().ok().context(Syntax {
+            line: self.line,
+            message: self.message,
+        })
+    }
+
+    fn note(&self) -> Result<Note, ScalaError> {
+        Note::try_from_midi(self.parse()?).context(Syntax {
+            line: self.line,
+            message: self.message,
+        })
+    }
+}
+
+/// Parse a pitch of a Scala scale into cents.
+fn parse_pitch(text: &str) -> Option<f64> {
+    if text.contains('.') {
+        return text.parse().ok();
+    }
+    let (numerator, denominator) = match text.find('/') {
+        Some(slash) => (&text[..slash], &text[slash + 1..]),
+        None => (text, "1"),
+    };
+    let ratio = numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?;
+    if ratio > 0.0 {
+        Some(1200.0 * ratio.log2())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyboardMapping, Scale, Tuning};
+    use syntxt_core::note::Note;
+
+    const MAJOR: &str = "! major.scl
+!
+Just major scale
+7
+!
+9/8
+5/4
+4/3
+3/2
+5/3
+15/8
+2/1
+";
+
+    #[test]
+    fn just_major_on_white_keys() {
+        let scale = Scale::parse_scl(MAJOR).unwrap();
+        assert_eq!(scale.pitches.len(), 7);
+        // The white keys from c4 on play the scale, the black keys are skipped
+        let kbm = "! white.kbm
+12
+0
+127
+60
+60
+261.6
+7
+0
+x
+1
+x
+2
+3
+x
+4
+x
+5
+x
+6
+";
+        let tuning = KeyboardMapping::parse_kbm(kbm, scale).unwrap();
+        assert_eq!(tuning.frequency(Note::from_midi(60)), 261.6);
+        let fifth = tuning.frequency(Note::from_midi(67)) / 261.6;
+        assert!((fifth - 1.5).abs() < 1e-9);
+        let octave = tuning.frequency(Note::from_midi(72)) / 261.6;
+        assert!((octave - 2.0).abs() < 1e-9);
+        let below = tuning.frequency(Note::from_midi(57)) / 261.6;
+        assert!((below - 5.0 / 6.0).abs() < 1e-9);
+        // The black key c#4 is unmapped and plays like c4
+        assert_eq!(tuning.frequency(Note::from_midi(61)), 261.6);
+    }
+
+    #[test]
+    fn scale_without_mapping() {
+        let scale = Scale::parse_scl(MAJOR).unwrap();
+        let tuning = Tuning {
+            scale,
+            ..Tuning::default()
+        };
+        let a4 = Note::from_midi(69);
+        assert_eq!(tuning.frequency(a4), 440.0);
+        assert!((tuning.frequency(Note::from_midi(69 + 7)) - 880.0).abs() < 1e-9);
+        assert!((tuning.frequency(Note::from_midi(69 + 2)) - 550.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(Scale::parse_scl("description\nseven\n").is_err());
+        assert!(Scale::parse_scl("description\n2\n3/2\n").is_err());
+        assert!(Scale::parse_scl("description\n1\n-3/2\n").is_err());
+        assert_eq!(Scale::parse_scl("\n0\n").unwrap().cents(3), 0.0,);
+    }
+
+    #[test]
+    fn equal_divisions() {
+        let edo24 = Tuning::equal(24);
+        let quarter = edo24.frequency(Note::from_midi(70)) / 440.0;
+        assert!((quarter - 2.0f64.powf(1.0 / 24.0)).abs() < 1e-12);
+        assert_eq!(Tuning::equal(12).frequency(Note::from_midi(57)), 220.0);
     }
 }
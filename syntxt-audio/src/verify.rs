@@ -0,0 +1,290 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Checking that the engine still renders songs the same way, either by a checksum
+//! of the whole output or by comparing two renders sample by sample.
+//!
+//! ```
+//! use syntxt_audio::play::{render, Options};
+//! use syntxt_audio::song::Song;
+//! use syntxt_audio::verify::{compare, Checksum};
+//! let source = "Song { bpm: 240 Track { Sequence { notes: [[ c4 e4 ]] } } }";
+//! let render_frames = || {
+//!     render(Song::from_source(source).unwrap(), &Options::default())
+//!         .unwrap()
+//!         .flat_map(|block| block.frames)
+//!         .collect::<Vec<_>>()
+//! };
+//! let (first, second) = (render_frames(), render_frames());
+//! assert_eq!(compare(&first, &second, 0.0), Ok(()));
+//! assert_eq!(Checksum::of(&first), Checksum::of(&second));
+//! ```
+
+use std::fmt;
+
+use crate::wave::Frame;
+
+/// Resolution of the samples entering a checksum, that of 24 bit files.
+const CHECKSUM_STEPS: f64 = 8_388_608.0;
+
+/// A hash of rendered audio that stays the same between runs, builds and versions of Rust.
+///
+/// Samples are rounded to 24 bits before hashing, so that tiny differences in floating point
+/// arithmetic rarely change it, while anything audible does. Use `compare` for renders that
+/// are only expected to be close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checksum {
+    /// 64 bit FNV-1a hash of the rounded samples
+    hash: u64,
+    frames: usize,
+    channels: usize,
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self {
+            hash: 0xcbf2_9ce4_8422_2325,
+            frames: 0,
+            channels: 0,
+        }
+    }
+}
+
+impl Checksum {
+    /// The checksum of no audio, to be fed with blocks of a render.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The checksum of a complete render.
+    pub fn of(frames: &[Frame]) -> Self {
+        let mut checksum = Self::new();
+        checksum.feed(frames);
+        checksum
+    }
+
+    /// Include the next frames of the audio.
+    pub fn feed(&mut self, frames: &[Frame]) {
+        for frame in frames {
+            if frame.channels() != self.channels {
+                // Starting with or switching to another layout changes the hash
+                self.channels = frame.channels();
+                self.write(&(self.channels as u32).to_le_bytes());
+            }
+            for value in frame.values() {
+                let rounded = (value * CHECKSUM_STEPS)
+                    .round()
+                    .clamp(-CHECKSUM_STEPS, CHECKSUM_STEPS - 1.0)
+                    as i32;
+                self.write(&rounded.to_le_bytes());
+            }
+        }
+        self.frames += frames.len();
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// The hash of everything fed so far.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Number of frames fed so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+}
+
+/// The hash as 16 hexadecimal digits, the form accepted by `--verify`.
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.hash)
+    }
+}
+
+/// The first way in which a render differs from the expected one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// The renders have different lengths in frames.
+    Length { expected: usize, actual: usize },
+    /// A frame has a different number of channels.
+    Channels {
+        frame: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// A sample differs by more than the tolerance.
+    Sample {
+        frame: usize,
+        channel: usize,
+        expected: f64,
+        actual: f64,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Length { expected, actual } => write!(
+                f,
+                "the render has {} frames instead of {}",
+                actual, expected
+            ),
+            Mismatch::Channels {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "frame {} has {} channels instead of {}",
+                frame, actual, expected
+            ),
+            Mismatch::Sample {
+                frame,
+                channel,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "channel {} of frame {} is {} instead of {}",
+                channel, frame, actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Compare two renders sample by sample, allowing every sample to differ by up to `tolerance`.
+/// Returns the first frame where they differ by more, or the difference in length.
+///
+/// NaN samples only match NaN samples, so that a broken render never passes.
+pub fn compare(expected: &[Frame], actual: &[Frame], tolerance: f64) -> Result<(), Mismatch> {
+    for (index, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        if expected.channels() != actual.channels() {
+            return Err(Mismatch::Channels {
+                frame: index,
+                expected: expected.channels(),
+                actual: actual.channels(),
+            });
+        }
+        let values = expected.values().iter().zip(actual.values().iter());
+        for (channel, (&expected, &actual)) in values.enumerate() {
+            let close = if expected.is_nan() || actual.is_nan() {
+                expected.is_nan() && actual.is_nan()
+            } else {
+                (expected - actual).abs() <= tolerance
+            };
+            if !close {
+                return Err(Mismatch::Sample {
+                    frame: index,
+                    channel,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+    if expected.len() != actual.len() {
+        return Err(Mismatch::Length {
+            expected: expected.len(),
+            actual: actual.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, Checksum, Mismatch};
+    use crate::wave::{Frame, Stereo};
+
+    fn frames(values: &[f64]) -> Vec<Frame> {
+        values
+            .iter()
+            .map(|&value| Frame::from(Stereo::new(value, -value)))
+            .collect()
+    }
+
+    #[test]
+    fn checksum() {
+        let audio = frames(&[0.0, 0.25, 0.5, -1.0]);
+        // Known value, which must never change
+        assert_eq!(Checksum::of(&audio).to_string(), "18be08a4074e2bcb");
+        assert_eq!(Checksum::of(&audio).frames(), 4);
+
+        // Feeding blocks gives the same hash as the whole render
+        let mut blocks = Checksum::new();
+        blocks.feed(&audio[..1]);
+        blocks.feed(&audio[1..]);
+        assert_eq!(blocks, Checksum::of(&audio));
+
+        // Differences below 24 bits do not matter, audible ones do
+        assert_eq!(
+            Checksum::of(&frames(&[0.0, 0.25 + 1e-12, 0.5, -1.0])),
+            Checksum::of(&audio)
+        );
+        assert_ne!(
+            Checksum::of(&frames(&[0.0, 0.25, 0.5001, -1.0])),
+            Checksum::of(&audio)
+        );
+        assert_ne!(
+            Checksum::of(&[Frame::silent(4)]),
+            Checksum::of(&frames(&[0.0, 0.0]))
+        );
+    }
+
+    #[test]
+    fn comparison() {
+        let audio = frames(&[0.0, 0.25, 0.5]);
+        assert_eq!(compare(&audio, &audio, 0.0), Ok(()));
+        assert_eq!(compare(&audio, &frames(&[0.0, 0.2501, 0.5]), 1e-3), Ok(()));
+        assert_eq!(
+            compare(&audio, &frames(&[0.0, 0.26, 0.5]), 1e-3),
+            Err(Mismatch::Sample {
+                frame: 1,
+                channel: 0,
+                expected: 0.25,
+                actual: 0.26
+            })
+        );
+        assert_eq!(
+            compare(&audio, &audio[..2], 0.0),
+            Err(Mismatch::Length {
+                expected: 3,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            compare(&audio, &[Frame::silent(4)], 0.0),
+            Err(Mismatch::Channels {
+                frame: 0,
+                expected: 2,
+                actual: 4
+            })
+        );
+        assert!(compare(&frames(&[f64::NAN]), &frames(&[0.0]), 1.0).is_err());
+        assert_eq!(
+            compare(&frames(&[f64::NAN]), &frames(&[f64::NAN]), 0.0),
+            Ok(())
+        );
+    }
+}
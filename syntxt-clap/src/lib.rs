@@ -0,0 +1,910 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The syn.txt engine as an instrument in the CLAP plugin format.
+//!
+//! The plugin plays a song written in the syn.txt language along with the transport of the
+//! host and at its tempo. The song is read from the file named by the `SYNTXT_SONG` environment
+//! variable and read again whenever that file changes, so that it can be edited in any editor
+//! while the host is playing. The source of the song is saved with the project of the host.
+//!
+//! Parsing the song and preparing the rendering takes too long for the audio thread, so the
+//! audio thread asks the host to call the plugin on the main thread, which prepares it there.
+
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use syntxt_audio::play;
+use syntxt_audio::plugin::clap;
+use syntxt_audio::song::{Song, Time};
+use syntxt_audio::wave::Stereo;
+
+/// Environment variable naming the song file that is played.
+pub const SONG_VARIABLE: &str = "SYNTXT_SONG";
+
+/// Resolution of the positions where rendering starts after the transport jumped.
+const TICKS_PER_BEAT: i64 = 960;
+
+/// Time in seconds after which the song file is checked for changes again.
+const RELOAD_SECONDS: f64 = 0.5;
+
+/// Makes constant data containing pointers usable in statics.
+#[repr(transparent)]
+struct Constant<T>(T);
+
+unsafe impl<T> Sync for Constant<T> {}
+
+const PLUGIN_ID: &str = "com.github.fatho.syn-txt\0";
+
+static FEATURES: Constant<[*const c_char; 4]> = Constant([
+    "instrument\0".as_ptr() as *const c_char,
+    "synthesizer\0".as_ptr() as *const c_char,
+    "stereo\0".as_ptr() as *const c_char,
+    ptr::null(),
+]);
+
+static DESCRIPTOR: Constant<clap::PluginDescriptor> = Constant(clap::PluginDescriptor {
+    clap_version: clap::VERSION,
+    id: PLUGIN_ID.as_ptr() as *const c_char,
+    name: "syn.txt\0".as_ptr() as *const c_char,
+    vendor: "syn.txt\0".as_ptr() as *const c_char,
+    url: "https://github.com/fatho/syn-txt\0".as_ptr() as *const c_char,
+    manual_url: ptr::null(),
+    support_url: ptr::null(),
+    version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+    description: "Plays songs written in the syn.txt language\0".as_ptr() as *const c_char,
+    features: &FEATURES.0 as *const [*const c_char; 4] as *const *const c_char,
+});
+
+/// The symbol through which hosts find the plugin.
+#[allow(non_upper_case_globals)]
+#[no_mangle]
+pub static clap_entry: clap::PluginEntry = clap::PluginEntry {
+    clap_version: clap::VERSION,
+    init: entry_init,
+    deinit: entry_deinit,
+    get_factory,
+};
+
+static FACTORY: clap::PluginFactory = clap::PluginFactory {
+    get_plugin_count,
+    get_plugin_descriptor,
+    create_plugin,
+};
+
+static STATE: clap::PluginState = clap::PluginState {
+    save: state_save,
+    load: state_load,
+};
+
+static AUDIO_PORTS: clap::PluginAudioPorts = clap::PluginAudioPorts {
+    count: audio_ports_count,
+    get: audio_ports_get,
+};
+
+/// An instance of the plugin, whose functions may be called on the main thread
+/// and the audio thread at the same time.
+#[repr(C)]
+struct Instance {
+    /// Passed to the host, which gives it back to every function.
+    plugin: clap::Plugin,
+    host: *const clap::Host,
+    source: Mutex<Source>,
+    /// The song file, only used on the main thread.
+    watched: Mutex<Watched>,
+    /// Whether there is a song file to watch.
+    watching: bool,
+    /// Only used on the audio thread while the plugin is active.
+    player: Mutex<Option<Player>>,
+    handoff: Mutex<Handoff>,
+}
+
+/// The current source of the song, passed from the main thread to the audio thread.
+#[derive(Default)]
+struct Source {
+    text: Option<String>,
+    /// Increased with every change of the text.
+    version: u64,
+}
+
+/// Passes requests for rendering from the audio thread to the main thread, and the renders
+/// prepared for them back.
+#[derive(Default)]
+struct Handoff {
+    request: Option<Request>,
+    prepared: Option<Prepared>,
+}
+
+/// Where the audio thread needs the song to be rendered from.
+#[derive(Clone, Copy)]
+struct Request {
+    /// Increased with every request, so that renders prepared for older ones are dropped.
+    id: u64,
+    sample_rate: f64,
+    /// Position in quarter notes where rendering starts.
+    beats: f64,
+    /// Tempo of the host, replacing the one of the song.
+    bpm: Option<i64>,
+}
+
+/// Rendering of the song, prepared on the main thread.
+///
+/// The render is only moved to the audio thread as a whole, so the reference counted buffers
+/// inside of it are never shared between the threads.
+struct Prepared {
+    request: Request,
+    /// Tempo of the song, if the host has none.
+    bpm: Option<i64>,
+    render: Option<play::Render>,
+    /// Samples of silence before the song starts.
+    silence: usize,
+    /// Samples rendered before the requested position.
+    skip: usize,
+}
+
+impl Prepared {
+    /// Parse the song and start rendering it at the requested position.
+    fn new(request: Request, source: Option<&str>) -> Self {
+        let mut prepared = Self {
+            request,
+            bpm: None,
+            render: None,
+            silence: 0,
+            skip: 0,
+        };
+        let mut song = match source.map(Song::from_source) {
+            Some(Ok(song)) => song,
+            _ => return prepared,
+        };
+        let bpm = match request.bpm {
+            Some(bpm) => bpm,
+            None => song.bpm,
+        };
+        song.bpm = bpm;
+        prepared.bpm = Some(bpm);
+        let seconds_per_beat = 60.0 / bpm as f64;
+        let beats = request.beats;
+        prepared.silence = (-beats * seconds_per_beat * request.sample_rate)
+            .round()
+            .max(0.0) as usize;
+
+        // Rendering starts at the tick before the position, whose samples are skipped
+        let ticks = (beats.max(0.0) * TICKS_PER_BEAT as f64).floor() as i64;
+        let offset = beats.max(0.0) - ticks as f64 / TICKS_PER_BEAT as f64;
+        prepared.skip = (offset * seconds_per_beat * request.sample_rate).round() as usize;
+        let options = play::Options {
+            sample_rate: request.sample_rate as i64,
+            from: Some(Time::new(ticks, 4 * TICKS_PER_BEAT)),
+            ..play::Options::default()
+        };
+        // Nothing is rendered after the end of the song
+        prepared.render = play::render(song, &options).ok();
+        prepared
+    }
+}
+
+struct Watched {
+    path: Option<PathBuf>,
+    /// Modification time of the file when it was last read.
+    modified: Option<SystemTime>,
+}
+
+impl Instance {
+    /// Read the song file if it changed since it was last read.
+    fn reload(&self) {
+        let mut watched = self.watched.lock().unwrap();
+        let path = match watched.path.as_ref() {
+            Some(path) => path,
+            None => return,
+        };
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == watched.modified {
+            return;
+        }
+        if let Ok(text) = std::fs::read_to_string(path) {
+            watched.modified = modified;
+            self.set_source(text);
+        }
+    }
+
+    fn set_source(&self, text: String) {
+        let mut source = self.source.lock().unwrap();
+        source.text = Some(text);
+        source.version += 1;
+    }
+
+    /// Prepare the rendering requested by the audio thread, if any.
+    fn prepare(&self) {
+        loop {
+            let request = match self.handoff.lock().unwrap().request.take() {
+                Some(request) => request,
+                None => return,
+            };
+            let text = self.source.lock().unwrap().text.clone();
+            let prepared = Prepared::new(request, text.as_deref());
+            let mut handoff = self.handoff.lock().unwrap();
+            // Otherwise the audio thread already asked for another position
+            if handoff.request.is_none() {
+                handoff.prepared = Some(prepared);
+                return;
+            }
+        }
+    }
+}
+
+/// Rendering of the song following the transport of the host.
+struct Player {
+    sample_rate: f64,
+    /// The version of the source of the song being rendered.
+    version: u64,
+    render: Option<play::Render>,
+    /// Rendered samples not yet output, starting `skip` samples before the current position.
+    queue: VecDeque<Stereo<f64>>,
+    skip: usize,
+    /// Samples of silence before the song starts, e.g. while the host counts in.
+    silence: usize,
+    /// Position of the next sample in quarter notes, if playing started.
+    beats: Option<f64>,
+    /// Tempo at which the song is rendered.
+    bpm: i64,
+    /// Samples output since the host was last asked to check the song file.
+    since_reload: usize,
+    /// Number of requests for rendering so far.
+    requests: u64,
+    /// The request whose rendering is awaited, and whether it was passed to the main thread.
+    waiting: Option<Request>,
+    sent: bool,
+    /// Whether the host should call the plugin on the main thread.
+    callback: bool,
+}
+
+impl Player {
+    fn new(sample_rate: f64) -> Self {
+        Self {
+            sample_rate,
+            version: 0,
+            render: None,
+            queue: VecDeque::new(),
+            skip: 0,
+            silence: 0,
+            beats: None,
+            bpm: 120,
+            since_reload: 0,
+            requests: 0,
+            waiting: None,
+            sent: false,
+            callback: false,
+        }
+    }
+
+    /// Render the next samples while the host is playing, and silence otherwise.
+    fn process(
+        &mut self,
+        transport: Option<&clap::EventTransport>,
+        source: &Mutex<Source>,
+        handoff: &Mutex<Handoff>,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        // Without a transport, the host is assumed to play from the beginning
+        let flags = transport.map_or(clap::TRANSPORT_IS_PLAYING, |t| t.flags);
+        if flags & clap::TRANSPORT_IS_PLAYING == 0 {
+            left.iter_mut()
+                .chain(right.iter_mut())
+                .for_each(|x| *x = 0.0);
+            return;
+        }
+        let bpm = transport
+            .filter(|_| flags & clap::TRANSPORT_HAS_TEMPO != 0)
+            .map(|t| t.tempo.round().max(1.0) as i64);
+        let position = transport
+            .filter(|_| flags & clap::TRANSPORT_HAS_BEATS_TIMELINE != 0)
+            .map(|t| t.song_pos_beats as f64 / clap::BEATTIME_FACTOR as f64);
+
+        let mut restart = false;
+        // The song is picked up on the next block if the main thread is just changing it
+        if let Ok(source) = source.try_lock() {
+            if source.version != self.version {
+                self.version = source.version;
+                restart = true;
+            }
+        }
+        restart |= bpm.map_or(false, |bpm| bpm != self.bpm);
+        let frames = left.len();
+        let beats = match (self.beats, position) {
+            (None, position) => {
+                restart = true;
+                position.unwrap_or(0.0)
+            }
+            (Some(expected), Some(position)) => {
+                // The transport jumped if it is off by more than a block
+                let block = frames as f64 * self.bpm as f64 / 60.0 / self.sample_rate;
+                restart |= (position - expected).abs() > block;
+                position
+            }
+            (Some(expected), None) => expected,
+        };
+        if restart {
+            self.request(beats, bpm);
+        }
+        self.exchange(handoff, beats);
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let sample = self.next_sample();
+            *left = sample.left as f32;
+            *right = sample.right as f32;
+        }
+        self.beats = Some(beats + frames as f64 * self.bpm as f64 / 60.0 / self.sample_rate);
+        self.since_reload += frames;
+    }
+
+    /// Stop playing and ask for the song to be rendered from a position in quarter notes.
+    fn request(&mut self, beats: f64, bpm: Option<i64>) {
+        self.render = None;
+        self.queue.clear();
+        self.silence = 0;
+        self.skip = 0;
+        if let Some(bpm) = bpm {
+            self.bpm = bpm;
+        }
+        self.requests += 1;
+        self.waiting = Some(Request {
+            id: self.requests,
+            sample_rate: self.sample_rate,
+            beats,
+            bpm,
+        });
+        self.sent = false;
+    }
+
+    /// Pass the awaited request to the main thread and take the rendering prepared for it,
+    /// where `beats` is the current position.
+    fn exchange(&mut self, handoff: &Mutex<Handoff>, beats: f64) {
+        let request = match self.waiting {
+            Some(request) => request,
+            None => return,
+        };
+        // The main thread only holds the lock for a moment, otherwise this is retried on the next block
+        let mut handoff = match handoff.try_lock() {
+            Ok(handoff) => handoff,
+            Err(_) => return,
+        };
+        if !self.sent {
+            handoff.request = Some(request);
+            self.sent = true;
+            self.callback = true;
+            return;
+        }
+        let prepared = match handoff.prepared.take() {
+            Some(prepared) if prepared.request.id == request.id => prepared,
+            _ => return,
+        };
+        drop(handoff);
+
+        self.waiting = None;
+        if let Some(bpm) = prepared.bpm {
+            self.bpm = bpm;
+        }
+        self.render = prepared.render;
+        // The transport moved on while the rendering was prepared
+        let late = ((beats - request.beats).max(0.0) * 60.0 / self.bpm as f64 * self.sample_rate)
+            .round() as usize;
+        let silent = late.min(prepared.silence);
+        self.silence = prepared.silence - silent;
+        self.skip = prepared.skip + late - silent;
+    }
+
+    fn next_sample(&mut self) -> Stereo<f64> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Stereo::mono(0.0);
+        }
+        loop {
+            if let Some(sample) = self.queue.pop_front() {
+                if self.skip == 0 {
+                    return sample;
+                }
+                self.skip -= 1;
+                continue;
+            }
+            match self.render.as_mut().and_then(|render| render.next()) {
+                // The stereo output of the plugin gets the front speakers of other layouts
+                Some(block) => self
+                    .queue
+                    .extend(block.frames.iter().map(|frame| frame.pair(0))),
+                None => {
+                    self.render = None;
+                    return Stereo::mono(0.0);
+                }
+            }
+        }
+    }
+}
+
+/// Whether an id passed by the host is the expected one, including the terminating zero.
+unsafe fn is_id(id: *const c_char, expected: &[u8]) -> bool {
+    !id.is_null() && CStr::from_ptr(id).to_bytes_with_nul() == expected
+}
+
+unsafe fn instance<'a>(plugin: *const clap::Plugin) -> &'a Instance {
+    &*(plugin as *const Instance)
+}
+
+/// Run the body of a function called by the host, returning `default` if it panics,
+/// as unwinding into the host is undefined behavior. Functions that only return constants
+/// need no guard.
+fn guard<T>(default: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(default)
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn get_factory(factory_id: *const c_char) -> *const c_void {
+    guard(ptr::null(), || {
+        if is_id(factory_id, clap::PLUGIN_FACTORY_ID) {
+            &FACTORY as *const clap::PluginFactory as *const c_void
+        } else {
+            ptr::null()
+        }
+    })
+}
+
+unsafe extern "C" fn get_plugin_count(_factory: *const clap::PluginFactory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn get_plugin_descriptor(
+    _factory: *const clap::PluginFactory,
+    index: u32,
+) -> *const clap::PluginDescriptor {
+    if index == 0 {
+        &DESCRIPTOR.0
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn create_plugin(
+    _factory: *const clap::PluginFactory,
+    host: *const clap::Host,
+    plugin_id: *const c_char,
+) -> *const clap::Plugin {
+    guard(ptr::null(), || {
+        if !is_id(plugin_id, PLUGIN_ID.as_bytes()) {
+            return ptr::null();
+        }
+        let path = std::env::var_os(SONG_VARIABLE).map(PathBuf::from);
+        let instance = Box::new(Instance {
+            plugin: clap::Plugin {
+                desc: &DESCRIPTOR.0,
+                plugin_data: ptr::null_mut(),
+                init: plugin_init,
+                destroy: plugin_destroy,
+                activate: plugin_activate,
+                deactivate: plugin_deactivate,
+                start_processing: plugin_start_processing,
+                stop_processing: plugin_nothing,
+                reset: plugin_reset,
+                process: plugin_process,
+                get_extension: plugin_get_extension,
+                on_main_thread: plugin_on_main_thread,
+            },
+            host,
+            source: Mutex::new(Source::default()),
+            watching: path.is_some(),
+            watched: Mutex::new(Watched {
+                path,
+                modified: None,
+            }),
+            player: Mutex::new(None),
+            handoff: Mutex::new(Handoff::default()),
+        });
+        Box::into_raw(instance) as *const clap::Plugin
+    })
+}
+
+unsafe extern "C" fn plugin_init(plugin: *const clap::Plugin) -> bool {
+    guard(false, || {
+        instance(plugin).reload();
+        true
+    })
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap::Plugin) {
+    guard((), || drop(Box::from_raw(plugin as *mut Instance)))
+}
+
+unsafe extern "C" fn plugin_activate(
+    plugin: *const clap::Plugin,
+    sample_rate: f64,
+    _min_frames_count: u32,
+    _max_frames_count: u32,
+) -> bool {
+    guard(false, || {
+        let instance = instance(plugin);
+        // Renders prepared for a previous activation are not wanted anymore
+        *instance.handoff.lock().unwrap() = Handoff::default();
+        *instance.player.lock().unwrap() = Some(Player::new(sample_rate));
+        true
+    })
+}
+
+unsafe extern "C" fn plugin_deactivate(plugin: *const clap::Plugin) {
+    guard((), || *instance(plugin).player.lock().unwrap() = None)
+}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap::Plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_nothing(_plugin: *const clap::Plugin) {}
+
+unsafe extern "C" fn plugin_reset(plugin: *const clap::Plugin) {
+    guard((), || {
+        if let Some(player) = instance(plugin).player.lock().unwrap().as_mut() {
+            // Rendering starts over with the next block
+            player.beats = None;
+        }
+    })
+}
+
+unsafe extern "C" fn plugin_process(
+    plugin: *const clap::Plugin,
+    process: *const clap::Process,
+) -> i32 {
+    guard(clap::PROCESS_ERROR, || {
+        let instance = instance(plugin);
+        let process = &*process;
+        let mut player = instance.player.lock().unwrap();
+        let player = match player.as_mut() {
+            Some(player) => player,
+            None => return clap::PROCESS_ERROR,
+        };
+        if process.audio_outputs_count == 0
+            || process.audio_outputs.is_null()
+            || (*process.audio_outputs).channel_count < 2
+        {
+            return clap::PROCESS_ERROR;
+        }
+        let channels = (*process.audio_outputs).data32;
+        if channels.is_null() || (*channels).is_null() || (*channels.add(1)).is_null() {
+            return clap::PROCESS_ERROR;
+        }
+        let frames = process.frames_count as usize;
+        player.process(
+            process.transport.as_ref(),
+            &instance.source,
+            &instance.handoff,
+            std::slice::from_raw_parts_mut(*channels, frames),
+            std::slice::from_raw_parts_mut(*channels.add(1), frames),
+        );
+        let reload =
+            instance.watching && player.since_reload as f64 > RELOAD_SECONDS * player.sample_rate;
+        if reload || player.callback {
+            // The file is checked and the song is prepared on the main thread
+            if reload {
+                player.since_reload = 0;
+            }
+            player.callback = false;
+            ((*instance.host).request_callback)(instance.host);
+        }
+        clap::PROCESS_CONTINUE
+    })
+}
+
+unsafe extern "C" fn plugin_get_extension(
+    _plugin: *const clap::Plugin,
+    id: *const c_char,
+) -> *const c_void {
+    guard(ptr::null(), || {
+        if is_id(id, clap::EXT_STATE) {
+            &STATE as *const clap::PluginState as *const c_void
+        } else if is_id(id, clap::EXT_AUDIO_PORTS) {
+            &AUDIO_PORTS as *const clap::PluginAudioPorts as *const c_void
+        } else {
+            ptr::null()
+        }
+    })
+}
+
+unsafe extern "C" fn plugin_on_main_thread(plugin: *const clap::Plugin) {
+    guard((), || {
+        let instance = instance(plugin);
+        instance.reload();
+        instance.prepare();
+    })
+}
+
+unsafe extern "C" fn state_save(
+    plugin: *const clap::Plugin,
+    stream: *const clap::OutputStream,
+) -> bool {
+    guard(false, || {
+        let text = instance(plugin).source.lock().unwrap().text.clone();
+        let mut bytes = text.as_deref().unwrap_or("").as_bytes();
+        while !bytes.is_empty() {
+            let written =
+                ((*stream).write)(stream, bytes.as_ptr() as *const c_void, bytes.len() as u64);
+            if written <= 0 {
+                return false;
+            }
+            bytes = &bytes[written as usize..];
+        }
+        true
+    })
+}
+
+unsafe extern "C" fn state_load(
+    plugin: *const clap::Plugin,
+    stream: *const clap::InputStream,
+) -> bool {
+    guard(false, || {
+        let mut bytes = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let read = ((*stream).read)(
+                stream,
+                buffer.as_mut_ptr() as *mut c_void,
+                buffer.len() as u64,
+            );
+            match read {
+                0 => break,
+                read if read < 0 => return false,
+                read => bytes.extend_from_slice(&buffer[..read as usize]),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => {
+                instance(plugin).set_source(text);
+                true
+            }
+            Err(_) => false,
+        }
+    })
+}
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap::Plugin, is_input: bool) -> u32 {
+    if is_input {
+        0
+    } else {
+        1
+    }
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap::Plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap::AudioPortInfo,
+) -> bool {
+    guard(false, || {
+        if is_input || index != 0 || info.is_null() {
+            return false;
+        }
+        let info = &mut *info;
+        info.id = 0;
+        for (target, byte) in info.name.iter_mut().zip(b"output\0") {
+            *target = *byte as c_char;
+        }
+        info.flags = clap::AUDIO_PORT_IS_MAIN;
+        info.channel_count = 2;
+        info.port_type = clap::PORT_STEREO.as_ptr() as *const c_char;
+        info.in_place_pair = clap::INVALID_ID;
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::{c_char, c_void};
+    use std::ptr;
+
+    use super::{clap, FACTORY, PLUGIN_ID};
+
+    unsafe extern "C" fn get_extension(
+        _host: *const clap::Host,
+        _extension_id: *const c_char,
+    ) -> *const c_void {
+        ptr::null()
+    }
+
+    unsafe extern "C" fn request(_host: *const clap::Host) {}
+
+    unsafe extern "C" fn read(
+        stream: *const clap::InputStream,
+        buffer: *mut c_void,
+        size: u64,
+    ) -> i64 {
+        let source = &mut *((*stream).ctx as *mut &[u8]);
+        let count = source.len().min(size as usize);
+        ptr::copy_nonoverlapping(source.as_ptr(), buffer as *mut u8, count);
+        *source = &source[count..];
+        count as i64
+    }
+
+    unsafe extern "C" fn write(
+        stream: *const clap::OutputStream,
+        buffer: *const c_void,
+        size: u64,
+    ) -> i64 {
+        // Take at most a few bytes at once, like a host writing into small chunks
+        let count = (size as usize).min(5);
+        let target = &mut *((*stream).ctx as *mut Vec<u8>);
+        target.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, count));
+        count as i64
+    }
+
+    unsafe extern "C" fn no_events(_list: *const clap::InputEvents) -> u32 {
+        0
+    }
+
+    unsafe extern "C" fn no_event(
+        _list: *const clap::InputEvents,
+        _index: u32,
+    ) -> *const clap::EventHeader {
+        ptr::null()
+    }
+
+    /// Process one block, returning the left channel.
+    unsafe fn process(
+        plugin: *const clap::Plugin,
+        transport: Option<&clap::EventTransport>,
+    ) -> Vec<f32> {
+        let mut left = vec![1.0f32; 512];
+        let mut right = vec![1.0f32; 512];
+        let mut channels = [left.as_mut_ptr(), right.as_mut_ptr()];
+        let mut output = clap::AudioBuffer {
+            data32: channels.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: 2,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let events = clap::InputEvents {
+            ctx: ptr::null_mut(),
+            size: no_events,
+            get: no_event,
+        };
+        let process = clap::Process {
+            steady_time: 0,
+            frames_count: 512,
+            transport: transport.map_or(ptr::null(), |t| t as *const clap::EventTransport),
+            audio_inputs: ptr::null(),
+            audio_outputs: &mut output,
+            audio_inputs_count: 0,
+            audio_outputs_count: 1,
+            in_events: &events,
+            out_events: ptr::null(),
+        };
+        assert_eq!(
+            ((*plugin).process)(plugin, &process),
+            clap::PROCESS_CONTINUE
+        );
+        left
+    }
+
+    /// Process a block, let the main thread prepare the rendering requested while processing it,
+    /// and process another block at the same position, returning its left channel.
+    unsafe fn play(
+        plugin: *const clap::Plugin,
+        transport: Option<&clap::EventTransport>,
+    ) -> Vec<f32> {
+        process(plugin, transport);
+        ((*plugin).on_main_thread)(plugin);
+        process(plugin, transport)
+    }
+
+    fn transport(beats: f64, playing: bool) -> clap::EventTransport {
+        let playing = if playing {
+            clap::TRANSPORT_IS_PLAYING
+        } else {
+            0
+        };
+        clap::EventTransport {
+            header: clap::EventHeader {
+                size: std::mem::size_of::<clap::EventTransport>() as u32,
+                time: 0,
+                space_id: clap::CORE_EVENT_SPACE_ID,
+                type_: 9,
+                flags: 0,
+            },
+            flags: clap::TRANSPORT_HAS_TEMPO | clap::TRANSPORT_HAS_BEATS_TIMELINE | playing,
+            song_pos_beats: (beats * clap::BEATTIME_FACTOR as f64) as i64,
+            song_pos_seconds: 0,
+            tempo: 120.0,
+            tempo_inc: 0.0,
+            loop_start_beats: 0,
+            loop_end_beats: 0,
+            loop_start_seconds: 0,
+            loop_end_seconds: 0,
+            bar_start: 0,
+            bar_number: 0,
+            tsig_num: 4,
+            tsig_denom: 4,
+        }
+    }
+
+    fn silent(samples: &[f32]) -> bool {
+        samples.iter().all(|x| *x == 0.0)
+    }
+
+    #[test]
+    fn follows_transport() {
+        let host = clap::Host {
+            clap_version: clap::VERSION,
+            host_data: ptr::null_mut(),
+            name: ptr::null(),
+            vendor: ptr::null(),
+            url: ptr::null(),
+            version: ptr::null(),
+            get_extension,
+            request_restart: request,
+            request_process: request,
+            request_callback: request,
+        };
+        let source = "Song { bpm: 60 Track { Sequence { notes: [[ c4 r r r c4 ]] } } }";
+        unsafe {
+            let plugin =
+                (FACTORY.create_plugin)(&FACTORY, &host, PLUGIN_ID.as_ptr() as *const c_char);
+            assert!(!plugin.is_null());
+            assert!(((*plugin).init)(plugin));
+            let state = ((*plugin).get_extension)(plugin, clap::EXT_STATE.as_ptr() as *const c_char)
+                as *const clap::PluginState;
+            let mut remaining = source.as_bytes();
+            let input = clap::InputStream {
+                ctx: &mut remaining as *mut &[u8] as *mut c_void,
+                read,
+            };
+            assert!(((*state).load)(plugin, &input));
+            assert!(((*plugin).activate)(plugin, 44100.0, 1, 512));
+
+            // Nothing is played until the main thread prepared the song
+            assert!(silent(&process(plugin, Some(&transport(0.0, true)))));
+            ((*plugin).on_main_thread)(plugin);
+            // The tempo of the host replaces the one of the song
+            assert!(!silent(&process(plugin, Some(&transport(0.0, true)))));
+            assert!(silent(&process(plugin, Some(&transport(0.0, false)))));
+            // Between the notes, and at the second note after jumping there
+            assert!(silent(&play(plugin, Some(&transport(3.0, true)))));
+            assert!(!silent(&play(plugin, Some(&transport(4.0, true)))));
+            // Continuing without a transport
+            assert!(!silent(&process(plugin, None)));
+            // After the end of the song
+            assert!(silent(&play(plugin, Some(&transport(100.0, true)))));
+
+            let mut saved = Vec::new();
+            let output = clap::OutputStream {
+                ctx: &mut saved as *mut Vec<u8> as *mut c_void,
+                write,
+            };
+            assert!(((*state).save)(plugin, &output));
+            assert_eq!(saved, source.as_bytes());
+
+            ((*plugin).deactivate)(plugin);
+            ((*plugin).destroy)(plugin);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The settings of a project in `syntxt.toml`, found in the directory of a song or above it.
+//!
+//! ```toml
+//! # Used where the command line does not say otherwise
+//! sample-rate = 48000
+//! # The output of `syntxt build` without --output, e.g. song.flac
+//! format = "flac"
+//! bits = 24
+//! # Where recordings and imported files are looked for after the directory of the song
+//! samples = ["samples", "/usr/share/samples"]
+//! # Where plugins are looked for when they are not found where the song says
+//! plugins = ["plugins"]
+//! # The tuning of all songs, either equal steps per octave or a Scala file
+//! tuning = "tunings/just.scl"
+//! keyboard-mapping = "tunings/just.kbm"
+//! ```
+//!
+//! Relative paths are relative to the directory of `syntxt.toml`.
+//! Only this subset of TOML is understood: keys with strings, numbers, booleans and arrays
+//! of them on one line, and comments.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use syntxt_audio::play::RenderArgs;
+use syntxt_audio::song::{Effect, Instrument, Song};
+use syntxt_audio::tuning::Tuning;
+use syntxt_lang::model;
+
+/// Name of the file with the settings of a project.
+pub const FILE_NAME: &str = "syntxt.toml";
+
+/// The settings of a project, all optional.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The file the settings were read from, if any.
+    pub path: Option<PathBuf>,
+    pub sample_rate: Option<i64>,
+    /// Extension of the files written by `syntxt build` without an output, e.g. `flac`.
+    pub format: Option<String>,
+    pub bits: Option<u32>,
+    /// Directories with recordings and other files a song refers to.
+    pub samples: Vec<PathBuf>,
+    /// Directories with plugins.
+    pub plugins: Vec<PathBuf>,
+    /// Replaces the concert tuning of songs.
+    pub tuning: Option<Tuning>,
+}
+
+impl Config {
+    /// The settings of the project containing a song, from the first `syntxt.toml` in its
+    /// directory or the directories above. Without one, everything is left to the defaults.
+    pub fn find(song: &Path) -> io::Result<Config> {
+        let directory = match song.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let directory = directory
+            .canonicalize()
+            .unwrap_or_else(|_| directory.to_path_buf());
+        match directory
+            .ancestors()
+            .map(|ancestor| ancestor.join(FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Read the settings from a file.
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let source = std::fs::read_to_string(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let settings = parse(&source).map_err(invalid)?;
+        let mut config = Config::from_settings(directory, settings).map_err(invalid)?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// The settings given by keys and their values, with paths relative to a directory.
+    fn from_settings(directory: &Path, settings: Vec<Setting>) -> Result<Config, String> {
+        let mut config = Config::default();
+        // The tuning is loaded once the keyboard mapping is known as well
+        let mut steps = None;
+        let mut scale = None;
+        let mut mapping = None;
+        for Setting { line, key, value } in settings {
+            let expected = |what: &str| Err(format!("line {}: `{}` must be {}", line, key, what));
+            match (key.as_str(), value) {
+                ("sample-rate", Value::Integer(rate)) if rate > 0 => {
+                    config.sample_rate = Some(rate)
+                }
+                ("sample-rate", _) => return expected("a positive number of Hz"),
+                ("format", Value::String(format)) if !format.is_empty() => {
+                    config.format = Some(format.trim_start_matches('.').to_string())
+                }
+                ("format", _) => return expected("a file extension like \"flac\""),
+                ("bits", Value::Integer(bits)) if bits > 0 && bits <= 64 => {
+                    config.bits = Some(bits as u32)
+                }
+                ("bits", _) => return expected("a number of bits like 16 or 24"),
+                ("samples", value) => match paths(directory, value) {
+                    Some(paths) => config.samples = paths,
+                    None => return expected("a list of directories"),
+                },
+                ("plugins", value) => match paths(directory, value) {
+                    Some(paths) => config.plugins = paths,
+                    None => return expected("a list of directories"),
+                },
+                ("tuning", Value::Integer(divisions)) if divisions > 0 => {
+                    steps = Some(divisions as usize)
+                }
+                ("tuning", Value::String(file)) => scale = Some(directory.join(file)),
+                ("tuning", _) => {
+                    return expected("a number of equal steps per octave or a Scala file")
+                }
+                ("keyboard-mapping", Value::String(file)) => mapping = Some(directory.join(file)),
+                ("keyboard-mapping", _) => return expected("a Scala keyboard mapping file"),
+                (_, _) => return Err(format!("line {}: unknown setting `{}`", line, key)),
+            }
+        }
+        config.tuning = match (steps, scale, mapping) {
+            (Some(steps), _, None) => Some(Tuning::equal(steps)),
+            (_, Some(scale), mapping) => Some(
+                Tuning::load_scala(&scale, mapping.as_deref()).map_err(|err| err.to_string())?,
+            ),
+            (_, _, Some(_)) => {
+                return Err("a `keyboard-mapping` needs a Scala file as `tuning`".into())
+            }
+            (None, None, None) => None,
+        };
+        Ok(config)
+    }
+
+    /// The song to render from its model, with the tuning and plugins of the project.
+    pub fn song(&self, model: &model::Song) -> Song {
+        let mut song = Song::from_model(model);
+        if let Some(tuning) = &self.tuning {
+            song.tuning = tuning.clone();
+        }
+        let plugins = song
+            .tracks
+            .iter_mut()
+            .flat_map(|track| {
+                let instrument = match &mut track.instrument {
+                    Instrument::Plugin(params) => Some(&mut params.path),
+                    _ => None,
+                };
+                instrument
+                    .into_iter()
+                    .chain(effect_plugins(&mut track.effects))
+            })
+            .chain(
+                song.buses
+                    .iter_mut()
+                    .flat_map(|bus| effect_plugins(&mut bus.effects)),
+            )
+            .chain(effect_plugins(&mut song.effects));
+        for path in plugins {
+            if !path.exists() {
+                if let Some(found) = self
+                    .plugins
+                    .iter()
+                    .map(|directory| directory.join(&path))
+                    .find(|found| found.exists())
+                {
+                    *path = found;
+                }
+            }
+        }
+        song
+    }
+
+    /// Where the files a song refers to are looked for: next to the song, then in the
+    /// sample directories.
+    pub fn files(&self, song: &Path) -> model::SearchPath {
+        let directory = song.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        model::SearchPath(
+            std::iter::once(directory)
+                .chain(self.samples.iter().cloned())
+                .collect(),
+        )
+    }
+
+    /// Fill in the settings for rendering that are not given on the command line.
+    pub fn apply(&self, render: &mut RenderArgs) {
+        render.defaults(self.sample_rate, self.bits);
+    }
+}
+
+/// The paths of the plugin effects.
+fn effect_plugins(effects: &mut [Effect]) -> impl Iterator<Item = &mut PathBuf> {
+    effects.iter_mut().filter_map(|effect| match effect {
+        Effect::Plugin(params) => Some(&mut params.path),
+        _ => None,
+    })
+}
+
+/// A list of directories relative to a directory, also given as a single string.
+fn paths(directory: &Path, value: Value) -> Option<Vec<PathBuf>> {
+    let values = match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::String(path) => Some(directory.join(path)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A key of a configuration file and its value.
+#[derive(Debug, PartialEq)]
+struct Setting {
+    line: usize,
+    key: String,
+    value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// Read the keys and values of a configuration file in order.
+fn parse(source: &str) -> Result<Vec<Setting>, String> {
+    let mut settings: Vec<Setting> = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: &str| Err(format!("line {}: {}", line, message));
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        if text.starts_with('[') {
+            return error("tables are not supported, only keys with values");
+        }
+        let equals = match text.find('=') {
+            Some(equals) => equals,
+            None => return error("expected `key = value`"),
+        };
+        let key = text[..equals].trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return error("expected a key of letters, digits, `-` and `_`");
+        }
+        if settings.iter().any(|setting| setting.key == key) {
+            return Err(format!("line {}: `{}` is set twice", line, key));
+        }
+        let mut rest = text[equals + 1..].chars().peekable();
+        let value = match value(&mut rest) {
+            Ok(value) => value,
+            Err(message) => return error(&message),
+        };
+        skip_space(&mut rest);
+        if !matches!(rest.peek(), None | Some('#')) {
+            return error("expected the end of the line after the value");
+        }
+        settings.push(Setting {
+            line,
+            key: key.to_string(),
+            value,
+        });
+    }
+    Ok(settings)
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_space(chars: &mut Chars) {
+    while matches!(chars.peek(), Some(' ') | Some('\t')) {
+        chars.next();
+    }
+}
+
+fn value(chars: &mut Chars) -> Result<Value, String> {
+    skip_space(chars);
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(Value::String(string)),
+                    Some('\\') => match chars.next() {
+                        Some('n') => string.push('\n'),
+                        Some('t') => string.push('\t'),
+                        Some('"') => string.push('"'),
+                        Some('\\') => string.push('\\'),
+                        _ => return Err("unknown escape sequence in string".into()),
+                    },
+                    Some(c) => string.push(c),
+                    None => return Err("the string is not closed".into()),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            loop {
+                skip_space(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(Value::Array(values));
+                }
+                values.push(value(chars)?);
+                skip_space(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(values)),
+                    _ => return Err("expected `,` or `]` in the list".into()),
+                }
+            }
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) = chars.peek().copied() {
+                if !(c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.' || c == '_') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let number = word.replace('_', "");
+            match word.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                _ => match (number.parse::<i64>(), number.parse::<f64>()) {
+                    (Ok(integer), _) => Ok(Value::Integer(integer)),
+                    (_, Ok(float)) => Ok(Value::Float(float)),
+                    _ => Err("expected a string, number, boolean or list".into()),
+                },
+            }
+        }
+        None => Err("expected a value".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Config, Setting, Value};
+    use std::path::Path;
+
+    #[test]
+    fn values() {
+        let settings = parse(
+            r#"
+# comment
+format = "flac" # comment
+sample-rate = 48_000
+gain = -1.5
+loud = true
+samples = ["a", "b\"c", ]
+"#,
+        )
+        .unwrap();
+        let setting = |line, key: &str, value| Setting {
+            line,
+            key: key.to_string(),
+            value,
+        };
+        assert_eq!(
+            settings,
+            vec![
+                setting(3, "format", Value::String("flac".into())),
+                setting(4, "sample-rate", Value::Integer(48000)),
+                setting(5, "gain", Value::Float(-1.5)),
+                setting(6, "loud", Value::Boolean(true)),
+                setting(
+                    7,
+                    "samples",
+                    Value::Array(vec![
+                        Value::String("a".into()),
+                        Value::String("b\"c".into())
+                    ])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let error = |source: &str| parse(source).unwrap_err();
+        assert_eq!(
+            error("[render]"),
+            "line 1: tables are not supported, only keys with values"
+        );
+        assert_eq!(error("\nformat"), "line 2: expected `key = value`");
+        assert_eq!(error("format = \"flac"), "line 1: the string is not closed");
+        assert_eq!(
+            error("bits = 16 24"),
+            "line 1: expected the end of the line after the value"
+        );
+        assert_eq!(error("bits = 16\nbits = 24"), "line 2: `bits` is set twice");
+    }
+
+    #[test]
+    fn settings() {
+        let config = Config::from_settings(
+            Path::new("project"),
+            parse("sample-rate = 48000\nformat = \".ogg\"\nsamples = \"samples\"\ntuning = 19")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config.sample_rate, Some(48000));
+        assert_eq!(config.format.as_deref(), Some("ogg"));
+        assert_eq!(config.samples, vec![Path::new("project/samples")]);
+        assert!(config.tuning.is_some());
+        let error = |source: &str| {
+            Config::from_settings(Path::new(""), parse(source).unwrap()).unwrap_err()
+        };
+        assert_eq!(
+            error("sample-rate = \"fast\""),
+            "line 1: `sample-rate` must be a positive number of Hz"
+        );
+        assert_eq!(error("volume = 1"), "line 1: unknown setting `volume`");
+    }
+
+    #[test]
+    fn find() {
+        let directory = std::env::temp_dir().join(format!("syntxt-config-{}", std::process::id()));
+        let songs = directory.join("songs");
+        std::fs::create_dir_all(&songs).unwrap();
+        std::fs::write(directory.join("syntxt.toml"), "bits = 16\n").unwrap();
+        let config = Config::find(&songs.join("song.syn")).unwrap();
+        assert_eq!(config.bits, Some(16));
+        assert!(config.path.is_some());
+        std::fs::write(directory.join("syntxt.toml"), "bits = \"16\"\n").unwrap();
+        assert!(Config::find(&songs.join("song.syn")).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The `syntxt` command, turning songs written in the syn.txt language into music.
+//!
+//! ```text
+//! syntxt new my-song --template techno
+//! syntxt play song.syn
+//! syntxt play song.syn --tui
+//! syntxt build song.syn --output song.flac --normalize-loudness -14
+//! syntxt check song.syn
+//! syntxt fmt song.syn
+//! syntxt export-midi song.syn --output song.mid
+//! syntxt graph song.syn --output song.dot
+//! syntxt dump-ast song.syn
+//! syntxt dump-model song.syn
+//! ```
+
+mod config;
+mod new;
+mod tui;
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use structopt::StructOpt;
+
+use config::Config;
+use syntxt_audio::play::{self, Options, RenderArgs};
+use syntxt_audio::sequencer;
+use syntxt_audio::song::Song;
+use syntxt_lang::ast::{self, Node};
+use syntxt_lang::cycles::check_cycles;
+use syntxt_lang::diagnostic::{Diagnostic, Severity};
+use syntxt_lang::json::ToJson;
+use syntxt_lang::lint::lint;
+use syntxt_lang::model;
+use syntxt_lang::parser::Parser;
+use syntxt_lang::report::Report;
+use syntxt_lang::symbols::SymbolTable;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "syntxt", about = "Compiling syn.txt songs into music")]
+struct Opt {
+    /// Log more details, or everything when given twice.
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences), global = true)]
+    verbose: usize,
+
+    /// When to color errors and warnings: "auto" colors them if they are printed to a terminal.
+    #[structopt(
+        long,
+        global = true,
+        default_value = "auto",
+        possible_values = &["auto", "always", "never"]
+    )]
+    color: String,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Create a project with a starter song, a .gitignore and a script rendering the song.
+    New {
+        /// Directory of the project, which must not exist yet. The song is named after it.
+        #[structopt(parse(from_os_str))]
+        directory: PathBuf,
+
+        /// The style of the starter song.
+        #[structopt(
+            short,
+            long,
+            default_value = "ambient",
+            possible_values = &["ambient", "techno", "chiptune"]
+        )]
+        template: String,
+    },
+    /// Render a song into an audio file.
+    Build {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Output file, written directly for WAV and AIFF files, through opusenc for Opus files
+        /// and through sox for any other format. By default, the song with the extension of the
+        /// `format` of syntxt.toml, or a WAV file.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        render: RenderArgs,
+    },
+    /// Play a song on the default speakers.
+    Play {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Play as a JACK client following the transport of the JACK session,
+        /// which requires building with the `jack` feature.
+        #[structopt(long)]
+        jack: bool,
+
+        /// Send the notes to this MIDI port in real time instead of rendering audio,
+        /// e.g. the raw MIDI device /dev/snd/midiC1D0.
+        #[structopt(long, parse(from_os_str), conflicts_with = "jack")]
+        midi_out: Option<PathBuf>,
+
+        /// Play in an interactive player in the terminal, which pauses, seeks by measures,
+        /// mutes and solos tracks, and shows their levels while playing.
+        #[structopt(
+            long,
+            conflicts_with_all = &["jack", "midi-out", "normalize-loudness", "normalize-peak", "stems"]
+        )]
+        tui: bool,
+
+        #[structopt(flatten)]
+        render: RenderArgs,
+    },
+    /// Report the errors and warnings of songs without rendering them, including parts that are
+    /// probably not intended, like unused ids, overlapping sequences or tracks that are never heard.
+    Check {
+        #[structopt(parse(from_os_str), required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Lay out songs uniformly, rewriting their files.
+    /// Without files, the song read from stdin is written to stdout.
+    Fmt {
+        /// Only report the files that would change, failing if there are any.
+        #[structopt(long)]
+        check: bool,
+
+        #[structopt(parse(from_os_str))]
+        inputs: Vec<PathBuf>,
+    },
+    /// Write the notes of a song to a Standard MIDI File.
+    ExportMidi {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Write the audio graph of a song in the DOT language of Graphviz, showing how its
+    /// instruments, effects, sends and buses are connected, e.g. for drawing it with `dot -Tsvg`.
+    Graph {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Print the syntax tree of a song as JSON, with the position of every node in the source.
+    DumpAst {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Print the model of a song as JSON, with all attributes evaluated, defaults filled in and
+    /// the notes of every sequence.
+    DumpModel {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    play::init_logging(opt.verbose);
+    let colored = match opt.color.as_str() {
+        "always" => true,
+        "never" => false,
+        _ => atty::is(atty::Stream::Stderr) && std::env::var_os("NO_COLOR").is_none(),
+    };
+    if let Err(err) = run(opt.command, colored) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Run a command, printing diagnostics in color if `colored` is set.
+fn run(command: Command, colored: bool) -> io::Result<()> {
+    match command {
+        Command::New {
+            directory,
+            template,
+        } => {
+            let template = new::template(&template).expect("checked by structopt");
+            template.create(&directory)?;
+            info!(
+                "created {} from the {} template, play it with `syntxt play {}`",
+                directory.display(),
+                template.name,
+                directory.join("song.syn").display()
+            );
+            Ok(())
+        }
+        Command::Build {
+            input,
+            output,
+            mut render,
+        } => {
+            let config = Config::find(&input)?;
+            config.apply(&mut render);
+            let output = output
+                .unwrap_or_else(|| input.with_extension(config.format.as_deref().unwrap_or("wav")));
+            let song = compile(&input, &config, colored)?;
+            render.defaults(song.sample_rate, None);
+            let levels = play::play(song, Some(&output), &render.options())?;
+            levels.log();
+            info!("wrote {}", output.display());
+            Ok(())
+        }
+        Command::Play {
+            input,
+            jack,
+            midi_out,
+            tui,
+            mut render,
+        } => {
+            let config = Config::find(&input)?;
+            config.apply(&mut render);
+            if tui {
+                let song = compile_model(&input, &config, colored)?;
+                if !song.sample_rate.is_default() {
+                    render.defaults(Some(song.sample_rate.value), None);
+                }
+                return tui::play(&input, song, &config, render.options(), colored);
+            }
+            let song = compile(&input, &config, colored)?;
+            render.defaults(song.sample_rate, None);
+            if let Some(port) = midi_out {
+                let messages = sequencer::messages(&song);
+                info!("sending {} messages to {}", messages.len(), port.display());
+                let mut port = std::fs::OpenOptions::new().write(true).open(port)?;
+                return sequencer::send(&messages, &mut port);
+            }
+            let options = Options {
+                jack,
+                ..render.options()
+            };
+            play::play(song, None, &options)?.log();
+            Ok(())
+        }
+        Command::Check { inputs } => {
+            let mut failed = 0;
+            for input in inputs.iter() {
+                let source = std::fs::read_to_string(input)?;
+                let config = Config::find(input)?;
+                let (root, song, mut diagnostics) = resolve(input, &source, &config);
+                let table = SymbolTable::build(&root);
+                diagnostics.extend(check_cycles(&root, &table));
+                if let Some(song) = &song {
+                    diagnostics.extend(lint(&root, &table, song));
+                }
+                report(input, &source, &diagnostics, colored);
+                if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+                    failed += 1;
+                }
+            }
+            match failed {
+                0 => Ok(()),
+                _ => Err(invalid(format!(
+                    "{} of {} songs have errors",
+                    failed,
+                    inputs.len()
+                ))),
+            }
+        }
+        Command::Fmt { check, inputs } => {
+            if inputs.is_empty() {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source)?;
+                let formatted = format(Path::new("<stdin>"), &source, colored)?;
+                return io::stdout().write_all(formatted.as_bytes());
+            }
+            // Every file is parsed before the first one is changed
+            let changed = inputs
+                .iter()
+                .map(|input| {
+                    let source = std::fs::read_to_string(input)?;
+                    let formatted = format(input, &source, colored)?;
+                    Ok((input, formatted != source, formatted))
+                })
+                .collect::<io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|(_, changed, _)| *changed)
+                .collect::<Vec<_>>();
+            if check {
+                for (input, _, _) in changed.iter() {
+                    println!("{}", input.display());
+                }
+                return match changed.len() {
+                    0 => Ok(()),
+                    unformatted => Err(invalid(format!(
+                        "{} of {} songs are not formatted",
+                        unformatted,
+                        inputs.len()
+                    ))),
+                };
+            }
+            for (input, _, formatted) in changed {
+                std::fs::write(input, formatted)?;
+                info!("formatted {}", input.display());
+            }
+            Ok(())
+        }
+        Command::ExportMidi { input, output } => {
+            let song = compile(&input, &Config::find(&input)?, colored)?;
+            std::fs::write(&output, sequencer::midi_file(&song))?;
+            info!("wrote {}", output.display());
+            Ok(())
+        }
+        Command::Graph { input, output } => {
+            let song = compile(&input, &Config::find(&input)?, colored)?;
+            // Frozen tracks are shown with everything they consist of
+            let rendering = play::render(song, &Options::default())?;
+            std::fs::write(&output, rendering.graph().to_dot())?;
+            info!("wrote {}", output.display());
+            Ok(())
+        }
+        Command::DumpAst { input } => {
+            let source = std::fs::read_to_string(&input)?;
+            let (root, diagnostics) = Parser::parse_with_diagnostics(&source);
+            report(&input, &source, &diagnostics, colored);
+            writeln!(io::stdout(), "{}", root.to_json())
+        }
+        Command::DumpModel { input } => {
+            let config = Config::find(&input)?;
+            let song = compile_model(&input, &config, colored)?;
+            writeln!(io::stdout(), "{}", song.to_json())
+        }
+    }
+}
+
+/// Build the song of a file in a project, reporting all problems found on the way.
+fn compile(input: &Path, config: &Config, colored: bool) -> io::Result<Song> {
+    compile_model(input, config, colored).map(|song| config.song(&song))
+}
+
+/// The model of the song of a file in a project, reporting all problems found on the way.
+fn compile_model(input: &Path, config: &Config, colored: bool) -> io::Result<model::Song> {
+    let source = std::fs::read_to_string(input)?;
+    let (_, song, diagnostics) = resolve(input, &source, config);
+    report(input, &source, &diagnostics, colored);
+    match song {
+        Some(song) if diagnostics.iter().all(|d| d.severity < Severity::Error) => Ok(song),
+        _ => Err(invalid(format!("could not compile {}", input.display()))),
+    }
+}
+
+/// The syntax tree and model of a song and all diagnostics of parsing and resolving it,
+/// with files it refers to found next to it or in the sample directories of the project.
+fn resolve(
+    input: &Path,
+    source: &str,
+    config: &Config,
+) -> (Node<ast::Root>, Option<model::Song>, Vec<Diagnostic>) {
+    let (root, mut diagnostics) = Parser::parse_with_diagnostics(source);
+    let (song, resolve_diagnostics) = model::resolve_with_files(&root, &config.files(input));
+    diagnostics.extend(resolve_diagnostics);
+    (root, song, diagnostics)
+}
+
+fn format(input: &Path, source: &str, colored: bool) -> io::Result<String> {
+    syntxt_lang::format::format(source).map_err(|diagnostics| {
+        report(input, source, &diagnostics, colored);
+        invalid(format!("could not parse {}", input.display()))
+    })
+}
+
+/// Print diagnostics with the lines of the source they refer to, in the order of their position.
+fn report(input: &Path, source: &str, diagnostics: &[Diagnostic], colored: bool) {
+    let name = input.display().to_string();
+    eprint!(
+        "{}",
+        Report::new(&name, source, colored).render_all(diagnostics)
+    );
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
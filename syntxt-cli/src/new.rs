@@ -0,0 +1,182 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Starter projects created by `syntxt new`.
+
+use std::io;
+use std::path::Path;
+
+/// A starter song in a particular style.
+pub struct Template {
+    pub name: &'static str,
+    /// The song, with `$name` standing in for the name of the project.
+    source: &'static str,
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "ambient",
+        source: include_str!("../templates/ambient.syn"),
+    },
+    Template {
+        name: "techno",
+        source: include_str!("../templates/techno.syn"),
+    },
+    Template {
+        name: "chiptune",
+        source: include_str!("../templates/chiptune.syn"),
+    },
+];
+
+pub fn template(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}
+
+const GITIGNORE: &str = "# Renderings of the song
+/*.wav
+/*.aiff
+/*.flac
+/*.ogg
+/*.opus
+/*.mp3
+# Tracks with `freeze: true`, see render.sh
+/.freeze/
+";
+
+const RENDER_SCRIPT: &str = r#"#!/bin/sh
+# Render the song into song.flac, or another file given as the first argument.
+# Any further arguments are passed on to `syntxt build`, see `syntxt build --help`.
+set -e
+cd "$(dirname "$0")"
+output="${1:-song.flac}"
+[ $# -gt 0 ] && shift
+exec syntxt build song.syn --output "$output" --normalize-loudness -14 --freeze-cache .freeze "$@"
+"#;
+
+const CONFIG: &str = r#"# Settings of the songs in this directory and below, used where the command line
+# does not say otherwise. Remove the `#` in front of a setting to use it.
+
+# Sample rate of the output in Hz
+# sample-rate = 48000
+# The file written by `syntxt build song.syn` without --output, e.g. song.flac
+# format = "flac"
+# bits = 24
+# Where recordings and imported files are looked for when they are not next to the song
+# samples = ["samples"]
+# Where plugins are looked for when they are not found where the song says
+# plugins = ["plugins"]
+# The tuning of the songs: a number of equal steps per octave, or a Scala file
+# tuning = "tunings/just.scl"
+# keyboard-mapping = "tunings/just.kbm"
+"#;
+
+impl Template {
+    /// The song with the name of the project filled in.
+    /// Strings cannot contain double quotes, so they are replaced by single ones.
+    pub fn song(&self, name: &str) -> String {
+        let escaped = name.replace('\\', "\\\\").replace('"', "'");
+        self.source.replace("$name", &escaped)
+    }
+
+    /// Create a project in a new directory, named after the directory.
+    pub fn create(&self, directory: &Path) -> io::Result<()> {
+        let name = directory
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} cannot be the directory of a project",
+                        directory.display()
+                    ),
+                )
+            })?;
+        // Fails if the directory already exists, so nothing is overwritten
+        std::fs::create_dir(directory).map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => io::Error::new(
+                err.kind(),
+                format!("{} already exists", directory.display()),
+            ),
+            _ => err,
+        })?;
+        std::fs::write(directory.join("song.syn"), self.song(&name))?;
+        std::fs::write(directory.join(".gitignore"), GITIGNORE)?;
+        std::fs::write(directory.join(crate::config::FILE_NAME), CONFIG)?;
+        let script = directory.join("render.sh");
+        std::fs::write(&script, RENDER_SCRIPT)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{template, CONFIG, TEMPLATES};
+    use crate::config::Config;
+    use syntxt_lang::{
+        cycles::check_cycles, lint::lint, model, parser::Parser, symbols::SymbolTable,
+    };
+
+    #[test]
+    fn templates_are_clean() {
+        for template in TEMPLATES {
+            let source = template.song("Starter");
+            let (root, mut diagnostics) = Parser::parse_with_diagnostics(&source);
+            let (song, resolve_diagnostics) = model::resolve(&root);
+            let table = SymbolTable::build(&root);
+            diagnostics.extend(resolve_diagnostics);
+            diagnostics.extend(check_cycles(&root, &table));
+            diagnostics.extend(lint(&root, &table, &song.unwrap()));
+            assert_eq!(diagnostics, vec![], "{}", template.name);
+            assert_eq!(
+                syntxt_lang::format::format(&source).unwrap(),
+                source,
+                "{} is not formatted",
+                template.name
+            );
+        }
+    }
+
+    /// The settings of new projects are all commented out, leaving everything to the defaults.
+    #[test]
+    fn config_is_empty() {
+        let directory =
+            std::env::temp_dir().join(format!("syntxt-new-config-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("syntxt.toml");
+        std::fs::write(&path, CONFIG).unwrap();
+        let config = Config::load(&path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(config.sample_rate, None);
+        assert_eq!(config.format, None);
+        assert!(config.samples.is_empty() && config.tuning.is_none());
+    }
+
+    #[test]
+    fn song_name() {
+        let song = template("techno").unwrap().song(r#"My "Song" \o/"#);
+        assert!(song.contains(r#"name: "My 'Song' \\o/""#));
+        let (root, _) = Parser::parse_with_diagnostics(&song);
+        let (song, _) = model::resolve(&root);
+        let name = song.unwrap().meta.value.name.value;
+        assert_eq!(name.as_deref(), Some(r#"My 'Song' \o/"#));
+    }
+}
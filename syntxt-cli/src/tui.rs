@@ -0,0 +1,446 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! An interactive player in the terminal, showing the position in the song and live meters
+//! of the tracks, which can be muted and soloed while listening.
+//!
+//! The screen is drawn with ANSI escape sequences, and `stty` lets single keys through without
+//! waiting for the return key, so that no terminal library is needed.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use syntxt_audio::graph::{SoxFormat, SoxSink, SoxTarget};
+use syntxt_audio::play::{self, Options, Render};
+use syntxt_audio::song::Time;
+use syntxt_lang::model;
+
+/// Time between redrawing the screen.
+const FRAME: Duration = Duration::from_millis(50);
+/// Width of the meters in characters.
+const METER_WIDTH: usize = 30;
+/// Level shown at the left end of the meters, in dBFS.
+const METER_FLOOR: f64 = -48.0;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+
+/// Play a song in the terminal until the user quits, starting at `from` of the options.
+pub fn play(
+    input: &Path,
+    song: model::Song,
+    config: &Config,
+    options: Options,
+    colored: bool,
+) -> io::Result<()> {
+    if !atty::is(atty::Stream::Stdin) || !atty::is(atty::Stream::Stdout) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the player needs a terminal",
+        ));
+    }
+    // Problems of the song are reported before the screen is taken over
+    let mut player = Player::new(input, song, config, options, colored)?;
+    let terminal = Terminal::enter()?;
+    let (sender, keys) = mpsc::channel();
+    std::thread::spawn(move || read_keys(sender));
+    // Log messages would scroll the screen away
+    let level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+    let result = player.run(&keys);
+    log::set_max_level(level);
+    drop(terminal);
+    result
+}
+
+/// A key pressed by the user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Read keys from the terminal until it is closed, decoding the escape sequences of arrow keys,
+/// which arrive at once.
+fn read_keys(keys: Sender<Key>) {
+    let mut stdin = io::stdin();
+    let mut buffer = [0; 64];
+    while let Ok(length) = stdin.read(&mut buffer) {
+        if length == 0 {
+            return;
+        }
+        let mut bytes = buffer[..length].iter().copied();
+        while let Some(byte) = bytes.next() {
+            let key = match byte {
+                0x1b => match (bytes.next(), bytes.next()) {
+                    (Some(b'['), Some(b'A')) => Key::Up,
+                    (Some(b'['), Some(b'B')) => Key::Down,
+                    (Some(b'['), Some(b'C')) => Key::Right,
+                    (Some(b'['), Some(b'D')) => Key::Left,
+                    _ => continue,
+                },
+                // Ctrl-C, which does not interrupt the player
+                0x03 => Key::Char('q'),
+                byte => Key::Char(byte as char),
+            };
+            if keys.send(key).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The terminal switched to single keys and a screen of its own, restored when dropped.
+struct Terminal {
+    /// The settings of the terminal before, as printed by `stty -g`
+    settings: String,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        let settings = stty(&["-g"])?.trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        // The alternate screen without the cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Self { settings })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.settings]);
+    }
+}
+
+/// Run `stty` on the terminal of the player, returning its output.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("stty failed to set up the terminal: {}", output.status),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The song being played and everything the user changed.
+struct Player<'a> {
+    /// Shown at the top, e.g. the file of the song
+    title: String,
+    /// Evaluated again when seeking, as rendering consumes the song
+    song: model::Song,
+    /// The project of the song, e.g. with the tuning of its songs
+    config: &'a Config,
+    options: Options,
+    colored: bool,
+    render: Render,
+    /// The rendered song ended and is played from the start again
+    finished: bool,
+    playing: bool,
+    tracks: Vec<String>,
+    buses: usize,
+    mute: Vec<bool>,
+    solo: Vec<bool>,
+    /// Index of the track that is muted and soloed by the keys
+    selected: usize,
+    /// Shown until the next key, e.g. when seeking failed
+    message: Option<String>,
+}
+
+impl<'a> Player<'a> {
+    fn new(
+        input: &Path,
+        song: model::Song,
+        config: &'a Config,
+        options: Options,
+        colored: bool,
+    ) -> io::Result<Self> {
+        let rendered = config.song(&song);
+        let tracks = rendered
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| match &track.name {
+                Some(name) => name.clone(),
+                None => format!("track {}", index),
+            })
+            .collect();
+        let buses = rendered.buses.len();
+        let mute = rendered.tracks.iter().map(|track| track.mute).collect();
+        let solo = rendered.tracks.iter().map(|track| track.solo).collect();
+        let render = play::render(rendered, &options)?;
+        Ok(Self {
+            title: input.display().to_string(),
+            song,
+            config,
+            options,
+            colored,
+            render,
+            finished: false,
+            playing: true,
+            tracks,
+            buses,
+            mute,
+            solo,
+            selected: 0,
+            message: None,
+        })
+    }
+
+    /// Play the song and handle the keys until the user quits.
+    fn run(&mut self, keys: &Receiver<Key>) -> io::Result<()> {
+        let song = self.config.song(&self.song);
+        let format = SoxFormat {
+            precision: self.options.precision,
+            dither: self.options.dither,
+            layout: song.layout,
+            ..SoxFormat::default()
+        };
+        let mut sink = SoxSink::with_tags(
+            self.options.sample_rate as i32,
+            SoxTarget::Play,
+            format,
+            &song.tags,
+        )?;
+        self.draw()?;
+        let mut drawn = Instant::now();
+        loop {
+            while let Ok(key) = keys.try_recv() {
+                self.message = None;
+                if !self.handle(key) {
+                    return Ok(());
+                }
+            }
+            // Writing blocks while sox is busy playing what it got before,
+            // which keeps rendering in step with the speakers
+            if !self.playing {
+                std::thread::sleep(FRAME / 2);
+            } else if let Some(block) = self.render.next() {
+                sink.write_frames(&block.frames);
+            } else {
+                self.playing = false;
+                self.finished = true;
+            }
+            if drawn.elapsed() >= FRAME {
+                self.draw()?;
+                drawn = Instant::now();
+            }
+        }
+    }
+
+    /// Act on a key, returning whether to go on playing.
+    fn handle(&mut self, key: Key) -> bool {
+        let measure = self.render.measure().floor() as i64;
+        match key {
+            Key::Char('q') => return false,
+            Key::Char(' ') if self.finished => {
+                self.seek(0);
+                self.playing = true;
+            }
+            Key::Char(' ') => self.playing = !self.playing,
+            Key::Char('0') => self.seek(0),
+            Key::Left => self.seek((measure - 1).max(0)),
+            Key::Right => self.seek(measure + 1),
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(self.tracks.len().max(1) - 1),
+            Key::Char('m') if self.selected < self.tracks.len() => {
+                self.mute[self.selected] = !self.mute[self.selected];
+                self.render
+                    .set_mute(self.selected, self.mute[self.selected]);
+            }
+            Key::Char('s') if self.selected < self.tracks.len() => {
+                self.solo[self.selected] = !self.solo[self.selected];
+                self.render
+                    .set_solo(self.selected, self.solo[self.selected]);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Continue playing at the start of a measure, counted from 0,
+    /// keeping the tracks muted and soloed as they are.
+    fn seek(&mut self, measure: i64) {
+        let options = Options {
+            from: Some(Time::int(measure)),
+            ..self.options.clone()
+        };
+        match play::render(self.config.song(&self.song), &options) {
+            Ok(mut render) => {
+                for (index, (mute, solo)) in self.mute.iter().zip(self.solo.iter()).enumerate() {
+                    render.set_mute(index, *mute);
+                    render.set_solo(index, *solo);
+                }
+                self.render = render;
+                self.finished = false;
+            }
+            Err(err) => {
+                self.message = Some(format!("cannot play measure {}: {}", measure + 1, err))
+            }
+        }
+    }
+
+    fn draw(&self) -> io::Result<()> {
+        let mut lines = Vec::new();
+        let state = if self.playing {
+            "playing"
+        } else if self.finished {
+            "finished"
+        } else {
+            "paused"
+        };
+        let measure = self.render.measure();
+        let sample_rate = self.options.sample_rate as f64;
+        lines.push(format!(
+            "{}{}{}  {}  bar {} beat {}  {} / {}",
+            self.style(BOLD),
+            self.title,
+            self.style(RESET),
+            state,
+            measure.floor() as i64 + 1,
+            (measure.fract() * 4.0).floor() as i64 + 1,
+            clock(self.render.frame() as f64 / sample_rate),
+            clock(self.render.end_frame() as f64 / sample_rate),
+        ));
+        lines.push(String::new());
+
+        let width = self
+            .tracks
+            .iter()
+            .map(|name| name.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        let peaks = self.render.peaks();
+        for (index, (name, peak)) in self.tracks.iter().zip(peaks.tracks.iter()).enumerate() {
+            lines.push(format!(
+                "{} {:width$}  {}  {}  {}",
+                if index == self.selected { ">" } else { " " },
+                name,
+                if self.mute[index] { "M" } else { "-" },
+                if self.solo[index] { "S" } else { "-" },
+                self.meter(*peak),
+                width = width
+            ));
+        }
+        for (index, peak) in peaks.buses.iter().enumerate().take(self.buses) {
+            let name = format!("bus {}", index);
+            lines.push(format!(
+                "  {:width$}        {}",
+                name,
+                self.meter(*peak),
+                width = width
+            ));
+        }
+        lines.push(format!(
+            "  {:width$}        {}",
+            "master",
+            self.meter(peaks.master),
+            width = width
+        ));
+        lines.push(String::new());
+        lines.push(self.message.clone().unwrap_or_default());
+        lines.push(
+            "space play/pause  left/right previous/next measure  0 start  \
+             up/down select track  m mute  s solo  q quit"
+                .to_string(),
+        );
+
+        let mut screen = String::from("\x1b[H");
+        for line in lines {
+            screen.push_str(&line);
+            // Clear what is left of longer lines drawn before
+            screen.push_str("\x1b[K\n");
+        }
+        screen.push_str("\x1b[J");
+        let mut stdout = io::stdout();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()
+    }
+
+    /// A bar growing with the peak level, with the level in dBFS.
+    fn meter(&self, peak: f64) -> String {
+        let decibels = 20.0 * peak.log10();
+        let filled = ((decibels - METER_FLOOR) / -METER_FLOOR * METER_WIDTH as f64)
+            .max(0.0)
+            .min(METER_WIDTH as f64) as usize;
+        let color = if decibels >= 0.0 {
+            RED
+        } else if decibels >= -6.0 {
+            YELLOW
+        } else {
+            GREEN
+        };
+        let level = if decibels > METER_FLOOR {
+            format!("{:6.1} dB", decibels)
+        } else {
+            "   -inf dB".to_string()
+        };
+        format!(
+            "{}{}{}{} {}",
+            self.style(color),
+            "#".repeat(filled),
+            self.style(RESET),
+            ".".repeat(METER_WIDTH - filled),
+            level
+        )
+    }
+
+    /// An escape sequence if the screen is colored.
+    fn style(&self, style: &'static str) -> &'static str {
+        if self.colored {
+            style
+        } else {
+            ""
+        }
+    }
+}
+
+/// A time in minutes and seconds, e.g. `1:05.2`.
+fn clock(seconds: f64) -> String {
+    let tenths = (seconds.max(0.0) * 10.0) as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clock;
+
+    #[test]
+    fn clock_times() {
+        assert_eq!(clock(0.0), "0:00.0");
+        assert_eq!(clock(65.25), "1:05.2");
+        assert_eq!(clock(3600.0), "60:00.0");
+    }
+}
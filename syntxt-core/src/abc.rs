@@ -0,0 +1,949 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reading tunes in ABC notation, e.g. for using traditional tunes as source material.
+//!
+//! The notes of all voices are kept with their repeats and endings played out, while chord
+//! symbols, decorations, grace notes, lyrics and the tempo are skipped. Durations are measured
+//! in whole notes, so that imported tunes follow the tempo of the song using them.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::note::Note;
+use crate::rational::Rational;
+
+/// A tune of an ABC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbcTune {
+    /// The reference number given by the `X:` field.
+    pub reference: Option<u32>,
+    /// The first title given by a `T:` field.
+    pub title: Option<String>,
+    /// The notes of all voices, ordered by their start.
+    pub notes: Vec<AbcNote>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbcNote {
+    pub note: Note,
+    /// Start in whole notes, counted from the beginning of the tune.
+    pub start: Rational,
+    /// Duration in whole notes.
+    pub duration: Rational,
+}
+
+/// Why an ABC file could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbcError {
+    /// The text does not contain a tune with a `K:` field.
+    NoTune,
+    /// The music in a line could not be decoded.
+    Malformed { line: usize, what: &'static str },
+}
+
+impl Error for AbcError {}
+
+impl fmt::Display for AbcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbcError::NoTune => write!(f, "no tune found"),
+            AbcError::Malformed { line, what } => write!(f, "malformed line {}: {}", line, what),
+        }
+    }
+}
+
+/// Read all tunes of an ABC file.
+///
+/// Every tune starts with an `X:` field and ends at the next empty line.
+/// Files without any `X:` field are read as a single tune.
+///
+/// # Examples
+///
+/// ```
+/// # use syntxt_core::abc::*;
+/// # use syntxt_core::note::Note;
+/// # use syntxt_core::rational::Rational;
+/// let tunes = parse("X:1\nT:Scale\nM:4/4\nL:1/4\nK:G\n|: G A B c :| d2 f2 |]\n").unwrap();
+/// assert_eq!(tunes[0].title.as_deref(), Some("Scale"));
+/// let notes = &tunes[0].notes;
+/// // The repeated bar is played twice, and the key of G raises every F
+/// assert_eq!(notes.len(), 10);
+/// assert_eq!(notes[4].start, Rational::int(1));
+/// assert_eq!(notes[9].note, Note::from_midi(78));
+/// assert_eq!(notes[9].duration, Rational::new(1, 2));
+/// ```
+///
+/// Endings are chosen by the number of the pass, and tied notes are joined:
+///
+/// ```
+/// # use syntxt_core::abc::*;
+/// # use syntxt_core::rational::Rational;
+/// let tunes = parse("K:C\nL:1/4\n|: c |1 d :|2 e- e |]").unwrap();
+/// let notes = tunes[0]
+///     .notes
+///     .iter()
+///     .map(|note| (note.note.to_midi(), note.duration))
+///     .collect::<Vec<_>>();
+/// let quarter = Rational::new(1, 4);
+/// assert_eq!(notes, vec![(72, quarter), (74, quarter), (72, quarter), (76, quarter * 2)]);
+/// ```
+pub fn parse(text: &str) -> Result<Vec<AbcTune>, AbcError> {
+    let mut tunes = Vec::new();
+    let numbered = text
+        .lines()
+        .any(|line| field(line).map(|f| f.0) == Some('X'));
+    let mut current = if numbered {
+        None
+    } else {
+        Some(TuneReader::new(None))
+    };
+    for (index, line) in text.lines().enumerate() {
+        if let Some(('X', value)) = field(line) {
+            tunes.extend(current.take().and_then(TuneReader::finish));
+            current = Some(TuneReader::new(value.trim().parse().ok()));
+            continue;
+        }
+        match current.as_mut() {
+            Some(_) if numbered && line.trim().is_empty() => {
+                tunes.extend(current.take().and_then(TuneReader::finish));
+            }
+            Some(tune) => tune.line(line, index + 1)?,
+            // Free text between the tunes
+            None => {}
+        }
+    }
+    tunes.extend(current.and_then(TuneReader::finish));
+    if tunes.is_empty() {
+        Err(AbcError::NoTune)
+    } else {
+        Ok(tunes)
+    }
+}
+
+/// The letter and the value of a field line like `K:G`.
+fn field(line: &str) -> Option<(char, &str)> {
+    let mut chars = line.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => Some((letter, &line[2..])),
+        _ => None,
+    }
+}
+
+/// A part of the music of a voice, in the order it is written.
+#[derive(Debug, Clone)]
+enum Element {
+    /// Notes starting together, or a rest if there are none.
+    Sound {
+        notes: Vec<Note>,
+        duration: Rational,
+        /// Whether the notes are tied to the same notes of the next sound.
+        tied: bool,
+    },
+    RepeatStart,
+    RepeatEnd,
+    /// A double bar line, after which a repeat without start does not go back.
+    SectionEnd,
+    /// The start of the ending played on the pass with this number.
+    Ending(u32),
+}
+
+#[derive(Debug, Default)]
+struct Voice {
+    id: String,
+    elements: Vec<Element>,
+    /// Accidentals written in the current bar, by letter and octave.
+    accidentals: HashMap<(usize, i32), i32>,
+    /// Factor for the durations of a tuplet, with the number of notes still to come.
+    tuplet: Option<(Rational, u32)>,
+    /// Factor for the duration of the next note, following a broken rhythm like `a>b`.
+    broken: Option<Rational>,
+}
+
+/// Collects the music of a tune line by line.
+struct TuneReader {
+    reference: Option<u32>,
+    title: Option<String>,
+    /// Whether the `K:` field ending the header was read.
+    in_body: bool,
+    /// Length of a bar in whole notes, or `None` for free meter.
+    meter: Option<Rational>,
+    /// Whether the meter is compound like `6/8`, which is lost when reducing it to a bar length.
+    compound: bool,
+    /// Length of a note without explicit length.
+    unit: Option<Rational>,
+    /// Alteration of every letter from C to B in semitones, following the key signature.
+    key: [i32; 7],
+    voices: Vec<Voice>,
+    /// Index of the voice that is currently written.
+    voice: usize,
+}
+
+impl TuneReader {
+    fn new(reference: Option<u32>) -> Self {
+        Self {
+            reference,
+            title: None,
+            in_body: false,
+            meter: None,
+            compound: false,
+            unit: None,
+            key: [0; 7],
+            voices: vec![Voice::default()],
+            voice: 0,
+        }
+    }
+
+    fn line(&mut self, line: &str, number: usize) -> Result<(), AbcError> {
+        let line = match line.find('%') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        if let Some((letter, value)) = field(line) {
+            self.field(letter, value.trim());
+            Ok(())
+        } else if self.in_body {
+            self.music(line)
+                .map_err(|what| AbcError::Malformed { line: number, what })
+        } else {
+            // Text before the key is not music
+            Ok(())
+        }
+    }
+
+    fn field(&mut self, letter: char, value: &str) {
+        match letter {
+            'T' if self.title.is_none() => self.title = Some(value.to_string()),
+            'M' => {
+                self.meter = meter(value);
+                self.compound = value
+                    .split('/')
+                    .next()
+                    .and_then(|beats| beats.trim().parse::<i64>().ok())
+                    .map_or(false, |beats| beats % 3 == 0 && beats > 3);
+            }
+            'L' => self.unit = fraction(value),
+            'K' => {
+                self.key = key(value);
+                if !self.in_body && self.unit.is_none() {
+                    // Short meters default to sixteenths, all others to eighths
+                    self.unit = Some(match self.meter {
+                        Some(meter) if meter < Rational::new(3, 4) => Rational::new(1, 16),
+                        _ => Rational::new(1, 8),
+                    });
+                }
+                self.in_body = true;
+            }
+            'V' => {
+                let id = value.split_whitespace().next().unwrap_or("");
+                self.voice = match self.voices.iter().position(|voice| voice.id == id) {
+                    Some(index) => index,
+                    None => {
+                        self.voices.push(Voice {
+                            id: id.to_string(),
+                            ..Voice::default()
+                        });
+                        self.voices.len() - 1
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn music(&mut self, line: &str) -> Result<(), &'static str> {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut pos = 0;
+        while let Some(&c) = chars.get(pos) {
+            match c {
+                // Chord symbols, annotations, decorations and grace notes are not played
+                '"' | '!' | '+' | '{' => {
+                    let end = if c == '{' { '}' } else { c };
+                    pos = match chars[pos + 1..].iter().position(|x| *x == end) {
+                        Some(offset) => pos + offset + 2,
+                        None => pos + 1,
+                    };
+                }
+                '(' if chars.get(pos + 1).map_or(false, char::is_ascii_digit) => {
+                    pos += 1;
+                    self.tuplet(&chars, &mut pos)?;
+                }
+                '[' if chars.get(pos + 1).map_or(false, char::is_ascii_alphabetic)
+                    && chars.get(pos + 2) == Some(&':') =>
+                {
+                    let end = chars[pos..]
+                        .iter()
+                        .position(|x| *x == ']')
+                        .ok_or("an inline field is not closed")?;
+                    let value = chars[pos + 3..pos + end].iter().collect::<String>();
+                    self.field(chars[pos + 1], value.trim());
+                    pos += end + 1;
+                }
+                '[' if chars.get(pos + 1).map_or(false, char::is_ascii_digit) => {
+                    pos += 1;
+                    self.ending(&chars, &mut pos)?;
+                }
+                '[' if chars.get(pos + 1) == Some(&'|') => {
+                    pos += 2;
+                    self.bar(Some(Element::SectionEnd));
+                }
+                '[' => {
+                    pos += 1;
+                    self.chord(&chars, &mut pos)?;
+                }
+                '|' => {
+                    pos += 1;
+                    let element = match chars.get(pos) {
+                        Some('|') | Some(']') => {
+                            pos += 1;
+                            Some(Element::SectionEnd)
+                        }
+                        Some(':') => {
+                            while chars.get(pos) == Some(&':') {
+                                pos += 1;
+                            }
+                            Some(Element::RepeatStart)
+                        }
+                        _ => None,
+                    };
+                    self.bar(element);
+                    self.bar_ending(&chars, &mut pos)?;
+                }
+                ':' => {
+                    while chars.get(pos) == Some(&':') {
+                        pos += 1;
+                    }
+                    self.bar(Some(Element::RepeatEnd));
+                    if chars.get(pos) == Some(&'|') {
+                        pos += 1;
+                        if chars.get(pos) == Some(&'|') {
+                            pos += 1;
+                        }
+                    } else {
+                        // `::` ends one repeat and starts the next
+                        self.bar(Some(Element::RepeatStart));
+                    }
+                    if chars.get(pos) == Some(&':') {
+                        while chars.get(pos) == Some(&':') {
+                            pos += 1;
+                        }
+                        self.bar(Some(Element::RepeatStart));
+                    }
+                    self.bar_ending(&chars, &mut pos)?;
+                }
+                '-' => {
+                    if let Some(Element::Sound { tied, .. }) = self.last_sound() {
+                        *tied = true;
+                    }
+                    pos += 1;
+                }
+                '>' | '<' => {
+                    let mut count = 0;
+                    while chars.get(pos) == Some(&c) {
+                        count += 1;
+                        pos += 1;
+                    }
+                    let short = Rational::int(2).powi(-count.min(8));
+                    let long = Rational::int(2) - short;
+                    let (first, second) = if c == '>' {
+                        (long, short)
+                    } else {
+                        (short, long)
+                    };
+                    if let Some(Element::Sound { duration, .. }) = self.last_sound() {
+                        *duration *= first;
+                    }
+                    self.voices[self.voice].broken = Some(second);
+                }
+                '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => {
+                    let note = self.pitch(&chars, &mut pos)?;
+                    let duration = self.unit() * length(&chars, &mut pos)?;
+                    self.sound(vec![note], duration);
+                }
+                'z' | 'x' => {
+                    pos += 1;
+                    let duration = self.unit() * length(&chars, &mut pos)?;
+                    self.sound(Vec::new(), duration);
+                }
+                // Rests lasting whole bars
+                'Z' | 'X' => {
+                    pos += 1;
+                    let bars = number(&chars, &mut pos)?.unwrap_or(1);
+                    let bar = self.meter.unwrap_or_else(Rational::one);
+                    self.voices[self.voice].elements.push(Element::Sound {
+                        notes: Vec::new(),
+                        duration: bar * bars,
+                        tied: false,
+                    });
+                }
+                _ => pos += 1,
+            }
+        }
+        Ok(())
+    }
+
+    fn unit(&self) -> Rational {
+        self.unit.unwrap_or_else(|| Rational::new(1, 8))
+    }
+
+    fn last_sound(&mut self) -> Option<&mut Element> {
+        self.voices[self.voice]
+            .elements
+            .iter_mut()
+            .rev()
+            .find(|element| matches!(element, Element::Sound { .. }))
+    }
+
+    /// Add notes or a rest, shortened or lengthened by a pending tuplet or broken rhythm.
+    fn sound(&mut self, notes: Vec<Note>, mut duration: Rational) {
+        let voice = &mut self.voices[self.voice];
+        if let Some((factor, remaining)) = voice.tuplet {
+            duration *= factor;
+            voice.tuplet = Some((factor, remaining - 1)).filter(|(_, left)| *left > 0);
+        }
+        if let Some(factor) = voice.broken.take() {
+            duration *= factor;
+        }
+        voice.elements.push(Element::Sound {
+            notes,
+            duration,
+            tied: false,
+        });
+    }
+
+    /// Read a bar line, which also ends all accidentals.
+    fn bar(&mut self, element: Option<Element>) {
+        let voice = &mut self.voices[self.voice];
+        voice.accidentals.clear();
+        voice.elements.extend(element);
+    }
+
+    /// Read the number of an ending directly following a bar line, like `|1` or `:|[2`.
+    fn bar_ending(&mut self, chars: &[char], pos: &mut usize) -> Result<(), &'static str> {
+        let bracket = (chars.get(*pos) == Some(&'[')) as usize;
+        if chars
+            .get(*pos + bracket)
+            .map_or(false, char::is_ascii_digit)
+        {
+            *pos += bracket;
+            self.ending(chars, pos)?;
+        }
+        Ok(())
+    }
+
+    /// Read the number of an ending, of which only the first is used in lists like `1,3`.
+    fn ending(&mut self, chars: &[char], pos: &mut usize) -> Result<(), &'static str> {
+        let pass = number(chars, pos)?.unwrap_or(1);
+        while chars
+            .get(*pos)
+            .map_or(false, |c| c.is_ascii_digit() || *c == ',' || *c == '-')
+        {
+            *pos += 1;
+        }
+        self.voices[self.voice]
+            .elements
+            .push(Element::Ending(pass as u32));
+        Ok(())
+    }
+
+    /// Read a tuplet like `(3` or `(3:2:3`, placing p notes into the time of q for r notes.
+    fn tuplet(&mut self, chars: &[char], pos: &mut usize) -> Result<(), &'static str> {
+        let p = number(chars, pos)?.unwrap_or(3).max(1);
+        let mut part = || -> Result<Option<i64>, &'static str> {
+            if chars.get(*pos) == Some(&':') {
+                *pos += 1;
+                number(chars, pos)
+            } else {
+                Ok(None)
+            }
+        };
+        let q = part()?;
+        let r = part()?;
+        let q = q.unwrap_or(match p {
+            3 | 6 => 2,
+            2 | 4 | 8 => 3,
+            _ if self.compound => 3,
+            _ => 2,
+        });
+        let r = r.unwrap_or(p).max(1);
+        self.voices[self.voice].tuplet = Some((Rational::new(q, p), r as u32));
+        Ok(())
+    }
+
+    /// Read notes played together like `[CEG]2`, which last as long as the first of them.
+    fn chord(&mut self, chars: &[char], pos: &mut usize) -> Result<(), &'static str> {
+        let mut notes = Vec::new();
+        let mut duration = None;
+        let mut tied = false;
+        loop {
+            match chars.get(*pos) {
+                None => return Err("a chord is not closed"),
+                Some(']') => break,
+                Some('^') | Some('_') | Some('=') | Some('A'..='G') | Some('a'..='g') => {
+                    notes.push(self.pitch(chars, pos)?);
+                    let length = length(chars, pos)?;
+                    duration.get_or_insert(length);
+                }
+                Some('-') => {
+                    tied = true;
+                    *pos += 1;
+                }
+                Some(_) => *pos += 1,
+            }
+        }
+        *pos += 1;
+        let duration = self.unit() * duration.unwrap_or_else(Rational::one) * length(chars, pos)?;
+        self.sound(notes, duration);
+        if tied {
+            if let Some(Element::Sound { tied, .. }) = self.last_sound() {
+                *tied = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a note name with its accidentals and octave marks.
+    fn pitch(&mut self, chars: &[char], pos: &mut usize) -> Result<Note, &'static str> {
+        let mut accidental = None;
+        while let Some(&c) = chars.get(*pos) {
+            let offset = match c {
+                '^' => 1,
+                '_' => -1,
+                '=' => 0,
+                _ => break,
+            };
+            accidental = Some(accidental.unwrap_or(0) + offset);
+            *pos += 1;
+        }
+        let letter = chars
+            .get(*pos)
+            .copied()
+            .ok_or("an accidental without a note")?;
+        let index = "CDEFGAB"
+            .find(letter.to_ascii_uppercase())
+            .filter(|_| letter.is_ascii_alphabetic())
+            .ok_or("an accidental without a note")?;
+        *pos += 1;
+        let mut octave = if letter.is_ascii_uppercase() { 4 } else { 5 };
+        while let Some(&c) = chars.get(*pos) {
+            match c {
+                '\'' => octave += 1,
+                ',' => octave -= 1,
+                _ => break,
+            }
+            *pos += 1;
+        }
+        let accidentals = &mut self.voices[self.voice].accidentals;
+        let alteration = match accidental {
+            Some(alteration) => {
+                accidentals.insert((index, octave), alteration);
+                alteration
+            }
+            None => match accidentals.get(&(index, octave)) {
+                Some(alteration) => *alteration,
+                None => self.key[index],
+            },
+        };
+        let semitone = [0, 2, 4, 5, 7, 9, 11][index];
+        // C4 is MIDI note number 60
+        let midi = (octave + 1) as i64 * 12 + semitone + alteration as i64;
+        Note::try_from_midi(midi).ok_or("a note is outside of the MIDI range")
+    }
+
+    /// The notes of all voices, unless the tune has no body.
+    fn finish(self) -> Option<AbcTune> {
+        if !self.in_body {
+            return None;
+        }
+        let mut notes = self
+            .voices
+            .iter()
+            .flat_map(|voice| play_out(&voice.elements))
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|note| note.start);
+        Some(AbcTune {
+            reference: self.reference,
+            title: self.title,
+            notes,
+        })
+    }
+}
+
+/// Place the notes of a voice in time, playing out repeats and joining tied notes.
+fn play_out(elements: &[Element]) -> Vec<AbcNote> {
+    let mut notes: Vec<AbcNote> = Vec::new();
+    let mut time = Rational::zero();
+    // Notes tied to the next sound, with their index in `notes`
+    let mut ties: Vec<(Note, usize)> = Vec::new();
+    // Index of the first element that is repeated, the current pass, and whether an ending
+    // for another pass is skipped
+    let (mut section, mut pass, mut skipping) = (0, 1, false);
+    let mut index = 0;
+    while let Some(element) = elements.get(index) {
+        index += 1;
+        match element {
+            Element::Sound { .. } if skipping => {}
+            Element::Sound {
+                notes: sound,
+                duration,
+                tied,
+            } => {
+                let mut open = Vec::new();
+                for &note in sound {
+                    let joined = match ties.iter().find(|(open, _)| *open == note) {
+                        Some(&(_, joined)) => {
+                            notes[joined].duration += *duration;
+                            joined
+                        }
+                        None => {
+                            notes.push(AbcNote {
+                                note,
+                                start: time,
+                                duration: *duration,
+                            });
+                            notes.len() - 1
+                        }
+                    };
+                    if *tied {
+                        open.push((note, joined));
+                    }
+                }
+                ties = open;
+                time += *duration;
+            }
+            Element::Ending(number) => skipping = *number != pass,
+            // The end of a skipped first ending, the second one follows
+            Element::RepeatEnd if skipping => {
+                skipping = false;
+                section = index;
+            }
+            Element::RepeatEnd if pass == 1 => {
+                pass = 2;
+                index = section;
+            }
+            Element::RepeatEnd | Element::RepeatStart | Element::SectionEnd => {
+                section = index;
+                pass = 1;
+                skipping = false;
+            }
+        }
+    }
+    notes
+}
+
+/// Read a number of at most four digits, e.g. of a note length.
+fn number(chars: &[char], pos: &mut usize) -> Result<Option<i64>, &'static str> {
+    let digits = chars[*pos..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    if digits.len() > 4 {
+        return Err("a number is too large");
+    }
+    *pos += digits.len();
+    Ok(digits.parse().ok())
+}
+
+/// Read a note length like `2`, `3/2`, `/` or `//` as multiple of the unit length.
+fn length(chars: &[char], pos: &mut usize) -> Result<Rational, &'static str> {
+    let numerator = number(chars, pos)?.unwrap_or(1);
+    let mut denominator = 1;
+    while chars.get(*pos) == Some(&'/') {
+        *pos += 1;
+        denominator *= number(chars, pos)?.unwrap_or(2);
+        if denominator == 0 || denominator > 1024 {
+            return Err("a note length is out of range");
+        }
+    }
+    Ok(Rational::new(numerator, denominator))
+}
+
+/// A fraction like `1/8` of a field.
+fn fraction(value: &str) -> Option<Rational> {
+    let mut parts = value.split('/');
+    let numerator = parts.next()?.trim().parse::<i64>().ok()?;
+    let denominator = parts.next()?.trim().parse::<i64>().ok()?;
+    if numerator > 0 && denominator > 0 {
+        Some(Rational::new(numerator, denominator))
+    } else {
+        None
+    }
+}
+
+/// The length of a bar given by the `M:` field, with `C` for common time and sums like
+/// `2+3/8` for additive meters. Free meter has no bars.
+fn meter(value: &str) -> Option<Rational> {
+    match value {
+        "C" | "C|" => Some(Rational::one()),
+        _ => {
+            let (beats, unit) = value.split_at(value.find('/')?);
+            let beats = beats
+                .split('+')
+                .map(|part| part.trim().parse::<i64>().ok())
+                .sum::<Option<i64>>()?;
+            fraction(&format!("{}{}", beats, unit))
+        }
+    }
+}
+
+/// The alteration of every letter from C to B following a key like `G`, `F#m`, `Bb dor`,
+/// or `D exp ^c ^f` with explicit accidentals.
+fn key(value: &str) -> [i32; 7] {
+    let mut key = [0; 7];
+    let mut words = value.split_whitespace().peekable();
+    let tonic = match words.next() {
+        Some(word) if !word.eq_ignore_ascii_case("none") => word,
+        _ => return key,
+    };
+    let mut chars = tonic.chars();
+    // Position of the letter in the circle of fifths, starting at C
+    let mut fifths = match chars.next() {
+        Some('F') => -1,
+        Some('C') => 0,
+        Some('G') => 1,
+        Some('D') => 2,
+        Some('A') => 3,
+        Some('E') => 4,
+        Some('B') => 5,
+        _ => return key,
+    };
+    let rest = chars.as_str();
+    let rest = if let Some(rest) = rest.strip_prefix('#') {
+        fifths += 7;
+        rest
+    } else if let Some(rest) = rest.strip_prefix('b') {
+        fifths -= 7;
+        rest
+    } else {
+        rest
+    };
+    let mode = if rest.is_empty() {
+        match words.peek() {
+            Some(word) if word.chars().all(|c| c.is_ascii_alphabetic()) && word != &"exp" => {
+                words.next().unwrap_or("")
+            }
+            _ => "",
+        }
+    } else {
+        rest
+    };
+    let mode = mode.to_ascii_lowercase();
+    fifths += match mode.get(..3).unwrap_or(&mode) {
+        "m" | "min" | "aeo" => -3,
+        "mix" => -1,
+        "dor" => -2,
+        "phr" => -4,
+        "lyd" => 1,
+        "loc" => -5,
+        _ => 0,
+    };
+    // The letters altered by the key signature, in the order they are added
+    let sharps = [3, 0, 4, 1, 5, 2, 6];
+    for &index in sharps.iter().take(fifths.max(0) as usize) {
+        key[index] = 1;
+    }
+    for &index in sharps.iter().rev().take((-fifths).max(0) as usize) {
+        key[index] = -1;
+    }
+    for word in words {
+        if word == "exp" {
+            key = [0; 7];
+            continue;
+        }
+        let letter = word.trim_start_matches(&['^', '_', '='][..]);
+        if letter.len() == word.len() {
+            continue;
+        }
+        let alteration = word.matches('^').count() as i32 - word.matches('_').count() as i32;
+        if let Some(index) = letter
+            .chars()
+            .next()
+            .and_then(|c| "CDEFGAB".find(c.to_ascii_uppercase()))
+        {
+            key[index] = alteration;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key, parse, AbcError};
+    use crate::rational::Rational;
+
+    /// The MIDI note numbers, starts and durations of the notes of the first tune.
+    fn notes(text: &str) -> Vec<(u8, Rational, Rational)> {
+        parse(text).unwrap()[0]
+            .notes
+            .iter()
+            .map(|note| (note.note.to_midi(), note.start, note.duration))
+            .collect()
+    }
+
+    fn durations(text: &str) -> Vec<Rational> {
+        notes(text).into_iter().map(|note| note.2).collect()
+    }
+
+    #[test]
+    fn unsupported_constructs() {
+        // Chord symbols, decorations, grace notes, lyrics and the tempo are skipped
+        let eighth = Rational::new(1, 8);
+        let skipped =
+            notes("Q:1/4=120\nL:1/8\nK:C\n\"Am\"c !trill!d {ag}e +fermata+f\nw:la la la la");
+        assert_eq!(
+            skipped,
+            vec![
+                (72, Rational::zero(), eighth),
+                (74, eighth, eighth),
+                (76, eighth * 2, eighth),
+                (77, eighth * 3, eighth),
+            ]
+        );
+        // Inline fields are read
+        assert_eq!(notes("K:C\n[K:G] f")[0].0, 78);
+        assert_eq!(
+            parse("K:C\nc d\n[CEG"),
+            Err(AbcError::Malformed {
+                line: 3,
+                what: "a chord is not closed"
+            })
+        );
+        assert_eq!(
+            parse("K:C\n^ c"),
+            Err(AbcError::Malformed {
+                line: 2,
+                what: "an accidental without a note"
+            })
+        );
+        assert_eq!(
+            parse("K:C\n[K:G c"),
+            Err(AbcError::Malformed {
+                line: 2,
+                what: "an inline field is not closed"
+            })
+        );
+        assert_eq!(
+            parse("K:C\nc/0"),
+            Err(AbcError::Malformed {
+                line: 2,
+                what: "a note length is out of range"
+            })
+        );
+        assert_eq!(
+            parse("K:C\nc'''''''"),
+            Err(AbcError::Malformed {
+                line: 2,
+                what: "a note is outside of the MIDI range"
+            })
+        );
+    }
+
+    #[test]
+    fn broken_rhythm() {
+        assert_eq!(
+            durations("L:1/4\nK:C\nc>d e<f g>>a"),
+            vec![
+                Rational::new(3, 8),
+                Rational::new(1, 8),
+                Rational::new(1, 8),
+                Rational::new(3, 8),
+                Rational::new(7, 16),
+                Rational::new(1, 16),
+            ]
+        );
+        // The bar keeps its length
+        let last = notes("L:1/4\nK:C\nc>d e<f g>>a")[5];
+        assert_eq!(last.1 + last.2, Rational::new(3, 2));
+    }
+
+    #[test]
+    fn tuplets() {
+        let third = Rational::new(1, 12);
+        // Three in the time of two, two in the time of three, and three in the time of two
+        // for only two notes
+        assert_eq!(
+            durations("L:1/8\nK:C\n(3cde f (2ga (3:2:2 bc d"),
+            vec![
+                third,
+                third,
+                third,
+                Rational::new(1, 8),
+                Rational::new(3, 16),
+                Rational::new(3, 16),
+                third,
+                third,
+                Rational::new(1, 8),
+            ]
+        );
+        // Five in the time of three in compound meters
+        assert_eq!(
+            durations("M:6/8\nL:1/8\nK:C\n(5cdefg")[0],
+            Rational::new(3, 40)
+        );
+        // A tuplet shortens a chord like a single note
+        assert_eq!(durations("L:1/8\nK:C\n(3[CE]DE")[0], third);
+    }
+
+    #[test]
+    fn key_signatures() {
+        assert_eq!(key("G"), [0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(key("F#m"), [1, 0, 0, 1, 1, 0, 0]);
+        assert_eq!(key("Bb dor"), [0, -1, -1, 0, 0, -1, -1]);
+        assert_eq!(key("Ddorian"), [0; 7]);
+        assert_eq!(key("D exp ^c =f"), [1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(key("none"), [0; 7]);
+        assert_eq!(key("H"), [0; 7]);
+    }
+
+    #[test]
+    fn key_signature_accidentals() {
+        let pitches = |text| {
+            notes(text)
+                .into_iter()
+                .map(|note| note.0)
+                .collect::<Vec<_>>()
+        };
+        // Accidentals last until the end of the bar, and only in their octave
+        assert_eq!(
+            pitches("K:F\nB ^B B b | B =B B | B"),
+            vec![70, 72, 72, 82, 70, 71, 71, 70]
+        );
+        // Accidentals in chords apply to the following notes
+        assert_eq!(pitches("K:D\n[=F=c] F c | F"), vec![65, 72, 65, 72, 66]);
+        assert_eq!(pitches("K:C\n__B ^^F"), vec![69, 67]);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(parse(""), Err(AbcError::NoTune));
+        assert_eq!(parse("X:1\nT:No key\nabc\n"), Err(AbcError::NoTune));
+        // A reference that is not a number is left out
+        let tunes = parse("X:one\nK:C\nc").unwrap();
+        assert_eq!(tunes[0].reference, None);
+        assert_eq!(tunes[0].notes.len(), 1);
+        // Meters and lengths that cannot be read are free meter and the default length
+        assert_eq!(
+            notes("M:3/x\nL:?/8\nK:C\nZ c"),
+            vec![(72, Rational::one(), Rational::new(1, 8))]
+        );
+        assert_eq!(durations("L:0/8\nK:C\nc"), vec![Rational::new(1, 8)]);
+        // Short meters default to sixteenths
+        assert_eq!(durations("M:2/4\nK:C\nc"), vec![Rational::new(1, 16)]);
+        // Additive meters add up their beats
+        assert_eq!(notes("M:2+3/8\nL:1/8\nK:C\nZ2 c")[0].1, Rational::new(5, 4));
+    }
+}
@@ -15,6 +15,8 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 // modules for making sounds
+pub mod abc;
+pub mod midi;
 pub mod nonnan;
 pub mod note;
 pub mod rational;
@@ -0,0 +1,497 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reading Standard MIDI Files (SMF), e.g. for importing melodies recorded elsewhere.
+//!
+//! Only notes and tempo changes are kept, all other events are skipped.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::note::{Note, Velocity};
+
+/// Microseconds per quarter note until the first tempo change, i.e. 120 beats per minute.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// The contents of a MIDI file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    /// 0 for a single track, 1 for tracks playing simultaneously.
+    /// Files of format 2 (independent patterns) are read like format 1.
+    pub format: u16,
+    pub tracks: Vec<MidiTrack>,
+    /// Converts the ticks of all tracks to time.
+    pub tempo_map: TempoMap,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MidiTrack {
+    /// The name given by the first track name event.
+    pub name: Option<String>,
+    /// The notes of all channels, ordered by their start.
+    pub notes: Vec<MidiNote>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiNote {
+    /// Channel between 0 and 15.
+    pub channel: u8,
+    pub note: Note,
+    pub velocity: Velocity,
+    /// Tick of the note on event.
+    pub start: u64,
+    /// Tick of the note off event. Notes that are never released end with their track.
+    pub end: u64,
+}
+
+/// How ticks are measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Ticks are fractions of a quarter note, whose length follows the tempo changes.
+    TicksPerQuarter(u16),
+    /// Ticks are fractions of a frame of an SMPTE time code, regardless of the tempo.
+    /// A frame rate of 29 stands for 29.97 frames per second (drop frame).
+    Smpte {
+        frames_per_second: u8,
+        ticks_per_frame: u8,
+    },
+}
+
+/// Maps ticks to time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    timing: Timing,
+    /// The ticks at which the tempo changes, with the new tempo in microseconds per quarter note,
+    /// ordered by tick.
+    changes: Vec<(u64, u32)>,
+}
+
+impl TempoMap {
+    /// A tempo map without tempo changes.
+    pub fn new(timing: Timing) -> Self {
+        Self {
+            timing,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Change the tempo to the given number of microseconds per quarter note at a tick.
+    pub fn change_tempo(&mut self, tick: u64, tempo: u32) {
+        let index = self
+            .changes
+            .iter()
+            .take_while(|(at, _)| *at <= tick)
+            .count();
+        self.changes.insert(index, (tick, tempo));
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// The time of a tick as fraction of seconds, which is exact for all practical files.
+    fn time(&self, tick: u64) -> (u128, u128) {
+        match self.timing {
+            Timing::TicksPerQuarter(ticks_per_quarter) => {
+                // Microseconds times ticks per quarter
+                let mut total = 0u128;
+                let mut last = (0, DEFAULT_TEMPO);
+                for &(at, tempo) in self.changes.iter().take_while(|(at, _)| *at < tick) {
+                    total += (at - last.0) as u128 * last.1 as u128;
+                    last = (at, tempo);
+                }
+                total += (tick - last.0) as u128 * last.1 as u128;
+                (total, ticks_per_quarter.max(1) as u128 * 1_000_000)
+            }
+            Timing::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => {
+                let ticks_per_frame = ticks_per_frame.max(1) as u128;
+                if frames_per_second == 29 {
+                    (tick as u128 * 1001, 30_000 * ticks_per_frame)
+                } else {
+                    (
+                        tick as u128,
+                        frames_per_second.max(1) as u128 * ticks_per_frame,
+                    )
+                }
+            }
+        }
+    }
+
+    /// The time of a tick in seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use syntxt_core::midi::*;
+    /// let mut tempo_map = TempoMap::new(Timing::TicksPerQuarter(96));
+    /// assert_eq!(tempo_map.seconds(192), 1.0);
+    /// // Twice as fast from the second quarter note on
+    /// tempo_map.change_tempo(96, 250_000);
+    /// assert_eq!(tempo_map.seconds(192), 0.75);
+    /// ```
+    pub fn seconds(&self, tick: u64) -> f64 {
+        let (numerator, denominator) = self.time(tick);
+        numerator as f64 / denominator as f64
+    }
+
+    /// The number of the sample, rounded to the nearest, at which a tick happens.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use syntxt_core::midi::*;
+    /// let tempo_map = TempoMap::new(Timing::Smpte { frames_per_second: 25, ticks_per_frame: 40 });
+    /// assert_eq!(tempo_map.sample(1000, 44100), 44100);
+    /// ```
+    pub fn sample(&self, tick: u64, sample_rate: u32) -> u64 {
+        let (numerator, denominator) = self.time(tick);
+        ((numerator * sample_rate as u128 + denominator / 2) / denominator) as u64
+    }
+}
+
+/// Why a MIDI file could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiError {
+    /// The data does not start with a MIDI header.
+    NotMidi,
+    /// The data ends in the middle of a chunk or event.
+    Truncated,
+    /// An event could not be decoded.
+    Malformed(&'static str),
+}
+
+impl Error for MidiError {}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::NotMidi => write!(f, "not a MIDI file"),
+            MidiError::Truncated => write!(f, "unexpected end of file"),
+            MidiError::Malformed(what) => write!(f, "malformed file: {}", what),
+        }
+    }
+}
+
+/// Read a MIDI file.
+///
+/// # Examples
+///
+/// ```
+/// # use syntxt_core::midi::*;
+/// # use syntxt_core::note::Note;
+/// let file = [
+///     b"MThd".as_ref(), &[0, 0, 0, 6, 0, 0, 0, 1, 0, 96],
+///     b"MTrk", &[0, 0, 0, 11],
+///     // Play a4 for a quarter note, with running status for the note off
+///     &[0x00, 0x90, 69, 100, 0x60, 69, 0, 0x00, 0xFF, 0x2F, 0x00],
+/// ].concat();
+/// let midi = parse(&file).unwrap();
+/// let note = midi.tracks[0].notes[0];
+/// assert_eq!((note.note, note.start, note.end), (Note::from_midi(69), 0, 96));
+/// assert_eq!(midi.tempo_map.seconds(note.end), 0.5);
+/// ```
+pub fn parse(bytes: &[u8]) -> Result<MidiFile, MidiError> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4).ok() != Some(b"MThd".as_ref()) {
+        return Err(MidiError::NotMidi);
+    }
+    let mut header = Reader {
+        bytes: reader.chunk()?,
+        position: 0,
+    };
+    let format = header.u16()?;
+    let _track_count = header.u16()?;
+    let division = header.u16()?;
+    let timing = if division & 0x8000 == 0 {
+        Timing::TicksPerQuarter(division)
+    } else {
+        Timing::Smpte {
+            frames_per_second: ((division >> 8) as u8 as i8).wrapping_neg() as u8,
+            ticks_per_frame: division as u8,
+        }
+    };
+
+    let mut tracks = Vec::new();
+    let mut tempo_map = TempoMap::new(timing);
+    while !reader.is_empty() {
+        let kind = reader.take(4)?;
+        let chunk = reader.chunk()?;
+        // Unknown chunks must be skipped
+        if kind == b"MTrk" {
+            tracks.push(track(chunk, &mut tempo_map)?);
+        }
+    }
+    Ok(MidiFile {
+        format,
+        tracks,
+        tempo_map,
+    })
+}
+
+/// Read the events of a track chunk.
+fn track(bytes: &[u8], tempo_map: &mut TempoMap) -> Result<MidiTrack, MidiError> {
+    let mut reader = Reader { bytes, position: 0 };
+    let mut track = MidiTrack::default();
+    // Pressed keys with their channel, velocity and start
+    let mut pressed: Vec<(u8, u8, u8, u64)> = Vec::new();
+    let mut running_status = None;
+    let mut tick = 0;
+    while !reader.is_empty() {
+        tick += reader.variable()? as u64;
+        let status = if reader.peek()? & 0x80 != 0 {
+            reader.u8()?
+        } else {
+            running_status.ok_or(MidiError::Malformed("data without status"))?
+        };
+        match status {
+            0xFF => {
+                running_status = None;
+                let kind = reader.u8()?;
+                let length = reader.variable()?;
+                let data = reader.take(length as usize)?;
+                match kind {
+                    0x03 if track.name.is_none() => {
+                        track.name = Some(String::from_utf8_lossy(data).into_owned())
+                    }
+                    0x2F => break,
+                    0x51 if data.len() == 3 => tempo_map.change_tempo(
+                        tick,
+                        (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32,
+                    ),
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                running_status = None;
+                let length = reader.variable()?;
+                reader.take(length as usize)?;
+            }
+            0xF1..=0xFE => return Err(MidiError::Malformed("system message in a track")),
+            _ => {
+                running_status = Some(status);
+                let channel = status & 0x0F;
+                let data = match status >> 4 {
+                    0xC | 0xD => [reader.u8()?, 0],
+                    _ => [reader.u8()?, reader.u8()?],
+                };
+                let key = data[0] & 0x7F;
+                match (status >> 4, data[1]) {
+                    (0x9, velocity) if velocity > 0 => pressed.push((channel, key, velocity, tick)),
+                    (0x8, _) | (0x9, _) => {
+                        // Releases go to the earliest press of the same key
+                        if let Some(index) =
+                            pressed.iter().position(|p| p.0 == channel && p.1 == key)
+                        {
+                            let press = pressed.remove(index);
+                            track.notes.push(note(press, tick));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    for press in pressed {
+        track.notes.push(note(press, tick));
+    }
+    track.notes.sort_by_key(|note| note.start);
+    Ok(track)
+}
+
+fn note((channel, key, velocity, start): (u8, u8, u8, u64), end: u64) -> MidiNote {
+    MidiNote {
+        channel,
+        note: Note::from_midi(key),
+        velocity: Velocity::from_f64((velocity & 0x7F) as f64 / 127.0),
+        start,
+        end,
+    }
+}
+
+/// Reads big-endian values from a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], MidiError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(MidiError::Truncated)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn peek(&self) -> Result<u8, MidiError> {
+        self.bytes
+            .get(self.position)
+            .copied()
+            .ok_or(MidiError::Truncated)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiError> {
+        let bytes = self.take(2)?;
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let bytes = self.take(4)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, byte| value << 8 | *byte as u32))
+    }
+
+    /// A variable length quantity of at most four bytes, seven bits each.
+    fn variable(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiError::Malformed("variable length quantity too long"))
+    }
+
+    /// The contents of a chunk, after its type.
+    fn chunk(&mut self) -> Result<&'a [u8], MidiError> {
+        let length = self.u32()?;
+        self.take(length as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, MidiError, Timing};
+    use crate::note::Note;
+
+    /// A file with the given division and track chunks.
+    fn file(division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 6, 0, 1, 0, tracks.len() as u8]);
+        bytes.extend_from_slice(&division.to_be_bytes());
+        for track in tracks {
+            bytes.extend_from_slice(b"MTrk");
+            bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(track);
+        }
+        bytes
+    }
+
+    #[test]
+    fn bad_header() {
+        assert_eq!(parse(b""), Err(MidiError::NotMidi));
+        assert_eq!(parse(b"MTrk\0\0\0\0"), Err(MidiError::NotMidi));
+        // A header chunk too short for the division
+        assert_eq!(
+            parse(b"MThd\0\0\0\x04\0\0\0\x01"),
+            Err(MidiError::Truncated)
+        );
+        // A header chunk longer than the file
+        assert_eq!(parse(b"MThd\0\0\0\x06\0\0"), Err(MidiError::Truncated));
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let mut bytes = file(96, &[&[0x00, 0x90, 69, 100, 0x60, 69, 0]]);
+        bytes.truncate(bytes.len() - 2);
+        assert_eq!(parse(&bytes), Err(MidiError::Truncated));
+        // The chunk is complete, but its last event is not
+        let bytes = file(96, &[&[0x00, 0x90, 69]]);
+        assert_eq!(parse(&bytes), Err(MidiError::Truncated));
+    }
+
+    #[test]
+    fn running_status() {
+        let bytes = file(
+            96,
+            &[&[
+                0x00, 0x90, 60, 100, // c4 on
+                0x00, 64, 90, // e4 on, running status
+                0x60, 60, 0, // c4 off by a note on without velocity
+                0x30, 0x80, 64, 0, // e4 off
+            ]],
+        );
+        let midi = parse(&bytes).unwrap();
+        let notes = midi.tracks[0]
+            .notes
+            .iter()
+            .map(|note| (note.note, note.start, note.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notes,
+            vec![(Note::from_midi(60), 0, 96), (Note::from_midi(64), 0, 144)]
+        );
+        // Meta events cancel the running status
+        let bytes = file(
+            96,
+            &[&[0x00, 0x90, 60, 100, 0x00, 0xFF, 0x01, 0x00, 0x60, 60, 0]],
+        );
+        assert_eq!(
+            parse(&bytes),
+            Err(MidiError::Malformed("data without status"))
+        );
+    }
+
+    #[test]
+    fn smpte_division() {
+        // 25 frames per second with 40 ticks each
+        let midi = parse(&file(0xE728, &[])).unwrap();
+        assert_eq!(
+            midi.tempo_map.timing(),
+            Timing::Smpte {
+                frames_per_second: 25,
+                ticks_per_frame: 40
+            }
+        );
+        assert_eq!(midi.tempo_map.seconds(1000), 1.0);
+        // Tempo changes do not matter
+        let midi = parse(&file(
+            0xE728,
+            &[&[0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40]],
+        ))
+        .unwrap();
+        assert_eq!(midi.tempo_map.seconds(1000), 1.0);
+        // 29.97 frames per second with 80 ticks each
+        let midi = parse(&file(0xE350, &[])).unwrap();
+        assert_eq!(midi.tempo_map.sample(30 * 80, 30_000), 30_030);
+    }
+
+    #[test]
+    fn zero_length_track() {
+        let midi = parse(&file(96, &[&[], &[0x00, 0x90, 69, 100, 0x60, 0x80, 69, 0]])).unwrap();
+        assert_eq!(midi.tracks.len(), 2);
+        assert_eq!(midi.tracks[0].name, None);
+        assert!(midi.tracks[0].notes.is_empty());
+        assert_eq!(midi.tracks[1].notes.len(), 1);
+    }
+}
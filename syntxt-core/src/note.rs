@@ -148,6 +148,40 @@ impl Note {
         Note::try_named(name, offset, octave)
     }
 
+    /// Look up the note of a drum sound by its name,
+    /// following the General MIDI percussion key map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use syntxt_core::note::*;
+    ///
+    /// assert_eq!(Note::drum("kick"), Some(Note::from_midi(36)));
+    /// assert_eq!(Note::drum("hat"), Some(Note::from_midi(42)));
+    /// assert_eq!(Note::drum("cowbell"), Some(Note::from_midi(56)));
+    /// assert_eq!(Note::drum("c4"), None);
+    /// ```
+    pub fn drum(name: &str) -> Option<Note> {
+        let midi_note = match name {
+            "kick" => 36,
+            "rim" => 37,
+            "snare" => 38,
+            "clap" => 39,
+            "hat" => 42,
+            "pedal" => 44,
+            "openhat" => 46,
+            "tom" => 45,
+            "midtom" => 47,
+            "hitom" => 50,
+            "crash" => 49,
+            "ride" => 51,
+            "tambourine" => 54,
+            "cowbell" => 56,
+            _ => return None,
+        };
+        Some(Note(midi_note))
+    }
+
     pub fn from_midi(midi_note: u8) -> Note {
         assert!(midi_note < 128, "MIDI only has notes 0 - 127");
         Note(midi_note)
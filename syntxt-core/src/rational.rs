@@ -16,6 +16,7 @@
 
 //! Rational numbers are used for designating times on the song level, e.g. note lenghts.
 
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::{cmp::Ordering, ops};
@@ -23,6 +24,9 @@ use std::{cmp::Ordering, ops};
 /// Underlying integral type for the rational numbers.
 type Int = i64;
 
+/// Integral type for intermediate results, wide enough to hold products of two `Int`s.
+type WideInt = i128;
+
 /// A rational number, always fully normalized.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Rational {
@@ -65,6 +69,20 @@ impl Rational {
         }
     }
 
+    /// Create a normalized rational from a fraction of wide integers,
+    /// or `None` if it cannot be represented or the denominator is zero.
+    fn from_wide(num: WideInt, denom: WideInt) -> Option<Rational> {
+        if denom == 0 {
+            return None;
+        }
+        let div = gcd_wide(num, denom);
+        let sign = num.signum() * denom.signum();
+        Some(Rational {
+            num: Int::try_from(sign * (num.abs() / div)).ok()?,
+            denom: Int::try_from(denom.abs() / div).ok()?,
+        })
+    }
+
     pub const fn int(int: Int) -> Rational {
         Rational { num: int, denom: 1 }
     }
@@ -177,24 +195,25 @@ impl Rational {
 
     // ==================== Checked Ops ====================
 
-    // TODO: Implement addition using LCM instead of naive multiplication of denominators
-    // so that we don't overflow as easily.
+    // Intermediate results are computed with twice the width, so only results that cannot be
+    // represented after normalization are reported as overflow.
 
     /// Addition with overflow check.
     ///
     /// ```
     /// use syntxt_core::rational::*;
     ///
-    /// assert_eq!(Rational::new(1, 4294967296).checked_add(Rational::new(3, 4294967296)), None);
+    /// assert_eq!(Rational::new(1, 4294967296).checked_add(Rational::new(3, 4294967296)), Some(Rational::new(1, 1073741824)));
+    /// assert_eq!(Rational::new(1, 4294967296).checked_add(Rational::new(1, 4294967295)), None);
+    /// assert_eq!(Rational::int(i64::MAX).checked_add(Rational::one()), None);
     /// assert_eq!(Rational::new(3, 4).checked_add(Rational::new(3, 4)), Some(Rational::new(3, 2)));
     /// ```
     pub fn checked_add(self, rhs: Rational) -> Option<Self> {
-        Some(Rational::new(
-            self.num
-                .checked_mul(rhs.denom)?
-                .checked_add(self.denom.checked_mul(rhs.num)?)?,
-            self.denom.checked_mul(rhs.denom)?,
-        ))
+        Rational::from_wide(
+            WideInt::from(self.num) * WideInt::from(rhs.denom)
+                + WideInt::from(rhs.num) * WideInt::from(self.denom),
+            WideInt::from(self.denom) * WideInt::from(rhs.denom),
+        )
     }
 
     /// Subtraction with overflow check.
@@ -203,16 +222,47 @@ impl Rational {
     }
 
     /// Multiplication with overflow check.
+    ///
+    /// ```
+    /// use syntxt_core::rational::*;
+    ///
+    /// assert_eq!(Rational::new(4294967296, 3).checked_mul(Rational::new(3, 4294967296)), Some(Rational::one()));
+    /// assert_eq!(Rational::int(4294967296).checked_mul(Rational::int(4294967296)), None);
+    /// ```
     pub fn checked_mul(self, rhs: Rational) -> Option<Self> {
-        Some(Rational::new(
-            self.num.checked_mul(rhs.num)?,
-            self.denom.checked_mul(rhs.denom)?,
-        ))
+        Rational::from_wide(
+            WideInt::from(self.num) * WideInt::from(rhs.num),
+            WideInt::from(self.denom) * WideInt::from(rhs.denom),
+        )
     }
 
-    /// Division with overflow check.
+    /// Division with overflow check. Dividing by zero returns `None` as well.
+    ///
+    /// ```
+    /// use syntxt_core::rational::*;
+    ///
+    /// assert_eq!(Rational::new(1, 2).checked_div(Rational::new(-1, 4)), Some(Rational::int(-2)));
+    /// assert_eq!(Rational::one().checked_div(Rational::zero()), None);
+    /// ```
     pub fn checked_div(self, rhs: Rational) -> Option<Self> {
-        self.checked_mul(rhs.recip())
+        Rational::from_wide(
+            WideInt::from(self.num) * WideInt::from(rhs.denom),
+            WideInt::from(self.denom) * WideInt::from(rhs.num),
+        )
+    }
+
+    /// Remainder with overflow check. Taking the remainder of a division by zero returns `None`
+    /// as well.
+    pub fn checked_rem(self, rhs: Rational) -> Option<Self> {
+        let self_num = WideInt::from(self.num) * WideInt::from(rhs.denom);
+        let rhs_num = WideInt::from(rhs.num) * WideInt::from(self.denom);
+        if rhs_num == 0 {
+            return None;
+        }
+        Rational::from_wide(
+            self_num % rhs_num,
+            WideInt::from(self.denom) * WideInt::from(rhs.denom),
+        )
     }
 
     /// Compute an integer power of the rational.
@@ -262,15 +312,16 @@ impl Rational {
 /// assert_eq!(Rational::new(3, 4) + Rational::new(3, 4), Rational::new(3, 2));
 /// assert_eq!(Rational::new(3, 4) + Rational::new(-5, 8), Rational::new(1, 8));
 /// ```
+///
+/// # Panics
+///
+/// The arithmetic operators panic if the result cannot be represented, in debug and release
+/// builds alike. Use the `checked_*` methods for values that are not under control.
 impl ops::Add for Rational {
     type Output = Rational;
 
-    #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Rational) -> Self::Output {
-        Rational::new(
-            self.num * rhs.denom + self.denom * rhs.num,
-            self.denom * rhs.denom,
-        )
+        self.checked_add(rhs).expect("Rational addition overflowed")
     }
 }
 
@@ -286,16 +337,17 @@ impl ops::Mul for Rational {
     type Output = Rational;
 
     fn mul(self, rhs: Rational) -> Self::Output {
-        Rational::new(self.num * rhs.num, self.denom * rhs.denom)
+        self.checked_mul(rhs)
+            .expect("Rational multiplication overflowed")
     }
 }
 
 impl ops::Div for Rational {
     type Output = Rational;
 
-    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, rhs: Rational) -> Self::Output {
-        self * rhs.recip()
+        assert!(!rhs.is_zero(), "Division by zero");
+        self.checked_div(rhs).expect("Rational division overflowed")
     }
 }
 
@@ -312,12 +364,10 @@ impl ops::Div for Rational {
 impl ops::Rem for Rational {
     type Output = Rational;
 
-    #[allow(clippy::suspicious_arithmetic_impl)]
     fn rem(self, rhs: Rational) -> Self::Output {
-        let common_denom = self.denom * rhs.denom;
-        let self_num = self.num * rhs.denom;
-        let rhs_num = rhs.num * self.denom;
-        Rational::new(self_num % rhs_num, common_denom)
+        assert!(!rhs.is_zero(), "Division by zero");
+        self.checked_rem(rhs)
+            .expect("Rational remainder overflowed")
     }
 }
 
@@ -325,7 +375,7 @@ impl ops::Mul<Int> for Rational {
     type Output = Rational;
 
     fn mul(self, rhs: Int) -> Self::Output {
-        Rational::new(self.num * rhs, self.denom)
+        self * Rational::int(rhs)
     }
 }
 
@@ -333,7 +383,7 @@ impl ops::Mul<Rational> for Int {
     type Output = Rational;
 
     fn mul(self, rhs: Rational) -> Self::Output {
-        Rational::new(self * rhs.num, rhs.denom)
+        Rational::int(self) * rhs
     }
 }
 
@@ -345,9 +395,8 @@ impl ops::Mul<Rational> for Int {
 impl ops::Div<Int> for Rational {
     type Output = Rational;
 
-    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, rhs: Int) -> Self::Output {
-        Rational::new(self.num, self.denom * rhs)
+        self / Rational::int(rhs)
     }
 }
 
@@ -404,8 +453,8 @@ impl Ord for Rational {
         // a < c * b / d
         // <=>
         // a * d < c * b
-        let l = self.num * other.denom;
-        let r = other.num * self.denom;
+        let l = WideInt::from(self.num) * WideInt::from(other.denom);
+        let r = WideInt::from(other.num) * WideInt::from(self.denom);
         l.cmp(&r)
     }
 }
@@ -514,3 +563,15 @@ pub fn gcd(mut a: Int, mut b: Int) -> Int {
     }
     a
 }
+
+/// Like `gcd`, but for intermediate results.
+fn gcd_wide(mut a: WideInt, mut b: WideInt) -> WideInt {
+    a = a.abs();
+    b = b.abs();
+    while b != 0 {
+        let t = b;
+        b = a % b;
+        a = t;
+    }
+    a
+}
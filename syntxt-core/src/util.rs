@@ -69,3 +69,17 @@ pub fn from_semitones(semitones: f64) -> f64 {
 pub fn from_decibels(decibels: f64) -> f64 {
     10.0f64.powf(decibels / 10.0)
 }
+
+/// Compute the decibels corresponding to a factor, the inverse of `from_decibels`.
+///
+/// # Example
+///
+/// ```
+/// # use syntxt_core::util::*;
+///
+/// assert_eq!(to_decibels(100.0), 20.0);
+/// assert_eq!(to_decibels(from_decibels(-6.0)), -6.0);
+/// ```
+pub fn to_decibels(factor: f64) -> f64 {
+    10.0 * factor.log10()
+}
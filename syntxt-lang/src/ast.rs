@@ -68,6 +68,12 @@ pub enum Expr {
         operator: Node<BinaryOp>,
         right: NodePtr<Expr>,
     },
+    /// A range of integers, e.g. `0..8` or `0..=8`.
+    Range {
+        start: NodePtr<Expr>,
+        operator: Node<RangeOp>,
+        end: NodePtr<Expr>,
+    },
     Paren {
         lparen: Node<()>,
         expr: NodePtr<Expr>,
@@ -85,9 +91,24 @@ pub enum Expr {
         lparen: Node<()>,
         // TODO: Also keep track of commas in argument list
         arguments: Vec<Node<Expr>>,
+        /// Arguments given by name, e.g. `rate: 1/8`, following the positional arguments.
+        named_arguments: Vec<Node<Attribute>>,
         rparen: Node<()>,
     },
     Sequence(NodePtr<Sequence>),
+    List {
+        lbracket: Node<()>,
+        // TODO: Also keep track of commas in element list
+        elements: Vec<Node<Expr>>,
+        rbracket: Node<()>,
+    },
+    /// Accessing an element of a list by its position, e.g. `steps[3]`.
+    Index {
+        expr: NodePtr<Expr>,
+        lbracket: Node<()>,
+        index: NodePtr<Expr>,
+        rbracket: Node<()>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -99,9 +120,47 @@ pub struct Sequence {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SeqSym {
-    Note { note: Note, duration: Rational },
+    Note {
+        note: Note,
+        duration: Rational,
+        articulation: Articulation,
+        /// Whether the note is accented, written as `>` after the duration.
+        accent: bool,
+    },
     Rest { duration: Rational },
     Group(NodePtr<Sequence>),
+    /// A line of a drum grid playing a single note, e.g. `kick--|x . X .|`.
+    Grid {
+        note: Note,
+        /// The duration of each step.
+        step: Rational,
+        steps: Vec<GridStep>,
+    },
+    /// Bending the pitch of the following notes, e.g. `^0.5`. The amount is between -1 and 1,
+    /// in multiples of the bend range of the instrument. A bend takes no time.
+    Bend { amount: F64N },
+}
+
+/// A step of a drum grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridStep {
+    /// Nothing is played, written as `.`.
+    Rest,
+    /// The note is played, written as `x`.
+    Hit,
+    /// The note is played accented, written as `X`.
+    Accent,
+}
+
+/// How long a note sounds in relation to its written duration,
+/// written as a marker after the duration of the note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Articulation {
+    Normal,
+    /// Short and detached, written as `'`.
+    Staccato,
+    /// Connected to the following note, written as `~`.
+    Legato,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -121,6 +180,14 @@ pub enum BinaryOp {
     Or,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeOp {
+    /// `..`, excluding the end
+    Exclusive,
+    /// `..=`, including the end
+    Inclusive,
+}
+
 pub trait Visit {
     fn visit(&self, visitor: &mut dyn Visitor);
 }
@@ -239,6 +306,14 @@ impl Walk for Expr {
                 left.visit(visitor);
                 right.visit(visitor);
             }
+            Expr::Range {
+                start,
+                operator,
+                end,
+            } => {
+                start.visit(visitor);
+                end.visit(visitor);
+            }
             Expr::Paren {
                 lparen,
                 expr,
@@ -260,14 +335,32 @@ impl Walk for Expr {
                 callee,
                 lparen,
                 arguments,
+                named_arguments,
                 rparen,
             } => {
                 callee.visit(visitor);
                 arguments.visit(visitor);
+                named_arguments.visit(visitor);
             }
             Expr::Sequence(seq) => {
                 seq.visit(visitor);
             }
+            Expr::List {
+                lbracket,
+                elements,
+                rbracket,
+            } => {
+                elements.visit(visitor);
+            }
+            Expr::Index {
+                expr,
+                lbracket,
+                index,
+                rbracket,
+            } => {
+                expr.visit(visitor);
+                index.visit(visitor);
+            }
         }
     }
 }
@@ -284,6 +377,8 @@ impl Walk for SeqSym {
             // These are leaves that cannot be walked further
             SeqSym::Note { .. } => {}
             SeqSym::Rest { .. } => {}
+            SeqSym::Grid { .. } => {}
+            SeqSym::Bend { .. } => {}
             // Visit the nested group
             SeqSym::Group(seq) => {
                 seq.visit(visitor);
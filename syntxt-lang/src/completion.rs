@@ -0,0 +1,374 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Completion suggestions for editors.
+//!
+//! Source code being edited is rarely valid, so the context of a completion request is
+//! determined from the tokens around the cursor rather than from the AST.
+
+use logos::Logos;
+
+use crate::{
+    lexer::{Span, Token},
+    schema,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// An attribute of an object.
+    Attribute,
+    /// An object type, e.g. `Track`.
+    ObjectType,
+    /// The id of an object defined in the file.
+    Id,
+    /// A builtin function.
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Short description of the suggested item, if available.
+    pub detail: Option<&'static str>,
+}
+
+/// Suggest what could be inserted at the given byte offset of the source code.
+///
+/// If the cursor is placed at the end of a partially typed identifier, only suggestions
+/// starting with that identifier are returned, and they are meant to replace it.
+pub fn complete(source: &str, offset: usize) -> Vec<Completion> {
+    let tokens = Token::lexer(source).spanned().collect::<Vec<_>>();
+    // Index of the first token that is not before the cursor
+    let mut cursor = tokens
+        .iter()
+        .position(|(_, span)| span.start >= offset)
+        .unwrap_or(tokens.len());
+    let mut prefix = "";
+    if cursor > 0 {
+        let span = &tokens[cursor - 1].1;
+        if offset <= span.end && is_identifier(&source[span.start..offset]) {
+            prefix = &source[span.start..offset];
+            cursor -= 1;
+        }
+    }
+
+    let context = Context::analyze(source, &tokens, cursor);
+    let mut completions = Vec::new();
+    match &context.position {
+        Position::Member => match context.enclosing() {
+            None => object_types(&mut completions, schema::ROOT_OBJECTS),
+            Some(frame) => {
+                let object = frame.name.as_deref().and_then(schema::object);
+                let attributes = std::iter::once(&schema::ID_ATTRIBUTE)
+                    .chain(object.iter().flat_map(|obj| obj.attributes.iter()));
+                for attr in attributes {
+                    if !frame.attributes.iter().any(|name| name == attr.name) {
+                        completions.push(Completion {
+                            label: attr.name.to_string(),
+                            kind: CompletionKind::Attribute,
+                            detail: Some(attr.doc),
+                        });
+                    }
+                }
+                if let Some(object) = object {
+                    object_types(&mut completions, object.children);
+                }
+            }
+        },
+        Position::Expr => {
+            for frame in context.frames.iter() {
+                if let Some(id) = frame.id.as_ref() {
+                    if !completions.iter().any(|c| &c.label == id) {
+                        completions.push(Completion {
+                            label: id.clone(),
+                            kind: CompletionKind::Id,
+                            detail: frame
+                                .name
+                                .as_deref()
+                                .and_then(schema::object)
+                                .map(|obj| obj.doc),
+                        });
+                    }
+                }
+            }
+            for function in schema::FUNCTIONS {
+                completions.push(Completion {
+                    label: function.name.to_string(),
+                    kind: CompletionKind::Function,
+                    detail: Some(function.doc),
+                });
+            }
+            let all = schema::OBJECTS
+                .iter()
+                .map(|obj| obj.name)
+                .collect::<Vec<_>>();
+            object_types(&mut completions, &all);
+        }
+        Position::Accessor(id) => {
+            // Like everywhere else, the first object with a given id wins
+            let frame = context
+                .frames
+                .iter()
+                .find(|frame| frame.id.as_ref() == Some(id));
+            for attr in frame.iter().flat_map(|frame| frame.attributes.iter()) {
+                if !completions.iter().any(|c| &c.label == attr) {
+                    completions.push(Completion {
+                        label: attr.clone(),
+                        kind: CompletionKind::Attribute,
+                        detail: None,
+                    });
+                }
+            }
+        }
+        Position::Definition => {}
+    }
+
+    completions.retain(|completion| completion.label.starts_with(prefix));
+    completions
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(ch) if ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+fn object_types(completions: &mut Vec<Completion>, names: &[&str]) {
+    for name in names {
+        if let Some(object) = schema::object(name) {
+            completions.push(Completion {
+                label: object.name.to_string(),
+                kind: CompletionKind::ObjectType,
+                detail: Some(object.doc),
+            });
+        }
+    }
+}
+
+/// What kind of thing is expected at the cursor.
+#[derive(Debug, PartialEq, Eq)]
+enum Position {
+    /// An attribute or a child object of the enclosing object, or a top-level object.
+    Member,
+    /// An arbitrary expression.
+    Expr,
+    /// An attribute after a `.` following the given id.
+    Accessor(String),
+    /// A new name, i.e. the value of an `id` attribute.
+    Definition,
+}
+
+/// An object or other bracketed group in the source code.
+#[derive(Debug)]
+struct Frame {
+    /// The object type, or `None` for groups that are not objects.
+    name: Option<String>,
+    is_object: bool,
+    /// The value of the `id` attribute, if any.
+    id: Option<String>,
+    /// The names of the attributes defined directly in this group.
+    attributes: Vec<String>,
+}
+
+struct Context {
+    position: Position,
+    /// All groups of the file in the order they are opened.
+    frames: Vec<Frame>,
+    /// The innermost group enclosing the cursor.
+    enclosing: Option<usize>,
+}
+
+impl Context {
+    fn analyze(source: &str, tokens: &[(Token, Span)], cursor: usize) -> Context {
+        let text = |index: usize| &source[tokens[index].1.clone()];
+        let token = |index: usize| tokens.get(index).map(|(token, _)| *token);
+
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut stack: Vec<usize> = Vec::new();
+        let mut enclosing = None;
+        for (index, (current, _)) in tokens.iter().enumerate() {
+            if index == cursor {
+                enclosing = stack.last().copied();
+            }
+            match current {
+                Token::LBrace | Token::LParen | Token::LBracket | Token::LLBracket => {
+                    let is_object = *current == Token::LBrace;
+                    let name = if is_object && index > 0 && token(index - 1) == Some(Token::Ident) {
+                        Some(text(index - 1).to_string())
+                    } else {
+                        None
+                    };
+                    frames.push(Frame {
+                        name,
+                        is_object,
+                        id: None,
+                        attributes: Vec::new(),
+                    });
+                    stack.push(frames.len() - 1);
+                }
+                Token::RBrace | Token::RParen | Token::RBracket | Token::RRBracket => {
+                    stack.pop();
+                }
+                Token::Colon if index > 0 && token(index - 1) == Some(Token::Ident) => {
+                    if let Some(&frame) = stack.last() {
+                        let name = text(index - 1);
+                        if name == "id" && token(index + 1) == Some(Token::Ident) {
+                            frames[frame].id = Some(text(index + 1).to_string());
+                        }
+                        frames[frame].attributes.push(name.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if cursor >= tokens.len() {
+            enclosing = stack.last().copied();
+        }
+
+        let previous = if cursor > 0 { token(cursor - 1) } else { None };
+        let position = match previous {
+            Some(Token::Dot) if cursor > 1 && token(cursor - 2) == Some(Token::Ident) => {
+                Position::Accessor(text(cursor - 2).to_string())
+            }
+            Some(Token::Colon) if cursor > 1 && text(cursor - 2) == "id" => Position::Definition,
+            Some(Token::Colon)
+            | Some(Token::Plus)
+            | Some(Token::Minus)
+            | Some(Token::Star)
+            | Some(Token::Slash)
+            | Some(Token::Percent)
+            | Some(Token::Not)
+            | Some(Token::And)
+            | Some(Token::Or)
+            | Some(Token::Pipe)
+            | Some(Token::DotDot)
+            | Some(Token::DotDotEq)
+            | Some(Token::Comma)
+            | Some(Token::LParen)
+            | Some(Token::LBracket) => Position::Expr,
+            _ => match enclosing.map(|index| frames[index].is_object) {
+                Some(false) => Position::Expr,
+                _ => Position::Member,
+            },
+        };
+
+        Context {
+            position,
+            frames,
+            enclosing,
+        }
+    }
+
+    fn enclosing(&self) -> Option<&Frame> {
+        self.enclosing.map(|index| &self.frames[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::complete;
+    use expect_test::{expect, Expect};
+
+    /// Complete at the position marked with `$`.
+    fn check(input: &str, output: Expect) {
+        let offset = input.find('$').unwrap();
+        let source = input.replacen('$', "", 1);
+        let completions = complete(&source, offset)
+            .into_iter()
+            .map(|completion| format!("{:?} {}", completion.kind, completion.label))
+            .collect::<Vec<_>>()
+            .join("\n");
+        output.assert_eq(&completions);
+    }
+
+    #[test]
+    fn top_level() {
+        check("$", expect![["ObjectType Song"]]);
+        check("S$", expect![["ObjectType Song"]]);
+        check("X$", expect![[""]]);
+    }
+
+    #[test]
+    fn attributes_of_enclosing_object() {
+        check(
+            r#"Song {
+    bpm: 120
+    $
+    Track { name: "x" }
+}"#,
+            expect![[r#"
+                Attribute id
+                Attribute sampleRate
+                Attribute volume
+                Attribute layout
+                Attribute meta
+                ObjectType Track
+                ObjectType Lyrics"#]],
+        );
+        check("Song { Track { n$ } }", expect![["Attribute name"]]);
+        check(
+            "Song { meta: Meta { name: \"x\"\n a$",
+            expect![["Attribute author"]],
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        check(
+            "Song { Track { id: lead } Track { name: $ } }",
+            expect![[r#"
+                Id lead
+                Function importMidi
+                Function importAbc
+                ObjectType Song
+                ObjectType Meta
+                ObjectType Track
+                ObjectType Sequence
+                ObjectType Eq
+                ObjectType LowShelf
+                ObjectType Peak
+                ObjectType HighShelf
+                ObjectType Gate
+                ObjectType Clip
+                ObjectType Plugin
+                ObjectType Param
+                ObjectType Lyrics
+                ObjectType Line"#]],
+        );
+        check(
+            "Song { Track { id: lead } Track { name: (1 + l$) } }",
+            expect![["Id lead"]],
+        );
+        check("Song { Track { id: l$ } }", expect![[""]]);
+        check(
+            "Song { Track { Sequence { notes: imp$ } } }",
+            expect![[r#"
+                Function importMidi
+                Function importAbc"#]],
+        );
+    }
+
+    #[test]
+    fn accessors() {
+        check(
+            "Song { Track { id: lead volume: 2 } Track { volume: lead.$ } }",
+            expect![[r#"
+                Attribute id
+                Attribute volume"#]],
+        );
+    }
+}
@@ -0,0 +1,232 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of attributes that (indirectly) depend on themselves, e.g.
+//!
+//! ```text
+//! Track { id: a  volume: b.volume }
+//! Track { id: b  volume: a.volume * 2 }
+//! ```
+//!
+//! Such attributes can never be computed. Each cycle is reported with one diagnostic per
+//! reference that is part of it, all of them describing the full chain.
+
+use std::collections::HashSet;
+
+use crate::{
+    ast::{self, Node, Visit, Walk},
+    diagnostic::Diagnostic,
+    symbols::{object_id, SymbolId, SymbolTable},
+};
+
+/// A reference from the value of one attribute to another attribute.
+struct Edge {
+    target: SymbolId,
+    /// The attribute name in the accessor expression.
+    reference: Node<String>,
+}
+
+/// Report cycles between the attributes of a file.
+pub fn check_cycles(root: &Node<ast::Root>, table: &SymbolTable) -> Vec<Diagnostic> {
+    let mut collector = EdgeCollector {
+        table,
+        edges: (0..table.symbols().len()).map(|_| Vec::new()).collect(),
+        source: None,
+    };
+    root.walk(&mut collector);
+
+    let mut search = CycleSearch {
+        table,
+        edges: &collector.edges,
+        finished: vec![false; collector.edges.len()],
+        stack: Vec::new(),
+        reported: HashSet::new(),
+        diagnostics: Vec::new(),
+    };
+    for start in 0..collector.edges.len() {
+        search.visit(start);
+    }
+    search.diagnostics
+}
+
+/// Collect the dependencies between the attributes of objects that have ids.
+struct EdgeCollector<'a> {
+    table: &'a SymbolTable,
+    /// Outgoing edges, indexed by the source symbol.
+    edges: Vec<Vec<Edge>>,
+    /// The attribute whose value is currently visited, if it can be referenced.
+    source: Option<SymbolId>,
+}
+
+impl<'a> ast::Visitor for EdgeCollector<'a> {
+    fn object(&mut self, node: &Node<ast::Object>) {
+        let id = object_id(&node.data).and_then(|id| self.table.object(&id.data));
+        // Attributes of nested objects are only needed once that object is accessed
+        let source = self.source.take();
+        for attr in node.data.attrs.iter() {
+            // Only the first definition of an attribute is referenced by accessors
+            self.source = id
+                .and_then(|object| self.table.attribute(object, &attr.data.name.data))
+                .filter(|symbol| self.table.get(*symbol).definition.span == attr.data.name.span);
+            attr.visit(self);
+        }
+        self.source = None;
+        node.data.children.visit(self);
+        self.source = source;
+    }
+
+    fn attribute(&mut self, node: &Node<ast::Attribute>) {
+        // Named arguments of calls belong to the attribute that contains the call
+        node.walk(self);
+    }
+
+    fn expr(&mut self, node: &Node<ast::Expr>) {
+        if let ast::Expr::Accessor {
+            expr, attribute, ..
+        } = &node.data
+        {
+            if let (Some(source), ast::Expr::Var(name)) = (self.source, &expr.data) {
+                let target = self
+                    .table
+                    .object(name)
+                    .and_then(|object| self.table.attribute(object, &attribute.data));
+                if let Some(target) = target {
+                    self.edges[source.index()].push(Edge {
+                        target,
+                        reference: attribute.clone(),
+                    });
+                }
+            }
+        }
+        node.walk(self);
+    }
+}
+
+/// Depth-first search keeping the chain of attributes that are being resolved.
+struct CycleSearch<'a> {
+    table: &'a SymbolTable,
+    edges: &'a [Vec<Edge>],
+    finished: Vec<bool>,
+    /// The attributes currently being resolved, along with the edge taken out of them.
+    stack: Vec<(usize, usize)>,
+    /// Cycles that have already been reported, identified by their sorted edges.
+    reported: HashSet<Vec<(usize, usize)>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> CycleSearch<'a> {
+    fn visit(&mut self, symbol: usize) {
+        if self.finished[symbol] {
+            return;
+        }
+        if let Some(start) = self.stack.iter().position(|(s, _)| *s == symbol) {
+            self.report(start);
+            return;
+        }
+        for edge in 0..self.edges[symbol].len() {
+            self.stack.push((symbol, edge));
+            self.visit(self.edges[symbol][edge].target.index());
+            self.stack.pop();
+        }
+        self.finished[symbol] = true;
+    }
+
+    /// Report the cycle formed by the stack entries starting at `start`.
+    fn report(&mut self, start: usize) {
+        let cycle = &self.stack[start..];
+        let mut key = cycle.to_vec();
+        key.sort_unstable();
+        if !self.reported.insert(key) {
+            return;
+        }
+
+        let mut chain = cycle
+            .iter()
+            .map(|(symbol, _)| self.qualified_name(*symbol))
+            .collect::<Vec<_>>();
+        chain.push(chain[0].clone());
+        let chain = chain.join(" -> ");
+
+        for (symbol, edge) in cycle {
+            let edge = &self.edges[*symbol][*edge];
+            self.diagnostics.push(Diagnostic::error(
+                edge.reference.span.clone(),
+                edge.reference.pos.clone(),
+                format!(
+                    "`{}` depends on `{}`, forming a cycle: {}",
+                    self.qualified_name(*symbol),
+                    self.qualified_name(edge.target.index()),
+                    chain
+                ),
+            ));
+        }
+    }
+
+    fn qualified_name(&self, symbol: usize) -> String {
+        let symbol = self.table.get(SymbolId::from_index(symbol));
+        match symbol.container {
+            Some(object) => format!("{}.{}", self.table.get(object).name(), symbol.name()),
+            None => symbol.name().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_cycles;
+    use crate::{parser::Parser, symbols::SymbolTable};
+    use expect_test::{expect, Expect};
+
+    fn check(input: &str, output: Expect) {
+        let root = Parser::parse(input).unwrap();
+        let table = SymbolTable::build(&root);
+        let diagnostics = check_cycles(&root, &table)
+            .into_iter()
+            .map(|diag| format!("{:?}: {}", diag.pos.start, diag.message))
+            .collect::<Vec<_>>()
+            .join("\n");
+        output.assert_eq(&diagnostics);
+    }
+
+    #[test]
+    fn no_cycles() {
+        check(
+            r#"Song {
+    Track { id: a  volume: 1  pan: a.volume }
+    Track { id: b  volume: a.volume + a.pan  x: Meta { y: b.x } }
+}"#,
+            expect![[""]],
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        check(
+            r#"Song {
+    Track { id: a  volume: b.volume }
+    Track { id: b  volume: a.volume * c.pan  pan: b.pan }
+    Track { id: c  pan: a.volume }
+}"#,
+            expect![[r#"
+                2:30: `a.volume` depends on `b.volume`, forming a cycle: a.volume -> b.volume -> a.volume
+                3:30: `b.volume` depends on `a.volume`, forming a cycle: a.volume -> b.volume -> a.volume
+                2:30: `a.volume` depends on `b.volume`, forming a cycle: a.volume -> b.volume -> c.pan -> a.volume
+                3:41: `b.volume` depends on `c.pan`, forming a cycle: a.volume -> b.volume -> c.pan -> a.volume
+                4:27: `c.pan` depends on `a.volume`, forming a cycle: a.volume -> b.volume -> c.pan -> a.volume
+                3:53: `b.pan` depends on `b.pan`, forming a cycle: b.pan -> b.pan"#]],
+        );
+    }
+}
@@ -0,0 +1,79 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Problems found in source code, shared by the parser and the later analysis passes.
+
+use std::ops::Range;
+
+use crate::{lexer::Span, line_map::Pos, parser::ParseError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub pos: Range<Pos>,
+    pub severity: Severity,
+    pub message: String,
+    /// Other places in the source that help understanding the problem.
+    pub related: Vec<Related>,
+}
+
+/// A source location mentioned by a diagnostic, e.g. the first definition of a duplicate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Related {
+    pub span: Span,
+    pub pos: Range<Pos>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, pos: Range<Pos>, message: String) -> Diagnostic {
+        Diagnostic {
+            span,
+            pos,
+            severity: Severity::Error,
+            message,
+            related: Vec::new(),
+        }
+    }
+
+    pub fn warning(span: Span, pos: Range<Pos>, message: String) -> Diagnostic {
+        Diagnostic {
+            span,
+            pos,
+            severity: Severity::Warning,
+            message,
+            related: Vec::new(),
+        }
+    }
+
+    /// Point to another location related to this diagnostic.
+    pub fn with_related(mut self, span: Span, pos: Range<Pos>, message: String) -> Diagnostic {
+        self.related.push(Related { span, pos, message });
+        self
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Diagnostic {
+        Diagnostic::error(err.span, err.pos, err.message)
+    }
+}
@@ -0,0 +1,538 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Laying out source code uniformly, as done by `syntxt fmt`.
+//!
+//! Every attribute and child object goes on its own line, indented by four spaces,
+//! and operators and commas are spaced the same everywhere. Objects, lists and argument lists
+//! written on a single line stay on one line, so short objects like `Line { start: 2 }`
+//! keep their compact form. Literals and sequences are kept as written, only moved to the
+//! new indentation.
+//!
+//! Comments and blank lines between attributes and objects are kept. Expressions containing
+//! comments are kept as written as well, since their comments have no place in the syntax tree.
+//!
+//! ```
+//! use syntxt_lang::format::format;
+//! let source = "Song{bpm:120\nTrack{ gain: 1/2*volume\nSequence{notes:[[ c4 e4 ]]}}}";
+//! assert_eq!(
+//!     format(source).unwrap(),
+//!     r#"Song {
+//!     bpm: 120
+//!     Track {
+//!         gain: 1/2 * volume
+//!         Sequence { notes: [[ c4 e4 ]] }
+//!     }
+//! }
+//! "#
+//! );
+//! ```
+
+use logos::Logos;
+
+use crate::ast::{self, Node};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lexer::{Span, Token};
+use crate::parser::Parser;
+
+const INDENT: &str = "    ";
+
+/// The formatted source code, or the errors preventing it from being parsed.
+/// Warnings do not matter, formatting even fixes some of them, e.g. missing colons.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let (root, diagnostics) = Parser::parse_with_diagnostics(source);
+    let errors = diagnostics
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut formatter = Formatter {
+        source,
+        out: String::new(),
+    };
+    formatter.root(&root);
+    Ok(formatter.out)
+}
+
+/// What lies between two formatted parts of the source.
+#[derive(Debug, Default)]
+struct Gap<'a> {
+    /// A comment on the same line as the part before
+    trailing: Option<&'a str>,
+    /// Comments on their own lines, where `None` stands for blank lines
+    lines: Vec<Option<&'a str>>,
+}
+
+/// An attribute or child object of an object.
+enum Member<'a> {
+    Attribute(&'a Node<ast::Attribute>),
+    Object(&'a Node<ast::Object>),
+}
+
+impl Member<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Member::Attribute(attribute) => attribute.span.clone(),
+            Member::Object(object) => object.span.clone(),
+        }
+    }
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    fn root(&mut self, root: &Node<ast::Root>) {
+        let mut end = 0;
+        for (index, object) in root.data.objects.iter().enumerate() {
+            let mut gap = self.gap(end..object.span.start);
+            if index > 0 {
+                self.trailing(&gap);
+                self.out.push('\n');
+            } else if let Some(comment) = gap.trailing.take() {
+                // Nothing precedes the first line
+                gap.lines.insert(0, Some(comment));
+            }
+            self.lines(&gap, 0, index == 0);
+            self.object(object, 0);
+            end = object.span.end;
+        }
+        let gap = self.gap(end..self.source.len());
+        self.trailing(&gap);
+        if !root.data.objects.is_empty() {
+            self.out.push('\n');
+        }
+        self.closing_lines(&gap, 0);
+    }
+
+    fn object(&mut self, object: &Node<ast::Object>, depth: usize) {
+        let mut members = object
+            .data
+            .attrs
+            .iter()
+            .map(Member::Attribute)
+            .chain(object.data.children.iter().map(Member::Object))
+            .collect::<Vec<_>>();
+        members.sort_by_key(|member| member.span().start);
+
+        self.out.push_str(&object.data.name.data);
+        let text = &self.source[object.span.clone()];
+        if !text.contains('\n') && !self.has_comment(object.span.clone()) {
+            self.out.push_str(" {");
+            for member in members.iter() {
+                self.out.push(' ');
+                self.member(member, depth);
+            }
+            self.out
+                .push_str(if members.is_empty() { "}" } else { " }" });
+            return;
+        }
+
+        self.out.push_str(" {");
+        let mut end = object.data.lbrace.span.end;
+        for (index, member) in members.iter().enumerate() {
+            let gap = self.gap(end..member.span().start);
+            self.trailing(&gap);
+            self.out.push('\n');
+            self.lines(&gap, depth + 1, index == 0);
+            self.indent(depth + 1);
+            self.member(member, depth + 1);
+            end = member.span().end;
+        }
+        let gap = self.gap(end..object.data.rbrace.span.start);
+        self.trailing(&gap);
+        self.out.push('\n');
+        self.closing_lines(&gap, depth + 1);
+        self.indent(depth);
+        self.out.push('}');
+    }
+
+    fn member(&mut self, member: &Member, depth: usize) {
+        match member {
+            Member::Object(object) => self.object(object, depth),
+            Member::Attribute(attribute) if self.has_comment(attribute.span.clone()) => {
+                self.verbatim(attribute.span.clone(), depth)
+            }
+            Member::Attribute(attribute) => self.attribute(attribute, depth),
+        }
+    }
+
+    fn attribute(&mut self, attribute: &Node<ast::Attribute>, depth: usize) {
+        self.out.push_str(&attribute.data.name.data);
+        self.out.push_str(": ");
+        self.expr(&attribute.data.value, depth);
+    }
+
+    fn expr(&mut self, expr: &Node<ast::Expr>, depth: usize) {
+        if self.has_comment(expr.span.clone()) {
+            self.verbatim(expr.span.clone(), depth);
+            return;
+        }
+        match &expr.data {
+            ast::Expr::String(_)
+            | ast::Expr::Int(_)
+            | ast::Expr::Ratio(_)
+            | ast::Expr::Float(_)
+            | ast::Expr::Bool(_)
+            | ast::Expr::Var(_)
+            | ast::Expr::Sequence(_) => self.verbatim(expr.span.clone(), depth),
+            ast::Expr::Unary { operator, operand } => {
+                self.out.push_str(match operator.data {
+                    ast::UnaryOp::Plus => "+",
+                    ast::UnaryOp::Minus => "-",
+                    ast::UnaryOp::Not => "not ",
+                });
+                self.expr(operand, depth);
+            }
+            ast::Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.expr(left, depth);
+                self.out.push_str(match operator.data {
+                    ast::BinaryOp::Add => " + ",
+                    ast::BinaryOp::Sub => " - ",
+                    ast::BinaryOp::Mult => " * ",
+                    ast::BinaryOp::Div => " / ",
+                    ast::BinaryOp::And => " and ",
+                    ast::BinaryOp::Or => " or ",
+                });
+                self.expr(right, depth);
+            }
+            ast::Expr::Range {
+                start,
+                operator,
+                end,
+            } => {
+                self.expr(start, depth);
+                self.out.push_str(match operator.data {
+                    ast::RangeOp::Exclusive => "..",
+                    ast::RangeOp::Inclusive => "..=",
+                });
+                self.expr(end, depth);
+            }
+            ast::Expr::Paren { expr, .. } => {
+                self.out.push('(');
+                self.expr(expr, depth);
+                self.out.push(')');
+            }
+            ast::Expr::Object(object) => self.object(object, depth),
+            ast::Expr::Accessor {
+                expr, attribute, ..
+            } => {
+                self.expr(expr, depth);
+                self.out.push('.');
+                self.out.push_str(&attribute.data);
+            }
+            ast::Expr::Call {
+                callee,
+                lparen,
+                arguments,
+                named_arguments,
+                rparen,
+            } => {
+                // Calls written with `|>` have their first argument in front of the function
+                let piped = arguments
+                    .first()
+                    .filter(|input| input.span.start < callee.span.start);
+                let arguments = match piped {
+                    Some(input) => {
+                        self.expr(input, depth);
+                        self.out.push_str(" |> ");
+                        &arguments[1..]
+                    }
+                    None => &arguments[..],
+                };
+                self.expr(callee, depth);
+                if lparen.span.is_empty() {
+                    return;
+                }
+                let multiline = self.source[lparen.span.start..rparen.span.end].contains('\n');
+                self.out.push('(');
+                let elements = arguments
+                    .iter()
+                    .map(Element::Expr)
+                    .chain(named_arguments.iter().map(Element::Named));
+                self.elements(elements, multiline, depth);
+                self.out.push(')');
+            }
+            ast::Expr::List {
+                lbracket,
+                elements,
+                rbracket,
+            } => {
+                let multiline = self.source[lbracket.span.start..rbracket.span.end].contains('\n');
+                self.out.push('[');
+                self.elements(elements.iter().map(Element::Expr), multiline, depth);
+                self.out.push(']');
+            }
+            ast::Expr::Index { expr, index, .. } => {
+                self.expr(expr, depth);
+                self.out.push('[');
+                self.expr(index, depth);
+                self.out.push(']');
+            }
+        }
+    }
+
+    /// The elements of a list or the arguments of a call, either separated by commas,
+    /// or each on its own line with a trailing comma.
+    fn elements<'e>(
+        &mut self,
+        elements: impl Iterator<Item = Element<'e>>,
+        multiline: bool,
+        depth: usize,
+    ) {
+        let mut empty = true;
+        for (index, element) in elements.enumerate() {
+            empty = false;
+            if multiline {
+                self.out.push('\n');
+                self.indent(depth + 1);
+            } else if index > 0 {
+                self.out.push_str(", ");
+            }
+            let element_depth = if multiline { depth + 1 } else { depth };
+            match element {
+                Element::Expr(expr) => self.expr(expr, element_depth),
+                Element::Named(attribute) => self.attribute(attribute, element_depth),
+            }
+            if multiline {
+                self.out.push(',');
+            }
+        }
+        if multiline && !empty {
+            self.out.push('\n');
+            self.indent(depth);
+        }
+    }
+
+    /// Copy source code as written, moving all but its first line to the new indentation.
+    fn verbatim(&mut self, span: Span, depth: usize) {
+        let line_start = self.source[..span.start]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let prefix = &self.source[line_start..span.start];
+        let old_indent = prefix.len() - prefix.trim_start().len();
+        for (index, line) in self.source[span].split('\n').enumerate() {
+            if index > 0 {
+                self.out.push('\n');
+                let indent = line.len() - line.trim_start().len();
+                let line = &line[indent.min(old_indent)..];
+                if !line.trim().is_empty() {
+                    self.indent(depth);
+                    self.out.push_str(line.trim_end());
+                }
+            } else {
+                self.out.push_str(line.trim_end());
+            }
+        }
+    }
+
+    fn indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// The comments and blank lines between two parts of the source.
+    fn gap(&self, span: Span) -> Gap<'a> {
+        let mut gap = Gap::default();
+        for (index, line) in self.source[span].split('\n').enumerate() {
+            let comment = line.find("//").map(|start| line[start..].trim_end());
+            if index == 0 {
+                gap.trailing = comment;
+            } else if comment.is_some() || line.trim().is_empty() {
+                gap.lines.push(comment);
+            }
+        }
+        // The last line is the one of the following part
+        if let Some(None) = gap.lines.last() {
+            gap.lines.pop();
+        }
+        gap
+    }
+
+    fn trailing(&mut self, gap: &Gap) {
+        if let Some(comment) = gap.trailing {
+            self.out.push(' ');
+            self.out.push_str(comment);
+        }
+    }
+
+    /// The comments of a gap before a part on its own line, keeping single blank lines
+    /// between them, except at the start of a block.
+    fn lines(&mut self, gap: &Gap, depth: usize, first: bool) {
+        let mut blank = first;
+        for line in gap.lines.iter() {
+            match line {
+                None if !blank => {
+                    self.out.push('\n');
+                    blank = true;
+                }
+                None => {}
+                Some(comment) => {
+                    self.indent(depth);
+                    self.out.push_str(comment);
+                    self.out.push('\n');
+                    blank = false;
+                }
+            }
+        }
+    }
+
+    /// The comments of a gap at the end of a block, without blank lines after them.
+    fn closing_lines(&mut self, gap: &Gap, depth: usize) {
+        let last_comment = gap.lines.iter().rposition(|line| line.is_some());
+        if let Some(last) = last_comment {
+            let gap = Gap {
+                trailing: None,
+                lines: gap.lines[..=last].to_vec(),
+            };
+            self.lines(&gap, depth, true);
+        }
+    }
+
+    /// Whether there is a comment between the tokens of a part of the source.
+    fn has_comment(&self, span: Span) -> bool {
+        let text = &self.source[span];
+        let mut end = 0;
+        for (_, token) in Token::lexer(text).spanned() {
+            if text[end..token.start].contains("//") {
+                return true;
+            }
+            end = token.end;
+        }
+        text[end..].contains("//")
+    }
+}
+
+/// An element of a list or argument list.
+enum Element<'a> {
+    Expr(&'a Node<ast::Expr>),
+    Named(&'a Node<ast::Attribute>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    fn check(source: &str, expected: &str) {
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        // Formatting twice changes nothing
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn layout() {
+        check(
+            "Song  {  bpm:120 volume : 0.5\n  Track{name:\"lead\"}\n}",
+            r#"Song {
+    bpm: 120
+    volume: 0.5
+    Track { name: "lead" }
+}
+"#,
+        );
+        check("Track {\n}\nTrack {  }", "Track {\n}\nTrack {}\n");
+    }
+
+    #[test]
+    fn expressions() {
+        check(
+            r#"Song {
+  a: -(1 + 2)*x.y
+  b: not true and false or true
+  c: steps[ 1 ]
+  d: 0..=8
+  e: lfo(1/4,shape:"sine")
+  f: 8 |> times(2) |> reverse
+  g: [1,2 , 3]
+  h: Meta { name: "x" }
+}"#,
+            r#"Song {
+    a: -(1 + 2) * x.y
+    b: not true and false or true
+    c: steps[1]
+    d: 0..=8
+    e: lfo(1/4, shape: "sine")
+    f: 8 |> times(2) |> reverse
+    g: [1, 2, 3]
+    h: Meta { name: "x" }
+}
+"#,
+        );
+        check(
+            "Song {\n  chords: [\n   1, 2,\n  3]\n}",
+            "Song {\n    chords: [\n        1,\n        2,\n        3,\n    ]\n}\n",
+        );
+    }
+
+    #[test]
+    fn sequences() {
+        check(
+            "Song {\n  Track {\n    Sequence {\n      notes: [[\n        c4 e4\n          g4\n      ]]\n    }\n  }\n}",
+            "Song {\n    Track {\n        Sequence {\n            notes: [[\n              c4 e4\n                g4\n            ]]\n        }\n    }\n}\n",
+        );
+    }
+
+    #[test]
+    fn comments() {
+        check(
+            r#"// A song
+Song { // the only one
+  bpm: 120 // fast
+
+
+  // The lead
+  Track {}
+  volume: [
+    1, // first
+    2
+  ]
+  // The end
+}
+// Bye"#,
+            r#"// A song
+Song { // the only one
+    bpm: 120 // fast
+
+    // The lead
+    Track {}
+    volume: [
+      1, // first
+      2
+    ]
+    // The end
+}
+// Bye
+"#,
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(format("Song { bpm: }").is_err());
+        // Missing colons are only warnings, which formatting fixes
+        assert_eq!(format("Song { bpm 120 }").unwrap(), "Song { bpm: 120 }\n");
+    }
+}
@@ -0,0 +1,834 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writing syntax trees and object models as JSON, for tools that work with syn.txt files.
+//!
+//! Every node of the syntax tree has a `span` with the byte offsets, lines and columns of its
+//! start and end, and so does every object of the model, pointing at the source code it was
+//! defined by. Rationals are written as strings like `"3/4"` so that they stay exact, and notes
+//! as MIDI note numbers.
+
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use syntxt_core::{
+    nonnan::F64N,
+    note::{Note, Velocity},
+    rational::Rational,
+};
+
+use crate::{
+    ast::{self, Node},
+    line_map::Pos,
+    model,
+    timeline::{BendEvent, NoteEvent},
+};
+
+/// A JSON value. The fields of objects keep their order.
+///
+/// Displaying a value pretty-prints it, keeping arrays and objects that only contain numbers,
+/// strings and the like on a single line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// A number, written as `null` if it is not finite.
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// The value of a field, if this is an object that has it.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Int(value) => write!(f, "{}", value),
+            Json::Float(value) if value.is_finite() => write!(f, "{}", value),
+            Json::Float(_) => write!(f, "null"),
+            Json::String(value) => write_string(f, value),
+            Json::Array(elements) => {
+                let items = elements.iter().map(|element| (None, element));
+                write_items(f, indent, "[]", items)
+            }
+            Json::Object(fields) => {
+                let items = fields
+                    .iter()
+                    .map(|(name, value)| (Some(name.as_str()), value));
+                write_items(f, indent, "{}", items)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+fn write_items<'a>(
+    f: &mut fmt::Formatter,
+    indent: usize,
+    brackets: &str,
+    items: impl Iterator<Item = (Option<&'a str>, &'a Json)> + Clone,
+) -> fmt::Result {
+    let (open, close) = brackets.split_at(1);
+    let flat = items.clone().all(|(_, value)| value.is_scalar());
+    write!(f, "{}", open)?;
+    for (i, (name, value)) in items.enumerate() {
+        if flat {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+        } else {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "\n{:width$}", "", width = indent + 2)?;
+        }
+        if let Some(name) = name {
+            write_string(f, name)?;
+            write!(f, ": ")?;
+        }
+        value.write(f, indent + 2)?;
+    }
+    if !flat {
+        write!(f, "\n{:width$}", "", width = indent)?;
+    }
+    write!(f, "{}", close)
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Values that can be written as JSON.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl ToJson for () {
+    fn to_json(&self) -> Json {
+        Json::Null
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl ToJson for i64 {
+    fn to_json(&self) -> Json {
+        Json::Int(*self)
+    }
+}
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Json {
+        Json::Float(*self)
+    }
+}
+
+impl ToJson for F64N {
+    fn to_json(&self) -> Json {
+        Json::Float(self.into_inner())
+    }
+}
+
+impl ToJson for Rational {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl ToJson for Note {
+    fn to_json(&self) -> Json {
+        Json::Int(self.to_midi().into())
+    }
+}
+
+impl ToJson for Velocity {
+    fn to_json(&self) -> Json {
+        Json::Float(self.as_f64())
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl ToJson for PathBuf {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string_lossy().into_owned())
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        match self {
+            Some(value) => value.to_json(),
+            None => Json::Null,
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Arc<T> {
+    fn to_json(&self) -> Json {
+        T::to_json(self)
+    }
+}
+
+/// Enums without data are written as the names of their variants.
+macro_rules! variant_names {
+    ($($ty:ty),*) => {
+        $(
+            impl ToJson for $ty {
+                fn to_json(&self) -> Json {
+                    Json::String(format!("{:?}", self))
+                }
+            }
+        )*
+    };
+}
+
+variant_names!(
+    ast::GridStep,
+    ast::Articulation,
+    ast::UnaryOp,
+    ast::BinaryOp,
+    ast::RangeOp,
+    model::EqBandKind
+);
+
+// Syntax tree
+
+/// The start and end of a node in the source code.
+fn span<T>(node: &Node<T>) -> Json {
+    let position = |offset: usize, pos: Pos| {
+        Json::object(vec![
+            ("offset", Json::Int(offset as i64)),
+            ("line", Json::Int(pos.line as i64)),
+            ("column", Json::Int(pos.column as i64)),
+        ])
+    };
+    Json::object(vec![
+        ("start", position(node.span.start, node.pos.start)),
+        ("end", position(node.span.end, node.pos.end)),
+    ])
+}
+
+/// Nodes are written as their data with an additional `span` field, or with the data in a
+/// `value` field if it is not an object.
+impl<T: ToJson> ToJson for Node<T> {
+    fn to_json(&self) -> Json {
+        let span = ("span".to_string(), span(self));
+        match self.data.to_json() {
+            Json::Object(mut fields) => {
+                fields.insert(0, span);
+                Json::Object(fields)
+            }
+            Json::Null => Json::Object(vec![span]),
+            value => Json::Object(vec![span, ("value".to_string(), value)]),
+        }
+    }
+}
+
+impl ToJson for ast::Root {
+    fn to_json(&self) -> Json {
+        Json::object(vec![("objects", self.objects.to_json())])
+    }
+}
+
+impl ToJson for ast::Object {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", self.name.to_json()),
+            ("lbrace", self.lbrace.to_json()),
+            ("attrs", self.attrs.to_json()),
+            ("children", self.children.to_json()),
+            ("rbrace", self.rbrace.to_json()),
+        ])
+    }
+}
+
+impl ToJson for ast::Attribute {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", self.name.to_json()),
+            ("colon", self.colon.to_json()),
+            ("value", self.value.to_json()),
+        ])
+    }
+}
+
+/// Expressions are objects with a `kind` field naming the variant.
+impl ToJson for ast::Expr {
+    fn to_json(&self) -> Json {
+        use ast::Expr;
+        let (kind, fields) = match self {
+            Expr::String(value) => ("String", vec![("value", value.to_json())]),
+            Expr::Int(value) => ("Int", vec![("value", value.to_json())]),
+            Expr::Ratio(value) => ("Ratio", vec![("value", value.to_json())]),
+            Expr::Float(value) => ("Float", vec![("value", value.to_json())]),
+            Expr::Bool(value) => ("Bool", vec![("value", value.to_json())]),
+            Expr::Unary { operator, operand } => (
+                "Unary",
+                vec![
+                    ("operator", operator.to_json()),
+                    ("operand", operand.to_json()),
+                ],
+            ),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => (
+                "Binary",
+                vec![
+                    ("left", left.to_json()),
+                    ("operator", operator.to_json()),
+                    ("right", right.to_json()),
+                ],
+            ),
+            Expr::Range {
+                start,
+                operator,
+                end,
+            } => (
+                "Range",
+                vec![
+                    ("start", start.to_json()),
+                    ("operator", operator.to_json()),
+                    ("end", end.to_json()),
+                ],
+            ),
+            Expr::Paren {
+                lparen,
+                expr,
+                rparen,
+            } => (
+                "Paren",
+                vec![
+                    ("lparen", lparen.to_json()),
+                    ("expr", expr.to_json()),
+                    ("rparen", rparen.to_json()),
+                ],
+            ),
+            Expr::Object(object) => ("Object", vec![("object", object.to_json())]),
+            Expr::Var(name) => ("Var", vec![("name", name.to_json())]),
+            Expr::Accessor {
+                expr,
+                dot,
+                attribute,
+            } => (
+                "Accessor",
+                vec![
+                    ("expr", expr.to_json()),
+                    ("dot", dot.to_json()),
+                    ("attribute", attribute.to_json()),
+                ],
+            ),
+            Expr::Call {
+                callee,
+                lparen,
+                arguments,
+                named_arguments,
+                rparen,
+            } => (
+                "Call",
+                vec![
+                    ("callee", callee.to_json()),
+                    ("lparen", lparen.to_json()),
+                    ("arguments", arguments.to_json()),
+                    ("named_arguments", named_arguments.to_json()),
+                    ("rparen", rparen.to_json()),
+                ],
+            ),
+            Expr::Sequence(sequence) => ("Sequence", vec![("sequence", sequence.to_json())]),
+            Expr::List {
+                lbracket,
+                elements,
+                rbracket,
+            } => (
+                "List",
+                vec![
+                    ("lbracket", lbracket.to_json()),
+                    ("elements", elements.to_json()),
+                    ("rbracket", rbracket.to_json()),
+                ],
+            ),
+            Expr::Index {
+                expr,
+                lbracket,
+                index,
+                rbracket,
+            } => (
+                "Index",
+                vec![
+                    ("expr", expr.to_json()),
+                    ("lbracket", lbracket.to_json()),
+                    ("index", index.to_json()),
+                    ("rbracket", rbracket.to_json()),
+                ],
+            ),
+        };
+        tagged(kind, fields)
+    }
+}
+
+impl ToJson for ast::Sequence {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("llbracket", self.llbracket.to_json()),
+            ("symbols", self.symbols.to_json()),
+            ("rrbracket", self.rrbracket.to_json()),
+        ])
+    }
+}
+
+/// Sequence symbols are objects with a `kind` field naming the variant.
+impl ToJson for ast::SeqSym {
+    fn to_json(&self) -> Json {
+        use ast::SeqSym;
+        let (kind, fields) = match self {
+            SeqSym::Note {
+                note,
+                duration,
+                articulation,
+                accent,
+            } => (
+                "Note",
+                vec![
+                    ("note", note.to_json()),
+                    ("duration", duration.to_json()),
+                    ("articulation", articulation.to_json()),
+                    ("accent", accent.to_json()),
+                ],
+            ),
+            SeqSym::Rest { duration } => ("Rest", vec![("duration", duration.to_json())]),
+            SeqSym::Group(sequence) => ("Group", vec![("sequence", sequence.to_json())]),
+            SeqSym::Grid { note, step, steps } => (
+                "Grid",
+                vec![
+                    ("note", note.to_json()),
+                    ("step", step.to_json()),
+                    ("steps", steps.to_json()),
+                ],
+            ),
+            SeqSym::Bend { amount } => ("Bend", vec![("amount", amount.to_json())]),
+        };
+        tagged(kind, fields)
+    }
+}
+
+fn tagged(kind: &str, mut fields: Vec<(&str, Json)>) -> Json {
+    fields.insert(0, ("kind", Json::String(kind.to_string())));
+    Json::object(fields)
+}
+
+// Object model
+
+/// Resolved values are written as just their value.
+impl<T: ToJson> ToJson for model::Resolved<T> {
+    fn to_json(&self) -> Json {
+        self.value.to_json()
+    }
+}
+
+impl ToJson for model::Layout {
+    fn to_json(&self) -> Json {
+        Json::String(self.name().to_string())
+    }
+}
+
+impl ToJson for model::Speakers {
+    fn to_json(&self) -> Json {
+        Json::String(self.name().to_string())
+    }
+}
+
+impl ToJson for model::Song {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("bpm", self.bpm.to_json()),
+            ("sample_rate", self.sample_rate.to_json()),
+            ("volume", self.volume.to_json()),
+            ("layout", self.layout.to_json()),
+            ("meta", self.meta.to_json()),
+            ("tracks", self.tracks.to_json()),
+            ("lyrics", self.lyrics.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Meta {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", self.name.to_json()),
+            ("author", self.author.to_json()),
+            ("year", self.year.to_json()),
+            ("description", self.description.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Track {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("name", self.name.to_json()),
+            ("volume", self.volume.to_json()),
+            ("pan", self.pan.to_json()),
+            ("speakers", self.speakers.to_json()),
+            ("mute", self.mute.to_json()),
+            ("solo", self.solo.to_json()),
+            ("freeze", self.freeze.to_json()),
+            ("voices", self.voices.to_json()),
+            ("sequences", self.sequences.to_json()),
+            ("eq", self.eq.to_json()),
+            ("gates", self.gates.to_json()),
+            ("plugin", self.plugin.to_json()),
+            ("params", self.params.to_json()),
+            ("plugins", self.plugins.to_json()),
+            ("clips", self.clips.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::EqBand {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("kind", self.kind.to_json()),
+            ("frequency", self.frequency.to_json()),
+            ("gain", self.gain.to_json()),
+            ("q", self.q.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Gate {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("threshold", self.threshold.to_json()),
+            ("attack", self.attack.to_json()),
+            ("release", self.release.to_json()),
+            ("pattern", self.pattern.to_json()),
+            ("length", self.length.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Plugin {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("path", self.path.to_json()),
+            ("params", self.params.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Clip {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("file", self.file.to_json()),
+            ("path", self.path.to_json()),
+            ("start", self.start.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Param {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("name", self.name.to_json()),
+            ("value", self.value.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Sequence {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("start", self.start.to_json()),
+            ("notes", self.notes.to_json()),
+            ("bends", self.bends.to_json()),
+        ])
+    }
+}
+
+impl ToJson for model::Line {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("start", self.start.to_json()),
+            ("text", self.text.to_json()),
+        ])
+    }
+}
+
+impl ToJson for NoteEvent {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("note", self.note.to_json()),
+            ("start", self.start.to_json()),
+            ("duration", self.duration.to_json()),
+            ("articulation", self.articulation.to_json()),
+            ("accent", self.accent.to_json()),
+            ("velocity", self.velocity.to_json()),
+        ])
+    }
+}
+
+impl ToJson for BendEvent {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("span", span(&self.origin)),
+            ("amount", self.amount.to_json()),
+            ("start", self.start.to_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Json, ToJson};
+    use crate::{model::resolve, parser::Parser};
+    use expect_test::{expect, Expect};
+
+    fn check(json: Json, output: Expect) {
+        output.assert_eq(&json.to_string());
+    }
+
+    #[test]
+    fn pretty_printing() {
+        check(
+            Json::object(vec![
+                ("text", Json::String("say \"hi\"\n\u{1}".to_string())),
+                ("flat", Json::Array(vec![Json::Int(1), Json::Float(0.5)])),
+                ("empty", Json::Object(vec![])),
+                (
+                    "nested",
+                    Json::Array(vec![Json::object(vec![("nan", Json::Float(f64::NAN))])]),
+                ),
+            ]),
+            expect![[r#"
+                {
+                  "text": "say \"hi\"\n\u0001",
+                  "flat": [1, 0.5],
+                  "empty": {},
+                  "nested": [
+                    {"nan": null}
+                  ]
+                }"#]],
+        );
+    }
+
+    #[test]
+    fn syntax_tree() {
+        let root = Parser::parse("Song { bpm: -120 }").unwrap();
+        check(
+            root.to_json(),
+            expect![[r#"
+            {
+              "span": {
+                "start": {"offset": 0, "line": 1, "column": 1},
+                "end": {"offset": 18, "line": 1, "column": 19}
+              },
+              "objects": [
+                {
+                  "span": {
+                    "start": {"offset": 0, "line": 1, "column": 1},
+                    "end": {"offset": 18, "line": 1, "column": 19}
+                  },
+                  "name": {
+                    "span": {
+                      "start": {"offset": 0, "line": 1, "column": 1},
+                      "end": {"offset": 4, "line": 1, "column": 5}
+                    },
+                    "value": "Song"
+                  },
+                  "lbrace": {
+                    "span": {
+                      "start": {"offset": 5, "line": 1, "column": 6},
+                      "end": {"offset": 6, "line": 1, "column": 7}
+                    }
+                  },
+                  "attrs": [
+                    {
+                      "span": {
+                        "start": {"offset": 7, "line": 1, "column": 8},
+                        "end": {"offset": 16, "line": 1, "column": 17}
+                      },
+                      "name": {
+                        "span": {
+                          "start": {"offset": 7, "line": 1, "column": 8},
+                          "end": {"offset": 10, "line": 1, "column": 11}
+                        },
+                        "value": "bpm"
+                      },
+                      "colon": {
+                        "span": {
+                          "start": {"offset": 10, "line": 1, "column": 11},
+                          "end": {"offset": 11, "line": 1, "column": 12}
+                        }
+                      },
+                      "value": {
+                        "span": {
+                          "start": {"offset": 12, "line": 1, "column": 13},
+                          "end": {"offset": 16, "line": 1, "column": 17}
+                        },
+                        "kind": "Int",
+                        "value": -120
+                      }
+                    }
+                  ],
+                  "children": [],
+                  "rbrace": {
+                    "span": {
+                      "start": {"offset": 17, "line": 1, "column": 18},
+                      "end": {"offset": 18, "line": 1, "column": 19}
+                    }
+                  }
+                }
+              ]
+            }"#]],
+        );
+    }
+
+    #[test]
+    fn model() {
+        let root = Parser::parse(
+            r#"Song {
+    Track {
+        name: "lead"
+        Sequence { notes: [[ c4+ ^0.5 d4> ]] }
+    }
+}"#,
+        )
+        .unwrap();
+        let (song, _) = resolve(&root);
+        let song = song.unwrap().to_json();
+        let track = match song.get("tracks") {
+            Some(Json::Array(tracks)) => &tracks[0],
+            _ => panic!("no tracks"),
+        };
+        assert_eq!(track.get("name"), Some(&Json::String("lead".to_string())));
+        check(
+            track.get("sequences").unwrap().clone(),
+            expect![[r#"
+            [
+              {
+                "span": {
+                  "start": {"offset": 48, "line": 4, "column": 9},
+                  "end": {"offset": 86, "line": 4, "column": 47}
+                },
+                "start": "0",
+                "notes": [
+                  {
+                    "span": {
+                      "start": {"offset": 69, "line": 4, "column": 30},
+                      "end": {"offset": 72, "line": 4, "column": 33}
+                    },
+                    "note": 60,
+                    "start": "0",
+                    "duration": "1/2",
+                    "articulation": "Normal",
+                    "accent": false,
+                    "velocity": null
+                  },
+                  {
+                    "span": {
+                      "start": {"offset": 78, "line": 4, "column": 39},
+                      "end": {"offset": 81, "line": 4, "column": 42}
+                    },
+                    "note": 62,
+                    "start": "1/2",
+                    "duration": "1/4",
+                    "articulation": "Normal",
+                    "accent": true,
+                    "velocity": null
+                  }
+                ],
+                "bends": [
+                  {
+                    "span": {
+                      "start": {"offset": 73, "line": 4, "column": 34},
+                      "end": {"offset": 77, "line": 4, "column": 38}
+                    },
+                    "amount": 0.5,
+                    "start": "1/2"
+                  }
+                ]
+              }
+            ]"#]],
+        );
+    }
+}
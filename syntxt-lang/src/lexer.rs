@@ -22,6 +22,7 @@ pub use logos::Span;
 #[derive(Logos, Debug, PartialEq, Clone, Copy)]
 #[logos(subpattern decimal = r"[0-9][_0-9]*")]
 #[logos(subpattern notelen = r"(\+*|-*)\.*")]
+#[logos(subpattern articulation = r"['~>]*")]
 pub enum Token {
     // Operators
     #[token("+")]
@@ -40,6 +41,8 @@ pub enum Token {
     And,
     #[token("or")]
     Or,
+    #[token("|>")]
+    Pipe,
 
     // Punctuation
     #[token(":")]
@@ -50,6 +53,10 @@ pub enum Token {
     Comma,
     #[token(".")]
     Dot,
+    #[token("..")]
+    DotDot,
+    #[token("..=")]
+    DotDotEq,
 
     // Grouping
     #[token("{")]
@@ -77,8 +84,15 @@ pub enum Token {
     Ident,
     // Note that this might conflict with identifiers. Normally though, one simply shouldn't
     // use identifiers that short anyways, so in practice, it might not be a big problem.
-    #[regex(r"([a-gA-G](♯|#|♭|b)?[0-9]|[rR])(?&notelen)(_(?&notelen))*", priority=2)]
+    #[regex(r"([a-gA-G](♯|#|♭|b)?[0-9]|[rR])(?&notelen)(_(?&notelen))*(?&articulation)", priority=2)]
     Note,
+    // A line of a drum grid, a drum name or note followed by the step length and the steps,
+    // e.g. `kick--|x . X .|`.
+    #[regex(r"[a-zA-Z_][a-zA-Z0-9_#♯♭]*(\+*|-*)\|[xX. ]*\|")]
+    Grid,
+    // A pitch bend in a sequence, e.g. `^0.5` or `^-1`.
+    #[regex(r"\^[+-]?(?&decimal)(\.(?&decimal))?")]
+    Bend,
 
     // Literals
     #[regex(r#""([^"\\\n]|\\[^\u0000-\u001F])*""#)]
@@ -221,5 +235,29 @@ mod tests {
         check("g3++", expect![[r#"[(Note, 0..4)]"#]]);
         check("c2-. d2--", expect![[r#"[(Note, 0..4), (Note, 5..9)]"#]]);
         check("[[ c2+__-. d2-- ]]", expect![[r#"[(LLBracket, 0..2), (Note, 3..10), (Note, 11..15), (RRBracket, 16..18)]"#]]);
+        check("a4' b4-.> c4~>", expect![[r#"[(Note, 0..3), (Note, 4..9), (Note, 10..14)]"#]]);
+    }
+
+    #[test]
+    fn pipes() {
+        check("x |> f(1)|>g", expect![[r#"[(Ident, 0..1), (Pipe, 2..4), (Ident, 5..6), (LParen, 6..7), (LitInt, 7..8), (RParen, 8..9), (Pipe, 9..11), (Ident, 11..12)]"#]]);
+    }
+
+    #[test]
+    fn ranges() {
+        check("0..8", expect![[r#"[(LitInt, 0..1), (DotDot, 1..3), (LitInt, 3..4)]"#]]);
+        check("1/4..=x.y", expect![[r#"[(LitRatio, 0..3), (DotDotEq, 3..6), (Ident, 6..7), (Dot, 7..8), (Ident, 8..9)]"#]]);
+    }
+
+    #[test]
+    fn grids() {
+        check("kick--|x . X .|", expect![[r#"[(Grid, 0..15)]"#]]);
+        check("[[ c#2|x x| hat|.x| ]]", expect![[r#"[(LLBracket, 0..2), (Grid, 3..11), (Grid, 12..19), (RRBracket, 20..22)]"#]]);
+        check("x |> y", expect![[r#"[(Ident, 0..1), (Pipe, 2..4), (Ident, 5..6)]"#]]);
+    }
+
+    #[test]
+    fn bends() {
+        check("[[ c4 ^0.5 d4 ^-1 ^0 ]]", expect![[r#"[(LLBracket, 0..2), (Note, 3..5), (Bend, 6..10), (Note, 11..13), (Bend, 14..17), (Bend, 18..20), (RRBracket, 21..23)]"#]]);
     }
 }
@@ -15,6 +15,20 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod ast;
+pub mod completion;
+pub mod cycles;
+pub mod diagnostic;
+pub mod format;
+pub mod json;
 pub mod lexer;
 pub mod line_map;
+pub mod lint;
+pub mod model;
 pub mod parser;
+pub mod report;
+pub mod schema;
+pub mod semantic;
+pub mod symbols;
+pub mod timeline;
+
+pub use completion::complete;
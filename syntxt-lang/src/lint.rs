@@ -0,0 +1,317 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks for songs that are valid, but probably do not sound as intended:
+//!
+//! - ids that are never referenced,
+//! - sequences overlapping each other on a track that plays only one voice,
+//! - tracks that are never heard, because they are muted, have no volume or other tracks are soloed,
+//! - tracks without notes or clips.
+//!
+//! All problems are reported as warnings.
+
+use std::collections::{HashMap, HashSet};
+
+use syntxt_core::rational::Rational;
+
+use crate::{
+    ast::{self, Node, Walk},
+    diagnostic::Diagnostic,
+    model::{Song, Track},
+    symbols::{SymbolKind, SymbolTable},
+};
+
+/// Report the problems of a song, which must have been resolved from `root`.
+pub fn lint(root: &Node<ast::Root>, table: &SymbolTable, song: &Song) -> Vec<Diagnostic> {
+    let mut names = NameCollector {
+        names: HashMap::new(),
+    };
+    root.walk(&mut names);
+    let mut linter = Linter {
+        names: names.names,
+        diagnostics: Vec::new(),
+    };
+    linter.unreferenced_ids(table);
+    let soloed = song.tracks.iter().find(|track| track.solo.value);
+    for track in song.tracks.iter() {
+        linter.overlapping_sequences(track);
+        linter.unheard(track, soloed);
+        linter.silent(track);
+    }
+    linter.diagnostics
+}
+
+/// The names of all objects by the start of their span.
+struct NameCollector {
+    names: HashMap<usize, Node<String>>,
+}
+
+impl ast::Visitor for NameCollector {
+    fn object(&mut self, node: &Node<ast::Object>) {
+        self.names.insert(node.span.start, node.data.name.clone());
+        node.walk(self);
+    }
+
+    fn attribute(&mut self, node: &Node<ast::Attribute>) {
+        node.walk(self);
+    }
+
+    fn expr(&mut self, node: &Node<ast::Expr>) {
+        node.walk(self);
+    }
+}
+
+struct Linter {
+    names: HashMap<usize, Node<String>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn unreferenced_ids(&mut self, table: &SymbolTable) {
+        for (index, symbol) in table.symbols().iter().enumerate() {
+            // Duplicates are already reported when resolving the song
+            let first = table.object(symbol.name()).map(|id| id.index()) == Some(index);
+            if symbol.kind == SymbolKind::Object && first && symbol.references.is_empty() {
+                self.warning(
+                    &symbol.definition,
+                    format!("`{}` is never referenced", symbol.name()),
+                );
+            }
+        }
+    }
+
+    /// Only one note sounds at a time on a track with a single voice, so a sequence starting
+    /// while another one still plays cuts off its notes.
+    fn overlapping_sequences(&mut self, track: &Track) {
+        let voices = match (track.voices.value, &track.voices.origin) {
+            (Some(1), Some(origin)) => origin,
+            _ => return,
+        };
+        let mut notes = track
+            .sequences
+            .iter()
+            .enumerate()
+            .flat_map(|(index, seq)| seq.notes.value.iter().map(move |note| (index, note)))
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|(_, note)| note.start);
+        // The end of the last note of each sequence played so far
+        let mut ends: Vec<Option<Rational>> = vec![None; track.sequences.len()];
+        let first = |seq: usize| {
+            track.sequences[seq]
+                .notes
+                .value
+                .first()
+                .map(|note| note.start)
+        };
+        let mut reported = HashSet::new();
+        for (index, note) in notes {
+            let overlapped =
+                (0..ends.len()).find(|&other| other != index && ends[other] > Some(note.start));
+            if let Some(other) = overlapped {
+                // Each pair is reported once, at the sequence starting later
+                let (earlier, later) = if (first(other), other) < (first(index), index) {
+                    (other, index)
+                } else {
+                    (index, other)
+                };
+                if reported.insert((earlier, later)) {
+                    let name = self.name(&track.sequences[later].origin);
+                    let other = self.name(&track.sequences[earlier].origin);
+                    self.diagnostics.push(
+                        Diagnostic::warning(
+                            name.span.clone(),
+                            name.pos.clone(),
+                            "the sequence starts while another one still plays, \
+                             cutting off its notes"
+                                .into(),
+                        )
+                        .with_related(
+                            other.span.clone(),
+                            other.pos.clone(),
+                            "the other sequence".into(),
+                        )
+                        .with_related(
+                            voices.span.clone(),
+                            voices.pos.clone(),
+                            "the track plays only one note at a time".into(),
+                        ),
+                    );
+                }
+            }
+            ends[index] = ends[index].max(Some(note.end()));
+        }
+    }
+
+    fn unheard(&mut self, track: &Track, soloed: Option<&Track>) {
+        if let (true, Some(origin)) = (track.mute.value, &track.mute.origin) {
+            self.warning(origin, "the track is muted and never heard".into());
+        } else if let Some(origin) = track
+            .volume
+            .origin
+            .as_ref()
+            .filter(|_| track.volume.value == 0.0)
+        {
+            self.warning(origin, "the track has no volume and is never heard".into());
+        } else if let Some(soloed) = soloed.filter(|_| !track.solo.value) {
+            let name = self.name(&track.origin);
+            let mut diagnostic = Diagnostic::warning(
+                name.span.clone(),
+                name.pos.clone(),
+                "the track is never heard because other tracks are soloed".into(),
+            );
+            if let Some(solo) = &soloed.solo.origin {
+                diagnostic = diagnostic.with_related(
+                    solo.span.clone(),
+                    solo.pos.clone(),
+                    "soloed here".into(),
+                );
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn silent(&mut self, track: &Track) {
+        let notes = track
+            .sequences
+            .iter()
+            .any(|seq| !seq.notes.value.is_empty());
+        if !notes && track.clips.is_empty() {
+            let name = self.name(&track.origin);
+            self.warning(&name, "the track plays no notes and no clips".into());
+        }
+    }
+
+    /// The name of an object, to point at it without highlighting all of its contents.
+    fn name(&self, origin: &Node<()>) -> Node<()> {
+        match self.names.get(&origin.span.start) {
+            Some(name) => Node {
+                span: name.span.clone(),
+                pos: name.pos.clone(),
+                data: (),
+            },
+            None => origin.clone(),
+        }
+    }
+
+    fn warning<T>(&mut self, node: &Node<T>, message: String) {
+        self.diagnostics.push(Diagnostic::warning(
+            node.span.clone(),
+            node.pos.clone(),
+            message,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lint;
+    use crate::{model::resolve, parser::Parser, symbols::SymbolTable};
+    use expect_test::{expect, Expect};
+
+    fn check(input: &str, output: Expect) {
+        let root = Parser::parse(input).unwrap();
+        let table = SymbolTable::build(&root);
+        let (song, _) = resolve(&root);
+        let diagnostics = lint(&root, &table, &song.unwrap())
+            .into_iter()
+            .map(|diag| {
+                let related = diag
+                    .related
+                    .iter()
+                    .map(|related| format!("\n  {:?}: {}", related.pos.start, related.message))
+                    .collect::<String>();
+                format!("{:?}: {}{}", diag.pos.start, diag.message, related)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        output.assert_eq(&diagnostics);
+    }
+
+    #[test]
+    fn no_problems() {
+        check(
+            r#"Song {
+    Track {
+        voices: 1
+        Sequence { id: verse  notes: [[ c4 d4 ]] }
+        Sequence { start: 2/4  use: verse }
+        Sequence { notes: [[ r r r r e4 ]] }
+    }
+    Track { Sequence { notes: [[ c4 e4 g4 ]] } }
+}"#,
+            expect![[""]],
+        );
+    }
+
+    #[test]
+    fn unreferenced_ids() {
+        check(
+            r#"Song {
+    Track {
+        id: lead
+        Sequence { id: verse  notes: [[ c4 ]] }
+        Sequence { id: chorus  use: verse }
+    }
+}"#,
+            expect![[r#"
+                3:13: `lead` is never referenced
+                5:24: `chorus` is never referenced"#]],
+        );
+    }
+
+    #[test]
+    fn overlapping_sequences() {
+        check(
+            r#"Song {
+    Track {
+        voices: 1
+        Sequence { notes: [[ c4 d4 ]] }
+        Sequence { start: 1/4  notes: [[ e4 f4 ]] }
+        Sequence { start: 1/8  notes: [[ g4 ]] }
+    }
+    Track { Sequence { notes: [[ c4 ]] } Sequence { notes: [[ e4 ]] } }
+}"#,
+            expect![[r#"
+                6:9: the sequence starts while another one still plays, cutting off its notes
+                  4:9: the other sequence
+                  3:17: the track plays only one note at a time
+                5:9: the sequence starts while another one still plays, cutting off its notes
+                  4:9: the other sequence
+                  3:17: the track plays only one note at a time"#]],
+        );
+    }
+
+    #[test]
+    fn unheard_tracks() {
+        check(
+            r#"Song {
+    Track { mute: true  Sequence { notes: [[ c4 ]] } }
+    Track { volume: 0  Sequence { notes: [[ c4 ]] } }
+    Track { solo: true  Sequence { notes: [[ c4 ]] } }
+    Track { Sequence { notes: [[ c4 ]] } }
+    Track { Sequence { start: 4 } }
+}"#,
+            expect![[r#"
+                2:19: the track is muted and never heard
+                3:21: the track has no volume and is never heard
+                5:5: the track is never heard because other tracks are soloed
+                  4:19: soloed here
+                6:5: the track is never heard because other tracks are soloed
+                  4:19: soloed here
+                6:5: the track plays no notes and no clips"#]],
+        );
+    }
+}
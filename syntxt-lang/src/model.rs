@@ -0,0 +1,1839 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Typed model of a song, with omitted attributes resolved to their documented defaults
+//! (see `schema`).
+//!
+//! Attribute values are taken from literals, optionally negated or in parentheses.
+//! Other expressions are reported and replaced by the default.
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::path::PathBuf;
+
+use syntxt_core::{abc, midi, nonnan::F64N, rational::Rational};
+
+use crate::{
+    ast::{self, Node, NodePtr},
+    diagnostic::Diagnostic,
+    schema,
+    symbols::{object_id, SymbolKind, SymbolTable},
+    timeline::{self, BendEvent, NoteEvent},
+};
+
+/// The value of an attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved<T> {
+    pub value: T,
+    /// The expression the value was taken from, or `None` if the default value was used.
+    pub origin: Option<Node<()>>,
+}
+
+impl<T> Resolved<T> {
+    pub fn default(value: T) -> Self {
+        Resolved {
+            value,
+            origin: None,
+        }
+    }
+
+    /// Whether the attribute was omitted.
+    pub fn is_default(&self) -> bool {
+        self.origin.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Song {
+    pub bpm: Resolved<i64>,
+    pub sample_rate: Resolved<i64>,
+    /// Linear gain applied to the mix of all tracks.
+    pub volume: Resolved<f64>,
+    /// The speakers the song is mixed for.
+    pub layout: Resolved<Layout>,
+    pub meta: Resolved<Meta>,
+    pub tracks: Vec<Track>,
+    /// The lines of all `Lyrics` objects, ordered by their start time.
+    pub lyrics: Vec<Line>,
+}
+
+/// An arrangement of speakers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Stereo,
+    Quad,
+    /// Front, center, low frequency effects and surround speakers.
+    Surround51,
+}
+
+impl Layout {
+    /// The groups of speakers tracks can play on, in the order of their channels.
+    pub fn speakers(self) -> &'static [Speakers] {
+        match self {
+            Layout::Stereo => &[Speakers::Front],
+            Layout::Quad => &[Speakers::Front, Speakers::Rear],
+            Layout::Surround51 => &[Speakers::Front, Speakers::Center, Speakers::Rear],
+        }
+    }
+
+    /// The name of the layout as written in a song, e.g. `5.1`.
+    pub fn name(self) -> &'static str {
+        choice_name(LAYOUTS, self)
+    }
+}
+
+/// A pair of speakers of a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speakers {
+    Front,
+    /// The center speaker on the left and the low frequency effects on the right.
+    Center,
+    Rear,
+}
+
+impl Speakers {
+    /// The name of the speakers as written in a track, e.g. `rear`.
+    pub fn name(self) -> &'static str {
+        choice_name(SPEAKERS, self)
+    }
+}
+
+const LAYOUTS: &[(&str, Layout)] = &[
+    ("stereo", Layout::Stereo),
+    ("quad", Layout::Quad),
+    ("5.1", Layout::Surround51),
+];
+
+const SPEAKERS: &[(&str, Speakers)] = &[
+    ("front", Speakers::Front),
+    ("center", Speakers::Center),
+    ("rear", Speakers::Rear),
+];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Meta {
+    pub name: Resolved<Option<String>>,
+    pub author: Resolved<Option<String>>,
+    pub year: Resolved<Option<i64>>,
+    pub description: Resolved<Option<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub name: Resolved<Option<String>>,
+    pub volume: Resolved<f64>,
+    /// Position in the stereo field between -1 (left) and 1 (right).
+    pub pan: Resolved<f64>,
+    /// The speakers of the layout of the song the track plays on, panned between their left and right.
+    pub speakers: Resolved<Speakers>,
+    /// Whether the track is silenced.
+    pub mute: Resolved<bool>,
+    /// Whether the track is soloed. As soon as one track is soloed, only soloed tracks are heard.
+    pub solo: Resolved<bool>,
+    /// Whether the track is rendered once and reused while it does not change.
+    pub freeze: Resolved<bool>,
+    /// Maximum number of notes the built-in synthesizer plays at once, `None` for no limit.
+    pub voices: Resolved<Option<i64>>,
+    pub sequences: Vec<Sequence>,
+    /// The bands of all `Eq` objects of the track, in order.
+    pub eq: Vec<EqBand>,
+    /// The `Gate` objects of the track, in order.
+    pub gates: Vec<Gate>,
+    /// Path of the CLAP plugin playing the notes instead of the built-in synthesizer.
+    pub plugin: Resolved<Option<String>>,
+    /// Settings of the parameters of the plugin playing the notes.
+    pub params: Vec<Param>,
+    /// The `Plugin` objects of the track, which are applied after the gates, in order.
+    pub plugins: Vec<Plugin>,
+    /// The `Clip` objects of the track, played alongside its notes.
+    pub clips: Vec<Clip>,
+    /// The `Track` object in the source code.
+    pub origin: Node<()>,
+}
+
+/// A filter band of an equalizer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqBand {
+    pub kind: EqBandKind,
+    /// Corner frequency of shelves or center frequency of peaks, in Hz.
+    pub frequency: Resolved<f64>,
+    /// Boost (positive) or cut (negative) in dB.
+    pub gain: Resolved<f64>,
+    pub q: Resolved<f64>,
+    /// The band object in the source code.
+    pub origin: Node<()>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqBandKind {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+/// A gate silencing a track while it is quiet, or chopping it following a rhythmic pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gate {
+    /// Level in dB below which the gate closes.
+    pub threshold: Resolved<f64>,
+    /// Time in seconds the gate takes to open.
+    pub attack: Resolved<f64>,
+    /// Time in seconds the gate takes to close.
+    pub release: Resolved<f64>,
+    /// The gate is open while these notes play, instead of following the level of the track.
+    /// Their pitch is irrelevant.
+    pub pattern: Resolved<Option<Vec<NoteEvent>>>,
+    /// Length of the pattern in whole notes, after which it repeats.
+    pub length: Resolved<Rational>,
+    /// The `Gate` object in the source code.
+    pub origin: Node<()>,
+}
+
+/// An effect in the CLAP plugin format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plugin {
+    /// Path of the plugin file.
+    pub path: String,
+    pub params: Vec<Param>,
+    /// The `Plugin` object in the source code.
+    pub origin: Node<()>,
+}
+
+/// A recording placed at a point in time of a track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    /// Path of the audio file as written in the song.
+    pub file: String,
+    /// Where the file is found, see `Files::locate`.
+    pub path: PathBuf,
+    /// Time at which the recording starts, measured in whole notes.
+    pub start: Resolved<Rational>,
+    /// The `Clip` object in the source code.
+    pub origin: Node<()>,
+}
+
+/// A parameter of a plugin set by a `Param` object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    /// Name of the parameter as given by the plugin.
+    pub name: String,
+    /// Value in the units of the plugin, e.g. Hz for a cutoff frequency.
+    pub value: Resolved<f64>,
+    /// The `Param` object in the source code.
+    pub origin: Node<()>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    pub start: Resolved<Rational>,
+    /// The notes of the sequence, already shifted by its start time.
+    pub notes: Resolved<Vec<NoteEvent>>,
+    /// The pitch bends of the sequence, already shifted by its start time.
+    pub bends: Vec<BendEvent>,
+    /// The `Sequence` object in the source code.
+    pub origin: Node<()>,
+}
+
+/// A piece of text shown at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub start: Resolved<Rational>,
+    pub text: String,
+    /// The `Line` object in the source code.
+    pub origin: Node<()>,
+}
+
+/// Access to the files a song refers to, e.g. by `importMidi`.
+pub trait Files {
+    /// Read the file at a path as written in the song.
+    fn read(&self, path: &str) -> Result<Vec<u8>, String>;
+
+    /// Where a file is found, for files that are not read while building the model,
+    /// e.g. recordings which are decoded when the song is played.
+    fn locate(&self, path: &str) -> PathBuf {
+        PathBuf::from(path)
+    }
+}
+
+/// No access to files at all, e.g. in the browser.
+pub struct NoFiles;
+
+impl Files for NoFiles {
+    fn read(&self, _path: &str) -> Result<Vec<u8>, String> {
+        Err("files cannot be read here".into())
+    }
+}
+
+/// Files relative to a directory, usually the one containing the song.
+pub struct Directory(pub PathBuf);
+
+impl Files for Directory {
+    fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        std::fs::read(self.0.join(path)).map_err(|err| err.to_string())
+    }
+
+    fn locate(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+/// Files relative to the first of several directories containing them,
+/// e.g. the directory of the song followed by the sample directories of a project.
+pub struct SearchPath(pub Vec<PathBuf>);
+
+impl Files for SearchPath {
+    fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        std::fs::read(self.locate(path)).map_err(|err| err.to_string())
+    }
+
+    /// The path in the first directory if the file is in none of them.
+    fn locate(&self, path: &str) -> PathBuf {
+        let candidates = self
+            .0
+            .iter()
+            .map(|directory| directory.join(path))
+            .collect::<Vec<_>>();
+        match candidates
+            .iter()
+            .find(|candidate| candidate.exists())
+            .or_else(|| candidates.first())
+        {
+            Some(found) => found.clone(),
+            None => PathBuf::from(path),
+        }
+    }
+}
+
+impl<T> Default for Resolved<Option<T>> {
+    fn default() -> Self {
+        Resolved::default(None)
+    }
+}
+
+/// Build the typed model from the first `Song` object of a file.
+///
+/// Problems are reported as diagnostics; the model is still built as far as possible.
+/// Only if there is no song at all, no model is returned.
+pub fn resolve(root: &Node<ast::Root>) -> (Option<Song>, Vec<Diagnostic>) {
+    resolve_with_files(root, &NoFiles)
+}
+
+/// Build the typed model like `resolve`, reading the files it refers to from `files`.
+pub fn resolve_with_files(
+    root: &Node<ast::Root>,
+    files: &dyn Files,
+) -> (Option<Song>, Vec<Diagnostic>) {
+    let mut resolver = Resolver {
+        diagnostics: Vec::new(),
+        sequences: HashMap::new(),
+        files,
+        timing: (120, 44_100),
+    };
+    resolver.duplicate_ids(root);
+    let mut song = None;
+    for object in root.data.objects.iter() {
+        if object.data.name.data != "Song" {
+            resolver.unknown_object(object, None);
+        } else if song.is_some() {
+            resolver.warning(&object.data.name, "only the first song is used".into());
+        } else {
+            song = Some(resolver.song(object));
+        }
+    }
+    if song.is_none() {
+        resolver.diagnostics.push(Diagnostic::error(
+            root.span.clone(),
+            root.pos.clone(),
+            "the file does not contain a song".into(),
+        ));
+    }
+    (song, resolver.diagnostics)
+}
+
+struct Resolver<'a> {
+    diagnostics: Vec<Diagnostic>,
+    /// `Sequence` objects by their id.
+    sequences: HashMap<String, &'a Node<ast::Object>>,
+    files: &'a dyn Files,
+    /// Beats per minute and sample rate of the song, for placing imported notes.
+    timing: (i64, i64),
+}
+
+/// The notes of a sequence, before they are placed at its start time.
+enum SequenceNotes {
+    Written(NodePtr<ast::Sequence>),
+    /// Notes imported from a file, starting at time zero.
+    Imported(Vec<NoteEvent>),
+}
+
+impl SequenceNotes {
+    fn events(&self, start: Rational) -> Vec<NoteEvent> {
+        match self {
+            SequenceNotes::Written(seq) => timeline::sequence_events(seq, start),
+            SequenceNotes::Imported(notes) => notes
+                .iter()
+                .map(|note| NoteEvent {
+                    start: note.start + start,
+                    ..note.clone()
+                })
+                .collect(),
+        }
+    }
+
+    fn bends(&self, start: Rational) -> Vec<BendEvent> {
+        match self {
+            SequenceNotes::Written(seq) => timeline::sequence_bends(seq, start),
+            SequenceNotes::Imported(_) => Vec::new(),
+        }
+    }
+}
+
+/// Whether a call imports the notes of a file, like `importMidi("riff.mid")`.
+/// All builtin functions do.
+fn is_import(callee: &ast::Expr) -> bool {
+    matches!(callee, ast::Expr::Var(name) if schema::function(name).is_some())
+}
+
+/// A literal value an attribute can be resolved from.
+enum Literal {
+    String(String),
+    Int(i64),
+    Ratio(Rational),
+    Float(F64N),
+    Bool(bool),
+}
+
+impl<'a> Resolver<'a> {
+    fn song(&mut self, obj: &'a Node<ast::Object>) -> Song {
+        // Sequences can be referenced before they are defined
+        for track in obj.data.children.iter() {
+            for child in track.data.children.iter() {
+                if child.data.name.data == "Sequence" {
+                    if let Some(id) = object_id(&child.data) {
+                        self.sequences.entry(id.data).or_insert(child);
+                    }
+                }
+            }
+        }
+
+        let mut song = Song {
+            bpm: Resolved::default(120),
+            sample_rate: Resolved::default(44_100),
+            volume: Resolved::default(1.0),
+            layout: Resolved::default(Layout::Stereo),
+            meta: Resolved::default(Meta::default()),
+            tracks: Vec::new(),
+            lyrics: Vec::new(),
+        };
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "bpm" => self.int(value, &mut song.bpm),
+                "sampleRate" => self.int(value, &mut song.sample_rate),
+                "volume" => self.float(value, &mut song.volume),
+                "layout" => self.choice(value, LAYOUTS, &mut song.layout),
+                "meta" => match &value.data {
+                    ast::Expr::Object(meta) if meta.data.name.data == "Meta" => {
+                        song.meta = resolved(value, self.meta(meta))
+                    }
+                    _ => self.error(value, "expected a `Meta` object".into()),
+                },
+                _ => {}
+            }
+        }
+        self.timing = (song.bpm.value, song.sample_rate.value);
+        for child in obj.data.children.iter() {
+            match child.data.name.data.as_str() {
+                "Track" => song.tracks.push(self.track(child)),
+                "Lyrics" => self.lyrics(child, &mut song.lyrics),
+                _ => self.unknown_object(child, Some(obj)),
+            }
+        }
+        for track in song.tracks.iter() {
+            let speakers = track.speakers.value;
+            if let Some(origin) = &track.speakers.origin {
+                if !song.layout.value.speakers().contains(&speakers) {
+                    self.error(
+                        origin,
+                        format!(
+                            "there are no {} speakers in the {} layout",
+                            speakers.name(),
+                            song.layout.value.name()
+                        ),
+                    );
+                }
+            }
+        }
+        song.lyrics.sort_by_key(|line| line.start.value);
+        song
+    }
+
+    fn lyrics(&mut self, obj: &'a Node<ast::Object>, lines: &mut Vec<Line>) {
+        self.attributes(obj);
+        for child in obj.data.children.iter() {
+            if child.data.name.data != "Line" {
+                self.unknown_object(child, Some(obj));
+                continue;
+            }
+            let mut start = Resolved::default(Rational::zero());
+            let mut text = None;
+            for attr in self.attributes(child) {
+                let value = &attr.data.value;
+                match attr.data.name.data.as_str() {
+                    "start" => self.rational(value, &mut start),
+                    "text" => match self.literal(value) {
+                        Some(Literal::String(str)) => text = Some(str),
+                        Some(_) => self.error(value, "expected a string".into()),
+                        None => {}
+                    },
+                    _ => {}
+                }
+            }
+            for nested in child.data.children.iter() {
+                self.unknown_object(nested, Some(child));
+            }
+            match text {
+                Some(text) => lines.push(Line {
+                    start,
+                    text,
+                    origin: unit(child),
+                }),
+                None => self.error(&child.data.name, "a line needs a `text`".into()),
+            }
+        }
+    }
+
+    fn meta(&mut self, obj: &'a Node<ast::Object>) -> Meta {
+        let mut meta = Meta::default();
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "name" => self.optional_string(value, &mut meta.name),
+                "author" => self.optional_string(value, &mut meta.author),
+                "year" => {
+                    if let Some(year) = self.int_value(value) {
+                        meta.year = resolved(value, Some(year));
+                    }
+                }
+                "description" => self.optional_string(value, &mut meta.description),
+                _ => {}
+            }
+        }
+        for child in obj.data.children.iter() {
+            self.unknown_object(child, Some(obj));
+        }
+        meta
+    }
+
+    fn track(&mut self, obj: &'a Node<ast::Object>) -> Track {
+        let mut track = Track {
+            name: Resolved::default(None),
+            volume: Resolved::default(1.0),
+            pan: Resolved::default(0.0),
+            speakers: Resolved::default(Speakers::Front),
+            mute: Resolved::default(false),
+            solo: Resolved::default(false),
+            freeze: Resolved::default(false),
+            voices: Resolved::default(None),
+            sequences: Vec::new(),
+            eq: Vec::new(),
+            gates: Vec::new(),
+            plugin: Resolved::default(None),
+            params: Vec::new(),
+            plugins: Vec::new(),
+            clips: Vec::new(),
+            origin: unit(obj),
+        };
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "name" => self.optional_string(value, &mut track.name),
+                "volume" => self.float(value, &mut track.volume),
+                "pan" => self.float(value, &mut track.pan),
+                "speakers" => self.choice(value, SPEAKERS, &mut track.speakers),
+                "mute" => self.bool(value, &mut track.mute),
+                "solo" => self.bool(value, &mut track.solo),
+                "freeze" => self.bool(value, &mut track.freeze),
+                "voices" => match self.int_value(value) {
+                    Some(voices) if voices >= 1 => track.voices = resolved(value, Some(voices)),
+                    Some(_) => self.error(value, "a track needs at least one voice".into()),
+                    None => {}
+                },
+                "plugin" => self.optional_string(value, &mut track.plugin),
+                _ => {}
+            }
+        }
+        for child in obj.data.children.iter() {
+            match child.data.name.data.as_str() {
+                "Sequence" => track.sequences.push(self.sequence(child)),
+                "Eq" => self.eq(child, &mut track.eq),
+                "Gate" => track.gates.push(self.gate(child)),
+                "Plugin" => {
+                    if let Some(plugin) = self.plugin(child) {
+                        track.plugins.push(plugin)
+                    }
+                }
+                "Param" => {
+                    if let Some(param) = self.param(child) {
+                        track.params.push(param)
+                    }
+                }
+                "Clip" => {
+                    if let Some(clip) = self.clip(child) {
+                        track.clips.push(clip)
+                    }
+                }
+                _ => self.unknown_object(child, Some(obj)),
+            }
+        }
+        if let (Some(_), Some(origin)) = (&track.plugin.value, &track.voices.origin) {
+            self.warning(
+                origin,
+                "the number of voices of a `plugin` is set by the plugin itself".into(),
+            );
+        }
+        if track.plugin.value.is_none() {
+            for param in track.params.iter() {
+                self.warning(
+                    &param.origin,
+                    "the track has no `plugin` the parameter could belong to".into(),
+                );
+            }
+        }
+        track
+    }
+
+    fn plugin(&mut self, obj: &'a Node<ast::Object>) -> Option<Plugin> {
+        let mut path = None;
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            if attr.data.name.data == "path" {
+                match self.literal(value) {
+                    Some(Literal::String(str)) => path = Some(str),
+                    Some(_) => self.error(value, "expected a string".into()),
+                    None => {}
+                }
+            }
+        }
+        let mut params = Vec::new();
+        for child in obj.data.children.iter() {
+            if child.data.name.data != "Param" {
+                self.unknown_object(child, Some(obj));
+            } else if let Some(param) = self.param(child) {
+                params.push(param);
+            }
+        }
+        match path {
+            Some(path) => Some(Plugin {
+                path,
+                params,
+                origin: unit(obj),
+            }),
+            None => {
+                self.error(&obj.data.name, "a plugin needs a `path`".into());
+                None
+            }
+        }
+    }
+
+    fn clip(&mut self, obj: &'a Node<ast::Object>) -> Option<Clip> {
+        let mut file = None;
+        let mut start = Resolved::default(Rational::zero());
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "file" => match self.literal(value) {
+                    Some(Literal::String(str)) => file = Some(str),
+                    Some(_) => self.error(value, "expected a string".into()),
+                    None => {}
+                },
+                "start" => self.rational(value, &mut start),
+                _ => {}
+            }
+        }
+        for child in obj.data.children.iter() {
+            self.unknown_object(child, Some(obj));
+        }
+        match file {
+            Some(file) => Some(Clip {
+                path: self.files.locate(&file),
+                file,
+                start,
+                origin: unit(obj),
+            }),
+            None => {
+                self.error(&obj.data.name, "a clip needs a `file`".into());
+                None
+            }
+        }
+    }
+
+    fn param(&mut self, obj: &'a Node<ast::Object>) -> Option<Param> {
+        let mut name = None;
+        let mut value = Resolved::default(0.0);
+        for attr in self.attributes(obj) {
+            let expr = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "name" => match self.literal(expr) {
+                    Some(Literal::String(str)) => name = Some(str),
+                    Some(_) => self.error(expr, "expected a string".into()),
+                    None => {}
+                },
+                "value" => self.float(expr, &mut value),
+                _ => {}
+            }
+        }
+        for child in obj.data.children.iter() {
+            self.unknown_object(child, Some(obj));
+        }
+        match name {
+            Some(name) => Some(Param {
+                name,
+                value,
+                origin: unit(obj),
+            }),
+            None => {
+                self.error(&obj.data.name, "a parameter needs a `name`".into());
+                None
+            }
+        }
+    }
+
+    fn eq(&mut self, obj: &'a Node<ast::Object>, bands: &mut Vec<EqBand>) {
+        self.attributes(obj);
+        for child in obj.data.children.iter() {
+            let (kind, frequency, q) = match child.data.name.data.as_str() {
+                "LowShelf" => (EqBandKind::LowShelf, 100.0, FRAC_1_SQRT_2),
+                "Peak" => (EqBandKind::Peak, 1000.0, 1.0),
+                "HighShelf" => (EqBandKind::HighShelf, 8000.0, FRAC_1_SQRT_2),
+                _ => {
+                    self.unknown_object(child, Some(obj));
+                    continue;
+                }
+            };
+            let mut band = EqBand {
+                kind,
+                frequency: Resolved::default(frequency),
+                gain: Resolved::default(0.0),
+                q: Resolved::default(q),
+                origin: unit(child),
+            };
+            for attr in self.attributes(child) {
+                let value = &attr.data.value;
+                match attr.data.name.data.as_str() {
+                    "frequency" => self.float(value, &mut band.frequency),
+                    "gain" => self.float(value, &mut band.gain),
+                    "q" => self.float(value, &mut band.q),
+                    _ => {}
+                }
+            }
+            for nested in child.data.children.iter() {
+                self.unknown_object(nested, Some(child));
+            }
+            bands.push(band);
+        }
+    }
+
+    fn gate(&mut self, obj: &'a Node<ast::Object>) -> Gate {
+        let mut gate = Gate {
+            threshold: Resolved::default(-40.0),
+            attack: Resolved::default(0.001),
+            release: Resolved::default(0.05),
+            pattern: Resolved::default(None),
+            length: Resolved::default(Rational::one()),
+            origin: unit(obj),
+        };
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "threshold" => self.float(value, &mut gate.threshold),
+                "attack" => self.float(value, &mut gate.attack),
+                "release" => self.float(value, &mut gate.release),
+                "pattern" => {
+                    if let Some(notes) = self.sequence_value(value, &mut Vec::new()) {
+                        gate.pattern = resolved(value, Some(notes.events(Rational::zero())));
+                    }
+                }
+                "length" => self.rational(value, &mut gate.length),
+                _ => {}
+            }
+        }
+        for child in obj.data.children.iter() {
+            self.unknown_object(child, Some(obj));
+        }
+        gate
+    }
+
+    fn sequence(&mut self, obj: &'a Node<ast::Object>) -> Sequence {
+        let mut start = Resolved::default(Rational::zero());
+        let mut notes = None;
+        for attr in self.attributes(obj) {
+            let value = &attr.data.value;
+            match attr.data.name.data.as_str() {
+                "start" => self.rational(value, &mut start),
+                "notes" | "use" => {
+                    let mut visiting = object_id(&obj.data).map(|id| id.data).into_iter().collect();
+                    if let Some(seq) = self.sequence_value(value, &mut visiting) {
+                        notes = Some((value, seq));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for child in obj.data.children.iter() {
+            self.unknown_object(child, Some(obj));
+        }
+        // The notes can only be placed once the start time is known
+        let (notes, bends) = match notes {
+            Some((value, notes)) => (
+                resolved(value, notes.events(start.value)),
+                notes.bends(start.value),
+            ),
+            None => (Resolved::default(Vec::new()), Vec::new()),
+        };
+        Sequence {
+            start,
+            notes,
+            bends,
+            origin: unit(obj),
+        }
+    }
+
+    /// Resolve the value of a `notes` or `use` attribute, which is either a sequence, notes
+    /// imported from a file or the id of another `Sequence` object whose notes are shared.
+    ///
+    /// `visiting` holds the ids of the sequences whose notes are being resolved.
+    fn sequence_value(
+        &mut self,
+        expr: &Node<ast::Expr>,
+        visiting: &mut Vec<String>,
+    ) -> Option<SequenceNotes> {
+        // Problems in referenced sequences are reported where they are defined
+        let nested = visiting.len() > 1;
+        match &expr.data {
+            ast::Expr::Sequence(seq) => Some(SequenceNotes::Written(seq.clone())),
+            ast::Expr::Call {
+                callee,
+                arguments,
+                named_arguments,
+                ..
+            } if is_import(&callee.data) => {
+                let reported = self.diagnostics.len();
+                let notes = match &callee.data {
+                    ast::Expr::Var(name) if name == schema::IMPORT_ABC.name => {
+                        self.import_abc(expr, arguments, named_arguments)
+                    }
+                    _ => self.import_midi(expr, arguments, named_arguments),
+                };
+                if nested {
+                    self.diagnostics.truncate(reported);
+                }
+                notes.map(SequenceNotes::Imported)
+            }
+            ast::Expr::Var(id) => {
+                let obj = match self.sequences.get(id) {
+                    Some(obj) => *obj,
+                    None if nested => return None,
+                    None => {
+                        self.error(expr, format!("there is no sequence with id `{}`", id));
+                        return None;
+                    }
+                };
+                if visiting.contains(id) {
+                    self.error(expr, format!("sequence `{}` refers to itself", id));
+                    return None;
+                }
+                visiting.push(id.clone());
+                let seq = obj
+                    .data
+                    .attrs
+                    .iter()
+                    .filter(|attr| matches!(attr.data.name.data.as_str(), "notes" | "use"))
+                    .find_map(|attr| self.sequence_value(&attr.data.value, visiting));
+                visiting.pop();
+                seq
+            }
+            _ if nested => None,
+            _ => {
+                self.error(expr, "expected a sequence or the id of a sequence".into());
+                None
+            }
+        }
+    }
+
+    /// Import the notes of a MIDI file with `importMidi("file.mid", track: 1, channel: 1)`,
+    /// where the track and channel are optional and count from 1.
+    ///
+    /// The notes keep their timing in seconds, following the tempo changes of the file.
+    fn import_midi(
+        &mut self,
+        expr: &Node<ast::Expr>,
+        arguments: &[Node<ast::Expr>],
+        named_arguments: &[Node<ast::Attribute>],
+    ) -> Option<Vec<NoteEvent>> {
+        let path = self.import_path(expr, arguments, "a MIDI file")?;
+        let mut track = None;
+        let mut channel = None;
+        for arg in named_arguments {
+            let value = &arg.data.value;
+            let (target, max, message) = match arg.data.name.data.as_str() {
+                "track" => (&mut track, i64::MAX, "tracks are counted from 1"),
+                "channel" => (&mut channel, 16, "channels are numbered from 1 to 16"),
+                name => {
+                    self.error(&arg.data.name, format!("unknown argument `{}`", name));
+                    continue;
+                }
+            };
+            match self.int_value(value) {
+                Some(number) if (1..=max).contains(&number) => {
+                    *target = Some(((number - 1) as usize, value))
+                }
+                Some(_) => self.error(value, message.into()),
+                None => {}
+            }
+        }
+
+        let origin = &arguments[0];
+        let file = match self.files.read(&path) {
+            Ok(bytes) => midi::parse(&bytes).map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+        let file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                self.error(origin, format!("cannot import `{}`: {}", path, err));
+                return None;
+            }
+        };
+        let tracks = match track {
+            Some((index, _)) if index < file.tracks.len() => &file.tracks[index..=index],
+            Some((_, value)) => {
+                let count = file.tracks.len();
+                self.error(value, format!("`{}` has only {} tracks", path, count));
+                return None;
+            }
+            None => &file.tracks[..],
+        };
+
+        let (bpm, sample_rate) = (self.timing.0, self.timing.1.max(1));
+        let time = |tick| {
+            let sample = file.tempo_map.sample(tick, sample_rate as u32) as i64;
+            // A whole note lasts four beats
+            Rational::new(sample * bpm, sample_rate * 240)
+        };
+        let mut notes = tracks
+            .iter()
+            .flat_map(|track| track.notes.iter())
+            .filter(|note| match channel {
+                Some((index, _)) => note.channel as usize == index,
+                None => true,
+            })
+            .map(|note| {
+                let start = time(note.start);
+                NoteEvent {
+                    note: note.note,
+                    start,
+                    duration: time(note.end) - start,
+                    articulation: ast::Articulation::Normal,
+                    accent: false,
+                    velocity: Some(note.velocity),
+                    origin: unit(expr),
+                }
+            })
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|note| note.start);
+        Some(notes)
+    }
+
+    /// Import the notes of a tune in ABC notation with `importAbc("tunes.abc", tune: 2)`,
+    /// where the tune is chosen by the reference number of its `X:` field and defaults to the first.
+    ///
+    /// The durations are kept in whole notes, so the tune follows the tempo of the song.
+    fn import_abc(
+        &mut self,
+        expr: &Node<ast::Expr>,
+        arguments: &[Node<ast::Expr>],
+        named_arguments: &[Node<ast::Attribute>],
+    ) -> Option<Vec<NoteEvent>> {
+        let path = self.import_path(expr, arguments, "an ABC file")?;
+        let mut reference = None;
+        for arg in named_arguments {
+            let value = &arg.data.value;
+            let name = arg.data.name.data.as_str();
+            if name != "tune" {
+                self.error(&arg.data.name, format!("unknown argument `{}`", name));
+                continue;
+            }
+            match self.int_value(value) {
+                Some(number) if (0..=u32::MAX as i64).contains(&number) => {
+                    reference = Some((number as u32, value))
+                }
+                Some(_) => self.error(value, "tunes are chosen by their `X:` number".into()),
+                None => {}
+            }
+        }
+
+        let origin = &arguments[0];
+        let tunes = match self.files.read(&path) {
+            Ok(bytes) => {
+                abc::parse(&String::from_utf8_lossy(&bytes)).map_err(|err| err.to_string())
+            }
+            Err(err) => Err(err),
+        };
+        let tunes = match tunes {
+            Ok(tunes) => tunes,
+            Err(err) => {
+                self.error(origin, format!("cannot import `{}`: {}", path, err));
+                return None;
+            }
+        };
+        let tune = match reference {
+            Some((number, value)) => match tunes.iter().find(|t| t.reference == Some(number)) {
+                Some(tune) => tune,
+                None => {
+                    self.error(value, format!("`{}` has no tune {}", path, number));
+                    return None;
+                }
+            },
+            None => &tunes[0],
+        };
+        Some(
+            tune.notes
+                .iter()
+                .map(|note| NoteEvent {
+                    note: note.note,
+                    start: note.start,
+                    duration: note.duration,
+                    articulation: ast::Articulation::Normal,
+                    accent: false,
+                    velocity: None,
+                    origin: unit(expr),
+                })
+                .collect(),
+        )
+    }
+
+    /// The path of the file passed to an import, which is the only unnamed argument.
+    fn import_path(
+        &mut self,
+        expr: &Node<ast::Expr>,
+        arguments: &[Node<ast::Expr>],
+        kind: &str,
+    ) -> Option<String> {
+        let path = match arguments.first().map(|arg| (arg, self.literal(arg))) {
+            Some((_, Some(Literal::String(path)))) => path,
+            Some((arg, Some(_))) => {
+                self.error(arg, format!("expected the path of {}", kind));
+                return None;
+            }
+            Some((_, None)) => return None,
+            None => {
+                self.error(expr, format!("expected the path of {}", kind));
+                return None;
+            }
+        };
+        for extra in arguments.iter().skip(1) {
+            self.error(extra, "only the path is passed without a name".into());
+        }
+        Some(path)
+    }
+
+    /// Report objects sharing an id. References always resolve to the first of them.
+    fn duplicate_ids(&mut self, root: &Node<ast::Root>) {
+        let table = SymbolTable::build(root);
+        let mut first = HashMap::new();
+        for symbol in table.symbols() {
+            if symbol.kind != SymbolKind::Object {
+                continue;
+            }
+            match first.entry(symbol.name()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(&symbol.definition);
+                }
+                Entry::Occupied(entry) => {
+                    let original = entry.get();
+                    self.diagnostics.push(
+                        Diagnostic::warning(
+                            symbol.definition.span.clone(),
+                            symbol.definition.pos.clone(),
+                            format!(
+                                "id `{}` is used more than once, it refers to the first object",
+                                symbol.name()
+                            ),
+                        )
+                        .with_related(
+                            original.span.clone(),
+                            original.pos.clone(),
+                            "first used here".into(),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Return the attributes of an object, reporting those that are not known for its type.
+    ///
+    /// Attributes that are set more than once are reported as well, only the first value is used.
+    fn attributes<'b>(&mut self, obj: &'b Node<ast::Object>) -> Vec<&'b Node<ast::Attribute>> {
+        let mut attrs: Vec<&'b Node<ast::Attribute>> = Vec::new();
+        for attr in obj.data.attrs.iter() {
+            let name = &attr.data.name;
+            match attrs.iter().find(|other| other.data.name.data == name.data) {
+                Some(original) => self.diagnostics.push(
+                    Diagnostic::warning(
+                        name.span.clone(),
+                        name.pos.clone(),
+                        format!(
+                            "attribute `{}` is set more than once, only the first value is used",
+                            name.data
+                        ),
+                    )
+                    .with_related(
+                        original.data.name.span.clone(),
+                        original.data.name.pos.clone(),
+                        "first set here".into(),
+                    ),
+                ),
+                None => attrs.push(attr),
+            }
+        }
+        if let Some(schema) = schema::object(&obj.data.name.data) {
+            for attr in obj.data.attrs.iter() {
+                let name = &attr.data.name;
+                if name.data != schema::ID_ATTRIBUTE.name && schema.attribute(&name.data).is_none()
+                {
+                    self.warning(
+                        name,
+                        format!(
+                            "unknown attribute `{}` of `{}` is ignored",
+                            name.data, schema.name
+                        ),
+                    );
+                }
+            }
+        }
+        attrs
+    }
+
+    fn unknown_object(&mut self, obj: &Node<ast::Object>, parent: Option<&Node<ast::Object>>) {
+        let message = match parent {
+            Some(parent) => format!(
+                "`{}` is not allowed inside `{}` and is ignored",
+                obj.data.name.data, parent.data.name.data
+            ),
+            None => format!(
+                "`{}` is not allowed at the top level and is ignored",
+                obj.data.name.data
+            ),
+        };
+        self.warning(&obj.data.name, message);
+    }
+
+    fn literal(&mut self, expr: &Node<ast::Expr>) -> Option<Literal> {
+        match &expr.data {
+            ast::Expr::String(str) => Some(Literal::String(str.clone())),
+            ast::Expr::Int(int) => Some(Literal::Int(*int)),
+            ast::Expr::Ratio(ratio) => Some(Literal::Ratio(*ratio)),
+            ast::Expr::Float(float) => Some(Literal::Float(*float)),
+            ast::Expr::Bool(bool) => Some(Literal::Bool(*bool)),
+            ast::Expr::Paren { expr, .. } => self.literal(expr),
+            ast::Expr::Unary { operator, operand } => {
+                let literal = self.literal(operand)?;
+                match (&operator.data, literal) {
+                    (ast::UnaryOp::Plus, lit @ Literal::Int(_))
+                    | (ast::UnaryOp::Plus, lit @ Literal::Ratio(_))
+                    | (ast::UnaryOp::Plus, lit @ Literal::Float(_)) => Some(lit),
+                    (ast::UnaryOp::Minus, Literal::Int(int)) => Some(Literal::Int(-int)),
+                    (ast::UnaryOp::Minus, Literal::Ratio(ratio)) => Some(Literal::Ratio(-ratio)),
+                    (ast::UnaryOp::Minus, Literal::Float(float)) => {
+                        F64N::new(-float.into_inner()).map(Literal::Float)
+                    }
+                    (ast::UnaryOp::Not, Literal::Bool(bool)) => Some(Literal::Bool(!bool)),
+                    _ => {
+                        self.error(expr, "invalid operand".into());
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.error(expr, "only literal values are supported here".into());
+                None
+            }
+        }
+    }
+
+    fn int_value(&mut self, expr: &Node<ast::Expr>) -> Option<i64> {
+        match self.literal(expr)? {
+            Literal::Int(int) => Some(int),
+            _ => {
+                self.error(expr, "expected an integer".into());
+                None
+            }
+        }
+    }
+
+    fn int(&mut self, expr: &Node<ast::Expr>, target: &mut Resolved<i64>) {
+        if let Some(int) = self.int_value(expr) {
+            *target = resolved(expr, int);
+        }
+    }
+
+    fn rational(&mut self, expr: &Node<ast::Expr>, target: &mut Resolved<Rational>) {
+        match self.literal(expr) {
+            Some(Literal::Int(int)) => *target = resolved(expr, Rational::int(int)),
+            Some(Literal::Ratio(ratio)) => *target = resolved(expr, ratio),
+            Some(_) => self.error(expr, "expected a ratio".into()),
+            None => {}
+        }
+    }
+
+    fn float(&mut self, expr: &Node<ast::Expr>, target: &mut Resolved<f64>) {
+        let value = match self.literal(expr) {
+            Some(Literal::Int(int)) => int as f64,
+            Some(Literal::Ratio(ratio)) => ratio.numerator() as f64 / ratio.denominator() as f64,
+            Some(Literal::Float(float)) => float.into_inner(),
+            Some(Literal::String(_)) | Some(Literal::Bool(_)) => {
+                return self.error(expr, "expected a number".into())
+            }
+            None => return,
+        };
+        *target = resolved(expr, value);
+    }
+
+    fn bool(&mut self, expr: &Node<ast::Expr>, target: &mut Resolved<bool>) {
+        match self.literal(expr) {
+            Some(Literal::Bool(bool)) => *target = resolved(expr, bool),
+            Some(_) => self.error(expr, "expected `true` or `false`".into()),
+            None => {}
+        }
+    }
+
+    fn optional_string(&mut self, expr: &Node<ast::Expr>, target: &mut Resolved<Option<String>>) {
+        match self.literal(expr) {
+            Some(Literal::String(str)) => *target = resolved(expr, Some(str)),
+            Some(_) => self.error(expr, "expected a string".into()),
+            None => {}
+        }
+    }
+
+    /// One of the values named by strings.
+    fn choice<T: Copy>(
+        &mut self,
+        expr: &Node<ast::Expr>,
+        choices: &[(&str, T)],
+        target: &mut Resolved<T>,
+    ) {
+        match self.literal(expr) {
+            Some(Literal::String(str)) => {
+                match choices.iter().find(|(name, _)| *name == str.as_str()) {
+                    Some((_, value)) => *target = resolved(expr, *value),
+                    None => {
+                        let names = choices
+                            .iter()
+                            .map(|(name, _)| format!("\"{}\"", name))
+                            .collect::<Vec<_>>();
+                        self.error(expr, format!("expected one of {}", names.join(", ")))
+                    }
+                }
+            }
+            Some(_) => self.error(expr, "expected a string".into()),
+            None => {}
+        }
+    }
+
+    fn error<T>(&mut self, node: &Node<T>, message: String) {
+        self.diagnostics.push(Diagnostic::error(
+            node.span.clone(),
+            node.pos.clone(),
+            message,
+        ));
+    }
+
+    fn warning<T>(&mut self, node: &Node<T>, message: String) {
+        self.diagnostics.push(Diagnostic::warning(
+            node.span.clone(),
+            node.pos.clone(),
+            message,
+        ));
+    }
+}
+
+fn unit<T>(node: &Node<T>) -> Node<()> {
+    Node {
+        span: node.span.clone(),
+        pos: node.pos.clone(),
+        data: (),
+    }
+}
+
+/// The name of a value in a list of choices.
+fn choice_name<T: PartialEq>(choices: &[(&'static str, T)], value: T) -> &'static str {
+    choices
+        .iter()
+        .find(|(_, choice)| *choice == value)
+        .map_or("", |(name, _)| name)
+}
+
+fn resolved<T, U>(node: &Node<U>, value: T) -> Resolved<T> {
+    Resolved {
+        value,
+        origin: Some(unit(node)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        resolve, resolve_with_files, Directory, EqBandKind, Files, Layout, Param, SearchPath,
+        Speakers,
+    };
+    use crate::parser::Parser;
+    use syntxt_core::rational::Rational;
+
+    #[test]
+    fn defaults() {
+        let root = Parser::parse("Song { Track { Sequence { } } }").unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let song = song.unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(song.bpm.value, 120);
+        assert!(song.bpm.is_default());
+        assert_eq!(song.sample_rate.value, 44_100);
+        assert!(song.meta.is_default());
+        assert_eq!(song.meta.value.name.value, None);
+        assert_eq!(song.tracks[0].volume.value, 1.0);
+        assert!(song.tracks[0].volume.is_default());
+        assert_eq!(song.tracks[0].pan.value, 0.0);
+        assert!(!song.tracks[0].mute.value && !song.tracks[0].solo.value);
+        assert!(!song.tracks[0].freeze.value);
+        assert_eq!(song.tracks[0].voices.value, None);
+        assert_eq!(song.volume.value, 1.0);
+        assert_eq!(song.tracks[0].sequences[0].start.value, Rational::zero());
+        assert!(song.tracks[0].sequences[0].notes.value.is_empty());
+    }
+
+    #[test]
+    fn given_values() {
+        let source = r#"Song {
+    bpm: 90
+    volume: 0.8
+    meta: Meta { name: "Example" year: 2021 }
+    Track {
+        volume: -(1/2)
+        pan: -0.25
+        mute: true
+        freeze: true
+        voices: 1
+        Sequence { start: 2 notes: [[ c4 d4 ]] }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let song = song.unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(song.bpm.value, 90);
+        assert_eq!(&source[song.bpm.origin.unwrap().span], "90");
+        assert!(song.sample_rate.is_default());
+        assert_eq!(song.meta.value.name.value.as_deref(), Some("Example"));
+        assert_eq!(song.meta.value.year.value, Some(2021));
+        assert!(song.meta.value.author.is_default());
+        assert_eq!(song.tracks[0].volume.value, -0.5);
+        assert_eq!(song.tracks[0].pan.value, -0.25);
+        assert!(song.tracks[0].mute.value);
+        assert!(song.tracks[0].solo.is_default());
+        assert!(song.tracks[0].freeze.value);
+        assert_eq!(song.tracks[0].voices.value, Some(1));
+        assert_eq!(song.volume.value, 0.8);
+        let notes = &song.tracks[0].sequences[0].notes.value;
+        assert_eq!(notes[1].start, Rational::new(9, 4));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let source = r#"Song {
+    bpm: "fast"
+    loud: true
+    Track { volume: 1 + 1 voices: 0 }
+    Meta { }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        assert!(song.unwrap().bpm.is_default());
+        let messages = diagnostics
+            .iter()
+            .map(|diag| format!("{:?} {:?}: {}", diag.severity, diag.pos.start, diag.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "Warning 3:5: unknown attribute `loud` of `Song` is ignored",
+                "Error 2:10: expected an integer",
+                "Error 4:21: only literal values are supported here",
+                "Error 4:35: a track needs at least one voice",
+                "Warning 5:5: `Meta` is not allowed inside `Song` and is ignored",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let source = r#"Song {
+    bpm: 90
+    bpm: 100
+    Track { id: lead }
+    Track { id: lead  volume: 1  volume: 2 }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let song = song.unwrap();
+        assert_eq!(song.bpm.value, 90);
+        assert_eq!(song.tracks[1].volume.value, 1.0);
+        let messages = diagnostics
+            .iter()
+            .map(|diag| {
+                let related = diag
+                    .related
+                    .iter()
+                    .map(|related| format!(" ({:?}: {})", related.pos.start, related.message))
+                    .collect::<String>();
+                format!("{:?}: {}{}", diag.pos.start, diag.message, related)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "5:17: id `lead` is used more than once, it refers to the first object (4:17: first used here)",
+                "3:5: attribute `bpm` is set more than once, only the first value is used (2:5: first set here)",
+                "5:34: attribute `volume` is set more than once, only the first value is used (5:23: first set here)",
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_references() {
+        let source = r#"Song {
+    Track {
+        Sequence { id: verse notes: [[ c4 d4 ]] }
+        Sequence { id: again start: 1 use: verse }
+    }
+    Track {
+        Sequence { start: 2 notes: again }
+        Sequence { use: missing }
+        Sequence { id: a use: b }
+        Sequence { id: b use: a }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let song = song.unwrap();
+        let starts = |track: usize, seq: usize| {
+            song.tracks[track].sequences[seq]
+                .notes
+                .value
+                .iter()
+                .map(|note| note.start)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(starts(0, 1), vec![Rational::int(1), Rational::new(5, 4)]);
+        assert_eq!(starts(1, 0), vec![Rational::int(2), Rational::new(9, 4)]);
+        // The notes still point to where they were written
+        let origin = &song.tracks[1].sequences[0].notes.value[0].origin;
+        assert_eq!(&source[origin.span.clone()], "c4");
+
+        let messages = diagnostics
+            .iter()
+            .map(|diag| format!("{:?}: {}", diag.pos.start, diag.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "8:25: there is no sequence with id `missing`",
+                "10:31: sequence `a` refers to itself",
+                "9:31: sequence `b` refers to itself",
+            ]
+        );
+    }
+
+    #[test]
+    fn equalizer() {
+        let source = r#"Song {
+    Track {
+        Eq {
+            LowShelf { gain: 3 }
+            Peak { frequency: 440 gain: -2.5 q: 4 }
+        }
+        Eq { HighShelf { frequency: 10000 } Sequence { } }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let bands = &song.unwrap().tracks[0].eq;
+        let values = bands
+            .iter()
+            .map(|band| (band.kind, band.frequency.value, band.gain.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                (EqBandKind::LowShelf, 100.0, 3.0),
+                (EqBandKind::Peak, 440.0, -2.5),
+                (EqBandKind::HighShelf, 10000.0, 0.0),
+            ]
+        );
+        assert_eq!(bands[1].q.value, 4.0);
+        assert!(bands[2].q.is_default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "`Sequence` is not allowed inside `Eq` and is ignored"
+        );
+    }
+
+    #[test]
+    fn gate() {
+        let source = r#"Song {
+    Track {
+        Sequence { id: chop notes: [[ c4- r- c4- ]] }
+        Gate { threshold: -30 }
+        Gate { pattern: chop length: 1/2 }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        assert!(diagnostics.is_empty());
+        let gates = &song.unwrap().tracks[0].gates;
+        assert_eq!(gates[0].threshold.value, -30.0);
+        assert_eq!(gates[0].pattern.value, None);
+        assert!(gates[1].threshold.is_default());
+        assert_eq!(gates[1].length.value, Rational::new(1, 2));
+        let starts = gates[1]
+            .pattern
+            .value
+            .iter()
+            .flatten()
+            .map(|note| note.start)
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![Rational::zero(), Rational::new(1, 4)]);
+    }
+
+    #[test]
+    fn plugins() {
+        let source = r#"Song {
+    Track {
+        plugin: "synth.clap"
+        Param { name: "cutoff" value: 800 }
+        Plugin {
+            path: "delay.clap"
+            Param { name: "feedback" value: 0.5 }
+            Param { value: 1 }
+        }
+        Plugin { }
+    }
+    Track { Param { name: "cutoff" } }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let song = song.unwrap();
+        let params = |params: &[Param]| {
+            params
+                .iter()
+                .map(|param| (param.name.clone(), param.value.value))
+                .collect::<Vec<_>>()
+        };
+        let track = &song.tracks[0];
+        assert_eq!(track.plugin.value.as_deref(), Some("synth.clap"));
+        assert_eq!(params(&track.params), vec![("cutoff".to_string(), 800.0)]);
+        assert_eq!(track.plugins.len(), 1);
+        assert_eq!(track.plugins[0].path, "delay.clap");
+        assert_eq!(
+            params(&track.plugins[0].params),
+            vec![("feedback".to_string(), 0.5)]
+        );
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "a parameter needs a `name`",
+                "a plugin needs a `path`",
+                "the track has no `plugin` the parameter could belong to",
+            ]
+        );
+    }
+
+    #[test]
+    fn clips() {
+        let source = r#"Song {
+    Track {
+        Clip { file: "vocals.wav" start: 16/4 }
+        Clip { file: "intro.flac" }
+        Clip { start: 1 }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve_with_files(&root, &Directory("songs".into()));
+        let clips = &song.unwrap().tracks[0].clips;
+        let placed = clips
+            .iter()
+            .map(|clip| (clip.path.to_str().unwrap(), clip.start.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            placed,
+            vec![
+                ("songs/vocals.wav", Rational::int(4)),
+                ("songs/intro.flac", Rational::zero()),
+            ]
+        );
+        assert_eq!(clips[0].file, "vocals.wav");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "a clip needs a `file`");
+    }
+
+    #[test]
+    fn search_path() {
+        let directory = std::env::temp_dir().join(format!("syntxt-search-{}", std::process::id()));
+        let samples = directory.join("samples");
+        std::fs::create_dir_all(&samples).unwrap();
+        std::fs::write(samples.join("kick.wav"), b"kick").unwrap();
+        let files = SearchPath(vec![directory.join("song"), samples.clone()]);
+        assert_eq!(files.locate("kick.wav"), samples.join("kick.wav"));
+        assert_eq!(files.read("kick.wav").unwrap(), b"kick");
+        // Missing files are expected next to the song
+        assert_eq!(files.locate("snare.wav"), directory.join("song/snare.wav"));
+        assert!(files.read("snare.wav").is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn lyrics() {
+        let source = r#"Song {
+    Lyrics {
+        Line { start: 2 text: "world" }
+        Line { text: "hello" }
+    }
+    Lyrics { Line { start: 1 } }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let lines = song
+            .unwrap()
+            .lyrics
+            .into_iter()
+            .map(|line| (line.start.value, line.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                (Rational::zero(), "hello".to_string()),
+                (Rational::int(2), "world".to_string())
+            ]
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "a line needs a `text`");
+    }
+
+    #[test]
+    fn layout() {
+        let source = r#"Song {
+    layout: "quad"
+    Track { speakers: "rear" }
+    Track { }
+    Track { speakers: "center" }
+    Track { speakers: "side" }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        let song = song.unwrap();
+        assert_eq!(song.layout.value, Layout::Quad);
+        let speakers = song
+            .tracks
+            .iter()
+            .map(|track| track.speakers.value)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            speakers,
+            vec![
+                Speakers::Rear,
+                Speakers::Front,
+                Speakers::Center,
+                Speakers::Front
+            ]
+        );
+
+        let messages = diagnostics
+            .iter()
+            .map(|diag| format!("{:?}: {}", diag.pos.start, diag.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                r#"6:23: expected one of "front", "center", "rear""#,
+                "5:23: there are no center speakers in the quad layout",
+            ]
+        );
+
+        let root = Parser::parse(r#"Song { layout: "7.1" }"#).unwrap();
+        let (song, diagnostics) = resolve(&root);
+        assert_eq!(song.unwrap().layout.value, Layout::Stereo);
+        assert_eq!(
+            diagnostics[0].message,
+            r#"expected one of "stereo", "quad", "5.1""#
+        );
+    }
+
+    #[test]
+    fn import_midi() {
+        struct Riff;
+        impl Files for Riff {
+            fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+                if path != "riff.mid" {
+                    return Err("not found".into());
+                }
+                Ok([
+                    b"MThd".as_ref(),
+                    &[0, 0, 0, 6, 0, 1, 0, 2, 0, 96],
+                    // Twice as fast from the second quarter note on
+                    b"MTrk",
+                    &[
+                        0, 0, 0, 11, 0x60, 0xFF, 0x51, 3, 0x03, 0xD0, 0x90, 0, 0xFF, 0x2F, 0,
+                    ],
+                    // c4 on the first channel, then e4 on the second one
+                    b"MTrk",
+                    &[
+                        0, 0, 0, 20, 0, 0x90, 60, 127, 0x60, 0x80, 60, 0, 0, 0x91, 64, 64,
+                    ],
+                    &[0x60, 0x91, 64, 0, 0, 0xFF, 0x2F, 0],
+                ]
+                .concat())
+            }
+        }
+
+        let source = r#"Song {
+    Track {
+        Sequence { start: 1 notes: importMidi("riff.mid", track: 2) }
+        Sequence { notes: importMidi("riff.mid", channel: 2) }
+        Sequence { notes: importMidi("missing.mid") }
+        Sequence { notes: importMidi("riff.mid", track: 3) }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve_with_files(&root, &Riff);
+        let notes = |index: usize| {
+            song.as_ref().unwrap().tracks[0].sequences[index]
+                .notes
+                .value
+                .iter()
+                .map(|note| {
+                    let velocity = note.velocity.unwrap().as_f64();
+                    (note.note.to_midi(), note.start, note.duration, velocity)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            notes(0),
+            vec![
+                (60, Rational::int(1), Rational::new(1, 4), 1.0),
+                (64, Rational::new(5, 4), Rational::new(1, 8), 64.0 / 127.0),
+            ]
+        );
+        assert_eq!(
+            notes(1),
+            vec![(64, Rational::new(1, 4), Rational::new(1, 8), 64.0 / 127.0)]
+        );
+
+        let messages = diagnostics
+            .iter()
+            .map(|diag| format!("{:?}: {}", diag.pos.start, diag.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "5:38: cannot import `missing.mid`: not found",
+                "6:57: `riff.mid` has only 2 tracks",
+            ]
+        );
+
+        // Without access to files, nothing is imported
+        let (_, diagnostics) = resolve(&root);
+        assert_eq!(diagnostics.len(), 4);
+    }
+
+    #[test]
+    fn import_abc() {
+        struct Tunes;
+        impl Files for Tunes {
+            fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+                match path {
+                    "tunes.abc" => {
+                        Ok(b"X:1\nK:C\nL:1/4\nc2 d\n\nX:7\nK:G\nL:1/8\n|: f :|\n".to_vec())
+                    }
+                    _ => Err("not found".into()),
+                }
+            }
+        }
+
+        let source = r#"Song {
+    Track {
+        Sequence { start: 1 notes: importAbc("tunes.abc") }
+        Sequence { notes: importAbc("tunes.abc", tune: 7) }
+        Sequence { notes: importAbc("tunes.abc", tune: 2) }
+        Sequence { notes: importAbc("tunes.abc", voice: 1) }
+        Sequence { notes: importAbc() }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let (song, diagnostics) = resolve_with_files(&root, &Tunes);
+        let notes = |index: usize| {
+            song.as_ref().unwrap().tracks[0].sequences[index]
+                .notes
+                .value
+                .iter()
+                .map(|note| (note.note.to_midi(), note.start, note.duration))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            notes(0),
+            vec![
+                (72, Rational::int(1), Rational::new(1, 2)),
+                (74, Rational::new(3, 2), Rational::new(1, 4)),
+            ]
+        );
+        // The key of G raises the F, which is repeated
+        assert_eq!(
+            notes(1),
+            vec![
+                (78, Rational::zero(), Rational::new(1, 8)),
+                (78, Rational::new(1, 8), Rational::new(1, 8)),
+            ]
+        );
+
+        let messages = diagnostics
+            .iter()
+            .map(|diag| format!("{:?}: {}", diag.pos.start, diag.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "5:56: `tunes.abc` has no tune 2",
+                "6:50: unknown argument `voice`",
+                "7:27: expected the path of an ABC file",
+            ]
+        );
+    }
+}
@@ -19,11 +19,13 @@ use std::{fmt::Display, iter::Peekable, ops::Range, str::FromStr, sync::Arc};
 use crate::ast::{self, Node};
 use logos::Logos;
 use syntxt_core::{
+    nonnan::F64N,
     note::{Accidental, Note, NoteName},
     rational::Rational,
 };
 
 use crate::{
+    diagnostic::Diagnostic,
     lexer::{Span, Token},
     line_map::{LineMap, Pos},
 };
@@ -43,14 +45,16 @@ pub struct Prec(u16);
 
 impl Prec {
     const LOWEST: Prec = Prec(0);
-    const DISJUNCTIVE: Prec = Prec(1);
-    const CONJUNCTIVE: Prec = Prec(2);
-    const ADDITIVE: Prec = Prec(3);
-    const MULTIPLICATIVE: Prec = Prec(4);
-    const UNARY: Prec = Prec(5);
-    const CALL: Prec = Prec(6);
-    const DOT: Prec = Prec(7);
-    const HIGHEST: Prec = Prec(8);
+    const PIPE: Prec = Prec(1);
+    const RANGE: Prec = Prec(2);
+    const DISJUNCTIVE: Prec = Prec(3);
+    const CONJUNCTIVE: Prec = Prec(4);
+    const ADDITIVE: Prec = Prec(5);
+    const MULTIPLICATIVE: Prec = Prec(6);
+    const UNARY: Prec = Prec(7);
+    const CALL: Prec = Prec(8);
+    const DOT: Prec = Prec(9);
+    const HIGHEST: Prec = Prec(10);
 
     pub fn succ(self) -> Prec {
         // this would be a parser bug:
@@ -61,12 +65,30 @@ impl Prec {
 
 pub type Parse<T> = Result<Node<T>, ParseError>;
 
+/// Opening delimiter, positional and named expressions, and closing delimiter of a list.
+type ExprList = (
+    Node<()>,
+    Vec<Node<ast::Expr>>,
+    Vec<Node<ast::Attribute>>,
+    Node<()>,
+);
+
+/// An expression with an optional name and colon in front of it.
+type NamedExpr = (Option<(Node<String>, Node<()>)>, Node<ast::Expr>);
+
 pub struct Parser<'a> {
     source: &'a str,
     stream: Peekable<logos::SpannedIter<'a, Token>>,
+    /// Tokens to be consumed before the rest of the stream, the next one being last.
+    pending: Vec<(Token, Span)>,
     consumed: usize,
     line_map: LineMap<'a>,
     errors: Vec<ParseError>,
+    /// Problems the parser could recover from without losing any input.
+    warnings: Vec<ParseError>,
+    /// Number of `{` without a matching `}` in the whole source. As long as there are any,
+    /// missing closing braces are guessed from the indentation.
+    unclosed: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -81,20 +103,54 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a source file, returning the (possibly partial) AST along with all errors and
+    /// warnings that were encountered, in source order.
+    pub fn parse_with_diagnostics(source: &'a str) -> (Node<ast::Root>, Vec<Diagnostic>) {
+        let mut parser = Parser::new(source);
+        let root = parser.parse_root();
+        let mut diagnostics = parser
+            .errors
+            .into_iter()
+            .map(Diagnostic::from)
+            .chain(
+                parser
+                    .warnings
+                    .into_iter()
+                    .map(|warning| Diagnostic::warning(warning.span, warning.pos, warning.message)),
+            )
+            .collect::<Vec<_>>();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        (root, diagnostics)
+    }
+
     // Private helpers
 
     fn new(source: &'a str) -> Self {
         Parser {
             source,
             stream: Token::lexer(source).spanned().peekable(),
+            pending: Vec::new(),
             consumed: 0,
             line_map: LineMap::new(source),
             errors: Vec::new(),
+            warnings: Vec::new(),
+            unclosed: Token::lexer(source)
+                .map(|token| match token {
+                    Token::LBrace => 1,
+                    Token::RBrace => -1,
+                    _ => 0,
+                })
+                .sum::<isize>()
+                .max(0) as usize,
         }
     }
 
     fn peek(&mut self) -> (Option<Token>, Span) {
-        if let Some((tok, span)) = self.stream.peek().cloned() {
+        let next = match self.pending.last() {
+            Some(pending) => Some(pending.clone()),
+            None => self.stream.peek().cloned(),
+        };
+        if let Some((tok, span)) = next {
             (Some(tok), span)
         } else {
             (None, self.eof())
@@ -102,14 +158,54 @@ impl<'a> Parser<'a> {
     }
 
     fn consume(&mut self) -> Option<(Token, Span)> {
-        let result = self.stream.next();
+        let result = self.pending.pop().or_else(|| self.stream.next());
         if let Some((_, span)) = &result {
             self.consumed = span.end;
         }
         result
     }
 
+    /// `[[` and `]]` are lexed as single tokens for sequences, but they can also be the brackets
+    /// of nested lists or indices, e.g. in `[[1, 2], [3]]` or `a[b[0]]`. If a single bracket
+    /// is expected where the next token is a double bracket, split it into two.
+    fn split_brackets(&mut self, expected: Token) {
+        let double = match expected {
+            Token::LBracket => Token::LLBracket,
+            Token::RBracket => Token::RRBracket,
+            _ => return,
+        };
+        if self.peek().0 != Some(double) {
+            return;
+        }
+        let (_, span) = self.consume().expect("peeked before");
+        self.pending.push((expected, span.start + 1..span.end));
+        self.pending.push((expected, span.start..span.start + 1));
+    }
+
+    /// Whether the next `[[` opens a list starting with a nested list, rather than a sequence.
+    fn starts_nested_list(&mut self) -> bool {
+        if self.peek().0 != Some(Token::LLBracket) {
+            return false;
+        }
+        // Only single brackets are split off, so a double bracket is moved to the pending
+        // tokens just for looking at the token after it.
+        if self.pending.is_empty() {
+            let llbracket = self.stream.next().expect("peeked before");
+            self.pending.push(llbracket);
+        }
+        let next = self.stream.peek().map(|(token, _)| *token);
+        !matches!(
+            next,
+            None | Some(Token::LLBracket)
+                | Some(Token::RRBracket)
+                | Some(Token::Note)
+                | Some(Token::Grid)
+                | Some(Token::Bend)
+        )
+    }
+
     fn parse_expect_token(&mut self, expected: Token) -> Parse<()> {
+        self.split_brackets(expected);
         if let Some((token, span)) = self.consume() {
             if token == expected {
                 return Ok(self.make_node(span, ()));
@@ -223,6 +319,19 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// The whitespace in front of the given offset, if nothing else precedes it on its line.
+    fn indentation(&self, offset: usize) -> Option<&'a str> {
+        let line_start = self.source[..offset]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let prefix = &self.source[line_start..offset];
+        if prefix.trim_start().is_empty() {
+            Some(prefix)
+        } else {
+            None
+        }
+    }
+
     // Parse rules
 
     fn parse_root(&mut self) -> Node<ast::Root> {
@@ -247,12 +356,51 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_object_body(&mut self, name: Node<String>) -> Parse<ast::Object> {
+        // Used for guessing where a missing closing brace should have been
+        let object_indent = self.indentation(name.span.start);
+        let mut body_indent = None;
+
         let lbrace = self.parse_expect_token(Token::LBrace)?;
 
         let mut attrs = Vec::new();
         let mut children = Vec::new();
         loop {
-            match self.peek().0 {
+            let (token, span) = self.peek();
+            let indent = self.indentation(span.start).filter(|_| self.unclosed > 0);
+            if let (Some(token), Some(indent)) = (token, indent) {
+                // A member that is indented less than the ones before, or a closing brace that
+                // is indented less than the object itself, most likely belongs to an outer object.
+                // Indentation that is not a prefix of the other one is not comparable.
+                let reference = match token {
+                    Token::RBrace => object_indent,
+                    _ => body_indent,
+                };
+                if reference.map_or(false, |reference| {
+                    reference.len() > indent.len() && reference.starts_with(indent)
+                }) {
+                    self.unclosed -= 1;
+                    self.errors.push(self.make_error(
+                        lbrace.span.clone(),
+                        format!("missing `}}` to close `{}`", name.data),
+                    ));
+                    let rbrace = self.make_node(self.consumed..self.consumed, ());
+                    return Ok(self.make_node(
+                        name.span.start..rbrace.span.end,
+                        ast::Object {
+                            name,
+                            lbrace,
+                            attrs,
+                            children,
+                            rbrace,
+                        },
+                    ));
+                }
+                if token != Token::RBrace && body_indent.is_none() {
+                    body_indent = Some(indent);
+                }
+            }
+
+            match token {
                 Some(Token::Ident) => {
                     let inner_name = self.parse_ident()?;
                     // Colon or brace
@@ -278,6 +426,30 @@ impl<'a> Parser<'a> {
                             let child_object = self.parse_object_body(inner_name)?;
                             children.push(child_object);
                         }
+                        Some(other)
+                            if starts_expr(other)
+                                && self.line_map.offset_to_pos(span.start).line
+                                    == inner_name.pos.end.line =>
+                        {
+                            // Most likely a forgotten colon, treat it as `name: value`
+                            self.warnings.push(self.make_error(
+                                inner_name.span.clone(),
+                                format!("missing `:` after `{}`", inner_name.data),
+                            ));
+                            let colon =
+                                self.make_node(inner_name.span.end..inner_name.span.end, ());
+                            let value_parse = self.parse_expr();
+                            if let Some(value) = self.recover_next_line(value_parse) {
+                                attrs.push(self.make_node(
+                                    inner_name.span.start..value.span.end,
+                                    ast::Attribute {
+                                        name: inner_name,
+                                        colon,
+                                        value,
+                                    },
+                                ))
+                            }
+                        }
                         Some(other) => {
                             self.errors
                                 .push(self.expected_but_got(span, EXPECTATION, other));
@@ -299,15 +471,20 @@ impl<'a> Parser<'a> {
                     ));
                     self.skip_until_next_line();
                 }
-                None => {
-                    self.errors
-                        .push(self.unexpected_eof(&[Token::Ident, Token::RBrace]));
-                    break;
-                }
+                None => break,
             }
         }
 
-        let rbrace = self.parse_expect_token(Token::RBrace)?;
+        let rbrace = if self.peek().0.is_some() {
+            self.parse_expect_token(Token::RBrace)?
+        } else {
+            // Close the object anyway so that everything parsed so far ends up in the AST
+            self.errors.push(self.make_error(
+                lbrace.span.clone(),
+                format!("missing `}}` to close `{}`", name.data),
+            ));
+            self.make_node(self.consumed..self.consumed, ())
+        };
         Ok(self.make_node(
             name.span.start..rbrace.span.end,
             ast::Object {
@@ -343,6 +520,13 @@ impl<'a> Parser<'a> {
 
             left = match token {
                 // Infix operations
+                Token::Pipe if min_prec <= Prec::PIPE => self.parse_pipe_target(left)?,
+                Token::DotDot if min_prec <= Prec::RANGE => {
+                    self.parse_range_end(left, self.make_node(span, ast::RangeOp::Exclusive))?
+                }
+                Token::DotDotEq if min_prec <= Prec::RANGE => {
+                    self.parse_range_end(left, self.make_node(span, ast::RangeOp::Inclusive))?
+                }
                 Token::Or if min_prec <= Prec::DISJUNCTIVE => self.parse_binary_operand(
                     left,
                     self.make_node(span, ast::BinaryOp::Or),
@@ -387,7 +571,7 @@ impl<'a> Parser<'a> {
                     )
                 }
                 Token::LParen if min_prec <= Prec::CALL => {
-                    let (lparen, arguments, rparen) =
+                    let (lparen, arguments, named_arguments, rparen) =
                         self.parse_expr_list(Token::LParen, Token::RParen)?;
                     self.make_node(
                         left.span.start..rparen.span.end,
@@ -395,10 +579,25 @@ impl<'a> Parser<'a> {
                             callee: Arc::new(left),
                             lparen,
                             arguments,
+                            named_arguments,
                             rparen,
                         },
                     )
                 }
+                Token::LBracket if min_prec <= Prec::CALL => {
+                    let lbracket = self.parse_expect_token(Token::LBracket)?;
+                    let index = self.parse_expr()?;
+                    let rbracket = self.parse_expect_token(Token::RBracket)?;
+                    self.make_node(
+                        left.span.start..rbracket.span.end,
+                        ast::Expr::Index {
+                            expr: Arc::new(left),
+                            lbracket,
+                            index: Arc::new(index),
+                            rbracket,
+                        },
+                    )
+                }
                 // any unexpected token is not consumed, this is a problem for the caller
                 _ => break,
             }
@@ -417,7 +616,9 @@ impl<'a> Parser<'a> {
             Token::Minus => self.parse_unary_operand(ast::UnaryOp::Minus, span),
             Token::Not => self.parse_unary_operand(ast::UnaryOp::Not, span),
             Token::LParen => self.parse_paren_expr(),
+            Token::LLBracket if self.starts_nested_list() => self.parse_list_expr(),
             Token::LLBracket => self.parse_sequence_expr(),
+            Token::LBracket => self.parse_list_expr(),
             Token::LitInt => self.parse_int_expr(),
             Token::LitFloat => self.parse_float_expr(),
             Token::LitRatio => self.parse_ratio_expr(),
@@ -470,6 +671,67 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Parse the function following `|>` and desugar `x |> f(a)` into the call `f(x, a)`.
+    /// A function without arguments, as in `x |> f`, becomes `f(x)`.
+    fn parse_pipe_target(&mut self, input: Node<ast::Expr>) -> Parse<ast::Expr> {
+        // assumes that the caller did not consume the operator yet
+        self.consume();
+        let target = self.parse_prec_expr(Prec::CALL)?;
+        let span = input.span.start..target.span.end;
+        let call = match target.data {
+            ast::Expr::Call {
+                callee,
+                lparen,
+                mut arguments,
+                named_arguments,
+                rparen,
+            } => {
+                arguments.insert(0, input);
+                ast::Expr::Call {
+                    callee,
+                    lparen,
+                    arguments,
+                    named_arguments,
+                    rparen,
+                }
+            }
+            _ => {
+                // There are no parentheses in the source, so they are empty nodes after the callee
+                let end = target.span.end..target.span.end;
+                ast::Expr::Call {
+                    lparen: self.make_node(end.clone(), ()),
+                    rparen: self.make_node(end, ()),
+                    callee: Arc::new(target),
+                    arguments: vec![input],
+                    named_arguments: Vec::new(),
+                }
+            }
+        };
+        Ok(self.make_node(span, call))
+    }
+
+    fn parse_range_end(
+        &mut self,
+        start: Node<ast::Expr>,
+        operator: Node<ast::RangeOp>,
+    ) -> Parse<ast::Expr> {
+        // Ranges don't associate, `a..b..c` has no sensible meaning
+        if let ast::Expr::Range { .. } = start.data {
+            return Err(self.make_error(operator.span, "ranges cannot be chained".into()));
+        }
+        // assumes that the caller did not consume the operator yet
+        self.consume();
+        let end = self.parse_prec_expr(Prec::RANGE.succ())?;
+        Ok(self.make_node(
+            start.span.start..end.span.end,
+            ast::Expr::Range {
+                start: Arc::new(start),
+                operator,
+                end: Arc::new(end),
+            },
+        ))
+    }
+
     fn parse_paren_expr(&mut self) -> Parse<ast::Expr> {
         let lparen = self.parse_expect_token(Token::LParen)?;
         let expr = Arc::new(self.parse_expr()?);
@@ -484,19 +746,51 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn parse_expr_list(
-        &mut self,
-        start: Token,
-        end: Token,
-    ) -> Result<(Node<()>, Vec<Node<ast::Expr>>, Node<()>), ParseError> {
+    /// Parse a list of expressions, where each expression can optionally be preceded by a name,
+    /// e.g. `(a, b, rate: 1/8)`. Named expressions must come last.
+    fn parse_expr_list(&mut self, start: Token, end: Token) -> Result<ExprList, ParseError> {
         let lparen = self.parse_expect_token(start)?;
         let mut arguments = Vec::new();
-        while self.peek().0 != Some(end) {
-            let expr_parse = self.parse_expr();
-            if let Some(expr) = self.recover_skip(expr_parse, false, &[end, Token::Comma]) {
-                arguments.push(expr);
+        let mut named_arguments = Vec::new();
+        loop {
+            self.split_brackets(end);
+            if self.peek().0 == Some(end) {
+                break;
+            }
+            let expr_parse = self.parse_named_expr();
+            let named_expr = self
+                .recover_skip(expr_parse, false, &[end, Token::Comma])
+                .map(|node| node.data);
+            match named_expr {
+                Some((None, expr)) => {
+                    if named_arguments.is_empty() {
+                        arguments.push(expr);
+                    } else {
+                        self.errors.push(self.make_error(
+                            expr.span,
+                            "positional arguments must come before named arguments".into(),
+                        ));
+                    }
+                }
+                Some((Some((name, colon)), value)) => {
+                    if named_arguments
+                        .iter()
+                        .any(|arg: &Node<ast::Attribute>| arg.data.name.data == name.data)
+                    {
+                        self.errors.push(self.make_error(
+                            name.span.clone(),
+                            format!("argument `{}` is given more than once", name.data),
+                        ));
+                    }
+                    named_arguments.push(self.make_node(
+                        name.span.start..value.span.end,
+                        ast::Attribute { name, colon, value },
+                    ));
+                }
+                None => {}
             }
 
+            self.split_brackets(end);
             let (token, span) = self.peek();
             match token {
                 Some(Token::Comma) => {
@@ -524,7 +818,25 @@ impl<'a> Parser<'a> {
 
         let rparen_parse = self.parse_expect_token(end);
         let rparen = self.recover_replace(rparen_parse, ());
-        Ok((lparen, arguments, rparen))
+        Ok((lparen, arguments, named_arguments, rparen))
+    }
+
+    /// Parse an expression that is optionally preceded by `name:`.
+    fn parse_named_expr(&mut self) -> Parse<NamedExpr> {
+        let expr = self.parse_expr()?;
+        if self.peek().0 != Some(Token::Colon) {
+            return Ok(self.make_node(expr.span.clone(), (None, expr)));
+        }
+        let name = match expr.data {
+            ast::Expr::Var(name) => self.make_node(expr.span, name),
+            _ => return Err(self.make_error(expr.span, "expected an argument name".into())),
+        };
+        let colon = self.parse_expect_token(Token::Colon)?;
+        let value = self.parse_expr()?;
+        Ok(self.make_node(
+            name.span.start..value.span.end,
+            (Some((name, colon)), value),
+        ))
     }
 
     fn parse_native<T: FromStr>(&mut self, token: Token, ignore_underscores: bool) -> Parse<T>
@@ -620,6 +932,25 @@ impl<'a> Parser<'a> {
         Ok(self.make_node(string.span, ast::Expr::String(string.data)))
     }
 
+    fn parse_list_expr(&mut self) -> Parse<ast::Expr> {
+        let (lbracket, elements, named_elements, rbracket) =
+            self.parse_expr_list(Token::LBracket, Token::RBracket)?;
+        for element in named_elements {
+            self.errors.push(self.make_error(
+                element.data.name.span,
+                "list elements cannot be named".into(),
+            ));
+        }
+        Ok(self.make_node(
+            lbracket.span.start..rbracket.span.end,
+            ast::Expr::List {
+                lbracket,
+                elements,
+                rbracket,
+            },
+        ))
+    }
+
     fn parse_sequence_expr(&mut self) -> Parse<ast::Expr> {
         let sequence = self.parse_sequence_group()?;
         Ok(self.make_node(
@@ -650,13 +981,41 @@ impl<'a> Parser<'a> {
                             .push(self.make_error(span, format!("Invalid note: {}", note_str)));
                     }
                 }
+                Some(Token::Grid) => {
+                    self.consume();
+                    let grid_str = &self.source[span.clone()];
+                    if let Some(grid) = grid_from_str(grid_str) {
+                        symbols.push(self.make_node(span, grid));
+                    } else {
+                        self.errors.push(
+                            self.make_error(span, format!("Invalid drum grid: {}", grid_str)),
+                        );
+                    }
+                }
+                Some(Token::Bend) => {
+                    self.consume();
+                    let bend_str = &self.source[span.clone()];
+                    if let Some(bend) = bend_from_str(bend_str) {
+                        symbols.push(self.make_node(span, bend));
+                    } else {
+                        self.errors.push(
+                            self.make_error(span, format!("Invalid pitch bend: {}", bend_str)),
+                        );
+                    }
+                }
                 Some(Token::RRBracket) => {
                     break;
                 }
                 Some(other) => {
                     self.errors.push(self.expected_but_got(
                         span,
-                        &[Token::LLBracket, Token::RRBracket, Token::Note],
+                        &[
+                            Token::LLBracket,
+                            Token::RRBracket,
+                            Token::Note,
+                            Token::Grid,
+                            Token::Bend,
+                        ],
                         other,
                     ));
                     let _ = self.consume();
@@ -688,14 +1047,83 @@ fn seq_sym_from_str(input: &str) -> Option<ast::SeqSym> {
     if matches!(chars.peek(), Some('r') | Some('R')) {
         chars.next();
         let duration = parse_duration(&mut chars)?;
+        // Rests cannot be articulated
+        if chars.peek().is_some() {
+            return None;
+        }
         Some(ast::SeqSym::Rest { duration })
     } else {
         // If it's not a rest, it's a note.
         let note = parse_note(&mut chars)?;
         let duration = parse_duration(&mut chars)?;
+        let (articulation, accent) = parse_articulation(&mut chars)?;
+
+        Some(ast::SeqSym::Note {
+            note,
+            duration,
+            articulation,
+            accent,
+        })
+    }
+}
+
+/// Parse a line of a drum grid.
+///
+/// The note is given by a drum name (see `Note::drum`) or a note name. Like for notes,
+/// the step length is a quarter by default and can be changed with `+` and `-`.
+fn grid_from_str(input: &str) -> Option<ast::SeqSym> {
+    let bar = input.find('|')?;
+    let (head, steps) = (&input[..bar], &input[bar + 1..input.len() - 1]);
+    let name = head.trim_end_matches(&['+', '-'][..]);
+    let note = Note::drum(name).or_else(|| {
+        let mut chars = name.chars().peekable();
+        let note = parse_note(&mut chars)?;
+        match chars.next() {
+            None => Some(note),
+            Some(_) => None,
+        }
+    })?;
+    let mut chars = head[name.len()..].chars().peekable();
+    let step = parse_duration(&mut chars)?;
+    let steps = steps
+        .chars()
+        .filter(|ch| *ch != ' ')
+        .map(|ch| match ch {
+            'x' => ast::GridStep::Hit,
+            'X' => ast::GridStep::Accent,
+            _ => ast::GridStep::Rest,
+        })
+        .collect();
+    Some(ast::SeqSym::Grid { note, step, steps })
+}
 
-        Some(ast::SeqSym::Note { note, duration })
+/// Parse a pitch bend, whose amount must lie between -1 and 1.
+fn bend_from_str(input: &str) -> Option<ast::SeqSym> {
+    let amount = input[1..].replace('_', "").parse::<f64>().ok()?;
+    if amount.abs() > 1.0 {
+        return None;
     }
+    Some(ast::SeqSym::Bend {
+        amount: F64N::new(amount)?,
+    })
+}
+
+/// Parse the articulation markers following the duration of a note.
+/// Each marker may appear at most once, and staccato and legato are mutually exclusive.
+fn parse_articulation<I: Iterator<Item = char>>(
+    chars: &mut Peekable<I>,
+) -> Option<(ast::Articulation, bool)> {
+    let mut articulation = ast::Articulation::Normal;
+    let mut accent = false;
+    for ch in chars {
+        match (ch, articulation) {
+            ('\'', ast::Articulation::Normal) => articulation = ast::Articulation::Staccato,
+            ('~', ast::Articulation::Normal) => articulation = ast::Articulation::Legato,
+            ('>', _) if !accent => accent = true,
+            _ => return None,
+        }
+    }
+    Some((articulation, accent))
 }
 
 fn parse_note<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> Option<Note> {
@@ -785,3 +1213,21 @@ fn parse_duration<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> Option<R
     }
     Some(full_duration)
 }
+
+/// Whether an expression can start with the given token.
+fn starts_expr(token: Token) -> bool {
+    matches!(
+        token,
+        Token::Plus
+            | Token::Minus
+            | Token::Not
+            | Token::LParen
+            | Token::LLBracket
+            | Token::LitInt
+            | Token::LitFloat
+            | Token::LitRatio
+            | Token::LitString
+            | Token::LitBool
+            | Token::Ident
+    )
+}
@@ -15,6 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::Parser;
+use crate::ast;
 use expect_test::{expect, Expect};
 
 fn check(input: &str, output: Expect) {
@@ -1209,6 +1210,7 @@ fn parse_call() {
                                         ),
                                     },
                                 ],
+                                named_arguments: [],
                                 rparen: Node {
                                     span: 12..13,
                                     pos: 1:13..1:14,
@@ -1295,6 +1297,7 @@ fn parse_call() {
                                                             ),
                                                         },
                                                     ],
+                                                    named_arguments: [],
                                                     rparen: Node {
                                                         span: 36..37,
                                                         pos: 1:37..1:38,
@@ -1303,6 +1306,7 @@ fn parse_call() {
                                                 },
                                             },
                                         ],
+                                        named_arguments: [],
                                         rparen: Node {
                                             span: 37..38,
                                             pos: 1:38..1:39,
@@ -1720,6 +1724,7 @@ fn parse_recover_from_errors() {
                                                                                 ),
                                                                             },
                                                                         ],
+                                                                        named_arguments: [],
                                                                         rparen: Node {
                                                                             span: 310..311,
                                                                             pos: 16:45..16:46,
@@ -1909,6 +1914,8 @@ fn parse_expr_sequence() {
                                                 num: 1,
                                                 denom: 4,
                                             },
+                                            articulation: Normal,
+                                            accent: false,
                                         },
                                     },
                                     Node {
@@ -1922,6 +1929,8 @@ fn parse_expr_sequence() {
                                                 num: 1,
                                                 denom: 4,
                                             },
+                                            articulation: Normal,
+                                            accent: false,
                                         },
                                     },
                                     Node {
@@ -1935,6 +1944,8 @@ fn parse_expr_sequence() {
                                                 num: 1,
                                                 denom: 4,
                                             },
+                                            articulation: Normal,
+                                            accent: false,
                                         },
                                     },
                                     Node {
@@ -1948,6 +1959,8 @@ fn parse_expr_sequence() {
                                                 num: 1,
                                                 denom: 4,
                                             },
+                                            articulation: Normal,
+                                            accent: false,
                                         },
                                     },
                                     Node {
@@ -1971,6 +1984,8 @@ fn parse_expr_sequence() {
                                                 num: 15,
                                                 denom: 16,
                                             },
+                                            articulation: Normal,
+                                            accent: false,
                                         },
                                     },
                                     Node {
@@ -1984,6 +1999,8 @@ fn parse_expr_sequence() {
                                                 num: 1,
                                                 denom: 16,
                                             },
+                                            articulation: Normal,
+                                            accent: false,
                                         },
                                     },
                                 ],
@@ -1999,3 +2016,1021 @@ fn parse_expr_sequence() {
             )"#]],
     );
 }
+
+#[test]
+fn parse_expr_sequence_articulation() {
+    check_expr(
+        "[[ a4'> c4~ ]]",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..14,
+                pos: 1:1..1:15,
+                data: Sequence(
+                    Node {
+                        span: 0..14,
+                        pos: 1:1..1:15,
+                        data: Sequence {
+                            llbracket: Node {
+                                span: 0..2,
+                                pos: 1:1..1:3,
+                                data: (),
+                            },
+                            symbols: [
+                                Node {
+                                    span: 3..7,
+                                    pos: 1:4..1:8,
+                                    data: Note {
+                                        note: Note(
+                                            69,
+                                        ),
+                                        duration: Rational {
+                                            num: 1,
+                                            denom: 4,
+                                        },
+                                        articulation: Staccato,
+                                        accent: true,
+                                    },
+                                },
+                                Node {
+                                    span: 8..11,
+                                    pos: 1:9..1:12,
+                                    data: Note {
+                                        note: Note(
+                                            60,
+                                        ),
+                                        duration: Rational {
+                                            num: 1,
+                                            denom: 4,
+                                        },
+                                        articulation: Legato,
+                                        accent: false,
+                                    },
+                                },
+                            ],
+                            rrbracket: Node {
+                                span: 12..14,
+                                pos: 1:13..1:15,
+                                data: (),
+                            },
+                        },
+                    },
+                ),
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_sequence_grid() {
+    check_expr(
+        "[[ kick-|x . X .| d2|x| ]]",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..26,
+                pos: 1:1..1:27,
+                data: Sequence(
+                    Node {
+                        span: 0..26,
+                        pos: 1:1..1:27,
+                        data: Sequence {
+                            llbracket: Node {
+                                span: 0..2,
+                                pos: 1:1..1:3,
+                                data: (),
+                            },
+                            symbols: [
+                                Node {
+                                    span: 3..17,
+                                    pos: 1:4..1:18,
+                                    data: Grid {
+                                        note: Note(
+                                            36,
+                                        ),
+                                        step: Rational {
+                                            num: 1,
+                                            denom: 8,
+                                        },
+                                        steps: [
+                                            Hit,
+                                            Rest,
+                                            Accent,
+                                            Rest,
+                                        ],
+                                    },
+                                },
+                                Node {
+                                    span: 18..23,
+                                    pos: 1:19..1:24,
+                                    data: Grid {
+                                        note: Note(
+                                            38,
+                                        ),
+                                        step: Rational {
+                                            num: 1,
+                                            denom: 4,
+                                        },
+                                        steps: [
+                                            Hit,
+                                        ],
+                                    },
+                                },
+                            ],
+                            rrbracket: Node {
+                                span: 24..26,
+                                pos: 1:25..1:27,
+                                data: (),
+                            },
+                        },
+                    },
+                ),
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_sequence_invalid_grid() {
+    let (_, errors) = Parser::parse("Song { notes: [[ bongo|x .| c2|x| ]] }").unwrap_err();
+    let messages = errors
+        .iter()
+        .map(|err| format!("{:?}: {}", err.pos.start, err.message))
+        .collect::<Vec<_>>()
+        .join("\n");
+    expect![[r#"1:18: Invalid drum grid: bongo|x .|"#]].assert_eq(&messages);
+}
+
+#[test]
+fn parse_expr_sequence_bend() {
+    check_expr(
+        "[[ c4 ^0.5 ^-1 ]]",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..17,
+                pos: 1:1..1:18,
+                data: Sequence(
+                    Node {
+                        span: 0..17,
+                        pos: 1:1..1:18,
+                        data: Sequence {
+                            llbracket: Node {
+                                span: 0..2,
+                                pos: 1:1..1:3,
+                                data: (),
+                            },
+                            symbols: [
+                                Node {
+                                    span: 3..5,
+                                    pos: 1:4..1:6,
+                                    data: Note {
+                                        note: Note(
+                                            60,
+                                        ),
+                                        duration: Rational {
+                                            num: 1,
+                                            denom: 4,
+                                        },
+                                        articulation: Normal,
+                                        accent: false,
+                                    },
+                                },
+                                Node {
+                                    span: 6..10,
+                                    pos: 1:7..1:11,
+                                    data: Bend {
+                                        amount: F64N(
+                                            0.5,
+                                        ),
+                                    },
+                                },
+                                Node {
+                                    span: 11..14,
+                                    pos: 1:12..1:15,
+                                    data: Bend {
+                                        amount: F64N(
+                                            -1.0,
+                                        ),
+                                    },
+                                },
+                            ],
+                            rrbracket: Node {
+                                span: 15..17,
+                                pos: 1:16..1:18,
+                                data: (),
+                            },
+                        },
+                    },
+                ),
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_sequence_invalid_bend() {
+    let (_, errors) = Parser::parse("Song { notes: [[ c4 ^1.5 ]] }").unwrap_err();
+    let messages = errors
+        .iter()
+        .map(|err| format!("{:?}: {}", err.pos.start, err.message))
+        .collect::<Vec<_>>()
+        .join("\n");
+    expect![[r#"1:21: Invalid pitch bend: ^1.5"#]].assert_eq(&messages);
+}
+
+#[test]
+fn parse_expr_sequence_invalid_articulation() {
+    check(
+        "Song { notes: [[ r' d4'~ ]] }",
+        expect![[r#"
+        Err(
+            (
+                Node {
+                    span: 0..29,
+                    pos: 1:1..1:30,
+                    data: Root {
+                        objects: [
+                            Node {
+                                span: 0..29,
+                                pos: 1:1..1:30,
+                                data: Object {
+                                    name: Node {
+                                        span: 0..4,
+                                        pos: 1:1..1:5,
+                                        data: "Song",
+                                    },
+                                    lbrace: Node {
+                                        span: 5..6,
+                                        pos: 1:6..1:7,
+                                        data: (),
+                                    },
+                                    attrs: [
+                                        Node {
+                                            span: 7..27,
+                                            pos: 1:8..1:28,
+                                            data: Attribute {
+                                                name: Node {
+                                                    span: 7..12,
+                                                    pos: 1:8..1:13,
+                                                    data: "notes",
+                                                },
+                                                colon: Node {
+                                                    span: 12..13,
+                                                    pos: 1:13..1:14,
+                                                    data: (),
+                                                },
+                                                value: Node {
+                                                    span: 14..27,
+                                                    pos: 1:15..1:28,
+                                                    data: Sequence(
+                                                        Node {
+                                                            span: 14..27,
+                                                            pos: 1:15..1:28,
+                                                            data: Sequence {
+                                                                llbracket: Node {
+                                                                    span: 14..16,
+                                                                    pos: 1:15..1:17,
+                                                                    data: (),
+                                                                },
+                                                                symbols: [],
+                                                                rrbracket: Node {
+                                                                    span: 25..27,
+                                                                    pos: 1:26..1:28,
+                                                                    data: (),
+                                                                },
+                                                            },
+                                                        },
+                                                    ),
+                                                },
+                                            },
+                                        },
+                                    ],
+                                    children: [],
+                                    rbrace: Node {
+                                        span: 28..29,
+                                        pos: 1:29..1:30,
+                                        data: (),
+                                    },
+                                },
+                            },
+                        ],
+                    },
+                },
+                [
+                    ParseError {
+                        span: 17..19,
+                        pos: 1:18..1:20,
+                        message: "Invalid note: r'",
+                    },
+                    ParseError {
+                        span: 20..24,
+                        pos: 1:21..1:25,
+                        message: "Invalid note: d4'~",
+                    },
+                ],
+            ),
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_call_named_arguments() {
+    check_expr(
+        r#"arp(chord, pattern: "updown")"#,
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..29,
+                pos: 1:1..1:30,
+                data: Call {
+                    callee: Node {
+                        span: 0..3,
+                        pos: 1:1..1:4,
+                        data: Var(
+                            "arp",
+                        ),
+                    },
+                    lparen: Node {
+                        span: 3..4,
+                        pos: 1:4..1:5,
+                        data: (),
+                    },
+                    arguments: [
+                        Node {
+                            span: 4..9,
+                            pos: 1:5..1:10,
+                            data: Var(
+                                "chord",
+                            ),
+                        },
+                    ],
+                    named_arguments: [
+                        Node {
+                            span: 11..28,
+                            pos: 1:12..1:29,
+                            data: Attribute {
+                                name: Node {
+                                    span: 11..18,
+                                    pos: 1:12..1:19,
+                                    data: "pattern",
+                                },
+                                colon: Node {
+                                    span: 18..19,
+                                    pos: 1:19..1:20,
+                                    data: (),
+                                },
+                                value: Node {
+                                    span: 20..28,
+                                    pos: 1:21..1:29,
+                                    data: String(
+                                        "updown",
+                                    ),
+                                },
+                            },
+                        },
+                    ],
+                    rparen: Node {
+                        span: 28..29,
+                        pos: 1:29..1:30,
+                        data: (),
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_call_invalid_named_arguments() {
+    let (_, errors) = Parser::parse("Song { x: f(a: 1, 2, a: 3, b.c: 4) }").unwrap_err();
+    let messages = errors
+        .iter()
+        .map(|err| format!("{:?}: {}", err.pos.start, err.message))
+        .collect::<Vec<_>>()
+        .join("\n");
+    expect![[r#"
+        1:19: positional arguments must come before named arguments
+        1:22: argument `a` is given more than once
+        1:28: expected an argument name"#]]
+    .assert_eq(&messages);
+}
+
+#[test]
+fn parse_expr_range() {
+    check_expr(
+        "0..=n * 2",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..9,
+                pos: 1:1..1:10,
+                data: Range {
+                    start: Node {
+                        span: 0..1,
+                        pos: 1:1..1:2,
+                        data: Int(
+                            0,
+                        ),
+                    },
+                    operator: Node {
+                        span: 1..4,
+                        pos: 1:2..1:5,
+                        data: Inclusive,
+                    },
+                    end: Node {
+                        span: 4..9,
+                        pos: 1:5..1:10,
+                        data: Binary {
+                            left: Node {
+                                span: 4..5,
+                                pos: 1:5..1:6,
+                                data: Var(
+                                    "n",
+                                ),
+                            },
+                            operator: Node {
+                                span: 6..7,
+                                pos: 1:7..1:8,
+                                data: Mult,
+                            },
+                            right: Node {
+                                span: 8..9,
+                                pos: 1:9..1:10,
+                                data: Int(
+                                    2,
+                                ),
+                            },
+                        },
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_range_chained() {
+    check_expr(
+        "0..4..8",
+        expect![[r#"
+        Err(
+            ParseError {
+                span: 4..6,
+                pos: 1:5..1:7,
+                message: "ranges cannot be chained",
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_pipe() {
+    check_expr(
+        "[[ c4 ]] |> transpose(12) |> reverse",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..36,
+                pos: 1:1..1:37,
+                data: Call {
+                    callee: Node {
+                        span: 29..36,
+                        pos: 1:30..1:37,
+                        data: Var(
+                            "reverse",
+                        ),
+                    },
+                    lparen: Node {
+                        span: 36..36,
+                        pos: 1:37..1:37,
+                        data: (),
+                    },
+                    arguments: [
+                        Node {
+                            span: 0..25,
+                            pos: 1:1..1:26,
+                            data: Call {
+                                callee: Node {
+                                    span: 12..21,
+                                    pos: 1:13..1:22,
+                                    data: Var(
+                                        "transpose",
+                                    ),
+                                },
+                                lparen: Node {
+                                    span: 21..22,
+                                    pos: 1:22..1:23,
+                                    data: (),
+                                },
+                                arguments: [
+                                    Node {
+                                        span: 0..8,
+                                        pos: 1:1..1:9,
+                                        data: Sequence(
+                                            Node {
+                                                span: 0..8,
+                                                pos: 1:1..1:9,
+                                                data: Sequence {
+                                                    llbracket: Node {
+                                                        span: 0..2,
+                                                        pos: 1:1..1:3,
+                                                        data: (),
+                                                    },
+                                                    symbols: [
+                                                        Node {
+                                                            span: 3..5,
+                                                            pos: 1:4..1:6,
+                                                            data: Note {
+                                                                note: Note(
+                                                                    60,
+                                                                ),
+                                                                duration: Rational {
+                                                                    num: 1,
+                                                                    denom: 4,
+                                                                },
+                                                                articulation: Normal,
+                                                                accent: false,
+                                                            },
+                                                        },
+                                                    ],
+                                                    rrbracket: Node {
+                                                        span: 6..8,
+                                                        pos: 1:7..1:9,
+                                                        data: (),
+                                                    },
+                                                },
+                                            },
+                                        ),
+                                    },
+                                    Node {
+                                        span: 22..24,
+                                        pos: 1:23..1:25,
+                                        data: Int(
+                                            12,
+                                        ),
+                                    },
+                                ],
+                                named_arguments: [],
+                                rparen: Node {
+                                    span: 24..25,
+                                    pos: 1:25..1:26,
+                                    data: (),
+                                },
+                            },
+                        },
+                    ],
+                    named_arguments: [],
+                    rparen: Node {
+                        span: 36..36,
+                        pos: 1:37..1:37,
+                        data: (),
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_pipe_precedence() {
+    check_expr(
+        "a + 1 |> f(b: 2) or c",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..21,
+                pos: 1:1..1:22,
+                data: Binary {
+                    left: Node {
+                        span: 0..16,
+                        pos: 1:1..1:17,
+                        data: Call {
+                            callee: Node {
+                                span: 9..10,
+                                pos: 1:10..1:11,
+                                data: Var(
+                                    "f",
+                                ),
+                            },
+                            lparen: Node {
+                                span: 10..11,
+                                pos: 1:11..1:12,
+                                data: (),
+                            },
+                            arguments: [
+                                Node {
+                                    span: 0..5,
+                                    pos: 1:1..1:6,
+                                    data: Binary {
+                                        left: Node {
+                                            span: 0..1,
+                                            pos: 1:1..1:2,
+                                            data: Var(
+                                                "a",
+                                            ),
+                                        },
+                                        operator: Node {
+                                            span: 2..3,
+                                            pos: 1:3..1:4,
+                                            data: Add,
+                                        },
+                                        right: Node {
+                                            span: 4..5,
+                                            pos: 1:5..1:6,
+                                            data: Int(
+                                                1,
+                                            ),
+                                        },
+                                    },
+                                },
+                            ],
+                            named_arguments: [
+                                Node {
+                                    span: 11..15,
+                                    pos: 1:12..1:16,
+                                    data: Attribute {
+                                        name: Node {
+                                            span: 11..12,
+                                            pos: 1:12..1:13,
+                                            data: "b",
+                                        },
+                                        colon: Node {
+                                            span: 12..13,
+                                            pos: 1:13..1:14,
+                                            data: (),
+                                        },
+                                        value: Node {
+                                            span: 14..15,
+                                            pos: 1:15..1:16,
+                                            data: Int(
+                                                2,
+                                            ),
+                                        },
+                                    },
+                                },
+                            ],
+                            rparen: Node {
+                                span: 15..16,
+                                pos: 1:16..1:17,
+                                data: (),
+                            },
+                        },
+                    },
+                    operator: Node {
+                        span: 17..19,
+                        pos: 1:18..1:20,
+                        data: Or,
+                    },
+                    right: Node {
+                        span: 20..21,
+                        pos: 1:21..1:22,
+                        data: Var(
+                            "c",
+                        ),
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+/// Show the diagnostics and the outline of the recovered objects.
+fn check_recovery(input: &str, output: Expect) {
+    fn outline(object: &ast::Node<ast::Object>, depth: usize, out: &mut String) {
+        let attrs = object
+            .data
+            .attrs
+            .iter()
+            .map(|attr| attr.data.name.data.as_str())
+            .collect::<Vec<_>>();
+        out.push_str(&format!(
+            "{}{} {:?} {:?}\n",
+            "  ".repeat(depth),
+            object.data.name.data,
+            attrs,
+            object.span
+        ));
+        for child in object.data.children.iter() {
+            outline(child, depth + 1, out);
+        }
+    }
+
+    let (root, diagnostics) = Parser::parse_with_diagnostics(input);
+    let mut out = String::new();
+    for diagnostic in diagnostics.iter() {
+        out.push_str(&format!(
+            "{:?} {:?}: {}\n",
+            diagnostic.severity, diagnostic.pos.start, diagnostic.message
+        ));
+    }
+    for object in root.data.objects.iter() {
+        outline(object, 0, &mut out);
+    }
+    output.assert_eq(&out);
+}
+
+#[test]
+fn parse_recover_missing_colon() {
+    check_recovery(
+        r#"Song {
+    bpm 120
+    Track {
+        name "Lead"
+        volume: 0.5
+    }
+}"#,
+        expect![[r#"
+            Warning 2:5: missing `:` after `bpm`
+            Warning 4:9: missing `:` after `name`
+            Song ["bpm"] 0..78
+              Track ["name", "volume"] 23..76
+        "#]],
+    );
+}
+
+#[test]
+fn parse_recover_missing_brace() {
+    check_recovery(
+        r#"Song {
+    Track {
+        name: "Lead"
+        Sequence {
+            start: 0
+    Track {
+        name: "Bass"
+}
+Song {
+    bpm: 90
+}"#,
+        expect![[r#"
+            Error 2:11: missing `}` to close `Track`
+            Error 4:18: missing `}` to close `Sequence`
+            Error 6:11: missing `}` to close `Track`
+            Song [] 0..114
+              Track ["name"] 11..79
+                Sequence ["start"] 48..79
+              Track ["name"] 84..112
+            Song ["bpm"] 115..135
+        "#]],
+    );
+}
+
+#[test]
+fn parse_recover_missing_brace_at_end() {
+    check_recovery(
+        r#"Song {
+    Track {
+        name: "Lead"
+        Sequence {
+            start: 0
+    }
+    Track {
+        name: "Bass"
+"#,
+        expect![[r#"
+            Error 1:6: missing `}` to close `Song`
+            Error 4:18: missing `}` to close `Sequence`
+            Error 7:11: missing `}` to close `Track`
+            Song [] 0..118
+              Track ["name"] 11..85
+                Sequence ["start"] 48..79
+              Track ["name"] 90..118
+        "#]],
+    );
+}
+
+#[test]
+fn parse_irregular_indentation() {
+    check_recovery(
+        "Song {\n    bpm: 120\n  Track {\n\tname: \"Lead\"\n        volume: 0.5\n}\n    }\n",
+        expect![[r#"
+            Song ["bpm"] 0..71
+              Track ["name", "volume"] 22..65
+        "#]],
+    );
+}
+
+#[test]
+fn parse_expr_list_index() {
+    check_expr(
+        "[1, x][0]",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..9,
+                pos: 1:1..1:10,
+                data: Index {
+                    expr: Node {
+                        span: 0..6,
+                        pos: 1:1..1:7,
+                        data: List {
+                            lbracket: Node {
+                                span: 0..1,
+                                pos: 1:1..1:2,
+                                data: (),
+                            },
+                            elements: [
+                                Node {
+                                    span: 1..2,
+                                    pos: 1:2..1:3,
+                                    data: Int(
+                                        1,
+                                    ),
+                                },
+                                Node {
+                                    span: 4..5,
+                                    pos: 1:5..1:6,
+                                    data: Var(
+                                        "x",
+                                    ),
+                                },
+                            ],
+                            rbracket: Node {
+                                span: 5..6,
+                                pos: 1:6..1:7,
+                                data: (),
+                            },
+                        },
+                    },
+                    lbracket: Node {
+                        span: 6..7,
+                        pos: 1:7..1:8,
+                        data: (),
+                    },
+                    index: Node {
+                        span: 7..8,
+                        pos: 1:8..1:9,
+                        data: Int(
+                            0,
+                        ),
+                    },
+                    rbracket: Node {
+                        span: 8..9,
+                        pos: 1:9..1:10,
+                        data: (),
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_list_nested() {
+    check_expr(
+        "[[1, 2], [3]]",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..13,
+                pos: 1:1..1:14,
+                data: List {
+                    lbracket: Node {
+                        span: 0..1,
+                        pos: 1:1..1:2,
+                        data: (),
+                    },
+                    elements: [
+                        Node {
+                            span: 1..7,
+                            pos: 1:2..1:8,
+                            data: List {
+                                lbracket: Node {
+                                    span: 1..2,
+                                    pos: 1:2..1:3,
+                                    data: (),
+                                },
+                                elements: [
+                                    Node {
+                                        span: 2..3,
+                                        pos: 1:3..1:4,
+                                        data: Int(
+                                            1,
+                                        ),
+                                    },
+                                    Node {
+                                        span: 5..6,
+                                        pos: 1:6..1:7,
+                                        data: Int(
+                                            2,
+                                        ),
+                                    },
+                                ],
+                                rbracket: Node {
+                                    span: 6..7,
+                                    pos: 1:7..1:8,
+                                    data: (),
+                                },
+                            },
+                        },
+                        Node {
+                            span: 9..12,
+                            pos: 1:10..1:13,
+                            data: List {
+                                lbracket: Node {
+                                    span: 9..10,
+                                    pos: 1:10..1:11,
+                                    data: (),
+                                },
+                                elements: [
+                                    Node {
+                                        span: 10..11,
+                                        pos: 1:11..1:12,
+                                        data: Int(
+                                            3,
+                                        ),
+                                    },
+                                ],
+                                rbracket: Node {
+                                    span: 11..12,
+                                    pos: 1:12..1:13,
+                                    data: (),
+                                },
+                            },
+                        },
+                    ],
+                    rbracket: Node {
+                        span: 12..13,
+                        pos: 1:13..1:14,
+                        data: (),
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_list_nested_index() {
+    check_expr(
+        "a[b[0]]",
+        expect![[r#"
+        Ok(
+            Node {
+                span: 0..7,
+                pos: 1:1..1:8,
+                data: Index {
+                    expr: Node {
+                        span: 0..1,
+                        pos: 1:1..1:2,
+                        data: Var(
+                            "a",
+                        ),
+                    },
+                    lbracket: Node {
+                        span: 1..2,
+                        pos: 1:2..1:3,
+                        data: (),
+                    },
+                    index: Node {
+                        span: 2..6,
+                        pos: 1:3..1:7,
+                        data: Index {
+                            expr: Node {
+                                span: 2..3,
+                                pos: 1:3..1:4,
+                                data: Var(
+                                    "b",
+                                ),
+                            },
+                            lbracket: Node {
+                                span: 3..4,
+                                pos: 1:4..1:5,
+                                data: (),
+                            },
+                            index: Node {
+                                span: 4..5,
+                                pos: 1:5..1:6,
+                                data: Int(
+                                    0,
+                                ),
+                            },
+                            rbracket: Node {
+                                span: 5..6,
+                                pos: 1:6..1:7,
+                                data: (),
+                            },
+                        },
+                    },
+                    rbracket: Node {
+                        span: 6..7,
+                        pos: 1:7..1:8,
+                        data: (),
+                    },
+                },
+            },
+        )"#]],
+    );
+}
+
+#[test]
+fn parse_expr_list_named() {
+    let (_, errors) = Parser::parse("Song { steps: [1, 2, a: 3] }").unwrap_err();
+    let messages = errors
+        .iter()
+        .map(|err| format!("{:?}: {}", err.pos.start, err.message))
+        .collect::<Vec<_>>()
+        .join("\n");
+    expect![[r#"1:22: list elements cannot be named"#]].assert_eq(&messages);
+}
@@ -0,0 +1,319 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rendering of diagnostics for terminals, showing the lines of source code they refer to:
+//!
+//! ```text
+//! warning: the sequence starts while another one still plays, cutting off its notes
+//!  --> song.syn:5:9
+//!   |
+//! 3 |     voices: 1
+//!   |             - the track plays only one note at a time
+//! 4 |     Sequence { notes: [[ c4 d4 ]] }
+//!   |     -------- the other sequence
+//! 5 |     Sequence { start: 1/8  notes: [[ e4 ]] }
+//!   |     ^^^^^^^^
+//! ```
+//!
+//! The range of a diagnostic is underlined with `^`, its related locations with `-` followed by
+//! their message. Ranges spanning several lines are only underlined on their first line.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    line_map::{LineMap, Pos},
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31;1m";
+const YELLOW: &str = "\x1b[33;1m";
+const BLUE: &str = "\x1b[34;1m";
+
+/// Renders the diagnostics of a source file.
+pub struct Report<'a> {
+    /// How the file is referred to, usually its path.
+    name: &'a str,
+    line_map: LineMap<'a>,
+    source: &'a str,
+    colored: bool,
+}
+
+/// An underlined range of a line.
+struct Label<'a> {
+    line: usize,
+    columns: Range<usize>,
+    primary: bool,
+    message: Option<&'a str>,
+}
+
+impl<'a> Report<'a> {
+    /// Render diagnostics of `source`, with ANSI colors if `colored` is set.
+    pub fn new(name: &'a str, source: &'a str, colored: bool) -> Self {
+        Report {
+            name,
+            line_map: LineMap::new(source),
+            source,
+            colored,
+        }
+    }
+
+    /// Render all diagnostics in the order of their position, each followed by an empty line.
+    pub fn render_all(&self, diagnostics: &[Diagnostic]) -> String {
+        let mut diagnostics = diagnostics.iter().collect::<Vec<_>>();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| self.render(diagnostic) + "\n")
+            .collect()
+    }
+
+    /// Render a single diagnostic: its severity and message, its location and
+    /// the lines of source code it refers to.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let (severity, color) = match diagnostic.severity {
+            Severity::Warning => ("warning", YELLOW),
+            Severity::Error => ("error", RED),
+        };
+
+        let mut labels = vec![self.label(&diagnostic.pos, true, None)];
+        labels.extend(
+            diagnostic
+                .related
+                .iter()
+                .map(|related| self.label(&related.pos, false, Some(&related.message))),
+        );
+        labels.sort_by_key(|label| (label.line, label.columns.start));
+        let last_line = labels.iter().map(|label| label.line).max().unwrap_or(1);
+        let gutter = " ".repeat(last_line.to_string().len());
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{}{}:{}{} {}{}",
+            self.paint(color),
+            severity,
+            self.paint(RESET),
+            self.paint(BOLD),
+            diagnostic.message,
+            self.paint(RESET)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "{}{}-->{} {}:{}",
+            gutter,
+            self.paint(BLUE),
+            self.paint(RESET),
+            self.name,
+            diagnostic.pos.start
+        )
+        .unwrap();
+        writeln!(out, "{} {}|{}", gutter, self.paint(BLUE), self.paint(RESET)).unwrap();
+
+        let mut previous = None;
+        for label in labels.iter() {
+            if previous != Some(label.line) {
+                if matches!(previous, Some(previous) if previous + 1 < label.line) {
+                    writeln!(out, "{}...{}", self.paint(BLUE), self.paint(RESET)).unwrap();
+                }
+                let line = self.line(label.line);
+                writeln!(
+                    out,
+                    "{}{:>width$} |{} {}",
+                    self.paint(BLUE),
+                    label.line,
+                    self.paint(RESET),
+                    line,
+                    width = gutter.len()
+                )
+                .unwrap();
+                previous = Some(label.line);
+            }
+            let (marker, color) = if label.primary {
+                ('^', color)
+            } else {
+                ('-', BLUE)
+            };
+            write!(
+                out,
+                "{} {}|{} {}{}",
+                gutter,
+                self.paint(BLUE),
+                self.paint(RESET),
+                " ".repeat(label.columns.start - 1),
+                self.paint(color)
+            )
+            .unwrap();
+            for _ in label.columns.clone() {
+                out.push(marker);
+            }
+            if let Some(message) = label.message {
+                write!(out, " {}", message).unwrap();
+            }
+            writeln!(out, "{}", self.paint(RESET)).unwrap();
+        }
+        out
+    }
+
+    fn label(&self, pos: &Range<Pos>, primary: bool, message: Option<&'a str>) -> Label<'a> {
+        let start = pos.start.column.max(1);
+        let end = if pos.end.line == pos.start.line {
+            pos.end.column
+        } else {
+            self.line(pos.start.line).chars().count() + 1
+        };
+        Label {
+            line: pos.start.line.max(1),
+            // Empty ranges, e.g. at the end of the file, are shown as a single character
+            columns: start..end.max(start + 1),
+            primary,
+            message,
+        }
+    }
+
+    fn line(&self, line: usize) -> &'a str {
+        let span = self.line_map.line_span(line);
+        self.source[span].trim_end_matches('\r')
+    }
+
+    fn paint(&self, color: &'static str) -> &'static str {
+        if self.colored {
+            color
+        } else {
+            ""
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use crate::{diagnostic::Diagnostic, line_map::LineMap};
+    use expect_test::{expect, Expect};
+
+    /// Render a diagnostic at the first occurrence of `primary`, related to the first occurrences
+    /// of the strings in `related`.
+    fn check(source: &str, primary: &str, related: &[&str], output: Expect) {
+        let line_map = LineMap::new(source);
+        let range = |text: &str| {
+            let start = source.find(text).unwrap();
+            let span = start..start + text.len();
+            let pos = line_map.offset_to_pos(span.start)..line_map.offset_to_pos(span.end);
+            (span, pos)
+        };
+        let (span, pos) = range(primary);
+        let mut diagnostic = Diagnostic::error(span, pos, "something is wrong".into());
+        for (index, text) in related.iter().enumerate() {
+            let (span, pos) = range(text);
+            diagnostic = diagnostic.with_related(span, pos, format!("related {}", index + 1));
+        }
+        output.assert_eq(&Report::new("song.syn", source, false).render(&diagnostic));
+    }
+
+    #[test]
+    fn single_line() {
+        check(
+            "Song {\n    bpm: \"fast\"\n}",
+            "\"fast\"",
+            &[],
+            expect![[r#"
+                error: something is wrong
+                 --> song.syn:2:10
+                  |
+                2 |     bpm: "fast"
+                  |          ^^^^^^
+            "#]],
+        );
+    }
+
+    #[test]
+    fn related() {
+        let source = r#"Song {
+    bpm: 90
+    volume: 1
+    layout: "quad"
+    Track { speakers: "center" }
+    Track { volume: 2 }
+    Track { volume: 3 }
+    Track { volume: 4 }
+    Track { volume: 5 }
+    Track { volume: 6 }
+    bpm: 100
+}"#;
+        check(
+            source,
+            "bpm: 100",
+            &["bpm: 90", "\"quad\"", "\"center\"", "speakers"],
+            expect![[r#"
+                error: something is wrong
+                  --> song.syn:11:5
+                   |
+                 2 |     bpm: 90
+                   |     ------- related 1
+                ...
+                 4 |     layout: "quad"
+                   |             ------ related 2
+                 5 |     Track { speakers: "center" }
+                   |             -------- related 4
+                   |                       -------- related 3
+                ...
+                11 |     bpm: 100
+                   |     ^^^^^^^^
+            "#]],
+        );
+    }
+
+    #[test]
+    fn multiple_lines() {
+        check(
+            "Song {\n    Track {\n    }\n}",
+            "Track {\n    }",
+            &[],
+            expect![[r#"
+                error: something is wrong
+                 --> song.syn:2:5
+                  |
+                2 |     Track {
+                  |     ^^^^^^^
+            "#]],
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let source = "Song { bpm: 1.5 }";
+        let line_map = LineMap::new(source);
+        let pos = line_map.offset_to_pos(12)..line_map.offset_to_pos(15);
+        let diagnostic = Diagnostic::warning(12..15, pos, "odd".into());
+        let plain = Report::new("song.syn", source, false).render(&diagnostic);
+        let colored = Report::new("song.syn", source, true).render(&diagnostic);
+        assert!(colored.starts_with("\x1b[33;1mwarning:"));
+        // Remove the escape sequences, which all end with `m`
+        let mut escaped = false;
+        let stripped = colored
+            .chars()
+            .filter(|&ch| {
+                let visible = !escaped && ch != '\x1b';
+                escaped = ch == '\x1b' || escaped && ch != 'm';
+                visible
+            })
+            .collect::<String>();
+        assert_eq!(stripped, plain);
+    }
+}
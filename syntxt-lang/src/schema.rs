@@ -0,0 +1,391 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Description of the object types that make up a song and the attributes they understand.
+
+/// Description of an object type.
+#[derive(Debug)]
+pub struct ObjectSchema {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub attributes: &'static [AttributeSchema],
+    /// The object types that can be nested directly inside this object.
+    pub children: &'static [&'static str],
+}
+
+/// Description of an attribute of an object.
+#[derive(Debug)]
+pub struct AttributeSchema {
+    pub name: &'static str,
+    pub doc: &'static str,
+    /// The value used when the attribute is omitted, or `None` if the attribute is optional.
+    pub default: Option<&'static str>,
+}
+
+/// Description of a builtin function.
+#[derive(Debug)]
+pub struct FunctionSchema {
+    pub name: &'static str,
+    pub doc: &'static str,
+}
+
+impl ObjectSchema {
+    pub fn attribute(&self, name: &str) -> Option<&'static AttributeSchema> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+}
+
+/// The attribute that every object understands.
+pub const ID_ATTRIBUTE: AttributeSchema = AttributeSchema {
+    name: "id",
+    doc: "Name under which the object can be referenced elsewhere",
+    default: None,
+};
+
+/// Object types that may appear at the top level of a file.
+pub const ROOT_OBJECTS: &[&str] = &["Song"];
+
+/// All known object types.
+pub const OBJECTS: &[ObjectSchema] = &[
+    ObjectSchema {
+        name: "Song",
+        doc: "A complete song consisting of several tracks",
+        attributes: &[
+            AttributeSchema {
+                name: "bpm",
+                doc: "The speed of the song measured in beats per minute",
+                default: Some("120"),
+            },
+            AttributeSchema {
+                name: "sampleRate",
+                doc: "Number of samples per second of the rendered audio",
+                default: Some("44_100"),
+            },
+            AttributeSchema {
+                name: "volume",
+                doc: "Linear gain applied to the mix of all tracks",
+                default: Some("1.0"),
+            },
+            AttributeSchema {
+                name: "layout",
+                doc: "The speakers the song is mixed for: \"stereo\", \"quad\" or \"5.1\"",
+                default: Some("\"stereo\""),
+            },
+            AttributeSchema {
+                name: "meta",
+                doc: "Information about the song",
+                default: None,
+            },
+        ],
+        children: &["Track", "Lyrics"],
+    },
+    ObjectSchema {
+        name: "Meta",
+        doc: "Information about a song",
+        attributes: &[
+            AttributeSchema {
+                name: "name",
+                doc: "Title of the song",
+                default: None,
+            },
+            AttributeSchema {
+                name: "author",
+                doc: "Who wrote the song",
+                default: None,
+            },
+            AttributeSchema {
+                name: "year",
+                doc: "When the song was written",
+                default: None,
+            },
+            AttributeSchema {
+                name: "description",
+                doc: "Free-form description of the song",
+                default: None,
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Track",
+        doc: "Notes played on a single instrument",
+        attributes: &[
+            AttributeSchema {
+                name: "name",
+                doc: "Name of the track",
+                default: None,
+            },
+            AttributeSchema {
+                name: "volume",
+                doc: "Linear gain applied to the track",
+                default: Some("1.0"),
+            },
+            AttributeSchema {
+                name: "pan",
+                doc: "Position in the stereo field between -1 (left) and 1 (right)",
+                default: Some("0.0"),
+            },
+            AttributeSchema {
+                name: "speakers",
+                doc: "The speakers of the layout the track plays on: \"front\", \"rear\" or \"center\", which pans between the center (left) and the subwoofer (right)",
+                default: Some("\"front\""),
+            },
+            AttributeSchema {
+                name: "mute",
+                doc: "Whether the track is silenced",
+                default: Some("false"),
+            },
+            AttributeSchema {
+                name: "solo",
+                doc: "Whether the track is soloed, silencing all tracks that are not",
+                default: Some("false"),
+            },
+            AttributeSchema {
+                name: "freeze",
+                doc: "Whether the track is rendered once and reused until it changes",
+                default: Some("false"),
+            },
+            AttributeSchema {
+                name: "voices",
+                doc: "Maximum number of notes the built-in synthesizer plays at once, 1 to play like a monophonic synthesizer",
+                default: None,
+            },
+            AttributeSchema {
+                name: "plugin",
+                doc: "Path of a CLAP plugin playing the notes instead of the built-in synthesizer",
+                default: None,
+            },
+        ],
+        children: &["Sequence", "Clip", "Eq", "Gate", "Plugin", "Param"],
+    },
+    ObjectSchema {
+        name: "Sequence",
+        doc: "Notes placed at a point in time of a track",
+        attributes: &[
+            AttributeSchema {
+                name: "start",
+                doc: "Time at which the sequence starts, measured in whole notes",
+                default: Some("0"),
+            },
+            AttributeSchema {
+                name: "notes",
+                doc: "The notes of the sequence, e.g. [[ c4 e4 g4 ]], importMidi(\"riff.mid\", track: 2) or importAbc(\"tunes.abc\", tune: 3)",
+                default: Some("[[ ]]"),
+            },
+            AttributeSchema {
+                name: "use",
+                doc: "Id of another sequence whose notes are played instead",
+                default: None,
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Eq",
+        doc: "Equalizer changing the volume of frequency ranges of a track",
+        attributes: &[],
+        children: &["LowShelf", "Peak", "HighShelf"],
+    },
+    ObjectSchema {
+        name: "LowShelf",
+        doc: "Boost or cut of the frequencies below a corner frequency",
+        attributes: &[
+            AttributeSchema {
+                name: "frequency",
+                doc: "Corner frequency, in Hz",
+                default: Some("100"),
+            },
+            AttributeSchema {
+                name: "gain",
+                doc: "Boost (positive) or cut (negative) in dB",
+                default: Some("0"),
+            },
+            AttributeSchema {
+                name: "q",
+                doc: "Steepness of the transition to the unchanged frequencies",
+                default: Some("0.707"),
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Peak",
+        doc: "Boost or cut of the frequencies around a center frequency",
+        attributes: &[
+            AttributeSchema {
+                name: "frequency",
+                doc: "Center frequency, in Hz",
+                default: Some("1000"),
+            },
+            AttributeSchema {
+                name: "gain",
+                doc: "Boost (positive) or cut (negative) in dB",
+                default: Some("0"),
+            },
+            AttributeSchema {
+                name: "q",
+                doc: "Narrowness of the band, higher values affect fewer frequencies",
+                default: Some("1.0"),
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "HighShelf",
+        doc: "Boost or cut of the frequencies above a corner frequency",
+        attributes: &[
+            AttributeSchema {
+                name: "frequency",
+                doc: "Corner frequency, in Hz",
+                default: Some("8000"),
+            },
+            AttributeSchema {
+                name: "gain",
+                doc: "Boost (positive) or cut (negative) in dB",
+                default: Some("0"),
+            },
+            AttributeSchema {
+                name: "q",
+                doc: "Steepness of the transition to the unchanged frequencies",
+                default: Some("0.707"),
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Gate",
+        doc: "Gate silencing a track while it is quiet, or chopping it following a pattern",
+        attributes: &[
+            AttributeSchema {
+                name: "threshold",
+                doc: "Level in dB below which the gate closes",
+                default: Some("-40"),
+            },
+            AttributeSchema {
+                name: "attack",
+                doc: "Time in seconds the gate takes to open",
+                default: Some("0.001"),
+            },
+            AttributeSchema {
+                name: "release",
+                doc: "Time in seconds the gate takes to close",
+                default: Some("0.05"),
+            },
+            AttributeSchema {
+                name: "pattern",
+                doc: "Sequence or id of a sequence during whose notes the gate is open, \
+                      instead of following the level of the track",
+                default: None,
+            },
+            AttributeSchema {
+                name: "length",
+                doc: "Length of the pattern in whole notes, after which it repeats",
+                default: Some("1"),
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Clip",
+        doc: "Audio file played at a point in time of a track, alongside its notes",
+        attributes: &[
+            AttributeSchema {
+                name: "file",
+                doc: "Path of the audio file, relative to the song",
+                default: None,
+            },
+            AttributeSchema {
+                name: "start",
+                doc: "Time at which the recording starts, measured in whole notes",
+                default: Some("0"),
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Plugin",
+        doc: "Effect in the CLAP plugin format applied to a track",
+        attributes: &[AttributeSchema {
+            name: "path",
+            doc: "Path of the plugin file",
+            default: None,
+        }],
+        children: &["Param"],
+    },
+    ObjectSchema {
+        name: "Param",
+        doc: "Setting of a parameter of a plugin",
+        attributes: &[
+            AttributeSchema {
+                name: "name",
+                doc: "Name of the parameter as given by the plugin",
+                default: None,
+            },
+            AttributeSchema {
+                name: "value",
+                doc: "Value of the parameter in the units of the plugin",
+                default: Some("0.0"),
+            },
+        ],
+        children: &[],
+    },
+    ObjectSchema {
+        name: "Lyrics",
+        doc: "Timed text shown alongside the song, e.g. lyrics or section markers",
+        attributes: &[],
+        children: &["Line"],
+    },
+    ObjectSchema {
+        name: "Line",
+        doc: "A piece of text shown at a point in time",
+        attributes: &[
+            AttributeSchema {
+                name: "start",
+                doc: "Time at which the line is shown, measured in whole notes",
+                default: Some("0"),
+            },
+            AttributeSchema {
+                name: "text",
+                doc: "The text of the line",
+                default: None,
+            },
+        ],
+        children: &[],
+    },
+];
+
+/// Look up an object type by name.
+pub fn object(name: &str) -> Option<&'static ObjectSchema> {
+    OBJECTS.iter().find(|obj| obj.name == name)
+}
+
+pub const IMPORT_MIDI: FunctionSchema = FunctionSchema {
+    name: "importMidi",
+    doc: "The notes of a MIDI file, e.g. `importMidi(\"riff.mid\", track: 1, channel: 1)`",
+};
+
+pub const IMPORT_ABC: FunctionSchema = FunctionSchema {
+    name: "importAbc",
+    doc: "The notes of a tune in ABC notation, e.g. `importAbc(\"tunes.abc\", tune: 2)`",
+};
+
+/// All builtin functions.
+pub const FUNCTIONS: &[FunctionSchema] = &[IMPORT_MIDI, IMPORT_ABC];
+
+/// Look up a builtin function by name.
+pub fn function(name: &str) -> Option<&'static FunctionSchema> {
+    FUNCTIONS.iter().find(|function| function.name == name)
+}
@@ -0,0 +1,347 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Classification of source ranges by their meaning, e.g. for semantic highlighting in editors.
+
+use std::ops::Range;
+
+use crate::{
+    ast::{self, Node, Visit, Walk},
+    lexer::Span,
+    line_map::{LineMap, Pos},
+};
+
+/// The meaning of a classified piece of source code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// The name of an object, e.g. `Song` in `Song { ... }`.
+    ObjectType,
+    /// The name of an attribute inside an object, e.g. `bpm` in `bpm: 120`.
+    AttributeName,
+    /// A variable reference.
+    Variable,
+    /// A variable that is called as a function.
+    Function,
+    /// The attribute name in an accessor expression, e.g. `name` in `meta.name`.
+    Property,
+    /// The pitch part of a note in a sequence.
+    Note,
+    /// A rest in a sequence.
+    Rest,
+    /// The duration suffix of a note or rest in a sequence.
+    Duration,
+    /// The articulation markers of a note in a sequence.
+    Articulation,
+    /// The steps of a drum grid line, e.g. `|x . X .|`.
+    GridSteps,
+    String,
+    Number,
+    Bool,
+    /// Unary and binary operators, including `and`, `or` and `not`.
+    Operator,
+}
+
+impl TokenKind {
+    /// All token kinds, in the order of their `index`.
+    pub const ALL: &'static [TokenKind] = &[
+        TokenKind::ObjectType,
+        TokenKind::AttributeName,
+        TokenKind::Variable,
+        TokenKind::Function,
+        TokenKind::Property,
+        TokenKind::Note,
+        TokenKind::Rest,
+        TokenKind::Duration,
+        TokenKind::Articulation,
+        TokenKind::GridSteps,
+        TokenKind::String,
+        TokenKind::Number,
+        TokenKind::Bool,
+        TokenKind::Operator,
+    ];
+
+    /// Stable numeric identifier of this kind, as used in the legend of semantic token providers.
+    pub fn index(self) -> usize {
+        TokenKind::ALL
+            .iter()
+            .position(|kind| *kind == self)
+            .expect("all kinds are listed")
+    }
+
+    /// Name of the closest standard LSP semantic token type.
+    pub fn lsp_name(self) -> &'static str {
+        match self {
+            TokenKind::ObjectType => "type",
+            TokenKind::AttributeName => "property",
+            TokenKind::Variable => "variable",
+            TokenKind::Function => "function",
+            TokenKind::Property => "property",
+            TokenKind::Note => "enumMember",
+            TokenKind::Rest => "enumMember",
+            TokenKind::Duration => "number",
+            TokenKind::Articulation => "modifier",
+            TokenKind::GridSteps => "string",
+            TokenKind::String => "string",
+            TokenKind::Number => "number",
+            TokenKind::Bool => "keyword",
+            TokenKind::Operator => "operator",
+        }
+    }
+}
+
+/// A classified range of the source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub pos: Range<Pos>,
+    pub kind: TokenKind,
+}
+
+/// Classify the parts of the AST that carry meaning beyond their lexical category.
+/// The `source` must be the text the AST was parsed from.
+///
+/// The returned tokens are ordered by their position in the source and do not overlap.
+/// Punctuation and comments are not classified.
+pub fn semantic_tokens(source: &str, root: &Node<ast::Root>) -> Vec<SemanticToken> {
+    let mut classifier = Classifier {
+        source,
+        line_map: LineMap::new(source),
+        tokens: Vec::new(),
+    };
+    root.walk(&mut classifier);
+    classifier.tokens.sort_by_key(|token| token.span.start);
+    classifier.tokens
+}
+
+struct Classifier<'a> {
+    source: &'a str,
+    line_map: LineMap<'a>,
+    tokens: Vec<SemanticToken>,
+}
+
+impl<'a> Classifier<'a> {
+    fn push(&mut self, span: Span, kind: TokenKind) {
+        if span.is_empty() {
+            return;
+        }
+        self.tokens.push(SemanticToken {
+            pos: self.line_map.offset_to_pos(span.start)..self.line_map.offset_to_pos(span.end),
+            span,
+            kind,
+        })
+    }
+
+    /// Split a note or rest symbol into its pitch, duration and articulation parts.
+    fn seq_symbol(&mut self, span: Span, kind: TokenKind) {
+        let text = &self.source[span.clone()];
+        let mut chars = text.char_indices().peekable();
+        // Note name or rest symbol
+        chars.next();
+        if kind == TokenKind::Note {
+            // Accidental
+            if let Some((_, '#')) | Some((_, '♯')) | Some((_, 'b')) | Some((_, '♭')) = chars.peek()
+            {
+                chars.next();
+            }
+            // Octave
+            if let Some((_, ch)) = chars.peek() {
+                if ch.is_ascii_digit() {
+                    chars.next();
+                }
+            }
+        }
+        let split = chars.peek().map_or(text.len(), |(index, _)| *index);
+        let markers = text.len() - text.trim_end_matches(&['\'', '~', '>'][..]).len();
+        self.push(span.start..span.start + split, kind);
+        self.push(span.start + split..span.end - markers, TokenKind::Duration);
+        self.push(span.end - markers..span.end, TokenKind::Articulation);
+    }
+
+    /// Split a drum grid line into its note, step duration and steps.
+    fn grid(&mut self, span: Span) {
+        let text = &self.source[span.clone()];
+        let bar = text.find('|').unwrap_or(text.len());
+        let name = text[..bar].trim_end_matches(&['+', '-'][..]).len();
+        self.push(span.start..span.start + name, TokenKind::Note);
+        self.push(span.start + name..span.start + bar, TokenKind::Duration);
+        self.push(span.start + bar..span.end, TokenKind::GridSteps);
+    }
+}
+
+impl<'a> ast::Visitor for Classifier<'a> {
+    fn object(&mut self, node: &Node<ast::Object>) {
+        self.push(node.data.name.span.clone(), TokenKind::ObjectType);
+        node.walk(self);
+    }
+
+    fn attribute(&mut self, node: &Node<ast::Attribute>) {
+        self.push(node.data.name.span.clone(), TokenKind::AttributeName);
+        node.walk(self);
+    }
+
+    fn expr(&mut self, node: &Node<ast::Expr>) {
+        match &node.data {
+            ast::Expr::String(_) => self.push(node.span.clone(), TokenKind::String),
+            ast::Expr::Int(_) | ast::Expr::Ratio(_) | ast::Expr::Float(_) => {
+                self.push(node.span.clone(), TokenKind::Number)
+            }
+            ast::Expr::Bool(_) => self.push(node.span.clone(), TokenKind::Bool),
+            ast::Expr::Var(_) => self.push(node.span.clone(), TokenKind::Variable),
+            ast::Expr::Unary { operator, .. } => {
+                self.push(operator.span.clone(), TokenKind::Operator)
+            }
+            ast::Expr::Binary { operator, .. } => {
+                self.push(operator.span.clone(), TokenKind::Operator)
+            }
+            ast::Expr::Range { operator, .. } => {
+                self.push(operator.span.clone(), TokenKind::Operator)
+            }
+            ast::Expr::Accessor { attribute, .. } => {
+                self.push(attribute.span.clone(), TokenKind::Property)
+            }
+            ast::Expr::Call {
+                callee,
+                arguments,
+                named_arguments,
+                ..
+            } if matches!(callee.data, ast::Expr::Var(_)) => {
+                self.push(callee.span.clone(), TokenKind::Function);
+                // Only visit the arguments, the callee has already been classified
+                arguments.visit(self);
+                named_arguments.visit(self);
+                return;
+            }
+            ast::Expr::Call { .. }
+            | ast::Expr::Paren { .. }
+            | ast::Expr::Object(_)
+            | ast::Expr::Sequence(_)
+            | ast::Expr::List { .. }
+            | ast::Expr::Index { .. } => {}
+        }
+        node.walk(self);
+    }
+
+    fn sequence(&mut self, node: &Node<ast::Sequence>) {
+        node.walk(self);
+    }
+
+    fn seq_sym(&mut self, node: &Node<ast::SeqSym>) {
+        match &node.data {
+            ast::SeqSym::Note { .. } => self.seq_symbol(node.span.clone(), TokenKind::Note),
+            ast::SeqSym::Rest { .. } => self.seq_symbol(node.span.clone(), TokenKind::Rest),
+            ast::SeqSym::Grid { .. } => self.grid(node.span.clone()),
+            ast::SeqSym::Bend { .. } => self.push(node.span.clone(), TokenKind::Number),
+            ast::SeqSym::Group(_) => node.walk(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::semantic_tokens;
+    use crate::parser::Parser;
+    use expect_test::{expect, Expect};
+
+    fn check(input: &str, output: Expect) {
+        let root = match Parser::parse(input) {
+            Ok(root) => root,
+            Err((root, _)) => root,
+        };
+        let tokens = semantic_tokens(input, &root)
+            .into_iter()
+            .map(|token| format!("{:?} {:?}", token.kind, &input[token.span]))
+            .collect::<Vec<_>>()
+            .join("\n");
+        output.assert_eq(&tokens);
+    }
+
+    #[test]
+    fn objects_and_attributes() {
+        check(
+            r#"Song {
+    bpm: 120 * 2
+    meta: Meta { name: "Song" }
+    Track { loud: not false }
+}"#,
+            expect![[r#"
+                ObjectType "Song"
+                AttributeName "bpm"
+                Number "120"
+                Operator "*"
+                Number "2"
+                AttributeName "meta"
+                ObjectType "Meta"
+                AttributeName "name"
+                String "\"Song\""
+                ObjectType "Track"
+                AttributeName "loud"
+                Operator "not"
+                Bool "false""#]],
+        );
+    }
+
+    #[test]
+    fn calls_and_accessors() {
+        check(
+            "Song { x: sin(time).value + song.bpm }",
+            expect![[r#"
+                ObjectType "Song"
+                AttributeName "x"
+                Function "sin"
+                Variable "time"
+                Property "value"
+                Operator "+"
+                Variable "song"
+                Property "bpm""#]],
+        );
+    }
+
+    #[test]
+    fn sequences() {
+        check(
+            "Song { notes: [[ c#4+.' r-_- [[ a4> eb3 ]] ]] }",
+            expect![[r#"
+                ObjectType "Song"
+                AttributeName "notes"
+                Note "c#4"
+                Duration "+."
+                Articulation "'"
+                Rest "r"
+                Duration "-_-"
+                Note "a4"
+                Articulation ">"
+                Note "eb3""#]],
+        );
+    }
+
+    #[test]
+    fn grids() {
+        check(
+            "Song { notes: [[ kick--|x . X .| c#2|x x| hat+|. x| ]] }",
+            expect![[r#"
+                ObjectType "Song"
+                AttributeName "notes"
+                Note "kick"
+                Duration "--"
+                GridSteps "|x . X .|"
+                Note "c#2"
+                GridSteps "|x x|"
+                Note "hat"
+                Duration "+"
+                GridSteps "|. x|""#]],
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Definitions and references of named things in a source file,
+//! the basis for go-to-definition, find-usages and rename.
+//!
+//! Objects can be given a name with an `id` attribute, e.g. `Track { id: lead }`.
+//! Ids are visible in the whole file and can be referenced like variables.
+//! Attributes are referenced through accessors on ids, e.g. `lead.name`.
+
+use std::collections::HashMap;
+
+use crate::ast::{self, Node, Visit, Walk};
+
+/// Index of a symbol in its `SymbolTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(usize);
+
+impl SymbolId {
+    /// Position of the symbol in `SymbolTable::symbols`.
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    pub(crate) fn from_index(index: usize) -> SymbolId {
+        SymbolId(index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// An object named by its `id` attribute.
+    Object,
+    /// An attribute of an object.
+    Attribute,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// Where the symbol is defined, i.e. the value of the `id` attribute for objects,
+    /// and the attribute name for attributes.
+    pub definition: Node<String>,
+    /// All places referring to this symbol.
+    pub references: Vec<Node<String>>,
+    /// The object symbol containing this symbol, if that object has an id.
+    pub container: Option<SymbolId>,
+}
+
+impl Symbol {
+    pub fn name(&self) -> &str {
+        &self.definition.data
+    }
+}
+
+/// All symbols defined in a source file, along with their references.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    /// Object symbols by their id.
+    ids: HashMap<String, SymbolId>,
+    /// Attribute symbols by the id of their object and their name.
+    attributes: HashMap<(SymbolId, String), SymbolId>,
+}
+
+impl SymbolTable {
+    /// Collect all symbols of a parsed file.
+    ///
+    /// If an id is used more than once, references resolve to the first definition.
+    pub fn build(root: &Node<ast::Root>) -> SymbolTable {
+        let mut collector = DefinitionCollector {
+            table: SymbolTable::default(),
+            containers: Vec::new(),
+        };
+        root.walk(&mut collector);
+
+        let mut resolver = ReferenceResolver {
+            table: collector.table,
+        };
+        root.walk(&mut resolver);
+        resolver.table
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    /// Look up the object with the given id.
+    pub fn object(&self, name: &str) -> Option<SymbolId> {
+        self.ids.get(name).copied()
+    }
+
+    /// Look up an attribute of an object that has an id.
+    pub fn attribute(&self, object: SymbolId, name: &str) -> Option<SymbolId> {
+        self.attributes.get(&(object, name.to_string())).copied()
+    }
+
+    /// Find the symbol whose definition or one of whose references contains the given byte offset.
+    pub fn symbol_at(&self, offset: usize) -> Option<SymbolId> {
+        let contains = |node: &Node<String>| node.span.start <= offset && offset <= node.span.end;
+        self.symbols
+            .iter()
+            .position(|symbol| {
+                contains(&symbol.definition) || symbol.references.iter().any(contains)
+            })
+            .map(SymbolId)
+    }
+
+    fn add(&mut self, symbol: Symbol) -> SymbolId {
+        let id = SymbolId(self.symbols.len());
+        self.symbols.push(symbol);
+        id
+    }
+}
+
+/// Return the name given by the `id` attribute of an object, if there is one.
+pub fn object_id(object: &ast::Object) -> Option<Node<String>> {
+    object.attrs.iter().find_map(|attr| {
+        if attr.data.name.data != "id" {
+            return None;
+        }
+        match &attr.data.value.data {
+            ast::Expr::Var(name) => Some(Node {
+                span: attr.data.value.span.clone(),
+                pos: attr.data.value.pos.clone(),
+                data: name.clone(),
+            }),
+            _ => None,
+        }
+    })
+}
+
+/// First pass: collect all objects with ids and their attributes.
+struct DefinitionCollector {
+    table: SymbolTable,
+    /// Symbols of the objects enclosing the current node (`None` for objects without id).
+    containers: Vec<Option<SymbolId>>,
+}
+
+impl ast::Visitor for DefinitionCollector {
+    fn object(&mut self, node: &Node<ast::Object>) {
+        let container = self.containers.last().copied().flatten();
+        let object = object_id(&node.data).map(|definition| {
+            let name = definition.data.clone();
+            let symbol = self.table.add(Symbol {
+                kind: SymbolKind::Object,
+                definition,
+                references: Vec::new(),
+                container,
+            });
+            self.table.ids.entry(name).or_insert(symbol);
+            symbol
+        });
+
+        for attr in node.data.attrs.iter() {
+            let name = attr.data.name.data.clone();
+            let symbol = self.table.add(Symbol {
+                kind: SymbolKind::Attribute,
+                definition: attr.data.name.clone(),
+                references: Vec::new(),
+                container: object,
+            });
+            if let Some(object) = object {
+                self.table
+                    .attributes
+                    .entry((object, name))
+                    .or_insert(symbol);
+            }
+        }
+
+        self.containers.push(object);
+        node.walk(self);
+        self.containers.pop();
+    }
+
+    fn attribute(&mut self, node: &Node<ast::Attribute>) {
+        node.walk(self);
+    }
+
+    fn expr(&mut self, node: &Node<ast::Expr>) {
+        // Objects can be nested in arbitrary expressions
+        node.walk(self);
+    }
+}
+
+/// Second pass: resolve variables and accessors to the collected definitions.
+struct ReferenceResolver {
+    table: SymbolTable,
+}
+
+impl ast::Visitor for ReferenceResolver {
+    fn object(&mut self, node: &Node<ast::Object>) {
+        for attr in node.data.attrs.iter() {
+            // The value of an `id` attribute is a definition, not a reference
+            if attr.data.name.data == "id" {
+                if let ast::Expr::Var(_) = attr.data.value.data {
+                    continue;
+                }
+            }
+            attr.visit(self);
+        }
+        node.data.children.visit(self);
+    }
+
+    fn attribute(&mut self, node: &Node<ast::Attribute>) {
+        node.walk(self);
+    }
+
+    fn expr(&mut self, node: &Node<ast::Expr>) {
+        match &node.data {
+            ast::Expr::Var(name) => {
+                if let Some(symbol) = self.table.object(name) {
+                    self.table.symbols[symbol.0].references.push(Node {
+                        span: node.span.clone(),
+                        pos: node.pos.clone(),
+                        data: name.clone(),
+                    });
+                }
+            }
+            ast::Expr::Accessor {
+                expr, attribute, ..
+            } => {
+                expr.visit(self);
+                if let ast::Expr::Var(name) = &expr.data {
+                    let symbol = self
+                        .table
+                        .object(name)
+                        .and_then(|object| self.table.attribute(object, &attribute.data));
+                    if let Some(symbol) = symbol {
+                        self.table.symbols[symbol.0]
+                            .references
+                            .push(attribute.clone());
+                    }
+                }
+            }
+            _ => node.walk(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SymbolKind, SymbolTable};
+    use crate::parser::Parser;
+
+    const SOURCE: &str = r#"Song {
+    bpm: 120
+    Track {
+        id: lead
+        name: "Lead"
+        volume: 0.5
+    }
+    Track {
+        name: lead.name + " copy"
+        volume: lead.volume * 2
+        follow: lead
+    }
+}"#;
+
+    #[test]
+    fn resolve_ids_and_attributes() {
+        let root = Parser::parse(SOURCE).unwrap();
+        let table = SymbolTable::build(&root);
+
+        let lead = table.object("lead").unwrap();
+        assert_eq!(table.get(lead).kind, SymbolKind::Object);
+        assert_eq!(table.get(lead).definition.pos.start.line, 4);
+        let lead_refs = table
+            .get(lead)
+            .references
+            .iter()
+            .map(|r| r.pos.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lead_refs, vec![9, 10, 11]);
+
+        let name = table.attribute(lead, "name").unwrap();
+        assert_eq!(table.get(name).kind, SymbolKind::Attribute);
+        assert_eq!(table.get(name).container, Some(lead));
+        assert_eq!(table.get(name).references.len(), 1);
+        assert_eq!(&SOURCE[table.get(name).references[0].span.clone()], "name");
+
+        // `lead`, plus the attributes of all three objects, even if they have no id
+        assert_eq!(table.symbols().len(), 8);
+        assert!(table.attribute(lead, "follow").is_none());
+    }
+
+    #[test]
+    fn symbol_at_offset() {
+        let root = Parser::parse(SOURCE).unwrap();
+        let table = SymbolTable::build(&root);
+        let lead = table.object("lead");
+
+        let definition = SOURCE.find("lead").unwrap();
+        assert_eq!(table.symbol_at(definition), lead);
+        let reference = SOURCE.rfind("lead").unwrap();
+        assert_eq!(table.symbol_at(reference + 2), lead);
+        let accessor = SOURCE.find(".volume").unwrap() + 1;
+        assert_eq!(
+            table.symbol_at(accessor),
+            table.attribute(lead.unwrap(), "volume")
+        );
+    }
+}
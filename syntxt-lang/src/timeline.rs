@@ -0,0 +1,335 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Timed note events of sequences, each linked back to the source code that produced it.
+//!
+//! All times are measured in whole notes from the start of the song.
+
+use syntxt_core::{
+    nonnan::F64N,
+    note::{Note, Velocity},
+    rational::Rational,
+};
+
+use crate::ast::{self, Node};
+
+/// A note played at a certain time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteEvent {
+    pub note: Note,
+    pub start: Rational,
+    /// The written duration, regardless of the articulation.
+    pub duration: Rational,
+    pub articulation: ast::Articulation,
+    pub accent: bool,
+    /// The velocity the note was recorded with, e.g. when imported from a MIDI file.
+    /// Otherwise it follows from the accent.
+    pub velocity: Option<Velocity>,
+    /// The note symbol, or the import, in the source code this event was produced by.
+    pub origin: Node<()>,
+}
+
+impl NoteEvent {
+    pub fn end(&self) -> Rational {
+        self.start + self.duration
+    }
+
+    /// Whether the note is sounding at the given time.
+    pub fn is_playing_at(&self, time: Rational) -> bool {
+        self.start <= time && time < self.end()
+    }
+}
+
+/// A change of the pitch bend at a certain time, see `ast::SeqSym::Bend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BendEvent {
+    pub amount: F64N,
+    pub start: Rational,
+    /// The bend symbol in the source code this event was produced by.
+    pub origin: Node<()>,
+}
+
+/// The events produced by flattening sequences.
+#[derive(Debug, Default)]
+struct Events {
+    notes: Vec<NoteEvent>,
+    bends: Vec<BendEvent>,
+}
+
+/// Flatten a sequence into note events, starting at the given time.
+///
+/// The symbols of a sequence are played one after the other.
+/// A group nested in a sequence is played as a stack, i.e. all its symbols start at the same time,
+/// and a group nested in a stack is again played as a sequence.
+pub fn sequence_events(sequence: &Node<ast::Sequence>, start: Rational) -> Vec<NoteEvent> {
+    let mut events = Events::default();
+    flatten(&sequence.data, false, start, &mut events);
+    events.notes
+}
+
+/// Flatten a sequence into pitch bend events, starting at the given time.
+pub fn sequence_bends(sequence: &Node<ast::Sequence>, start: Rational) -> Vec<BendEvent> {
+    let mut events = Events::default();
+    flatten(&sequence.data, false, start, &mut events);
+    events.bends
+}
+
+/// The length of a sequence, including trailing rests.
+pub fn sequence_duration(sequence: &Node<ast::Sequence>) -> Rational {
+    flatten(
+        &sequence.data,
+        false,
+        Rational::zero(),
+        &mut Events::default(),
+    )
+}
+
+/// Collect the note events of all `Sequence` objects nested in a track.
+///
+/// The start time of a sequence must be given as a number literal, as expressions cannot be
+/// evaluated yet. Sequences without a (literal) start time begin at the start of the song.
+pub fn track_events(track: &Node<ast::Object>) -> Vec<NoteEvent> {
+    let mut events = Events::default();
+    for child in track.data.children.iter() {
+        if child.data.name.data != "Sequence" {
+            continue;
+        }
+        let mut start = Rational::zero();
+        let mut notes = None;
+        for attr in child.data.attrs.iter() {
+            match (attr.data.name.data.as_str(), &attr.data.value.data) {
+                ("start", ast::Expr::Int(int)) => start = Rational::int(*int),
+                ("start", ast::Expr::Ratio(ratio)) => start = *ratio,
+                ("notes", ast::Expr::Sequence(sequence)) => notes = Some(sequence),
+                _ => {}
+            }
+        }
+        if let Some(notes) = notes {
+            flatten(&notes.data, false, start, &mut events);
+        }
+    }
+    events.notes.sort_by_key(|event| event.start);
+    events.notes
+}
+
+/// Return the events sounding at the given time.
+pub fn playing_at(events: &[NoteEvent], time: Rational) -> impl Iterator<Item = &NoteEvent> {
+    events.iter().filter(move |event| event.is_playing_at(time))
+}
+
+/// Add the events of a sequence (or stack) to `events` and return the time at which it ends.
+fn flatten(
+    sequence: &ast::Sequence,
+    stack: bool,
+    start: Rational,
+    events: &mut Events,
+) -> Rational {
+    let mut time = start;
+    for sym in sequence.symbols.iter() {
+        let symbol_start = if stack { start } else { time };
+        let symbol_end = match &sym.data {
+            ast::SeqSym::Note {
+                note,
+                duration,
+                articulation,
+                accent,
+            } => {
+                events.notes.push(NoteEvent {
+                    note: *note,
+                    start: symbol_start,
+                    duration: *duration,
+                    articulation: *articulation,
+                    accent: *accent,
+                    velocity: None,
+                    origin: Node {
+                        span: sym.span.clone(),
+                        pos: sym.pos.clone(),
+                        data: (),
+                    },
+                });
+                symbol_start + *duration
+            }
+            ast::SeqSym::Rest { duration } => symbol_start + *duration,
+            ast::SeqSym::Grid { note, step, steps } => {
+                let mut step_start = symbol_start;
+                for grid_step in steps.iter() {
+                    if *grid_step != ast::GridStep::Rest {
+                        events.notes.push(NoteEvent {
+                            note: *note,
+                            start: step_start,
+                            duration: *step,
+                            articulation: ast::Articulation::Normal,
+                            accent: *grid_step == ast::GridStep::Accent,
+                            velocity: None,
+                            origin: Node {
+                                span: sym.span.clone(),
+                                pos: sym.pos.clone(),
+                                data: (),
+                            },
+                        });
+                    }
+                    step_start += *step;
+                }
+                step_start
+            }
+            ast::SeqSym::Bend { amount } => {
+                events.bends.push(BendEvent {
+                    amount: *amount,
+                    start: symbol_start,
+                    origin: Node {
+                        span: sym.span.clone(),
+                        pos: sym.pos.clone(),
+                        data: (),
+                    },
+                });
+                // Bends take no time
+                symbol_start
+            }
+            ast::SeqSym::Group(group) => flatten(&group.data, !stack, symbol_start, events),
+        };
+        time = if stack {
+            time.max(symbol_end)
+        } else {
+            symbol_end
+        };
+    }
+    time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{playing_at, sequence_bends, sequence_duration, track_events};
+    use crate::{ast, parser::Parser};
+    use syntxt_core::rational::Rational;
+
+    #[test]
+    fn events_of_track() {
+        let source = r#"Song {
+    Track {
+        Sequence {
+            start: 1
+            notes: [[ c4 r [[ e4 g4+ ]] a4- ]]
+        }
+        Sequence { notes: [[ r+ f4 ]] }
+        Sequence { start: 2 }
+    }
+}"#;
+        let root = Parser::parse(source).unwrap();
+        let track = &root.data.objects[0].data.children[0];
+        let events = track_events(track)
+            .into_iter()
+            .map(|event| {
+                format!(
+                    "{} {} {} {}",
+                    event.note.to_midi(),
+                    event.start,
+                    event.duration,
+                    event.origin.pos.start.line
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                "65 1/2 1/4 7",
+                "60 1 1/4 5",
+                "64 3/2 1/4 5",
+                "67 3/2 1/2 5",
+                "69 2 1/8 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn events_playing_at() {
+        let source = "Sequence { notes: [[ c4 [[ [[ d4 e4 ]] f4+. ]] ]] }";
+        let root = Parser::parse(&format!("Track {{ {} }}", source)).unwrap();
+        let events = track_events(&root.data.objects[0]);
+        let at = |time| {
+            playing_at(&events, time)
+                .map(|event| event.note.to_midi())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(at(Rational::new(1, 8)), vec![60]);
+        assert_eq!(at(Rational::new(1, 4)), vec![62, 65]);
+        assert_eq!(at(Rational::new(1, 2)), vec![65, 64]);
+        assert_eq!(at(Rational::new(3, 4)), vec![65]);
+        assert!(at(Rational::int(1)).is_empty());
+    }
+
+    #[test]
+    fn drum_grid() {
+        let source = "Sequence { notes: [[ [[ kick-|x . . x| hat-|X x X x| ]] snare|x| ]] }";
+        let root = Parser::parse(&format!("Track {{ {} }}", source)).unwrap();
+        let events = track_events(&root.data.objects[0])
+            .into_iter()
+            .map(|event| {
+                format!(
+                    "{} {} {}{}",
+                    event.note.to_midi(),
+                    event.start,
+                    event.duration,
+                    if event.accent { " >" } else { "" }
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                "36 0 1/8",
+                "42 0 1/8 >",
+                "42 1/8 1/8",
+                "42 1/4 1/8 >",
+                "36 3/8 1/8",
+                "42 3/8 1/8",
+                "38 1/2 1/4",
+            ]
+        );
+    }
+
+    #[test]
+    fn duration() {
+        let duration = |source: &str| {
+            let root = Parser::parse(&format!("Sequence {{ notes: {} }}", source)).unwrap();
+            match &root.data.objects[0].data.attrs[0].data.value.data {
+                ast::Expr::Sequence(seq) => sequence_duration(seq),
+                _ => panic!("not a sequence"),
+            }
+        };
+        assert_eq!(duration("[[ ]]"), Rational::zero());
+        assert_eq!(duration("[[ c4 r+ ]]"), Rational::new(3, 4));
+        assert_eq!(duration("[[ hat--|x . . .| ]]"), Rational::new(1, 4));
+        assert_eq!(
+            duration("[[ [[ c4 [[ d4 e4 ]] ]] r- ]]"),
+            Rational::new(5, 8)
+        );
+        assert_eq!(duration("[[ c4 ^1 ^0 ]]"), Rational::new(1, 4));
+    }
+
+    #[test]
+    fn bends() {
+        let root = Parser::parse("Sequence { notes: [[ c4 ^0.5 d4 [[ ^-1 e4 ]] ^0 ]] }").unwrap();
+        let bends = match &root.data.objects[0].data.attrs[0].data.value.data {
+            ast::Expr::Sequence(seq) => sequence_bends(seq, Rational::int(1)),
+            _ => panic!("not a sequence"),
+        };
+        let bends = bends
+            .into_iter()
+            .map(|bend| format!("{} {}", bend.amount, bend.start))
+            .collect::<Vec<_>>();
+        assert_eq!(bends, vec!["0.5 5/4", "-1 3/2", "0 7/4"]);
+    }
+}
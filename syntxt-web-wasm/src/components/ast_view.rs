@@ -120,11 +120,14 @@ impl ast::Visitor for AstTreeVisitor {
             // nested expressions
             ast::Expr::Unary { operator, .. } => self.nested(format!("{:?}", operator.data), node),
             ast::Expr::Binary { operator, .. } => self.nested(format!("{:?}", operator.data), node),
+            ast::Expr::Range { operator, .. } => self.nested(format!("{:?}", operator.data), node),
             ast::Expr::Paren { .. } => self.nested("()", node),
             ast::Expr::Accessor { attribute, .. } => {
                 self.nested(format!(".{}", attribute.data), node)
             }
             ast::Expr::Call { .. } => self.nested("Call", node),
+            ast::Expr::List { .. } => self.nested("List", node),
+            ast::Expr::Index { .. } => self.nested("[]", node),
             // nested, but not an expression, hide expression node
             ast::Expr::Object(obj) => obj.visit(self),
             ast::Expr::Sequence(seq) => seq.visit(self),
@@ -137,10 +140,29 @@ impl ast::Visitor for AstTreeVisitor {
 
     fn seq_sym(&mut self, node: &ast::Node<ast::SeqSym>) {
         match &node.data {
-            ast::SeqSym::Note { note, duration } => {
-                self.leaf(format!("{} @ {}", note.to_midi(), duration), node)
+            ast::SeqSym::Note {
+                note,
+                duration,
+                articulation,
+                accent,
+            } => {
+                let mut label = format!("{} @ {}", note.to_midi(), duration);
+                match articulation {
+                    ast::Articulation::Normal => {}
+                    ast::Articulation::Staccato => label.push_str(" staccato"),
+                    ast::Articulation::Legato => label.push_str(" legato"),
+                }
+                if *accent {
+                    label.push_str(" accent");
+                }
+                self.leaf(label, node)
             }
             ast::SeqSym::Rest { duration } => self.leaf(format!("R @ {}", duration), node),
+            ast::SeqSym::Grid { note, step, steps } => self.leaf(
+                format!("Grid {} @ {} x {}", note.to_midi(), step, steps.len()),
+                node,
+            ),
+            ast::SeqSym::Bend { amount } => self.leaf(format!("Bend {}", amount), node),
             ast::SeqSym::Group(_) => self.nested("Group", node),
         }
     }
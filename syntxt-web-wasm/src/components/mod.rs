@@ -23,6 +23,7 @@ pub mod editor;
 pub mod list;
 pub mod song_view;
 pub mod splitter;
+pub mod transport;
 pub mod tree;
 
 pub struct WeakComponentLink<C: Component>(Rc<RefCell<Option<ComponentLink<C>>>>);
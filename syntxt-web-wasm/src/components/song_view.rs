@@ -1,4 +1,4 @@
-use syntxt_lang::{ast, line_map::Pos};
+use syntxt_lang::{ast, line_map::Pos, model};
 use yew::prelude::*;
 
 /// A component for displaying the syntxt AST as a tree.
@@ -39,13 +39,15 @@ impl Component for SongView {
 fn render(ast: &ast::Node<ast::Root>, onjump: Callback<Pos>) -> Html {
     for child in &ast.data.objects {
         if child.data.name.data == "Song" {
-            return view_song(child, onjump);
+            let (song, _) = model::resolve(ast);
+            let lyrics = song.map(|song| song.lyrics).unwrap_or_default();
+            return view_song(child, &lyrics, onjump);
         }
     }
     html! { { "Not a song" }}
 }
 
-fn view_song(ast: &ast::Node<ast::Object>, onjump: Callback<Pos>) -> Html {
+fn view_song(ast: &ast::Node<ast::Object>, lyrics: &[model::Line], onjump: Callback<Pos>) -> Html {
     html! {
         <div style="height: 100%; width: 100%; overflow-x: hidden; overflow-y: auto;">
         {
@@ -55,6 +57,35 @@ fn view_song(ast: &ast::Node<ast::Object>, onjump: Callback<Pos>) -> Html {
             .filter(|(_, child)| child.data.name.data == "Track")
             .map(|(index, child)| view_track(child, index, onjump.clone()))
         }
+        { view_lyrics(lyrics, onjump) }
+        </div>
+    }
+}
+
+fn view_lyrics(lyrics: &[model::Line], onjump: Callback<Pos>) -> Html {
+    if lyrics.is_empty() {
+        return html! {};
+    }
+    html! {
+        <div class=classes!("song-view-track", "song-view-lyrics")>
+            <div class=classes!("song-view-track-header") style="width: 200px;">
+                <div class=classes!("song-view-track-name")>{ "Lyrics" }</div>
+            </div>
+            { for lyrics.iter().map(|line| view_line(line, onjump.clone())) }
+        </div>
+    }
+}
+
+fn view_line(line: &model::Line, onjump: Callback<Pos>) -> Html {
+    let pos = line.origin.pos.start;
+    let onclick = onjump.reform(move |e: MouseEvent| {
+        e.stop_propagation();
+        pos
+    });
+    html! {
+        <div class=classes!("song-view-line") onclick=onclick>
+            <span class=classes!("song-view-line-start")>{ line.start.value.to_string() }</span>
+            { &line.text }
         </div>
     }
 }
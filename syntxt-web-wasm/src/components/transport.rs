@@ -0,0 +1,245 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use yew::{prelude::*, web_sys::HtmlInputElement};
+
+use super::WeakComponentLink;
+use crate::player;
+
+/// Whether the song is being played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Playback {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// Buttons, a slider and displays of the time for controlling how the song is played.
+///
+/// The app tells it what the player is doing with messages. Releasing the slider plays the song
+/// from the beat at its position.
+pub struct Transport {
+    link: ComponentLink<Self>,
+    props: Props,
+    slider: NodeRef,
+    playback: Playback,
+    /// Seconds of the song that have been played.
+    position: f64,
+    /// Seconds until the end of the song, 0 until it has been played.
+    length: f64,
+    bpm: f64,
+    sample_rate: f64,
+    looping: bool,
+    /// Seconds at the position of the slider while it is being dragged.
+    dragging: Option<f64>,
+    /// Why the song could not be played the last time.
+    error: Option<String>,
+}
+
+#[derive(Clone, Properties)]
+pub struct Props {
+    pub weak_link: WeakComponentLink<Transport>,
+    #[prop_or_default]
+    pub onplay: Callback<()>,
+    #[prop_or_default]
+    pub onpause: Callback<()>,
+    #[prop_or_default]
+    pub onstop: Callback<()>,
+    /// Callback for playing the song from a beat, counted from 0.
+    #[prop_or_default]
+    pub onseek: Callback<u32>,
+    /// Callback for whether the song starts over when it ends.
+    #[prop_or_default]
+    pub onloop: Callback<bool>,
+}
+
+pub enum Msg {
+    /// The app started, paused or stopped playing.
+    Playback(Playback),
+    Player(player::Event),
+    Drag,
+    Seek,
+    ToggleLoop,
+}
+
+impl Transport {
+    fn slider_seconds(&self) -> f64 {
+        self.slider
+            .cast::<HtmlInputElement>()
+            .map_or(0.0, |slider| slider.value_as_number())
+    }
+}
+
+impl Component for Transport {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        props.weak_link.attach(link.clone());
+        Self {
+            link,
+            props,
+            slider: NodeRef::default(),
+            playback: Playback::Stopped,
+            position: 0.0,
+            length: 0.0,
+            bpm: 120.0,
+            sample_rate: 0.0,
+            looping: false,
+            dragging: None,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Playback(playback) => {
+                self.playback = playback;
+                match playback {
+                    Playback::Playing => self.error = None,
+                    Playback::Stopped => self.position = 0.0,
+                    Playback::Paused => {}
+                }
+                true
+            }
+            Msg::Player(player::Event::Loaded {
+                start,
+                end,
+                sample_rate,
+                bpm,
+            }) => {
+                self.sample_rate = sample_rate;
+                self.bpm = bpm;
+                self.position = start / sample_rate;
+                self.length = end / sample_rate;
+                true
+            }
+            Msg::Player(player::Event::Position { frame }) => {
+                // Positions reported before stopping may still arrive afterwards
+                if self.playback == Playback::Stopped || self.sample_rate == 0.0 {
+                    return false;
+                }
+                self.position = frame / self.sample_rate;
+                true
+            }
+            Msg::Player(player::Event::Ended) => false,
+            Msg::Player(player::Event::Error { message }) => {
+                self.error = Some(message);
+                true
+            }
+            Msg::Drag => {
+                self.dragging = Some(self.slider_seconds());
+                true
+            }
+            Msg::Seek => {
+                self.dragging = None;
+                self.position = self.slider_seconds();
+                self.props.onseek.emit(beat_at(self.position, self.bpm));
+                true
+            }
+            Msg::ToggleLoop => {
+                self.looping = !self.looping;
+                self.props.onloop.emit(self.looping);
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        false
+    }
+
+    fn view(&self) -> Html {
+        let position = self.dragging.unwrap_or(self.position);
+        let playing = self.playback == Playback::Playing;
+        let stopped = self.playback == Playback::Stopped;
+        let unknown_length = self.length == 0.0;
+        let (symbol, title, onclick) = if playing {
+            ("⏸", "Pause", self.props.onpause.reform(|_| ()))
+        } else {
+            ("▶", "Play", self.props.onplay.reform(|_| ()))
+        };
+        html! {
+            <div class=classes!("transport")>
+                <button
+                    class=classes!("button-flat")
+                    title=title
+                    onclick=onclick
+                    >{ symbol }</button>
+                <button
+                    class=classes!("button-flat")
+                    title="Stop"
+                    disabled=stopped
+                    onclick=self.props.onstop.reform(|_| ())
+                    >{ "⏹" }</button>
+                <button
+                    class=classes!("button-flat", self.looping.then(|| "transport-active"))
+                    title="Loop"
+                    onclick=self.link.callback(|_| Msg::ToggleLoop)
+                    >{ "🔁" }</button>
+                <input
+                    type="range"
+                    ref=self.slider.clone()
+                    class=classes!("transport-slider")
+                    min="0"
+                    max=self.length.to_string()
+                    step="any"
+                    value=position.to_string()
+                    disabled=unknown_length
+                    oninput=self.link.callback(|_| Msg::Drag)
+                    onchange=self.link.callback(|_| Msg::Seek)
+                    />
+                <span class=classes!("transport-time") title="Bar and beat">
+                    { bar_and_beat(position, self.bpm) }
+                </span>
+                <span class=classes!("transport-time") title="Minutes and seconds">
+                    { format!("{} / {}", minutes_and_seconds(position), minutes_and_seconds(self.length)) }
+                </span>
+                {
+                    match &self.error {
+                        Some(message) => html! {
+                            <span class=classes!("transport-error")>{ message }</span>
+                        },
+                        None => html! {},
+                    }
+                }
+            </div>
+        }
+    }
+
+    fn destroy(&mut self) {
+        self.props.weak_link.detach();
+    }
+}
+
+/// The beat playing at a time of the song, counted from 0, where beats are quarter notes.
+fn beat_at(seconds: f64, bpm: f64) -> u32 {
+    // Times at the start of a beat may come out just below it
+    (seconds * bpm / 60.0 + 1e-6).floor() as u32
+}
+
+/// The bar and beat playing at a time of the song in 4/4, counted from 1, e.g. `3:2`.
+fn bar_and_beat(seconds: f64, bpm: f64) -> String {
+    let beat = beat_at(seconds, bpm);
+    format!("{}:{}", beat / 4 + 1, beat % 4 + 1)
+}
+
+/// A time in minutes and seconds, e.g. `1:05`.
+fn minutes_and_seconds(seconds: f64) -> String {
+    let seconds = seconds.floor() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -16,12 +16,14 @@
 
 use std::{sync::Arc, vec};
 
-use syntxt_lang::{ast, line_map::Pos};
+use syntxt_lang::{ast, diagnostic::Severity, line_map::Pos};
 use wasm_bindgen::prelude::*;
 use yew::prelude::*;
 
 pub mod components;
 pub mod console;
+pub mod player;
+pub mod render;
 
 use components::{
     ast_view::AstView,
@@ -29,6 +31,7 @@ use components::{
     list::List,
     song_view::SongView,
     splitter::{Orientation, SplitContainer, SplitPane},
+    transport::{self, Playback, Transport},
     Size,
 };
 use components::{
@@ -36,19 +39,29 @@ use components::{
     list::ListItem,
     WeakComponentLink,
 };
+use player::Player;
 
 #[wasm_bindgen(start)]
 pub fn run() {
     console_error_panic_hook::set_once();
-    App::<AppModel>::new().mount_to_body();
+    // The worker rendering songs loads the module too, but has no page to show the app on
+    if yew::web_sys::window().is_some() {
+        App::<AppModel>::new().mount_to_body();
+    }
 }
 
 struct AppModel {
     link: ComponentLink<Self>,
     editor: WeakComponentLink<Editor>,
+    transport: WeakComponentLink<Transport>,
     showing_issues: bool,
+    source: String,
     ast: ast::NodePtr<ast::Root>,
     issues: Vec<Issue>,
+    player: Player,
+    playback: Playback,
+    /// Whether the song starts over when it ends.
+    looping: bool,
 }
 
 enum Msg {
@@ -56,22 +69,34 @@ enum Msg {
     ShowIssues(bool),
     GoToIssue(usize),
     JumpToEditor { line: u32, column: u32 },
+    Play,
+    Pause,
+    Stop,
+    Seek(u32),
+    SetLoop(bool),
+    Player(player::Event),
 }
 
 impl Component for AppModel {
     type Message = Msg;
     type Properties = ();
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let player = Player::new(link.callback(Msg::Player));
         Self {
             link,
             editor: WeakComponentLink::default(),
+            transport: WeakComponentLink::default(),
             showing_issues: false,
+            source: String::new(),
             ast: Arc::new(ast::Node {
                 span: 0..0,
                 pos: Pos::origin()..Pos::origin(),
                 data: ast::Root { objects: vec![] },
             }),
             issues: Vec::new(),
+            player,
+            playback: Playback::Stopped,
+            looping: false,
         }
     }
 
@@ -79,20 +104,16 @@ impl Component for AppModel {
         match msg {
             Msg::SourceCodeChanged(code) => {
                 self.issues.clear();
-                match syntxt_lang::parser::Parser::parse(&code) {
-                    Ok(ast) => {
-                        self.ast = Arc::new(ast);
-                    }
-                    Err((partial_ast, errors)) => {
-                        self.ast = Arc::new(partial_ast);
-                        for err in errors {
-                            self.issues.push(Issue {
-                                message: err.message,
-                                start: err.pos.start,
-                                end: err.pos.end,
-                            })
-                        }
-                    }
+                let (ast, diagnostics) = syntxt_lang::parser::Parser::parse_with_diagnostics(&code);
+                self.ast = Arc::new(ast);
+                self.source = code;
+                for diagnostic in diagnostics {
+                    self.issues.push(Issue {
+                        message: diagnostic.message,
+                        severity: diagnostic.severity,
+                        start: diagnostic.pos.start,
+                        end: diagnostic.pos.end,
+                    })
                 }
                 true
             }
@@ -117,6 +138,54 @@ impl Component for AppModel {
                 self.editor.send_message(editor::Msg::GoTo { line, column });
                 false
             }
+            Msg::Play => {
+                if self.playback == Playback::Paused {
+                    self.player.resume();
+                } else {
+                    self.player.play(&self.source, 0);
+                }
+                self.set_playback(Playback::Playing);
+                false
+            }
+            Msg::Pause => {
+                self.player.pause();
+                self.set_playback(Playback::Paused);
+                false
+            }
+            Msg::Stop => {
+                self.player.stop();
+                self.set_playback(Playback::Stopped);
+                false
+            }
+            Msg::Seek(beat) => {
+                self.player.play(&self.source, beat);
+                self.set_playback(Playback::Playing);
+                false
+            }
+            Msg::SetLoop(looping) => {
+                self.looping = looping;
+                false
+            }
+            Msg::Player(event) => {
+                match event {
+                    // Only the end of what is playing, not of songs stopped in the meantime
+                    player::Event::Ended if self.playback == Playback::Playing => {
+                        if self.looping {
+                            self.player.play(&self.source, 0);
+                        } else {
+                            self.set_playback(Playback::Stopped);
+                        }
+                    }
+                    player::Event::Error { .. } => {
+                        self.player.stop();
+                        self.set_playback(Playback::Stopped);
+                    }
+                    _ => {}
+                }
+                // Only the transport shows what is playing, which changes many times per second
+                self.transport.send_message(transport::Msg::Player(event));
+                false
+            }
         }
     }
 
@@ -142,7 +211,10 @@ impl Component for AppModel {
                                                 end_line_number: issue.end.line as u32,
                                                 end_column: issue.end.column as u32,
                                                 message: issue.message.clone(),
-                                                severity: MarkerSeverity::Error,
+                                                severity: match issue.severity {
+                                                    Severity::Error => MarkerSeverity::Error,
+                                                    Severity::Warning => MarkerSeverity::Warning,
+                                                },
                                             }
                                         }).collect::<Vec<_>>()
                                         on_content_changed=self.link.callback(|code| Msg::SourceCodeChanged(code))
@@ -189,6 +261,14 @@ impl Component for AppModel {
                         style="height: 100%;"
                         onclick=self.link.callback(move |_| Msg::ShowIssues(!showing_issues))
                         >{ format!("ⓧ {}", self.issues.len()) }</button>
+                    <Transport
+                        weak_link=&self.transport
+                        onplay=self.link.callback(|_| Msg::Play)
+                        onpause=self.link.callback(|_| Msg::Pause)
+                        onstop=self.link.callback(|_| Msg::Stop)
+                        onseek=self.link.callback(Msg::Seek)
+                        onloop=self.link.callback(Msg::SetLoop)
+                        />
                 </SplitPane>
             </SplitContainer>
         }
@@ -212,6 +292,7 @@ impl Component for AppModel {
 
       Sequence {
         start: 8/4
+        notes: [[ c4 e4 g4 c5+ ]]
       }
     }
     // Test for comments
@@ -228,9 +309,17 @@ impl Component for AppModel {
     }
 }
 
+impl AppModel {
+    fn set_playback(&mut self, playback: Playback) {
+        self.playback = playback;
+        self.transport.send_message(transport::Msg::Playback(playback));
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 struct Issue {
     message: String,
+    severity: Severity,
     start: Pos,
     end: Pos,
 }
@@ -239,7 +328,16 @@ impl ListItem for Issue {
     fn view(&self) -> Html {
         html! {
             <>
-                <span style="color:red; font-weight: bold; margin-right: 5px">{"ⓧ"}</span>
+                {
+                    match self.severity {
+                        Severity::Error => html! {
+                            <span style="color:red; font-weight: bold; margin-right: 5px">{"ⓧ"}</span>
+                        },
+                        Severity::Warning => html! {
+                            <span style="color:orange; font-weight: bold; margin-right: 5px">{"⚠"}</span>
+                        },
+                    }
+                }
                 <span>{&self.message}</span>
                 <span style="color:gray; margin-left: 5px">{self.start.line}{":"}{self.start.column}</span>
             </>
@@ -0,0 +1,110 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Playing songs with WebAudio.
+//!
+//! The JavaScript side (`static/player.js`) renders the song in a web worker and streams the
+//! blocks to an audio worklet, staying a few seconds ahead of what has been played.
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use yew::Callback;
+
+use crate::console_log;
+
+/// Happenings while playing a song, reported by the JavaScript side.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// The song compiled and is rendered from the `start` frame until the `end` frame.
+    /// Beats are quarter notes.
+    #[serde(rename_all = "camelCase")]
+    Loaded {
+        start: f64,
+        end: f64,
+        sample_rate: f64,
+        bpm: f64,
+    },
+    /// The frame of the song playing right now, reported a few times per second.
+    Position { frame: f64 },
+    /// The song has been played until the end.
+    Ended,
+    /// The song could not be compiled or rendered.
+    Error { message: String },
+}
+
+pub struct Player {
+    state: JsValue,
+    /// Must stay alive as long as the JavaScript side may call it.
+    _on_event: Closure<dyn Fn(JsValue)>,
+}
+
+impl Player {
+    pub fn new(on_event: Callback<Event>) -> Self {
+        let on_event = Closure::wrap(Box::new(move |event: JsValue| match event.into_serde() {
+            Ok(event) => on_event.emit(event),
+            Err(err) => console_log!("unexpected player event: {}", err),
+        }) as Box<dyn Fn(JsValue)>);
+        Self {
+            state: createPlayer(&on_event),
+            _on_event: on_event,
+        }
+    }
+
+    /// Start playing a song from a beat, counted from 0, replacing whatever was playing before.
+    pub fn play(&self, source: &str, from_beat: u32) {
+        play(&self.state, source, from_beat);
+    }
+
+    pub fn pause(&self) {
+        pause(&self.state);
+    }
+
+    pub fn resume(&self) {
+        resume(&self.state);
+    }
+
+    pub fn stop(&self) {
+        stop(&self.state);
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        destroyPlayer(&self.state);
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = syntxt_player, js_name = create)]
+    fn createPlayer(on_event: &Closure<dyn Fn(JsValue)>) -> JsValue;
+
+    #[wasm_bindgen(js_namespace = syntxt_player, js_name = destroy)]
+    fn destroyPlayer(player: &JsValue);
+
+    #[wasm_bindgen(js_namespace = syntxt_player)]
+    fn play(player: &JsValue, source: &str, from_beat: u32);
+
+    #[wasm_bindgen(js_namespace = syntxt_player)]
+    fn pause(player: &JsValue);
+
+    #[wasm_bindgen(js_namespace = syntxt_player)]
+    fn resume(player: &JsValue);
+
+    #[wasm_bindgen(js_namespace = syntxt_player)]
+    fn stop(player: &JsValue);
+}
@@ -0,0 +1,101 @@
+// syn.txt -- a text based synthesizer and audio workstation
+// Copyright (C) 2021  Fabian Thorand
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rendering songs for playing them in the browser.
+//!
+//! The rendering runs in a web worker (`static/render-worker.js`) loading this module a second
+//! time, so that the page stays responsive. It sends the blocks to an audio worklet playing them.
+
+use syntxt_audio::{
+    play::{self, Options, Render},
+    song::{Song, Time},
+};
+use syntxt_lang::{diagnostic::Severity, model, parser::Parser};
+use wasm_bindgen::prelude::*;
+
+/// A song being rendered block by block.
+#[wasm_bindgen]
+pub struct Renderer {
+    render: Render,
+    bpm: u32,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    /// Compile a song and start rendering it at a sample rate from a beat, counted from 0,
+    /// failing with the first error in the song.
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str, sample_rate: u32, from_beat: u32) -> Result<Renderer, JsValue> {
+        let (root, mut diagnostics) = Parser::parse_with_diagnostics(source);
+        let (song, resolve_diagnostics) = model::resolve(&root);
+        diagnostics.extend(resolve_diagnostics);
+        if let Some(error) = diagnostics.iter().find(|d| d.severity == Severity::Error) {
+            return Err(JsValue::from_str(&format!(
+                "{}:{}: {}",
+                error.pos.start.line, error.pos.start.column, error.message
+            )));
+        }
+        let song = song.ok_or_else(|| JsValue::from_str("there is no song to play"))?;
+        let options = Options {
+            sample_rate: sample_rate.into(),
+            from: if from_beat > 0 {
+                Some(Time::new(from_beat.into(), 4))
+            } else {
+                None
+            },
+            ..Options::default()
+        };
+        let render = play::render(Song::from_model(&song), &options)
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        Ok(Self {
+            render,
+            bpm: song.bpm.value as u32,
+        })
+    }
+
+    /// The sample of the whole song that is rendered first.
+    pub fn start_frame(&self) -> usize {
+        self.render.start_frame()
+    }
+
+    /// The sample of the whole song that is rendered next.
+    pub fn frame(&self) -> usize {
+        self.render.frame()
+    }
+
+    /// The sample of the whole song where it ends.
+    pub fn end_frame(&self) -> usize {
+        self.render.end_frame()
+    }
+
+    /// Beats per minute of the song, where a beat is a quarter note.
+    pub fn bpm(&self) -> u32 {
+        self.bpm
+    }
+
+    /// The next block of the song with the left and right channel interleaved,
+    /// or nothing after the end. Only the front speakers of other layouts are played.
+    pub fn next_block(&mut self) -> Option<Vec<f32>> {
+        let block = self.render.next()?;
+        let mut samples = Vec::with_capacity(2 * block.frames.len());
+        for frame in block.frames.iter() {
+            let front = frame.pair(0);
+            samples.push(front.left as f32);
+            samples.push(front.right as f32);
+        }
+        Some(samples)
+    }
+}
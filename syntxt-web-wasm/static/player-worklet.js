@@ -0,0 +1,71 @@
+// Playing the blocks rendered by `render-worker.js` on the audio thread.
+//
+// Blocks are queued until they are played, with silence while there are none. How many frames
+// have been played and the frame of the song playing are reported a few times per second,
+// and once more when the song has ended.
+const REPORTS_PER_SECOND = 20;
+
+class SongPlayer extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        this.clear();
+        this.port.onmessage = (event) => {
+            const message = event.data;
+            switch (message.type) {
+                case "block":
+                    this.blocks.push(message);
+                    break;
+                case "end":
+                    this.ending = true;
+                    break;
+                case "clear":
+                    this.clear();
+                    break;
+            }
+        };
+    }
+
+    clear() {
+        this.blocks = [];
+        // Position of the next sample in the first block
+        this.offset = 0;
+        this.played = 0;
+        this.reported = 0;
+        // Frame of the song played next
+        this.position = 0;
+        // Whether the last block has been received
+        this.ending = false;
+    }
+
+    report() {
+        this.reported = this.played;
+        this.port.postMessage({ type: "played", frames: this.played, position: this.position });
+    }
+
+    process(inputs, outputs) {
+        const [left, right] = outputs[0];
+        for (let i = 0; i < left.length && this.blocks.length > 0; i++) {
+            const block = this.blocks[0];
+            left[i] = block.samples[this.offset];
+            right[i] = block.samples[this.offset + 1];
+            this.offset += 2;
+            this.played += 1;
+            this.position = block.start + this.offset / 2;
+            if (this.offset >= block.samples.length) {
+                this.blocks.shift();
+                this.offset = 0;
+            }
+        }
+        if (this.played - this.reported >= sampleRate / REPORTS_PER_SECOND) {
+            this.report();
+        }
+        if (this.ending && this.blocks.length === 0) {
+            this.ending = false;
+            this.report();
+            this.port.postMessage({ type: "ended" });
+        }
+        return true;
+    }
+}
+
+registerProcessor("song-player", SongPlayer);
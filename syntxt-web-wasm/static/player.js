@@ -0,0 +1,125 @@
+// Playing songs with WebAudio, used by the `Player` of the WASM app.
+//
+// A worker renders the song with the WASM module and sends the blocks here, which forwards them
+// to an audio worklet playing them. The worklet reports how much it has played, so that the worker
+// can stay a few seconds ahead without rendering the whole song at once.
+window.syntxt_player = {
+    create: function(onEvent) {
+        return {
+            onEvent: onEvent,
+            context: null,
+            node: null,
+            worker: null,
+            // Set up once on the first play, which must happen in response to the user
+            setup: null,
+            // Incremented for every song played, blocks of earlier ones are dropped
+            generation: 0,
+        };
+    },
+    destroy: function(state) {
+        state.generation += 1;
+        if (state.setup) {
+            state.setup.then(() => {
+                state.worker.terminate();
+                state.context.close();
+            });
+        }
+    },
+    // Start playing a song from a beat, counted from 0
+    play: function(state, source, fromBeat) {
+        if (!state.setup) {
+            state.setup = syntxt_player.setup(state);
+        }
+        state.generation += 1;
+        const generation = state.generation;
+        state.setup.then(() => {
+            if (generation !== state.generation) {
+                return;
+            }
+            state.node.port.postMessage({ type: "clear" });
+            state.worker.postMessage({
+                type: "load",
+                generation: generation,
+                source: source,
+                fromBeat: fromBeat,
+                sampleRate: state.context.sampleRate,
+            });
+            state.context.resume();
+        }).catch((error) => {
+            state.onEvent({ type: "error", message: String(error) });
+        });
+    },
+    pause: function(state) {
+        if (state.context) {
+            state.context.suspend();
+        }
+    },
+    resume: function(state) {
+        if (state.context) {
+            state.context.resume();
+        }
+    },
+    stop: function(state) {
+        state.generation += 1;
+        if (state.setup) {
+            state.setup.then(() => {
+                state.worker.postMessage({ type: "stop" });
+                state.node.port.postMessage({ type: "clear" });
+                state.context.suspend();
+            });
+        }
+    },
+    setup: async function(state) {
+        const context = new AudioContext();
+        await context.audioWorklet.addModule("static/player-worklet.js");
+        const node = new AudioWorkletNode(context, "song-player", {
+            numberOfInputs: 0,
+            outputChannelCount: [2],
+        });
+        node.connect(context.destination);
+        const worker = new Worker("static/render-worker.js", { type: "module" });
+
+        worker.onmessage = (event) => {
+            const message = event.data;
+            if (message.generation !== state.generation) {
+                return;
+            }
+            switch (message.type) {
+                case "block":
+                    node.port.postMessage(message, [message.samples.buffer]);
+                    break;
+                case "end":
+                    node.port.postMessage(message);
+                    break;
+                case "loaded":
+                    state.onEvent({
+                        type: "loaded",
+                        start: message.start,
+                        end: message.end,
+                        sampleRate: message.sampleRate,
+                        bpm: message.bpm,
+                    });
+                    break;
+                case "error":
+                    state.onEvent({ type: "error", message: message.message });
+                    break;
+            }
+        };
+        node.port.onmessage = (event) => {
+            const message = event.data;
+            switch (message.type) {
+                case "played":
+                    worker.postMessage(message);
+                    state.onEvent({ type: "position", frame: message.position });
+                    break;
+                case "ended":
+                    state.onEvent({ type: "ended" });
+                    break;
+            }
+        };
+
+        state.context = context;
+        state.node = node;
+        state.worker = worker;
+    },
+};
@@ -0,0 +1,75 @@
+// Rendering songs for `player.js` with the WASM module, off the main thread.
+//
+// Blocks are rendered while less than a few seconds have been rendered ahead of what the
+// worklet has played, and sent with the generation of the song they belong to and the frame
+// of the song they start at.
+import wasm, { Renderer } from "../pkg/syntxt_web_wasm.js";
+
+const AHEAD_SECONDS = 3;
+
+const ready = wasm();
+let renderer = null;
+let generation = 0;
+let sampleRate = 44100;
+let rendered = 0;
+let played = 0;
+
+function unload() {
+    if (renderer) {
+        renderer.free();
+        renderer = null;
+    }
+}
+
+function renderAhead() {
+    while (renderer && rendered - played < AHEAD_SECONDS * sampleRate) {
+        const start = renderer.frame();
+        const samples = renderer.next_block();
+        if (samples === undefined) {
+            unload();
+            postMessage({ type: "end", generation: generation });
+            return;
+        }
+        rendered += samples.length / 2;
+        postMessage(
+            { type: "block", generation: generation, start: start, samples: samples },
+            [samples.buffer]
+        );
+    }
+}
+
+onmessage = async (event) => {
+    await ready;
+    const message = event.data;
+    switch (message.type) {
+        case "load":
+            unload();
+            generation = message.generation;
+            sampleRate = message.sampleRate;
+            rendered = 0;
+            played = 0;
+            try {
+                renderer = new Renderer(message.source, sampleRate, message.fromBeat);
+            } catch (error) {
+                postMessage({ type: "error", generation: generation, message: String(error) });
+                return;
+            }
+            postMessage({
+                type: "loaded",
+                generation: generation,
+                start: renderer.start_frame(),
+                end: renderer.end_frame(),
+                sampleRate: sampleRate,
+                bpm: renderer.bpm(),
+            });
+            renderAhead();
+            break;
+        case "played":
+            played = message.frames;
+            renderAhead();
+            break;
+        case "stop":
+            unload();
+            break;
+    }
+};